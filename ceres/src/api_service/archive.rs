@@ -0,0 +1,397 @@
+//! Archives of a monorepo directory, written entry by entry so they can be streamed.
+
+use std::io::{self, Write};
+
+use chrono::{DateTime, Datelike, Timelike};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{Compression, Crc};
+use serde::Deserialize;
+
+use mercury::internal::object::tree::TreeItemMode;
+
+/// Files accepted in one archive, zip without the zip64 extension stops at 65535.
+pub const MAX_ARCHIVE_ENTRIES: usize = 50_000;
+/// Total uncompressed size of the files in one archive.
+pub const MAX_ARCHIVE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+const TAR_BLOCK: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "application/gzip",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+pub struct ArchiveWriter {
+    inner: Inner,
+    mtime: i64,
+}
+
+enum Inner {
+    TarGz(GzEncoder<Vec<u8>>),
+    Zip(ZipState),
+}
+
+#[derive(Default)]
+struct ZipState {
+    out: Vec<u8>,
+    /// bytes written before `out`, which has been taken by the caller
+    offset: u64,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl ArchiveWriter {
+    /// Every entry gets `mtime`, usually the time of the archived commit.
+    pub fn new(format: ArchiveFormat, mtime: i64) -> Self {
+        let inner = match format {
+            ArchiveFormat::TarGz => {
+                Inner::TarGz(GzEncoder::new(Vec::new(), Compression::default()))
+            }
+            ArchiveFormat::Zip => Inner::Zip(ZipState::default()),
+        };
+        ArchiveWriter { inner, mtime }
+    }
+
+    /// Append a file, `data` is the link target for symlinks. Directories are implied by
+    /// the paths of their files, other modes are skipped.
+    pub fn append(&mut self, path: &str, mode: TreeItemMode, data: &[u8]) -> io::Result<()> {
+        let unix_mode = match mode {
+            TreeItemMode::Blob => 0o100644,
+            TreeItemMode::BlobExecutable => 0o100755,
+            TreeItemMode::Link => 0o120777,
+            _ => return Ok(()),
+        };
+        match &mut self.inner {
+            Inner::TarGz(encoder) => write_tar_entry(encoder, path, unix_mode, data, self.mtime),
+            Inner::Zip(zip) => zip.append(path, unix_mode, data, self.mtime),
+        }
+    }
+
+    /// Bytes produced so far that haven't been taken yet.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        match &mut self.inner {
+            Inner::TarGz(encoder) => std::mem::take(encoder.get_mut()),
+            Inner::Zip(zip) => {
+                zip.offset += zip.out.len() as u64;
+                std::mem::take(&mut zip.out)
+            }
+        }
+    }
+
+    /// Write the end of the archive and return the remaining bytes.
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        match self.inner {
+            Inner::TarGz(mut encoder) => {
+                // two empty blocks mark the end of a tar
+                encoder.write_all(&[0; TAR_BLOCK * 2])?;
+                encoder.finish()
+            }
+            Inner::Zip(zip) => Ok(zip.finish()),
+        }
+    }
+}
+
+fn write_tar_entry(
+    out: &mut impl Write,
+    path: &str,
+    unix_mode: u32,
+    data: &[u8],
+    mtime: i64,
+) -> io::Result<()> {
+    let is_link = unix_mode & 0o170000 == 0o120000;
+    let link = if is_link {
+        String::from_utf8_lossy(data).into_owned()
+    } else {
+        String::new()
+    };
+    let size = if is_link { 0 } else { data.len() as u64 };
+
+    // names that don't fit the header are sent in a GNU long name entry first
+    if link.len() > 100 {
+        write_tar_long_name(out, b'K', &link, mtime)?;
+    }
+    let (prefix, name) = match split_tar_path(path) {
+        Some(split) => split,
+        None => {
+            write_tar_long_name(out, b'L', path, mtime)?;
+            ("", &path[..100.min(path.len())])
+        }
+    };
+    let typeflag = if is_link { b'2' } else { b'0' };
+    let header = tar_header(
+        name,
+        prefix,
+        unix_mode & 0o7777,
+        size,
+        mtime,
+        typeflag,
+        &link,
+    );
+    out.write_all(&header)?;
+    if !is_link {
+        write_tar_data(out, data)?;
+    }
+    Ok(())
+}
+
+fn write_tar_long_name(
+    out: &mut impl Write,
+    typeflag: u8,
+    value: &str,
+    mtime: i64,
+) -> io::Result<()> {
+    let mut data = value.as_bytes().to_vec();
+    data.push(0);
+    let header = tar_header(
+        "././@LongLink",
+        "",
+        0o644,
+        data.len() as u64,
+        mtime,
+        typeflag,
+        "",
+    );
+    out.write_all(&header)?;
+    write_tar_data(out, &data)
+}
+
+fn write_tar_data(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    out.write_all(data)?;
+    let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+    out.write_all(&[0; TAR_BLOCK][..padding])
+}
+
+/// Split a path into the ustar `prefix` and `name` fields, `None` if it doesn't fit.
+fn split_tar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+fn tar_header(
+    name: &str,
+    prefix: &str,
+    mode: u32,
+    size: u64,
+    mtime: i64,
+    typeflag: u8,
+    link: &str,
+) -> [u8; TAR_BLOCK] {
+    fn put(header: &mut [u8], offset: usize, len: usize, value: &[u8]) {
+        let len = value.len().min(len);
+        header[offset..offset + len].copy_from_slice(&value[..len]);
+    }
+    fn octal(header: &mut [u8], offset: usize, len: usize, value: u64) {
+        put(
+            header,
+            offset,
+            len,
+            format!("{:0width$o}\0", value, width = len - 1).as_bytes(),
+        );
+    }
+
+    let mut header = [0u8; TAR_BLOCK];
+    put(&mut header, 0, 100, name.as_bytes());
+    octal(&mut header, 100, 8, mode as u64);
+    octal(&mut header, 108, 8, 0);
+    octal(&mut header, 116, 8, 0);
+    octal(&mut header, 124, 12, size);
+    octal(&mut header, 136, 12, mtime.max(0) as u64);
+    header[156] = typeflag;
+    put(&mut header, 157, 100, link.as_bytes());
+    put(&mut header, 257, 8, b"ustar\x0000");
+    put(&mut header, 345, 155, prefix.as_bytes());
+
+    // the checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|x| *x as u32).sum();
+    put(
+        &mut header,
+        148,
+        8,
+        format!("{:06o}\0 ", checksum).as_bytes(),
+    );
+    header
+}
+
+impl ZipState {
+    fn append(&mut self, path: &str, unix_mode: u32, data: &[u8], mtime: i64) -> io::Result<()> {
+        if self.entries == u16::MAX {
+            return Err(io::Error::other("too many entries for a zip archive"));
+        }
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        let offset = self.offset + self.out.len() as u64;
+        if data.len() as u64 > u32::MAX as u64 || offset > u32::MAX as u64 {
+            return Err(io::Error::other("archive is too large for a zip archive"));
+        }
+        let (time, date) = dos_datetime(mtime);
+
+        // fields shared by the local header and the central directory
+        let mut common = Vec::with_capacity(26);
+        common.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+        common.extend_from_slice(&0x0800u16.to_le_bytes()); // names are UTF-8
+        common.extend_from_slice(&8u16.to_le_bytes()); // deflate
+        common.extend_from_slice(&time.to_le_bytes());
+        common.extend_from_slice(&date.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(path.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        self.out.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.out.extend_from_slice(&common);
+        self.out.extend_from_slice(path.as_bytes());
+        self.out.extend_from_slice(&compressed);
+
+        self.central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        // made by unix, so that the mode in the external attributes is used
+        self.central
+            .extend_from_slice(&(3u16 << 8 | 20).to_le_bytes());
+        self.central.extend_from_slice(&common);
+        self.central.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        self.central.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        self.central.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        self.central
+            .extend_from_slice(&(unix_mode << 16).to_le_bytes());
+        self.central
+            .extend_from_slice(&(offset as u32).to_le_bytes());
+        self.central.extend_from_slice(path.as_bytes());
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let central_offset = self.offset + self.out.len() as u64;
+        let central_size = self.central.len() as u32;
+        self.out.append(&mut self.central);
+        self.out.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.out.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        self.out.extend_from_slice(&0u16.to_le_bytes()); // disk with the central directory
+        self.out.extend_from_slice(&self.entries.to_le_bytes());
+        self.out.extend_from_slice(&self.entries.to_le_bytes());
+        self.out.extend_from_slice(&central_size.to_le_bytes());
+        self.out
+            .extend_from_slice(&(central_offset as u32).to_le_bytes());
+        self.out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        self.out
+    }
+}
+
+/// MS-DOS time and date of a unix timestamp, which can't go before 1980.
+fn dos_datetime(timestamp: i64) -> (u16, u16) {
+    let Some(dt) = DateTime::from_timestamp(timestamp, 0).filter(|x| x.year() >= 1980) else {
+        return (0, 1 << 5 | 1);
+    };
+    let time = (dt.hour() << 11 | dt.minute() << 5 | dt.second() / 2) as u16;
+    let date = (((dt.year() - 1980) as u32) << 9 | dt.month() << 5 | dt.day()) as u16;
+    (time, date)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_octal(field: &[u8]) -> u64 {
+        let text = std::str::from_utf8(field).unwrap();
+        u64::from_str_radix(text.trim_matches(|c| c == '\0' || c == ' '), 8).unwrap()
+    }
+
+    #[test]
+    fn test_tar_entry() {
+        let mut out = Vec::new();
+        write_tar_entry(&mut out, "foo/a.txt", 0o100755, b"hello\n", 1_700_000_000).unwrap();
+        assert_eq!(out.len(), TAR_BLOCK * 2);
+        assert_eq!(&out[..9], b"foo/a.txt");
+        assert_eq!(parse_octal(&out[100..108]), 0o755);
+        assert_eq!(parse_octal(&out[124..136]), 6);
+        assert_eq!(&out[257..263], b"ustar\0");
+        let mut header = out[..TAR_BLOCK].to_vec();
+        let checksum = parse_octal(&header[148..156]);
+        header[148..156].fill(b' ');
+        assert_eq!(checksum, header.iter().map(|x| *x as u64).sum::<u64>());
+        assert_eq!(&out[TAR_BLOCK..TAR_BLOCK + 6], b"hello\n");
+    }
+
+    #[test]
+    fn test_tar_long_path() {
+        let dir = "d".repeat(120);
+        let path = format!("{}/file.txt", dir);
+        assert_eq!(split_tar_path(&path), Some((dir.as_str(), "file.txt")));
+
+        let long = "f".repeat(150);
+        assert_eq!(split_tar_path(&long), None);
+        let mut out = Vec::new();
+        write_tar_entry(&mut out, &long, 0o100644, b"x", 0).unwrap();
+        // long name header and its data, then the entry itself
+        assert_eq!(out.len(), TAR_BLOCK * 4);
+        assert_eq!(out[156], b'L');
+        assert_eq!(&out[TAR_BLOCK..TAR_BLOCK + 150], long.as_bytes());
+    }
+
+    #[test]
+    fn test_zip_layout() {
+        let mut writer = ArchiveWriter::new(ArchiveFormat::Zip, 1_700_000_000);
+        writer
+            .append("foo/a.txt", TreeItemMode::Blob, b"hello\n")
+            .unwrap();
+        let first = writer.take_output();
+        writer
+            .append("foo/sub/b.txt", TreeItemMode::Blob, b"")
+            .unwrap();
+        writer
+            .append("foo/module", TreeItemMode::Commit, b"")
+            .unwrap();
+        let mut out = first;
+        out.extend(writer.finish().unwrap());
+
+        assert_eq!(&out[..4], &0x04034b50u32.to_le_bytes());
+        let eocd = &out[out.len() - 22..];
+        assert_eq!(&eocd[..4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([eocd[10], eocd[11]]), 2);
+        let central_size = u32::from_le_bytes(eocd[12..16].try_into().unwrap()) as usize;
+        let central_offset = u32::from_le_bytes(eocd[16..20].try_into().unwrap()) as usize;
+        assert_eq!(central_offset + central_size, out.len() - 22);
+        assert_eq!(
+            &out[central_offset..central_offset + 4],
+            &0x02014b50u32.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_dos_datetime() {
+        // 2023-11-14 22:13:20 UTC
+        assert_eq!(
+            dos_datetime(1_700_000_000),
+            (22 << 11 | 13 << 5 | 10, 43 << 9 | 11 << 5 | 14)
+        );
+        assert_eq!(dos_datetime(0), (0, 1 << 5 | 1));
+    }
+}
@@ -45,104 +45,90 @@ impl ApiHandler for ImportApiService {
         }
     }
 
-    async fn get_root_commit(&self) -> Commit {
+    async fn get_root_commit(&self) -> Result<Commit, GitError> {
         let storage = self.context.services.git_db_storage.clone();
         let refs = storage
             .get_default_ref(self.repo.repo_id)
-            .await
-            .unwrap()
-            .unwrap();
-        storage
-            .get_commit_by_hash(self.repo.repo_id, &refs.ref_git_id)
-            .await
-            .unwrap()
-            .unwrap()
-            .into()
+            .await?
+            .ok_or(GitError::RepoNotFound)?;
+        self.get_commit(&refs.ref_git_id).await
     }
 
-    async fn get_root_tree(&self) -> Tree {
-        let storage = self.context.services.git_db_storage.clone();
-        let refs = storage
-            .get_default_ref(self.repo.repo_id)
-            .await
-            .unwrap()
-            .unwrap();
-
-        let root_commit = storage
-            .get_commit_by_hash(self.repo.repo_id, &refs.ref_git_id)
-            .await
-            .unwrap()
-            .unwrap();
-        storage
-            .get_tree_by_hash(self.repo.repo_id, &root_commit.tree)
+    async fn get_root_tree(&self) -> Result<Tree, GitError> {
+        let root_commit = self.get_root_commit().await?;
+        self.get_tree_by_hash(&root_commit.tree_id.to_string())
             .await
-            .unwrap()
-            .unwrap()
-            .into()
     }
 
-    async fn get_tree_by_hash(&self, hash: &str) -> Tree {
+    async fn get_tree_by_hash(&self, hash: &str) -> Result<Tree, GitError> {
         self.context
             .services
             .git_db_storage
             .get_tree_by_hash(self.repo.repo_id, hash)
-            .await
-            .unwrap()
-            .unwrap()
-            .into()
+            .await?
+            .map(Tree::from)
+            .ok_or_else(|| GitError::ObjectNotFound(hash.to_owned()))
     }
 
-    async fn get_tree_relate_commit(&self, t_hash: &str) -> Commit {
+    async fn get_tree_relate_commit(&self, t_hash: &str) -> Result<Commit, GitError> {
         let storage = self.context.services.git_db_storage.clone();
         let tree_info = storage
             .get_tree_by_hash(self.repo.repo_id, t_hash)
-            .await
-            .unwrap()
-            .unwrap();
-        storage
-            .get_commit_by_hash(self.repo.repo_id, &tree_info.commit_id)
-            .await
-            .unwrap()
-            .unwrap()
-            .into()
+            .await?
+            .ok_or_else(|| GitError::ObjectNotFound(t_hash.to_owned()))?;
+        self.get_commit(&tree_info.commit_id).await
     }
 
     async fn add_trees_to_map(
         &self,
         item_to_commit: &mut HashMap<String, String>,
         hashes: Vec<String>,
-    ) {
+    ) -> Result<(), GitError> {
         let storage = self.context.services.git_db_storage.clone();
         let trees = storage
             .get_trees_by_hashes(self.repo.repo_id, hashes)
-            .await
-            .unwrap();
+            .await?;
         for tree in trees {
             item_to_commit.insert(tree.tree_id, tree.commit_id);
         }
+        Ok(())
     }
 
     async fn add_blobs_to_map(
         &self,
         item_to_commit: &mut HashMap<String, String>,
         hashes: Vec<String>,
-    ) {
+    ) -> Result<(), GitError> {
         let storage = self.context.services.git_db_storage.clone();
         let blobs = storage
             .get_blobs_by_hashes(self.repo.repo_id, hashes)
-            .await
-            .unwrap();
+            .await?;
         for blob in blobs {
             item_to_commit.insert(blob.blob_id, blob.commit_id);
         }
+        Ok(())
+    }
+
+    async fn add_blob_sizes_to_map(
+        &self,
+        item_to_size: &mut HashMap<String, usize>,
+        hashes: Vec<String>,
+    ) -> Result<(), GitError> {
+        let storage = self.context.services.git_db_storage.clone();
+        let blobs = storage
+            .get_blobs_by_hashes(self.repo.repo_id, hashes)
+            .await?;
+        for blob in blobs {
+            item_to_size.insert(blob.blob_id, blob.size as usize);
+        }
+        Ok(())
     }
 
     async fn get_commits_by_hashes(&self, c_hashes: Vec<String>) -> Result<Vec<Commit>, GitError> {
         let storage = self.context.services.git_db_storage.clone();
         let commits = storage
             .get_commits_by_hashes(self.repo.repo_id, &c_hashes)
-            .await
-            .unwrap();
+            .await?;
         Ok(commits.into_iter().map(|x| x.into()).collect())
     }
 
@@ -151,7 +137,7 @@ impl ApiHandler for ImportApiService {
         path: &Path,
         start_commit: Commit,
         target: &TreeItem,
-    ) -> Commit {
+    ) -> Result<Commit, GitError> {
         let mut target_commit = start_commit.clone();
         let mut visited = HashSet::new();
         let mut p_stack = VecDeque::new();
@@ -160,11 +146,8 @@ impl ApiHandler for ImportApiService {
         p_stack.push_back(start_commit);
 
         while let Some(commit) = p_stack.pop_front() {
-            let root_tree = self.get_tree_by_hash(&commit.tree_id.to_string()).await;
-            let reachable = self
-                .reachable_in_tree(&root_tree, path, target)
-                .await
-                .unwrap();
+            let root_tree = self.get_tree_by_hash(&commit.tree_id.to_string()).await?;
+            let reachable = self.reachable_in_tree(&root_tree, path, target).await?;
             if reachable {
                 let mut p_ids = vec![];
                 for p_id in commit.parent_commit_ids.clone() {
@@ -176,10 +159,22 @@ impl ApiHandler for ImportApiService {
                 if target_commit.committer.timestamp > commit.committer.timestamp {
                     target_commit = commit;
                 }
-                let parent_commits = self.get_commits_by_hashes(p_ids).await.unwrap();
+                let parent_commits = self.get_commits_by_hashes(p_ids).await?;
                 p_stack.extend(parent_commits);
             }
         }
-        target_commit
+        Ok(target_commit)
+    }
+}
+
+impl ImportApiService {
+    async fn get_commit(&self, hash: &str) -> Result<Commit, GitError> {
+        self.context
+            .services
+            .git_db_storage
+            .get_commit_by_hash(self.repo.repo_id, hash)
+            .await?
+            .map(Commit::from)
+            .ok_or_else(|| GitError::ObjectNotFound(hash.to_owned()))
     }
 }
@@ -5,25 +5,40 @@ use std::{
 
 use async_trait::async_trait;
 
-use callisto::raw_blob;
-use common::errors::MegaError;
+use callisto::{raw_blob, user};
+use common::{
+    errors::MegaError,
+    model::{Page, Pagination},
+    path::MonoPath,
+};
 use jupiter::{context::Context, utils::converter::generate_git_keep_with_timestamp};
 use mercury::{
+    diff::split_lines,
     errors::GitError,
+    hash::SHA1,
     internal::object::{
         commit::Commit,
+        signature::Signature,
         tree::{Tree, TreeItem, TreeItemMode},
         ObjectTrait,
     },
 };
 
 use crate::model::{
+    blame::{BlameHunk, BlameTracker},
+    blob::BlobBatchItem,
     create_file::CreateFileInfo,
+    query::{ListEntry, TreeListOptions, TreeSortKey},
     tree::{LatestCommitInfo, TreeBriefItem, TreeCommitItem, UserInfo},
 };
 
+pub mod archive;
 pub mod import_api_service;
 pub mod mono_api_service;
+pub mod tree_ops;
+
+/// Upper bound of commits visited by one blame request.
+const MAX_BLAME_SCAN: usize = 1000;
 
 #[async_trait]
 pub trait ApiHandler: Send + Sync {
@@ -40,35 +55,72 @@ pub trait ApiHandler: Send + Sync {
             .await
     }
 
+    /// Fetch a batch of blobs in one query, keeping the order of the requested hashes.
+    /// Hashes that can't be found are skipped.
+    async fn get_blobs_by_hashes(
+        &self,
+        hashes: Vec<String>,
+        metadata_only: bool,
+    ) -> Result<Vec<BlobBatchItem>, MegaError> {
+        let mut seen = HashSet::new();
+        let hashes: Vec<String> = hashes
+            .into_iter()
+            .filter(|x| seen.insert(x.clone()))
+            .collect();
+        let mut models: HashMap<String, raw_blob::Model> = self
+            .get_context()
+            .services
+            .raw_db_storage
+            .get_raw_blobs_by_hashes(hashes.clone())
+            .await?
+            .into_iter()
+            .map(|x| (x.sha1.clone(), x))
+            .collect();
+        Ok(hashes
+            .into_iter()
+            .filter_map(|hash| {
+                models.remove(&hash).map(|model| {
+                    BlobBatchItem::new(hash, model.data.unwrap_or_default(), metadata_only)
+                })
+            })
+            .collect())
+    }
+
     fn strip_relative(&self, path: &Path) -> Result<PathBuf, GitError>;
 
-    async fn get_root_commit(&self) -> Commit;
+    async fn get_root_commit(&self) -> Result<Commit, GitError>;
 
-    async fn get_root_tree(&self) -> Tree;
+    async fn get_root_tree(&self) -> Result<Tree, GitError>;
 
     async fn get_tree_as_data(&self, path: &Path) -> Result<Vec<u8>, GitError> {
-        let res = self.search_tree_by_path(path).await.unwrap();
-        if let Some(tree) = res {
+        if let Some(tree) = self.search_tree_by_path(path).await? {
             return tree.to_data();
         }
         Ok(vec![])
     }
 
-    async fn get_tree_by_hash(&self, hash: &str) -> Tree;
+    /// The tree `hash`, `GitError::ObjectNotFound` if it isn't stored.
+    async fn get_tree_by_hash(&self, hash: &str) -> Result<Tree, GitError>;
 
-    async fn get_tree_relate_commit(&self, t_hash: &str) -> Commit;
+    async fn get_tree_relate_commit(&self, t_hash: &str) -> Result<Commit, GitError>;
 
     async fn add_trees_to_map(
         &self,
         item_to_commit: &mut HashMap<String, String>,
         hashes: Vec<String>,
-    );
+    ) -> Result<(), GitError>;
 
     async fn add_blobs_to_map(
         &self,
         item_to_commit: &mut HashMap<String, String>,
         hashes: Vec<String>,
-    );
+    ) -> Result<(), GitError>;
+
+    async fn add_blob_sizes_to_map(
+        &self,
+        item_to_size: &mut HashMap<String, usize>,
+        hashes: Vec<String>,
+    ) -> Result<(), GitError>;
 
     async fn get_commits_by_hashes(&self, c_hashes: Vec<String>) -> Result<Vec<Commit>, GitError>;
 
@@ -77,22 +129,179 @@ pub trait ApiHandler: Send + Sync {
         path: &Path,
         commit: Commit,
         target: &TreeItem,
-    ) -> Commit;
+    ) -> Result<Commit, GitError>;
 
     async fn get_blob_as_string(&self, file_path: PathBuf) -> Result<Option<String>, GitError> {
-        let filename = file_path.file_name().unwrap().to_str().unwrap();
-        let parent = file_path.parent().unwrap();
+        if let Some(item) = self.get_item_by_path(&file_path).await? {
+            match self.get_raw_blob_by_hash(&item.id.to_string()).await {
+                Ok(Some(model)) => {
+                    return String::from_utf8(model.data.unwrap_or_default())
+                        .map(Some)
+                        .map_err(|_| GitError::ConversionError(item.id.to_string()))
+                }
+                _ => return Ok(None),
+            };
+        }
+        return Ok(None);
+    }
+
+    /// Find the tree item that `file_path` points to in its parent tree.
+    async fn get_item_by_path(&self, file_path: &Path) -> Result<Option<TreeItem>, GitError> {
+        let (Some(filename), Some(parent)) = (file_path.file_name(), file_path.parent()) else {
+            return Ok(None);
+        };
+        let filename = filename.to_str().unwrap();
         if let Some(tree) = self.search_tree_by_path(parent).await? {
-            if let Some(item) = tree.tree_items.into_iter().find(|x| x.name == filename) {
-                match self.get_raw_blob_by_hash(&item.id.to_string()).await {
-                    Ok(Some(model)) => {
-                        return Ok(Some(String::from_utf8(model.data.unwrap()).unwrap()))
-                    }
-                    _ => return Ok(None),
+            return Ok(tree.tree_items.into_iter().find(|x| x.name == filename));
+        }
+        Ok(None)
+    }
+
+    /// Blob id of the file at `relative_path` in the tree of `commit`, `None` when the path
+    /// doesn't exist there or isn't a regular file.
+    async fn get_blob_id_in_commit(
+        &self,
+        commit: &Commit,
+        relative_path: &Path,
+    ) -> Result<Option<SHA1>, GitError> {
+        let mut tree = self.get_tree_by_hash(&commit.tree_id.to_string()).await?;
+        let mut components = relative_path
+            .components()
+            .filter(|x| *x != Component::RootDir)
+            .peekable();
+        while let Some(component) = components.next() {
+            let name = component.as_os_str().to_str().unwrap();
+            let Some(item) = tree.tree_items.into_iter().find(|x| x.name == name) else {
+                return Ok(None);
+            };
+            match item.mode {
+                TreeItemMode::Blob | TreeItemMode::BlobExecutable
+                    if components.peek().is_none() =>
+                {
+                    return Ok(Some(item.id))
+                }
+                TreeItemMode::Tree if components.peek().is_some() => {
+                    tree = self.get_tree_by_hash(&item.id.to_string()).await?;
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Attribute every line of a file to the commit that last changed it.
+    ///
+    /// The first parent history is walked back from `refs`, or the latest commit, diffing
+    /// each version of the file against the previous one. Lines brought in by a merge are
+    /// attributed to the merge commit, and lines still unattributed after
+    /// `MAX_BLAME_SCAN` commits to the oldest commit visited.
+    async fn get_blame(
+        &self,
+        path: PathBuf,
+        refs: Option<String>,
+    ) -> Result<Vec<BlameHunk>, GitError> {
+        let path = MonoPath::try_from(path.as_path())?;
+        let relative_path = self.strip_relative(path.as_path())?;
+        let mut commit = match refs {
+            Some(refs) => self
+                .get_commits_by_hashes(vec![refs.clone()])
+                .await?
+                .pop()
+                .ok_or(GitError::ObjectNotFound(refs))?,
+            None => self.get_root_commit().await?,
+        };
+        let Some(mut current) = self.get_blob_id_in_commit(&commit, &relative_path).await? else {
+            return Err(GitError::InvalidPathError(format!("{} is not a file", path)));
+        };
+        let Some(mut text) = self.get_blob_text(&current).await? else {
+            return Err(GitError::InvalidArgument(format!("{} is not a text file", path)));
+        };
+
+        let mut tracker = BlameTracker::new(split_lines(&text).len());
+        let mut commits = Vec::new();
+        let mut scanned = 0;
+        while !tracker.is_done() {
+            scanned += 1;
+            let parent = match commit.parent_commit_ids.first() {
+                Some(parent) if scanned < MAX_BLAME_SCAN => self
+                    .get_commits_by_hashes(vec![parent.to_string()])
+                    .await?
+                    .pop(),
+                _ => None,
+            };
+            let previous = match &parent {
+                Some(parent) if parent.tree_id == commit.tree_id => Some(current),
+                Some(parent) => self.get_blob_id_in_commit(parent, &relative_path).await?,
+                None => None,
+            };
+            if previous != Some(current) {
+                let index = commits.len();
+                commits.push(commit);
+                let previous_text = match previous {
+                    Some(previous) => self.get_blob_text(&previous).await?,
+                    None => None,
                 };
+                match (previous, previous_text) {
+                    (Some(previous), Some(previous_text)) => {
+                        tracker.pass(index, &split_lines(&previous_text), &split_lines(&text));
+                        current = previous;
+                        text = previous_text;
+                    }
+                    // created here, or replaced a binary file
+                    _ => tracker.finish(index),
+                }
+            }
+            match parent {
+                Some(parent) => commit = parent,
+                None => break,
             }
         }
-        return Ok(None);
+
+        let ranges = tracker.ranges();
+        let mut indexes: Vec<usize> = ranges.iter().map(|(_, _, index)| *index).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        let blamed = indexes.iter().map(|x| commits[*x].clone()).collect();
+        let infos: HashMap<usize, LatestCommitInfo> = indexes
+            .into_iter()
+            .zip(self.convert_commits_to_info(blamed).await?)
+            .collect();
+        let hunks = ranges
+            .into_iter()
+            .map(|(start_line, end_line, index)| {
+                let info: &LatestCommitInfo = &infos[&index];
+                BlameHunk {
+                    oid: info.oid.clone(),
+                    author: info.author.clone(),
+                    date: info.date.clone(),
+                    short_message: info.short_message.clone(),
+                    start_line,
+                    end_line,
+                }
+            })
+            .collect();
+        Ok(hunks)
+    }
+
+    /// Content of a blob as text, `None` for binary content.
+    async fn get_blob_text(&self, id: &SHA1) -> Result<Option<String>, GitError> {
+        let model = self
+            .get_raw_blob_by_hash(&id.to_string())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or_else(|| GitError::ObjectNotFound(id.to_string()))?;
+        Ok(String::from_utf8(model.data.unwrap_or_default()).ok())
+    }
+
+    /// Get the commit that introduced the given blob, used as the last modified time of a file.
+    async fn get_blob_relate_commit(&self, hash: &str) -> Result<Option<Commit>, GitError> {
+        let mut item_to_commit = HashMap::new();
+        self.add_blobs_to_map(&mut item_to_commit, vec![hash.to_owned()])
+            .await?;
+        match item_to_commit.remove(hash) {
+            Some(commit_id) => Ok(self.get_commits_by_hashes(vec![commit_id]).await?.pop()),
+            None => Ok(None),
+        }
     }
 
     async fn get_latest_commit(&self, path: PathBuf) -> Result<LatestCommitInfo, GitError> {
@@ -103,119 +312,241 @@ pub trait ApiHandler: Send + Sync {
                 "can't find target parent tree under latest commit".to_string(),
             ));
         };
-        let commit = self.get_tree_relate_commit(&tree.id.to_string()).await;
-        self.convert_commit_to_info(commit)
+        let commit = self.get_tree_relate_commit(&tree.id.to_string()).await?;
+        self.convert_commit_to_info(commit).await
     }
 
-    async fn get_tree_info(&self, path: PathBuf) -> Result<Vec<TreeBriefItem>, GitError> {
+    async fn get_tree_info(
+        &self,
+        path: PathBuf,
+        options: &TreeListOptions,
+        page: Option<Pagination>,
+    ) -> Result<Page<TreeBriefItem>, GitError> {
         match self.search_tree_by_path(&path).await? {
             Some(tree) => {
-                let mut items = Vec::new();
-                for item in tree.tree_items {
+                let mut tree_items = tree.tree_items;
+                tree_items.retain(|x| options.matches(&x.name));
+
+                let sizes = self.get_item_sizes(&tree_items).await?;
+                let dates = if options.sort == Some(TreeSortKey::LastModified) {
+                    self.get_item_dates(&tree_items).await?
+                } else {
+                    HashMap::new()
+                };
+                tree_items.sort_by(|a, b| {
+                    options.compare(
+                        &list_entry(a, &sizes, &dates),
+                        &list_entry(b, &sizes, &dates),
+                    )
+                });
+
+                Ok(paginate(tree_items, page).map(|item| {
                     let mut info: TreeBriefItem = item.clone().into();
+                    info.size = sizes.get(&item.id.to_string()).copied();
                     path.join(item.name)
                         .to_str()
                         .unwrap()
                         .clone_into(&mut info.path);
-                    items.push(info);
-                }
-                Ok(items)
+                    info
+                }))
             }
-            None => Ok(Vec::new()),
+            None => Ok(paginate(Vec::new(), page)),
         }
     }
 
-    async fn get_tree_commit_info(&self, path: PathBuf) -> Result<Vec<TreeCommitItem>, GitError> {
-        match self.search_tree_by_path(&path).await? {
-            Some(tree) => {
-                let mut item_to_commit = HashMap::new();
-
-                self.add_trees_to_map(
-                    &mut item_to_commit,
-                    tree.tree_items
-                        .iter()
-                        .filter(|x| x.mode == TreeItemMode::Tree)
-                        .map(|x| x.id.to_string())
-                        .collect(),
-                )
-                .await;
-
-                self.add_blobs_to_map(
-                    &mut item_to_commit,
-                    tree.tree_items
-                        .iter()
-                        .filter(|x| x.mode == TreeItemMode::Blob)
-                        .map(|x| x.id.to_string())
-                        .collect(),
+    /// List a directory with the last commit of every entry, in name order unless sorted
+    /// otherwise.
+    ///
+    /// Commits are looked up in one batch for the returned entries only. Sorting by last
+    /// modification is the slow path, it needs the commits of the whole directory before
+    /// paging.
+    async fn get_tree_commit_info(
+        &self,
+        path: PathBuf,
+        options: &TreeListOptions,
+        page: Option<Pagination>,
+    ) -> Result<Page<TreeCommitItem>, GitError> {
+        let Some(mut tree) = self.search_tree_by_path(&path).await? else {
+            return Ok(paginate(Vec::new(), page));
+        };
+        tree.tree_items.retain(|x| options.matches(&x.name));
+        let total = tree.tree_items.len() as u64;
+        let sizes = self.get_item_sizes(&tree.tree_items).await?;
+
+        let by_date = options.sort == Some(TreeSortKey::LastModified);
+        let tree_items = if by_date {
+            tree.tree_items
+        } else {
+            tree.tree_items.sort_by(|a, b| {
+                options.compare(
+                    &list_entry(a, &sizes, &HashMap::new()),
+                    &list_entry(b, &sizes, &HashMap::new()),
                 )
-                .await;
-
-                let mut items = Vec::new();
-                let commit_ids: HashSet<String> = item_to_commit.values().cloned().collect();
-                let commits = self
-                    .get_commits_by_hashes(commit_ids.into_iter().collect())
-                    .await
-                    .unwrap();
-                let commit_map: HashMap<String, Commit> = commits
-                    .into_iter()
-                    .map(|x| (x.id.to_string(), x))
-                    .collect();
-
-                let root_commit: Option<Commit> = None;
-                for item in tree.tree_items {
-                    let mut info: TreeCommitItem = item.clone().into();
-                    if let Some(commit_id) = item_to_commit.get(&item.id.to_string()) {
-                        let commit = if let Some(commit) = commit_map.get(commit_id) {
-                            commit
-                        } else {
-                            tracing::warn!("failed fecth commit: {}", commit_id);
-                            let root_commit = if let Some(ref root_commit) = root_commit {
-                                root_commit.clone()
-                            } else {
-                                self.get_root_commit().await
-                            };
-                            &self
-                                .traverse_commit_history(&path, root_commit, &item)
-                                .await
-                        };
-                        info.oid = commit.id.to_string();
-                        info.message = commit.format_message();
-                        info.date = commit.committer.timestamp.to_string();
+            });
+            paginate(tree.tree_items, page).items
+        };
+
+        let mut item_to_commit = HashMap::new();
+        self.add_trees_to_map(
+            &mut item_to_commit,
+            tree_items
+                .iter()
+                .filter(|x| x.mode == TreeItemMode::Tree)
+                .map(|x| x.id.to_string())
+                .collect(),
+        )
+        .await?;
+        self.add_blobs_to_map(
+            &mut item_to_commit,
+            tree_items
+                .iter()
+                .filter(|x| !matches!(x.mode, TreeItemMode::Tree | TreeItemMode::Commit))
+                .map(|x| x.id.to_string())
+                .collect(),
+        )
+        .await?;
+
+        let commit_ids: HashSet<String> = item_to_commit.values().cloned().collect();
+        let commit_map: HashMap<String, Commit> = self
+            .get_commits_by_hashes(commit_ids.into_iter().collect())
+            .await?
+            .into_iter()
+            .map(|x| (x.id.to_string(), x))
+            .collect();
+
+        let mut root_commit: Option<Commit> = None;
+        let mut items = Vec::new();
+        for item in tree_items {
+            let mut info: TreeCommitItem = item.clone().into();
+            if let Some(commit_id) = item_to_commit.get(&item.id.to_string()) {
+                let commit = match commit_map.get(commit_id) {
+                    Some(commit) => commit.clone(),
+                    None => {
+                        tracing::warn!("failed fecth commit: {}", commit_id);
+                        if root_commit.is_none() {
+                            root_commit = Some(self.get_root_commit().await?);
+                        }
+                        self.traverse_commit_history(&path, root_commit.clone().unwrap(), &item)
+                            .await?
                     }
-                    items.push(info);
-                }
-                // sort with type and date
-                items.sort_by(|a, b| {
-                    a.content_type
-                        .cmp(&b.content_type)
-                        .then(b.date.cmp(&a.date))
-                });
-                Ok(items)
+                };
+                info.oid = commit.id.to_string();
+                info.message = commit.format_message();
+                info.date = commit.committer.timestamp.to_string();
             }
-            None => Ok(Vec::new()),
+            info.size = sizes.get(&item.id.to_string()).copied();
+            items.push((info.size.unwrap_or_default(), info));
+        }
+
+        if !by_date {
+            // already sorted and paged
+            let items = items.into_iter().map(|(_, x)| x).collect();
+            return Ok(page_of(items, total, page));
         }
+        items.sort_by(|(s1, a), (s2, b)| options.compare(&a.list_entry(*s1), &b.list_entry(*s2)));
+        Ok(paginate(items, page).map(|(_, x)| x))
     }
 
-    fn convert_commit_to_info(&self, commit: Commit) -> Result<LatestCommitInfo, GitError> {
-        let message = commit.format_message();
-        let committer = UserInfo {
-            display_name: commit.committer.name,
-            ..Default::default()
-        };
-        let author = UserInfo {
-            display_name: commit.author.name,
-            ..Default::default()
-        };
+    /// Blob sizes of the listed items, shown with them and used to sort by size.
+    async fn get_item_sizes(&self, items: &[TreeItem]) -> Result<HashMap<String, usize>, GitError> {
+        let mut item_to_size = HashMap::new();
+        self.add_blob_sizes_to_map(
+            &mut item_to_size,
+            items
+                .iter()
+                .filter(|x| !matches!(x.mode, TreeItemMode::Tree | TreeItemMode::Commit))
+                .map(|x| x.id.to_string())
+                .collect(),
+        )
+        .await?;
+        Ok(item_to_size)
+    }
 
-        let res = LatestCommitInfo {
-            oid: commit.id.to_string(),
-            date: commit.committer.timestamp.to_string(),
-            short_message: message,
-            author,
-            committer,
-            status: "success".to_string(),
-        };
-        Ok(res)
+    /// Committer timestamp of the commit that last touched each item.
+    async fn get_item_dates(&self, items: &[TreeItem]) -> Result<HashMap<String, usize>, GitError> {
+        let mut item_to_commit = HashMap::new();
+        let (trees, blobs): (Vec<&TreeItem>, Vec<&TreeItem>) =
+            items.iter().partition(|x| x.mode == TreeItemMode::Tree);
+        self.add_trees_to_map(
+            &mut item_to_commit,
+            trees.iter().map(|x| x.id.to_string()).collect(),
+        )
+        .await?;
+        self.add_blobs_to_map(
+            &mut item_to_commit,
+            blobs.iter().map(|x| x.id.to_string()).collect(),
+        )
+        .await?;
+        let commit_ids: HashSet<String> = item_to_commit.values().cloned().collect();
+        let commit_dates: HashMap<String, usize> = self
+            .get_commits_by_hashes(commit_ids.into_iter().collect())
+            .await?
+            .into_iter()
+            .map(|x| (x.id.to_string(), x.committer.timestamp))
+            .collect();
+        Ok(item_to_commit
+            .into_iter()
+            .filter_map(|(item, commit)| commit_dates.get(&commit).map(|date| (item, *date)))
+            .collect())
+    }
+
+    async fn convert_commit_to_info(&self, commit: Commit) -> Result<LatestCommitInfo, GitError> {
+        Ok(self.convert_commits_to_info(vec![commit]).await?.remove(0))
+    }
+
+    /// Infos of a page of commits, the users behind their signatures are looked up at once.
+    async fn convert_commits_to_info(
+        &self,
+        commits: Vec<Commit>,
+    ) -> Result<Vec<LatestCommitInfo>, GitError> {
+        let signs: Vec<&Signature> = commits
+            .iter()
+            .flat_map(|x| [&x.author, &x.committer])
+            .collect();
+        let users = self.find_signers(&signs).await;
+        let mut infos = Vec::with_capacity(commits.len());
+        for commit in commits {
+            infos.push(LatestCommitInfo {
+                oid: commit.id.to_string(),
+                date: commit.committer.timestamp.to_string(),
+                short_message: commit.format_message(),
+                author: user_info(&users, &commit.author),
+                committer: user_info(&users, &commit.committer),
+                status: "success".to_string(),
+                verified: self.is_verified(&commit.id.to_string()).await,
+            });
+        }
+        Ok(infos)
+    }
+
+    /// Whether the commit is signed and its signature was verified when it was pushed.
+    async fn is_verified(&self, commit_id: &str) -> bool {
+        let signature = self
+            .get_context()
+            .signature_stg()
+            .get_commit_signature(commit_id)
+            .await;
+        matches!(signature, Ok(Some(x)) if x.verified)
+    }
+
+    /// Link a commit signature to the mega user with the same email, so the profile name
+    /// and avatar can be shown, falls back to the name in the signature.
+    async fn get_user_info(&self, sign: &Signature) -> UserInfo {
+        user_info(&self.find_signers(&[sign]).await, sign)
+    }
+
+    /// Mega users with the emails of `signs` by email, found with a single query. Signatures
+    /// are shown without a profile when the lookup fails.
+    async fn find_signers(&self, signs: &[&Signature]) -> HashMap<String, user::Model> {
+        let emails: HashSet<String> = signs.iter().map(|x| x.email.clone()).collect();
+        self.get_context()
+            .user_stg()
+            .find_users_by_emails(emails.into_iter().collect())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| (x.email.clone(), x))
+            .collect()
     }
 
     /// Searches for a tree in the Git repository by its path and returns the trees involved in the update and the target tree.
@@ -235,7 +566,7 @@ pub trait ApiHandler: Send + Sync {
     /// Returns a `GitError` if the path does not exist.
     async fn search_tree_for_update(&self, path: &Path) -> Result<(Vec<Tree>, Tree), GitError> {
         let relative_path = self.strip_relative(path)?;
-        let root_tree = self.get_root_tree().await;
+        let root_tree = self.get_root_tree().await?;
         let mut search_tree = root_tree.clone();
         let mut update_tree = vec![root_tree];
 
@@ -249,13 +580,14 @@ pub trait ApiHandler: Send + Sync {
                     .find(|x| x.name == target_name);
 
                 if let Some(search_res) = search_res {
-                    let res = self.get_tree_by_hash(&search_res.id.to_string()).await;
+                    let res = self.get_tree_by_hash(&search_res.id.to_string()).await?;
                     search_tree = res.clone();
                     update_tree.push(res);
                 } else {
-                    return Err(GitError::CustomError(
-                        "Path not exist, please create path first!".to_string(),
-                    ));
+                    return Err(GitError::InvalidPathError(format!(
+                        "{} doesn't exist, please create it first",
+                        path.display()
+                    )));
                 }
             }
         }
@@ -277,8 +609,9 @@ pub trait ApiHandler: Send + Sync {
     ///
     /// * `Result<Option<Tree>, GitError>` - A result containing an optional tree or a Git error.
     async fn search_tree_by_path(&self, path: &Path) -> Result<Option<Tree>, GitError> {
-        let relative_path = self.strip_relative(path)?;
-        let root_tree = self.get_root_tree().await;
+        let path = MonoPath::try_from(path)?;
+        let relative_path = self.strip_relative(path.as_path())?;
+        let root_tree = self.get_root_tree().await?;
         let mut search_tree = root_tree.clone();
         for component in relative_path.components() {
             // root tree already found
@@ -288,11 +621,16 @@ pub trait ApiHandler: Send + Sync {
                     .tree_items
                     .iter()
                     .find(|x| x.name == target_name);
-                if let Some(search_res) = search_res {
-                    let res = self.get_tree_by_hash(&search_res.id.to_string()).await;
-                    search_tree = res.clone();
-                } else {
-                    return Ok(None);
+                match search_res {
+                    Some(item) if item.mode == TreeItemMode::Tree => {
+                        let res = self.get_tree_by_hash(&item.id.to_string()).await?;
+                        search_tree = res.clone();
+                    }
+                    // never resolve a path through a symlink or submodule
+                    Some(item) if is_opaque_entry(item) => {
+                        return Err(not_traversable(path.as_str(), item));
+                    }
+                    _ => return Ok(None),
                 }
             }
         }
@@ -314,8 +652,9 @@ pub trait ApiHandler: Send + Sync {
     ///
     /// Returns a `GitError` if an error occurs during the search or tree creation process.
     async fn search_and_create_tree(&self, path: &Path) -> Result<VecDeque<Tree>, GitError> {
-        let relative_path = self.strip_relative(path)?;
-        let root_tree = self.get_root_tree().await;
+        let path = MonoPath::try_from(path)?;
+        let relative_path = self.strip_relative(path.as_path())?;
+        let root_tree = self.get_root_tree().await?;
         let mut search_tree = root_tree.clone();
         let mut update_item_tree = VecDeque::new();
         update_item_tree.push_back((root_tree, Component::RootDir));
@@ -333,7 +672,10 @@ pub trait ApiHandler: Send + Sync {
                 .iter()
                 .find(|x| x.name == target_name)
             {
-                search_tree = self.get_tree_by_hash(&search_res.id.to_string()).await;
+                if search_res.mode != TreeItemMode::Tree {
+                    return Err(not_traversable(path.as_str(), search_res));
+                }
+                search_tree = self.get_tree_by_hash(&search_res.id.to_string()).await?;
                 update_item_tree.push_back((search_tree.clone(), component));
             } else {
                 stack.push_back(component);
@@ -345,8 +687,7 @@ pub trait ApiHandler: Send + Sync {
             mode: TreeItemMode::Blob,
             id: blob.id,
             name: String::from(".gitkeep"),
-        }])
-        .unwrap();
+        }])?;
         let mut last_tree_name = "";
         let mut first_element = true;
 
@@ -358,8 +699,7 @@ pub trait ApiHandler: Send + Sync {
                     mode: TreeItemMode::Tree,
                     id: last_tree.id,
                     name: last_tree_name.to_owned(),
-                }])
-                .unwrap();
+                }])?;
             }
             saving_trees.push_back(last_tree.clone());
             last_tree_name = component.as_os_str().to_str().unwrap();
@@ -371,7 +711,7 @@ pub trait ApiHandler: Send + Sync {
                 id: last_tree.id,
                 name: last_tree_name.to_owned(),
             });
-            last_tree = Tree::from_tree_items(new_item_tree.tree_items).unwrap();
+            last_tree = Tree::from_tree_items(new_item_tree.tree_items)?;
             saving_trees.push_back(last_tree.clone());
 
             let mut replace_hash = last_tree.id;
@@ -379,7 +719,7 @@ pub trait ApiHandler: Send + Sync {
             while let Some((mut tree, component)) = update_item_tree.pop_back() {
                 if let Some(index) = tree.tree_items.iter().position(|x| x.name == search_name) {
                     tree.tree_items[index].id = replace_hash;
-                    let new_tree = Tree::from_tree_items(tree.tree_items).unwrap();
+                    let new_tree = Tree::from_tree_items(tree.tree_items)?;
                     replace_hash = new_tree.id;
                     search_name = component.as_os_str().to_str().unwrap();
                     saving_trees.push_back(new_tree);
@@ -396,7 +736,7 @@ pub trait ApiHandler: Send + Sync {
         path: &Path,
         target: &TreeItem,
     ) -> Result<bool, GitError> {
-        let relative_path = self.strip_relative(path).unwrap();
+        let relative_path = self.strip_relative(path)?;
         let mut search_tree = root_tree.clone();
         // first find search tree by path
         for component in relative_path.components() {
@@ -408,7 +748,7 @@ pub trait ApiHandler: Send + Sync {
                     .iter()
                     .find(|x| x.name == target_name);
                 if let Some(search_res) = search_res {
-                    search_tree = self.get_tree_by_hash(&search_res.id.to_string()).await;
+                    search_tree = self.get_tree_by_hash(&search_res.id.to_string()).await?;
                 } else {
                     return Ok(false);
                 }
@@ -421,3 +761,72 @@ pub trait ApiHandler: Send + Sync {
         Ok(false)
     }
 }
+
+/// Profile of the user with the email of `sign` in `users`, or the name in the signature.
+fn user_info(users: &HashMap<String, user::Model>, sign: &Signature) -> UserInfo {
+    match users.get(&sign.email) {
+        Some(user) => UserInfo {
+            display_name: user.name.clone(),
+            avatar_url: user.avatar_url.clone(),
+        },
+        None => UserInfo {
+            display_name: sign.name.clone(),
+            ..Default::default()
+        },
+    }
+}
+
+/// Symlinks and submodules look like leaves in a tree but stand for another location.
+fn is_opaque_entry(item: &TreeItem) -> bool {
+    matches!(item.mode, TreeItemMode::Link | TreeItemMode::Commit)
+}
+
+fn not_traversable(path: &str, item: &TreeItem) -> GitError {
+    let kind = match item.mode {
+        TreeItemMode::Link => "symlink",
+        TreeItemMode::Commit => "submodule",
+        _ => "file",
+    };
+    GitError::InvalidPathError(format!("{} (`{}` is a {})", path, item.name, kind))
+}
+
+/// Cut one page out of a sorted listing, the whole listing is one page without `page`.
+fn paginate<T>(items: Vec<T>, page: Option<Pagination>) -> Page<T> {
+    let total = items.len() as u64;
+    let items = match page {
+        Some(page) => items
+            .into_iter()
+            .skip(page.offset() as usize)
+            .take(page.per_page as usize)
+            .collect(),
+        None => items,
+    };
+    page_of(items, total, page)
+}
+
+fn page_of<T>(items: Vec<T>, total: u64, page: Option<Pagination>) -> Page<T> {
+    match page {
+        Some(page) => Page::new(items, total, &page),
+        None => Page {
+            items,
+            total,
+            page: 1,
+            per_page: total,
+        },
+    }
+}
+
+/// Sort fields of a tree item, sizes and dates are looked up by object id.
+fn list_entry<'a>(
+    item: &'a TreeItem,
+    sizes: &HashMap<String, usize>,
+    dates: &HashMap<String, usize>,
+) -> ListEntry<'a> {
+    let id = item.id.to_string();
+    ListEntry {
+        name: &item.name,
+        is_dir: item.mode == TreeItemMode::Tree,
+        size: sizes.get(&id).copied().unwrap_or_default(),
+        date: dates.get(&id).copied().unwrap_or_default(),
+    }
+}
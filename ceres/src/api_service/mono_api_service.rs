@@ -1,30 +1,82 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::{env, fs};
+use std::sync::Arc;
+use std::{env, fs, io};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use tokio::process::Command;
+use tokio_stream::wrappers::ReceiverStream;
 
-use callisto::db_enums::ConvType;
-use callisto::{mega_blob, mega_tree, raw_blob};
+use callisto::db_enums::{CheckConclusion, ConvType, MergeStatus, ReviewState};
+use callisto::{mega_blob, mega_refs, mega_tag, mega_tree};
 use common::errors::MegaError;
+use common::model::CursorPage;
+use common::path::{normalize_name, MonoPath};
+use common::utils::{self, branch_ref_name, validate_ref_name, MEGA_DEFAULT_BRANCH, ZERO_ID};
 use jupiter::context::Context;
 use jupiter::storage::batch_save_model;
+use jupiter::storage::protection_storage::rule_names;
+use jupiter::storage::search_storage::trigrams;
 use jupiter::utils::converter::generate_git_keep_with_timestamp;
+use mercury::diff::{find_similar, merge_blobs, BlobMerge, MergeLabels};
 use mercury::errors::GitError;
 use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::Signature;
+use mercury::internal::object::tag::Tag;
 use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::tree_diff::diff_trees;
+use taurus::event::dir_stats::DirStatsEvent;
+use taurus::event::pack_cache::PackCacheEvent;
+use taurus::event::search_index::SearchIndexEvent;
 
+use crate::api_service::archive::{
+    ArchiveFormat, ArchiveWriter, MAX_ARCHIVE_ENTRIES, MAX_ARCHIVE_SIZE,
+};
+use crate::api_service::tree_ops::{self, load_tree, TreeChange};
 use crate::api_service::ApiHandler;
-use crate::model::create_file::CreateFileInfo;
-use crate::protocol::mr::MergeRequest;
+use crate::model::branch::{BranchInfo, BranchPath};
+use crate::model::commit::{CherryPickRequest, CommitDetail, CommitPerson, CommitResult};
+use crate::model::create_file::{
+    BatchCommitInfo, CreateFileInfo, DeleteEntryInfo, FileOperation, MoveEntryInfo, UpdateFileInfo,
+    MAX_BATCH_OPERATIONS,
+};
+use crate::model::diff::{
+    ChangedFile, CommitDiff, Compare, CompareSpec, DiffStat, FileChangeKind, FileDiff, FileStat,
+    MAX_COMPARE_COMMITS, MAX_DIFF_FILES, MAX_RENAME_PAIRS, RENAME_THRESHOLD,
+};
+use crate::model::search::SearchMatch;
+use crate::model::tag::{CreateTagRequest, TagInfo};
+use crate::model::tree::LatestCommitInfo;
+use crate::pack::commit_message;
+use crate::pack::monorepo::MonoRepo;
+use crate::pack::PackHandler;
+use crate::protocol::mr::{MergeOperation, MergeRequest, MergeResult};
+use crate::protocol::PushOptions;
+
+/// Upper bound of commits visited by one history request.
+const MAX_HISTORY_SCAN: usize = 2000;
+/// Upper bound of commits listed for, and replayed from, one merge request.
+const MAX_MR_COMMITS: usize = 250;
+/// Blobs loaded per query while writing an archive.
+const ARCHIVE_BLOB_BATCH: usize = 100;
+/// Search index candidates loaded per query.
+const SEARCH_BATCH: usize = 100;
+/// Upper bound of search index candidates checked by one search request.
+const MAX_SEARCH_SCAN: usize = 1000;
+/// Times a write is started over after a concurrent write moved the root ref under it.
+const REF_CONFLICT_RETRIES: usize = 3;
 
 #[derive(Clone)]
 pub struct MonoApiService {
     pub context: Context,
+    /// The user the service acts for, author of the commits it makes. Commits are
+    /// authored by the mega bot when unset.
+    pub author: Option<Signature>,
 }
 
 #[async_trait]
@@ -41,64 +93,190 @@ impl ApiHandler for MonoApiService {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success, or a `GitError` on failure.
+    /// Returns `Ok(())` on success, `GitError::EntryExists` if the name is already taken in
+    /// the directory, `GitError::InvalidArgument` for an illegal path or name, or another
+    /// `GitError` on failure.
     async fn create_monorepo_file(&self, file_info: CreateFileInfo) -> Result<(), GitError> {
+        // the blobs, the trees and the moved refs are written together or not at all
+        let mut retries = 0;
+        loop {
+            let file_info = file_info.clone();
+            let res = self
+                .context
+                .transaction(|context| async move {
+                    let service = MonoApiService {
+                        context,
+                        ..self.clone()
+                    };
+                    service.create_file(file_info).await
+                })
+                .await;
+            match res {
+                Err(GitError::RefConflict(msg)) if retries < REF_CONFLICT_RETRIES => {
+                    tracing::info!("retry creating file: {}", msg);
+                    retries += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    fn strip_relative(&self, path: &Path) -> Result<PathBuf, GitError> {
+        Ok(path.to_path_buf())
+    }
+
+    async fn get_root_commit(&self) -> Result<Commit, GitError> {
+        let refs = self.root_ref().await?;
+        self.get_mega_commit(&refs.ref_commit_hash).await
+    }
+
+    async fn get_root_tree(&self) -> Result<Tree, GitError> {
+        let refs = self.root_ref().await?;
+        self.get_tree_by_hash(&refs.ref_tree_hash).await
+    }
+
+    async fn get_tree_by_hash(&self, hash: &str) -> Result<Tree, GitError> {
+        self.context
+            .services
+            .mono_storage
+            .get_tree_by_hash(hash)
+            .await?
+            .map(Tree::from)
+            .ok_or_else(|| GitError::ObjectNotFound(hash.to_owned()))
+    }
+
+    async fn get_tree_relate_commit(&self, t_hash: &str) -> Result<Commit, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let tree_info = storage
+            .get_tree_by_hash(t_hash)
+            .await?
+            .ok_or_else(|| GitError::ObjectNotFound(t_hash.to_owned()))?;
+        self.get_mega_commit(&tree_info.commit_id).await
+    }
+
+    async fn add_trees_to_map(
+        &self,
+        item_to_commit: &mut HashMap<String, String>,
+        hashes: Vec<String>,
+    ) -> Result<(), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let trees = storage.get_trees_by_hashes(hashes).await?;
+        for tree in trees {
+            item_to_commit.insert(tree.tree_id, tree.commit_id);
+        }
+        Ok(())
+    }
+
+    async fn add_blobs_to_map(
+        &self,
+        item_to_commit: &mut HashMap<String, String>,
+        hashes: Vec<String>,
+    ) -> Result<(), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let blobs = storage.get_mega_blobs_by_hashes(hashes).await?;
+        for blob in blobs {
+            item_to_commit.insert(blob.blob_id, blob.commit_id);
+        }
+        Ok(())
+    }
+
+    async fn add_blob_sizes_to_map(
+        &self,
+        item_to_size: &mut HashMap<String, usize>,
+        hashes: Vec<String>,
+    ) -> Result<(), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let blobs = storage.get_mega_blobs_by_hashes(hashes).await?;
+        for blob in blobs {
+            item_to_size.insert(blob.blob_id, blob.size as usize);
+        }
+        Ok(())
+    }
+
+    async fn get_commits_by_hashes(&self, c_hashes: Vec<String>) -> Result<Vec<Commit>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let commits = storage.get_commits_by_hashes(&c_hashes).await?;
+        Ok(commits.into_iter().map(|x| x.into()).collect())
+    }
+
+    async fn traverse_commit_history(
+        &self,
+        _: &Path,
+        _: Commit,
+        _: &TreeItem,
+    ) -> Result<Commit, GitError> {
+        unreachable!()
+    }
+}
+
+impl MonoApiService {
+    /// Add the file or directory of `file_info` on top of the root ref, see
+    /// [`ApiHandler::create_monorepo_file`].
+    async fn create_file(&self, file_info: CreateFileInfo) -> Result<(), GitError> {
         let storage = self.context.services.mono_storage.clone();
-        let path = PathBuf::from(file_info.path);
+        let path = MonoPath::parse(&file_info.path)?.to_path_buf();
+        let name = normalize_name(&file_info.name)?;
         let mut save_trees = vec![];
 
         // Search for the tree to update and get its tree items
         let (update_trees, search_tree) = self.search_tree_for_update(&path).await?;
         let mut t_items = search_tree.tree_items;
 
+        // a file and a directory can't share a name either
+        if t_items.iter().any(|x| x.name == name) {
+            return Err(GitError::EntryExists(
+                path.join(&name).to_string_lossy().into_owned(),
+            ));
+        }
+
         // Create a new tree item based on whether it's a directory or file
         let new_item = if file_info.is_directory {
-            if t_items
-                .iter()
-                .any(|x| x.mode == TreeItemMode::Tree && x.name == file_info.name)
-            {
-                return Err(GitError::CustomError("Duplicate name".to_string()));
-            }
             let blob = generate_git_keep_with_timestamp();
             let tree_item = TreeItem {
                 mode: TreeItemMode::Blob,
                 id: blob.id,
                 name: String::from(".gitkeep"),
             };
-            let child_tree = Tree::from_tree_items(vec![tree_item]).unwrap();
+            let child_tree = Tree::from_tree_items(vec![tree_item])?;
             save_trees.push(child_tree.clone());
             TreeItem {
                 mode: TreeItemMode::Tree,
                 id: child_tree.id,
-                name: file_info.name.clone(),
+                name: name.clone(),
             }
         } else {
-            let content = file_info.content.unwrap();
+            let content = file_info.content.unwrap_or_default();
             let blob = Blob::from_content(&content);
             let mega_blob: mega_blob::ActiveModel = Into::<mega_blob::Model>::into(&blob).into();
-            let raw_blob: raw_blob::ActiveModel =
-                Into::<raw_blob::Model>::into(blob.clone()).into();
 
-            let conn = storage.get_connection();
-            batch_save_model(conn, vec![mega_blob]).await.unwrap();
-            batch_save_model(conn, vec![raw_blob]).await.unwrap();
+            batch_save_model(storage.get_connection(), vec![mega_blob]).await?;
+            storage.save_raw_blob(blob.clone().into()).await?;
             TreeItem {
                 mode: TreeItemMode::Blob,
                 id: blob.id,
-                name: file_info.name.clone(),
+                name: name.clone(),
             }
         };
         // Add the new item to the tree items and create a new tree
         t_items.push(new_item);
-        let p_tree = Tree::from_tree_items(t_items).unwrap();
+        let p_tree = Tree::from_tree_items(t_items)?;
 
         // Create a commit for the new tree
-        let refs = storage.get_ref("/").await.unwrap().unwrap();
-        let commit = Commit::from_tree_id(
+        let refs = self.root_ref().await?;
+        let mut commit = Commit::from_tree_id(
             p_tree.id,
             vec![SHA1::from_str(&refs.ref_commit_hash).unwrap()],
-            &format!("\ncreate file {} commit", file_info.name),
+            &format!("\ncreate file {} commit", name),
         );
+        if let Some(author) = self.author.clone() {
+            commit = Commit::new(
+                author,
+                commit.committer,
+                commit.tree_id,
+                commit.parent_commit_ids,
+                &commit.message,
+            );
+        }
 
         // Update the parent tree with the new commit
         let commit_id = self.update_parent_tree(path, update_trees, commit).await?;
@@ -112,136 +290,737 @@ impl ApiHandler for MonoApiService {
                 tree_model.into()
             })
             .collect();
-        batch_save_model(storage.get_connection(), save_trees)
-            .await
-            .unwrap();
+        batch_save_model(storage.get_connection(), save_trees).await?;
         Ok(())
     }
 
-    fn strip_relative(&self, path: &Path) -> Result<PathBuf, GitError> {
-        Ok(path.to_path_buf())
+    /// Checks of `required` whose latest run on the head commit of the MR is missing, still
+    /// running or didn't succeed.
+    async fn failing_checks(
+        &self,
+        mr: &MergeRequest,
+        required: &[String],
+    ) -> Result<Vec<String>, MegaError> {
+        if required.is_empty() {
+            return Ok(vec![]);
+        }
+        // newest first, so the first run of a name is its latest one
+        let runs = self
+            .context
+            .check_stg()
+            .get_check_runs_by_commit(&mr.to_hash)
+            .await?;
+        Ok(required
+            .iter()
+            .filter(|name| {
+                !runs.iter().find(|x| x.name == **name).is_some_and(|x| {
+                    matches!(
+                        x.conclusion,
+                        Some(
+                            CheckConclusion::Success
+                                | CheckConclusion::Neutral
+                                | CheckConclusion::Skipped
+                        )
+                    )
+                })
+            })
+            .cloned()
+            .collect())
     }
 
-    async fn get_root_commit(&self) -> Commit {
-        unreachable!()
+    /// Merge the changes of a merge request into the monorepo.
+    ///
+    /// When the path hasn't moved since the MR was opened its tree is taken as is, otherwise
+    /// the MR is merged three-way against the current root tree: paths are compared first,
+    /// files changed on both sides are merged line by line, and the remaining conflicts are
+    /// reported without writing anything.
+    ///
+    /// `operation` decides whether the commits of the MR are replayed one by one or squashed,
+    /// a diverged MR is always merged as a single commit.
+    ///
+    /// The MR is refused until it has the approvals configured for its path and the required
+    /// checks passed on its head commit, the checks of the protection rule of the target
+    /// branch included. A rule listing allowed mergers also refuses everyone else, a draft
+    /// MR isn't merged at all and one depending on another waits until that's merged.
+    pub async fn merge_mr(
+        &self,
+        mr: &mut MergeRequest,
+        operation: MergeOperation,
+        merger: &str,
+    ) -> Result<MergeResult, MegaError> {
+        if mr.draft {
+            return Err(MegaError::with_message(
+                "the MR is a draft, mark it ready before merging",
+            ));
+        }
+        if let Some(depends_on) = &mr.depends_on {
+            let merged = self
+                .context
+                .mr_stg()
+                .get_mr(depends_on)
+                .await?
+                .is_some_and(|x| x.status == MergeStatus::Merged);
+            if !merged {
+                return Err(MegaError::with_message(&format!(
+                    "the MR depends on MR {}, merge that first",
+                    depends_on
+                )));
+            }
+        }
+        let rule = self
+            .context
+            .protection_stg()
+            .find_rule(&mr.path, &mr.target_branch)
+            .await?;
+        let mut required_checks = self
+            .context
+            .config
+            .monorepo
+            .required_checks(&mr.path)
+            .to_vec();
+        if let Some(rule) = &rule {
+            let mergers = rule_names(&rule.allowed_mergers);
+            if !mergers.is_empty() && !mergers.iter().any(|x| x == merger) {
+                return Err(MegaError::with_message(&format!(
+                    "{} isn't allowed to merge into the protected {} of {}",
+                    merger, mr.target_branch, mr.path
+                )));
+            }
+            for name in rule_names(&rule.required_checks) {
+                if !required_checks.contains(&name) {
+                    required_checks.push(name);
+                }
+            }
+        }
+        let required = self.context.config.monorepo.required_approvals(&mr.path);
+        if required > 0 {
+            let approvals = self
+                .context
+                .mr_stg()
+                .get_mr_reviews(&mr.link)
+                .await?
+                .iter()
+                .filter(|x| x.state == ReviewState::Approved)
+                .count();
+            if approvals < required {
+                return Err(MegaError::with_message(&format!(
+                    "{} approvals required, {} given",
+                    required, approvals
+                )));
+            }
+        }
+        let failing = self.failing_checks(mr, &required_checks).await?;
+        if !failing.is_empty() {
+            return Err(MegaError::with_message(&format!(
+                "required checks haven't passed: {}",
+                failing.join(", ")
+            )));
+        }
+        // commits of MRs opened before the rule was added or on other paths
+        if let Some(rule) = self.context.config.monorepo.commit_message_rule(&mr.path) {
+            let commits = self
+                .get_mr_commits(mr)
+                .await
+                .map_err(|e| MegaError::with_message(&e.to_string()))?;
+            commit_message::check(rule, &commits).map_err(|e| MegaError::with_message(&e))?;
+        }
+        // the refs, trees and the MR are written together or not at all
+        if mr.target_branch != MEGA_DEFAULT_BRANCH {
+            let forbid_force_update = rule.is_some_and(|x| x.forbid_force_update);
+            return self
+                .context
+                .transaction(|context| async move {
+                    let service = MonoApiService {
+                        context,
+                        ..self.clone()
+                    };
+                    service.merge_into_branch(mr, forbid_force_update).await?;
+                    service.finish_merge(mr).await
+                })
+                .await;
+        }
+        let path = mr.path.clone();
+        let mut retries = 0;
+        let res = loop {
+            let merging = &mut *mr;
+            let res = self
+                .context
+                .transaction(|context| async move {
+                    let service = MonoApiService {
+                        context,
+                        ..self.clone()
+                    };
+                    service.merge_into_main(merging, operation).await
+                })
+                .await;
+            match res {
+                // another merge moved the root ref, merge onto the new one
+                Err(e) if e.is_ref_conflict() && retries < REF_CONFLICT_RETRIES => {
+                    tracing::info!("retry merging {}: {}", mr.link, e);
+                    retries += 1;
+                }
+                res => break res?,
+            }
+        };
+        // warm the pack cache once the merge is committed
+        if res.merged {
+            let root = self.root_ref().await?;
+            PackCacheEvent::notify(&path, &root.ref_commit_hash);
+        }
+        Ok(res)
     }
 
-    async fn get_root_tree(&self) -> Tree {
+    /// Apply the MR onto the monorepo at its path, replaying its commits when the path
+    /// hasn't moved since the MR was opened and merging three ways otherwise.
+    async fn merge_into_main(
+        &self,
+        mr: &mut MergeRequest,
+        operation: MergeOperation,
+    ) -> Result<MergeResult, MegaError> {
         let storage = self.context.services.mono_storage.clone();
-        let refs = storage.get_ref("/").await.unwrap().unwrap();
+        let refs = storage.get_ref(&mr.path).await?.ok_or_else(|| {
+            MegaError::with_message(&format!("{} has no ref to merge into", mr.path))
+        })?;
 
-        storage
-            .get_tree_by_hash(&refs.ref_tree_hash)
-            .await
-            .unwrap()
-            .unwrap()
-            .into()
+        if mr.from_hash == refs.ref_commit_hash {
+            let commit = self.get_mega_commit(&mr.to_hash).await?;
+
+            if mr.path != "/" {
+                let path = PathBuf::from(mr.path.clone());
+                let commits = match operation {
+                    MergeOperation::Merge => self.get_mr_commits(mr).await?,
+                    MergeOperation::Squash => vec![self.squash_commit(mr, commit).await],
+                };
+                for commit in commits {
+                    // beacuse only parent tree is needed so we skip current directory
+                    let (tree_vec, _) = self.search_tree_for_update(path.parent().unwrap()).await?;
+                    self.update_parent_tree(path.clone(), tree_vec, commit)
+                        .await?;
+                }
+                // remove refs start with path
+                storage.remove_refs(&mr.path).await?;
+                // the MR commits rewritten onto the monorepo are left to garbage collection
+            }
+        } else {
+            let res = self.three_way_merge(mr).await?;
+            if !res.conflicts.is_empty() {
+                return Ok(MergeResult::conflict(res.conflicts));
+            }
+            if mr.path != "/" {
+                storage.remove_refs(&mr.path).await?;
+            }
+        }
+        self.finish_merge(mr).await
+    }
+
+    /// Merge requests between named branches move the target branch to the head of the
+    /// source, the commits of the source branch are kept as they are. The target must not
+    /// have moved since the MR was opened, and with `forbid_force_update` the source must
+    /// contain it.
+    async fn merge_into_branch(
+        &self,
+        mr: &MergeRequest,
+        forbid_force_update: bool,
+    ) -> Result<(), MegaError> {
+        let storage = self.context.services.mono_storage.clone();
+        let Some(mut target) = storage
+            .get_ref_by_name(&mr.path, &branch_ref_name(&mr.target_branch))
+            .await?
+        else {
+            return Err(MegaError::with_message(&format!(
+                "branch {} doesn't exist",
+                mr.target_branch
+            )));
+        };
+        if target.ref_commit_hash != mr.from_hash {
+            return Err(MegaError::with_message(&format!(
+                "{} has moved since the merge request was opened",
+                mr.target_branch
+            )));
+        }
+        if forbid_force_update {
+            let path =
+                MonoPath::parse(&mr.path).map_err(|e| MegaError::with_message(&e.to_string()))?;
+            let forward = self
+                .mono_repo(&path)
+                .is_ancestor(&mr.from_hash, &mr.to_hash)
+                .await
+                .map_err(|e| MegaError::with_message(&e.to_string()))?;
+            if !forward {
+                return Err(MegaError::with_message(&format!(
+                    "{} is protected, the merge request doesn't contain its head",
+                    mr.target_branch
+                )));
+            }
+        }
+        let commit: Commit = storage
+            .get_commit_by_hash(&mr.to_hash)
+            .await?
+            .ok_or_else(|| MegaError::with_message(&format!("commit {} not found", mr.to_hash)))?
+            .into();
+        target.ref_commit_hash = mr.to_hash.clone();
+        target.ref_tree_hash = commit.tree_id.to_string();
+        storage.update_ref(target, &mr.from_hash).await
     }
 
-    async fn get_tree_by_hash(&self, hash: &str) -> Tree {
+    async fn finish_merge(&self, mr: &mut MergeRequest) -> Result<MergeResult, MegaError> {
+        // update mr
+        mr.merge();
+        // add conversation
         self.context
-            .services
-            .mono_storage
-            .get_tree_by_hash(hash)
-            .await
-            .unwrap()
-            .unwrap()
-            .into()
+            .mr_stg()
+            .add_mr_conversation(&mr.link, 0, ConvType::Merged, None)
+            .await?;
+        // update mr status last
+        self.context.mr_stg().update_mr(mr.clone().into()).await?;
+        Ok(MergeResult::merged())
+    }
+
+    /// Commits pushed to a merge request, oldest first, following first parents from the
+    /// head back to the commit the MR was opened on.
+    pub async fn get_mr_commits(&self, mr: &MergeRequest) -> Result<Vec<Commit>, GitError> {
+        let mut commits = Vec::new();
+        let mut next = Some(mr.to_hash.clone());
+        while let Some(hash) = next {
+            if hash == mr.from_hash || commits.len() >= MAX_MR_COMMITS {
+                break;
+            }
+            let commit = self.get_mega_commit(&hash).await?;
+            next = commit.parent_commit_ids.first().map(|x| x.to_string());
+            commits.push(commit);
+        }
+        commits.reverse();
+        Ok(commits)
+    }
+
+    /// One commit with the tree of the MR head, listing the subjects of all its commits.
+    async fn squash_commit(&self, mr: &MergeRequest, head: Commit) -> Commit {
+        let commits = match self.get_mr_commits(mr).await {
+            Ok(commits) if commits.len() > 1 => commits,
+            _ => return head,
+        };
+        let subjects: Vec<String> = commits
+            .iter()
+            .map(|x| format!("* {}", x.format_message()))
+            .collect();
+        let message = format!("\n{}\n\n{}\n", mr.title, subjects.join("\n"));
+        Commit::new(head.author, head.committer, head.tree_id, vec![], &message)
     }
 
-    async fn get_tree_relate_commit(&self, t_hash: &str) -> Commit {
+    /// Replay the changes between `from_hash` and `to_hash` onto the current root tree.
+    async fn three_way_merge(&self, mr: &MergeRequest) -> Result<CommitResult, GitError> {
         let storage = self.context.services.mono_storage.clone();
-        let tree_info = storage.get_tree_by_hash(t_hash).await.unwrap().unwrap();
-        storage
-            .get_commit_by_hash(&tree_info.commit_id)
-            .await
-            .unwrap()
-            .unwrap()
-            .into()
+        let base = self.get_mega_commit(&mr.from_hash).await?;
+        let head = self.get_mega_commit(&mr.to_hash).await?;
+        let changes = diff_trees(
+            &storage,
+            Some(&load_tree(&storage, &base.tree_id).await?),
+            Some(&load_tree(&storage, &head.tree_id).await?),
+            PathBuf::from(&mr.path),
+        )
+        .await?;
+
+        let (resolved, unresolved) = self.merge_changes(changes, "merge request").await?;
+        if !unresolved.is_empty() {
+            return Ok(CommitResult::conflict(unresolved));
+        }
+
+        let message = format!("Merge merge request {} into {}", mr.link, mr.path);
+        let author = Some(head.author.clone());
+        // the merge runs in a transaction already
+        self.write_changes(&resolved, author, &message).await
     }
 
-    async fn add_trees_to_map(
+    /// Fit `changes` to the current root tree, files changed on both sides are merged line
+    /// by line. Returns the changes to commit and the paths that still conflict, labelled
+    /// `theirs` in conflict markers.
+    async fn merge_changes(
         &self,
-        item_to_commit: &mut HashMap<String, String>,
-        hashes: Vec<String>,
-    ) {
+        changes: Vec<TreeChange>,
+        theirs: &str,
+    ) -> Result<(Vec<TreeChange>, Vec<String>), GitError> {
         let storage = self.context.services.mono_storage.clone();
-        let trees = storage.get_trees_by_hashes(hashes).await.unwrap();
-        for tree in trees {
-            item_to_commit.insert(tree.tree_id, tree.commit_id);
+        // tree level: paths changed on one side only
+        let root_ref = self.root_ref().await?;
+        let root_id = SHA1::from_str(&root_ref.ref_tree_hash).unwrap();
+        let root_tree = load_tree(&storage, &root_id).await?;
+        let res = tree_ops::apply_changes(&storage, root_tree, &changes).await?;
+        let conflicts: HashSet<PathBuf> = res.conflicts.into_iter().collect();
+
+        // blob level: files changed on both sides
+        let mut resolved = Vec::with_capacity(changes.len());
+        let mut unresolved = Vec::new();
+        for change in changes {
+            if !conflicts.contains(&change.path) {
+                resolved.push(change);
+                continue;
+            }
+            let current = tree_ops::entry_at_path(&storage, &root_id, &change.path).await?;
+            match self.merge_file(&change, current.as_ref(), theirs).await? {
+                Some(item) => resolved.push(TreeChange {
+                    path: change.path,
+                    old: current,
+                    new: Some(item),
+                }),
+                None => unresolved.push(change.path.to_string_lossy().into_owned()),
+            }
         }
+        Ok((resolved, unresolved))
     }
 
-    async fn add_blobs_to_map(
+    /// Merge the content of a file changed both by `change` and on the root tree, the
+    /// merged blob is saved and its entry returned, `None` if the changes overlap or both
+    /// sides changed a binary file.
+    async fn merge_file(
         &self,
-        item_to_commit: &mut HashMap<String, String>,
-        hashes: Vec<String>,
-    ) {
+        change: &TreeChange,
+        current: Option<&TreeItem>,
+        theirs_label: &str,
+    ) -> Result<Option<TreeItem>, GitError> {
+        let is_file =
+            |x: &TreeItem| matches!(x.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable);
+        let (Some(base), Some(theirs), Some(ours)) = (&change.old, &change.new, current) else {
+            return Ok(None);
+        };
+        if ![base, ours, theirs].into_iter().all(is_file) {
+            return Ok(None);
+        }
+        let blobs = self
+            .load_blobs([base, ours, theirs].map(|x| x.id.to_string()).to_vec())
+            .await?;
+        let [Some(base), Some(ours), Some(theirs_content)] =
+            [base, ours, theirs].map(|x| blobs.get(&x.id.to_string()))
+        else {
+            return Ok(None);
+        };
+        let labels = MergeLabels {
+            ours: "current",
+            base: "base",
+            theirs: theirs_label,
+        };
+        let BlobMerge::Clean(merged) = merge_blobs(base, ours, theirs_content, &labels) else {
+            return Ok(None);
+        };
+
+        let id = self.save_blob(merged).await?;
+        Ok(Some(TreeItem::new(theirs.mode, id, theirs.name.clone())))
+    }
+
+    async fn save_text_blob(&self, content: &str) -> Result<SHA1, GitError> {
+        self.save_blob(content.as_bytes().to_vec()).await
+    }
+
+    async fn save_blob(&self, content: Vec<u8>) -> Result<SHA1, GitError> {
+        let blob = Blob::from_content_bytes(content);
+        let mega_blob: mega_blob::ActiveModel = Into::<mega_blob::Model>::into(&blob).into();
+        let storage = &self.context.services.mono_storage;
+        batch_save_model(storage.get_connection(), vec![mega_blob]).await?;
+        storage.save_raw_blob(blob.clone().into()).await?;
+        Ok(blob.id)
+    }
+
+    /// Remove a file or a whole directory, committed on top of the root ref.
+    pub async fn delete_monorepo_entry(
+        &self,
+        entry_info: DeleteEntryInfo,
+    ) -> Result<CommitResult, GitError> {
         let storage = self.context.services.mono_storage.clone();
-        let blobs = storage.get_mega_blobs_by_hashes(hashes).await.unwrap();
-        for blob in blobs {
-            item_to_commit.insert(blob.blob_id, blob.commit_id);
+        let mono_path = MonoPath::parse(&entry_info.path)?;
+        if mono_path.is_root() {
+            return Err(GitError::CustomError(
+                "the root directory can't be deleted".to_string(),
+            ));
+        }
+        let path = mono_path.to_path_buf();
+        let root_ref = self.root_ref().await?;
+        let Some(current) = tree_ops::entry_at_path(
+            &storage,
+            &SHA1::from_str(&root_ref.ref_tree_hash).unwrap(),
+            &path,
+        )
+        .await?
+        else {
+            return Err(GitError::CustomError(format!(
+                "{} doesn't exist",
+                mono_path
+            )));
+        };
+
+        let is_dir = current.mode == TreeItemMode::Tree;
+        let tree_id = current.id.to_string();
+        let change = TreeChange {
+            path,
+            old: Some(current),
+            new: None,
+        };
+        let message = entry_info
+            .message
+            .filter(|x| !x.trim().is_empty())
+            .unwrap_or_else(|| format!("delete {}", mono_path));
+        let res = self.commit_changes(&[change], None, &message).await?;
+        if let Some(commit_id) = res.commit_id.as_ref().filter(|_| is_dir) {
+            storage
+                .save_tombstone(mono_path.as_str(), commit_id, &tree_id)
+                .await?;
+            self.remove_dir_refs(&mono_path).await?;
         }
+        Ok(res)
     }
 
-    async fn get_commits_by_hashes(&self, c_hashes: Vec<String>) -> Result<Vec<Commit>, GitError> {
+    /// Bring back the directory deleted as recorded by tombstone `id`, in a new commit on
+    /// top of the root ref. Nothing may have taken its path since.
+    pub async fn restore_tombstone(
+        &self,
+        id: i64,
+        message: Option<String>,
+    ) -> Result<CommitResult, GitError> {
         let storage = self.context.services.mono_storage.clone();
-        let commits = storage.get_commits_by_hashes(&c_hashes).await.unwrap();
-        Ok(commits.into_iter().map(|x| x.into()).collect())
+        let Some(tombstone) = storage.get_tombstone(id).await? else {
+            return Err(GitError::CustomError(format!("tombstone {} not found", id)));
+        };
+        if let Some(commit) = &tombstone.restored_commit {
+            return Err(GitError::CustomError(format!(
+                "{} was already restored by {}",
+                tombstone.path, commit
+            )));
+        }
+        let mono_path = MonoPath::parse(&tombstone.path)?;
+        let root_ref = self.root_ref().await?;
+        let root = SHA1::from_str(&root_ref.ref_tree_hash).unwrap();
+        if tree_ops::id_at_path(&storage, &root, &mono_path)
+            .await?
+            .is_some()
+        {
+            return Err(GitError::CustomError(format!(
+                "{} exists again, move it away before restoring",
+                mono_path
+            )));
+        }
+
+        let name = mono_path.segments().last().unwrap_or_default().to_owned();
+        let tree_id = SHA1::from_str(&tombstone.tree_id).unwrap();
+        let change = TreeChange {
+            path: mono_path.to_path_buf(),
+            old: None,
+            new: Some(TreeItem::new(TreeItemMode::Tree, tree_id, name)),
+        };
+        let message = message
+            .filter(|x| !x.trim().is_empty())
+            .unwrap_or_else(|| format!("restore {}", mono_path));
+        let res = self.commit_changes(&[change], None, &message).await?;
+        if let Some(commit_id) = &res.commit_id {
+            storage.set_tombstone_restored(tombstone, commit_id).await?;
+        }
+        Ok(res)
     }
 
-    async fn traverse_commit_history(&self, _: &Path, _: Commit, _: &TreeItem) -> Commit {
-        unreachable!()
+    /// Move or rename a file or a whole directory in a single commit on top of the root ref.
+    pub async fn move_monorepo_entry(
+        &self,
+        entry_info: MoveEntryInfo,
+    ) -> Result<CommitResult, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let from = MonoPath::parse(&entry_info.from)?;
+        let to = MonoPath::parse(&entry_info.to)?;
+        if from.is_root() || to.is_root() {
+            return Err(GitError::CustomError(
+                "the root directory can't be moved".to_string(),
+            ));
+        }
+        if to == from || to.to_string().starts_with(&format!("{}/", from)) {
+            return Err(GitError::CustomError(format!(
+                "{} can't be moved into itself",
+                from
+            )));
+        }
+        let root_ref = self.root_ref().await?;
+        let root = SHA1::from_str(&root_ref.ref_tree_hash).unwrap();
+        let Some(current) = tree_ops::entry_at_path(&storage, &root, &from.to_path_buf()).await?
+        else {
+            return Err(GitError::CustomError(format!("{} doesn't exist", from)));
+        };
+        if tree_ops::id_at_path(&storage, &root, &to).await?.is_some() {
+            return Err(GitError::CustomError(format!("{} already exists", to)));
+        }
+
+        let is_dir = current.mode == TreeItemMode::Tree;
+        let changes = [
+            TreeChange {
+                path: from.to_path_buf(),
+                old: Some(current.clone()),
+                new: None,
+            },
+            TreeChange {
+                path: to.to_path_buf(),
+                old: None,
+                new: Some(current),
+            },
+        ];
+        let message = entry_info
+            .message
+            .filter(|x| !x.trim().is_empty())
+            .unwrap_or_else(|| format!("move {} to {}", from, to));
+        let res = self.commit_changes(&changes, None, &message).await?;
+        if is_dir && res.commit_id.is_some() {
+            self.remove_dir_refs(&from).await?;
+        }
+        Ok(res)
     }
-}
 
-impl MonoApiService {
-    pub async fn merge_mr(&self, mr: &mut MergeRequest) -> Result<(), MegaError> {
+    /// Apply a list of file operations as a single commit on top of the root ref.
+    ///
+    /// Every operation is checked against the current tree first, the paths that can't be
+    /// applied are reported as conflicts and nothing is committed.
+    pub async fn batch_commit(&self, info: BatchCommitInfo) -> Result<CommitResult, GitError> {
+        if info.operations.is_empty() || info.operations.len() > MAX_BATCH_OPERATIONS {
+            return Err(GitError::CustomError(format!(
+                "a batch commit needs between 1 and {} operations",
+                MAX_BATCH_OPERATIONS
+            )));
+        }
+        if info.message.trim().is_empty() {
+            return Err(GitError::CustomError(
+                "commit message can't be empty".to_string(),
+            ));
+        }
         let storage = self.context.services.mono_storage.clone();
-        let refs = storage.get_ref(&mr.path).await.unwrap().unwrap();
+        let root_ref = self.root_ref().await?;
+        let root = SHA1::from_str(&root_ref.ref_tree_hash).unwrap();
 
-        if mr.from_hash == refs.ref_commit_hash {
-            let commit: Commit = storage
-                .get_commit_by_hash(&mr.to_hash)
-                .await
-                .unwrap()
-                .unwrap()
-                .into();
+        let is_file =
+            |x: &TreeItem| matches!(x.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable);
+        let mut paths = HashSet::new();
+        let mut changes = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut deleted_dirs = Vec::new();
+        for op in info.operations {
+            let mono_path = MonoPath::parse(op.path())?;
+            let Some(name) = mono_path.name().map(|x| x.to_owned()) else {
+                return Err(GitError::CustomError(
+                    "the root directory can't be changed".to_string(),
+                ));
+            };
+            if !paths.insert(mono_path.clone()) {
+                return Err(GitError::CustomError(format!(
+                    "{} is changed more than once",
+                    mono_path
+                )));
+            }
+            let path = mono_path.to_path_buf();
+            let current = tree_ops::entry_at_path(&storage, &root, &path).await?;
+            let change = match (op, current) {
+                (FileOperation::Create { content, .. }, None) => {
+                    let id = self.save_text_blob(&content).await?;
+                    TreeChange {
+                        path,
+                        old: None,
+                        new: Some(TreeItem::new(TreeItemMode::Blob, id, name)),
+                    }
+                }
+                (
+                    FileOperation::Update {
+                        content, old_oid, ..
+                    },
+                    Some(current),
+                ) if is_file(&current) && !old_oid.is_some_and(|x| x != current.id.to_string()) => {
+                    let id = self.save_text_blob(&content).await?;
+                    TreeChange {
+                        path,
+                        new: Some(TreeItem::new(current.mode, id, name)),
+                        old: Some(current),
+                    }
+                }
+                (FileOperation::Delete { .. }, Some(current)) => {
+                    if current.mode == TreeItemMode::Tree {
+                        deleted_dirs.push(mono_path);
+                    }
+                    TreeChange {
+                        path,
+                        old: Some(current),
+                        new: None,
+                    }
+                }
+                _ => {
+                    conflicts.push(mono_path.to_string());
+                    continue;
+                }
+            };
+            changes.push(change);
+        }
+        if !conflicts.is_empty() {
+            return Ok(CommitResult::conflict(conflicts));
+        }
 
-            if mr.path != "/" {
-                let path = PathBuf::from(mr.path.clone());
-                // beacuse only parent tree is needed so we skip current directory
-                let (tree_vec, _) = self
-                    .search_tree_for_update(path.parent().unwrap())
-                    .await
-                    .unwrap();
-                self.update_parent_tree(path, tree_vec, commit)
-                    .await
-                    .unwrap();
-                // remove refs start with path
-                storage.remove_refs(&mr.path).await.unwrap();
-                // TODO: self.clean_dangling_commits().await;
+        let res = self.commit_changes(&changes, None, &info.message).await?;
+        if res.commit_id.is_some() {
+            for dir in &deleted_dirs {
+                self.remove_dir_refs(dir).await?;
             }
-            // update mr
-            mr.merge();
-            // add conversation
-            self.context
-                .mr_stg()
-                .add_mr_conversation(&mr.link, 0, ConvType::Merged, None)
-                .await
-                .unwrap();
-            // update mr status last
-            self.context
-                .mr_stg()
-                .update_mr(mr.clone().into())
-                .await
-                .unwrap();
-        } else {
-            return Err(MegaError::with_message("ref hash conflict"));
         }
+        Ok(res)
+    }
+
+    /// Remove the refs of a directory that no longer exists and of everything below it.
+    async fn remove_dir_refs(&self, dir: &MonoPath) -> Result<(), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let path = dir.to_string();
+        if let Some(refs) = storage.get_ref(&path).await? {
+            storage.remove_ref(refs).await?;
+        }
+        storage.remove_refs(&format!("{}/", path)).await?;
         Ok(())
     }
 
+    /// Replace the content of an existing file, committed on top of the root ref.
+    ///
+    /// The edit is reported as a conflict when `old_oid` is set and the file has changed since.
+    pub async fn update_monorepo_file(
+        &self,
+        file_info: UpdateFileInfo,
+    ) -> Result<CommitResult, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let mono_path = MonoPath::parse(&file_info.path)?;
+        if mono_path.is_root() {
+            return Err(GitError::CustomError(
+                "path must point to a file".to_string(),
+            ));
+        }
+        let path = mono_path.to_path_buf();
+        let root_ref = self.root_ref().await?;
+        let current = tree_ops::entry_at_path(
+            &storage,
+            &SHA1::from_str(&root_ref.ref_tree_hash).unwrap(),
+            &path,
+        )
+        .await?
+        .filter(|x| matches!(x.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable));
+        let Some(current) = current else {
+            return Err(GitError::CustomError(format!(
+                "{} is not a file",
+                mono_path
+            )));
+        };
+        if file_info
+            .old_oid
+            .as_ref()
+            .is_some_and(|x| *x != current.id.to_string())
+        {
+            return Ok(CommitResult::conflict(vec![mono_path.to_string()]));
+        }
+
+        let id = self.save_text_blob(&file_info.content).await?;
+        let change = TreeChange {
+            path,
+            new: Some(TreeItem::new(current.mode, id, current.name.clone())),
+            old: Some(current),
+        };
+        let message = file_info
+            .message
+            .filter(|x| !x.trim().is_empty())
+            .unwrap_or_else(|| format!("update file {}", mono_path));
+        self.commit_changes(&[change], None, &message).await
+    }
+
     async fn update_parent_tree(
         &self,
         mut path: PathBuf,
@@ -251,6 +1030,17 @@ impl MonoApiService {
         let storage = self.context.services.mono_storage.clone();
         let mut save_trees = Vec::new();
         let mut p_commit_id = String::new();
+        let mut p_refs: HashMap<String, mega_refs::Model> = storage
+            .get_refs_by_paths(
+                path.ancestors()
+                    .skip(1)
+                    .map(|x| x.to_str().unwrap().to_owned())
+                    .collect(),
+            )
+            .await?
+            .into_iter()
+            .map(|x| (x.path.clone(), x))
+            .collect();
 
         let mut target_hash = commit.tree_id;
 
@@ -259,16 +1049,19 @@ impl MonoApiService {
             let name = cloned_path.file_name().unwrap().to_str().unwrap();
             path.pop();
 
-            let index = tree.tree_items.iter().position(|x| x.name == name).unwrap();
+            let index = tree
+                .tree_items
+                .iter()
+                .position(|x| x.name == name)
+                .ok_or_else(|| GitError::InvalidPathError(cloned_path.display().to_string()))?;
             tree.tree_items[index].id = target_hash;
-            let new_tree = Tree::from_tree_items(tree.tree_items).unwrap();
+            let new_tree = Tree::from_tree_items(tree.tree_items)?;
             target_hash = new_tree.id;
 
             let model: mega_tree::Model = new_tree.into();
             save_trees.push(model);
 
-            let p_ref = storage.get_ref(path.to_str().unwrap()).await.unwrap();
-            if let Some(mut p_ref) = p_ref {
+            if let Some(mut p_ref) = p_refs.remove(path.to_str().unwrap()) {
                 if path == Path::new("/") {
                     let p_commit = Commit::new(
                         commit.author.clone(),
@@ -279,12 +1072,15 @@ impl MonoApiService {
                     );
                     p_commit_id = p_commit.id.to_string();
                     // update p_ref
-                    p_ref.ref_commit_hash = p_commit.id.to_string();
+                    let old_commit_hash =
+                        std::mem::replace(&mut p_ref.ref_commit_hash, p_commit.id.to_string());
                     p_ref.ref_tree_hash = target_hash.to_string();
-                    storage.update_ref(p_ref).await.unwrap();
-                    storage.save_mega_commits(vec![p_commit]).await.unwrap();
+                    storage.update_ref(p_ref, &old_commit_hash).await?;
+                    storage.save_mega_commits(vec![p_commit]).await?;
+                    SearchIndexEvent::notify(&p_commit_id);
+                    DirStatsEvent::notify(&p_commit_id);
                 } else {
-                    storage.remove_ref(p_ref).await.unwrap();
+                    storage.remove_ref(p_ref).await?;
                 }
             }
         }
@@ -296,67 +1092,1163 @@ impl MonoApiService {
             })
             .collect();
 
-        batch_save_model(storage.get_connection(), save_trees)
-            .await
-            .unwrap();
+        batch_save_model(storage.get_connection(), save_trees).await?;
         Ok(p_commit_id)
     }
 
-    pub async fn content_diff(&self, mr_link: &str) -> Result<String, GitError> {
-        let stg = self.context.mr_stg();
-        if let Some(mr) = stg.get_mr(mr_link).await.unwrap() {
-            let base_path = self.context.config.base_dir.clone();
-            env::set_current_dir(&base_path).unwrap();
-            let clone_path = base_path.join(mr_link);
-            if !fs::exists(&clone_path).unwrap() {
-                // fs::remove_dir_all(&clone_path).unwrap();
-                Command::new("mkdir")
-                    .arg(mr_link)
-                    .output()
-                    .await
-                    .expect("Failed to mkdir");
-                // cd mr
-                env::set_current_dir(&clone_path).unwrap();
-                // libra init
-                Command::new("libra")
-                    .arg("init")
-                    .output()
-                    .await
-                    .expect("Failed to execute libra init");
-                // libra remote add origin http://localhost:8000/project
-                Command::new("libra")
-                    .arg("remote")
-                    .arg("add")
-                    .arg("origin")
-                    .arg(format!("http://localhost:8000{}", mr.path))
-                    .output()
+    /// Apply `changes` on top of the root ref and commit the result.
+    /// Nothing is written if any of the changes conflicts with the current tree.
+    pub async fn commit_changes(
+        &self,
+        changes: &[TreeChange],
+        author: Option<Signature>,
+        message: &str,
+    ) -> Result<CommitResult, GitError> {
+        // the trees, the commit and the moved refs are written together or not at all
+        let mut retries = 0;
+        loop {
+            let author = author.clone();
+            let res = self
+                .context
+                .transaction(|context| async move {
+                    let service = MonoApiService {
+                        context,
+                        ..self.clone()
+                    };
+                    service.write_changes(changes, author, message).await
+                })
+                .await;
+            match res {
+                // another write moved the root ref, apply onto the new one
+                Err(GitError::RefConflict(msg)) if retries < REF_CONFLICT_RETRIES => {
+                    tracing::info!("retry committing changes: {}", msg);
+                    retries += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// [`commit_changes`](Self::commit_changes) within the transaction of the caller.
+    async fn write_changes(
+        &self,
+        changes: &[TreeChange],
+        author: Option<Signature>,
+        message: &str,
+    ) -> Result<CommitResult, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let mut root_ref = self.root_ref().await?;
+        let root_tree = load_tree(
+            &storage,
+            &SHA1::from_str(&root_ref.ref_tree_hash).unwrap(),
+        )
+        .await?;
+
+        let res = tree_ops::apply_changes(&storage, root_tree.clone(), changes).await?;
+        if !res.conflicts.is_empty() {
+            return Ok(CommitResult::conflict(
+                res.conflicts
+                    .iter()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .collect(),
+            ));
+        }
+        if res.root.id == root_tree.id {
+            return Err(GitError::CustomError(
+                "nothing to commit, changes are already applied".to_string(),
+            ));
+        }
+
+        let parents = vec![SHA1::from_str(&root_ref.ref_commit_hash).unwrap()];
+        // the message is separated from the headers by an empty line
+        let message = format!("\n{}", message);
+        let mut commit = Commit::from_tree_id(res.root.id, parents.clone(), &message);
+        if let Some(author) = author.or_else(|| self.author.clone()) {
+            commit = Commit::new(author, commit.committer, res.root.id, parents, &message);
+        }
+        let commit_id = commit.id.to_string();
+
+        let save_trees: Vec<mega_tree::ActiveModel> = res
+            .new_trees
+            .into_iter()
+            .map(|save_t| {
+                let mut tree_model: mega_tree::Model = save_t.into();
+                tree_model.commit_id.clone_from(&commit_id);
+                tree_model.into()
+            })
+            .collect();
+        batch_save_model(storage.get_connection(), save_trees).await?;
+        storage.save_mega_commits(vec![commit]).await?;
+
+        let old_commit_hash = std::mem::replace(&mut root_ref.ref_commit_hash, commit_id.clone());
+        root_ref.ref_tree_hash = res.root.id.to_string();
+        storage.update_ref(root_ref, &old_commit_hash).await?;
+        SearchIndexEvent::notify(&commit_id);
+        DirStatsEvent::notify(&commit_id);
+
+        self.remove_stale_refs(changes).await?;
+        Ok(CommitResult::committed(commit_id))
+    }
+
+    /// Refs of sub directories are snapshots taken from the root tree, remove the ones
+    /// above a changed path so they are generated again on next fetch.
+    async fn remove_stale_refs(&self, changes: &[TreeChange]) -> Result<(), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let dirs: HashSet<&Path> = changes
+            .iter()
+            .flat_map(|x| x.path.ancestors().skip(1))
+            .filter(|x| *x != Path::new("/") && !x.as_os_str().is_empty())
+            .collect();
+        let dirs = dirs
+            .into_iter()
+            .map(|x| x.to_str().unwrap().to_owned())
+            .collect();
+        for refs in storage.get_refs_by_paths(dirs).await? {
+            storage.remove_ref(refs).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_mega_commit(&self, hash: &str) -> Result<Commit, GitError> {
+        self.context
+            .services
+            .mono_storage
+            .get_commit_by_hash(hash)
+            .await?
+            .map(Commit::from)
+            .ok_or_else(|| GitError::ObjectNotFound(hash.to_owned()))
+    }
+
+    /// The ref of the monorepo root, every write moves it.
+    async fn root_ref(&self) -> Result<mega_refs::Model, GitError> {
+        self.context
+            .services
+            .mono_storage
+            .get_ref("/")
+            .await?
+            .ok_or_else(|| GitError::ObjectNotFound("root ref".to_owned()))
+    }
+
+    /// Replay the changes of a commit, or of a whole merge request, onto the root ref.
+    /// Files changed on the root ref since are merged line by line, only overlapping
+    /// changes are reported as conflicts.
+    pub async fn cherry_pick(&self, req: CherryPickRequest) -> Result<CommitResult, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let (base, head, default_target, origin) =
+            if let Some(link) = &req.mr_link {
+                let mr =
+                    self.context.mr_stg().get_mr(link).await?.ok_or_else(|| {
+                        GitError::ObjectNotFound(format!("merge request {}", link))
+                    })?;
+                (
+                    Some(mr.from_hash),
+                    mr.to_hash,
+                    mr.path,
+                    format!("merge request {}", link),
+                )
+            } else if let Some(hash) = &req.commit {
+                let commit = self.get_mega_commit(hash).await?;
+                (
+                    commit.parent_commit_ids.first().map(|x| x.to_string()),
+                    hash.to_owned(),
+                    String::from("/"),
+                    format!("commit {}", hash),
+                )
+            } else {
+                return Err(GitError::InvalidArgument(
+                    "either commit or mr_link is required".to_string(),
+                ));
+            };
+
+        let head_commit = self.get_mega_commit(&head).await?;
+        let base_tree = match base {
+            Some(base) => {
+                let base_commit = self.get_mega_commit(&base).await?;
+                Some(load_tree(&storage, &base_commit.tree_id).await?)
+            }
+            None => None,
+        };
+        let head_tree = load_tree(&storage, &head_commit.tree_id).await?;
+        let target = PathBuf::from(req.target_path.unwrap_or(default_target));
+
+        let changes = diff_trees(&storage, base_tree.as_ref(), Some(&head_tree), target).await?;
+        if changes.is_empty() {
+            return Err(GitError::CustomError(format!("{} has no changes", origin)));
+        }
+        let (resolved, unresolved) = self.merge_changes(changes, &origin).await?;
+        if !unresolved.is_empty() {
+            return Ok(CommitResult::conflict(unresolved));
+        }
+        let message = format!(
+            "{}\n\n(cherry picked from {})",
+            message_body(&head_commit.message).trim_end(),
+            origin
+        );
+        self.commit_changes(&resolved, Some(head_commit.author), &message)
+            .await
+    }
+
+    /// Changes a commit made against its first parent, paths are rooted at `/`.
+    pub async fn diff_with_parent(
+        &self,
+        hash: &str,
+    ) -> Result<(Commit, Vec<TreeChange>), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let commit = self.get_mega_commit(hash).await?;
+        let old_tree = match commit.parent_commit_ids.first() {
+            Some(parent) => {
+                let parent = self.get_mega_commit(&parent.to_string()).await?;
+                Some(load_tree(&storage, &parent.tree_id).await?)
+            }
+            None => None,
+        };
+        let new_tree = load_tree(&storage, &commit.tree_id).await?;
+        let changes = diff_trees(
+            &storage,
+            old_tree.as_ref(),
+            Some(&new_tree),
+            PathBuf::from("/"),
+        )
+        .await?;
+        Ok((commit, changes))
+    }
+
+    /// Undo a single commit of the root ref by committing the inverse of its changes,
+    /// `changes` are the ones returned by `diff_with_parent`. Commits that aren't
+    /// ancestors of the root ref are refused.
+    pub async fn revert_commit(
+        &self,
+        commit: &Commit,
+        changes: &[TreeChange],
+    ) -> Result<CommitResult, GitError> {
+        if commit.parent_commit_ids.is_empty() {
+            return Err(GitError::CustomError(
+                "can't revert a commit without parent".to_string(),
+            ));
+        }
+        if changes.is_empty() {
+            return Err(GitError::CustomError(format!(
+                "commit {} has no changes",
+                commit.id
+            )));
+        }
+        let head = self.root_ref().await?.ref_commit_hash;
+        if !self
+            .context
+            .services
+            .mono_storage
+            .is_ancestor(&commit.id.to_string(), &head)
+            .await?
+        {
+            return Err(GitError::InvalidArgument(format!(
+                "commit {} isn't on the root ref",
+                commit.id
+            )));
+        }
+        let changes: Vec<TreeChange> = changes.iter().map(|x| x.reverse()).collect();
+        let message = format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.",
+            commit.format_message(),
+            commit.id
+        );
+        self.commit_changes(&changes, None, &message).await
+    }
+
+    /// Which of `oids` the tree of the root ref holds outside the `hidden` directories.
+    /// Objects asked for by hash are only served to a user who can't read everything
+    /// when they're found there.
+    pub async fn visible_objects(
+        &self,
+        mut oids: HashSet<String>,
+        hidden: &[String],
+    ) -> Result<HashSet<String>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let root = self.root_ref().await?.ref_tree_hash;
+        let mut found = HashSet::new();
+        if oids.remove(&root) {
+            found.insert(root.clone());
+        }
+        let mut level = HashMap::from([(root, PathBuf::from("/"))]);
+        while !level.is_empty() && !oids.is_empty() {
+            let mut next = HashMap::new();
+            for model in storage
+                .get_trees_by_hashes(level.keys().cloned().collect())
+                .await?
+            {
+                let tree = Tree::from(model);
+                let dir = &level[&tree.id.to_string()];
+                for item in tree.tree_items {
+                    let path = dir.join(&item.name);
+                    if hidden.iter().any(|x| path.starts_with(x)) {
+                        continue;
+                    }
+                    let id = item.id.to_string();
+                    if oids.remove(&id) {
+                        found.insert(id.clone());
+                    }
+                    if item.mode == TreeItemMode::Tree {
+                        next.insert(id, path);
+                    }
+                }
+            }
+            level = next;
+        }
+        Ok(found)
+    }
+
+    /// Commits of the root ref that changed `path`, newest first, following first parents.
+    ///
+    /// `cursor` is the commit to continue from, it's returned as `next_cursor` when the
+    /// page is full or after scanning `MAX_HISTORY_SCAN` commits without filling it.
+    pub async fn get_file_history(
+        &self,
+        path: &MonoPath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<CursorPage<LatestCommitInfo>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let start = match cursor {
+            Some(cursor) => cursor,
+            None => self.root_ref().await?.ref_commit_hash,
+        };
+        let mut commit = self.get_mega_commit(&start).await?;
+        let mut current = tree_ops::id_at_path(&storage, &commit.tree_id, path).await?;
+        let mut items = Vec::new();
+        let mut scanned = 0;
+        loop {
+            let parent = match commit.parent_commit_ids.first() {
+                Some(parent) => Some(self.get_mega_commit(&parent.to_string()).await?),
+                None => None,
+            };
+            let previous = match &parent {
+                Some(parent) if parent.tree_id == commit.tree_id => current,
+                Some(parent) => tree_ops::id_at_path(&storage, &parent.tree_id, path).await?,
+                None => None,
+            };
+            if current != previous {
+                items.push(commit);
+            }
+            scanned += 1;
+            match parent {
+                Some(parent) if items.len() < limit && scanned < MAX_HISTORY_SCAN => {
+                    commit = parent;
+                    current = previous;
+                }
+                parent => {
+                    return Ok(CursorPage {
+                        items: self.convert_commits_to_info(items).await?,
+                        next_cursor: parent.map(|x| x.id.to_string()),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Changes between two commits with unified diff hunks for every changed file.
+    ///
+    /// `from` defaults to the first parent of `to`, a root commit is compared with the
+    /// empty tree. Blobs are loaded in one batch for the first `MAX_DIFF_FILES` files.
+    pub async fn commit_diff(
+        &self,
+        from: Option<String>,
+        to: String,
+    ) -> Result<CommitDiff, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let to_commit = self.get_mega_commit(&to).await?;
+        let from = from.or_else(|| to_commit.parent_commit_ids.first().map(|x| x.to_string()));
+        let old_tree = match &from {
+            Some(from) => {
+                let from_commit = self.get_mega_commit(from).await?;
+                Some(load_tree(&storage, &from_commit.tree_id).await?)
+            }
+            None => None,
+        };
+        let new_tree = load_tree(&storage, &to_commit.tree_id).await?;
+        let (files, truncated) = self
+            .tree_file_diffs(old_tree, Some(new_tree), PathBuf::from("/"))
+            .await?;
+        Ok(CommitDiff {
+            from,
+            to,
+            files,
+            truncated,
+        })
+    }
+
+    /// Files changed by a merge request in path order, renamed and copied files are
+    /// listed where they are now.
+    pub async fn mr_changed_files(&self, mr: &MergeRequest) -> Result<Vec<ChangedFile>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let from = self.get_mega_commit(&mr.from_hash).await?;
+        let to = self.get_mega_commit(&mr.to_hash).await?;
+        let old_tree = load_tree(&storage, &from.tree_id).await?;
+        let new_tree = load_tree(&storage, &to.tree_id).await?;
+        let changes = diff_trees(
+            &storage,
+            Some(&old_tree),
+            Some(&new_tree),
+            PathBuf::from("/"),
+        )
+        .await?;
+        Ok(self
+            .pair_renames(changes)
+            .await?
+            .into_iter()
+            .map(|x| {
+                let mut file = ChangedFile::new(
+                    x.change.path.to_string_lossy().into_owned(),
+                    x.change.old.map(|x| x.id.to_string()),
+                    x.change.new.map(|x| x.id.to_string()),
+                );
+                if let Some((renamed_from, kind)) = x.source {
+                    file.status = kind;
+                    file.renamed_from = Some(renamed_from);
+                }
+                file
+            })
+            .collect())
+    }
+
+    /// Hunks of one file changed by a merge request, a renamed or copied file is compared
+    /// with the file at `renamed_from` before the MR.
+    pub async fn mr_file_diff(
+        &self,
+        mr: &MergeRequest,
+        path: &str,
+        renamed_from: Option<&str>,
+    ) -> Result<FileDiff, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let from = self.get_mega_commit(&mr.from_hash).await?;
+        let to = self.get_mega_commit(&mr.to_hash).await?;
+        let file_at = |tree_id: SHA1, path: &str| {
+            let storage = storage.clone();
+            let path = PathBuf::from(path);
+            async move {
+                tree_ops::entry_at_path(&storage, &tree_id, &path)
                     .await
-                    .expect("Failed to execute libra remote add");
+                    .map(|x| x.filter(|x| x.mode != TreeItemMode::Tree))
+            }
+        };
+        let renamed_from = renamed_from.filter(|x| *x != path);
+        let old = file_at(from.tree_id, renamed_from.unwrap_or(path)).await?;
+        let new = file_at(to.tree_id, path).await?;
+        let kind = match renamed_from {
+            Some(source) if old.is_some() && new.is_some() => {
+                match file_at(to.tree_id, source).await? {
+                    Some(_) => Some(FileChangeKind::Copied),
+                    None => Some(FileChangeKind::Renamed),
+                }
+            }
+            _ => None,
+        };
+        if kind.is_none() && old.as_ref().map(|x| x.id) == new.as_ref().map(|x| x.id) {
+            return Err(GitError::InvalidPathError(format!(
+                "{} isn't changed by the MR",
+                path
+            )));
+        }
+
+        let hashes = [&old, &new]
+            .into_iter()
+            .flatten()
+            .filter(|x| x.mode != TreeItemMode::Commit)
+            .map(|x| x.id.to_string())
+            .collect();
+        let blobs = self.load_blobs(hashes).await?;
+        let mut file = FileDiff::new(
+            path.to_owned(),
+            old.as_ref().map(|x| x.id.to_string()),
+            new.as_ref().map(|x| x.id.to_string()),
+        )
+        .with_content(
+            old.as_ref().map(|x| entry_content(&blobs, x)).as_deref(),
+            new.as_ref().map(|x| entry_content(&blobs, x)).as_deref(),
+        );
+        if let Some(kind) = kind {
+            file.kind = kind;
+            file.renamed_from = renamed_from.map(str::to_owned);
+        }
+        Ok(file)
+    }
+
+    /// Compare two commits or directory states, described by `CompareSpec`. Commits ahead
+    /// and behind are counted on the history of both sides, each walked for at most
+    /// `MAX_HISTORY_SCAN` commits.
+    pub async fn compare(&self, base: &str, head: &str) -> Result<Compare, GitError> {
+        let base_spec = CompareSpec::parse(base);
+        let head_spec = CompareSpec::parse(head);
+        let (base_commit, base_tree) = self.resolve_compare_spec(&base_spec).await?;
+        let (head_commit, head_tree) = self.resolve_compare_spec(&head_spec).await?;
+        if base_tree.is_none() && head_tree.is_none() {
+            return Err(GitError::InvalidPathError(format!(
+                "{} and {} don't exist",
+                base, head
+            )));
+        }
+        let prefix = head_spec.path.or(base_spec.path).unwrap_or("/".to_owned());
+        let (files, truncated) = self
+            .tree_file_diffs(base_tree, head_tree, PathBuf::from(prefix))
+            .await?;
+
+        let merge_base = self
+            .context
+            .services
+            .mono_storage
+            .merge_base(&base_commit.id.to_string(), &head_commit.id.to_string())
+            .await?;
+        let base_history = self.ancestors(&base_commit.id).await?;
+        let head_history = self.ancestors(&head_commit.id).await?;
+        let base_ids: HashSet<SHA1> = base_history.iter().map(|x| x.id).collect();
+        let head_ids: HashSet<SHA1> = head_history.iter().map(|x| x.id).collect();
+        let behind_by = base_history
+            .iter()
+            .filter(|x| !head_ids.contains(&x.id))
+            .count();
+        let ahead: Vec<Commit> = head_history
+            .into_iter()
+            .filter(|x| !base_ids.contains(&x.id))
+            .collect();
+        let ahead_by = ahead.len();
+        let commits = self
+            .convert_commits_to_info(ahead.into_iter().take(MAX_COMPARE_COMMITS).rev().collect())
+            .await?;
+        Ok(Compare {
+            base: base_commit.id.to_string(),
+            head: head_commit.id.to_string(),
+            merge_base,
+            ahead_by,
+            behind_by,
+            commits,
+            stat: DiffStat::from_files(&files),
+            files,
+            truncated,
+        })
+    }
+
+    /// Commit and tree of one side of a compare, the tree is `None` when the directory
+    /// doesn't exist in that commit.
+    async fn resolve_compare_spec(
+        &self,
+        spec: &CompareSpec,
+    ) -> Result<(Commit, Option<Tree>), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let commit = match &spec.commit {
+            Some(commit) => self.get_mega_commit(commit).await?,
+            None => self.get_root_commit().await?,
+        };
+        let path = match &spec.path {
+            Some(path) => {
+                MonoPath::parse(path).map_err(|e| GitError::InvalidPathError(e.to_string()))?
+            }
+            None => MonoPath::root(),
+        };
+        let tree_id = if path.is_root() {
+            Some(commit.tree_id)
+        } else {
+            tree_ops::entry_at_path(&storage, &commit.tree_id, path.as_path())
+                .await?
+                .filter(|x| x.mode == TreeItemMode::Tree)
+                .map(|x| x.id)
+        };
+        let tree = match tree_id {
+            Some(id) => Some(load_tree(&storage, &id).await?),
+            None => None,
+        };
+        Ok((commit, tree))
+    }
+
+    /// `start` and its ancestors, children before their parents, at most
+    /// `MAX_HISTORY_SCAN` commits.
+    async fn ancestors(&self, start: &SHA1) -> Result<Vec<Commit>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let ids: Vec<String> = storage
+            .walk(&start.to_string(), MAX_HISTORY_SCAN)
+            .await?
+            .into_iter()
+            .map(|x| x.commit_id)
+            .collect();
+        let mut commits: HashMap<String, Commit> = storage
+            .get_commits_by_hashes(&ids)
+            .await?
+            .into_iter()
+            .map(|x| (x.commit_id.clone(), x.into()))
+            .collect();
+        Ok(ids.iter().filter_map(|x| commits.remove(x)).collect())
+    }
+
+    /// File diffs between two trees, `None` standing for an empty tree. Blobs are loaded
+    /// in one batch for the first `MAX_DIFF_FILES` files, the flag tells if there were more.
+    async fn tree_file_diffs(
+        &self,
+        old_tree: Option<Tree>,
+        new_tree: Option<Tree>,
+        prefix: PathBuf,
+    ) -> Result<(Vec<FileDiff>, bool), GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let changes = diff_trees(&storage, old_tree.as_ref(), new_tree.as_ref(), prefix).await?;
+        let changes = self.pair_renames(changes).await?;
+
+        let hashes: Vec<String> = changes
+            .iter()
+            .take(MAX_DIFF_FILES)
+            .flat_map(|x| [x.change.old.as_ref(), x.change.new.as_ref()])
+            .flatten()
+            .filter(|x| x.mode != TreeItemMode::Commit)
+            .map(|x| x.id.to_string())
+            .collect();
+        let blobs = self.load_blobs(hashes).await?;
+
+        let truncated = changes.len() > MAX_DIFF_FILES;
+        let files = changes
+            .into_iter()
+            .enumerate()
+            .map(|(index, PairedChange { change, source })| {
+                let mut file = FileDiff::new(
+                    change.path.to_string_lossy().into_owned(),
+                    change.old.as_ref().map(|x| x.id.to_string()),
+                    change.new.as_ref().map(|x| x.id.to_string()),
+                );
+                if let Some((renamed_from, kind)) = source {
+                    file.kind = kind;
+                    file.renamed_from = Some(renamed_from);
+                }
+                if index >= MAX_DIFF_FILES {
+                    return file;
+                }
+                let old = change.old.as_ref().map(|x| entry_content(&blobs, x));
+                let new = change.new.as_ref().map(|x| entry_content(&blobs, x));
+                file.with_content(old.as_deref(), new.as_deref())
+            })
+            .collect();
+        Ok((files, truncated))
+    }
+
+    /// Pair the added files of `changes` with the deleted files they were moved from and
+    /// the changed files they were copied from. A renamed file takes the old side of its
+    /// source, which is dropped, so that it's compared with it. Files moved without
+    /// changes are found by their hash, similar ones while there are at most
+    /// `MAX_RENAME_PAIRS` pairs of files left to compare.
+    async fn pair_renames(&self, changes: Vec<TreeChange>) -> Result<Vec<PairedChange>, GitError> {
+        let is_file = |x: Option<&TreeItem>| x.is_some_and(|x| x.mode != TreeItemMode::Commit);
+        let (mut deleted, mut added, mut modified) = (vec![], vec![], vec![]);
+        for (i, change) in changes.iter().enumerate() {
+            match (is_file(change.old.as_ref()), is_file(change.new.as_ref())) {
+                (true, true) => modified.push(i),
+                (true, false) if change.new.is_none() => deleted.push(i),
+                (false, true) if change.old.is_none() => added.push(i),
+                _ => {}
+            }
+        }
+        let old_id = |i: usize| changes[i].old.as_ref().unwrap().id;
+        let new_id = |i: usize| changes[i].new.as_ref().unwrap().id;
+
+        // the source of every renamed or copied file, by index in `changes`
+        let mut sources: HashMap<usize, (usize, FileChangeKind)> = HashMap::new();
+        let empty = SHA1::from_type_and_data(ObjectType::Blob, &[]);
+        let mut by_hash: HashMap<SHA1, Vec<usize>> = HashMap::new();
+        for &i in deleted.iter().rev() {
+            by_hash.entry(old_id(i)).or_default().push(i);
+        }
+        for &i in added.iter().filter(|&&i| new_id(i) != empty) {
+            if let Some(source) = by_hash.get_mut(&new_id(i)).and_then(|x| x.pop()) {
+                sources.insert(i, (source, FileChangeKind::Renamed));
+            }
+        }
+
+        let moved: HashSet<usize> = sources.values().map(|x| x.0).collect();
+        let deleted: Vec<usize> = deleted.into_iter().filter(|x| !moved.contains(x)).collect();
+        let added: Vec<usize> = added
+            .into_iter()
+            .filter(|x| !sources.contains_key(x))
+            .collect();
+        if !added.is_empty() && added.len() * (deleted.len() + modified.len()) <= MAX_RENAME_PAIRS {
+            let hashes = deleted
+                .iter()
+                .chain(&modified)
+                .map(|&i| old_id(i))
+                .chain(added.iter().map(|&i| new_id(i)))
+                .map(|x| x.to_string())
+                .collect();
+            let blobs = self.load_blobs(hashes).await?;
+            let content = |id: SHA1| {
+                blobs
+                    .get(&id.to_string())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+            };
+            let targets: Vec<&[u8]> = added.iter().map(|&i| content(new_id(i))).collect();
+            let renamed: Vec<&[u8]> = deleted.iter().map(|&i| content(old_id(i))).collect();
+            for x in find_similar(&renamed, &targets, RENAME_THRESHOLD, true) {
+                sources.insert(
+                    added[x.target],
+                    (deleted[x.source], FileChangeKind::Renamed),
+                );
+            }
+            // the added files left may be copies of changed files
+            let left: Vec<usize> = (0..added.len())
+                .filter(|&x| !sources.contains_key(&added[x]))
+                .collect();
+            let left_targets: Vec<&[u8]> = left.iter().map(|&x| targets[x]).collect();
+            let copied: Vec<&[u8]> = modified.iter().map(|&i| content(old_id(i))).collect();
+            for x in find_similar(&copied, &left_targets, RENAME_THRESHOLD, false) {
+                sources.insert(
+                    added[left[x.target]],
+                    (modified[x.source], FileChangeKind::Copied),
+                );
+            }
+        }
+
+        let dropped: HashSet<usize> = sources
+            .values()
+            .filter(|x| x.1 == FileChangeKind::Renamed)
+            .map(|x| x.0)
+            .collect();
+        let origins: Vec<(String, Option<TreeItem>)> = changes
+            .iter()
+            .map(|x| (x.path.to_string_lossy().into_owned(), x.old.clone()))
+            .collect();
+        Ok(changes
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !dropped.contains(i))
+            .map(|(i, mut change)| {
+                let source = sources.get(&i).map(|&(source, kind)| {
+                    if kind == FileChangeKind::Renamed {
+                        change.old = origins[source].1.clone();
+                    }
+                    (origins[source].0.clone(), kind)
+                });
+                PairedChange { change, source }
+            })
+            .collect())
+    }
+
+    /// Contents of the blobs `hashes` by hash, missing blobs are left out.
+    async fn load_blobs(&self, hashes: Vec<String>) -> Result<HashMap<String, Vec<u8>>, GitError> {
+        Ok(self
+            .context
+            .services
+            .raw_db_storage
+            .get_raw_blobs_by_hashes(hashes)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .into_iter()
+            .map(|x| (x.sha1, x.data.unwrap_or_default()))
+            .collect())
+    }
+
+    /// Signatures, full message, parents and per file line counts of a commit, the
+    /// files are compared with its first parent like `commit_diff` does. A verified
+    /// commit signature comes with its signer.
+    pub async fn commit_detail(&self, oid: &str) -> Result<CommitDetail, GitError> {
+        let commit = self.get_mega_commit(oid).await?;
+        let diff = self.commit_diff(None, commit.id.to_string()).await?;
+        let files: Vec<FileStat> = diff.files.iter().map(FileStat::from).collect();
+        let signature = self
+            .context
+            .signature_stg()
+            .get_commit_signature(&commit.id.to_string())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .filter(|x| x.verified);
+        Ok(CommitDetail {
+            oid: commit.id.to_string(),
+            tree_id: commit.tree_id.to_string(),
+            parents: commit
+                .parent_commit_ids
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            author: self.commit_person(&commit.author).await,
+            committer: self.commit_person(&commit.committer).await,
+            message: message_body(&commit.message).to_owned(),
+            stat: DiffStat::from_files(&diff.files),
+            files,
+            truncated: diff.truncated,
+            verified: signature.is_some(),
+            signer: signature.and_then(|x| x.signer),
+        })
+    }
+
+    async fn commit_person(&self, sign: &Signature) -> CommitPerson {
+        CommitPerson {
+            name: sign.name.clone(),
+            email: sign.email.clone(),
+            timestamp: sign.timestamp,
+            timezone: sign.timezone.clone(),
+            user: self.get_user_info(sign).await,
+        }
+    }
+
+    /// Create an annotated tag of a commit, the latest one when no target is given. Tags
+    /// are scoped to a directory which must exist in the commit, names are unique per scope.
+    pub async fn create_tag(
+        &self,
+        req: CreateTagRequest,
+        tagger: Signature,
+    ) -> Result<TagInfo, GitError> {
+        validate_ref_name(&req.name).map_err(GitError::InvalidArgument)?;
+        let path = MonoPath::parse(&req.path)?;
+        let storage = self.context.services.mono_storage.clone();
+        let existing = storage
+            .get_tag_by_name(path.as_str(), &req.name)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        if existing.is_some() {
+            return Err(GitError::EntryExists(req.name));
+        }
+        let commit = match &req.target {
+            Some(target) => self.get_mega_commit(target).await?,
+            None => self.get_root_commit().await?,
+        };
+        if !path.is_root() {
+            let dir = tree_ops::entry_at_path(&storage, &commit.tree_id, path.as_path())
+                .await?
+                .filter(|x| x.mode == TreeItemMode::Tree);
+            if dir.is_none() {
+                return Err(GitError::InvalidPathError(path.to_string()));
+            }
+        }
+        let mut message = req.message;
+        if !message.is_empty() && !message.ends_with('\n') {
+            message.push('\n');
+        }
+        let tag = Tag::new(commit.id, ObjectType::Commit, req.name, tagger, &message);
+        let mut model = mega_tag::Model::from(tag);
+        model.path = path.to_string();
+        storage
+            .save_mega_tag(model.clone())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(self.tag_info(model).await)
+    }
+
+    /// Tags scoped to `path`, newest first.
+    pub async fn list_tags(&self, path: &MonoPath) -> Result<Vec<TagInfo>, GitError> {
+        let tags = self
+            .context
+            .services
+            .mono_storage
+            .get_tags_by_path(path.as_str())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        let mut items = Vec::with_capacity(tags.len());
+        for tag in tags {
+            items.push(self.tag_info(tag).await);
+        }
+        Ok(items)
+    }
+
+    async fn tag_info(&self, model: mega_tag::Model) -> TagInfo {
+        let path = model.path.clone();
+        let created_at = model.created_at.and_utc().timestamp();
+        let tag = Tag::from(model);
+        TagInfo {
+            name: tag.tag_name,
+            path,
+            tag_id: tag.id.to_string(),
+            object_id: tag.object_hash.to_string(),
+            object_type: tag.object_type.to_string(),
+            message: tag.message.trim_start_matches('\n').to_owned(),
+            tagger: self.commit_person(&tag.tagger).await,
+            created_at,
+        }
+    }
+
+    /// Branches of the directory at `path`, `main` first.
+    pub async fn list_branches(&self, path: &MonoPath) -> Result<Vec<BranchInfo>, GitError> {
+        let (head, refs) = self.mono_repo(path).head_hash().await;
+        if head == ZERO_ID {
+            return Err(GitError::InvalidPathError(path.to_string()));
+        }
+        let mut branches: Vec<BranchInfo> = refs
+            .into_iter()
+            .filter_map(|x| {
+                x.ref_name
+                    .strip_prefix("refs/heads/")
+                    .map(|name| BranchInfo {
+                        name: name.to_owned(),
+                        commit_id: x.ref_hash.clone(),
+                        default: x.default_branch,
+                    })
+            })
+            .collect();
+        branches.sort_by(|a, b| b.default.cmp(&a.default).then_with(|| a.name.cmp(&b.name)));
+        Ok(branches)
+    }
+
+    /// Create a named branch of a directory at `from`, the head of `main` when not given.
+    pub async fn create_branch(
+        &self,
+        branch: &BranchPath,
+        from: Option<String>,
+    ) -> Result<BranchInfo, GitError> {
+        if branch.is_default() || self.branch_head(branch).await?.is_some() {
+            return Err(GitError::EntryExists(branch.branch.clone()));
+        }
+        let commit = match from {
+            Some(from) => self.get_mega_commit(&from).await?,
+            None => {
+                let main = BranchPath {
+                    path: branch.path.clone(),
+                    branch: MEGA_DEFAULT_BRANCH.to_owned(),
+                };
+                let head = self
+                    .branch_head(&main)
+                    .await?
+                    .ok_or(GitError::InvalidPathError(branch.path.to_string()))?;
+                self.get_mega_commit(&head).await?
+            }
+        };
+        self.context
+            .services
+            .mono_storage
+            .save_ref(
+                branch.path.as_str(),
+                Some(branch.ref_name()),
+                &commit.id.to_string(),
+                &commit.tree_id.to_string(),
+            )
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(BranchInfo {
+            name: branch.branch.clone(),
+            commit_id: commit.id.to_string(),
+            default: false,
+        })
+    }
+
+    /// Delete a named branch, `main` and branches with an open merge request are kept.
+    pub async fn delete_branch(&self, branch: &BranchPath) -> Result<(), GitError> {
+        if branch.is_default() {
+            return Err(GitError::InvalidArgument(
+                "the main branch can't be deleted".to_owned(),
+            ));
+        }
+        let open_mr = self
+            .context
+            .mr_stg()
+            .get_open_mr_by_branch(branch.path.as_str(), &branch.branch)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        if let Some(mr) = open_mr {
+            return Err(GitError::InvalidArgument(format!(
+                "{} has the open merge request {}",
+                branch.branch, mr.link
+            )));
+        }
+        let storage = self.context.services.mono_storage.clone();
+        let refs = storage
+            .get_ref_by_name(branch.path.as_str(), &branch.ref_name())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or(GitError::ObjectNotFound(branch.branch.clone()))?;
+        storage
+            .remove_ref(refs)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))
+    }
+
+    /// Retarget the open MRs built on `merged` now that it's merged, returns their links.
+    ///
+    /// A stacked branch MR targeting the source branch of `merged` goes to its target
+    /// instead. `from_hash` moves to the head of the target when the MR contains it, so
+    /// the MR shows its own changes only. Otherwise it's kept, a merge then applies the
+    /// changes from where the MR started.
+    pub async fn retarget_dependents(
+        &self,
+        merged: &MergeRequest,
+    ) -> Result<Vec<String>, GitError> {
+        let mr_stg = self.context.mr_stg();
+        let dependents = mr_stg
+            .get_open_mrs_depending_on(&merged.link)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        let mut links = Vec::with_capacity(dependents.len());
+        for model in dependents {
+            let mut mr: MergeRequest = model.into();
+            if mr.path == merged.path && merged.source_branch.as_ref() == Some(&mr.target_branch) {
+                mr.target_branch = merged.target_branch.clone();
+            }
+            let path =
+                MonoPath::parse(&mr.path).map_err(|e| GitError::InvalidArgument(e.to_string()))?;
+            let target = BranchPath {
+                path: path.clone(),
+                branch: mr.target_branch.clone(),
+            };
+            if let Some(head) = self.branch_head(&target).await? {
+                if head != mr.from_hash
+                    && self
+                        .mono_repo(&path)
+                        .is_ancestor(&head, &mr.to_hash)
+                        .await?
+                {
+                    mr.from_hash = head;
+                }
+            }
+            mr_stg
+                .update_mr(mr.clone().into())
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?;
+            mr_stg
+                .add_mr_conversation(
+                    &mr.link,
+                    0,
+                    ConvType::Edit,
+                    Some(format!(
+                        "Mega retargeted this onto {} as MR {} was merged",
+                        mr.target_branch, merged.link
+                    )),
+                )
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?;
+            links.push(mr.link);
+        }
+        Ok(links)
+    }
+
+    /// Open a merge request from a named branch into `target` of the same directory and
+    /// return its link. A branch has at most one open merge request, pushing to the branch
+    /// moves its head.
+    pub async fn open_branch_mr(
+        &self,
+        source: &BranchPath,
+        target: &str,
+        title: Option<String>,
+        draft: bool,
+        depends_on: Option<String>,
+        author: &str,
+    ) -> Result<String, GitError> {
+        if source.is_default() || source.branch == target {
+            return Err(GitError::InvalidArgument(
+                "merge requests go from a named branch to another branch".to_owned(),
+            ));
+        }
+        validate_ref_name(target).map_err(GitError::InvalidArgument)?;
+        let target = BranchPath {
+            path: source.path.clone(),
+            branch: target.to_owned(),
+        };
+        let mr_stg = self.context.mr_stg();
+        let open_mr = mr_stg
+            .get_open_mr_by_branch(source.path.as_str(), &source.branch)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        if let Some(mr) = open_mr {
+            return Err(GitError::EntryExists(mr.link));
+        }
+        let to_hash = self
+            .branch_head(source)
+            .await?
+            .ok_or(GitError::ObjectNotFound(source.branch.clone()))?;
+        let from_hash = self
+            .branch_head(&target)
+            .await?
+            .ok_or(GitError::ObjectNotFound(target.branch.clone()))?;
+        if from_hash == to_hash {
+            return Err(GitError::InvalidArgument(format!(
+                "{} has nothing to merge into {}",
+                source.branch, target.branch
+            )));
+        }
+        let link = utils::generate_link();
+        let mut mr = MergeRequest {
+            link: link.clone(),
+            title: title
+                .unwrap_or_else(|| format!("Merge {} into {}", source.branch, target.branch)),
+            path: source.path.to_string(),
+            from_hash,
+            to_hash,
+            source_branch: Some(source.branch.clone()),
+            target_branch: target.branch,
+            draft,
+            author: Some(author.to_owned()),
+            ..Default::default()
+        };
+        if let Some(depends_on) = depends_on {
+            mr.check_dependency(&mr_stg, &depends_on).await?;
+            mr.depends_on = Some(depends_on);
+        }
+        mr_stg
+            .save_mr(mr.into())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        Ok(link)
+    }
+
+    /// Commit a branch points to, `main` of a directory is derived from the monorepo.
+    async fn branch_head(&self, branch: &BranchPath) -> Result<Option<String>, GitError> {
+        if branch.is_default() {
+            let (head, _) = self.mono_repo(&branch.path).head_hash().await;
+            return Ok((head != ZERO_ID).then_some(head));
+        }
+        Ok(self
+            .context
+            .services
+            .mono_storage
+            .get_ref_by_name(branch.path.as_str(), &branch.ref_name())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .map(|x| x.ref_commit_hash))
+    }
+
+    fn mono_repo(&self, path: &MonoPath) -> MonoRepo {
+        MonoRepo {
+            context: self.context.clone(),
+            path: path.as_path().to_path_buf(),
+            from_hash: String::new(),
+            to_hash: String::new(),
+            hidden: vec![],
+            view_trees: Arc::default(),
+            filter: None,
+            push_options: PushOptions::default(),
+            pusher: None,
+        }
+    }
+
+    pub async fn content_diff(&self, mr_link: &str) -> Result<String, GitError> {
+        let stg = self.context.mr_stg();
+        if let Some(mr) = stg.get_mr(mr_link).await? {
+            let base_path = self.context.config.base_dir.clone();
+            env::set_current_dir(&base_path)?;
+            let clone_path = base_path.join(mr_link);
+            if !fs::exists(&clone_path)? {
+                // fs::remove_dir_all(&clone_path).unwrap();
+                Command::new("mkdir").arg(mr_link).output().await?;
+                // cd mr
+                env::set_current_dir(&clone_path)?;
+                // libra init
+                Command::new("libra").arg("init").output().await?;
+                // libra remote add origin http://localhost:8000/project
+                Command::new("libra")
+                    .arg("remote")
+                    .arg("add")
+                    .arg("origin")
+                    .arg(format!("http://localhost:8000{}", mr.path))
+                    .output()
+                    .await?;
                 // libra fetch origin QB0X1X1K
                 Command::new("libra")
                     .arg("fetch")
                     .arg("origin")
                     .arg(mr_link)
                     .output()
-                    .await
-                    .expect("Failed to execute libra fetch");
+                    .await?;
                 // libra branch QB0X1X1K origin/QB0X1X1K
                 Command::new("libra")
                     .arg("branch")
                     .arg(mr_link)
                     .arg(format!("origin/{}", mr_link))
                     .output()
-                    .await
-                    .expect("Failed to execute libra branch");
+                    .await?;
                 // libra switch QB0X1X1K
                 Command::new("libra")
                     .arg("switch")
                     .arg(mr_link)
                     .output()
-                    .await
-                    .expect("Failed to execute libra switch");
+                    .await?;
             } else {
-                env::set_current_dir(&clone_path).unwrap();
+                env::set_current_dir(&clone_path)?;
             }
             // libra diff --old hash
             let output = Command::new("libra")
@@ -364,8 +2256,7 @@ impl MonoApiService {
                 .arg("--old")
                 .arg(mr.from_hash)
                 .output()
-                .await
-                .expect("Failed to execute libra diff");
+                .await?;
             if output.status.success() {
                 return Ok(String::from_utf8_lossy(&output.stdout).to_string());
             } else {
@@ -377,11 +2268,276 @@ impl MonoApiService {
         }
         Ok(String::new())
     }
+
+    /// Stream an archive of the directory `path` at commit `refs`, the root ref by default.
+    ///
+    /// Files are listed up front so that a missing path or too many files is an error rather
+    /// than a broken download, blobs are then loaded in batches while the archive is written.
+    /// Entries are put under a directory named after `path`, submodules are left out.
+    pub async fn archive(
+        &self,
+        path: &MonoPath,
+        refs: Option<String>,
+        format: ArchiveFormat,
+    ) -> Result<ReceiverStream<Result<Bytes, io::Error>>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let refs = match refs {
+            Some(refs) => refs,
+            None => self.root_ref().await?.ref_commit_hash,
+        };
+        let commit = self.get_mega_commit(&refs).await?;
+        let tree_id = if path.is_root() {
+            commit.tree_id
+        } else {
+            match tree_ops::entry_at_path(&storage, &commit.tree_id, path.as_path()).await? {
+                Some(item) if item.mode == TreeItemMode::Tree => item.id,
+                _ => return Err(GitError::InvalidPathError(path.to_string())),
+            }
+        };
+
+        let prefix = path.name().unwrap_or("monorepo").to_owned();
+        let mut files = Vec::new();
+        let mut pending = vec![(prefix, tree_id)];
+        while let Some((dir, id)) = pending.pop() {
+            for item in load_tree(&storage, &id).await?.tree_items {
+                let item_path = format!("{}/{}", dir, item.name);
+                match item.mode {
+                    TreeItemMode::Tree => pending.push((item_path, item.id)),
+                    TreeItemMode::Commit => {}
+                    _ => files.push((item_path, item)),
+                }
+            }
+            if files.len() > MAX_ARCHIVE_ENTRIES {
+                return Err(GitError::CustomError(format!(
+                    "more than {} files to archive",
+                    MAX_ARCHIVE_ENTRIES
+                )));
+            }
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let raw_storage = self.context.services.raw_db_storage.clone();
+        let mtime = commit.committer.timestamp as i64;
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut writer = ArchiveWriter::new(format, mtime);
+            let mut total = 0u64;
+            for batch in files.chunks(ARCHIVE_BLOB_BATCH) {
+                let hashes = batch.iter().map(|(_, x)| x.id.to_string()).collect();
+                let blobs: HashMap<String, Vec<u8>> =
+                    match raw_storage.get_raw_blobs_by_hashes(hashes).await {
+                        Ok(blobs) => blobs
+                            .into_iter()
+                            .map(|x| (x.sha1, x.data.unwrap_or_default()))
+                            .collect(),
+                        Err(err) => {
+                            let _ = tx.send(Err(io::Error::other(err.to_string()))).await;
+                            return;
+                        }
+                    };
+                for (item_path, item) in batch {
+                    let data = blobs.get(&item.id.to_string()).map(Vec::as_slice);
+                    total += data.map_or(0, |x| x.len() as u64);
+                    let result = if total > MAX_ARCHIVE_SIZE {
+                        Err(io::Error::other("archive is too large"))
+                    } else {
+                        writer.append(item_path, item.mode, data.unwrap_or_default())
+                    };
+                    if let Err(err) = result {
+                        tracing::error!("archive of {} failed: {}", item_path, err);
+                        let _ = tx.send(Err(err)).await;
+                        return;
+                    }
+                }
+                let output = writer.take_output();
+                if !output.is_empty() && tx.send(Ok(Bytes::from(output))).await.is_err() {
+                    // the client went away
+                    return;
+                }
+            }
+            let _ = tx.send(writer.finish().map(Bytes::from)).await;
+        });
+        Ok(ReceiverStream::new(rx))
+    }
+
+    /// Files under `path` containing `query`, looked up in the search index and checked
+    /// against their content. `cursor` is the number of index candidates already looked
+    /// at, a page stops after `MAX_SEARCH_SCAN` candidates even when it isn't full.
+    pub async fn search_code(
+        &self,
+        query: &str,
+        path: &MonoPath,
+        cursor: Option<String>,
+        limit: usize,
+    ) -> Result<CursorPage<SearchMatch>, GitError> {
+        let grams = trigrams(query);
+        if grams.is_empty() {
+            return Err(GitError::InvalidArgument(
+                "query needs at least 3 characters in a row without spaces".to_owned(),
+            ));
+        }
+        let mut offset = match cursor {
+            Some(cursor) => cursor
+                .parse::<u64>()
+                .map_err(|_| GitError::InvalidArgument(format!("invalid cursor {}", cursor)))?,
+            None => 0,
+        };
+        let needle = query.to_lowercase();
+        let search_storage = self.context.search_stg();
+        let mut items = Vec::new();
+        let mut scanned = 0;
+        loop {
+            let candidates = search_storage
+                .find_candidates(&grams, path.as_str(), offset, SEARCH_BATCH as u64)
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?;
+            if candidates.is_empty() {
+                return Ok(CursorPage {
+                    items,
+                    next_cursor: None,
+                });
+            }
+            let hashes = candidates.iter().map(|x| x.blob_id.clone()).collect();
+            let blobs: HashMap<String, Vec<u8>> = self
+                .context
+                .services
+                .raw_db_storage
+                .get_raw_blobs_by_hashes(hashes)
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?
+                .into_iter()
+                .map(|x| (x.sha1, x.data.unwrap_or_default()))
+                .collect();
+            for file in candidates {
+                offset += 1;
+                scanned += 1;
+                let content = blobs
+                    .get(&file.blob_id)
+                    .and_then(|x| std::str::from_utf8(x).ok());
+                if let Some(found) =
+                    content.and_then(|x| SearchMatch::find(file.path, file.blob_id, x, &needle))
+                {
+                    items.push(found);
+                }
+                if items.len() >= limit || scanned >= MAX_SEARCH_SCAN {
+                    return Ok(CursorPage {
+                        items,
+                        next_cursor: Some(offset.to_string()),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Content of a changed entry, submodules are shown by the commit they point to like git does.
+/// A changed file, with the path and kind of the file it comes from when it was
+/// renamed or copied.
+struct PairedChange {
+    change: TreeChange,
+    source: Option<(String, FileChangeKind)>,
+}
+
+fn entry_content(blobs: &HashMap<String, Vec<u8>>, item: &TreeItem) -> Vec<u8> {
+    if item.mode == TreeItemMode::Commit {
+        return format!("Subproject commit {}\n", item.id).into_bytes();
+    }
+    blobs.get(&item.id.to_string()).cloned().unwrap_or_default()
+}
+
+/// Commit message without the leading pgp signature, if any.
+fn message_body(message: &str) -> &str {
+    const END_SIGNATURE: &str = "-----END PGP SIGNATURE-----";
+    match message.find(END_SIGNATURE) {
+        Some(pos) => message[pos + END_SIGNATURE.len()..].trim_start(),
+        None => message.trim_start(),
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+
+    use common::config::Config;
+    use common::utils::ZERO_ID;
+    use jupiter::context::Context;
+    use mercury::errors::GitError;
+    use mercury::hash::SHA1;
+    use mercury::internal::object::commit::Commit;
+    use mercury::internal::object::tree::{TreeItem, TreeItemMode};
+
+    use super::{message_body, MonoApiService};
+    use crate::api_service::tree_ops::TreeChange;
+    use crate::api_service::ApiHandler;
+
+    /// A service on a fresh sqlite database without any ref or object.
+    async fn empty_monorepo(dir: &Path) -> MonoApiService {
+        let mut config = Config::default();
+        config.database.db_path = dir.join("mega.db").to_string_lossy().into_owned();
+        config.lfs.lfs_obj_local_path = dir.join("lfs");
+        MonoApiService {
+            context: Context::new(config).await,
+            author: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_objects_are_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = empty_monorepo(dir.path()).await;
+        assert!(matches!(
+            service.get_root_tree().await,
+            Err(GitError::ObjectNotFound(_))
+        ));
+        assert!(matches!(
+            service.get_tree_by_hash(ZERO_ID).await,
+            Err(GitError::ObjectNotFound(_))
+        ));
+        assert!(matches!(
+            service.search_tree_by_path(Path::new("/project")).await,
+            Err(GitError::ObjectNotFound(_))
+        ));
+        assert!(matches!(
+            service.get_latest_commit(PathBuf::from("/project")).await,
+            Err(GitError::ObjectNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_revert_commit_off_the_root_ref() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = empty_monorepo(dir.path()).await;
+        let head = SHA1::new(b"head");
+        service
+            .context
+            .services
+            .mono_storage
+            .save_ref("/", None, &head.to_string(), ZERO_ID)
+            .await
+            .unwrap();
+
+        let other = Commit::from_tree_id(SHA1::new(b"tree"), vec![head], "other");
+        let changes = vec![TreeChange {
+            path: PathBuf::from("/a.txt"),
+            old: None,
+            new: Some(TreeItem::new(
+                TreeItemMode::Blob,
+                SHA1::new(b"a"),
+                "a.txt".to_owned(),
+            )),
+        }];
+        assert!(matches!(
+            service.revert_commit(&other, &changes).await,
+            Err(GitError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_message_body() {
+        assert_eq!(message_body("\nfix typo\n"), "fix typo\n");
+        let signed = "gpgsig -----BEGIN PGP SIGNATURE-----\n abc\n -----END PGP SIGNATURE-----\n\nfix typo";
+        assert_eq!(message_body(signed), "fix typo");
+    }
 
     #[test]
     pub fn test() {
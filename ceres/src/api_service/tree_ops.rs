@@ -0,0 +1,269 @@
+//! Path level operations on monorepo trees.
+//!
+//! `mercury::tree_diff` flattens the difference of two trees into per-path changes and
+//! `apply_changes` replays such changes onto another tree, it's the building block
+//! of server side commits like cherry-pick and revert: every change carries the
+//! entry it expects to replace, a path whose current entry differs is a conflict.
+
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use futures::future::BoxFuture;
+
+use common::path::MonoPath;
+use jupiter::storage::mono_storage::MonoStorage;
+use mercury::{
+    errors::GitError,
+    hash::SHA1,
+    internal::object::tree::{Tree, TreeItem, TreeItemMode},
+    tree_diff::{same_entry, TreeLoader},
+};
+
+pub use mercury::tree_diff::TreeChange;
+
+pub struct ApplyResult {
+    pub root: Tree,
+    /// trees created while applying, which should be saved along with the commit
+    pub new_trees: Vec<Tree>,
+    pub conflicts: Vec<PathBuf>,
+}
+
+pub async fn load_tree(storage: &MonoStorage, id: &SHA1) -> Result<Tree, GitError> {
+    TreeLoader::load_tree(storage, id).await
+}
+
+/// Object id at `path` below the tree `root`, `None` when the path doesn't exist.
+pub async fn id_at_path(
+    storage: &MonoStorage,
+    root: &SHA1,
+    path: &MonoPath,
+) -> Result<Option<SHA1>, GitError> {
+    let mut id = *root;
+    let mut is_tree = true;
+    for name in path.segments() {
+        if !is_tree {
+            return Ok(None);
+        }
+        let tree = load_tree(storage, &id).await?;
+        match tree.tree_items.into_iter().find(|x| x.name == name) {
+            Some(item) => {
+                id = item.id;
+                is_tree = item.mode == TreeItemMode::Tree;
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(id))
+}
+
+/// Entry at `path` below the tree `root`, `None` for the root itself or a missing path.
+pub async fn entry_at_path(
+    storage: &MonoStorage,
+    root: &SHA1,
+    path: &Path,
+) -> Result<Option<TreeItem>, GitError> {
+    let mut tree_id = *root;
+    let mut entry = None;
+    for name in path_components(path) {
+        if entry
+            .as_ref()
+            .is_some_and(|x: &TreeItem| x.mode != TreeItemMode::Tree)
+        {
+            return Ok(None);
+        }
+        let tree = load_tree(storage, &tree_id).await?;
+        match tree.tree_items.into_iter().find(|x| x.name == name) {
+            Some(item) => {
+                tree_id = item.id;
+                entry = Some(item);
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(entry)
+}
+
+/// Apply `changes` onto `root`. Paths whose current entry is neither the expected old
+/// entry nor already the new one are reported as conflicts and left untouched.
+/// Directories are created on demand and removed once they become empty.
+pub async fn apply_changes(
+    storage: &MonoStorage,
+    root: Tree,
+    changes: &[TreeChange],
+) -> Result<ApplyResult, GitError> {
+    let mut new_trees = Vec::new();
+    let mut conflicts = Vec::new();
+    let changes = changes
+        .iter()
+        .map(|c| (path_components(&c.path), c))
+        .collect();
+    let res = apply_level(storage, Some(root), changes, &mut new_trees, &mut conflicts).await?;
+    match res {
+        Some(root) => Ok(ApplyResult {
+            root,
+            new_trees,
+            conflicts,
+        }),
+        None => Err(GitError::CustomError(
+            "the root tree can't be empty".to_string(),
+        )),
+    }
+}
+
+type PendingChange<'a> = (Vec<String>, &'a TreeChange);
+
+fn apply_level<'a>(
+    storage: &'a MonoStorage,
+    tree: Option<Tree>,
+    changes: Vec<PendingChange<'a>>,
+    new_trees: &'a mut Vec<Tree>,
+    conflicts: &'a mut Vec<PathBuf>,
+) -> BoxFuture<'a, Result<Option<Tree>, GitError>> {
+    Box::pin(async move {
+        let origin_id = tree.as_ref().map(|t| t.id);
+        let mut items = tree.map(|t| t.tree_items).unwrap_or_default();
+
+        let mut direct = Vec::new();
+        let mut nested: BTreeMap<String, Vec<PendingChange>> = BTreeMap::new();
+        for (mut components, change) in changes {
+            match components.len() {
+                0 => conflicts.push(change.path.clone()),
+                1 => direct.push((components.remove(0), change)),
+                _ => {
+                    let first = components.remove(0);
+                    nested.entry(first).or_default().push((components, change));
+                }
+            }
+        }
+
+        for (name, change) in direct {
+            let pos = items.iter().position(|x| x.name == name);
+            let current = pos.map(|i| &items[i]);
+            if same_entry(current, change.old.as_ref()) {
+                if let Some(i) = pos {
+                    items.remove(i);
+                }
+                if let Some(new) = &change.new {
+                    items.push(TreeItem::new(new.mode, new.id, name));
+                }
+            } else if !same_entry(current, change.new.as_ref()) {
+                conflicts.push(change.path.clone());
+            }
+        }
+
+        for (name, sub_changes) in nested {
+            let pos = items.iter().position(|x| x.name == name);
+            let sub_tree = match pos.map(|i| &items[i]) {
+                Some(item) if item.mode == TreeItemMode::Tree => {
+                    Some(load_tree(storage, &item.id).await?)
+                }
+                Some(_) => {
+                    // a file is in the way of a directory
+                    conflicts.extend(sub_changes.into_iter().map(|(_, c)| c.path.clone()));
+                    continue;
+                }
+                None => None,
+            };
+            let res = apply_level(storage, sub_tree, sub_changes, new_trees, conflicts).await?;
+            if let Some(i) = pos {
+                items.remove(i);
+            }
+            if let Some(sub_tree) = res {
+                items.push(TreeItem::new(TreeItemMode::Tree, sub_tree.id, name));
+            }
+        }
+
+        if items.is_empty() {
+            return Ok(None);
+        }
+        sort_tree_items(&mut items);
+        let new_tree = Tree::from_tree_items(items)?;
+        if origin_id != Some(new_tree.id) {
+            new_trees.push(new_tree.clone());
+        }
+        Ok(Some(new_tree))
+    })
+}
+
+/// Sort items in the order git expects, directories compare as if their name ends with `/`.
+pub fn sort_tree_items(items: &mut [TreeItem]) {
+    items.sort_by_cached_key(|item| {
+        let mut key = item.name.as_bytes().to_vec();
+        if item.mode == TreeItemMode::Tree {
+            key.push(b'/');
+        }
+        key
+    });
+}
+
+fn path_components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use mercury::{
+        hash::SHA1,
+        internal::object::tree::{TreeItem, TreeItemMode},
+    };
+
+    use super::{path_components, same_entry, sort_tree_items, TreeChange};
+
+    fn item(mode: TreeItemMode, name: &str, data: &str) -> TreeItem {
+        TreeItem::new(mode, SHA1::new(data.as_bytes()), name.to_owned())
+    }
+
+    #[test]
+    fn test_sort_tree_items() {
+        let mut items = vec![
+            item(TreeItemMode::Blob, "foo.txt", "1"),
+            item(TreeItemMode::Tree, "foo", "2"),
+            item(TreeItemMode::Blob, "foo-bar", "3"),
+            item(TreeItemMode::Blob, "Makefile", "4"),
+        ];
+        sort_tree_items(&mut items);
+        let names: Vec<&str> = items.iter().map(|x| x.name.as_str()).collect();
+        // "foo/" sorts after "foo.txt" because '/' > '.'
+        assert_eq!(names, vec!["Makefile", "foo-bar", "foo.txt", "foo"]);
+    }
+
+    #[test]
+    fn test_same_entry_ignores_name() {
+        let a = item(TreeItemMode::Blob, "a", "content");
+        let b = item(TreeItemMode::Blob, "b", "content");
+        let c = item(TreeItemMode::BlobExecutable, "a", "content");
+        assert!(same_entry(Some(&a), Some(&b)));
+        assert!(!same_entry(Some(&a), Some(&c)));
+        assert!(!same_entry(Some(&a), None));
+        assert!(same_entry(None, None));
+    }
+
+    #[test]
+    fn test_change_helpers() {
+        let change = TreeChange {
+            path: PathBuf::from("src/lib.rs"),
+            old: None,
+            new: Some(item(TreeItemMode::Blob, "lib.rs", "1")),
+        };
+        let reversed = change.reverse();
+        assert_eq!(reversed.old, change.new);
+        assert!(reversed.new.is_none());
+        assert_eq!(
+            change.with_prefix(Path::new("/project/mega")).path,
+            PathBuf::from("/project/mega/src/lib.rs")
+        );
+        assert_eq!(
+            path_components(Path::new("/project/mega/src/lib.rs")),
+            vec!["project", "mega", "src", "lib.rs"]
+        );
+    }
+}
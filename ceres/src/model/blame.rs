@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use mercury::diff::line_mapping;
+
+use crate::model::tree::UserInfo;
+
+#[derive(Debug, Deserialize)]
+pub struct BlameQuery {
+    pub path: String,
+    /// commit to blame at, the latest commit when missing
+    pub refs: Option<String>,
+}
+
+/// A range of lines last changed by the same commit, lines are 1 based and inclusive.
+#[derive(Serialize, Deserialize)]
+pub struct BlameHunk {
+    pub oid: String,
+    pub author: UserInfo,
+    pub date: String,
+    pub short_message: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Attribution of the lines of one file version, filled in while walking back its history.
+///
+/// Commits are referred to by an index chosen by the caller, lines that aren't attributed
+/// yet are kept with their position in the version that is currently looked at.
+pub struct BlameTracker {
+    owners: Vec<Option<usize>>,
+    /// (line in the current version, line in the blamed version)
+    pending: Vec<(usize, usize)>,
+}
+
+impl BlameTracker {
+    pub fn new(lines: usize) -> Self {
+        BlameTracker {
+            owners: vec![None; lines],
+            pending: (0..lines).map(|x| (x, x)).collect(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// `commit` changed the file from `parent` to `current`: lines it added are attributed
+    /// to it, the others move to their position in `parent`.
+    pub fn pass<T: PartialEq>(&mut self, commit: usize, parent: &[T], current: &[T]) {
+        let mapping = line_mapping(parent, current);
+        let mut pending = Vec::with_capacity(self.pending.len());
+        for (line, origin) in self.pending.drain(..) {
+            match mapping[line] {
+                Some(old) => pending.push((old, origin)),
+                None => self.owners[origin] = Some(commit),
+            }
+        }
+        self.pending = pending;
+    }
+
+    /// Attribute every remaining line to `commit`, where the file was created or the walk stops.
+    pub fn finish(&mut self, commit: usize) {
+        for (_, origin) in self.pending.drain(..) {
+            self.owners[origin] = Some(commit);
+        }
+    }
+
+    /// Consecutive lines with the same commit as `(start_line, end_line, commit)`.
+    pub fn ranges(&self) -> Vec<(usize, usize, usize)> {
+        let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+        for (line, owner) in self.owners.iter().enumerate() {
+            let Some(owner) = *owner else {
+                continue;
+            };
+            match ranges.last_mut() {
+                Some((_, end, commit)) if *commit == owner && *end == line => *end = line + 1,
+                _ => ranges.push((line + 1, line + 1, owner)),
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mercury::diff::split_lines;
+
+    use super::*;
+
+    #[test]
+    fn test_blame_tracker() {
+        // newest first: commit 0 edits line 2, commit 1 appends a line, commit 2 creates the file
+        let versions = ["a\nB\nc\nd\n", "a\nb\nc\nd\n", "a\nb\nc\n"];
+        let mut tracker = BlameTracker::new(4);
+        for (commit, pair) in versions.windows(2).enumerate() {
+            tracker.pass(commit, &split_lines(pair[1]), &split_lines(pair[0]));
+        }
+        assert!(!tracker.is_done());
+        tracker.finish(2);
+        assert!(tracker.is_done());
+        assert_eq!(
+            tracker.ranges(),
+            vec![(1, 1, 2), (2, 2, 0), (3, 3, 2), (4, 4, 1)]
+        );
+    }
+
+    #[test]
+    fn test_blame_ranges_merge() {
+        let mut tracker = BlameTracker::new(3);
+        tracker.pass(0, &split_lines("x\n"), &split_lines("a\nb\nx\n"));
+        tracker.finish(1);
+        assert_eq!(tracker.ranges(), vec![(1, 2, 0), (3, 3, 1)]);
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Upper bound of blobs that can be requested in a single batch call.
+pub const MAX_BATCH_BLOBS: usize = 100;
+/// Largest blob served by the raw endpoint, bigger files are expected to live in LFS.
+pub const MAX_RAW_BLOB_SIZE: usize = 100 * 1024 * 1024;
+/// Leading bytes searched for a NUL when telling text from binary, as git does.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Magic numbers of the binary formats served with their own content type.
+const MAGIC_TYPES: [(&[u8], &str); 8] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\x00asm", "application/wasm"),
+];
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BlobBatchQuery {
+    pub hashes: Vec<String>,
+    /// only return size and binary flag, skip the content
+    #[serde(default)]
+    pub metadata_only: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BlobBatchItem {
+    pub oid: String,
+    pub size: usize,
+    /// not known for blobs over the inline limit, they aren't read
+    pub is_binary: bool,
+    /// `None` for binary blobs and blobs over the inline limit
+    pub line_count: Option<usize>,
+    /// `None` for binary blobs, blobs over the inline limit or when only metadata is
+    /// requested
+    pub content: Option<String>,
+    /// where the content of a blob over the inline limit is streamed from
+    pub raw_url: Option<String>,
+}
+
+impl BlobBatchItem {
+    pub fn new(oid: String, data: Vec<u8>, metadata_only: bool) -> Self {
+        let size = data.len();
+        let binary = is_binary(&data);
+        let text = if binary {
+            None
+        } else {
+            String::from_utf8(data).ok()
+        };
+        BlobBatchItem {
+            oid,
+            size,
+            is_binary: binary,
+            line_count: text.as_deref().map(|x| x.lines().count()),
+            content: text.filter(|_| !metadata_only),
+            raw_url: None,
+        }
+    }
+
+    /// A blob too large to be returned in JSON, with a link to its raw content.
+    pub fn too_large(oid: String, size: usize, raw_url: String) -> Self {
+        BlobBatchItem {
+            oid,
+            size,
+            is_binary: false,
+            line_count: None,
+            content: None,
+            raw_url: Some(raw_url),
+        }
+    }
+}
+
+/// Blobs that aren't UTF-8 or have a NUL near the start are binary.
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_SNIFF_LEN)].contains(&0) || std::str::from_utf8(data).is_err()
+}
+
+/// Content type of a raw blob from its leading bytes.
+///
+/// Text is always plain text so that html or svg files can't run scripts when opened
+/// from the raw endpoint, other binaries are an octet stream.
+pub fn sniff_content_type(data: &[u8]) -> &'static str {
+    if let Some((_, content_type)) = MAGIC_TYPES.iter().find(|(x, _)| data.starts_with(x)) {
+        return content_type;
+    }
+    if data.len() > 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if is_binary(data) {
+        "application/octet-stream"
+    } else {
+        "text/plain; charset=utf-8"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sniff_content_type, BlobBatchItem};
+
+    #[test]
+    fn test_batch_item_binary_flag() {
+        let text = BlobBatchItem::new("a".to_owned(), b"hello".to_vec(), false);
+        assert!(!text.is_binary);
+        assert_eq!(text.size, 5);
+        assert_eq!(text.content.as_deref(), Some("hello"));
+
+        let binary = BlobBatchItem::new("b".to_owned(), vec![0xff, 0xfe, 0x00], false);
+        assert!(binary.is_binary);
+        assert!(binary.content.is_none());
+
+        let meta = BlobBatchItem::new("c".to_owned(), b"hello".to_vec(), true);
+        assert!(!meta.is_binary);
+        assert!(meta.content.is_none());
+        assert_eq!(meta.line_count, Some(1));
+
+        let nul = BlobBatchItem::new("d".to_owned(), b"a\0b".to_vec(), false);
+        assert!(nul.is_binary);
+        assert!(nul.content.is_none());
+        assert_eq!(nul.line_count, None);
+    }
+
+    #[test]
+    fn test_batch_item_line_count() {
+        let count =
+            |data: &[u8]| BlobBatchItem::new("a".to_owned(), data.to_vec(), false).line_count;
+        assert_eq!(count(b""), Some(0));
+        assert_eq!(count(b"a\nb\n"), Some(2));
+        assert_eq!(count(b"a\nb"), Some(2));
+    }
+
+    #[test]
+    fn test_sniff_content_type() {
+        assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(
+            sniff_content_type(b"<svg><script/></svg>"),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(sniff_content_type(b""), "text/plain; charset=utf-8");
+        assert_eq!(
+            sniff_content_type(&[0xff, 0xfe, 0x00]),
+            "application/octet-stream"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use common::path::MonoPath;
+use common::utils::{branch_ref_name, validate_ref_name, MEGA_DEFAULT_BRANCH};
+
+use crate::model::query::default_path;
+
+#[derive(Debug, Deserialize)]
+pub struct BranchQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBranchRequest {
+    /// directory and branch as `/project/foo#feature-x`
+    pub branch: String,
+    /// commit the branch starts at, the head of `main` of the directory when missing
+    pub from: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteBranchRequest {
+    pub branch: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateBranchMrRequest {
+    /// branch merged, as `/project/foo#feature-x`
+    pub source: String,
+    /// branch of the same directory merged into
+    #[serde(default = "default_branch")]
+    pub target: String,
+    pub title: Option<String>,
+    /// open the MR as a draft, it can't be merged until marked ready
+    #[serde(default)]
+    pub draft: bool,
+    /// link of the MR this one builds on, it has to be merged first
+    pub depends_on: Option<String>,
+}
+
+fn default_branch() -> String {
+    MEGA_DEFAULT_BRANCH.to_owned()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BranchInfo {
+    pub name: String,
+    pub commit_id: String,
+    /// `main`, the branch merge requests opened by a push go to
+    pub default: bool,
+}
+
+/// A branch of a directory, written as `/project/foo#feature-x`. Without `#` it's the
+/// `main` branch of the directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchPath {
+    pub path: MonoPath,
+    pub branch: String,
+}
+
+impl BranchPath {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let (path, branch) = match raw.rsplit_once('#') {
+            Some((path, branch)) => (path, branch),
+            None => (raw, MEGA_DEFAULT_BRANCH),
+        };
+        let path = MonoPath::parse(path).map_err(|e| e.to_string())?;
+        validate_ref_name(branch)?;
+        Ok(BranchPath {
+            path,
+            branch: branch.to_owned(),
+        })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.branch == MEGA_DEFAULT_BRANCH
+    }
+
+    pub fn ref_name(&self) -> String {
+        branch_ref_name(&self.branch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BranchPath;
+
+    #[test]
+    fn test_branch_path() {
+        let branch = BranchPath::parse("/project/foo#feature/x").unwrap();
+        assert_eq!(branch.path.as_str(), "/project/foo");
+        assert_eq!(branch.branch, "feature/x");
+        assert_eq!(branch.ref_name(), "refs/heads/feature/x");
+        assert!(!branch.is_default());
+
+        let main = BranchPath::parse("/project/foo").unwrap();
+        assert!(main.is_default());
+        assert_eq!(main.ref_name(), "refs/heads/main");
+
+        assert!(BranchPath::parse("/project/foo#").is_err());
+        assert!(BranchPath::parse("/project/foo#a..b").is_err());
+        assert!(BranchPath::parse("/project/../foo#x").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::model::diff::{DiffStat, FileStat};
+use crate::model::tree::UserInfo;
+
+#[derive(Debug, Deserialize)]
+pub struct CherryPickRequest {
+    /// commit to pick, the changes are taken against its first parent
+    pub commit: Option<String>,
+    /// pick all changes of a merge request instead of a single commit
+    pub mr_link: Option<String>,
+    /// directory the changes are applied to, defaults to the MR path
+    /// when picking a merge request, or the root directory otherwise
+    pub target_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevertRequest {
+    /// commit on the root ref to revert
+    pub commit: String,
+}
+
+/// Outcome of a server side commit, either the new commit or the conflicting paths.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct CommitResult {
+    pub commit_id: Option<String>,
+    pub conflicts: Vec<String>,
+}
+
+impl CommitResult {
+    pub fn committed(commit_id: String) -> Self {
+        CommitResult {
+            commit_id: Some(commit_id),
+            conflicts: vec![],
+        }
+    }
+
+    pub fn conflict(conflicts: Vec<String>) -> Self {
+        CommitResult {
+            commit_id: None,
+            conflicts,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CommitPerson {
+    pub name: String,
+    pub email: String,
+    /// unix timestamp
+    pub timestamp: usize,
+    pub timezone: String,
+    /// profile of the mega user with the same email, or the name in the commit
+    pub user: UserInfo,
+}
+
+/// A commit with the files it changed against its first parent.
+#[derive(Serialize, Deserialize)]
+pub struct CommitDetail {
+    pub oid: String,
+    pub tree_id: String,
+    pub parents: Vec<String>,
+    pub author: CommitPerson,
+    pub committer: CommitPerson,
+    /// full message without the pgp signature
+    pub message: String,
+    pub stat: DiffStat,
+    pub files: Vec<FileStat>,
+    /// more than `MAX_DIFF_FILES` files changed, line counts are only known for the first ones
+    pub truncated: bool,
+    /// the commit is signed and the signature was verified when it was pushed
+    pub verified: bool,
+    /// owner of the key the signature was verified with
+    pub signer: Option<String>,
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct CreateFileInfo {
     /// can be a file or directory
     pub is_directory: bool,
@@ -10,3 +11,95 @@ pub struct CreateFileInfo {
     // pub import_dir: bool,
     pub content: Option<String>,
 }
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateFileInfo {
+    /// full path of an existing file
+    pub path: String,
+    pub content: String,
+    /// commit message, a default one is generated when missing
+    pub message: Option<String>,
+    /// blob the edit is based on, the update is refused if the file changed since
+    pub old_oid: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DeleteEntryInfo {
+    /// file or directory to delete, directories are removed with everything below them
+    pub path: String,
+    /// commit message, a default one is generated when missing
+    pub message: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct MoveEntryInfo {
+    /// file or directory to move
+    pub from: String,
+    /// new full path, which must not exist yet
+    pub to: String,
+    /// commit message, a default one is generated when missing
+    pub message: Option<String>,
+}
+
+/// Operations accepted by a single batch commit.
+pub const MAX_BATCH_OPERATIONS: usize = 100;
+
+/// File changes applied together as one commit.
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct BatchCommitInfo {
+    pub message: String,
+    pub operations: Vec<FileOperation>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FileOperation {
+    /// missing parent directories are created
+    Create { path: String, content: String },
+    Update {
+        path: String,
+        content: String,
+        /// blob the edit is based on, the commit is refused if the file changed since
+        old_oid: Option<String>,
+    },
+    /// a directory is removed with everything below it
+    Delete { path: String },
+}
+
+impl FileOperation {
+    pub fn path(&self) -> &str {
+        match self {
+            FileOperation::Create { path, .. }
+            | FileOperation::Update { path, .. }
+            | FileOperation::Delete { path } => path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_batch_commit_info() {
+        let info: BatchCommitInfo = serde_json::from_str(
+            r#"{
+                "message": "rename config",
+                "operations": [
+                    {"action": "create", "path": "/project/b.toml", "content": "a = 1\n"},
+                    {"action": "delete", "path": "/project/a.toml"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(info.operations.len(), 2);
+        assert_eq!(info.operations[1].path(), "/project/a.toml");
+        assert_eq!(
+            info.operations[0],
+            FileOperation::Create {
+                path: "/project/b.toml".to_owned(),
+                content: "a = 1\n".to_owned(),
+            }
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use mercury::diff::unified_diff;
+
+use crate::model::tree::LatestCommitInfo;
+
+/// Unchanged lines shown around every change.
+pub const DIFF_CONTEXT: usize = 3;
+/// Upper bound of files rendered in one diff, the rest are listed without hunks.
+pub const MAX_DIFF_FILES: usize = 300;
+/// Minimum similarity in percent for an added file to be taken as renamed or copied.
+pub const RENAME_THRESHOLD: u8 = 50;
+/// Upper bound of file pairs compared looking for renames and copies, beyond it only
+/// files moved without changes are found.
+pub const MAX_RENAME_PAIRS: usize = 1000;
+/// Upper bound of commits listed by a compare request.
+pub const MAX_COMPARE_COMMITS: usize = 250;
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    /// base commit, the first parent of `to` when missing
+    pub from: Option<String>,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    /// moved, maybe with changes
+    Renamed,
+    /// added as a copy of a file that was changed too, maybe with changes
+    Copied,
+}
+
+/// A changed file listed without its hunks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ChangedFile {
+    pub path: String,
+    /// where a renamed or copied file comes from
+    pub renamed_from: Option<String>,
+    pub status: FileChangeKind,
+    pub old_oid: Option<String>,
+    pub new_oid: Option<String>,
+}
+
+impl ChangedFile {
+    pub fn new(path: String, old_oid: Option<String>, new_oid: Option<String>) -> Self {
+        let status = match (&old_oid, &new_oid) {
+            (None, _) => FileChangeKind::Added,
+            (_, None) => FileChangeKind::Deleted,
+            _ => FileChangeKind::Modified,
+        };
+        ChangedFile {
+            path,
+            renamed_from: None,
+            status,
+            old_oid,
+            new_oid,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FileDiff {
+    pub path: String,
+    pub kind: FileChangeKind,
+    /// where a renamed or copied file comes from, the hunks are against that file
+    pub renamed_from: Option<String>,
+    pub old_oid: Option<String>,
+    pub new_oid: Option<String>,
+    pub is_binary: bool,
+    /// unified diff hunks, empty for binary files, mode changes and files over the limit
+    pub diff: String,
+}
+
+impl FileDiff {
+    pub fn new(path: String, old_oid: Option<String>, new_oid: Option<String>) -> Self {
+        let kind = match (&old_oid, &new_oid) {
+            (None, _) => FileChangeKind::Added,
+            (_, None) => FileChangeKind::Deleted,
+            _ => FileChangeKind::Modified,
+        };
+        FileDiff {
+            path,
+            kind,
+            renamed_from: None,
+            old_oid,
+            new_oid,
+            is_binary: false,
+            diff: String::new(),
+        }
+    }
+
+    /// Render the hunks from the contents of both sides, `None` where the file doesn't exist.
+    pub fn with_content(mut self, old: Option<&[u8]>, new: Option<&[u8]>) -> Self {
+        let old = std::str::from_utf8(old.unwrap_or_default());
+        let new = std::str::from_utf8(new.unwrap_or_default());
+        match (old, new) {
+            (Ok(old), Ok(new)) => self.diff = unified_diff(old, new, DIFF_CONTEXT),
+            _ => self.is_binary = true,
+        }
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitDiff {
+    /// `None` when `to` is a root commit and is compared with the empty tree
+    pub from: Option<String>,
+    pub to: String,
+    pub files: Vec<FileDiff>,
+    /// more than `MAX_DIFF_FILES` files changed, only the first ones have hunks
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    /// a commit, or a directory as `/path@commit`, at the latest commit without `@`
+    pub base: String,
+    pub head: String,
+}
+
+/// One side of a compare request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompareSpec {
+    /// directory compared, the whole tree when `None`
+    pub path: Option<String>,
+    /// the latest commit when `None`
+    pub commit: Option<String>,
+}
+
+impl CompareSpec {
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        if !raw.starts_with('/') {
+            return CompareSpec {
+                path: None,
+                commit: Some(raw.to_owned()),
+            };
+        }
+        let (path, commit) = match raw.rsplit_once('@') {
+            Some((path, commit)) => (path, Some(commit).filter(|x| !x.is_empty())),
+            None => (raw, None),
+        };
+        CompareSpec {
+            path: Some(path.to_owned()),
+            commit: commit.map(str::to_owned),
+        }
+    }
+}
+
+/// Difference of two commits or directory states, with the commits on each side.
+#[derive(Serialize, Deserialize)]
+pub struct Compare {
+    pub base: String,
+    pub head: String,
+    /// the best common ancestor of `base` and `head`, `None` when they're unrelated
+    pub merge_base: Option<String>,
+    /// commits reachable from `head` but not from `base`, and the other way round
+    pub ahead_by: usize,
+    pub behind_by: usize,
+    /// the latest `MAX_COMPARE_COMMITS` commits ahead, oldest first
+    pub commits: Vec<LatestCommitInfo>,
+    pub stat: DiffStat,
+    pub files: Vec<FileDiff>,
+    /// more than `MAX_DIFF_FILES` files changed, only the first ones have hunks
+    pub truncated: bool,
+}
+
+/// Summary of a diff: changed files, added and removed lines.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    /// Lines of files listed without hunks, binary or over the limit, aren't counted.
+    pub fn from_files(files: &[FileDiff]) -> Self {
+        let mut stat = DiffStat {
+            files_changed: files.len(),
+            ..Default::default()
+        };
+        for file in files.iter().map(FileStat::from) {
+            stat.additions += file.additions;
+            stat.deletions += file.deletions;
+        }
+        stat
+    }
+}
+
+/// Added and removed lines of one changed file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    pub path: String,
+    pub kind: FileChangeKind,
+    pub renamed_from: Option<String>,
+    pub is_binary: bool,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+impl From<&FileDiff> for FileStat {
+    fn from(file: &FileDiff) -> Self {
+        let mut stat = FileStat {
+            path: file.path.clone(),
+            kind: file.kind,
+            renamed_from: file.renamed_from.clone(),
+            is_binary: file.is_binary,
+            additions: 0,
+            deletions: 0,
+        };
+        for line in file.diff.lines() {
+            match line.as_bytes().first() {
+                Some(b'+') => stat.additions += 1,
+                Some(b'-') => stat.deletions += 1,
+                _ => {}
+            }
+        }
+        stat
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_diff() {
+        let added = FileDiff::new("/a.txt".to_owned(), None, Some("1".to_owned()))
+            .with_content(None, Some(b"hello\n".as_slice()));
+        assert_eq!(added.kind, FileChangeKind::Added);
+        assert_eq!(added.diff, "@@ -0,0 +1 @@\n+hello\n");
+
+        let deleted = FileDiff::new("/a.txt".to_owned(), Some("1".to_owned()), None);
+        assert_eq!(deleted.kind, FileChangeKind::Deleted);
+
+        let binary = FileDiff::new(
+            "/a.bin".to_owned(),
+            Some("1".to_owned()),
+            Some("2".to_owned()),
+        )
+        .with_content(Some([0xff, 0xfe].as_slice()), Some(b"text".as_slice()));
+        assert_eq!(binary.kind, FileChangeKind::Modified);
+        assert!(binary.is_binary);
+        assert!(binary.diff.is_empty());
+    }
+
+    #[test]
+    fn test_compare_spec() {
+        let commit = "a".repeat(40);
+        assert_eq!(
+            CompareSpec::parse(&commit),
+            CompareSpec {
+                path: None,
+                commit: Some(commit.clone()),
+            }
+        );
+        assert_eq!(
+            CompareSpec::parse(&format!("/project/foo@{}", commit)),
+            CompareSpec {
+                path: Some("/project/foo".to_owned()),
+                commit: Some(commit),
+            }
+        );
+        assert_eq!(
+            CompareSpec::parse("/project/foo"),
+            CompareSpec {
+                path: Some("/project/foo".to_owned()),
+                commit: None,
+            }
+        );
+        assert_eq!(CompareSpec::parse("/project@").commit, None);
+    }
+
+    #[test]
+    fn test_diff_stat() {
+        let files = vec![
+            FileDiff::new(
+                "/a.txt".to_owned(),
+                Some("1".to_owned()),
+                Some("2".to_owned()),
+            )
+            .with_content(
+                Some(b"a\nb\nc\n".as_slice()),
+                Some(b"a\n+b\nc\nd".as_slice()),
+            ),
+            FileDiff::new("/b.bin".to_owned(), None, Some("3".to_owned()))
+                .with_content(None, Some([0xff].as_slice())),
+        ];
+        assert_eq!(
+            DiffStat::from_files(&files),
+            DiffStat {
+                files_changed: 2,
+                additions: 2,
+                deletions: 1,
+            }
+        );
+        let stat = FileStat::from(&files[0]);
+        assert_eq!((stat.additions, stat.deletions), (2, 1));
+        assert!(FileStat::from(&files[1]).is_binary);
+    }
+}
@@ -1,3 +1,10 @@
+pub mod blame;
+pub mod blob;
+pub mod branch;
+pub mod commit;
 pub mod create_file;
+pub mod diff;
 pub mod query;
+pub mod search;
+pub mod tag;
 pub mod tree;
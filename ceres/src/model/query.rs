@@ -1,20 +1,213 @@
+use std::cmp::Ordering;
+
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use common::model::Pagination;
+use common::utils::{glob_match, natural_cmp};
+
+use crate::api_service::archive::ArchiveFormat;
 
 #[allow(dead_code)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct CodePreviewQuery {
     #[serde(default)]
     pub refs: String,
     #[serde(default = "default_path")]
     pub path: String,
+    /// sort key of the listing, names when missing. Sorting by `last_modified` reads the
+    /// last commit of every entry and is slow on large directories
+    pub sort: Option<TreeSortKey>,
+    #[serde(default)]
+    pub desc: bool,
+    #[serde(default = "default_true")]
+    pub dirs_first: bool,
+    /// glob matched against entry names, e.g. `*.rs`
+    pub filter: Option<String>,
+    /// the whole listing is returned when neither `page` nor `per_page` is set
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
+impl CodePreviewQuery {
+    pub fn list_options(&self) -> TreeListOptions {
+        TreeListOptions {
+            sort: self.sort,
+            desc: self.desc,
+            dirs_first: self.dirs_first,
+            filter: self.filter.clone().filter(|x| !x.is_empty()),
+        }
+    }
+
+    pub fn pagination(&self) -> Option<Pagination> {
+        if self.page.is_none() && self.per_page.is_none() {
+            return None;
+        }
+        let default = Pagination::default();
+        Some(
+            Pagination {
+                page: self.page.unwrap_or(default.page),
+                per_page: self.per_page.unwrap_or(default.per_page),
+            }
+            .normalized(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct BlobContentQuery {
     #[serde(default = "default_path")]
     pub path: String,
 }
 
-fn default_path() -> String {
+#[derive(Debug, Deserialize)]
+pub struct ArchiveQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// commit to archive, the latest commit when missing
+    #[serde(alias = "ref")]
+    pub refs: Option<String>,
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeSortKey {
+    Name,
+    Size,
+    LastModified,
+}
+
+/// Server side sorting and filtering of a directory listing.
+#[derive(Debug, Clone)]
+pub struct TreeListOptions {
+    pub sort: Option<TreeSortKey>,
+    pub desc: bool,
+    pub dirs_first: bool,
+    pub filter: Option<String>,
+}
+
+impl Default for TreeListOptions {
+    fn default() -> Self {
+        TreeListOptions {
+            sort: None,
+            desc: false,
+            dirs_first: true,
+            filter: None,
+        }
+    }
+}
+
+/// The fields of a listing entry that take part in sorting.
+pub struct ListEntry<'a> {
+    pub name: &'a str,
+    pub is_dir: bool,
+    pub size: usize,
+    pub date: usize,
+}
+
+impl TreeListOptions {
+    pub fn matches(&self, name: &str) -> bool {
+        match &self.filter {
+            Some(pattern) => glob_match(pattern, name),
+            None => true,
+        }
+    }
+
+    pub fn compare(&self, a: &ListEntry, b: &ListEntry) -> Ordering {
+        let group = if self.dirs_first {
+            b.is_dir.cmp(&a.is_dir)
+        } else {
+            Ordering::Equal
+        };
+        let ord = match self.sort {
+            Some(TreeSortKey::Name) => natural_cmp(a.name, b.name),
+            Some(TreeSortKey::Size) => a.size.cmp(&b.size),
+            Some(TreeSortKey::LastModified) => a.date.cmp(&b.date),
+            None => Ordering::Equal,
+        };
+        let ord = if self.desc { ord.reverse() } else { ord };
+        // name is the tiebreaker so that the output is stable
+        group.then(ord).then_with(|| natural_cmp(a.name, b.name))
+    }
+}
+
+pub(crate) fn default_path() -> String {
     "/".to_string()
 }
+
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(name: &str, is_dir: bool, size: usize, date: usize) -> ListEntry<'_> {
+        ListEntry {
+            name,
+            is_dir,
+            size,
+            date,
+        }
+    }
+
+    #[test]
+    fn test_list_options_compare() {
+        let entries = [
+            entry("b10.txt", false, 5, 3),
+            entry("b2.txt", false, 50, 1),
+            entry("src", true, 0, 2),
+        ];
+        let sorted = |options: &TreeListOptions| {
+            let mut items: Vec<&ListEntry> = entries.iter().collect();
+            items.sort_by(|a, b| options.compare(a, b));
+            items.iter().map(|x| x.name).collect::<Vec<_>>()
+        };
+
+        let mut options = TreeListOptions {
+            sort: Some(TreeSortKey::Name),
+            ..Default::default()
+        };
+        assert_eq!(sorted(&options), vec!["src", "b2.txt", "b10.txt"]);
+
+        options.dirs_first = false;
+        options.desc = true;
+        assert_eq!(sorted(&options), vec!["src", "b10.txt", "b2.txt"]);
+
+        options.sort = Some(TreeSortKey::Size);
+        assert_eq!(sorted(&options), vec!["b2.txt", "b10.txt", "src"]);
+
+        options.sort = Some(TreeSortKey::LastModified);
+        options.desc = false;
+        assert_eq!(sorted(&options), vec!["b2.txt", "src", "b10.txt"]);
+    }
+
+    #[test]
+    fn test_query_pagination() {
+        let query: CodePreviewQuery = serde_json::from_str(r#"{"path": "/"}"#).unwrap();
+        assert_eq!(query.pagination(), None);
+        let query: CodePreviewQuery =
+            serde_json::from_str(r#"{"path": "/", "page": 0, "per_page": 500}"#).unwrap();
+        assert_eq!(
+            query.pagination(),
+            Some(Pagination {
+                page: 1,
+                per_page: 100
+            })
+        );
+    }
+
+    #[test]
+    fn test_list_options_filter() {
+        let mut options = TreeListOptions::default();
+        assert!(options.matches("anything"));
+        options.filter = Some("*.rs".to_owned());
+        assert!(options.matches("lib.rs"));
+        assert!(!options.matches("Cargo.toml"));
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+/// Matching lines returned for one file, the rest are only counted.
+pub const MAX_LINES_PER_FILE: usize = 20;
+/// Longer lines are cut, minified files would otherwise blow up the response.
+const MAX_LINE_LENGTH: usize = 300;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    /// searched case insensitively as a plain substring
+    pub q: String,
+    /// directory to search in, the whole monorepo when missing
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchLine {
+    /// 1 based
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub path: String,
+    pub blob_id: String,
+    pub total_lines: usize,
+    /// the first `MAX_LINES_PER_FILE` matching lines
+    pub lines: Vec<SearchLine>,
+}
+
+impl SearchMatch {
+    /// `None` when no line of `content` contains `needle`, which has to be lowercase.
+    pub fn find(path: String, blob_id: String, content: &str, needle: &str) -> Option<Self> {
+        let mut total_lines = 0;
+        let mut lines = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            if !line.to_lowercase().contains(needle) {
+                continue;
+            }
+            total_lines += 1;
+            if lines.len() < MAX_LINES_PER_FILE {
+                lines.push(SearchLine {
+                    line: index + 1,
+                    text: line.chars().take(MAX_LINE_LENGTH).collect(),
+                });
+            }
+        }
+        (total_lines > 0).then_some(SearchMatch {
+            path,
+            blob_id,
+            total_lines,
+            lines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SearchMatch, MAX_LINES_PER_FILE};
+
+    #[test]
+    fn test_search_match() {
+        let content = "fn main() {\n    Main::run();\n}\n";
+        let found = SearchMatch::find("/a.rs".to_owned(), "1".to_owned(), content, "main").unwrap();
+        assert_eq!(found.total_lines, 2);
+        assert_eq!(found.lines[0].line, 1);
+        assert_eq!(found.lines[1].text, "    Main::run();");
+
+        // trigrams matched but the substring doesn't
+        assert!(SearchMatch::find("/a.rs".to_owned(), "1".to_owned(), "ma in", "main").is_none());
+
+        let many = "x\n".repeat(MAX_LINES_PER_FILE + 5);
+        let found = SearchMatch::find("/b".to_owned(), "2".to_owned(), &many, "x").unwrap();
+        assert_eq!(found.total_lines, MAX_LINES_PER_FILE + 5);
+        assert_eq!(found.lines.len(), MAX_LINES_PER_FILE);
+    }
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::commit::CommitPerson;
+use crate::model::query::default_path;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTagRequest {
+    pub name: String,
+    /// commit tagged, the latest commit when missing
+    pub target: Option<String>,
+    /// directory the tag is scoped to
+    #[serde(default = "default_path")]
+    pub path: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+/// An annotated tag of the monorepo.
+#[derive(Serialize, Deserialize)]
+pub struct TagInfo {
+    pub name: String,
+    pub path: String,
+    pub tag_id: String,
+    /// the tagged object, a commit for tags created through the API
+    pub object_id: String,
+    pub object_type: String,
+    pub message: String,
+    pub tagger: CommitPerson,
+    pub created_at: i64,
+}
@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use mercury::internal::object::tree::{TreeItem, TreeItemMode};
 
-#[derive(Serialize, Deserialize)]
+use crate::model::query::ListEntry;
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct LatestCommitInfo {
     pub oid: String,
     pub date: String,
@@ -10,9 +13,11 @@ pub struct LatestCommitInfo {
     pub author: UserInfo,
     pub committer: UserInfo,
     pub status: String,
+    /// the commit is signed and the signature was verified when it was pushed
+    pub verified: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub struct UserInfo {
     pub display_name: String,
     pub avatar_url: String,
@@ -27,24 +32,34 @@ impl Default for UserInfo {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TreeCommitItem {
     pub oid: String,
     pub name: String,
     pub content_type: String,
+    /// bytes of a file, `None` for directories and submodules
+    pub size: Option<usize>,
     pub message: String,
     pub date: String,
 }
 
+impl TreeCommitItem {
+    pub fn list_entry(&self, size: usize) -> ListEntry<'_> {
+        ListEntry {
+            name: &self.name,
+            is_dir: self.content_type == "directory",
+            size,
+            date: self.date.parse().unwrap_or_default(),
+        }
+    }
+}
+
 impl From<TreeItem> for TreeCommitItem {
     fn from(value: TreeItem) -> Self {
         TreeCommitItem {
             name: value.name,
-            content_type: if value.mode == TreeItemMode::Tree {
-                "directory".to_owned()
-            } else {
-                "file".to_owned()
-            },
+            content_type: content_type(value.mode).to_owned(),
+            size: None,
             oid: String::new(),
             message: String::new(),
             date: String::new(),
@@ -52,11 +67,13 @@ impl From<TreeItem> for TreeCommitItem {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TreeBriefItem {
     pub name: String,
     pub path: String,
     pub content_type: String,
+    /// bytes of a file, `None` for directories and submodules
+    pub size: Option<usize>,
 }
 
 impl From<TreeItem> for TreeBriefItem {
@@ -64,15 +81,22 @@ impl From<TreeItem> for TreeBriefItem {
         TreeBriefItem {
             name: value.name,
             path: String::new(),
-            content_type: if value.mode == TreeItemMode::Tree {
-                "directory".to_owned()
-            } else {
-                "file".to_owned()
-            },
+            content_type: content_type(value.mode).to_owned(),
+            size: None,
         }
     }
 }
 
+/// Type shown to clients, symlinks and submodules are reported so they aren't opened as folders.
+pub fn content_type(mode: TreeItemMode) -> &'static str {
+    match mode {
+        TreeItemMode::Tree => "directory",
+        TreeItemMode::Link => "symlink",
+        TreeItemMode::Commit => "submodule",
+        TreeItemMode::Blob | TreeItemMode::BlobExecutable => "file",
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct MRFileTree {
     pub title: String,
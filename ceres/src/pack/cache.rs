@@ -0,0 +1,290 @@
+//! Packs of full clones kept on disk by path and head commit. A clone of a head that
+//! hasn't moved is sent the stored pack instead of one built again from the object
+//! storage. Merges drop the packs of the paths they change, the warmer builds the pack
+//! of the new head before the next clone asks for it.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ring::digest;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+use common::config::PackConfig;
+use common::utils::ZERO_ID;
+use jupiter::context::Context;
+use mercury::hash::SHA1;
+use taurus::event::pack_cache::PackCacheEvent;
+
+use crate::pack::{monorepo::MonoRepo, PackHandler};
+use crate::protocol::PushOptions;
+
+/// Holds the monorepo path of a cache directory, the directory is named by its hash.
+const PATH_FILE: &str = "path";
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct PackCache {
+    root: PathBuf,
+}
+
+impl PackCache {
+    /// `None` when the cache is turned off in the config.
+    pub fn new(config: &PackConfig) -> Option<Self> {
+        config.enable_pack_cache.then(|| PackCache {
+            root: config.pack_cache_path.clone(),
+        })
+    }
+
+    fn dir(&self, path: &str) -> PathBuf {
+        self.root.join(SHA1::new(path.as_bytes()).to_string())
+    }
+
+    /// The pack cached for `path` at the head `tip`.
+    pub async fn get(&self, path: &str, tip: &str) -> Option<ReceiverStream<Vec<u8>>> {
+        let mut file = File::open(self.dir(path).join(pack_name(tip))).await.ok()?;
+        tracing::info!("send cached pack of {} at {}", path, tip);
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let mut buf = vec![0; CHUNK_SIZE];
+                match file.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        buf.truncate(n);
+                        if tx.send(buf).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("failed to read cached pack: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        Some(ReceiverStream::new(rx))
+    }
+
+    /// Pass `pack` on, a copy becomes the cached pack of `path` at `tip` once it's complete.
+    /// The copy is finished even if the client stops reading, a pack that breaks off
+    /// isn't kept.
+    pub fn store(
+        &self,
+        path: &str,
+        tip: &str,
+        mut pack: ReceiverStream<Vec<u8>>,
+    ) -> ReceiverStream<Vec<u8>> {
+        let dir = self.dir(path);
+        let path = path.to_owned();
+        let tip = tip.to_owned();
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            let tmp = dir.join(format!("{}.{}", pack_name(&tip), rand::random::<u32>()));
+            let mut file = match create_pack_file(&dir, &path, &tmp).await {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    tracing::error!("failed to cache pack of {}: {}", path, e);
+                    None
+                }
+            };
+            let mut client = Some(tx);
+            let mut checksum = PackChecksum::new();
+            while let Some(chunk) = pack.next().await {
+                if let Some(f) = &mut file {
+                    checksum.update(&chunk);
+                    if let Err(e) = f.write_all(&chunk).await {
+                        tracing::error!("failed to cache pack of {}: {}", path, e);
+                        file = None;
+                    }
+                }
+                if let Some(tx) = &client {
+                    if tx.send(chunk).await.is_err() {
+                        client = None;
+                    }
+                }
+                if file.is_none() && client.is_none() {
+                    break;
+                }
+            }
+            let Some(mut file) = file else {
+                let _ = fs::remove_file(&tmp).await;
+                return;
+            };
+            if !checksum.matches() || file.flush().await.is_err() {
+                tracing::warn!("pack of {} at {} is incomplete, not cached", path, tip);
+                let _ = fs::remove_file(&tmp).await;
+                return;
+            }
+            if let Err(e) = replace_pack(&dir, &tmp, &tip).await {
+                tracing::error!("failed to cache pack of {}: {}", path, e);
+                let _ = fs::remove_file(&tmp).await;
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+
+    /// Drop the packs of `path` and of the paths above and below it, a change to `path`
+    /// changes their trees too.
+    pub async fn invalidate(&self, path: &str) {
+        let Ok(mut entries) = fs::read_dir(&self.root).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(cached) = fs::read_to_string(entry.path().join(PATH_FILE)).await else {
+                continue;
+            };
+            if overlaps(&cached, path) {
+                tracing::debug!("drop cached packs of {}", cached);
+                if let Err(e) = fs::remove_dir_all(entry.path()).await {
+                    tracing::error!("failed to drop cached packs of {}: {}", cached, e);
+                }
+            }
+        }
+    }
+}
+
+/// Warm the pack cache in the background, every merge queues a [`PackCacheEvent`] which
+/// drops the outdated packs and builds the pack of the merged path's new head.
+pub fn spawn_warmer(context: Context) {
+    let Some(cache) = PackCache::new(&context.config.pack) else {
+        return;
+    };
+    let mut events = PackCacheEvent::subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("pack cache warmer skipped {} merges", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            cache.invalidate(&event.path).await;
+            warm(&context, &event.path).await;
+        }
+    });
+}
+
+/// Build the pack of the head of `path`, a full pack of it goes through the cache.
+async fn warm(context: &Context, path: &str) {
+    let repo = MonoRepo {
+        context: context.clone(),
+        path: PathBuf::from(path),
+        from_hash: String::new(),
+        to_hash: String::new(),
+        hidden: vec![],
+        view_trees: Arc::default(),
+        filter: None,
+        push_options: PushOptions::default(),
+        pusher: None,
+    };
+    let (head, _) = repo.head_hash().await;
+    if head == ZERO_ID {
+        return;
+    }
+    match repo.full_pack(vec![head.clone()]).await {
+        Ok(mut pack) => {
+            while pack.next().await.is_some() {}
+            tracing::info!("warmed pack cache of {} at {}", path, head);
+        }
+        Err(e) => tracing::error!("failed to warm pack cache of {}: {}", path, e),
+    }
+}
+
+fn pack_name(tip: &str) -> String {
+    format!("{}.pack", tip)
+}
+
+async fn create_pack_file(dir: &Path, path: &str, tmp: &Path) -> std::io::Result<File> {
+    fs::create_dir_all(dir).await?;
+    fs::write(dir.join(PATH_FILE), path).await?;
+    File::create(tmp).await
+}
+
+/// Move the finished pack at `tmp` in place, the packs of older heads go away.
+async fn replace_pack(dir: &Path, tmp: &Path, tip: &str) -> std::io::Result<()> {
+    let name = pack_name(tip);
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.ends_with(".pack") && file_name != name {
+            fs::remove_file(entry.path()).await?;
+        }
+    }
+    fs::rename(tmp, dir.join(name)).await
+}
+
+/// Whether one of the monorepo paths is the other or below it.
+fn overlaps(a: &str, b: &str) -> bool {
+    let a = a.trim_end_matches('/');
+    let b = b.trim_end_matches('/');
+    let below = |x: &str, y: &str| x.strip_prefix(y).is_some_and(|rest| rest.starts_with('/'));
+    a == b || below(a, b) || below(b, a)
+}
+
+/// A pack ends with the SHA-1 of everything before it, a pack whose trailer matches
+/// was written completely.
+struct PackChecksum {
+    hasher: digest::Context,
+    /// the last bytes seen, they may be the trailer
+    tail: Vec<u8>,
+}
+
+impl PackChecksum {
+    const LEN: usize = 20;
+
+    fn new() -> Self {
+        PackChecksum {
+            hasher: digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+            tail: Vec::with_capacity(Self::LEN),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        self.tail.extend_from_slice(chunk);
+        if self.tail.len() > Self::LEN {
+            let split = self.tail.len() - Self::LEN;
+            self.hasher.update(&self.tail[..split]);
+            self.tail.drain(..split);
+        }
+    }
+
+    fn matches(self) -> bool {
+        self.tail.len() == Self::LEN && self.hasher.finish().as_ref() == self.tail.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlaps() {
+        assert!(overlaps("/project", "/project"));
+        assert!(overlaps("/", "/project/a"));
+        assert!(overlaps("/project/a/b", "/project/a/"));
+        assert!(!overlaps("/project/a", "/project/ab"));
+        assert!(!overlaps("/project/a", "/project/b"));
+    }
+
+    #[test]
+    fn test_pack_checksum() {
+        let body = b"PACK\0\0\0\x02\0\0\0\0".to_vec();
+        let mut pack = body.clone();
+        pack.extend_from_slice(digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &body).as_ref());
+
+        let mut checksum = PackChecksum::new();
+        for chunk in pack.chunks(7) {
+            checksum.update(chunk);
+        }
+        assert!(checksum.matches());
+
+        let mut checksum = PackChecksum::new();
+        checksum.update(&pack[..pack.len() - 1]);
+        assert!(!checksum.matches());
+    }
+}
@@ -0,0 +1,129 @@
+//! Checks commit messages against the `commit_message_rules` of a directory, for pushes
+//! and merges of merge requests.
+
+use regex::Regex;
+
+use common::config::CommitMessageRule;
+use mercury::internal::object::commit::Commit;
+
+/// Commits listed in a refusal, the count of the others is given.
+const MAX_REPORTED: usize = 5;
+
+/// Refuse `commits` when one of them doesn't follow `rule`, the message names every
+/// commit and what's wrong with it on a single line, as report-status needs.
+pub fn check(rule: &CommitMessageRule, commits: &[Commit]) -> Result<(), String> {
+    let subject_pattern = compile(rule.subject_pattern.as_deref(), "subject_pattern")?;
+    let issue_pattern = compile(rule.issue_pattern.as_deref(), "issue_pattern")?;
+
+    let mut problems = Vec::new();
+    for commit in commits.iter().filter(|c| c.parent_commit_ids.len() <= 1) {
+        let subject = commit.format_message();
+        let mut reasons = Vec::new();
+        if let Some(pattern) = &subject_pattern {
+            if !pattern.is_match(&subject) {
+                reasons.push(format!("subject doesn't match {}", pattern.as_str()));
+            }
+        }
+        if let Some(max) = rule.max_subject_length {
+            let len = subject.chars().count();
+            if len > max {
+                reasons.push(format!("subject is {} characters, at most {}", len, max));
+            }
+        }
+        if let Some(pattern) = &issue_pattern {
+            if !pattern.is_match(&commit.message) {
+                reasons.push(format!("no issue reference matching {}", pattern.as_str()));
+            }
+        }
+        if !reasons.is_empty() {
+            let id = commit.id.to_string();
+            problems.push(format!(
+                "{} \"{}\": {}",
+                &id[..7],
+                subject,
+                reasons.join(", ")
+            ));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let more = problems.len().saturating_sub(MAX_REPORTED);
+    problems.truncate(MAX_REPORTED);
+    let mut report = format!(
+        "commit messages must follow the conventions of {}: {}",
+        rule.path,
+        problems.join("; ")
+    );
+    if more > 0 {
+        report.push_str(&format!(" and {} more", more));
+    }
+    Err(report)
+}
+
+fn compile(pattern: Option<&str>, name: &str) -> Result<Option<Regex>, String> {
+    pattern
+        .map(|x| {
+            Regex::new(x).map_err(|e| format!("invalid {} in commit message rule: {}", name, e))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mercury::hash::SHA1;
+
+    fn commit(parents: usize, message: &str) -> Commit {
+        let parents = (0..parents).map(|i| SHA1::new(&[i as u8])).collect();
+        Commit::from_tree_id(SHA1::new(&[]), parents, message)
+    }
+
+    fn rule() -> CommitMessageRule {
+        CommitMessageRule {
+            path: "/project".to_owned(),
+            subject_pattern: Some(r"^(feat|fix|docs|chore)(\([\w-]+\))?: .+".to_owned()),
+            max_subject_length: Some(50),
+            issue_pattern: Some(r"#\d+".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let good = [
+            commit(1, "feat(api): add readme endpoint\n\nCloses #12\n"),
+            commit(1, "fix: typo #3\n"),
+        ];
+        assert!(check(&rule(), &good).is_ok());
+
+        let bad = [commit(1, "Added stuff\n")];
+        let report = check(&rule(), &bad).unwrap_err();
+        assert!(report.contains("\"Added stuff\""));
+        assert!(report.contains("subject doesn't match"));
+        assert!(report.contains("no issue reference"));
+        assert!(!report.contains('\n'));
+
+        let long = [commit(1, &format!("fix: {} #1\n", "x".repeat(60)))];
+        assert!(check(&rule(), &long).unwrap_err().contains("at most 50"));
+
+        // merge commits are left out
+        assert!(check(&rule(), &[commit(2, "Merge branch 'dev'\n")]).is_ok());
+    }
+
+    #[test]
+    fn test_report_is_bounded() {
+        let bad: Vec<Commit> = (0..8).map(|i| commit(1, &format!("wip {}\n", i))).collect();
+        let report = check(&rule(), &bad).unwrap_err();
+        assert_eq!(report.matches("wip").count(), MAX_REPORTED);
+        assert!(report.ends_with(" and 3 more"));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let rule = CommitMessageRule {
+            subject_pattern: Some("(".to_owned()),
+            ..rule()
+        };
+        assert!(check(&rule, &[]).unwrap_err().contains("subject_pattern"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Pre-receive hooks: external commands and policies built into mega that can refuse a
+//! pushed ref update. Post-receive hooks run from the [`PushEvent`] queued after the update.
+//! The commit message rules of the directory are checked before them.
+
+use common::config::{HookStage, MonoConfig};
+use mercury::internal::object::commit::Commit;
+use taurus::event::push::{run_hook, PushEvent};
+
+use crate::pack::commit_message;
+
+/// A push policy written in Rust, chosen with `builtin` in a hook entry.
+pub trait PolicyPlugin: Send + Sync {
+    /// Refuse the update with a message for the pusher, `commits` are the ones it adds.
+    fn check(&self, push: &PushEvent, commits: &[Commit]) -> Result<(), String>;
+}
+
+/// Keeps the history linear, merges happen through merge requests.
+pub struct NoMergeCommits;
+
+impl PolicyPlugin for NoMergeCommits {
+    fn check(&self, _push: &PushEvent, commits: &[Commit]) -> Result<(), String> {
+        match commits.iter().find(|c| c.parent_commit_ids.len() > 1) {
+            Some(c) => Err(format!("{} is a merge commit, rebase instead", c.id)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Every commit carries a `Signed-off-by` trailer.
+pub struct SignedOffBy;
+
+impl PolicyPlugin for SignedOffBy {
+    fn check(&self, _push: &PushEvent, commits: &[Commit]) -> Result<(), String> {
+        let unsigned = commits.iter().find(|c| {
+            !c.message
+                .lines()
+                .any(|line| line.trim_start().starts_with("Signed-off-by:"))
+        });
+        match unsigned {
+            Some(c) => Err(format!("{} has no Signed-off-by line", c.id)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The built-in policy called `name`.
+pub fn builtin(name: &str) -> Option<Box<dyn PolicyPlugin>> {
+    match name {
+        "no-merge-commits" => Some(Box::new(NoMergeCommits)),
+        "signed-off-by" => Some(Box::new(SignedOffBy)),
+        _ => None,
+    }
+}
+
+/// Check the commit message rule of the pushed directory, then run its pre-receive hooks
+/// in order, the first failing one refuses the update with its message.
+pub async fn pre_receive(
+    config: &MonoConfig,
+    push: &PushEvent,
+    commits: &[Commit],
+) -> Result<(), String> {
+    if let Some(rule) = config.commit_message_rule(&push.path) {
+        commit_message::check(rule, commits)?;
+    }
+    for hook in config.hooks(HookStage::PreReceive, &push.path) {
+        if let Some(name) = &hook.builtin {
+            let plugin = builtin(name).ok_or_else(|| format!("unknown builtin hook {}", name))?;
+            plugin.check(push, commits)?;
+        } else if !hook.command.is_empty() {
+            run_hook(&hook.command, push).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mercury::hash::SHA1;
+
+    fn commit(parents: usize, message: &str) -> Commit {
+        let parents = (0..parents).map(|i| SHA1::new(&[i as u8])).collect();
+        Commit::from_tree_id(SHA1::new(&[]), parents, message)
+    }
+
+    fn push() -> PushEvent {
+        PushEvent {
+            path: "/project".to_owned(),
+            ref_name: "refs/heads/dev".to_owned(),
+            old_id: "0".repeat(40),
+            new_id: "1".repeat(40),
+            commits: vec![],
+        }
+    }
+
+    #[test]
+    fn test_no_merge_commits() {
+        let plugin = builtin("no-merge-commits").unwrap();
+        let linear = [commit(1, "b\n"), commit(0, "a\n")];
+        assert!(plugin.check(&push(), &linear).is_ok());
+        let merge = [commit(2, "merge\n"), commit(1, "a\n")];
+        assert!(plugin.check(&push(), &merge).is_err());
+    }
+
+    #[test]
+    fn test_signed_off_by() {
+        let plugin = builtin("signed-off-by").unwrap();
+        let signed = [commit(1, "fix\n\nSigned-off-by: mega <mega@example.com>\n")];
+        assert!(plugin.check(&push(), &signed).is_ok());
+        let unsigned = [signed[0].clone(), commit(1, "fix\n")];
+        assert!(plugin.check(&push(), &unsigned).is_err());
+        assert!(builtin("no-such-policy").is_none());
+    }
+}
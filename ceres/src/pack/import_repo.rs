@@ -10,30 +10,39 @@ use std::{
 
 use async_trait::async_trait;
 use futures::{future::join_all, StreamExt};
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, task::JoinHandle};
 use tokio_stream::wrappers::ReceiverStream;
 
-use callisto::{db_enums::RefType, mega_tree, raw_blob};
+use callisto::{
+    db_enums::{RefType, StorageType},
+    mega_tree, raw_blob,
+};
 use common::errors::MegaError;
 use jupiter::{context::Context, storage::batch_save_model};
 use mercury::{
     errors::GitError,
     internal::{
-        object::{blob::Blob, commit::Commit, tag::Tag, tree::Tree},
+        object::{blob::Blob, commit::Commit, fsck, tag::Tag, tree::Tree},
         pack::entry::Entry,
     },
 };
-use mercury::{hash::SHA1, internal::pack::encode::PackEncoder};
+use mercury::{
+    hash::SHA1,
+    internal::pack::{encode::PackEncoder, ExternalBase},
+};
+use taurus::event::dir_stats::DirStatsEvent;
+use taurus::event::search_index::SearchIndexEvent;
 
 use crate::{
     api_service::{mono_api_service::MonoApiService, ApiHandler},
-    pack::PackHandler,
+    pack::{finish_unpack, stored_base, PackHandler, Unpacked, MAX_PENDING_BATCHES},
     protocol::{
         import_refs::{CommandType, RefCommand, Refs},
         repo::Repo,
     },
 };
 
+#[derive(Clone)]
 pub struct ImportRepo {
     pub context: Context,
     pub repo: Repo,
@@ -55,29 +64,31 @@ impl PackHandler for ImportRepo {
         self.find_head_hash(refs)
     }
 
-    async fn handle_receiver(&self, receiver: Receiver<Entry>) -> Result<Option<Commit>, GitError> {
-        let storage = self.context.services.git_db_storage.clone();
-        let mut entry_list = vec![];
-        let mut join_tasks = vec![];
-        let repo_id = self.repo.repo_id;
-        for entry in receiver {
-            entry_list.push(entry);
-            if entry_list.len() >= 10000 {
-                let stg_clone = storage.clone();
-                let handle = tokio::spawn(async move {
-                    stg_clone.save_entry(repo_id, entry_list).await.unwrap();
-                });
-                join_tasks.push(handle);
-                entry_list = vec![];
-            }
-        }
-        join_all(join_tasks).await;
-        storage.save_entry(repo_id, entry_list).await.unwrap();
-        self.attach_to_monorepo_parent().await.unwrap();
-        Ok(None)
+    async fn handle_receiver(
+        &self,
+        receiver: Receiver<Entry>,
+        unpacked: Unpacked,
+    ) -> Result<Option<Commit>, GitError> {
+        // the objects of a push are stored together or not at all
+        self.context
+            .transaction(|context| async move {
+                let repo = ImportRepo {
+                    context,
+                    ..self.clone()
+                };
+                repo.save_entries(receiver, unpacked).await
+            })
+            .await
+    }
+
+    fn external_base(&self) -> Option<ExternalBase> {
+        Some(stored_base(self.clone()))
     }
 
     async fn full_pack(&self, _: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        if let Some(stream) = self.stored_full_pack().await? {
+            return Ok(stream);
+        }
         let pack_config = &self.context.config.pack;
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
@@ -85,7 +96,8 @@ impl PackHandler for ImportRepo {
         let storage = self.context.services.git_db_storage.clone();
         let raw_storage = self.context.services.raw_db_storage.clone();
         let total = storage.get_obj_count_by_repo_id(self.repo.repo_id).await;
-        let encoder = PackEncoder::new(total, 0, stream_tx);
+        let encoder = PackEncoder::new(total, pack_config.delta_window, stream_tx)
+            .with_max_depth(pack_config.delta_depth);
         encoder.encode_async(entry_rx).await.unwrap();
 
         let repo_id = self.repo.repo_id;
@@ -137,7 +149,6 @@ impl PackHandler for ImportRepo {
                     while let Some(model) = blob_stream.next().await {
                         match model {
                             Ok(m) => {
-                                // todo handle storage type
                                 let b: Blob = m.into();
                                 let entry: Entry = b.into();
                                 sender_clone.send(entry).await.unwrap();
@@ -169,71 +180,48 @@ impl PackHandler for ImportRepo {
         want: Vec<String>,
         have: Vec<String>,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
-        let mut want_clone = want.clone();
         let pack_config = &self.context.config.pack;
-        let storage = self.context.services.git_db_storage.clone();
         let obj_num = AtomicUsize::new(0);
 
-        let mut exist_objs = HashSet::new();
+        let (want_commits, have_commits) = self.negotiate_commits(&want, &have).await?;
 
-        let mut want_commits: Vec<Commit> = storage
-            .get_commits_by_hashes(self.repo.repo_id, &want_clone)
-            .await
-            .unwrap()
-            .into_iter()
-            .map(|x| x.into())
+        let want_tree_ids = want_commits
+            .iter()
+            .map(|c| c.tree_id.to_string())
             .collect();
-        let mut traversal_list: Vec<Commit> = want_commits.clone();
-
-        // traverse commit's all parents to find the commit that client does not have
-        while let Some(temp) = traversal_list.pop() {
-            for p_commit_id in temp.parent_commit_ids {
-                let p_commit_id = p_commit_id.to_string();
-
-                if !have.contains(&p_commit_id) && !want_clone.contains(&p_commit_id) {
-                    let parent: Commit = storage
-                        .get_commit_by_hash(self.repo.repo_id, &p_commit_id)
-                        .await
-                        .unwrap()
-                        .unwrap()
-                        .into();
-                    want_commits.push(parent.clone());
-                    want_clone.push(p_commit_id);
-                    traversal_list.push(parent);
-                }
-            }
-        }
-
-        let want_tree_ids = want_commits.iter().map(|c| c.tree_id.to_string()).collect();
-        let want_trees: HashMap<SHA1, Tree> = storage
-            .get_trees_by_hashes(self.repo.repo_id, want_tree_ids)
+        let want_trees: HashMap<SHA1, Tree> = self
+            .get_trees_by_hashes(want_tree_ids)
             .await
             .unwrap()
             .into_iter()
-            .map(|m| (SHA1::from_str(&m.tree_id).unwrap(), m.into()))
+            .map(|t| (t.id, t))
             .collect();
 
         obj_num.fetch_add(want_commits.len(), Ordering::SeqCst);
 
-        let have_commits = storage
-            .get_commits_by_hashes(self.repo.repo_id, &have)
-            .await
-            .unwrap();
-        let have_trees = storage
+        // everything reachable from the trees the client already has is left out
+        let mut exist_objs = HashSet::new();
+        let have_trees = self
             .get_trees_by_hashes(
-                self.repo.repo_id,
-                have_commits.iter().map(|x| x.tree.clone()).collect(),
+                have_commits
+                    .iter()
+                    .map(|c| c.tree_id.to_string())
+                    .collect(),
             )
             .await
             .unwrap();
-        // traverse to get exist_objs
         for have_tree in have_trees {
-            self.traverse(have_tree.into(), &mut exist_objs, None).await;
+            exist_objs.insert(have_tree.id.to_string());
+            self.traverse(have_tree, &mut exist_objs, None).await;
         }
 
         let mut counted_obj = HashSet::new();
         // traverse for get obj nums
-        for c in want_commits.clone() {
+        for c in &want_commits {
+            let tree_id = c.tree_id.to_string();
+            if exist_objs.contains(&tree_id) || !counted_obj.insert(tree_id) {
+                continue;
+            }
             self.traverse_for_count(
                 want_trees.get(&c.tree_id).unwrap().clone(),
                 &exist_objs,
@@ -244,23 +232,40 @@ impl PackHandler for ImportRepo {
         }
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
-        let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
+        let encoder = PackEncoder::new(obj_num.into_inner(), pack_config.delta_window, stream_tx)
+            .with_max_depth(pack_config.delta_depth);
         encoder.encode_async(entry_rx).await.unwrap();
 
-        for c in want_commits {
-            self.traverse(
-                want_trees.get(&c.tree_id).unwrap().clone(),
-                &mut exist_objs,
-                Some(&entry_tx),
-            )
-            .await;
-            entry_tx.send(c.into()).await.unwrap();
-        }
-        drop(entry_tx);
+        let repo = self.clone();
+        tokio::spawn(async move {
+            for c in want_commits {
+                if exist_objs.insert(c.tree_id.to_string()) {
+                    repo.traverse(
+                        want_trees.get(&c.tree_id).unwrap().clone(),
+                        &mut exist_objs,
+                        Some(&entry_tx),
+                    )
+                    .await;
+                }
+                entry_tx.send(c.into()).await.unwrap();
+            }
+        });
 
         Ok(ReceiverStream::new(stream_rx))
     }
 
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError> {
+        Ok(self
+            .context
+            .services
+            .git_db_storage
+            .get_commits_by_hashes(self.repo.repo_id, &hashes)
+            .await?
+            .into_iter()
+            .map(|x| x.into())
+            .collect())
+    }
+
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
         Ok(self
             .context
@@ -337,7 +342,94 @@ impl PackHandler for ImportRepo {
 }
 
 impl ImportRepo {
+    /// The packs the objects of the repo were stored in, copied into one, when they hold
+    /// exactly the objects of the repo. Objects stored by another repo or kept outside
+    /// of packs make it `None`, the pack is built from the database then.
+    async fn stored_full_pack(&self) -> Result<Option<ReceiverStream<Vec<u8>>>, GitError> {
+        let services = &self.context.services;
+        let Some(pack_storage) = services.raw_db_storage.blob_store().pack_storage() else {
+            return Ok(None);
+        };
+        let ids = services
+            .git_db_storage
+            .get_obj_ids_by_repo_id(self.repo.repo_id)
+            .await?;
+        let mut packs = HashSet::new();
+        let hashes: Vec<String> = ids.iter().cloned().collect();
+        for chunk in hashes.chunks(10000) {
+            for (storage_type, location) in services
+                .raw_db_storage
+                .get_raw_blob_locations(chunk.to_vec())
+                .await?
+            {
+                match (storage_type, location) {
+                    (StorageType::PackFile, Some(pack)) => packs.insert(pack),
+                    _ => return Ok(None),
+                };
+            }
+        }
+
+        // every object is in one of the packs and the packs hold nothing else
+        let mut indexes = vec![];
+        for pack in &packs {
+            indexes.push(pack_storage.index(pack.as_ref())?);
+        }
+        let stored: usize = indexes.iter().map(|x| x.len()).sum();
+        let all_stored = ids.iter().all(|id| {
+            SHA1::from_str(id).is_ok_and(|hash| indexes.iter().any(|x| x.contains(&hash)))
+        });
+        if packs.is_empty() || stored != ids.len() || !all_stored {
+            return Ok(None);
+        }
+
+        let packs: Vec<String> = packs.into_iter().collect();
+        let (stream_tx, stream_rx) = mpsc::channel(self.context.config.pack.channel_message_size);
+        tokio::spawn(async move {
+            if let Err(err) = pack_storage.combine(&packs, stream_tx).await {
+                tracing::error!("failed to send stored packs: {}", err);
+            }
+        });
+        Ok(Some(ReceiverStream::new(stream_rx)))
+    }
+
     // attach import repo to monorepo parent tree
+    /// Store the objects of a push, fails when the pack they come from fails to decode.
+    async fn save_entries(
+        &self,
+        receiver: Receiver<Entry>,
+        unpacked: Unpacked,
+    ) -> Result<Option<Commit>, GitError> {
+        let storage = self.context.services.git_db_storage.clone();
+        let mut entry_list = vec![];
+        let mut join_tasks: Vec<JoinHandle<Result<(), MegaError>>> = vec![];
+        let repo_id = self.repo.repo_id;
+        for entry in receiver {
+            fsck::check_entry(&entry)?;
+            entry_list.push(entry);
+            if entry_list.len() >= 10000 {
+                if join_tasks.len() >= MAX_PENDING_BATCHES {
+                    join_tasks
+                        .remove(0)
+                        .await
+                        .map_err(|e| GitError::CustomError(e.to_string()))??;
+                }
+                let stg_clone = storage.clone();
+                let handle =
+                    tokio::spawn(async move { stg_clone.save_entry(repo_id, entry_list).await });
+                join_tasks.push(handle);
+                entry_list = vec![];
+            }
+        }
+        // every batch has to be stored before the transaction ends
+        for res in join_all(join_tasks).await {
+            res.map_err(|e| GitError::CustomError(e.to_string()))??;
+        }
+        finish_unpack(unpacked).await?;
+        storage.save_entry(repo_id, entry_list).await?;
+        self.attach_to_monorepo_parent().await?;
+        Ok(None)
+    }
+
     async fn attach_to_monorepo_parent(&self) -> Result<(), GitError> {
         let iter = self
             .command_list
@@ -352,6 +444,7 @@ impl ImportRepo {
         let path = PathBuf::from(self.repo.repo_path.clone());
         let mono_api_service = MonoApiService {
             context: self.context.clone(),
+            author: None,
         };
         let storage = self.context.services.mono_storage.clone();
         let save_trees = mono_api_service.search_and_create_tree(&path).await?;
@@ -386,10 +479,14 @@ impl ImportRepo {
             .await
             .unwrap();
 
-        root_ref.ref_commit_hash = new_commit.id.to_string();
+        let old_commit_hash =
+            std::mem::replace(&mut root_ref.ref_commit_hash, new_commit.id.to_string());
         root_ref.ref_tree_hash = new_commit.tree_id.to_string();
-        storage.update_ref(root_ref).await.unwrap();
+        storage.update_ref(root_ref, &old_commit_hash).await?;
+        let commit_id = new_commit.id.to_string();
         storage.save_mega_commits(vec![new_commit]).await.unwrap();
+        SearchIndexEvent::notify(&commit_id);
+        DirStatsEvent::notify(&commit_id);
         Ok(())
     }
 }
@@ -1,27 +1,36 @@
 use std::{
-    collections::HashSet,
+    collections::{BinaryHeap, HashMap, HashSet},
     pin::Pin,
     sync::{
         atomic::{AtomicUsize, Ordering},
         mpsc::Receiver,
+        Arc,
     },
 };
 
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::Stream;
+use tokio::task::JoinHandle;
 use tokio_stream::wrappers::ReceiverStream;
 
-use crate::protocol::import_refs::{RefCommand, Refs};
+use crate::protocol::{
+    import_refs::{RefCommand, Refs},
+    ObjectFilter,
+};
 use callisto::raw_blob;
 use common::{
     config::PackConfig,
     errors::{MegaError, ProtocolError},
     utils::ZERO_ID,
 };
-use mercury::internal::{object::commit::Commit, pack::Pack};
+use mercury::internal::{
+    object::commit::Commit,
+    pack::{encode::PackEncoder, ExternalBase, Pack},
+};
 use mercury::{
     errors::GitError,
+    hash::SHA1,
     internal::{
         object::{
             blob::Blob,
@@ -31,22 +40,61 @@ use mercury::{
     },
 };
 
+pub mod cache;
+pub mod commit_message;
+pub mod hooks;
 pub mod import_repo;
 pub mod monorepo;
 
+/// Thin pack bases looked up in what `handler` stores. The lookup blocks, it is made from
+/// the thread decoding the pack.
+pub(crate) fn stored_base<T: PackHandler + 'static>(handler: T) -> ExternalBase {
+    let handle = tokio::runtime::Handle::current();
+    Arc::new(move |hash: SHA1| handle.block_on(handler.load_entry(&hash.to_string())))
+}
+
+/// The task decoding a pushed pack, it ends with the error of a pack too large or broken.
+pub type Unpacked = JoinHandle<Result<(), ProtocolError>>;
+
+/// Wait until the pushed pack the objects were received from is decoded. A pack that
+/// fails to decode fails the push, none of the objects received from it may be kept.
+pub(crate) async fn finish_unpack(unpacked: Unpacked) -> Result<(), GitError> {
+    unpacked
+        .await
+        .map_err(|e| GitError::CustomError(e.to_string()))?
+        .map_err(|e| GitError::CustomError(format!("failed to decode the pack: {}", e)))
+}
+
+/// Batches of pushed objects being stored at once, receiving waits for the oldest one
+/// beyond them so that a push faster than the database doesn't pile up in memory.
+const MAX_PENDING_BATCHES: usize = 8;
+
+/// Blobs are loaded from storage in batches of this size while packing,
+/// so a directory of large files is never held in memory at once.
+const BLOB_BATCH_SIZE: usize = 64;
+
 #[async_trait]
 pub trait PackHandler: Send + Sync {
     async fn head_hash(&self) -> (String, Vec<Refs>);
 
-    async fn handle_receiver(&self, rx: Receiver<Entry>) -> Result<Option<Commit>, GitError>;
+    /// Store the objects received while the pack is decoded by `unpacked`, nothing is kept
+    /// when it fails to decode.
+    async fn handle_receiver(
+        &self,
+        rx: Receiver<Entry>,
+        unpacked: Unpacked,
+    ) -> Result<Option<Commit>, GitError>;
 
     /// Asynchronously retrieves the full pack data for the specified repository path.
-    /// This function collects commits and nodes from the storage and packs them into
-    /// a single binary vector. There is no need to build the entire tree; the function
-    /// only sends all the data related to this repository.
+    /// This function collects commits and nodes from the storage and encodes them into
+    /// a pack. There is no need to build the entire tree; the function only sends all
+    /// the data related to this repository.
+    ///
+    /// Objects are loaded and encoded in a background task while the returned stream is
+    /// consumed, so the pack is never held in memory as a whole.
     ///
     /// # Returns
-    /// * `Result<Vec<u8>, GitError>` - The packed binary data as a vector of bytes.
+    /// * `Result<ReceiverStream<Vec<u8>>, GitError>` - The stream of encoded pack chunks.
     ///
     async fn full_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError>;
 
@@ -56,6 +104,8 @@ pub trait PackHandler: Send + Sync {
         have: Vec<String>,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError>;
 
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError>;
+
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError>;
 
     async fn get_blobs_by_hashes(
@@ -76,6 +126,84 @@ pub trait PackHandler: Send + Sync {
 
     async fn check_default_branch(&self) -> bool;
 
+    /// Whether pushed commits for `ref_name` go through a merge request, other refs are
+    /// updated by `update_refs` without one.
+    fn push_opens_mr(&self, _ref_name: &str) -> bool {
+        true
+    }
+
+    /// Refuse a pushed ref update before anything is written for it, after the pack has
+    /// been unpacked so the pushed commits can be looked at.
+    async fn check_ref_update(&self, _refs: &RefCommand) -> Result<(), GitError> {
+        Ok(())
+    }
+
+    /// Follow-up work for a pushed ref that was updated, it can't fail the push.
+    async fn post_receive(&self, _refs: &RefCommand) {}
+
+    /// Where the delta bases missing from a pushed thin pack are looked up.
+    fn external_base(&self) -> Option<ExternalBase> {
+        None
+    }
+
+    /// Load a stored commit, tree or blob as a pack entry.
+    async fn load_entry(&self, hash: &str) -> Option<Entry> {
+        let hashes = vec![hash.to_owned()];
+        if let Some(c) = self.get_commits_by_hashes(hashes.clone()).await.ok()?.pop() {
+            return Some(c.into());
+        }
+        if let Some(t) = self.get_trees_by_hashes(hashes.clone()).await.ok()?.pop() {
+            return Some(t.into());
+        }
+        let blob: Blob = self.get_blobs_by_hashes(hashes).await.ok()?.pop()?.into();
+        Some(blob.into())
+    }
+
+    /// Objects left out of the packs built by `traverse`, set by a partial clone.
+    fn object_filter(&self) -> Option<ObjectFilter> {
+        None
+    }
+
+    /// Pack just the objects in `want`, for a partial clone fetching the blobs and trees
+    /// it left out before. Hashes not found are skipped.
+    async fn objects_pack(
+        &self,
+        pack_config: &PackConfig,
+        want: Vec<String>,
+    ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        let to_git_err = |e: MegaError| GitError::CustomError(e.to_string());
+        let mut entries: Vec<Entry> = vec![];
+        for t in self
+            .get_trees_by_hashes(want.clone())
+            .await
+            .map_err(to_git_err)?
+        {
+            entries.push(t.into());
+        }
+        for chunk in want.chunks(BLOB_BATCH_SIZE) {
+            for b in self
+                .get_blobs_by_hashes(chunk.to_vec())
+                .await
+                .map_err(to_git_err)?
+            {
+                let blob: Blob = b.into();
+                entries.push(blob.into());
+            }
+        }
+
+        let (entry_tx, entry_rx) = tokio::sync::mpsc::channel(pack_config.channel_message_size);
+        let (stream_tx, stream_rx) = tokio::sync::mpsc::channel(pack_config.channel_message_size);
+        let encoder = PackEncoder::new(entries.len(), pack_config.delta_window, stream_tx)
+            .with_max_depth(pack_config.delta_depth);
+        encoder.encode_async(entry_rx).await.unwrap();
+        tokio::spawn(async move {
+            for entry in entries {
+                entry_tx.send(entry).await.unwrap();
+            }
+        });
+        Ok(ReceiverStream::new(stream_rx))
+    }
+
     fn find_head_hash(&self, refs: Vec<Refs>) -> (String, Vec<Refs>) {
         let mut head_hash = ZERO_ID.to_string();
         for git_ref in refs.iter() {
@@ -86,18 +214,24 @@ pub trait PackHandler: Send + Sync {
         (head_hash, refs)
     }
 
+    /// Decode a pushed pack, its objects are received while it's decoded and at most
+    /// `decode_window` of them wait to be handled. Deltas are resolved by the thread pool
+    /// of the decoder, one thread per core.
     async fn unpack_stream(
         &self,
         pack_config: &PackConfig,
         stream: Pin<Box<dyn Stream<Item = Result<Bytes, axum::Error>> + Send>>,
-    ) -> Result<Receiver<Entry>, ProtocolError> {
-        let (sender, receiver) = std::sync::mpsc::channel();
-        let p = Pack::new(
+    ) -> (Receiver<Entry>, Unpacked) {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(pack_config.decode_window);
+        let mut p = Pack::new(
             None,
             Some(1024 * 1024 * 1024 * pack_config.pack_decode_mem_size),
             Some(pack_config.pack_decode_cache_path.clone()),
             pack_config.clean_cache_after_decode,
         );
+        if let Some(external_base) = self.external_base() {
+            p = p.with_external_base(external_base);
+        }
         let (unpack_handle, convert) = p
             .decode_stream(
                 stream,
@@ -105,15 +239,96 @@ pub trait PackHandler: Send + Sync {
                 sender,
             )
             .await;
-        match convert.await.unwrap() {
-            Ok(_) => (),
-            Err(err) => {
-                // not working in spawn_blocking?
-                unpack_handle.abort();
-                return Err(err);
+        let unpacked = tokio::spawn(async move {
+            let converted = convert.await.unwrap();
+            let decoded = unpack_handle.await.unwrap();
+            // a pack over the limit is cut short, which is why it fails to decode
+            converted?;
+            decoded
+                .map(|_| ())
+                .map_err(|e| ProtocolError::InvalidInput(e.to_string()))
+        });
+        (receiver, unpacked)
+    }
+
+    /// Split the history behind `want` into the commits the client is missing and the
+    /// commits it already has on the edge of that history, like `git rev-list want --not have`.
+    ///
+    /// Commits are visited newest first, a commit reachable from any `have` marks its parents
+    /// as had too, and the walk stops once only had commits are pending. Unknown `have`
+    /// hashes are ignored.
+    ///
+    /// # Returns
+    /// * `(missing, boundary)` - the commits to send and the had commits whose trees the
+    ///   client is known to have, including the known `have` commits themselves.
+    async fn negotiate_commits(
+        &self,
+        want: &[String],
+        have: &[String],
+    ) -> Result<(Vec<Commit>, Vec<Commit>), GitError> {
+        let load = |hashes: Vec<String>| async move {
+            self.get_commits_by_hashes(hashes)
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))
+        };
+        let mut loaded: HashMap<SHA1, Commit> = HashMap::new();
+        let mut had: HashSet<SHA1> = HashSet::new();
+        let mut queue = BinaryHeap::new();
+
+        let have_commits = load(have.to_vec()).await?;
+        for c in &have_commits {
+            had.insert(c.id);
+        }
+        for c in have_commits
+            .iter()
+            .cloned()
+            .chain(load(want.to_vec()).await?)
+        {
+            if !loaded.contains_key(&c.id) {
+                queue.push((c.committer.timestamp, c.id));
+                loaded.insert(c.id, c);
             }
         }
-        Ok(receiver)
+
+        let mut missing = vec![];
+        let mut edge = HashSet::new();
+        while queue.iter().any(|(_, id)| !had.contains(id)) {
+            let (_, id) = queue.pop().unwrap();
+            let commit = loaded.get(&id).unwrap().clone();
+            let is_had = had.contains(&id);
+            for p in &commit.parent_commit_ids {
+                if is_had {
+                    had.insert(*p);
+                } else {
+                    edge.insert(*p);
+                }
+            }
+            let parents: Vec<String> = commit
+                .parent_commit_ids
+                .iter()
+                .filter(|p| !loaded.contains_key(p))
+                .map(|p| p.to_string())
+                .collect();
+            if !parents.is_empty() {
+                for c in load(parents).await? {
+                    queue.push((c.committer.timestamp, c.id));
+                    loaded.insert(c.id, c);
+                }
+            }
+            if !is_had {
+                missing.push(commit);
+            }
+        }
+
+        let mut boundary = have_commits;
+        for id in edge {
+            if had.contains(&id) && !boundary.iter().any(|c| c.id == id) {
+                if let Some(c) = loaded.get(&id) {
+                    boundary.push(c.clone());
+                }
+            }
+        }
+        Ok((missing, boundary))
     }
 
     async fn traverse_for_count(
@@ -123,22 +338,62 @@ pub trait PackHandler: Send + Sync {
         counted_obj: &mut HashSet<String>,
         obj_num: &AtomicUsize,
     ) {
+        self.traverse_for_count_at(tree, 0, exist_objs, counted_obj, obj_num)
+            .await;
+    }
+
+    /// Count the objects `traverse` sends for `tree`, found `depth` levels below the root tree.
+    async fn traverse_for_count_at(
+        &self,
+        tree: Tree,
+        depth: usize,
+        exist_objs: &HashSet<String>,
+        counted_obj: &mut HashSet<String>,
+        obj_num: &AtomicUsize,
+    ) {
+        let filter = self.object_filter();
+        if filter.is_some_and(|f| !f.allows(depth, true)) {
+            return;
+        }
         let mut search_tree_ids = vec![];
         let mut search_blob_ids = vec![];
         for item in &tree.tree_items {
             let hash = item.id.to_string();
+            if item.mode == TreeItemMode::Commit {
+                // gitlink objects live in another repository and are never packed
+                continue;
+            }
+            let is_tree = item.mode == TreeItemMode::Tree;
+            if filter.is_some_and(|f| !f.allows(depth + 1, is_tree)) {
+                continue;
+            }
             if !exist_objs.contains(&hash) && counted_obj.insert(hash.clone()) {
-                if item.mode == TreeItemMode::Tree {
+                if is_tree {
                     search_tree_ids.push(hash.clone())
                 } else {
                     search_blob_ids.push(hash.clone());
                 }
             }
         }
-        obj_num.fetch_add(search_blob_ids.len(), Ordering::SeqCst);
+        match filter {
+            // the size is only known once the blob is loaded
+            Some(f @ ObjectFilter::BlobLimit(_)) => {
+                for chunk in search_blob_ids.chunks(BLOB_BATCH_SIZE) {
+                    let blobs = self.get_blobs_by_hashes(chunk.to_vec()).await.unwrap();
+                    let allowed = blobs
+                        .into_iter()
+                        .filter(|b| f.allows_size(b.data.as_ref().map_or(0, Vec::len)))
+                        .count();
+                    obj_num.fetch_add(allowed, Ordering::SeqCst);
+                }
+            }
+            _ => {
+                obj_num.fetch_add(search_blob_ids.len(), Ordering::SeqCst);
+            }
+        }
         let trees = self.get_trees_by_hashes(search_tree_ids).await.unwrap();
         for t in trees {
-            self.traverse_for_count(t, exist_objs, counted_obj, obj_num)
+            self.traverse_for_count_at(t, depth + 1, exist_objs, counted_obj, obj_num)
                 .await;
         }
         obj_num.fetch_add(1, Ordering::SeqCst);
@@ -168,13 +423,36 @@ pub trait PackHandler: Send + Sync {
         exist_objs: &mut HashSet<String>,
         sender: Option<&tokio::sync::mpsc::Sender<Entry>>,
     ) {
+        self.traverse_at(tree, 0, exist_objs, sender).await;
+    }
+
+    /// `traverse` for a tree found `depth` levels below the root tree. The object filter
+    /// only applies when sending, objects the client has are always marked.
+    async fn traverse_at(
+        &self,
+        tree: Tree,
+        depth: usize,
+        exist_objs: &mut HashSet<String>,
+        sender: Option<&tokio::sync::mpsc::Sender<Entry>>,
+    ) {
+        let filter = sender.and(self.object_filter());
+        if filter.is_some_and(|f| !f.allows(depth, true)) {
+            return;
+        }
         let mut search_tree_ids = vec![];
         let mut search_blob_ids = vec![];
 
         for item in &tree.tree_items {
+            if item.mode == TreeItemMode::Commit {
+                continue;
+            }
+            let is_tree = item.mode == TreeItemMode::Tree;
+            if filter.is_some_and(|f| !f.allows(depth + 1, is_tree)) {
+                continue;
+            }
             let hash = item.id.to_string();
             if exist_objs.insert(hash.clone()) {
-                if item.mode == TreeItemMode::Tree {
+                if is_tree {
                     search_tree_ids.push(hash);
                 } else {
                     search_blob_ids.push(hash);
@@ -183,16 +461,21 @@ pub trait PackHandler: Send + Sync {
         }
 
         if let Some(sender) = sender {
-            let blobs = self.get_blobs_by_hashes(search_blob_ids).await.unwrap();
-            for b in blobs {
-                let blob: Blob = b.into();
-                sender.send(blob.into()).await.unwrap();
+            for chunk in search_blob_ids.chunks(BLOB_BATCH_SIZE) {
+                let blobs = self.get_blobs_by_hashes(chunk.to_vec()).await.unwrap();
+                for b in blobs {
+                    let blob: Blob = b.into();
+                    if filter.is_some_and(|f| !f.allows_size(blob.data.len())) {
+                        continue;
+                    }
+                    sender.send(blob.into()).await.unwrap();
+                }
             }
         }
 
         let trees = self.get_trees_by_hashes(search_tree_ids).await.unwrap();
         for t in trees {
-            self.traverse(t, exist_objs, sender).await;
+            self.traverse_at(t, depth + 1, exist_objs, sender).await;
         }
 
         if let Some(sender) = sender {
@@ -1,60 +1,109 @@
 use std::{
     collections::{HashMap, HashSet},
     path::{Component, PathBuf},
-    str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
         mpsc::Receiver,
+        Arc,
     },
     vec,
 };
 
 use async_trait::async_trait;
 use futures::future::join_all;
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, task::JoinHandle};
 use tokio_stream::wrappers::ReceiverStream;
 
-use callisto::{db_enums::ConvType, raw_blob};
+use callisto::{
+    db_enums::{ConvType, NotificationKind},
+    raw_blob,
+};
 use common::{
+    config::QuotaRule,
     errors::MegaError,
-    utils::{self, MEGA_BRANCH_NAME},
+    utils::{self, MEGA_BRANCH_NAME, ZERO_ID},
 };
 use jupiter::{context::Context, storage::mr_storage::MrStorage};
-use mercury::internal::{object::ObjectTrait, pack::encode::PackEncoder};
+use mercury::internal::{
+    object::{fsck, ObjectTrait},
+    pack::{encode::PackEncoder, ExternalBase},
+};
 use mercury::{
     errors::GitError,
     hash::SHA1,
     internal::{
-        object::{commit::Commit, tree::Tree, types::ObjectType},
+        object::{
+            commit::Commit,
+            tree::{Tree, TreeItemMode},
+            types::ObjectType,
+        },
         pack::entry::Entry,
     },
 };
+use serde_json::json;
+use taurus::event::live_update::{LiveUpdateEvent, LiveUpdateKind};
+use taurus::event::notification::NotificationEvent;
+use taurus::event::push::PushEvent;
 
 use crate::{
-    pack::PackHandler,
+    api_service::tree_ops::{apply_changes, entry_at_path, load_tree, TreeChange},
+    pack::{
+        cache::PackCache, finish_unpack, hooks, stored_base, PackHandler, Unpacked,
+        MAX_PENDING_BATCHES,
+    },
     protocol::{
-        import_refs::{RefCommand, Refs},
+        import_refs::{CommandType, RefCommand, Refs},
         mr::MergeRequest,
+        signature, ObjectFilter, PushOptions,
     },
 };
 
+/// Upper bound of commits visited when walking the history of a pushed update.
+const MAX_ANCESTRY_SCAN: usize = 10000;
+
+#[derive(Clone)]
 pub struct MonoRepo {
     pub context: Context,
     pub path: PathBuf,
     pub from_hash: String,
     pub to_hash: String,
+    /// Directories below `path` left out of what is served, a user who can't read them
+    /// fetches a filtered view of the main head instead of the real history.
+    pub hidden: Vec<PathBuf>,
+    /// Trees of the filtered view by hash, they're only kept in memory while it's served.
+    pub view_trees: Arc<HashMap<String, Tree>>,
+    pub filter: Option<ObjectFilter>,
+    pub push_options: PushOptions,
+    /// user name of who pushes, opens the MR of the push
+    pub pusher: Option<String>,
 }
 
 #[async_trait]
 impl PackHandler for MonoRepo {
     async fn head_hash(&self) -> (String, Vec<Refs>) {
+        if !self.hidden.is_empty() {
+            return match self.view_commit().await {
+                Ok(Some((view, _))) => self.find_head_hash(vec![Refs {
+                    ref_name: MEGA_BRANCH_NAME.to_string(),
+                    ref_hash: view.id.to_string(),
+                    default_branch: true,
+                    ..Default::default()
+                }]),
+                _ => self.find_head_hash(vec![]),
+            };
+        }
         let storage = self.context.services.mono_storage.clone();
 
-        let result = storage.get_refs(self.path.to_str().unwrap()).await.unwrap();
-        let refs = if !result.is_empty() {
-            let refs: Vec<Refs> = result.into_iter().map(|x| x.into()).collect();
-            refs
-        } else {
+        let mut refs: Vec<Refs> = storage
+            .get_refs(self.path.to_str().unwrap())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.into())
+            .collect();
+        // the main ref of a directory is derived from the monorepo on first use, named
+        // branches may exist without it after a merge dropped it
+        if !refs.iter().any(|x| x.default_branch) {
             let target_path = self.path.clone();
             let refs = storage.get_ref("/").await.unwrap().unwrap();
             let tree_hash = refs.ref_tree_hash.clone();
@@ -79,7 +128,7 @@ impl PackHandler for MonoRepo {
                     let sha1 = tree
                         .tree_items
                         .iter()
-                        .find(|x| x.name == path_name)
+                        .find(|x| x.name == path_name && x.mode == TreeItemMode::Tree)
                         .map(|x| x.id);
                     if let Some(sha1) = sha1 {
                         tree = storage
@@ -89,7 +138,7 @@ impl PackHandler for MonoRepo {
                             .clone()
                             .into();
                     } else {
-                        return self.find_head_hash(vec![]);
+                        return self.find_head_hash(refs);
                     }
                 }
             }
@@ -112,57 +161,48 @@ impl PackHandler for MonoRepo {
                 .unwrap();
             storage.save_mega_commits(vec![c.clone()]).await.unwrap();
 
-            vec![Refs {
+            refs.push(Refs {
                 ref_name: MEGA_BRANCH_NAME.to_string(),
                 ref_hash: c.id.to_string(),
                 default_branch: true,
                 ..Default::default()
-            }]
-        };
+            });
+        }
         self.find_head_hash(refs)
     }
 
-    async fn handle_receiver(&self, receiver: Receiver<Entry>) -> Result<Option<Commit>, GitError> {
-        let storage = self.context.services.mono_storage.clone();
-        let mut entry_list = Vec::new();
-        let mut join_tasks = vec![];
-        let mut current_commit_id = String::new();
-        let mut current_commit = None;
-        for entry in receiver {
-            if current_commit.is_none() {
-                if entry.obj_type == ObjectType::Commit {
-                    current_commit_id = entry.hash.to_string();
-                    let commit = Commit::from_bytes(&entry.data, entry.hash).unwrap();
-                    current_commit = Some(commit);
-                }
-            } else {
-                if entry.obj_type == ObjectType::Commit {
-                    return Err(GitError::CustomError(
-                        "only single commit support in each push".to_string(),
-                    ));
-                }
-                if entry_list.len() >= 1000 {
-                    let stg_clone = storage.clone();
-                    let commit_id = current_commit_id.clone();
-                    let handle = tokio::spawn(async move {
-                        stg_clone.save_entry(&commit_id, entry_list).await.unwrap();
-                    });
-                    join_tasks.push(handle);
-                    entry_list = vec![];
-                }
-            }
-            entry_list.push(entry);
-        }
-        join_all(join_tasks).await;
-        storage
-            .save_entry(&current_commit_id, entry_list)
+    /// Save the pushed objects, a push may carry a series of commits which all become
+    /// part of the merge request. Returns the pushed head commit.
+    async fn handle_receiver(
+        &self,
+        receiver: Receiver<Entry>,
+        unpacked: Unpacked,
+    ) -> Result<Option<Commit>, GitError> {
+        // the objects of a push are stored together or not at all
+        self.context
+            .transaction(|context| async move {
+                let repo = MonoRepo {
+                    context,
+                    ..self.clone()
+                };
+                repo.save_entries(receiver, unpacked).await
+            })
             .await
-            .unwrap();
-        Ok(current_commit)
+    }
+
+    fn external_base(&self) -> Option<ExternalBase> {
+        Some(stored_base(self.clone()))
+    }
+
+    fn object_filter(&self) -> Option<ObjectFilter> {
+        self.filter
     }
 
     // monorepo full pack should follow the shallow clone command 'git clone --depth=1'
     async fn full_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        if !self.hidden.is_empty() {
+            return self.view_pack(want).await;
+        }
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.mono_storage.clone();
         let obj_num = AtomicUsize::new(0);
@@ -173,6 +213,15 @@ impl PackHandler for MonoRepo {
             .await
             .unwrap()
             .unwrap();
+        // only the pack of the head alone is cached, that's what a clone asks for
+        let cache = PackCache::new(pack_config)
+            .filter(|_| self.filter.is_none() && want == [refs.ref_commit_hash.clone()]);
+        let cache_key = (self.path.to_str().unwrap(), refs.ref_commit_hash.clone());
+        if let Some(cache) = &cache {
+            if let Some(pack) = cache.get(cache_key.0, &cache_key.1).await {
+                return Ok(pack);
+            }
+        }
         let commit: Commit = storage
             .get_commit_by_hash(&refs.ref_commit_hash)
             .await
@@ -223,16 +272,24 @@ impl PackHandler for MonoRepo {
             entry_tx.send(commit.into()).await.unwrap();
         }
 
-        let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
+        let encoder = PackEncoder::new(obj_num.into_inner(), pack_config.delta_window, stream_tx)
+            .with_max_depth(pack_config.delta_depth);
         encoder.encode_async(entry_rx).await.unwrap();
-        let mut send_exist = HashSet::new();
-        for tree in trees {
-            self.traverse(tree, &mut send_exist, Some(&entry_tx))
-                .await;
+        // objects are produced while the caller consumes the stream, the bounded
+        // channels keep only a few of them in memory at any time
+        let repo = self.clone();
+        tokio::spawn(async move {
+            let mut send_exist = HashSet::new();
+            for tree in trees {
+                repo.traverse(tree, &mut send_exist, Some(&entry_tx)).await;
+            }
+            entry_tx.send(commit.into()).await.unwrap();
+        });
+        let pack = ReceiverStream::new(stream_rx);
+        match cache {
+            Some(cache) => Ok(cache.store(cache_key.0, &cache_key.1, pack)),
+            None => Ok(pack),
         }
-        entry_tx.send(commit.into()).await.unwrap();
-        drop(entry_tx);
-        Ok(ReceiverStream::new(stream_rx))
     }
 
     async fn incremental_pack(
@@ -240,67 +297,52 @@ impl PackHandler for MonoRepo {
         want: Vec<String>,
         have: Vec<String>,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
-        let mut want_clone = want.clone();
+        // the view has no history, what the client has doesn't matter
+        if !self.hidden.is_empty() {
+            return self.view_pack(want).await;
+        }
         let pack_config = &self.context.config.pack;
-        let storage = self.context.services.mono_storage.clone();
         let obj_num = AtomicUsize::new(0);
 
-        let mut exist_objs = HashSet::new();
-
-        let mut want_commits: Vec<Commit> = storage
-            .get_commits_by_hashes(&want_clone)
-            .await
-            .unwrap()
-            .into_iter()
-            .map(|x| x.into())
-            .collect();
-        let mut traversal_list: Vec<Commit> = want_commits.clone();
-
-        // traverse commit's all parents to find the commit that client does not have
-        while let Some(temp) = traversal_list.pop() {
-            for p_commit_id in temp.parent_commit_ids {
-                let p_commit_id = p_commit_id.to_string();
-
-                if !have.contains(&p_commit_id) && !want_clone.contains(&p_commit_id) {
-                    let parent: Commit = storage
-                        .get_commit_by_hash(&p_commit_id)
-                        .await
-                        .unwrap()
-                        .unwrap()
-                        .into();
-                    want_commits.push(parent.clone());
-                    want_clone.push(p_commit_id);
-                    traversal_list.push(parent);
-                }
-            }
-        }
+        let (want_commits, have_commits) = self.negotiate_commits(&want, &have).await?;
 
         let want_tree_ids = want_commits
             .iter()
             .map(|c| c.tree_id.to_string())
             .collect();
-        let want_trees: HashMap<SHA1, Tree> = storage
+        let want_trees: HashMap<SHA1, Tree> = self
             .get_trees_by_hashes(want_tree_ids)
             .await
             .unwrap()
             .into_iter()
-            .map(|m| (SHA1::from_str(&m.tree_id).unwrap(), m.into()))
+            .map(|t| (t.id, t))
             .collect();
 
         obj_num.fetch_add(want_commits.len(), Ordering::SeqCst);
 
-        let have_commits = storage.get_commits_by_hashes(&have).await.unwrap();
-        let have_trees = storage
-            .get_trees_by_hashes(have_commits.iter().map(|x| x.tree.clone()).collect())
+        // everything reachable from the trees the client already has is left out
+        let mut exist_objs = HashSet::new();
+        let have_trees = self
+            .get_trees_by_hashes(
+                have_commits
+                    .iter()
+                    .map(|c| c.tree_id.to_string())
+                    .collect(),
+            )
             .await
             .unwrap();
         for have_tree in have_trees {
-            self.traverse(have_tree.into(), &mut exist_objs, None).await;
+            exist_objs.insert(have_tree.id.to_string());
+            self.traverse(have_tree, &mut exist_objs, None).await;
         }
 
         let mut counted_obj = HashSet::new();
         // traverse for get obj nums
-        for c in want_commits.clone() {
+        for c in &want_commits {
+            let tree_id = c.tree_id.to_string();
+            if exist_objs.contains(&tree_id) || !counted_obj.insert(tree_id) {
+                continue;
+            }
             self.traverse_for_count(
                 want_trees.get(&c.tree_id).unwrap().clone(),
                 &exist_objs,
@@ -311,36 +353,57 @@ impl PackHandler for MonoRepo {
         }
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
-        let encoder = PackEncoder::new(obj_num.into_inner(), 0, stream_tx);
+        let encoder = PackEncoder::new(obj_num.into_inner(), pack_config.delta_window, stream_tx)
+            .with_max_depth(pack_config.delta_depth);
         encoder.encode_async(entry_rx).await.unwrap();
 
-        for c in want_commits {
-            self.traverse(
-                want_trees.get(&c.tree_id).unwrap().clone(),
-                &mut exist_objs,
-                Some(&entry_tx),
-            )
-            .await;
-            entry_tx.send(c.into()).await.unwrap();
-        }
-        drop(entry_tx);
+        let repo = self.clone();
+        tokio::spawn(async move {
+            for c in want_commits {
+                if exist_objs.insert(c.tree_id.to_string()) {
+                    repo.traverse(
+                        want_trees.get(&c.tree_id).unwrap().clone(),
+                        &mut exist_objs,
+                        Some(&entry_tx),
+                    )
+                    .await;
+                }
+                entry_tx.send(c.into()).await.unwrap();
+            }
+        });
 
         Ok(ReceiverStream::new(stream_rx))
     }
 
-    async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError> {
         Ok(self
             .context
             .services
             .mono_storage
-            .get_trees_by_hashes(hashes)
-            .await
-            .unwrap()
+            .get_commits_by_hashes(&hashes)
+            .await?
             .into_iter()
             .map(|x| x.into())
             .collect())
     }
 
+    async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
+        let (view, stored): (Vec<String>, Vec<String>) = hashes
+            .into_iter()
+            .partition(|x| self.view_trees.contains_key(x));
+        let mut trees: Vec<Tree> = view.iter().map(|x| self.view_trees[x].clone()).collect();
+        trees.extend(
+            self.context
+                .services
+                .mono_storage
+                .get_trees_by_hashes(stored)
+                .await?
+                .into_iter()
+                .map(Tree::from),
+        );
+        Ok(trees)
+    }
+
     async fn get_blobs_by_hashes(
         &self,
         hashes: Vec<String>,
@@ -368,15 +431,18 @@ impl PackHandler for MonoRepo {
                     )));
                 }
                 let link: String = utils::generate_link();
-                let mr = MergeRequest {
+                let mut mr = MergeRequest {
                     path: path_str.to_owned(),
                     from_hash: self.from_hash.clone(),
                     to_hash: self.to_hash.clone(),
                     link: link.clone(),
                     title: title.to_string(),
+                    author: self.pusher.clone(),
                     ..Default::default()
                 };
+                self.apply_push_options(&mut mr, &storage).await?;
                 storage.save_mr(mr.clone().into()).await.unwrap();
+                self.request_reviews(&mr, mr.reviewers.clone());
                 Ok(link)
             }
         }
@@ -388,13 +454,17 @@ impl PackHandler for MonoRepo {
         commit: Option<Commit>,
         refs: &RefCommand,
     ) -> Result<(), GitError> {
-        let ref_name = utils::mr_ref_name(&mr_link.unwrap());
+        let Some(mr_link) = mr_link else {
+            return self.update_branch(refs).await;
+        };
+        let ref_name = utils::mr_ref_name(&mr_link);
 
         let storage = self.context.services.mono_storage.clone();
         if let Some(mut mr_ref) = storage.get_mr_ref(&ref_name).await.unwrap() {
-            mr_ref.ref_commit_hash = refs.new_id.clone();
+            let old_commit_hash =
+                std::mem::replace(&mut mr_ref.ref_commit_hash, refs.new_id.clone());
             mr_ref.ref_tree_hash = commit.unwrap().tree_id.to_string();
-            storage.update_ref(mr_ref).await.unwrap();
+            storage.update_ref(mr_ref, &old_commit_hash).await?;
         } else {
             storage
                 .save_ref(
@@ -422,15 +492,430 @@ impl PackHandler for MonoRepo {
     async fn check_default_branch(&self) -> bool {
         true
     }
+
+    fn push_opens_mr(&self, ref_name: &str) -> bool {
+        ref_name == MEGA_BRANCH_NAME
+    }
+
+    /// Enforce the protection rule of the pushed branch, then let the pre-receive hooks of
+    /// the directory refuse the update.
+    async fn check_ref_update(&self, refs: &RefCommand) -> Result<(), GitError> {
+        self.check_protection(refs).await?;
+        let push = self.push_event(refs).await?;
+        let commits = self
+            .get_commits_by_hashes(push.commits.clone())
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        hooks::pre_receive(&self.context.config.monorepo, &push, &commits)
+            .await
+            .map_err(|e| GitError::CustomError(format!("pre-receive hook declined: {}", e)))
+    }
+
+    /// Queue the update for the post-receive hooks of the directory and for the pages
+    /// watching it, and verify the signatures of the pushed commits in the background.
+    async fn post_receive(&self, refs: &RefCommand) {
+        let push = match self.push_event(refs).await {
+            Ok(push) => push,
+            Err(e) => {
+                tracing::error!("failed to list pushed commits of {}: {}", refs.ref_name, e);
+                return;
+            }
+        };
+        let repo = self.clone();
+        let hashes = push.commits.clone();
+        tokio::spawn(async move {
+            if let Ok(commits) = repo.get_commits_by_hashes(hashes).await {
+                signature::verify_commits(&repo.context, &commits).await;
+            }
+        });
+        LiveUpdateEvent::notify(
+            LiveUpdateKind::RefUpdate,
+            &push.path,
+            None,
+            json!({ "reason": "push", "ref": push.ref_name, "commit": push.new_id }),
+        );
+        PushEvent::notify(push).await;
+    }
 }
 
 impl MonoRepo {
+    /// Store the objects of a push, the head commit pushed is returned. Fails when the
+    /// pack they come from fails to decode.
+    async fn save_entries(
+        &self,
+        receiver: Receiver<Entry>,
+        unpacked: Unpacked,
+    ) -> Result<Option<Commit>, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let mut entry_list = Vec::new();
+        let mut join_tasks: Vec<JoinHandle<Result<(), MegaError>>> = vec![];
+        let mut commits = Vec::new();
+        let usage = self.quota_usage().await?;
+        let mut incoming = 0;
+        let mut refused = Ok(());
+        for entry in receiver {
+            // nothing more is stored once an object is refused
+            refused = self.check_pushed(&entry);
+            if refused.is_ok() && entry.obj_type == ObjectType::Blob {
+                incoming += entry.data.len() as u64;
+                refused = check_quota(usage.as_ref(), incoming);
+            }
+            if refused.is_err() {
+                break;
+            }
+            if entry.obj_type == ObjectType::Commit {
+                commits.push(Commit::from_bytes(&entry.data, entry.hash).unwrap());
+            }
+            if entry_list.len() >= 1000 {
+                if join_tasks.len() >= MAX_PENDING_BATCHES {
+                    join_tasks
+                        .remove(0)
+                        .await
+                        .map_err(|e| GitError::CustomError(e.to_string()))??;
+                }
+                let stg_clone = storage.clone();
+                let commit_id = self.to_hash.clone();
+                let handle =
+                    tokio::spawn(async move { stg_clone.save_entry(&commit_id, entry_list).await });
+                join_tasks.push(handle);
+                entry_list = vec![];
+            }
+            entry_list.push(entry);
+        }
+        // every batch has to be stored before the transaction ends, also when it's rolled
+        // back for a refused object
+        for res in join_all(join_tasks).await {
+            res.map_err(|e| GitError::CustomError(e.to_string()))??;
+        }
+        refused?;
+        finish_unpack(unpacked).await?;
+        storage.save_entry(&self.to_hash, entry_list).await?;
+
+        if commits.is_empty() {
+            return Ok(None);
+        }
+        let head = commits
+            .iter()
+            .position(|x| x.id.to_string() == self.to_hash)
+            .unwrap_or(0);
+        Ok(Some(commits.swap_remove(head)))
+    }
+
+    /// Refuse a pushed object before it's stored, a malformed one or a blob over the size
+    /// limit.
+    fn check_pushed(&self, entry: &Entry) -> Result<(), GitError> {
+        fsck::check_entry(entry)?;
+        let max_blob_size = self.context.config.lfs.max_blob_size;
+        // large files belong in LFS, keep them out of raw_blob
+        if entry.obj_type == ObjectType::Blob
+            && max_blob_size > 0
+            && entry.data.len() > max_blob_size
+        {
+            return Err(GitError::CustomError(format!(
+                "blob {} exceeds the {} bytes limit, track large files with git lfs",
+                entry.hash, max_blob_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// The quota of the directory pushed to and the bytes already used below it.
+    async fn quota_usage(&self) -> Result<Option<(QuotaRule, u64)>, GitError> {
+        let path = self.path.to_str().unwrap();
+        let Some(quota) = self.context.config.monorepo.quota(path) else {
+            return Ok(None);
+        };
+        let used = self
+            .context
+            .stats_stg()
+            .get_stats(&quota.path)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .map_or(0, |x| x.size as u64);
+        Ok(Some((quota.clone(), used)))
+    }
+
+    /// A branch requiring merge requests can't be pushed to directly, and a branch
+    /// forbidding force updates only moves forward.
+    async fn check_protection(&self, refs: &RefCommand) -> Result<(), GitError> {
+        let Some(branch) = refs.ref_name.strip_prefix("refs/heads/") else {
+            return Ok(());
+        };
+        let path = self.path.to_str().unwrap();
+        let rule = self
+            .context
+            .protection_stg()
+            .find_rule(path, branch)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        let Some(rule) = rule else {
+            return Ok(());
+        };
+        if rule.require_mr && !self.push_opens_mr(&refs.ref_name) {
+            return Err(GitError::CustomError(format!(
+                "{} of {} is protected, changes go through merge requests",
+                branch, path
+            )));
+        }
+        if rule.forbid_force_update {
+            if refs.command_type == CommandType::Delete {
+                return Err(GitError::CustomError(format!(
+                    "{} of {} is protected and can't be deleted",
+                    branch, path
+                )));
+            }
+            if refs.old_id != ZERO_ID && !self.is_ancestor(&refs.old_id, &refs.new_id).await? {
+                return Err(GitError::CustomError(format!(
+                    "{} of {} is protected, force updates are not allowed",
+                    branch, path
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The pushed update with the commits it adds: those reachable from the new id but
+    /// not from the old one or the head of the directory, newest first.
+    async fn push_event(&self, refs: &RefCommand) -> Result<PushEvent, GitError> {
+        let storage = self.context.services.mono_storage.clone();
+        let (head, _) = self.head_hash().await;
+        let mut visited = HashSet::from([refs.old_id.clone(), head, ZERO_ID.to_owned()]);
+        let mut generation = vec![refs.new_id.clone()];
+        let mut commits = vec![];
+        // one query for each generation of ancestors
+        while commits.len() < MAX_ANCESTRY_SCAN {
+            let hashes: Vec<String> = generation
+                .drain(..)
+                .filter(|x| visited.insert(x.clone()))
+                .collect();
+            if hashes.is_empty() {
+                break;
+            }
+            let mut found: HashMap<String, Commit> = storage
+                .get_commits_by_hashes(&hashes)
+                .await
+                .map_err(|e| GitError::CustomError(e.to_string()))?
+                .into_iter()
+                .map(|x| (x.commit_id.clone(), x.into()))
+                .collect();
+            for hash in hashes {
+                if commits.len() >= MAX_ANCESTRY_SCAN {
+                    break;
+                }
+                if let Some(commit) = found.remove(&hash) {
+                    generation.extend(commit.parent_commit_ids.iter().map(|x| x.to_string()));
+                    commits.push(hash);
+                }
+            }
+        }
+        Ok(PushEvent {
+            path: self.path.to_str().unwrap().to_owned(),
+            ref_name: refs.ref_name.clone(),
+            old_id: refs.old_id.clone(),
+            new_id: refs.new_id.clone(),
+            commits,
+        })
+    }
+    /// The main head with the hidden directories removed, as a commit without parents,
+    /// along with the trees it doesn't share with the head. It only depends on the head,
+    /// so fetching it twice gives the same commit. Nothing of it is stored.
+    async fn view_commit(&self) -> Result<Option<(Commit, Vec<Tree>)>, GitError> {
+        let repo = MonoRepo {
+            hidden: vec![],
+            ..self.clone()
+        };
+        let (head, _) = repo.head_hash().await;
+        if head == ZERO_ID {
+            return Ok(None);
+        }
+        let storage = self.context.services.mono_storage.clone();
+        let commit: Commit = storage
+            .get_commit_by_hash(&head)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or(GitError::ObjectNotFound(head))?
+            .into();
+
+        let mut changes = Vec::new();
+        for dir in &self.hidden {
+            let Ok(rel) = dir.strip_prefix(&self.path) else {
+                continue;
+            };
+            if let Some(entry) = entry_at_path(&storage, &commit.tree_id, rel).await? {
+                changes.push(TreeChange {
+                    path: rel.to_path_buf(),
+                    old: Some(entry),
+                    new: None,
+                });
+            }
+        }
+        let root = load_tree(&storage, &commit.tree_id).await?;
+        let res = apply_changes(&storage, root, &changes).await?;
+        let view = Commit::new(
+            commit.author,
+            commit.committer,
+            res.root.id,
+            vec![],
+            &commit.message,
+        );
+        Ok(Some((view, res.new_trees)))
+    }
+
+    /// Pack of the filtered view, the only commit that can be fetched while some
+    /// directories are hidden.
+    async fn view_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        let pack_config = &self.context.config.pack;
+        let (view, trees) = self
+            .view_commit()
+            .await?
+            .ok_or(GitError::CustomError("nothing to fetch".to_string()))?;
+        if let Some(x) = want.iter().find(|x| **x != view.id.to_string()) {
+            return Err(GitError::CustomError(format!("{} can't be fetched", x)));
+        }
+        let repo = MonoRepo {
+            view_trees: Arc::new(trees.into_iter().map(|x| (x.id.to_string(), x)).collect()),
+            ..self.clone()
+        };
+        let tree = repo
+            .get_trees_by_hashes(vec![view.tree_id.to_string()])
+            .await?
+            .pop()
+            .ok_or(GitError::ObjectNotFound(view.tree_id.to_string()))?;
+
+        let obj_num = AtomicUsize::new(0);
+        let mut counted_obj = HashSet::new();
+        repo.traverse_for_count(tree.clone(), &HashSet::new(), &mut counted_obj, &obj_num)
+            .await;
+        obj_num.fetch_add(1, Ordering::SeqCst);
+
+        let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
+        let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
+        let encoder = PackEncoder::new(obj_num.into_inner(), pack_config.delta_window, stream_tx)
+            .with_max_depth(pack_config.delta_depth);
+        encoder.encode_async(entry_rx).await.unwrap();
+        tokio::spawn(async move {
+            repo.traverse(tree, &mut HashSet::new(), Some(&entry_tx))
+                .await;
+            entry_tx.send(view.into()).await.unwrap();
+        });
+        Ok(ReceiverStream::new(stream_rx))
+    }
+
+    /// Whether `ancestor` can be reached from `head` through parents.
+    pub async fn is_ancestor(&self, ancestor: &str, head: &str) -> Result<bool, GitError> {
+        self.context
+            .services
+            .mono_storage
+            .is_ancestor(ancestor, head)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))
+    }
+
+    /// Create, move or delete a named branch of the directory, an open MR from the branch
+    /// follows its new head. `main` only changes through merge requests.
+    async fn update_branch(&self, refs: &RefCommand) -> Result<(), GitError> {
+        if refs.ref_name == MEGA_BRANCH_NAME {
+            return Err(GitError::CustomError(
+                "the main branch only changes through merge requests".to_owned(),
+            ));
+        }
+        let Some(branch) = refs.ref_name.strip_prefix("refs/heads/") else {
+            return Err(GitError::CustomError(format!(
+                "{} can't be pushed, tags are created through the API",
+                refs.ref_name
+            )));
+        };
+        let path = self.path.to_str().unwrap();
+        let storage = self.context.services.mono_storage.clone();
+        let current = storage
+            .get_ref_by_name(path, &refs.ref_name)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        let current_hash = current
+            .as_ref()
+            .map(|x| x.ref_commit_hash.as_str())
+            .unwrap_or(ZERO_ID);
+        if current_hash != refs.old_id {
+            return Err(GitError::CustomError(format!(
+                "{} has moved, fetch and try again",
+                branch
+            )));
+        }
+        if refs.command_type == CommandType::Delete {
+            if let Some(current) = current {
+                storage
+                    .remove_ref(current)
+                    .await
+                    .map_err(|e| GitError::CustomError(e.to_string()))?;
+            }
+            return Ok(());
+        }
+
+        let commit: Commit = storage
+            .get_commit_by_hash(&refs.new_id)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or(GitError::ObjectNotFound(refs.new_id.clone()))?
+            .into();
+        let res = match current {
+            Some(mut current) => {
+                current.ref_commit_hash = refs.new_id.clone();
+                current.ref_tree_hash = commit.tree_id.to_string();
+                storage.update_ref(current, &refs.old_id).await
+            }
+            None => {
+                storage
+                    .save_ref(
+                        path,
+                        Some(refs.ref_name.clone()),
+                        &refs.new_id,
+                        &commit.tree_id.to_string(),
+                    )
+                    .await
+            }
+        };
+        res?;
+
+        let mr_stg = self.context.mr_stg();
+        if let Some(mut mr) = mr_stg.get_open_mr_by_branch(path, branch).await.unwrap() {
+            mr.to_hash = refs.new_id.clone();
+            mr_stg.update_mr(mr).await.unwrap();
+        }
+        Ok(())
+    }
+
     async fn handle_existing_mr(
         &self,
         mr: &mut MergeRequest,
         storage: &MrStorage,
     ) -> Result<String, GitError> {
         if mr.from_hash == self.from_hash {
+            let (reviewers, was_draft) = (mr.reviewers.clone(), mr.draft);
+            self.apply_push_options(mr, storage).await?;
+            if was_draft && !mr.draft {
+                // `-o draft=false` marks the MR ready, all its reviewers are asked then
+                storage
+                    .add_mr_conversation(
+                        &mr.link,
+                        0,
+                        ConvType::Ready,
+                        Some(format!(
+                            "{} marked this ready for review",
+                            self.pusher.as_deref().unwrap_or("anonymous")
+                        )),
+                    )
+                    .await
+                    .unwrap();
+                self.request_reviews(mr, mr.reviewers.clone());
+            } else {
+                let added = mr
+                    .reviewers
+                    .iter()
+                    .filter(|x| !reviewers.contains(x))
+                    .cloned()
+                    .collect();
+                self.request_reviews(mr, added);
+            }
             if mr.to_hash != self.to_hash {
                 let comment = self.comment_for_force_update(&mr.to_hash, &self.to_hash);
                 mr.to_hash = self.to_hash.clone();
@@ -442,22 +927,111 @@ impl MonoRepo {
                 tracing::info!("repeat commit with mr: {}, do nothing", mr.id);
             }
         } else {
+            // the push doesn't build on the base of the MR, the pushed commits go to a
+            // new MR that takes its place
+            let successor = self.open_successor(mr, storage).await?;
             mr.close();
+            storage.update_mr(mr.clone().into()).await.unwrap();
             storage
                 .add_mr_conversation(
                     &mr.link,
                     0,
                     ConvType::Closed,
-                    Some("Mega closed MR due to conflict".to_string()),
+                    Some(format!(
+                        "Mega closed MR due to conflict, superseded by MR {}",
+                        successor
+                    )),
                 )
                 .await
                 .unwrap();
+            NotificationEvent::notify(
+                NotificationKind::MrClosed,
+                "mega",
+                mr.author.iter().cloned().collect(),
+                &format!("/mr/{}", mr.link),
+                &mr.title,
+                Some(format!("Superseded by MR {}", successor)),
+            );
+            return Ok(successor);
         }
 
         storage.update_mr(mr.clone().into()).await.unwrap();
         Ok(mr.link.clone())
     }
 
+    /// Open the MR replacing `mr` with the pushed commits, it keeps the metadata of `mr`
+    /// and the open MRs depending on `mr` depend on it instead.
+    async fn open_successor(
+        &self,
+        mr: &MergeRequest,
+        storage: &MrStorage,
+    ) -> Result<String, GitError> {
+        let mut successor = MergeRequest {
+            link: utils::generate_link(),
+            title: mr.title.clone(),
+            path: mr.path.clone(),
+            from_hash: self.from_hash.clone(),
+            to_hash: self.to_hash.clone(),
+            description: mr.description.clone(),
+            reviewers: mr.reviewers.clone(),
+            draft: mr.draft,
+            author: mr.author.clone().or_else(|| self.pusher.clone()),
+            depends_on: mr.depends_on.clone(),
+            labels: mr.labels.clone(),
+            assignees: mr.assignees.clone(),
+            milestone: mr.milestone.clone(),
+            supersedes: Some(mr.link.clone()),
+            ..Default::default()
+        };
+        self.apply_push_options(&mut successor, storage).await?;
+        storage.save_mr(successor.clone().into()).await.unwrap();
+        for mut dependent in storage.get_open_mrs_depending_on(&mr.link).await.unwrap() {
+            dependent.depends_on = Some(successor.link.clone());
+            storage.update_mr(dependent).await.unwrap();
+        }
+        self.request_reviews(&successor, successor.reviewers.clone());
+        Ok(successor.link)
+    }
+
+    /// Apply the push options with the `team:<name>` reviewers replaced by the team
+    /// members, a dependency on an MR it can't build on refuses the push.
+    async fn apply_push_options(
+        &self,
+        mr: &mut MergeRequest,
+        storage: &MrStorage,
+    ) -> Result<(), GitError> {
+        if let Some(depends_on) = &self.push_options.depends_on {
+            mr.check_dependency(storage, depends_on).await?;
+        }
+        let reviewers = self
+            .context
+            .acl_stg()
+            .expand_teams(&self.push_options.reviewers)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?;
+        PushOptions {
+            reviewers,
+            ..self.push_options.clone()
+        }
+        .apply(mr);
+        Ok(())
+    }
+
+    /// Ask `reviewers` to review `mr`, they're asked once a draft is marked ready.
+    fn request_reviews(&self, mr: &MergeRequest, reviewers: Vec<String>) {
+        if mr.draft {
+            return;
+        }
+        NotificationEvent::notify(
+            NotificationKind::ReviewRequested,
+            self.pusher.as_deref().unwrap_or("anonymous"),
+            reviewers,
+            &format!("/mr/{}", mr.link),
+            &mr.title,
+            None,
+        );
+    }
+
     fn comment_for_force_update(&self, from: &str, to: &str) -> String {
         format!(
             "Mega updated the mr automatic from {} to {}",
@@ -466,3 +1040,169 @@ impl MonoRepo {
         )
     }
 }
+
+/// Refuse a push whose blobs of `incoming` bytes so far would take the directory over its
+/// quota. Every pushed blob counts, also those replacing files or already stored.
+fn check_quota(usage: Option<&(QuotaRule, u64)>, incoming: u64) -> Result<(), GitError> {
+    let Some((quota, used)) = usage else {
+        return Ok(());
+    };
+    if used + incoming > quota.max_size {
+        return Err(GitError::CustomError(format!(
+            "quota of {} exceeded, {} of {} bytes are used and the push adds at least {}",
+            quota.path, used, quota.max_size, incoming
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use common::{config::QuotaRule, utils::ZERO_ID};
+    use jupiter::context::Context;
+    use mercury::{errors::GitError, hash::SHA1, internal::object::types::ObjectType};
+
+    use super::MonoRepo;
+    use crate::pack::PackHandler;
+    use crate::protocol::{PushOptions, ServiceType, SmartProtocol};
+
+    /// A pack of whole blobs and the hashes of the blobs, without its trailing checksum.
+    fn blob_pack(blobs: &[Vec<u8>]) -> (Vec<u8>, Vec<String>) {
+        let mut pack = b"PACK".to_vec();
+        pack.extend(2u32.to_be_bytes());
+        pack.extend((blobs.len() as u32).to_be_bytes());
+        let mut hashes = vec![];
+        for blob in blobs {
+            // type 3 (blob), then the size 4 bits and 7 bits at a time
+            let mut size = blob.len() >> 4;
+            let mut header = vec![0x30 | (blob.len() & 0x0f) as u8];
+            while size > 0 {
+                *header.last_mut().unwrap() |= 0x80;
+                header.push((size & 0x7f) as u8);
+                size >>= 7;
+            }
+            let mut encoder = ZlibEncoder::new(header, Compression::default());
+            encoder.write_all(blob).unwrap();
+            pack.extend(encoder.finish().unwrap());
+            hashes.push(SHA1::from_type_and_data(ObjectType::Blob, blob).to_string());
+        }
+        (pack, hashes)
+    }
+
+    fn test_repo(context: &Context) -> MonoRepo {
+        MonoRepo {
+            context: context.clone(),
+            path: PathBuf::from("/project"),
+            from_hash: ZERO_ID.to_owned(),
+            to_hash: ZERO_ID.to_owned(),
+            hidden: vec![],
+            view_trees: Arc::default(),
+            filter: None,
+            push_options: PushOptions::default(),
+            pusher: None,
+        }
+    }
+
+    /// Unpack and store `pack` the way a push does.
+    async fn receive(repo: &MonoRepo, pack: Vec<u8>) -> Result<(), GitError> {
+        let stream = futures::stream::iter(vec![Ok::<_, axum::Error>(Bytes::from(pack))]);
+        let (receiver, unpacked) = repo
+            .unpack_stream(&repo.context.config.pack, Box::pin(stream))
+            .await;
+        let repo = repo.clone();
+        tokio::task::spawn_blocking(move || {
+            let handle = tokio::runtime::Handle::current();
+            handle.block_on(async { repo.handle_receiver(receiver, unpacked).await })
+        })
+        .await
+        .unwrap()
+        .map(|_| ())
+    }
+
+    /// How many of the blobs `hashes` are stored.
+    async fn stored(context: &Context, hashes: &[String]) -> usize {
+        let blobs = context
+            .services
+            .mono_storage
+            .get_mega_blobs_by_hashes(hashes.to_vec())
+            .await
+            .unwrap();
+        let raw_blobs = context
+            .services
+            .raw_db_storage
+            .get_raw_blobs_by_hashes(hashes.to_vec())
+            .await
+            .unwrap();
+        blobs.len() + raw_blobs.len()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_truncated_pack_stores_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = Context::sqlite(dir.path()).await;
+        let repo = test_repo(&context);
+        // more blobs than a batch, the first batch is stored before the pack breaks off
+        let blobs: Vec<Vec<u8>> = (0..1500).map(|i| format!("{}\n", i).into_bytes()).collect();
+        let (mut pack, hashes) = blob_pack(&blobs);
+        pack.truncate(pack.len() - 4);
+
+        assert!(receive(&repo, pack).await.is_err());
+        assert_eq!(stored(&context, &hashes).await, 0);
+
+        // the same blobs in a whole pack are stored
+        let (mut pack, _) = blob_pack(&blobs);
+        pack.extend(SHA1::new(&pack).0);
+        receive(&repo, pack).await.unwrap();
+        assert_eq!(stored(&context, &hashes).await, 2 * hashes.len());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_oversized_blob_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut context = Context::sqlite(dir.path()).await;
+        context.config.lfs.max_blob_size = 16;
+        let repo = test_repo(&context);
+        // the oversized blob comes after a whole batch of small ones
+        let mut blobs: Vec<Vec<u8>> = (0..1500).map(|i| format!("{}\n", i).into_bytes()).collect();
+        blobs.push(vec![b'x'; 17]);
+        let (mut pack, hashes) = blob_pack(&blobs);
+        pack.extend(SHA1::new(&pack).0);
+
+        let err = receive(&repo, pack).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds the 16 bytes limit"));
+        assert_eq!(stored(&context, &hashes).await, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_over_quota_push_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut context = Context::sqlite(dir.path()).await;
+        context.config.monorepo.quotas = vec![QuotaRule {
+            path: "/project".to_owned(),
+            max_size: 4096,
+        }];
+        let mut protocol = SmartProtocol::mock();
+        protocol.context = context.clone();
+        protocol.path = PathBuf::from("/project");
+        protocol.service_type = Some(ServiceType::ReceivePack);
+        let blobs: Vec<Vec<u8>> = (0..1500).map(|i| format!("{}\n", i).into_bytes()).collect();
+        let (mut pack, hashes) = blob_pack(&blobs);
+        pack.extend(SHA1::new(&pack).0);
+
+        let stream = futures::stream::iter(vec![Ok::<_, axum::Error>(Bytes::from(pack))]);
+        let report = protocol
+            .git_receive_pack_stream(Box::pin(stream))
+            .await
+            .unwrap();
+        let report = String::from_utf8_lossy(&report);
+        assert!(report.contains("unpack quota of /project exceeded, 0 of 4096 bytes are used"));
+        assert_eq!(stored(&context, &hashes).await, 0);
+    }
+}
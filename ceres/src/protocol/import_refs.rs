@@ -103,6 +103,10 @@ impl RefCommand {
         }
     }
 
+    pub fn is_ok(&self) -> bool {
+        RefCommand::OK_STATUS == self.status
+    }
+
     pub fn failed(&mut self, msg: String) {
         RefCommand::FAILED_STATUS.clone_into(&mut self.status);
         self.error_msg = msg;
@@ -8,7 +8,9 @@ use common::{
 };
 use import_refs::RefCommand;
 use jupiter::context::Context;
+use mr::MergeRequest;
 use repo::Repo;
+use signature::PushCert;
 
 use crate::pack::{PackHandler, import_repo::ImportRepo, monorepo::MonoRepo};
 
@@ -16,6 +18,7 @@ pub mod smart;
 pub mod repo;
 pub mod import_refs;
 pub mod mr;
+pub mod signature;
 
 #[derive(Clone)]
 pub struct SmartProtocol {
@@ -25,6 +28,16 @@ pub struct SmartProtocol {
     pub command_list: Vec<RefCommand>,
     pub service_type: Option<ServiceType>,
     pub context: Context,
+    /// Directories below `path` the requesting user can't read, left out of what is served.
+    pub hidden_paths: Vec<PathBuf>,
+    /// Objects a partial clone asked to leave out of the pack.
+    pub filter: Option<ObjectFilter>,
+    /// Options of `git push -o`, applied to the MR the push opens or updates.
+    pub push_options: PushOptions,
+    /// Certificate of a signed push, recorded once the pack is received.
+    pub push_cert: Option<PushCert>,
+    /// User the request is made by, `None` when anonymous.
+    pub user: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
@@ -80,6 +93,9 @@ pub enum Capability {
     OfsDelta,
     DeepenSince,
     DeepenNot,
+    Filter,
+    NoProgress,
+    PushOptions,
 }
 
 impl FromStr for Capability {
@@ -97,11 +113,134 @@ impl FromStr for Capability {
             "no-done" => Ok(Capability::NoDone),
             "deepen-since" => Ok(Capability::DeepenSince),
             "deepen-not" => Ok(Capability::DeepenNot),
+            "filter" => Ok(Capability::Filter),
+            "no-progress" => Ok(Capability::NoProgress),
+            "push-options" => Ok(Capability::PushOptions),
             _ => Err(()),
         }
     }
 }
 
+/// Object filter sent by a partial clone, see the `--filter` option of git-rev-list.
+/// Objects the client asks for by hash are always sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFilter {
+    /// `blob:none`, no blobs at all.
+    BlobNone,
+    /// `blob:limit=<n>`, blobs of n bytes or more are left out.
+    BlobLimit(usize),
+    /// `tree:<depth>`, trees and blobs `depth` or more levels below the root tree are left out.
+    TreeDepth(usize),
+}
+
+impl ObjectFilter {
+    /// Whether a tree or blob `depth` levels below the root tree is sent, the root tree
+    /// itself being at depth 0.
+    pub fn allows(&self, depth: usize, is_tree: bool) -> bool {
+        match self {
+            ObjectFilter::BlobNone => is_tree,
+            ObjectFilter::BlobLimit(_) => true,
+            ObjectFilter::TreeDepth(max) => depth < *max,
+        }
+    }
+
+    /// Whether a blob of `size` bytes passes the size limit.
+    pub fn allows_size(&self, size: usize) -> bool {
+        match self {
+            ObjectFilter::BlobLimit(limit) => size < *limit,
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for ObjectFilter {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProtocolError::InvalidInput(format!("unsupported filter: {}", s));
+        if s == "blob:none" {
+            return Ok(ObjectFilter::BlobNone);
+        }
+        if let Some(limit) = s.strip_prefix("blob:limit=") {
+            let (num, unit) = match limit.char_indices().last() {
+                Some((i, 'k')) => (&limit[..i], 1024),
+                Some((i, 'm')) => (&limit[..i], 1024 * 1024),
+                Some((i, 'g')) => (&limit[..i], 1024 * 1024 * 1024),
+                _ => (limit, 1),
+            };
+            let num: usize = num.parse().map_err(|_| invalid())?;
+            return Ok(ObjectFilter::BlobLimit(num * unit));
+        }
+        if let Some(depth) = s.strip_prefix("tree:") {
+            return Ok(ObjectFilter::TreeDepth(
+                depth.parse().map_err(|_| invalid())?,
+            ));
+        }
+        Err(invalid())
+    }
+}
+
+/// Merge request settings sent with `git push -o <key>=<value>`, unknown options are
+/// ignored. `reviewer` can be repeated or list several names separated by commas,
+/// `team:<name>` asks every member of the team. A bare `draft` is `draft=true`, and `\n`
+/// in `description` starts a new line as an option can't hold line breaks. `depends-on`
+/// takes the link of the MR this one builds on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushOptions {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub reviewers: Vec<String>,
+    pub draft: Option<bool>,
+    pub depends_on: Option<String>,
+}
+
+impl PushOptions {
+    pub fn parse(options: &[String]) -> Self {
+        let mut res = PushOptions::default();
+        for option in options {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            match key.trim() {
+                "title" if !value.trim().is_empty() => res.title = Some(value.trim().to_owned()),
+                "description" => res.description = Some(value.replace("\\n", "\n")),
+                "reviewer" => {
+                    for name in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+                        if !res.reviewers.iter().any(|x| x == name) {
+                            res.reviewers.push(name.to_owned());
+                        }
+                    }
+                }
+                "draft" => res.draft = Some(!matches!(value.trim(), "false" | "0" | "no")),
+                "depends-on" if !value.trim().is_empty() => {
+                    res.depends_on = Some(value.trim().to_owned())
+                }
+                _ => tracing::debug!("ignored push option: {}", option),
+            }
+        }
+        res
+    }
+
+    /// Set what the options give on `mr`, reviewers are added to the ones it has.
+    pub fn apply(&self, mr: &mut MergeRequest) {
+        if let Some(title) = &self.title {
+            mr.title = title.clone();
+        }
+        if let Some(description) = &self.description {
+            mr.description = Some(description.clone());
+        }
+        for name in &self.reviewers {
+            if !mr.reviewers.contains(name) {
+                mr.reviewers.push(name.clone());
+            }
+        }
+        if let Some(draft) = self.draft {
+            mr.draft = draft;
+        }
+        if let Some(depends_on) = &self.depends_on {
+            mr.depends_on = Some(depends_on.clone());
+        }
+    }
+}
+
 pub enum SideBind {
     // sideband 1 will contain packfile data,
     PackfileData,
@@ -133,6 +272,11 @@ impl SmartProtocol {
             command_list: Vec::new(),
             service_type: None,
             context,
+            hidden_paths: Vec::new(),
+            filter: None,
+            push_options: PushOptions::default(),
+            push_cert: None,
+            user: None,
         }
     }
 
@@ -145,12 +289,22 @@ impl SmartProtocol {
             command_list: Vec::new(),
             service_type: None,
             context,
+            hidden_paths: Vec::new(),
+            filter: None,
+            push_options: PushOptions::default(),
+            push_cert: None,
+            user: None,
         }
     }
 
     pub async fn pack_handler(&self) -> Result<Arc<dyn PackHandler>, ProtocolError> {
         let import_dir = self.context.config.monorepo.import_dir.clone();
         if self.path.starts_with(import_dir.clone()) {
+            if !self.hidden_paths.is_empty() {
+                return Err(ProtocolError::Forbidden(
+                    "Repository is not fully readable.".to_owned(),
+                ));
+            }
             let storage = self.context.services.git_db_storage.clone();
             let path_str = self.path.to_str().unwrap();
             let model = storage.find_git_repo_exact_match(path_str).await.unwrap();
@@ -179,6 +333,11 @@ impl SmartProtocol {
                 path: self.path.clone(),
                 from_hash: String::new(),
                 to_hash: String::new(),
+                hidden: self.hidden_paths.clone(),
+                view_trees: Arc::default(),
+                filter: self.filter,
+                push_options: self.push_options.clone(),
+                pusher: self.user.clone(),
             };
             if let Some(command) = self
                 .command_list
@@ -194,4 +353,65 @@ impl SmartProtocol {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_object_filter() {
+        let parse = |s: &str| s.parse::<ObjectFilter>().ok();
+        assert_eq!(parse("blob:none"), Some(ObjectFilter::BlobNone));
+        assert_eq!(parse("blob:limit=512"), Some(ObjectFilter::BlobLimit(512)));
+        assert_eq!(parse("blob:limit=1k"), Some(ObjectFilter::BlobLimit(1024)));
+        assert_eq!(parse("tree:0"), Some(ObjectFilter::TreeDepth(0)));
+        assert_eq!(parse("blob:limit="), None);
+        assert_eq!(parse("sparse:oid=abc"), None);
+    }
+
+    #[test]
+    fn test_object_filter_allows() {
+        let depth = ObjectFilter::TreeDepth(1);
+        assert!(depth.allows(0, true));
+        assert!(!depth.allows(1, true));
+        assert!(!depth.allows(1, false));
+        assert!(!ObjectFilter::BlobNone.allows(1, false));
+        assert!(ObjectFilter::BlobNone.allows(3, true));
+        assert!(!ObjectFilter::BlobLimit(10).allows_size(10));
+        assert!(ObjectFilter::BlobLimit(10).allows_size(9));
+    }
+
+    #[test]
+    fn test_push_options() {
+        let options: Vec<String> = [
+            "title=My change",
+            "description=first line\\nsecond line",
+            "reviewer=alice",
+            "reviewer=bob, alice",
+            "draft",
+            "depends-on= A1B2C3D4 ",
+            "ci.skip",
+        ]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+        let options = PushOptions::parse(&options);
+        assert_eq!(options.title.as_deref(), Some("My change"));
+        assert_eq!(
+            options.description.as_deref(),
+            Some("first line\nsecond line")
+        );
+        assert_eq!(options.reviewers, vec!["alice", "bob"]);
+        assert_eq!(options.draft, Some(true));
+        assert_eq!(options.depends_on.as_deref(), Some("A1B2C3D4"));
+
+        let mut mr = MergeRequest {
+            title: "from commit".to_owned(),
+            reviewers: vec!["carol".to_owned()],
+            draft: true,
+            ..Default::default()
+        };
+        PushOptions::parse(&["draft=false".to_owned(), "reviewer=alice".to_owned()]).apply(&mut mr);
+        assert_eq!(mr.title, "from commit");
+        assert_eq!(mr.reviewers, vec!["carol", "alice"]);
+        assert!(!mr.draft);
+    }
+}
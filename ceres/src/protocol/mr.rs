@@ -1,7 +1,14 @@
 use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use callisto::{db_enums::MergeStatus, mega_mr};
-use common::utils::generate_id;
+use common::utils::{generate_id, MEGA_DEFAULT_BRANCH};
+use jupiter::storage::mr_storage::MrStorage;
+use mercury::errors::GitError;
+
+/// Dependencies followed looking for a cycle, longer stacks aren't checked further.
+const MAX_DEPENDENCY_CHAIN: usize = 32;
 
 #[derive(Clone)]
 pub struct MergeRequest {
@@ -13,6 +20,24 @@ pub struct MergeRequest {
     pub path: String,
     pub from_hash: String,
     pub to_hash: String,
+    /// `None` when the MR was opened by a push
+    pub source_branch: Option<String>,
+    pub target_branch: String,
+    pub description: Option<String>,
+    /// usernames asked to review the MR
+    pub reviewers: Vec<String>,
+    /// a draft MR can't be merged yet
+    pub draft: bool,
+    /// user name of who opened the MR
+    pub author: Option<String>,
+    /// link of the MR this one builds on, it has to be merged first
+    pub depends_on: Option<String>,
+    pub labels: Vec<String>,
+    /// usernames working on the MR
+    pub assignees: Vec<String>,
+    pub milestone: Option<String>,
+    /// link of the MR closed for a conflict that this one replaces
+    pub supersedes: Option<String>,
 }
 
 impl Default for MergeRequest {
@@ -26,11 +51,103 @@ impl Default for MergeRequest {
             path: String::new(),
             from_hash: String::new(),
             to_hash: String::new(),
+            source_branch: None,
+            target_branch: MEGA_DEFAULT_BRANCH.to_owned(),
+            description: None,
+            reviewers: vec![],
+            draft: false,
+            author: None,
+            depends_on: None,
+            labels: vec![],
+            assignees: vec![],
+            milestone: None,
+            supersedes: None,
+        }
+    }
+}
+
+/// How the commits of a merge request end up in the monorepo.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeOperation {
+    /// every commit of the MR is replayed as its own commit
+    Merge,
+    /// a single commit with the final tree and the messages of all commits
+    #[default]
+    Squash,
+}
+
+/// Outcome of merging a merge request, the paths changed on both sides in ways that
+/// can't be combined are listed in `conflicts` and nothing is written then.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct MergeResult {
+    pub merged: bool,
+    pub conflicts: Vec<String>,
+}
+
+impl MergeResult {
+    pub fn merged() -> Self {
+        MergeResult {
+            merged: true,
+            conflicts: vec![],
+        }
+    }
+
+    pub fn conflict(conflicts: Vec<String>) -> Self {
+        MergeResult {
+            merged: false,
+            conflicts,
         }
     }
 }
 
 impl MergeRequest {
+    /// Refuse `depends_on` as the dependency of this MR unless it's an MR that isn't
+    /// closed, on the path of this one or a parent of it, and not built on this one.
+    pub async fn check_dependency(
+        &self,
+        storage: &MrStorage,
+        depends_on: &str,
+    ) -> Result<(), GitError> {
+        let dependency = storage
+            .get_mr(depends_on)
+            .await
+            .map_err(|e| GitError::CustomError(e.to_string()))?
+            .ok_or_else(|| GitError::InvalidArgument(format!("MR {} not found", depends_on)))?;
+        if dependency.status == MergeStatus::Closed {
+            return Err(GitError::InvalidArgument(format!(
+                "MR {} is closed",
+                depends_on
+            )));
+        }
+        if !is_within(&self.path, &dependency.path) {
+            return Err(GitError::InvalidArgument(format!(
+                "MR {} is on {}, neither the path of this MR nor a parent of it",
+                depends_on, dependency.path
+            )));
+        }
+        let mut next = Some(dependency);
+        for _ in 0..MAX_DEPENDENCY_CHAIN {
+            let Some(mr) = next else {
+                break;
+            };
+            if mr.link == self.link {
+                return Err(GitError::InvalidArgument(format!(
+                    "MR {} depends on this MR already",
+                    depends_on
+                )));
+            }
+            next = match mr.depends_on {
+                Some(link) => storage
+                    .get_mr(&link)
+                    .await
+                    .map_err(|e| GitError::CustomError(e.to_string()))?,
+                None => None,
+            };
+        }
+        Ok(())
+    }
+
     pub fn close(&mut self) {
         self.status = MergeStatus::Closed;
     }
@@ -52,6 +169,17 @@ impl From<MergeRequest> for mega_mr::Model {
             path: value.path,
             from_hash: value.from_hash,
             to_hash: value.to_hash,
+            source_branch: value.source_branch,
+            target_branch: value.target_branch,
+            description: value.description,
+            reviewers: serde_json::json!(value.reviewers),
+            draft: value.draft,
+            author: value.author,
+            depends_on: value.depends_on,
+            labels: serde_json::json!(value.labels),
+            assignees: serde_json::json!(value.assignees),
+            milestone: value.milestone,
+            supersedes: value.supersedes,
             created_at: chrono::Utc::now().naive_utc(),
             updated_at: chrono::Utc::now().naive_utc(),
         }
@@ -69,7 +197,40 @@ impl From<mega_mr::Model> for MergeRequest {
             path: value.path,
             from_hash: value.from_hash,
             to_hash: value.to_hash,
+            source_branch: value.source_branch,
+            target_branch: value.target_branch,
+            description: value.description,
+            reviewers: serde_json::from_value(value.reviewers).unwrap_or_default(),
+            draft: value.draft,
+            author: value.author,
+            depends_on: value.depends_on,
+            labels: serde_json::from_value(value.labels).unwrap_or_default(),
+            assignees: serde_json::from_value(value.assignees).unwrap_or_default(),
+            milestone: value.milestone,
+            supersedes: value.supersedes,
         }
     }
 }
 
+/// Whether `path` is `dir` or lies below it.
+fn is_within(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_within;
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("/project/app", "/project"));
+        assert!(is_within("/project", "/project"));
+        assert!(is_within("/project", "/"));
+        assert!(!is_within("/projects", "/project"));
+        assert!(!is_within("/project", "/project/app"));
+    }
+}
@@ -0,0 +1,383 @@
+//! Verification of commit signatures and of the certificates of signed pushes. Like git
+//! does, signatures are checked by running gpg or ssh-keygen: gpg against the keyring set
+//! in the config, ssh-keygen against the ssh keys the signer registered with mega.
+
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+use ring::hmac;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use callisto::db_enums::SignatureFormat;
+use callisto::{mega_commit_signature, mega_push_cert};
+use common::config::SigningConfig;
+use common::utils::generate_id;
+use jupiter::context::Context;
+use mercury::internal::object::commit::Commit;
+
+/// Outcome of checking one signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub format: SignatureFormat,
+    pub verified: bool,
+    pub signer: Option<String>,
+    pub reason: String,
+}
+
+/// A push certificate, sent by `git push --signed` in place of the plain ref update
+/// commands, see the push-cert section of the git pack protocol.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PushCert {
+    /// ident of the pusher, `Name <email> <seconds> <+hhmm>`
+    pub pusher: String,
+    pub pushee: String,
+    pub nonce: String,
+    /// ref update commands, `<old-id> <new-id> <ref>`
+    pub commands: Vec<String>,
+    /// the certificate up to the signature, what the signature covers
+    pub payload: String,
+    pub signature: String,
+}
+
+impl PushCert {
+    /// Parse the pkt-lines following `push-cert`, each with its line feed. `None` when
+    /// the certificate has no commands section or no signature.
+    pub fn parse(lines: &[String]) -> Option<Self> {
+        let mut cert = PushCert::default();
+        let mut in_commands = false;
+        for line in lines {
+            if line == "push-cert-end\n" {
+                break;
+            }
+            if !cert.signature.is_empty() || line.starts_with("-----BEGIN ") {
+                cert.signature.push_str(line);
+                continue;
+            }
+            cert.payload.push_str(line);
+            let line = line.trim_end_matches('\n');
+            if in_commands {
+                cert.commands.push(line.to_owned());
+            } else if line.is_empty() {
+                in_commands = true;
+            } else if let Some(pusher) = line.strip_prefix("pusher ") {
+                pusher.clone_into(&mut cert.pusher);
+            } else if let Some(pushee) = line.strip_prefix("pushee ") {
+                pushee.clone_into(&mut cert.pushee);
+            } else if let Some(nonce) = line.strip_prefix("nonce ") {
+                nonce.clone_into(&mut cert.nonce);
+            }
+        }
+        (in_commands && !cert.signature.is_empty()).then_some(cert)
+    }
+
+    /// Email in the pusher ident.
+    pub fn pusher_email(&self) -> &str {
+        let start = self.pusher.find('<').map_or(0, |x| x + 1);
+        let end = self.pusher.find('>').unwrap_or(self.pusher.len());
+        self.pusher.get(start..end).unwrap_or_default()
+    }
+}
+
+/// Nonce offered to signed pushes to `path`, a timestamp and its HMAC under `seed` so it
+/// can be checked later without keeping it.
+pub fn cert_nonce(seed: &str, path: &str, timestamp: i64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, seed.as_bytes());
+    let tag = hmac::sign(&key, format!("{}:{}", path, timestamp).as_bytes());
+    format!("{}-{}", timestamp, hex::encode(&tag.as_ref()[..20]))
+}
+
+/// Status of the nonce of a push certificate, named like `GIT_PUSH_CERT_NONCE_STATUS`:
+/// `OK`, `SLOP` when it was issued more than `slop` seconds ago, `BAD` when it wasn't
+/// issued for `path` at all, and `MISSING`.
+pub fn nonce_status(seed: &str, path: &str, nonce: &str, now: i64, slop: i64) -> &'static str {
+    if nonce.is_empty() {
+        return "MISSING";
+    }
+    let Some(timestamp) = nonce
+        .split_once('-')
+        .and_then(|(ts, _)| ts.parse::<i64>().ok())
+    else {
+        return "BAD";
+    };
+    if cert_nonce(seed, path, timestamp) != nonce {
+        return "BAD";
+    }
+    if (now - timestamp).abs() > slop {
+        "SLOP"
+    } else {
+        "OK"
+    }
+}
+
+pub fn signature_format(signature: &str) -> Option<SignatureFormat> {
+    if signature.starts_with("-----BEGIN PGP SIGNATURE-----") {
+        Some(SignatureFormat::Gpg)
+    } else if signature.starts_with("-----BEGIN SSH SIGNATURE-----") {
+        Some(SignatureFormat::Ssh)
+    } else {
+        None
+    }
+}
+
+/// Check that `signature` over `payload` was made by the owner of `email`. `None` when the
+/// signature is neither a gpg nor an ssh one.
+pub async fn verify_signature(
+    context: &Context,
+    payload: &[u8],
+    signature: &str,
+    email: &str,
+) -> Option<Verification> {
+    let format = signature_format(signature)?;
+    let res = match format {
+        SignatureFormat::Gpg => {
+            verify_gpg(&context.config.signing, payload, signature, email).await
+        }
+        SignatureFormat::Ssh => verify_ssh(context, payload, signature, email).await,
+    };
+    Some(res.unwrap_or_else(|reason| Verification {
+        format,
+        verified: false,
+        signer: None,
+        reason,
+    }))
+}
+
+/// Verify the signed ones of `commits` against their committer and store the results,
+/// unsigned commits get none.
+pub async fn verify_commits(context: &Context, commits: &[Commit]) {
+    for commit in commits {
+        let Some((payload, signature)) = commit.signature() else {
+            continue;
+        };
+        let email = &commit.committer.email;
+        let Some(res) = verify_signature(context, &payload, &signature, email).await else {
+            continue;
+        };
+        let model = mega_commit_signature::Model {
+            id: generate_id(),
+            commit_id: commit.id.to_string(),
+            format: res.format,
+            verified: res.verified,
+            signer: res.signer,
+            reason: res.reason,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        if let Err(err) = context.signature_stg().save_commit_signature(model).await {
+            tracing::error!("failed to save the signature of {}: {}", commit.id, err);
+        }
+    }
+}
+
+/// Verify the certificate of a signed push to `path` against the pusher, and store it
+/// with the result and the status of its nonce.
+pub async fn record_push_cert(context: &Context, path: &str, cert: &PushCert) {
+    let config = &context.config.signing;
+    let now = chrono::Utc::now();
+    let status = nonce_status(
+        &config.cert_nonce_seed,
+        path,
+        &cert.nonce,
+        now.timestamp(),
+        config.cert_nonce_slop,
+    );
+    let res = verify_signature(
+        context,
+        cert.payload.as_bytes(),
+        &cert.signature,
+        cert.pusher_email(),
+    )
+    .await;
+    let model = mega_push_cert::Model {
+        id: generate_id(),
+        path: path.to_owned(),
+        pusher: cert.pusher.clone(),
+        nonce_status: status.to_owned(),
+        format: res.as_ref().map(|x| x.format),
+        verified: res.as_ref().is_some_and(|x| x.verified),
+        signer: res.as_ref().and_then(|x| x.signer.clone()),
+        reason: res.map_or("unsupported signature".to_owned(), |x| x.reason),
+        certificate: format!("{}{}", cert.payload, cert.signature),
+        created_at: now.naive_utc(),
+    };
+    if let Err(err) = context.signature_stg().save_push_cert(model).await {
+        tracing::error!("failed to save the push certificate of {}: {}", path, err);
+    }
+}
+
+/// `gpg --verify` with the status lines on stdout, the signing key's user id has to carry
+/// `email`.
+async fn verify_gpg(
+    config: &SigningConfig,
+    payload: &[u8],
+    signature: &str,
+    email: &str,
+) -> Result<Verification, String> {
+    let sig_file = temp_file(signature.as_bytes())?;
+    let mut command = Command::new(&config.gpg_program);
+    command
+        .args(["--status-fd=1", "--verify"])
+        .arg(sig_file.path())
+        .arg("-");
+    if !config.gpg_home.is_empty() {
+        command.env("GNUPGHOME", &config.gpg_home);
+    }
+    let output = run(command, payload).await?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let Some(good) = status
+        .lines()
+        .find_map(|x| x.strip_prefix("[GNUPG:] GOODSIG "))
+    else {
+        return Err(gpg_failure(&status).to_owned());
+    };
+    // GOODSIG <long key id> <user id>
+    let uid = good.split_once(' ').map_or("", |x| x.1).to_owned();
+    let verified = uid.contains(&format!("<{}>", email));
+    Ok(Verification {
+        format: SignatureFormat::Gpg,
+        verified,
+        reason: if verified {
+            "good signature".to_owned()
+        } else {
+            format!("signed by {}, not by {}", uid, email)
+        },
+        signer: Some(uid),
+    })
+}
+
+fn gpg_failure(status: &str) -> &'static str {
+    let has = |keyword: &str| status.lines().any(|x| x.contains(keyword));
+    if has("NO_PUBKEY") {
+        "unknown signing key"
+    } else if has("BADSIG") {
+        "bad signature"
+    } else if has("EXPKEYSIG") || has("EXPSIG") {
+        "expired signature or key"
+    } else if has("REVKEYSIG") {
+        "revoked key"
+    } else {
+        "signature can't be verified"
+    }
+}
+
+/// `ssh-keygen -Y verify` with the ssh keys of the mega user owning `email` as the allowed
+/// signers.
+async fn verify_ssh(
+    context: &Context,
+    payload: &[u8],
+    signature: &str,
+    email: &str,
+) -> Result<Verification, String> {
+    let user_stg = context.user_stg();
+    let user = user_stg
+        .find_user_by_email(email)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("no user with email {}", email))?;
+    let keys = user_stg
+        .list_user_ssh(user.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if keys.is_empty() {
+        return Err(format!("{} has no ssh key", user.name));
+    }
+    let allowed_signers: String = keys
+        .iter()
+        .map(|x| format!("{} {}\n", email, x.ssh_key.trim()))
+        .collect();
+    let signers_file = temp_file(allowed_signers.as_bytes())?;
+    let sig_file = temp_file(signature.as_bytes())?;
+    let mut command = Command::new(&context.config.signing.ssh_keygen_program);
+    command
+        .args(["-Y", "verify", "-n", "git", "-I", email, "-f"])
+        .arg(signers_file.path())
+        .arg("-s")
+        .arg(sig_file.path());
+    let output = run(command, payload).await?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(if reason.is_empty() {
+            "bad signature".to_owned()
+        } else {
+            reason
+        });
+    }
+    Ok(Verification {
+        format: SignatureFormat::Ssh,
+        verified: true,
+        signer: Some(user.name),
+        reason: String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+    })
+}
+
+fn temp_file(content: &[u8]) -> Result<NamedTempFile, String> {
+    let mut file = NamedTempFile::new().map_err(|e| e.to_string())?;
+    file.write_all(content).map_err(|e| e.to_string())?;
+    Ok(file)
+}
+
+/// Run `command` with `input` on its stdin.
+async fn run(mut command: Command, input: &[u8]) -> Result<Output, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to run the verifier: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the verifier exits early on a malformed signature without reading its input
+        let _ = stdin.write_all(input).await;
+    }
+    child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("failed to run the verifier: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split_inclusive('\n').map(|x| x.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_parse_push_cert() {
+        let cert = "certificate version 0.1\n\
+            pusher Mega <mega@example.com> 1700000000 +0800\n\
+            pushee https://example.com/project\n\
+            nonce 1700000000-abc\n\
+            \n\
+            0000000000000000000000000000000000000000 8ab686eafeb1f44702738c8b0f24f2567c36da6d refs/heads/main\n";
+        let signature = "-----BEGIN PGP SIGNATURE-----\n\nabc\n-----END PGP SIGNATURE-----\n";
+        let text = format!("{}{}push-cert-end\n", cert, signature);
+        let res = PushCert::parse(&lines(&text)).unwrap();
+        assert_eq!(res.pusher_email(), "mega@example.com");
+        assert_eq!(res.pushee, "https://example.com/project");
+        assert_eq!(res.nonce, "1700000000-abc");
+        assert_eq!(res.commands.len(), 1);
+        assert!(res.commands[0].ends_with(" refs/heads/main"));
+        assert_eq!(res.payload, cert);
+        assert_eq!(res.signature, signature);
+        assert_eq!(signature_format(&res.signature), Some(SignatureFormat::Gpg));
+
+        assert!(PushCert::parse(&lines(cert)).is_none());
+    }
+
+    #[test]
+    fn test_nonce_status() {
+        let nonce = cert_nonce("seed", "/project", 1000);
+        assert_eq!(nonce_status("seed", "/project", &nonce, 1100, 300), "OK");
+        assert_eq!(nonce_status("seed", "/project", &nonce, 2000, 300), "SLOP");
+        assert_eq!(nonce_status("seed", "/other", &nonce, 1100, 300), "BAD");
+        assert_eq!(nonce_status("other", "/project", &nonce, 1100, 300), "BAD");
+        assert_eq!(
+            nonce_status("seed", "/project", "1000-abc", 1100, 300),
+            "BAD"
+        );
+        assert_eq!(nonce_status("seed", "/project", "", 1100, 300), "MISSING");
+    }
+}
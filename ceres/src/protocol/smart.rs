@@ -2,15 +2,20 @@ use std::pin::Pin;
 
 use anyhow::Result;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use futures::Stream;
+use futures::{stream, Stream, StreamExt};
 use tokio_stream::wrappers::ReceiverStream;
 
-use callisto::db_enums::RefType;
+use callisto::db_enums::{AuditAction, RefType};
 use common::errors::ProtocolError;
+use serde_json::json;
+use taurus::event::audit::AuditEvent;
 
 use crate::protocol::import_refs::RefCommand;
+use crate::protocol::signature::{cert_nonce, record_push_cert, PushCert};
 use crate::protocol::ZERO_ID;
-use crate::protocol::{Capability, ServiceType, SideBind, SmartProtocol, TransportProtocol};
+use crate::protocol::{
+    Capability, PushOptions, ServiceType, SideBind, SmartProtocol, TransportProtocol,
+};
 
 const LF: char = '\n';
 
@@ -20,17 +25,21 @@ const NUL: char = '\0';
 
 pub const PKT_LINE_END_MARKER: &[u8; 4] = b"0000";
 
+/// Largest payload put in one sideband packet, below the 65520 bytes of side-band-64k.
+const SIDE_BAND_DATA_SIZE: usize = 65500;
+
 // see https://git-scm.com/docs/protocol-capabilities
 // The atomic, report-status, report-status-v2, delete-refs, quiet,
 // and push-cert capabilities are sent and recognized by the receive-pack (push to server) process.
-const RECEIVE_CAP_LIST: &str = "report-status report-status-v2 delete-refs quiet atomic no-thin ";
+const RECEIVE_CAP_LIST: &str =
+    "report-status report-status-v2 delete-refs quiet atomic push-options ";
 
 // The ofs-delta and side-band-64k capabilities are sent and recognized by both upload-pack and receive-pack protocols.
 // The agent and session-id capabilities may optionally be sent in both protocols.
 const COMMON_CAP_LIST: &str = "side-band-64k ofs-delta agent=mega/0.1.0";
 
 // All other capabilities are only recognized by the upload-pack (fetch from server) process.
-const UPLOAD_CAP_LIST: &str = "multi_ack_detailed no-done include-tag ";
+const UPLOAD_CAP_LIST: &str = "multi_ack_detailed no-done include-tag filter ";
 
 impl SmartProtocol {
     /// # Retrieves the information about Git references (refs) for the specified service type.
@@ -71,7 +80,17 @@ impl SmartProtocol {
         };
         let cap_list = match service_type {
             ServiceType::UploadPack => format!("{}{}", UPLOAD_CAP_LIST, COMMON_CAP_LIST),
-            ServiceType::ReceivePack => format!("{}{}", RECEIVE_CAP_LIST, COMMON_CAP_LIST),
+            ServiceType::ReceivePack => {
+                let seed = &self.context.config.signing.cert_nonce_seed;
+                let push_cert = if seed.is_empty() {
+                    String::new()
+                } else {
+                    let path = self.path.to_str().unwrap();
+                    let nonce = cert_nonce(seed, path, chrono::Utc::now().timestamp());
+                    format!("push-cert={} ", nonce)
+                };
+                format!("{}{}{}", RECEIVE_CAP_LIST, push_cert, COMMON_CAP_LIST)
+            }
         };
         let pkt_line = format!("{}{}{}{}{}{}", head_hash, SP, name, NUL, cap_list, LF);
         let mut ref_list = vec![pkt_line];
@@ -89,8 +108,6 @@ impl SmartProtocol {
         &mut self,
         upload_request: &mut Bytes,
     ) -> Result<(ReceiverStream<Vec<u8>>, BytesMut), ProtocolError> {
-        let pack_handler = self.pack_handler().await?;
-
         let mut want: Vec<String> = Vec::new();
         let mut have: Vec<String> = Vec::new();
         let mut last_common_commit = String::new();
@@ -117,6 +134,11 @@ impl SmartProtocol {
                     have.push(String::from_utf8(dst[5..45].to_vec()).unwrap());
                 }
                 b"done" => break,
+                b"filt" => {
+                    let spec = String::from_utf8_lossy(&dst[7..]);
+                    self.filter = Some(spec.trim().parse()?);
+                    continue;
+                }
                 other => {
                     tracing::error!(
                         "unsupported command: {:?}",
@@ -132,17 +154,37 @@ impl SmartProtocol {
         }
 
         tracing::info!(
-            "want commands: {:?}\n have commands: {:?}\n caps:{:?}",
+            "want commands: {:?}\n have commands: {:?}\n caps:{:?}\n filter:{:?}",
             want,
             have,
-            self.capabilities
+            self.capabilities,
+            self.filter
         );
 
+        // the filter is known once the request is read
+        let pack_handler = self.pack_handler().await?;
         let pack_data;
         let mut protocol_buf = BytesMut::new();
 
         if have.is_empty() {
-            pack_data = pack_handler.full_pack(want.clone()).await.unwrap();
+            // a partial clone fetching objects it left out wants them by hash
+            let want_commits = pack_handler
+                .get_commits_by_hashes(want.clone())
+                .await
+                .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+            pack_data = if want_commits.is_empty() && !want.is_empty() {
+                if !self.hidden_paths.is_empty() {
+                    return Err(ProtocolError::Forbidden(
+                        "Objects can only be fetched from a fully readable path.".to_owned(),
+                    ));
+                }
+                pack_handler
+                    .objects_pack(&self.context.config.pack, want.clone())
+                    .await
+                    .unwrap()
+            } else {
+                pack_handler.full_pack(want.clone()).await.unwrap()
+            };
             add_pkt_line_string(&mut protocol_buf, String::from("NAK\n"));
         } else {
             if self.capabilities.contains(&Capability::MultiAckDetailed) {
@@ -189,9 +231,26 @@ impl SmartProtocol {
     }
 
     pub fn git_receive_pack_protocol(&mut self, mut protocol_bytes: Bytes) {
+        let mut commands_end = false;
+        let mut push_options = vec![];
+        // a signed push sends its commands inside a push certificate
+        let mut cert_lines: Option<Vec<String>> = None;
         while !protocol_bytes.is_empty() {
             let (bytes_take, mut pkt_line) = read_pkt_line(&mut protocol_bytes);
-            if bytes_take != 0 {
+            if bytes_take == 0 {
+                // push options come after the flush-pkt ending the commands
+                commands_end = true;
+            } else if commands_end {
+                if self.capabilities.contains(&Capability::PushOptions) {
+                    let option = String::from_utf8_lossy(&pkt_line);
+                    push_options.push(option.trim_end_matches('\n').to_owned());
+                }
+            } else if let Some(lines) = cert_lines.as_mut() {
+                lines.push(String::from_utf8_lossy(&pkt_line).into_owned());
+            } else if let Some(caps) = pkt_line.strip_prefix(b"push-cert\0") {
+                self.parse_capabilities(&String::from_utf8_lossy(caps));
+                cert_lines = Some(vec![]);
+            } else {
                 let command = self.parse_ref_command(&mut pkt_line);
                 self.parse_capabilities(core::str::from_utf8(&pkt_line).unwrap());
                 tracing::debug!(
@@ -202,6 +261,14 @@ impl SmartProtocol {
                 self.command_list.push(command);
             }
         }
+        if let Some(cert) = cert_lines.and_then(|x| PushCert::parse(&x)) {
+            for command in &cert.commands {
+                let command = self.parse_ref_command(&mut Bytes::from(command.clone()));
+                self.command_list.push(command);
+            }
+            self.push_cert = Some(cert);
+        }
+        self.push_options = PushOptions::parse(&push_options);
     }
 
     pub async fn git_receive_pack_stream(
@@ -211,30 +278,44 @@ impl SmartProtocol {
         // After receiving the pack data from the sender, the receiver sends a report
         let mut report_status = BytesMut::new();
         let pack_handler = self.pack_handler().await?;
-        //1. unpack progress
-        let receiver = pack_handler
+        //1. unpack progress, objects are handled while the pack is decoded
+        let (receiver, unpacked) = pack_handler
             .unpack_stream(&self.context.config.pack, data_stream)
-            .await?;
+            .await;
 
-        // do not block main thread here.
+        // do not block main thread here. Nothing is stored when the pack fails to decode
         let handler_clone = pack_handler.clone();
         let unpack_result = tokio::task::spawn_blocking(move || {
             let handle = tokio::runtime::Handle::current();
-            handle.block_on(async { handler_clone.handle_receiver(receiver).await })
+            handle.block_on(async { handler_clone.handle_receiver(receiver, unpacked).await })
         })
         .await
         .unwrap();
 
-        // write "unpack ok\n to report"
-        add_pkt_line_string(&mut report_status, "unpack ok\n".to_owned());
+        // write "unpack ok\n to report", or why the objects were refused
+        match unpack_result {
+            Ok(_) => add_pkt_line_string(&mut report_status, "unpack ok\n".to_owned()),
+            Err(ref err) => {
+                add_pkt_line_string(&mut report_status, format!("unpack {}\n", err));
+            }
+        }
+
+        if let (Ok(_), Some(cert)) = (&unpack_result, &self.push_cert) {
+            record_push_cert(&self.context, self.path.to_str().unwrap(), cert).await;
+        }
 
         let mut default_exist = pack_handler.check_default_branch().await;
 
         //2. update each refs and build report
         for command in &mut self.command_list {
-            if command.ref_type == RefType::Tag {
+            if let Err(ref err) = unpack_result {
+                // nothing is updated when a pushed object was refused
+                command.failed(err.to_string());
+            } else if command.ref_type == RefType::Tag {
                 // just update if refs type is tag
-                pack_handler.update_refs(None, None, command).await.unwrap();
+                if let Err(e) = pack_handler.update_refs(None, None, command).await {
+                    command.failed(e.to_string());
+                }
             } else {
                 // Updates can be unsuccessful for a number of reasons.
                 // a.The reference can have changed since the reference discovery phase was originally sent, meaning someone pushed in the meantime.
@@ -242,22 +323,35 @@ impl SmartProtocol {
                 // c.Also, some references can be updated while others can be rejected.
                 match unpack_result {
                     Ok(ref commit) => {
-                        if let Some(c) = commit {
-                            let mr_title = c.format_message();
-                            if let Ok(mr_link) = pack_handler.handle_mr(&mr_title).await {
-                                pack_handler
-                                    .update_refs(Some(mr_link), Some(c.clone()), command)
+                        if let Err(e) = pack_handler.check_ref_update(command).await {
+                            command.failed(e.to_string());
+                        } else if let Some(c) = commit {
+                            if !pack_handler.push_opens_mr(&command.ref_name) {
+                                if let Err(e) = pack_handler
+                                    .update_refs(None, Some(c.clone()), command)
                                     .await
-                                    .unwrap();
-                            } else if let Err(e) = pack_handler.handle_mr(&mr_title).await {
-                                command.failed(e.to_string());
+                                {
+                                    command.failed(e.to_string());
+                                }
+                            } else {
+                                let mr_title = c.format_message();
+                                if let Ok(mr_link) = pack_handler.handle_mr(&mr_title).await {
+                                    pack_handler
+                                        .update_refs(Some(mr_link), Some(c.clone()), command)
+                                        .await
+                                        .unwrap();
+                                } else if let Err(e) = pack_handler.handle_mr(&mr_title).await {
+                                    command.failed(e.to_string());
+                                }
                             }
                         } else {
                             if !default_exist {
                                 command.default_branch = true;
                                 default_exist = true;
                             }
-                            pack_handler.update_refs(None, None, command).await.unwrap();
+                            if let Err(e) = pack_handler.update_refs(None, None, command).await {
+                                command.failed(e.to_string());
+                            }
                         }
                     }
                     Err(ref err) => {
@@ -265,6 +359,20 @@ impl SmartProtocol {
                     }
                 }
             }
+            if command.is_ok() {
+                pack_handler.post_receive(command).await;
+                AuditEvent::notify(
+                    self.user.as_deref().unwrap_or("anonymous"),
+                    AuditAction::Push,
+                    self.path.to_str().unwrap(),
+                    json!({
+                        "ref": command.ref_name,
+                        "old_id": command.old_id,
+                        "new_id": command.new_id,
+                    }),
+                )
+                .await;
+            }
             add_pkt_line_string(&mut report_status, command.get_status());
         }
         report_status.put(&PKT_LINE_END_MARKER[..]);
@@ -306,6 +414,34 @@ impl SmartProtocol {
         from_bytes
     }
 
+    /// Frame the encoded pack for the client. With sideband the pack goes on the data band
+    /// and the encoding progress on the progress band, unless the client sent `no-progress`.
+    pub fn pack_data_stream(
+        &self,
+        pack_data: ReceiverStream<Vec<u8>>,
+    ) -> impl Stream<Item = Bytes> + Send {
+        let side_band = self.capabilities.contains(&Capability::SideBand)
+            || self.capabilities.contains(&Capability::SideBand64k);
+        let mut progress = (side_band && !self.capabilities.contains(&Capability::NoProgress))
+            .then(PackProgress::default);
+        pack_data.flat_map(move |chunk| {
+            let mut packets: Vec<Bytes> = chunk
+                .chunks(SIDE_BAND_DATA_SIZE)
+                .map(|data| {
+                    if side_band {
+                        side_band_packet(SideBind::PackfileData, data)
+                    } else {
+                        Bytes::copy_from_slice(data)
+                    }
+                })
+                .collect();
+            if let Some(msg) = progress.as_mut().and_then(|p| p.update(&chunk)) {
+                packets.push(side_band_packet(SideBind::ProgressInfo, msg.as_bytes()));
+            }
+            stream::iter(packets)
+        })
+    }
+
     pub fn build_smart_reply(&self, ref_list: &Vec<String>, service: String) -> BytesMut {
         let mut pkt_line_stream = BytesMut::new();
         if self.transport_protocol == TransportProtocol::Http {
@@ -352,6 +488,56 @@ fn read_until_white_space(bytes: &mut Bytes) -> String {
     String::from_utf8(buf).unwrap()
 }
 
+fn side_band_packet(band: SideBind, data: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(data.len() + 5);
+    buf.put(Bytes::from(format!("{:04x}", data.len() + 5)));
+    buf.put_u8(band.value());
+    buf.put(data);
+    buf.freeze()
+}
+
+/// Encoding progress of a pack, followed through the chunks `PackEncoder` sends: the
+/// header, one chunk for each object, then the checksum.
+#[derive(Default)]
+struct PackProgress {
+    total: Option<usize>,
+    done: usize,
+    percent: usize,
+}
+
+impl PackProgress {
+    /// Take the next chunk of the pack, returns a message when there is news to report.
+    fn update(&mut self, chunk: &[u8]) -> Option<String> {
+        let Some(total) = self.total else {
+            if chunk.len() < 12 || !chunk.starts_with(b"PACK") {
+                return None;
+            }
+            let total = u32::from_be_bytes(chunk[8..12].try_into().unwrap()) as usize;
+            self.total = Some(total);
+            return Some(format!("Counting objects: {}, done.\n", total));
+        };
+        if self.done >= total {
+            return None;
+        }
+        self.done += 1;
+        if self.done == total {
+            return Some(format!(
+                "Compressing objects: 100% ({}/{}), done.\n",
+                total, total
+            ));
+        }
+        let percent = self.done * 100 / total;
+        if self.done > 1 && percent == self.percent {
+            return None;
+        }
+        self.percent = percent;
+        Some(format!(
+            "Compressing objects: {:>3}% ({}/{})\r",
+            percent, self.done, total
+        ))
+    }
+}
+
 pub fn add_pkt_line_string(pkt_line_stream: &mut BytesMut, buf_str: String) {
     let buf_str_length = buf_str.len() + 4;
     pkt_line_stream.put(Bytes::from(format!("{buf_str_length:04x}")));
@@ -412,7 +598,10 @@ pub mod test {
     use callisto::db_enums::RefType;
 
     use crate::protocol::import_refs::{CommandType, RefCommand};
-    use crate::protocol::smart::{add_pkt_line_string, read_pkt_line, read_until_white_space};
+    use crate::protocol::smart::{
+        add_pkt_line_string, read_pkt_line, read_until_white_space, PackProgress,
+    };
+    use crate::protocol::ZERO_ID;
     use crate::protocol::{Capability, SmartProtocol};
 
     #[test]
@@ -491,4 +680,77 @@ pub mod test {
             vec![Capability::ReportStatusv2, Capability::SideBand64k]
         );
     }
+
+    #[test]
+    pub fn test_parse_push_options() {
+        let mut mock = SmartProtocol::mock();
+        let mut bytes = BytesMut::new();
+        add_pkt_line_string(
+            &mut bytes,
+            format!(
+                "{} {} refs/heads/main\0report-status push-options\n",
+                ZERO_ID, ZERO_ID
+            ),
+        );
+        bytes.extend_from_slice(b"0000");
+        add_pkt_line_string(&mut bytes, "title=My change\n".to_owned());
+        add_pkt_line_string(&mut bytes, "reviewer=alice\n".to_owned());
+        bytes.extend_from_slice(b"0000");
+        mock.git_receive_pack_protocol(bytes.freeze());
+
+        assert_eq!(mock.command_list.len(), 1);
+        assert_eq!(mock.push_options.title.as_deref(), Some("My change"));
+        assert_eq!(mock.push_options.reviewers, vec!["alice"]);
+    }
+
+    #[test]
+    pub fn test_parse_push_cert() {
+        let mut mock = SmartProtocol::mock();
+        let mut bytes = BytesMut::new();
+        add_pkt_line_string(&mut bytes, "push-cert\0report-status\n".to_owned());
+        for line in [
+            "certificate version 0.1\n".to_owned(),
+            "pusher Mega <mega@example.com> 1700000000 +0800\n".to_owned(),
+            "nonce 1700000000-abc\n".to_owned(),
+            "\n".to_owned(),
+            format!("{} {} refs/heads/main\n", ZERO_ID, ZERO_ID),
+            "-----BEGIN SSH SIGNATURE-----\n".to_owned(),
+            "-----END SSH SIGNATURE-----\n".to_owned(),
+            "push-cert-end\n".to_owned(),
+        ] {
+            add_pkt_line_string(&mut bytes, line);
+        }
+        bytes.extend_from_slice(b"0000");
+        mock.git_receive_pack_protocol(bytes.freeze());
+
+        assert_eq!(mock.capabilities, vec![Capability::ReportStatus]);
+        assert_eq!(mock.command_list.len(), 1);
+        assert_eq!(mock.command_list[0].ref_name, "refs/heads/main");
+        assert_eq!(mock.push_cert.unwrap().nonce, "1700000000-abc");
+    }
+
+    #[test]
+    pub fn test_pack_progress() {
+        let mut progress = PackProgress::default();
+        assert_eq!(progress.update(b"not a pack"), None);
+        let header = [b'P', b'A', b'C', b'K', 0, 0, 0, 2, 0, 0, 0, 3];
+        assert_eq!(
+            progress.update(&header).as_deref(),
+            Some("Counting objects: 3, done.\n")
+        );
+        assert_eq!(
+            progress.update(b"obj").as_deref(),
+            Some("Compressing objects:  33% (1/3)\r")
+        );
+        assert_eq!(
+            progress.update(b"obj").as_deref(),
+            Some("Compressing objects:  66% (2/3)\r")
+        );
+        assert_eq!(
+            progress.update(b"obj").as_deref(),
+            Some("Compressing objects: 100% (3/3), done.\n")
+        );
+        // the checksum
+        assert_eq!(progress.update(&[0; 20]), None);
+    }
 }
@@ -18,6 +18,18 @@ pub struct Config {
     pub pack: PackConfig,
     pub authentication: AuthConfig,
     pub lfs: LFSConfig,
+    #[serde(default)]
+    pub signing: SigningConfig,
+    #[serde(default)]
+    pub gc: GcConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub notification: NotificationConfig,
     // Not used in mega app
     #[serde(default)]
     pub oauth: Option<OauthConfig>,
@@ -194,6 +206,38 @@ pub struct StorageConfig {
     pub obs_secret_key: String,
     pub obs_region: String,
     pub obs_endpoint: String,
+    /// bucket raw blobs are kept in when `raw_obj_storage_type` is "S3"
+    #[serde(default)]
+    pub obs_bucket: String,
+    /// where raw blobs larger than `big_obj_threshold` are kept, "DATABASE", "LOCAL", "S3"
+    /// or "PACK", which keeps every pushed object in packfiles
+    #[serde(default = "default_raw_obj_storage_type")]
+    pub raw_obj_storage_type: String,
+    /// directory of the "LOCAL" and "PACK" storages
+    #[serde(default = "default_raw_obj_local_path")]
+    pub raw_obj_local_path: PathBuf,
+    /// size in bytes above which a raw blob leaves the database
+    #[serde(default = "default_big_obj_threshold")]
+    pub big_obj_threshold: usize,
+    /// zlib compress the content of raw blobs kept in the database
+    #[serde(default = "default_compress_raw_obj")]
+    pub compress_raw_obj: bool,
+}
+
+fn default_raw_obj_storage_type() -> String {
+    String::from("DATABASE")
+}
+
+fn default_raw_obj_local_path() -> PathBuf {
+    PathBuf::from("/tmp/.mega/objects")
+}
+
+fn default_big_obj_threshold() -> usize {
+    1024 * 1024
+}
+
+fn default_compress_raw_obj() -> bool {
+    true
 }
 
 impl Default for StorageConfig {
@@ -203,6 +247,11 @@ impl Default for StorageConfig {
             obs_secret_key: String::new(),
             obs_region: String::from("cn-east-3"),
             obs_endpoint: String::from("https://obs.cn-east-3.myhuaweicloud.com"),
+            obs_bucket: String::new(),
+            raw_obj_storage_type: default_raw_obj_storage_type(),
+            raw_obj_local_path: default_raw_obj_local_path(),
+            big_obj_threshold: default_big_obj_threshold(),
+            compress_raw_obj: default_compress_raw_obj(),
         }
     }
 }
@@ -212,6 +261,141 @@ pub struct MonoConfig {
     pub import_dir: PathBuf,
     pub admin: String,
     pub root_dirs: Vec<String>,
+    /// approvals needed before a MR can be merged, MRs matching no rule need none
+    #[serde(default)]
+    pub approval_rules: Vec<ApprovalRule>,
+    /// checks that must pass on the head of a MR before it can be merged
+    #[serde(default)]
+    pub check_rules: Vec<CheckRule>,
+    /// commands or built-in policies run when refs of a directory are pushed
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+    /// most bytes of files a directory may hold, pushes going over are rejected
+    #[serde(default)]
+    pub quotas: Vec<QuotaRule>,
+    /// conventions commit messages pushed or merged must follow
+    #[serde(default)]
+    pub commit_message_rules: Vec<CommitMessageRule>,
+    /// largest file whose content the blob endpoints return in JSON, bigger ones get a
+    /// link to the raw content instead
+    #[serde(default = "default_max_inline_blob_size")]
+    pub max_inline_blob_size: usize,
+}
+
+fn default_max_inline_blob_size() -> usize {
+    1024 * 1024
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApprovalRule {
+    /// monorepo directory the rule applies to, including everything below it
+    pub path: String,
+    pub required: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheckRule {
+    /// monorepo directory the rule applies to, including everything below it
+    pub path: String,
+    /// check run names, or commit status contexts
+    pub names: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookStage {
+    /// before a ref is updated, a failing hook rejects the update
+    PreReceive,
+    /// after a ref is updated, run in the background
+    PostReceive,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HookConfig {
+    pub stage: HookStage,
+    /// monorepo directory the hook applies to, including everything below it
+    pub path: String,
+    /// program and arguments, it reads the update as JSON from stdin
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// name of a policy built into mega, used instead of `command`
+    #[serde(default)]
+    pub builtin: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuotaRule {
+    /// monorepo directory the quota applies to, including everything below it
+    pub path: String,
+    /// in bytes
+    pub max_size: u64,
+}
+
+/// Conventions of commit messages, commits not following them are refused on push and
+/// merge. Merge commits are left out, their message is written by git.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CommitMessageRule {
+    /// monorepo directory the rule applies to, including everything below it
+    pub path: String,
+    /// regex the subject line must match, e.g. for conventional commits
+    #[serde(default)]
+    pub subject_pattern: Option<String>,
+    #[serde(default)]
+    pub max_subject_length: Option<usize>,
+    /// regex matched anywhere in the message, e.g. `#\d+` to require an issue reference
+    #[serde(default)]
+    pub issue_pattern: Option<String>,
+}
+
+impl MonoConfig {
+    /// Approvals required for a MR on `path`, taken from the most specific matching rule.
+    pub fn required_approvals(&self, path: &str) -> usize {
+        most_specific(&self.approval_rules, path, |rule| &rule.path).map_or(0, |rule| rule.required)
+    }
+
+    /// Commit message rule for `path`, the most specific matching one.
+    pub fn commit_message_rule(&self, path: &str) -> Option<&CommitMessageRule> {
+        most_specific(&self.commit_message_rules, path, |rule| &rule.path)
+    }
+
+    /// Checks required for a MR on `path`, taken from the most specific matching rule.
+    pub fn required_checks(&self, path: &str) -> &[String] {
+        most_specific(&self.check_rules, path, |rule| &rule.path).map_or(&[], |rule| &rule.names)
+    }
+
+    /// Hooks of `stage` for pushes to `path`, every matching hook runs, in config order.
+    pub fn hooks<'a>(
+        &'a self,
+        stage: HookStage,
+        path: &'a str,
+    ) -> impl Iterator<Item = &'a HookConfig> + 'a {
+        self.hooks
+            .iter()
+            .filter(move |hook| hook.stage == stage && is_under(path, &hook.path))
+    }
+
+    /// Quota covering `path`, the most specific matching rule.
+    pub fn quota(&self, path: &str) -> Option<&QuotaRule> {
+        most_specific(&self.quotas, path, |rule| &rule.path)
+    }
+}
+
+/// Whether `path` is `dir` or below it.
+fn is_under(path: &str, dir: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    let dir = dir.trim_end_matches('/');
+    path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The rule with the longest path that is `path` or one of its parent directories.
+pub fn most_specific<'a, T>(rules: &'a [T], path: &str, key: impl Fn(&T) -> &str) -> Option<&'a T> {
+    rules
+        .iter()
+        .filter(|rule| is_under(path, key(rule)))
+        .max_by_key(|rule| key(rule).trim_end_matches('/').len())
 }
 
 impl Default for MonoConfig {
@@ -225,6 +409,12 @@ impl Default for MonoConfig {
                 "doc".to_string(),
                 "release".to_string(),
             ],
+            approval_rules: vec![],
+            check_rules: vec![],
+            hooks: vec![],
+            quotas: vec![],
+            commit_message_rules: vec![],
+            max_inline_blob_size: default_max_inline_blob_size(),
         }
     }
 }
@@ -254,7 +444,56 @@ pub struct PackConfig {
     pub pack_decode_cache_path: PathBuf,
     pub clean_cache_after_decode: bool,
     pub channel_message_size: usize,
+    /// objects of a push decoded but not stored yet, decoding waits beyond them
+    #[serde(default = "default_decode_window")]
+    pub decode_window: usize,
     pub maximum_pack_size: usize,
+    /// number of recent objects a new object is compared with to be stored as a delta,
+    /// 0 sends all objects whole
+    #[serde(default = "default_delta_window")]
+    pub delta_window: usize,
+    /// longest chain of deltas behind an object
+    #[serde(default = "default_delta_depth")]
+    pub delta_depth: usize,
+    /// where the chunks of resumable receive-pack uploads are kept until the push completes
+    #[serde(default = "default_upload_session_path")]
+    pub upload_session_path: PathBuf,
+    /// seconds an upload session is kept without receiving a chunk
+    #[serde(default = "default_upload_session_ttl")]
+    pub upload_session_ttl: u64,
+    /// keep the packs of full clones to send them again while the head doesn't move
+    #[serde(default = "default_enable_pack_cache")]
+    pub enable_pack_cache: bool,
+    #[serde(default = "default_pack_cache_path")]
+    pub pack_cache_path: PathBuf,
+}
+
+fn default_decode_window() -> usize {
+    10_000
+}
+
+fn default_delta_window() -> usize {
+    10
+}
+
+fn default_delta_depth() -> usize {
+    50
+}
+
+fn default_upload_session_path() -> PathBuf {
+    PathBuf::from("/tmp/.mega/uploads")
+}
+
+fn default_upload_session_ttl() -> u64 {
+    86400
+}
+
+fn default_enable_pack_cache() -> bool {
+    true
+}
+
+fn default_pack_cache_path() -> PathBuf {
+    PathBuf::from("/tmp/.mega/pack-cache")
 }
 
 impl Default for PackConfig {
@@ -264,7 +503,14 @@ impl Default for PackConfig {
             pack_decode_cache_path: PathBuf::from("/tmp/.mega/cache"),
             clean_cache_after_decode: true,
             channel_message_size: 1_000_000,
+            decode_window: default_decode_window(),
             maximum_pack_size: 4,
+            delta_window: default_delta_window(),
+            delta_depth: default_delta_depth(),
+            upload_session_path: default_upload_session_path(),
+            upload_session_ttl: default_upload_session_ttl(),
+            enable_pack_cache: default_enable_pack_cache(),
+            pack_cache_path: default_pack_cache_path(),
         }
     }
 }
@@ -275,6 +521,14 @@ pub struct LFSConfig {
     pub lfs_obj_local_path: PathBuf,
     pub enable_split: bool,
     pub split_size: usize,
+    /// blobs larger than this are rejected on push to the monorepo and must go through LFS,
+    /// 0 disables the limit
+    #[serde(default = "default_max_blob_size")]
+    pub max_blob_size: usize,
+}
+
+fn default_max_blob_size() -> usize {
+    100 * 1024 * 1024
 }
 
 impl Default for LFSConfig {
@@ -284,6 +538,331 @@ impl Default for LFSConfig {
             lfs_obj_local_path: PathBuf::from("/tmp/.mega/lfs"),
             enable_split: true,
             split_size: 20 * 1024 * 1024, // 20MB
+            max_blob_size: default_max_blob_size(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SigningConfig {
+    /// program verifying gpg signatures, called like git calls `gpg.program`
+    #[serde(default = "default_gpg_program")]
+    pub gpg_program: String,
+    /// GNUPGHOME with the public keys gpg signatures are checked against, the default
+    /// keyring of the server user when empty
+    #[serde(default)]
+    pub gpg_home: String,
+    /// program verifying ssh signatures against the ssh keys users registered
+    #[serde(default = "default_ssh_keygen_program")]
+    pub ssh_keygen_program: String,
+    /// secret the nonces of push certificates are made from, signed pushes are not
+    /// offered when empty
+    #[serde(default)]
+    pub cert_nonce_seed: String,
+    /// seconds a push certificate nonce stays valid
+    #[serde(default = "default_cert_nonce_slop")]
+    pub cert_nonce_slop: i64,
+}
+
+fn default_gpg_program() -> String {
+    "gpg".to_owned()
+}
+
+fn default_ssh_keygen_program() -> String {
+    "ssh-keygen".to_owned()
+}
+
+fn default_cert_nonce_slop() -> i64 {
+    300
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            gpg_program: default_gpg_program(),
+            gpg_home: String::new(),
+            ssh_keygen_program: default_ssh_keygen_program(),
+            cert_nonce_seed: String::new(),
+            cert_nonce_slop: default_cert_nonce_slop(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GcConfig {
+    /// seconds between scheduled collections of unreachable objects, 0 only runs them
+    /// when an admin asks for one
+    #[serde(default = "default_gc_interval")]
+    pub interval: u64,
+    /// seconds an object is kept after it was stored even if nothing reaches it, a push
+    /// stores its objects before the ref moves to them
+    #[serde(default = "default_gc_grace_period")]
+    pub grace_period: u64,
+}
+
+fn default_gc_interval() -> u64 {
+    86400
+}
+
+fn default_gc_grace_period() -> u64 {
+    7 * 86400
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_gc_interval(),
+            grace_period: default_gc_grace_period(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheConfig {
+    /// bytes of monorepo refs, trees and commits kept in memory, 0 disables the cache
+    #[serde(default = "default_cache_mem_size")]
+    pub mem_size: usize,
+    /// `redis://` url of a Redis shared by all instances, used instead of the in-process
+    /// cache when set
+    #[serde(default)]
+    pub redis_url: String,
+    /// seconds an entry is kept in Redis
+    #[serde(default = "default_cache_redis_ttl")]
+    pub redis_ttl: u64,
+}
+
+fn default_cache_mem_size() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_cache_redis_ttl() -> u64 {
+    3600
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            mem_size: default_cache_mem_size(),
+            redis_url: String::new(),
+            redis_ttl: default_cache_redis_ttl(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueConfig {
+    /// messages the event queue holds before senders have to wait or see it full
+    #[serde(default = "default_queue_capacity")]
+    pub capacity: usize,
+    /// carries events to the instances processing them
+    #[serde(default)]
+    pub broker: QueueBroker,
+    /// `redis://` url of the Redis holding the stream, for the `redis` broker
+    #[serde(default)]
+    pub redis_url: String,
+    /// Redis stream the events are added to, shared by all instances
+    #[serde(default = "default_queue_stream")]
+    pub stream: String,
+    /// name of this instance among the consumers of the stream, the host name when empty,
+    /// must stay the same across restarts to pick up what it left unprocessed
+    #[serde(default)]
+    pub consumer: String,
+    /// seconds an idempotency key keeps out events with the same key
+    #[serde(default = "default_queue_idempotency_ttl")]
+    pub idempotency_ttl: u64,
+    /// seconds a processed event is kept in the database, 0 keeps them
+    #[serde(default = "default_queue_message_retention")]
+    pub message_retention: u64,
+    /// events sent on a schedule
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueueBroker {
+    /// events are processed by the instance sending them
+    #[default]
+    Local,
+    /// events go through a Redis stream, any instance may process them
+    Redis,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobConfig {
+    /// unique name, admins run the job by it
+    pub name: String,
+    /// cron expression in UTC: minute, hour, day of month, month and day of week
+    pub schedule: String,
+    /// topic the job publishes to, the handlers subscribed to it do the work
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// name of a job built into mega, used instead of `topic`
+    #[serde(default)]
+    pub builtin: Option<String>,
+}
+
+fn default_queue_capacity() -> usize {
+    10000
+}
+
+fn default_queue_idempotency_ttl() -> u64 {
+    86400
+}
+
+fn default_queue_message_retention() -> u64 {
+    7 * 86400
+}
+
+fn default_queue_stream() -> String {
+    String::from("mega:events")
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_queue_capacity(),
+            broker: QueueBroker::default(),
+            redis_url: String::new(),
+            stream: default_queue_stream(),
+            consumer: String::new(),
+            idempotency_ttl: default_queue_idempotency_ttl(),
+            message_retention: default_queue_message_retention(),
+            jobs: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// take the client address from `X-Forwarded-For`, only behind a proxy setting it
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    /// proxies in front of mega appending to `X-Forwarded-For`, the client address is
+    /// the entry this many from the right, entries further left are made up by clients
+    #[serde(default = "default_forwarded_hops")]
+    pub forwarded_hops: usize,
+    /// JSON API requests of a client address without an access token
+    #[serde(default = "default_rate_limit_api")]
+    pub api: RateLimit,
+    /// git clone, fetch and push requests of a client address without an access token
+    #[serde(default = "default_rate_limit_pack")]
+    pub pack: RateLimit,
+    /// JSON API requests of an access token
+    #[serde(default = "default_rate_limit_token_api")]
+    pub token_api: RateLimit,
+    /// git clone, fetch and push requests of an access token
+    #[serde(default = "default_rate_limit_token_pack")]
+    pub token_pack: RateLimit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// requests a minute, 0 for no limit
+    pub per_minute: u32,
+    /// requests that can be made at once before the rate applies
+    pub burst: u32,
+}
+
+fn default_forwarded_hops() -> usize {
+    1
+}
+
+fn default_rate_limit_api() -> RateLimit {
+    RateLimit {
+        per_minute: 600,
+        burst: 100,
+    }
+}
+
+fn default_rate_limit_pack() -> RateLimit {
+    RateLimit {
+        per_minute: 30,
+        burst: 10,
+    }
+}
+
+fn default_rate_limit_token_api() -> RateLimit {
+    RateLimit {
+        per_minute: 3000,
+        burst: 300,
+    }
+}
+
+fn default_rate_limit_token_pack() -> RateLimit {
+    RateLimit {
+        per_minute: 300,
+        burst: 50,
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            trust_forwarded_for: false,
+            forwarded_hops: default_forwarded_hops(),
+            api: default_rate_limit_api(),
+            pack: default_rate_limit_pack(),
+            token_api: default_rate_limit_token_api(),
+            token_pack: default_rate_limit_token_pack(),
+        }
+    }
+}
+
+/// Notifications are always kept for the UI, sending them by email as well is optional.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationConfig {
+    #[serde(default)]
+    pub email: bool,
+    #[serde(default)]
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    /// no authentication when empty
+    #[serde(default)]
+    pub smtp_username: String,
+    #[serde(default)]
+    pub smtp_password: String,
+    /// upgrade the connection with STARTTLS, plain text otherwise
+    #[serde(default = "default_smtp_starttls")]
+    pub smtp_starttls: bool,
+    #[serde(default = "default_email_from")]
+    pub from: String,
+    /// where the UI is served, the links in emails point there
+    #[serde(default = "default_notification_ui_url")]
+    pub ui_url: String,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_starttls() -> bool {
+    true
+}
+
+fn default_email_from() -> String {
+    "Mega <mega@localhost>".to_owned()
+}
+
+fn default_notification_ui_url() -> String {
+    "http://localhost:3000".to_owned()
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            email: false,
+            smtp_host: String::new(),
+            smtp_port: default_smtp_port(),
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            smtp_starttls: default_smtp_starttls(),
+            from: default_email_from(),
+            ui_url: default_notification_ui_url(),
         }
     }
 }
@@ -17,6 +17,8 @@ pub struct MegaError {
 }
 
 impl MegaError {
+    const REF_CONFLICT: i32 = 409;
+
     pub fn new(error: anyhow::Error, code: i32) -> MegaError {
         MegaError {
             error: Some(error),
@@ -41,6 +43,18 @@ impl MegaError {
             code: 0,
         }
     }
+
+    /// A ref was moved by another write between reading and updating it.
+    pub fn ref_conflict(msg: &str) -> MegaError {
+        MegaError {
+            error: anyhow::anyhow!("{}", msg).into(),
+            code: Self::REF_CONFLICT,
+        }
+    }
+
+    pub fn is_ref_conflict(&self) -> bool {
+        self.code == Self::REF_CONFLICT
+    }
 }
 
 impl std::fmt::Display for MegaError {
@@ -49,6 +63,8 @@ impl std::fmt::Display for MegaError {
     }
 }
 
+impl std::error::Error for MegaError {}
+
 impl From<anyhow::Error> for MegaError {
     fn from(err: anyhow::Error) -> MegaError {
         MegaError::new(err, 101)
@@ -80,12 +96,30 @@ pub enum GitLFSError {
     GeneralError(String),
 }
 
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MonoPathError {
+    #[error("Path must not contain `..`: {0}")]
+    ParentDir(String),
+    #[error("Path must not contain backslash: {0}")]
+    Backslash(String),
+    #[error("Path contains control characters: {0}")]
+    ControlChar(String),
+    #[error("Reserved name in path: {0}")]
+    Reserved(String),
+    #[error("Path segment too long: {0}")]
+    TooLong(String),
+    #[error("Invalid file name: {0:?}")]
+    InvalidName(String),
+}
+
 #[derive(Debug, Error)]
 pub enum ProtocolError {
     #[error("{0}")]
     IO(#[from] std::io::Error),
     #[error("Authentication failed: {0}")]
     Deny(String),
+    #[error("Access denied: {0}")]
+    Forbidden(String),
     #[error("Repository not found: {0}")]
     NotFound(String),
     #[error("PackFile too large: {0}")]
@@ -96,6 +130,12 @@ pub enum ProtocolError {
     Disabled,
 }
 
+impl From<MonoPathError> for ProtocolError {
+    fn from(err: MonoPathError) -> Self {
+        ProtocolError::InvalidInput(err.to_string())
+    }
+}
+
 impl IntoResponse for ProtocolError {
     fn into_response(self) -> Response {
         let (status, message) = match self {
@@ -103,6 +143,7 @@ impl IntoResponse for ProtocolError {
                 // This error is caused by bad user input so don't log it
                 (StatusCode::UNAUTHORIZED, err)
             }
+            ProtocolError::Forbidden(err) => (StatusCode::FORBIDDEN, err),
             ProtocolError::TooLarge(err) => {
                 (StatusCode::PAYLOAD_TOO_LARGE, err)
             }
@@ -2,4 +2,5 @@ pub mod config;
 pub mod enums;
 pub mod errors;
 pub mod model;
+pub mod path;
 pub mod utils;
@@ -1,5 +1,6 @@
 use clap::Args;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Args, Clone, Debug)]
 pub struct CommonOptions {
@@ -25,7 +26,14 @@ pub struct InfoRefsParams {
     pub refspec: Option<String>,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+/// Query of a chunk sent to a resumable receive-pack upload.
+#[derive(Deserialize, Debug)]
+pub struct UploadParams {
+    /// where the chunk starts in the request body
+    pub offset: Option<u64>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct CommonResult<T> {
     pub req_result: bool,
     pub data: Option<T>,
@@ -49,30 +57,179 @@ impl<T> CommonResult<T> {
     }
 }
 
-#[derive(Deserialize)]
+/// What an access token can be used for, a token without scopes can't do anything.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenScope {
+    #[serde(rename = "repo:read")]
+    RepoRead,
+    #[serde(rename = "repo:write")]
+    RepoWrite,
+    #[serde(rename = "mr:merge")]
+    MrMerge,
+}
+
+impl TokenScope {
+    /// Whether a token with the `granted` scopes can be used for `self`, write access
+    /// includes read access.
+    pub fn granted_by(self, granted: &[TokenScope]) -> bool {
+        granted.contains(&self)
+            || (self == TokenScope::RepoRead && granted.contains(&TokenScope::RepoWrite))
+    }
+}
+
+/// Largest page size a client may ask for.
+pub const MAX_PER_PAGE: u64 = 100;
+
+/// Page number based pagination, used both in json bodies and as `?page=&per_page=` query.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Pagination {
+    #[serde(default = "default_page")]
     pub page: u64,
+    #[serde(default = "default_per_page")]
     pub per_page: u64,
 }
 
 impl Default for Pagination {
     fn default() -> Self {
         Pagination {
-            page: 1,
-            per_page: 20,
+            page: default_page(),
+            per_page: default_per_page(),
         }
     }
 }
 
-#[derive(Deserialize)]
+impl Pagination {
+    /// Clamp out of range values sent by clients, pages start at 1.
+    pub fn normalized(self) -> Self {
+        Pagination {
+            page: self.page.max(1),
+            per_page: self.per_page.clamp(1, MAX_PER_PAGE),
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        (self.page.max(1) - 1) * self.per_page
+    }
+}
+
+fn default_page() -> u64 {
+    1
+}
+
+fn default_per_page() -> u64 {
+    20
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct PageParams<T> {
+    #[serde(default)]
     pub pagination: Pagination,
     pub additional: T,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
-
-pub struct CommonPage<T> {
+/// A page of a list whose total size is known.
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
     pub total: u64,
+    pub page: u64,
+    pub per_page: u64,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: u64, pagination: &Pagination) -> Self {
+        Page {
+            items,
+            total,
+            page: pagination.page,
+            per_page: pagination.per_page,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            page: self.page,
+            per_page: self.per_page,
+        }
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.page * self.per_page < self.total
+    }
+}
+
+/// Cursor based pagination for lists that are walked rather than counted,
+/// e.g. history. `cursor` is opaque to clients.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct CursorParams {
+    pub cursor: Option<String>,
+    #[serde(default = "default_per_page")]
+    pub limit: u64,
+}
+
+impl CursorParams {
+    pub fn limit(&self) -> u64 {
+        self.limit.clamp(1, MAX_PER_PAGE)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CursorPage<T> {
     pub items: Vec<T>,
+    /// pass back as `cursor` to get the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> CursorPage<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> CursorPage<U> {
+        CursorPage {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pagination_normalized() {
+        let page = Pagination {
+            page: 0,
+            per_page: 1000,
+        }
+        .normalized();
+        assert_eq!(page.page, 1);
+        assert_eq!(page.per_page, MAX_PER_PAGE);
+        assert_eq!(page.offset(), 0);
+
+        let page: Pagination = serde_json::from_str(r#"{"page": 3}"#).unwrap();
+        assert_eq!(page.per_page, 20);
+        assert_eq!(page.offset(), 40);
+    }
+
+    #[test]
+    fn test_page_has_next() {
+        let pagination = Pagination {
+            page: 2,
+            per_page: 10,
+        };
+        assert!(Page::new(vec![0; 10], 25, &pagination).has_next());
+        assert!(!Page::new(vec![0; 5], 20, &pagination).has_next());
+    }
+
+    #[test]
+    fn test_token_scope() {
+        let scopes: Vec<TokenScope> =
+            serde_json::from_str(r#"["repo:write", "mr:merge"]"#).unwrap();
+        assert!(TokenScope::RepoRead.granted_by(&scopes));
+        assert!(TokenScope::MrMerge.granted_by(&scopes));
+        assert!(!TokenScope::RepoWrite.granted_by(&[TokenScope::RepoRead]));
+        assert!(!TokenScope::RepoRead.granted_by(&[]));
+        assert!(serde_json::from_str::<TokenScope>(r#""admin""#).is_err());
+    }
 }
@@ -0,0 +1,212 @@
+//! Canonical form of paths inside the monorepo.
+//!
+//! Client supplied paths are parsed into a [`MonoPath`] before they are used to walk or
+//! build trees, so that `/a//b/`, `a/./b` and the decomposed and composed unicode forms
+//! of the same name all address the same tree entry.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::errors::MonoPathError;
+
+/// Git and most filesystems limit a single path component to 255 bytes.
+const MAX_SEGMENT_LEN: usize = 255;
+
+/// An absolute, normalized monorepo path, always starting with `/` and never ending with one
+/// unless it is the root.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MonoPath(String);
+
+impl MonoPath {
+    pub fn root() -> Self {
+        MonoPath("/".to_owned())
+    }
+
+    /// Parse a raw client path. Empty segments and `.` are dropped, `..`, backslashes,
+    /// control characters and `.git` segments are rejected, names are NFC normalized.
+    pub fn parse(raw: &str) -> Result<Self, MonoPathError> {
+        if raw.contains('\\') {
+            return Err(MonoPathError::Backslash(raw.to_owned()));
+        }
+        let mut segments = Vec::new();
+        for segment in raw.split('/') {
+            match segment {
+                "" | "." => continue,
+                ".." => return Err(MonoPathError::ParentDir(raw.to_owned())),
+                _ => segments.push(normalize_name(segment)?),
+            }
+        }
+        Ok(MonoPath(format!("/{}", segments.join("/"))))
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0 == "/"
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn as_path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+
+    pub fn to_path_buf(&self) -> PathBuf {
+        PathBuf::from(&self.0)
+    }
+
+    /// Segments of the path, empty for the root.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/').filter(|x| !x.is_empty())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.0.rsplit('/').next().filter(|x| !x.is_empty())
+    }
+
+    pub fn parent(&self) -> Option<MonoPath> {
+        if self.is_root() {
+            return None;
+        }
+        let idx = self.0.rfind('/').unwrap();
+        Some(MonoPath(if idx == 0 {
+            "/".to_owned()
+        } else {
+            self.0[..idx].to_owned()
+        }))
+    }
+
+    /// Append a single validated name.
+    pub fn join(&self, name: &str) -> Result<MonoPath, MonoPathError> {
+        let name = normalize_name(name)?;
+        Ok(if self.is_root() {
+            MonoPath(format!("/{}", name))
+        } else {
+            MonoPath(format!("{}/{}", self.0, name))
+        })
+    }
+}
+
+/// Validate a single file or directory name and return its NFC form.
+pub fn normalize_name(name: &str) -> Result<String, MonoPathError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(MonoPathError::InvalidName(name.to_owned()));
+    }
+    if name.contains('\\') {
+        return Err(MonoPathError::Backslash(name.to_owned()));
+    }
+    if name.chars().any(|c| c.is_control()) {
+        return Err(MonoPathError::ControlChar(name.escape_debug().to_string()));
+    }
+    if name.eq_ignore_ascii_case(".git") {
+        return Err(MonoPathError::Reserved(name.to_owned()));
+    }
+    let name: String = name.nfc().collect();
+    if name.len() > MAX_SEGMENT_LEN {
+        return Err(MonoPathError::TooLong(name));
+    }
+    Ok(name)
+}
+
+impl fmt::Display for MonoPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<Path> for MonoPath {
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl From<MonoPath> for PathBuf {
+    fn from(value: MonoPath) -> Self {
+        PathBuf::from(value.0)
+    }
+}
+
+impl TryFrom<&str> for MonoPath {
+    type Error = MonoPathError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        MonoPath::parse(value)
+    }
+}
+
+impl TryFrom<&Path> for MonoPath {
+    type Error = MonoPathError;
+
+    fn try_from(value: &Path) -> Result<Self, Self::Error> {
+        MonoPath::parse(&value.to_string_lossy())
+    }
+}
+
+impl Serialize for MonoPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for MonoPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        MonoPath::parse(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_normalizes() {
+        assert_eq!(MonoPath::parse("").unwrap(), MonoPath::root());
+        assert_eq!(MonoPath::parse("/").unwrap(), MonoPath::root());
+        assert_eq!(MonoPath::parse("a//b/./c/").unwrap().as_str(), "/a/b/c");
+        // decomposed `é` is stored composed
+        let decomposed = MonoPath::parse("/cafe\u{301}").unwrap();
+        let composed = MonoPath::parse("/caf\u{e9}").unwrap();
+        assert_eq!(decomposed, composed);
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        assert!(matches!(
+            MonoPath::parse("/a/../b"),
+            Err(MonoPathError::ParentDir(_))
+        ));
+        assert!(matches!(
+            MonoPath::parse("/a\\b"),
+            Err(MonoPathError::Backslash(_))
+        ));
+        assert!(matches!(
+            MonoPath::parse("/a\nb"),
+            Err(MonoPathError::ControlChar(_))
+        ));
+        assert!(matches!(
+            MonoPath::parse("/repo/.GIT/config"),
+            Err(MonoPathError::Reserved(_))
+        ));
+        assert!(matches!(
+            MonoPath::parse(&format!("/{}", "x".repeat(256))),
+            Err(MonoPathError::TooLong(_))
+        ));
+    }
+
+    #[test]
+    fn test_parent_and_join() {
+        let path = MonoPath::parse("/project/src").unwrap();
+        assert_eq!(path.name(), Some("src"));
+        assert_eq!(path.parent().unwrap().as_str(), "/project");
+        assert_eq!(path.parent().unwrap().parent(), Some(MonoPath::root()));
+        assert_eq!(MonoPath::root().parent(), None);
+        assert_eq!(MonoPath::root().join("a").unwrap().as_str(), "/a");
+        assert_eq!(path.join("main.rs").unwrap().as_str(), "/project/src/main.rs");
+        assert!(path.join("a/b").is_err());
+        assert!(path.join("..").is_err());
+    }
+}
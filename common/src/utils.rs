@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 use idgenerator::IdInstance;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use regex::Regex;
@@ -23,6 +25,9 @@ pub fn generate_link() -> String {
 }
 
 pub const MEGA_BRANCH_NAME: &str = "refs/heads/main";
+/// Short name of `MEGA_BRANCH_NAME`, the branch every directory has.
+pub const MEGA_DEFAULT_BRANCH: &str = "main";
+const MAX_REF_NAME_LEN: usize = 255;
 
 pub fn generate_rich_text(content: &str) -> String {
     let json_str = r#"
@@ -42,8 +47,38 @@ pub fn generate_rich_text(content: &str) -> String {
     serde_json::to_string_pretty(&data).expect("Failed to serialize JSON")
 }
 
+/// MR refs live outside `refs/heads` so that they aren't taken for branches.
 pub fn mr_ref_name(mr_link: &str) -> String {
-    format!("refs/heads/{}", mr_link)
+    format!("refs/mr/{}", mr_link)
+}
+
+pub fn branch_ref_name(branch: &str) -> String {
+    format!("refs/heads/{}", branch)
+}
+
+/// Check a branch or tag name against the rules git applies to ref names.
+pub fn validate_ref_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_REF_NAME_LEN {
+        return Err(format!("name must be 1 to {} bytes long", MAX_REF_NAME_LEN));
+    }
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return Err(format!("{} contains a forbidden character", name));
+    }
+    if name == "@"
+        || name.contains("..")
+        || name.contains("@{")
+        || name.starts_with('-')
+        || name.ends_with('.')
+        || name
+            .split('/')
+            .any(|x| x.is_empty() || x.starts_with('.') || x.ends_with(".lock"))
+    {
+        return Err(format!("{} is not a valid ref name", name));
+    }
+    Ok(())
 }
 
 /// Format commit message with GPG signature<br>
@@ -125,10 +160,134 @@ pub fn check_conventional_commits_message(msg: &str) -> bool {
     false
 }
 
+/// Compare two names in natural order, so that `file2` sorts before `file10`.
+/// Letters are compared case-insensitively, the raw bytes break ties.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (x.peek().copied(), y.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c1), Some(c2)) if c1.is_ascii_digit() && c2.is_ascii_digit() => {
+                let n1: String = std::iter::from_fn(|| x.next_if(|c| c.is_ascii_digit())).collect();
+                let n2: String = std::iter::from_fn(|| y.next_if(|c| c.is_ascii_digit())).collect();
+                let (t1, t2) = (n1.trim_start_matches('0'), n2.trim_start_matches('0'));
+                let ord = t1.len().cmp(&t2.len()).then_with(|| t1.cmp(t2));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(c1), Some(c2)) => {
+                let ord = c1.to_lowercase().cmp(c2.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                x.next();
+                y.next();
+            }
+        }
+    }
+}
+
+/// Match `name` against a shell style glob, `*` matches any run of characters,
+/// `?` matches exactly one, and `[abc]`/`[a-z]` match one character of a set.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the name index it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let step = match pattern.get(p).copied() {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_class(&pattern[p..], name[n]),
+            Some(c) if c == name[n] => Some(1),
+            _ => None,
+        };
+        match (step, star) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((sp, sn))) => {
+                p = sp + 1;
+                n = sn + 1;
+                star = Some((sp, sn + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns the pattern length of the class when `c` matches it.
+fn match_class(class: &[char], c: char) -> Option<usize> {
+    let end = class.iter().skip(1).position(|x| *x == ']')? + 1;
+    let set = &class[1..end];
+    let (negate, set) = match set.first().copied() {
+        Some('!') | Some('^') => (true, &set[1..]),
+        _ => (false, set),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            found |= set[i] <= c && c <= set[i + 2];
+            i += 3;
+        } else {
+            found |= set[i] == c;
+            i += 1;
+        }
+    }
+    (found != negate).then_some(end + 1)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_validate_ref_name() {
+        for name in ["v1.0.0", "release/2026-10", "foo_bar-1"] {
+            assert!(validate_ref_name(name).is_ok(), "{}", name);
+        }
+        for name in [
+            "", "v1 0", "v1..0", "v1.", "-v1", ".v1", "a//b", "/v1", "v1/", "v1.lock", "a^b",
+            "a:b", "a@{b", "@", "a\\b",
+        ] {
+            assert!(validate_ref_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["file10", "File2", "file1", "a", "file02", "b1c"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec!["a", "b1c", "file1", "File2", "file02", "file10"]);
+        assert_eq!(natural_cmp("x", "x"), Ordering::Equal);
+        assert_eq!(natural_cmp("x", "x1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "main.rs.bak"));
+        assert!(glob_match("test_?.py", "test_a.py"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+        assert!(glob_match("[Cc]argo.*", "cargo.toml"));
+        assert!(glob_match("v[0-9]", "v7"));
+        assert!(!glob_match("v[!0-9]", "v7"));
+        assert!(!glob_match("[abc", "a"));
+    }
+
     #[test]
     fn test_check_conventional_commits() {
         // successfull cases
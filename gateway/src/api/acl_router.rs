@@ -0,0 +1,261 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+
+use callisto::{db_enums::AclPrincipal, mega_team};
+use common::{model::CommonResult, path::MonoPath, utils::generate_id};
+use jupiter::storage::acl_storage::AclStorage;
+
+use crate::api::model::{
+    PathAclItem, PathAclReq, TeamItem, TeamMemberItem, TeamMemberReq, TeamReq,
+};
+use crate::api::MegaApiServiceState;
+
+/// Admin endpoints managing who can read or write below a path, enforced on the smart
+/// HTTP routes and the monorepo mutation APIs, and the teams grants are made to.
+pub fn routers() -> Router<MegaApiServiceState> {
+    Router::new()
+        .route("/acls", get(list_acls).post(create_acl))
+        .route("/acls/{id}/delete", post(delete_acl))
+        .route("/teams", get(list_teams).post(create_team))
+        .route("/teams/{team}", get(get_team))
+        .route("/teams/{team}/update", post(update_team))
+        .route("/teams/{team}/delete", post(delete_team))
+        .route("/teams/{team}/members", get(list_members).post(add_member))
+        .route(
+            "/teams/{team}/members/{username}/delete",
+            post(remove_member),
+        )
+}
+
+async fn list_acls(
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<Vec<PathAclItem>>>, (StatusCode, String)> {
+    let acls = state
+        .inner
+        .context
+        .acl_stg()
+        .get_acls()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(
+        acls.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+async fn create_acl(
+    state: State<MegaApiServiceState>,
+    Json(mut json): Json<PathAclReq>,
+) -> Result<Json<CommonResult<PathAclItem>>, (StatusCode, String)> {
+    json.path = MonoPath::parse(&json.path)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?
+        .to_string();
+    if json.principal.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, String::from("principal is empty")));
+    }
+    let stg = state.inner.context.acl_stg();
+    if json.principal_type == AclPrincipal::Team {
+        let team = stg
+            .get_team(&json.principal)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if team.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("team {} doesn't exist", json.principal),
+            ));
+        }
+    }
+    let existing = stg
+        .get_acl_by_principal(&json.path, json.principal_type, &json.principal)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if existing.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{} already has a grant on {}", json.principal, json.path),
+        ));
+    }
+    let acl = stg
+        .save_acl(json.into_model())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(acl.into()))))
+}
+
+async fn delete_acl(
+    Path(id): Path<i64>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    let stg = state.inner.context.acl_stg();
+    let acl = stg
+        .get_acl(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if acl.is_none() {
+        return Err((StatusCode::NOT_FOUND, String::from("grant not found")));
+    }
+    stg.delete_acl(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+async fn list_teams(
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<Vec<TeamItem>>>, (StatusCode, String)> {
+    let teams = state
+        .inner
+        .context
+        .acl_stg()
+        .get_teams()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(
+        teams.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+async fn create_team(
+    state: State<MegaApiServiceState>,
+    Json(json): Json<TeamReq>,
+) -> Result<Json<CommonResult<TeamItem>>, (StatusCode, String)> {
+    let name = json.name.trim();
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ':' || c == '/') {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            String::from("team names can't be empty or contain spaces, ':' or '/'"),
+        ));
+    }
+    let stg = state.inner.context.acl_stg();
+    let existing = stg
+        .get_team(name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if existing.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("team {} already exists", name),
+        ));
+    }
+    let now = chrono::Utc::now().naive_utc();
+    let team = stg
+        .save_team(mega_team::Model {
+            id: generate_id(),
+            name: name.to_owned(),
+            description: json.description,
+            created_at: now,
+            updated_at: now,
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(team.into()))))
+}
+
+/// A team with its members.
+async fn get_team(
+    Path(team): Path<String>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<TeamItem>>, (StatusCode, String)> {
+    let stg = state.inner.context.acl_stg();
+    let model = team_or_404(&stg, &team).await?;
+    let members = stg
+        .get_team_members(&team)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut item: TeamItem = model.into();
+    item.members = Some(members.into_iter().map(|x| x.username).collect());
+    Ok(Json(CommonResult::success(Some(item))))
+}
+
+async fn update_team(
+    Path(team): Path<String>,
+    state: State<MegaApiServiceState>,
+    Json(json): Json<TeamReq>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    let stg = state.inner.context.acl_stg();
+    let mut model = team_or_404(&stg, &team).await?;
+    if json.name != model.name {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            String::from("teams can't be renamed"),
+        ));
+    }
+    model.description = json.description;
+    stg.update_team(model)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// Delete a team, its members and the grants made to it go with it.
+async fn delete_team(
+    Path(team): Path<String>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    let stg = state.inner.context.acl_stg();
+    team_or_404(&stg, &team).await?;
+    stg.delete_team(&team)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+async fn team_or_404(
+    stg: &AclStorage,
+    team: &str,
+) -> Result<mega_team::Model, (StatusCode, String)> {
+    stg.get_team(team)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, format!("team {} not found", team)))
+}
+
+async fn list_members(
+    Path(team): Path<String>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<Vec<TeamMemberItem>>>, (StatusCode, String)> {
+    let members = state
+        .inner
+        .context
+        .acl_stg()
+        .get_team_members(&team)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(
+        members.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+async fn add_member(
+    Path(team): Path<String>,
+    state: State<MegaApiServiceState>,
+    Json(json): Json<TeamMemberReq>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    if json.username.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, String::from("username is empty")));
+    }
+    let stg = state.inner.context.acl_stg();
+    team_or_404(&stg, &team).await?;
+    stg.add_team_member(&team, &json.username)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+async fn remove_member(
+    Path((team, username)): Path<(String, String)>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    state
+        .inner
+        .context
+        .acl_stg()
+        .remove_team_member(&team, &username)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
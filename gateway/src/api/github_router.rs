@@ -31,6 +31,10 @@ async fn webhook(
         .and_then(|v| v.to_str().ok())
         .expect("Missing X-GitHub-Event header");
     payload["event_type"] = event_type.into();
+    let delivery = headers
+        .get("X-GitHub-Delivery")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
 
     let event_type = WebhookType::from(event_type);
     match event_type {
@@ -50,14 +54,14 @@ async fn webhook(
                 let _ = payload["pull_request"]["body"].as_str().unwrap();
             }
 
-            GithubWebhookEvent::notify(WebhookType::PullRequest, payload);
+            GithubWebhookEvent::notify(WebhookType::PullRequest, payload, delivery);
         }
         WebhookType::Issues => {
-            GithubWebhookEvent::notify(WebhookType::Issues, payload);
+            GithubWebhookEvent::notify(WebhookType::Issues, payload, delivery);
         }
         WebhookType::Unknown(_type) => {
             tracing::warn!("Unknown event type: {}", _type);
-            GithubWebhookEvent::notify(WebhookType::Unknown(_type), payload);
+            GithubWebhookEvent::notify(WebhookType::Unknown(_type), payload, delivery);
         }
     }
 
@@ -1,8 +1,11 @@
 use common::model::ZtmOptions;
 use mono::api::MonoApiServiceState;
 
+pub mod acl_router;
 pub mod github_router;
 pub mod nostr_router;
+pub mod protection_router;
+pub mod user_router;
 pub mod ztm_router;
 mod model;
 
@@ -1,7 +1,13 @@
-use serde::Deserialize;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use callisto::ztm_path_mapping;
-use common::utils::generate_id;
+use callisto::db_enums::{AclPermission, AclPrincipal};
+use callisto::{
+    mega_path_acl, mega_protection_rule, mega_team, mega_team_member, user, ztm_path_mapping,
+};
+use common::utils::{generate_id, MEGA_DEFAULT_BRANCH};
+use jupiter::storage::protection_storage::rule_names;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct RepoProvideQuery {
@@ -20,3 +26,218 @@ impl From<RepoProvideQuery> for ztm_path_mapping::Model {
         }
     }
 }
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProtectionRuleReq {
+    /// monorepo directory the rule applies to, including everything below it
+    pub path: String,
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    /// pushes can't update the branch directly
+    #[serde(default)]
+    pub require_mr: bool,
+    /// the branch only moves forward and can't be deleted
+    #[serde(default)]
+    pub forbid_force_update: bool,
+    /// checks that must pass before a MR is merged into the branch
+    #[serde(default)]
+    pub required_checks: Vec<String>,
+    /// users who may merge into the branch, anyone with permission on the path when empty
+    #[serde(default)]
+    pub allowed_mergers: Vec<String>,
+}
+
+fn default_branch() -> String {
+    MEGA_DEFAULT_BRANCH.to_owned()
+}
+
+impl ProtectionRuleReq {
+    pub fn into_model(self, id: i64, created_at: NaiveDateTime) -> mega_protection_rule::Model {
+        mega_protection_rule::Model {
+            id,
+            path: self.path,
+            branch: self.branch,
+            require_mr: self.require_mr,
+            forbid_force_update: self.forbid_force_update,
+            required_checks: json!(self.required_checks),
+            allowed_mergers: json!(self.allowed_mergers),
+            created_at,
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtectionRuleItem {
+    pub id: i64,
+    pub path: String,
+    pub branch: String,
+    pub require_mr: bool,
+    pub forbid_force_update: bool,
+    pub required_checks: Vec<String>,
+    pub allowed_mergers: Vec<String>,
+    pub updated_at: i64,
+}
+
+impl From<mega_protection_rule::Model> for ProtectionRuleItem {
+    fn from(value: mega_protection_rule::Model) -> Self {
+        Self {
+            id: value.id,
+            required_checks: rule_names(&value.required_checks),
+            allowed_mergers: rule_names(&value.allowed_mergers),
+            path: value.path,
+            branch: value.branch,
+            require_mr: value.require_mr,
+            forbid_force_update: value.forbid_force_update,
+            updated_at: value.updated_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PathAclReq {
+    /// monorepo directory the grant applies to, including everything below it
+    pub path: String,
+    pub principal_type: AclPrincipal,
+    /// user or team name
+    pub principal: String,
+    /// write access includes read access
+    pub permission: AclPermission,
+}
+
+impl PathAclReq {
+    pub fn into_model(self) -> mega_path_acl::Model {
+        mega_path_acl::Model {
+            id: generate_id(),
+            path: self.path,
+            principal_type: self.principal_type,
+            principal: self.principal,
+            permission: self.permission,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathAclItem {
+    pub id: i64,
+    pub path: String,
+    pub principal_type: AclPrincipal,
+    pub principal: String,
+    pub permission: AclPermission,
+    pub created_at: i64,
+}
+
+impl From<mega_path_acl::Model> for PathAclItem {
+    fn from(value: mega_path_acl::Model) -> Self {
+        Self {
+            id: value.id,
+            path: value.path,
+            principal_type: value.principal_type,
+            principal: value.principal,
+            permission: value.permission,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TeamMemberReq {
+    pub username: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamMemberItem {
+    pub team: String,
+    pub username: String,
+    pub created_at: i64,
+}
+
+impl From<mega_team_member::Model> for TeamMemberItem {
+    fn from(value: mega_team_member::Model) -> Self {
+        Self {
+            team: value.team,
+            username: value.username,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TeamReq {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamItem {
+    pub name: String,
+    pub description: Option<String>,
+    /// user names, left out of listings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<String>>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<mega_team::Model> for TeamItem {
+    fn from(value: mega_team::Model) -> Self {
+        Self {
+            name: value.name,
+            description: value.description,
+            members: None,
+            created_at: value.created_at.and_utc().timestamp(),
+            updated_at: value.updated_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UserReq {
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub avatar_url: String,
+}
+
+impl UserReq {
+    pub fn into_model(self) -> user::Model {
+        user::Model {
+            id: generate_id(),
+            name: self.name,
+            email: self.email,
+            avatar_url: self.avatar_url,
+            is_github: false,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserItem {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+    pub avatar_url: String,
+    /// signed up through GitHub
+    pub is_github: bool,
+    /// names of the teams of the user, left out of listings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub teams: Option<Vec<String>>,
+    pub created_at: i64,
+}
+
+impl From<user::Model> for UserItem {
+    fn from(value: user::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            email: value.email,
+            avatar_url: value.avatar_url,
+            is_github: value.is_github,
+            teams: None,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
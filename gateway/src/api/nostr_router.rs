@@ -7,7 +7,7 @@ use axum::{
     Json, Router,
 };
 
-use common::model::CommonResult;
+use common::{model::CommonResult, path::MonoPath};
 use gemini::{
     nostr::{event::NostrEvent, relay_message::RelayMessage, GitEvent},
     util::repo_path_to_identifier,
@@ -98,10 +98,12 @@ async fn send(
             return Err((StatusCode::BAD_REQUEST, e.to_string()));
         }
     };
+    let path = MonoPath::parse(&git_event_req.path)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
 
     let git_db_storage = state.inner.context.services.git_db_storage.clone();
     let git_model = git_db_storage
-        .find_git_repo_exact_match(&git_event_req.path)
+        .find_git_repo_exact_match(path.as_str())
         .await
         .unwrap();
 
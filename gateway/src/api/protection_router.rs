@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+
+use common::{
+    model::CommonResult,
+    path::MonoPath,
+    utils::{generate_id, validate_ref_name},
+};
+
+use crate::api::model::{ProtectionRuleItem, ProtectionRuleReq};
+use crate::api::MegaApiServiceState;
+
+/// Admin endpoints managing the protection rules of branches, enforced on push and merge.
+pub fn routers() -> Router<MegaApiServiceState> {
+    Router::new()
+        .route("/protection-rules", get(list_rules).post(create_rule))
+        .route("/protection-rules/{id}/update", post(update_rule))
+        .route("/protection-rules/{id}/delete", post(delete_rule))
+}
+
+async fn list_rules(
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<Vec<ProtectionRuleItem>>>, (StatusCode, String)> {
+    let rules = state
+        .inner
+        .context
+        .protection_stg()
+        .get_rules()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(
+        rules.into_iter().map(|x| x.into()).collect(),
+    ))))
+}
+
+async fn create_rule(
+    state: State<MegaApiServiceState>,
+    Json(json): Json<ProtectionRuleReq>,
+) -> Result<Json<CommonResult<ProtectionRuleItem>>, (StatusCode, String)> {
+    let json = validate_rule(json)?;
+    let stg = state.inner.context.protection_stg();
+    let existing = stg
+        .get_rule_by_path(&json.path, &json.branch)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if existing.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{} of {} already has a rule", json.branch, json.path),
+        ));
+    }
+    let rule = json.into_model(generate_id(), chrono::Utc::now().naive_utc());
+    let rule = stg
+        .save_rule(rule)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(rule.into()))))
+}
+
+async fn update_rule(
+    Path(id): Path<i64>,
+    state: State<MegaApiServiceState>,
+    Json(json): Json<ProtectionRuleReq>,
+) -> Result<Json<CommonResult<ProtectionRuleItem>>, (StatusCode, String)> {
+    let json = validate_rule(json)?;
+    let stg = state.inner.context.protection_stg();
+    let Some(current) = stg
+        .get_rule(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    else {
+        return Err((StatusCode::NOT_FOUND, String::from("rule not found")));
+    };
+    let other = stg
+        .get_rule_by_path(&json.path, &json.branch)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if other.is_some_and(|x| x.id != id) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{} of {} already has a rule", json.branch, json.path),
+        ));
+    }
+    let rule = stg
+        .update_rule(json.into_model(id, current.created_at))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(rule.into()))))
+}
+
+async fn delete_rule(
+    Path(id): Path<i64>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    let stg = state.inner.context.protection_stg();
+    let rule = stg
+        .get_rule(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if rule.is_none() {
+        return Err((StatusCode::NOT_FOUND, String::from("rule not found")));
+    }
+    stg.delete_rule(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// Normalize the path of a rule and check its branch name.
+fn validate_rule(mut json: ProtectionRuleReq) -> Result<ProtectionRuleReq, (StatusCode, String)> {
+    json.path = MonoPath::parse(&json.path)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?
+        .to_string();
+    validate_ref_name(&json.branch).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    Ok(json)
+}
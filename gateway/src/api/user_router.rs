@@ -0,0 +1,124 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+
+use callisto::user;
+use common::model::{CommonResult, Page, Pagination};
+
+use crate::api::model::{UserItem, UserReq};
+use crate::api::MegaApiServiceState;
+
+/// Admin endpoints managing the users, users signing in with GitHub are added on their
+/// first login.
+pub fn routers() -> Router<MegaApiServiceState> {
+    Router::new()
+        .route("/users", get(list_users).post(create_user))
+        .route("/users/{name}", get(get_user))
+        .route("/users/{name}/delete", post(delete_user))
+}
+
+async fn list_users(
+    Query(pagination): Query<Pagination>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<Page<UserItem>>>, (StatusCode, String)> {
+    let pagination = pagination.normalized();
+    let (users, total) = state
+        .inner
+        .context
+        .user_stg()
+        .list_users(pagination)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(
+        Page::new(users, total, &pagination).map(Into::into),
+    ))))
+}
+
+async fn create_user(
+    state: State<MegaApiServiceState>,
+    Json(mut json): Json<UserReq>,
+) -> Result<Json<CommonResult<UserItem>>, (StatusCode, String)> {
+    json.name = json.name.trim().to_owned();
+    json.email = json.email.trim().to_owned();
+    if json.name.is_empty() || json.name.starts_with("team:") {
+        return Err((StatusCode::BAD_REQUEST, String::from("invalid user name")));
+    }
+    if !json.email.contains('@') {
+        return Err((StatusCode::BAD_REQUEST, String::from("invalid email")));
+    }
+    let stg = state.inner.context.user_stg();
+    let by_name = stg
+        .find_user_by_name(&json.name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let by_email = stg
+        .find_user_by_email(&json.email)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if by_name.is_some() || by_email.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            String::from("a user with the name or email exists already"),
+        ));
+    }
+    let user = json.into_model();
+    stg.save_user(user.clone())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(Some(user.into()))))
+}
+
+/// A user with the teams they are a member of.
+async fn get_user(
+    Path(name): Path<String>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<UserItem>>, (StatusCode, String)> {
+    let user = user_or_404(&state, &name).await?;
+    let teams = state
+        .inner
+        .context
+        .acl_stg()
+        .get_user_teams(&name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut item: UserItem = user.into();
+    item.teams = Some(teams.into_iter().map(|x| x.team).collect());
+    Ok(Json(CommonResult::success(Some(item))))
+}
+
+/// Delete a user with their ssh keys, access tokens and team memberships.
+async fn delete_user(
+    Path(name): Path<String>,
+    state: State<MegaApiServiceState>,
+) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    let user = user_or_404(&state, &name).await?;
+    let context = &state.inner.context;
+    context
+        .acl_stg()
+        .remove_user_memberships(&user.name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    context
+        .user_stg()
+        .delete_user(user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(CommonResult::success(None)))
+}
+
+async fn user_or_404(
+    state: &MegaApiServiceState,
+    name: &str,
+) -> Result<user::Model, (StatusCode, String)> {
+    state
+        .inner
+        .context
+        .user_stg()
+        .find_user_by_name(name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, format!("user {} not found", name)))
+}
@@ -8,7 +8,7 @@ use axum::{
 };
 
 use callisto::ztm_path_mapping;
-use common::model::CommonResult;
+use common::{model::CommonResult, path::MonoPath};
 use gemini::nostr::subscribe_git_event;
 use vault::get_peerid;
 
@@ -25,8 +25,11 @@ pub fn routers() -> Router<MegaApiServiceState> {
 
 async fn repo_provide(
     state: State<MegaApiServiceState>,
-    Json(json): Json<RepoProvideQuery>,
+    Json(mut json): Json<RepoProvideQuery>,
 ) -> Result<Json<CommonResult<String>>, (StatusCode, String)> {
+    json.path = MonoPath::parse(&json.path)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?
+        .to_string();
     let bootstrap_node = match state.ztm.bootstrap_node.clone() {
         Some(b) => b.clone(),
         None => {
@@ -4,7 +4,7 @@ use std::str::FromStr;
 use std::{thread, time};
 
 use axum::routing::get;
-use axum::{http, Router};
+use axum::{http, middleware, Router};
 use axum_server::tls_rustls::RustlsConfig;
 use clap::Args;
 
@@ -13,15 +13,25 @@ use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::trace::TraceLayer;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 use common::model::{CommonOptions, ZtmOptions};
 use gemini::ztm::agent::{run_ztm_client, LocalZTMAgent};
 use jupiter::context::Context;
+use mono::api::events::events_router;
 use mono::api::lfs::lfs_router;
+use mono::api::metrics;
+use mono::api::openapi::ApiDoc;
+use mono::api::rate_limit::{self, RateLimiter};
 use mono::api::MonoApiServiceState;
 use mono::server::https_server::{get_method_router, post_method_router, AppState};
+use mono::server::{shutdown_handle, shutdown_signal};
 
-use crate::api::{github_router, nostr_router, ztm_router, MegaApiServiceState};
+use crate::api::{
+    acl_router, github_router, nostr_router, protection_router, user_router, ztm_router,
+    MegaApiServiceState,
+};
 
 #[derive(Args, Clone, Debug)]
 pub struct HttpOptions {
@@ -79,7 +89,8 @@ pub async fn https_server(context: Context, options: HttpsOptions) {
         .await
         .unwrap();
     axum_server::bind_rustls(addr, config)
-        .serve(app.into_make_service())
+        .handle(shutdown_handle())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -106,9 +117,13 @@ pub async fn http_server(context: Context, options: HttpOptions) {
 
     let addr = SocketAddr::from_str(&server_url).unwrap();
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
 }
 
 pub async fn app(
@@ -118,6 +133,8 @@ pub async fn app(
     common: CommonOptions,
     ztm: ZtmOptions,
 ) -> Router {
+    metrics::install();
+    let rate_limiter = RateLimiter::new(&context);
     let state = AppState {
         host,
         port,
@@ -148,6 +165,9 @@ pub async fn app(
             .merge(ztm_router::routers())
             .merge(nostr_router::routers())
             .merge(github_router::routers())
+            .merge(protection_router::routers())
+            .merge(acl_router::routers())
+            .merge(user_router::routers())
     }
 
     // add RequestDecompressionLayer for handle gzip encode
@@ -159,15 +179,37 @@ pub async fn app(
             Router::new()
                 .nest(
                     "/api/v1/mono",
-                    mono::api::api_router::routers().with_state(mono_api_state.clone()),
+                    mono::api::api_router::routers()
+                        .layer(middleware::from_fn_with_state(
+                            mono_api_state.clone(),
+                            mono::api::access_log::access_log,
+                        ))
+                        .layer(middleware::from_fn_with_state(
+                            mono_api_state.clone(),
+                            mono::api::oauth::token_auth,
+                        ))
+                        .with_state(mono_api_state.clone()),
                 )
                 .nest(
                     "/api/v1/mega",
                     mega_routers().with_state(mega_api_state.clone()),
+                )
+                // live updates for web UIs, at `/api/v1/events/stream` and `/api/v1/events/ws`
+                .nest(
+                    "/api/v1",
+                    events_router::routers().with_state(mono_api_state.clone()),
                 ),
         )
+        .route("/metrics", get(metrics::render))
+        // description of the mono API and a Swagger UI to try it
+        .merge(SwaggerUi::new("/swagger-ui").url("/api/openapi.json", ApiDoc::openapi()))
         // Using Regular Expressions for Path Matching in Protocol
         .route("/{*path}", get(get_method_router).post(post_method_router))
+        .layer(middleware::from_fn_with_state(
+            rate_limiter,
+            rate_limit::rate_limit,
+        ))
+        .layer(middleware::from_fn(metrics::track))
         .layer(
             ServiceBuilder::new().layer(CorsLayer::new().allow_origin(Any).allow_headers(vec![
                 http::header::AUTHORIZATION,
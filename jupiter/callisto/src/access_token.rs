@@ -9,7 +9,12 @@ pub struct Model {
     pub id: i64,
     pub user_id: i64,
     #[sea_orm(column_type = "Text")]
-    pub token: String,
+    pub name: String,
+    #[sea_orm(column_type = "Text", unique)]
+    pub token_hash: String,
+    pub token_prefix: String,
+    pub scopes: Json,
+    pub last_used_at: Option<DateTime>,
     pub created_at: DateTime,
 }
 
@@ -3,6 +3,7 @@ use std::fmt::Display;
 
 use sea_orm::prelude::StringLen;
 use sea_orm::{DeriveActiveEnum, EnumIter};
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
 #[sea_orm(
@@ -14,6 +15,7 @@ pub enum StorageType {
     Database,
     LocalFs,
     RemoteUrl,
+    PackFile,
 }
 
 impl fmt::Display for StorageType {
@@ -22,16 +24,38 @@ impl fmt::Display for StorageType {
             StorageType::Database => write!(f, "database"),
             StorageType::LocalFs => write!(f, "local_fs"),
             StorageType::RemoteUrl => write!(f, "remote_url"),
+            StorageType::PackFile => write!(f, "pack_file"),
         }
     }
 }
 
+/// How the content of a raw blob kept in the database is encoded, rows without one keep
+/// it as is.
 #[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Copy)]
 #[sea_orm(
     rs_type = "String",
     db_type = "String(StringLen::None)",
     rename_all = "snake_case"
 )]
+pub enum CompressionType {
+    Zlib,
+}
+
+impl fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionType::Zlib => write!(f, "zlib"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Copy, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
 pub enum MergeStatus {
     Open,
     Merged,
@@ -60,14 +84,16 @@ pub enum RefType {
     Tag,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(
     rs_type = "String",
     db_type = "String(StringLen::None)",
     rename_all = "snake_case"
 )]
+#[serde(rename_all = "snake_case")]
 pub enum ConvType {
     Comment,
+    ReviewComment,
     Deploy,
     Commit,
     ForcePush,
@@ -78,12 +104,17 @@ pub enum ConvType {
     Merged,
     Closed,
     Reopen,
+    /// the MR was marked as a draft
+    Draft,
+    /// the MR was marked ready for review
+    Ready,
 }
 
 impl Display for ConvType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             ConvType::Comment => "Comment",
+            ConvType::ReviewComment => "ReviewComment",
             ConvType::Deploy => "Deploy",
             ConvType::Commit => "Commit",
             ConvType::ForcePush => "ForcePush",
@@ -94,6 +125,303 @@ impl Display for ConvType {
             ConvType::Merged => "Merged",
             ConvType::Closed => "Closed",
             ConvType::Reopen => "Reopen",
+            ConvType::Draft => "Draft",
+            ConvType::Ready => "Ready",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficKind {
+    RawDownload,
+    ArchiveDownload,
+    Clone,
+    Fetch,
+}
+
+impl Display for TrafficKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TrafficKind::RawDownload => "raw_download",
+            TrafficKind::ArchiveDownload => "archive_download",
+            TrafficKind::Clone => "clone",
+            TrafficKind::Fetch => "fetch",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureFormat {
+    Gpg,
+    Ssh,
+}
+
+impl Display for SignatureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SignatureFormat::Gpg => "gpg",
+            SignatureFormat::Ssh => "ssh",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Queued,
+    InProgress,
+    Completed,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckConclusion {
+    Success,
+    Failure,
+    Neutral,
+    Cancelled,
+    Skipped,
+    TimedOut,
+    ActionRequired,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationLevel {
+    Notice,
+    Warning,
+    Failure,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewState {
+    Approved,
+    ChangesRequested,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            CheckStatus::Queued => "queued",
+            CheckStatus::InProgress => "in_progress",
+            CheckStatus::Completed => "completed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for CheckConclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            CheckConclusion::Success => "success",
+            CheckConclusion::Failure => "failure",
+            CheckConclusion::Neutral => "neutral",
+            CheckConclusion::Cancelled => "cancelled",
+            CheckConclusion::Skipped => "skipped",
+            CheckConclusion::TimedOut => "timed_out",
+            CheckConclusion::ActionRequired => "action_required",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for AnnotationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AnnotationLevel::Notice => "notice",
+            AnnotationLevel::Warning => "warning",
+            AnnotationLevel::Failure => "failure",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for ReviewState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ReviewState::Approved => "approved",
+            ReviewState::ChangesRequested => "changes_requested",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    DeriveActiveEnum,
+    Serialize,
+    Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AclPermission {
+    Read,
+    Write,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AclPrincipal {
+    User,
+    Team,
+}
+
+impl Display for AclPermission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AclPermission::Read => "read",
+            AclPermission::Write => "write",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for AclPrincipal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AclPrincipal::User => "user",
+            AclPrincipal::Team => "team",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Push,
+    Merge,
+    CreateFile,
+    UpdateFile,
+    DeleteEntry,
+    MoveEntry,
+    BatchCommit,
+    CherryPick,
+    Revert,
+    CreateBranch,
+    DeleteBranch,
+    CreateToken,
+    DeleteToken,
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            AuditAction::Push => "push",
+            AuditAction::Merge => "merge",
+            AuditAction::CreateFile => "create_file",
+            AuditAction::UpdateFile => "update_file",
+            AuditAction::DeleteEntry => "delete_entry",
+            AuditAction::MoveEntry => "move_entry",
+            AuditAction::BatchCommit => "batch_commit",
+            AuditAction::CherryPick => "cherry_pick",
+            AuditAction::Revert => "revert",
+            AuditAction::CreateBranch => "create_branch",
+            AuditAction::DeleteBranch => "delete_branch",
+            AuditAction::CreateToken => "create_token",
+            AuditAction::DeleteToken => "delete_token",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "String(StringLen::None)",
+    rename_all = "snake_case"
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// `@name` in a comment
+    Mention,
+    ReviewRequested,
+    MrMerged,
+    MrClosed,
+}
+
+impl Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            NotificationKind::Mention => "mention",
+            NotificationKind::ReviewRequested => "review_requested",
+            NotificationKind::MrMerged => "mr_merged",
+            NotificationKind::MrClosed => "mr_closed",
         };
         write!(f, "{}", s)
     }
@@ -3,7 +3,11 @@
 pub mod prelude;
 
 pub mod access_token;
+pub mod audit_log;
+pub mod check_annotations;
+pub mod check_runs;
 pub mod db_enums;
+pub mod dir_stats;
 pub mod git_blob;
 pub mod git_commit;
 pub mod git_issue;
@@ -17,15 +21,34 @@ pub mod lfs_objects;
 pub mod lfs_split_relations;
 pub mod mega_blob;
 pub mod mega_commit;
+pub mod mega_commit_graph;
+pub mod mega_commit_signature;
 pub mod mega_issue;
 pub mod mega_mr;
 pub mod mega_conversation;
+pub mod mega_mr_review;
+pub mod mega_notification;
+pub mod mega_path_acl;
+pub mod mega_protection_rule;
+pub mod mega_push_cert;
 pub mod mega_refs;
+pub mod mega_release;
+pub mod mega_release_asset;
 pub mod mega_tag;
+pub mod mega_team;
+pub mod mega_team_member;
+pub mod mega_tombstone;
 pub mod mega_tree;
+pub mod mq_dead_letter;
+pub mod mq_idempotency_key;
+pub mod mq_job;
 pub mod mq_storage;
 pub mod raw_blob;
+pub mod search_file;
+pub mod search_state;
+pub mod search_trigram;
 pub mod ssh_keys;
+pub mod traffic_stats;
 pub mod user;
 pub mod ztm_lfs_info;
 pub mod ztm_node;
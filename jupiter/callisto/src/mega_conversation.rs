@@ -1,10 +1,11 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::db_enums::ConvType;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "mega_conversation")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -14,6 +15,12 @@ pub struct Model {
     pub conv_type: ConvType,
     #[sea_orm(column_type = "Text", nullable)]
     pub comment: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub path: Option<String>,
+    pub commit_id: Option<String>,
+    pub line: Option<i32>,
+    pub reply_to: Option<i64>,
+    pub resolved: bool,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
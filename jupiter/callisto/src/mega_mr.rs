@@ -1,10 +1,11 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::db_enums::MergeStatus;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "mega_mr")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -17,6 +18,29 @@ pub struct Model {
     pub path: String,
     pub from_hash: String,
     pub to_hash: String,
+    /// branch the MR was opened from, `None` for MRs opened by a push
+    #[sea_orm(column_type = "Text", nullable)]
+    pub source_branch: Option<String>,
+    #[sea_orm(column_type = "Text")]
+    pub target_branch: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    /// usernames asked to review the MR
+    pub reviewers: Json,
+    pub draft: bool,
+    /// user name of who opened the MR, `None` when it isn't known
+    #[sea_orm(column_type = "Text", nullable)]
+    pub author: Option<String>,
+    /// link of the MR this one builds on, it's merged first
+    pub depends_on: Option<String>,
+    /// names of the labels of the MR
+    pub labels: Json,
+    /// usernames working on the MR
+    pub assignees: Json,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub milestone: Option<String>,
+    /// link of the MR closed for a conflict that this one replaces
+    pub supersedes: Option<String>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
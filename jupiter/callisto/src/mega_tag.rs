@@ -1,14 +1,18 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "mega_tag")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub id: i64,
     #[sea_orm(unique)]
     pub tag_id: String,
+    /// directory the tag is scoped to, `/` for the whole monorepo
+    #[sea_orm(column_type = "Text")]
+    pub path: String,
     pub object_id: String,
     pub object_type: String,
     #[sea_orm(column_type = "Text")]
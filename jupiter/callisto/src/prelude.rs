@@ -1,6 +1,10 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
 pub use crate::access_token::Entity as AccessToken;
+pub use crate::audit_log::Entity as AuditLog;
+pub use crate::check_annotations::Entity as CheckAnnotations;
+pub use crate::check_runs::Entity as CheckRuns;
+pub use crate::dir_stats::Entity as DirStats;
 pub use crate::git_blob::Entity as GitBlob;
 pub use crate::git_commit::Entity as GitCommit;
 pub use crate::git_issue::Entity as GitIssue;
@@ -14,14 +18,30 @@ pub use crate::lfs_objects::Entity as LfsObjects;
 pub use crate::lfs_split_relations::Entity as LfsSplitRelations;
 pub use crate::mega_blob::Entity as MegaBlob;
 pub use crate::mega_commit::Entity as MegaCommit;
+pub use crate::mega_commit_graph::Entity as MegaCommitGraph;
+pub use crate::mega_commit_signature::Entity as MegaCommitSignature;
 pub use crate::mega_issue::Entity as MegaIssue;
 pub use crate::mega_mr::Entity as MegaMr;
 pub use crate::mega_conversation::Entity as MegaMrConv;
+pub use crate::mega_mr_review::Entity as MegaMrReview;
+pub use crate::mega_notification::Entity as MegaNotification;
+pub use crate::mega_path_acl::Entity as MegaPathAcl;
+pub use crate::mega_protection_rule::Entity as MegaProtectionRule;
+pub use crate::mega_push_cert::Entity as MegaPushCert;
 pub use crate::mega_refs::Entity as MegaRefs;
+pub use crate::mega_release::Entity as MegaRelease;
+pub use crate::mega_release_asset::Entity as MegaReleaseAsset;
 pub use crate::mega_tag::Entity as MegaTag;
+pub use crate::mega_team::Entity as MegaTeam;
+pub use crate::mega_team_member::Entity as MegaTeamMember;
+pub use crate::mega_tombstone::Entity as MegaTombstone;
 pub use crate::mega_tree::Entity as MegaTree;
 pub use crate::raw_blob::Entity as RawBlob;
+pub use crate::search_file::Entity as SearchFile;
+pub use crate::search_state::Entity as SearchState;
+pub use crate::search_trigram::Entity as SearchTrigram;
 pub use crate::ssh_keys::Entity as SshKeys;
+pub use crate::traffic_stats::Entity as TrafficStats;
 pub use crate::user::Entity as User;
 pub use crate::ztm_lfs_info::Entity as ZtmLFSInfo;
 pub use crate::ztm_node::Entity as ZtmNode;
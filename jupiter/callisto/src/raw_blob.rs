@@ -2,7 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
-use crate::db_enums::StorageType;
+use crate::db_enums::{CompressionType, StorageType};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "raw_blob")]
@@ -17,6 +17,8 @@ pub struct Model {
     pub storage_type: StorageType,
     #[sea_orm(column_type = "VarBinary(StringLen::None)", nullable)]
     pub data: Option<Vec<u8>>,
+    /// encoding of `data` as stored, it's always decoded when read through a storage
+    pub compression: Option<CompressionType>,
     #[sea_orm(column_type = "Text", nullable)]
     pub local_path: Option<String>,
     #[sea_orm(column_type = "Text", nullable)]
@@ -0,0 +1,356 @@
+//! Backups of the whole monorepo, for disaster recovery or moving to another instance.
+//!
+//! A bundle is a tar.gz holding `manifest.json`, the rows of every table as JSON lines
+//! in `<table>.jsonl`, and the content of all raw blobs in `blobs.pack`, a packfile git
+//! can read too. Blobs are restored through the blob storage of the instance restored
+//! into, wherever it keeps them.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use chrono::{NaiveDateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures::StreamExt;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use callisto::{
+    mega_blob, mega_commit, mega_conversation, mega_mr, mega_mr_review, mega_refs, mega_tag,
+    mega_tree, raw_blob,
+};
+use common::errors::MegaError;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::pack::{entry::Entry, Pack};
+
+use crate::blob_storage::pack_storage::{encode_object, pack_header};
+use crate::context::Context;
+use crate::storage::batch_save_model;
+
+/// Format of the bundles written, bundles of a later version aren't restored.
+pub const BUNDLE_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const BLOB_PACK: &str = "blobs.pack";
+/// Rows and blobs written to the database at once while restoring.
+const RESTORE_BATCH: usize = 1000;
+/// Raw blobs loaded per query while exporting.
+const EXPORT_BLOB_BATCH: u64 = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    /// rows exported of each table
+    pub rows: BTreeMap<String, u64>,
+    pub blobs: u64,
+}
+
+/// Write a bundle of everything the monorepo stores to `out`. Writes made meanwhile may
+/// be missing from it, stop the server for a consistent one.
+pub async fn export(context: &Context, out: impl Write) -> Result<Manifest, MegaError> {
+    let connection = context.services.connection.as_ref();
+    let mut files = Vec::new();
+    let mut rows = BTreeMap::new();
+    macro_rules! dump {
+        ($($table:ident),*) => {$(
+            let mut file = tempfile::tempfile()?;
+            let count = dump_rows::<$table::Entity>(connection, &mut file).await?;
+            rows.insert(stringify!($table).to_owned(), count);
+            files.push((format!("{}.jsonl", stringify!($table)), file));
+        )*};
+    }
+    dump!(
+        mega_refs,
+        mega_commit,
+        mega_tree,
+        mega_blob,
+        mega_tag,
+        mega_mr,
+        mega_conversation,
+        mega_mr_review
+    );
+    let mut pack = tempfile::tempfile()?;
+    let blobs = dump_blobs(context, &mut pack).await?;
+    files.push((BLOB_PACK.to_owned(), pack));
+
+    let manifest = Manifest {
+        version: BUNDLE_VERSION,
+        created_at: Utc::now().naive_utc(),
+        rows,
+        blobs,
+    };
+    let mut bundle = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    let data = serde_json::to_vec_pretty(&manifest).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at.and_utc().timestamp() as u64);
+    bundle.append_data(&mut header, MANIFEST, data.as_slice())?;
+    for (name, mut file) in files {
+        file.seek(SeekFrom::Start(0))?;
+        bundle.append_file(name, &mut file)?;
+    }
+    bundle.into_inner()?.finish()?;
+    Ok(manifest)
+}
+
+/// Restore a bundle read from `input` into the database of `context`, which must not
+/// hold a monorepo yet. A restore failing half way leaves what it wrote, start over
+/// on a fresh database.
+pub async fn restore(context: &Context, input: impl Read) -> Result<Manifest, MegaError> {
+    let connection = context.services.connection.as_ref();
+    if mega_refs::Entity::find().count(connection).await? > 0 {
+        return Err(MegaError::with_message(
+            "the database already holds a monorepo, restore before the server first starts",
+        ));
+    }
+    let mut bundle = tar::Archive::new(GzDecoder::new(input));
+    let mut manifest: Option<Manifest> = None;
+    for entry in bundle.entries()? {
+        let entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if name == MANIFEST {
+            let read: Manifest = serde_json::from_reader(entry)
+                .map_err(|e| MegaError::with_message(&format!("invalid manifest: {}", e)))?;
+            if read.version > BUNDLE_VERSION {
+                return Err(MegaError::with_message(&format!(
+                    "bundle version {} is newer than the supported {}",
+                    read.version, BUNDLE_VERSION
+                )));
+            }
+            manifest = Some(read);
+            continue;
+        }
+        if manifest.is_none() {
+            return Err(MegaError::with_message(
+                "bundle doesn't start with a manifest",
+            ));
+        }
+        let reader = BufReader::new(entry);
+        macro_rules! load {
+            ($($table:ident),*) => {
+                match name.as_str() {
+                    $(n if n == concat!(stringify!($table), ".jsonl") => {
+                        load_rows::<$table::Entity, $table::ActiveModel>(connection, reader)
+                            .await?
+                    })*
+                    BLOB_PACK => load_blobs(context, reader).await?,
+                    _ => tracing::warn!("skipped {} of the bundle", name),
+                }
+            };
+        }
+        load!(
+            mega_refs,
+            mega_commit,
+            mega_tree,
+            mega_blob,
+            mega_tag,
+            mega_mr,
+            mega_conversation,
+            mega_mr_review
+        );
+    }
+    manifest.ok_or_else(|| MegaError::with_message("bundle has no manifest"))
+}
+
+/// Write every row of `E` to `out`, one JSON object a line. Returns how many there are.
+async fn dump_rows<E>(connection: &DatabaseConnection, out: &mut File) -> Result<u64, MegaError>
+where
+    E: EntityTrait,
+    E::Model: Serialize,
+{
+    let mut out = BufWriter::new(out);
+    let mut count = 0;
+    let mut stream = E::find().stream(connection).await?;
+    while let Some(model) = stream.next().await {
+        serde_json::to_writer(&mut out, &model?).unwrap();
+        out.write_all(b"\n")?;
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+async fn load_rows<E, A>(
+    connection: &DatabaseConnection,
+    reader: impl BufRead,
+) -> Result<(), MegaError>
+where
+    E: EntityTrait,
+    E::Model: DeserializeOwned + IntoActiveModel<A>,
+    A: ActiveModelTrait<Entity = E> + From<E::Model> + Send,
+{
+    let mut batch = Vec::with_capacity(RESTORE_BATCH);
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let model: E::Model = serde_json::from_str(&line)
+            .map_err(|e| MegaError::with_message(&format!("invalid row: {}", e)))?;
+        batch.push(model.into_active_model());
+        if batch.len() >= RESTORE_BATCH {
+            batch_save_model(connection, std::mem::take(&mut batch)).await?;
+        }
+    }
+    batch_save_model(connection, batch).await
+}
+
+/// Write the content of every raw blob stored so far to `out` as a pack, returns how
+/// many blobs it holds.
+async fn dump_blobs(context: &Context, out: &mut File) -> Result<u64, MegaError> {
+    let connection = context.services.connection.as_ref();
+    let blob_store = context.services.raw_db_storage.blob_store();
+    // blobs stored while exporting are left out, the count of the header must hold
+    let last = raw_blob::Entity::find()
+        .order_by_desc(raw_blob::Column::Id)
+        .one(connection)
+        .await?
+        .map_or(i64::MIN, |x| x.id);
+    let count = raw_blob::Entity::find()
+        .filter(raw_blob::Column::Id.lte(last))
+        .count(connection)
+        .await?;
+
+    let mut writer = PackWriter::new(out, count)?;
+    let mut last_id = i64::MIN;
+    loop {
+        let models = raw_blob::Entity::find()
+            .filter(raw_blob::Column::Id.gt(last_id))
+            .filter(raw_blob::Column::Id.lte(last))
+            .order_by_asc(raw_blob::Column::Id)
+            .limit(EXPORT_BLOB_BATCH)
+            .all(connection)
+            .await?;
+        let Some(model) = models.last() else {
+            break;
+        };
+        last_id = model.id;
+        for model in models {
+            let blob: Blob = blob_store.load(model).await?.into();
+            writer.write(&Entry::from(blob))?;
+        }
+    }
+    writer.finish()?;
+    Ok(count)
+}
+
+/// Store the blobs of the pack read from `reader` as raw blobs.
+async fn load_blobs(context: &Context, mut reader: impl Read) -> Result<(), MegaError> {
+    // the pack is decoded on a thread of its own, which needs to own what it reads
+    let mut pack = tempfile::tempfile()?;
+    std::io::copy(&mut reader, &mut pack)?;
+    pack.seek(SeekFrom::Start(0))?;
+
+    let pack_config = &context.config.pack;
+    let decoder = Pack::new(
+        None,
+        Some(1024 * 1024 * 1024 * pack_config.pack_decode_mem_size),
+        Some(pack_config.pack_decode_cache_path.clone()),
+        pack_config.clean_cache_after_decode,
+    );
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let handle = decoder.decode_async(BufReader::new(pack), sender);
+    let mut batch = Vec::with_capacity(RESTORE_BATCH);
+    for entry in receiver {
+        let blob = Blob {
+            id: entry.hash,
+            data: entry.data,
+        };
+        batch.push(raw_blob::Model::from(blob));
+        if batch.len() >= RESTORE_BATCH {
+            save_blobs(context, std::mem::take(&mut batch)).await?;
+        }
+    }
+    save_blobs(context, batch).await?;
+    handle
+        .join()
+        .map_err(|_| MegaError::with_message(&format!("{} of the bundle is corrupt", BLOB_PACK)))?;
+    Ok(())
+}
+
+async fn save_blobs(context: &Context, models: Vec<raw_blob::Model>) -> Result<(), MegaError> {
+    let storage = &context.services.raw_db_storage;
+    let models: Vec<raw_blob::ActiveModel> = storage
+        .blob_store()
+        .offload_all(models)
+        .await?
+        .into_iter()
+        .map(|x| x.into_active_model())
+        .collect();
+    batch_save_model(storage.get_connection(), models).await
+}
+
+/// A pack of whole objects written one by one, its header needs their number up front.
+struct PackWriter<'a> {
+    out: BufWriter<&'a mut File>,
+    hash: Sha1,
+}
+
+impl<'a> PackWriter<'a> {
+    fn new(out: &'a mut File, count: u64) -> Result<Self, MegaError> {
+        let mut writer = PackWriter {
+            out: BufWriter::new(out),
+            hash: Sha1::new(),
+        };
+        writer.put(&pack_header(count as usize))?;
+        Ok(writer)
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<(), MegaError> {
+        let data = encode_object(entry)?;
+        self.put(&data)
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<(), MegaError> {
+        self.hash.update(data);
+        Ok(self.out.write_all(data)?)
+    }
+
+    fn finish(mut self) -> Result<(), MegaError> {
+        let checksum = self.hash.finalize();
+        self.out.write_all(&checksum)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Seek, SeekFrom};
+
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::pack::{entry::Entry, Pack};
+
+    use super::PackWriter;
+
+    #[test]
+    fn test_blob_pack_decodes() {
+        let blobs: Vec<Blob> = (0..50)
+            .map(|i| Blob::from_content(&format!("line {}\n", i).repeat(i)))
+            .collect();
+        let mut file = tempfile::tempfile().unwrap();
+        let mut writer = PackWriter::new(&mut file, blobs.len() as u64).unwrap();
+        for blob in &blobs {
+            writer.write(&Entry::from(blob.clone())).unwrap();
+        }
+        writer.finish().unwrap();
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let handle = Pack::new(None, None, None, true).decode_async(BufReader::new(file), sender);
+        let decoded: Vec<Entry> = receiver.into_iter().collect();
+        handle.join().unwrap();
+        assert_eq!(decoded.len(), blobs.len());
+        for blob in &blobs {
+            assert!(decoded
+                .iter()
+                .any(|e| e.hash == blob.id && e.data == blob.data));
+        }
+    }
+}
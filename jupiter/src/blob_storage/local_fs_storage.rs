@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use callisto::db_enums::StorageType;
+use common::errors::MegaError;
+
+use crate::blob_storage::BlobStorage;
+
+/// Blobs kept as files under `base_path`, fanned out by the first two characters of the
+/// hash like git's loose objects.
+pub struct LocalFsStorage {
+    base_path: PathBuf,
+}
+
+impl LocalFsStorage {
+    pub fn init(base_path: PathBuf) -> LocalFsStorage {
+        fs::create_dir_all(&base_path).expect("Create directory failed!");
+        LocalFsStorage { base_path }
+    }
+
+    pub fn read(location: &str) -> Result<Vec<u8>, MegaError> {
+        Ok(fs::read(location)?)
+    }
+
+    pub fn remove(location: &str) -> Result<(), MegaError> {
+        match fs::remove_file(location) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStorage for LocalFsStorage {
+    fn storage_type(&self) -> StorageType {
+        StorageType::LocalFs
+    }
+
+    async fn put_object(&self, sha1: &str, data: &[u8]) -> Result<String, MegaError> {
+        let dir = self.base_path.join(&sha1[..2]);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(&sha1[2..]);
+        if !path.exists() {
+            // written aside first so a reader never sees half a blob
+            let tmp = dir.join(format!("{}.{}", &sha1[2..], Uuid::new_v4().simple()));
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, &path)?;
+        }
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    async fn get_object(&self, _sha1: &str, location: &str) -> Result<Vec<u8>, MegaError> {
+        Self::read(location)
+    }
+
+    async fn delete_object(&self, _sha1: &str, location: &str) -> Result<(), MegaError> {
+        Self::remove(location)
+    }
+}
@@ -0,0 +1,309 @@
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use callisto::{
+    db_enums::{CompressionType, StorageType},
+    raw_blob,
+};
+use common::config::StorageConfig;
+use common::errors::MegaError;
+use mercury::internal::pack::entry::Entry;
+
+use crate::blob_storage::{
+    local_fs_storage::LocalFsStorage, pack_storage::PackStorage, s3_storage::S3Storage,
+};
+
+pub mod local_fs_storage;
+pub mod pack_storage;
+pub mod s3_storage;
+
+/// Somewhere outside the database the content of raw blobs can be kept.
+#[async_trait]
+pub trait BlobStorage: Sync + Send {
+    /// How the rows of blobs kept here are marked.
+    fn storage_type(&self) -> StorageType;
+
+    /// Store the content of blob `sha1`, returns the location to keep in its row.
+    async fn put_object(&self, sha1: &str, data: &[u8]) -> Result<String, MegaError>;
+
+    /// Store all objects of a push together, returns the location of every blob among
+    /// them, or `None` when objects are only stored one by one.
+    async fn put_pack(&self, _entries: &[Entry]) -> Result<Option<String>, MegaError> {
+        Ok(None)
+    }
+
+    async fn get_object(&self, sha1: &str, location: &str) -> Result<Vec<u8>, MegaError>;
+
+    /// Remove the content of blob `sha1` kept at `location`, a location already gone
+    /// isn't an error.
+    async fn delete_object(&self, sha1: &str, location: &str) -> Result<(), MegaError>;
+}
+
+/// Decides where the content of a raw blob goes, blobs larger than `threshold` bytes are
+/// written to the backend and their rows only keep the location. Without a backend
+/// everything stays in the database, compressed when `compress` is set.
+#[derive(Clone, Default)]
+pub struct BlobStore {
+    backend: Option<Arc<dyn BlobStorage>>,
+    threshold: usize,
+    packs: Option<Arc<PackStorage>>,
+    compress: bool,
+}
+
+impl BlobStore {
+    pub fn new(backend: Arc<dyn BlobStorage>, threshold: usize) -> Self {
+        BlobStore {
+            backend: Some(backend),
+            threshold,
+            packs: None,
+            compress: false,
+        }
+    }
+
+    /// Compress the content of the blobs staying in the database.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Keep every pushed object in the packs of `packs`, other blobs larger than
+    /// `threshold` go there one by one.
+    pub fn with_packs(packs: Arc<PackStorage>, threshold: usize) -> Self {
+        BlobStore {
+            packs: Some(packs.clone()),
+            ..BlobStore::new(packs, threshold)
+        }
+    }
+
+    /// Move the content of `model` to the backend when it's over the threshold.
+    pub async fn offload(&self, model: raw_blob::Model) -> Result<raw_blob::Model, MegaError> {
+        if model.storage_type != StorageType::Database {
+            return Ok(model);
+        }
+        if let Some(backend) = &self.backend {
+            if let Some(data) = model.data.as_ref().filter(|x| x.len() > self.threshold) {
+                let location = backend.put_object(&model.sha1, data).await?;
+                return Ok(moved(model, backend.storage_type(), location));
+            }
+        }
+        if self.compress {
+            return compress(model);
+        }
+        Ok(model)
+    }
+
+    /// Store the blobs `models` of a push made of `entries`. A storage keeping packs
+    /// takes the whole push whatever the size of its blobs, others take the large blobs
+    /// one by one.
+    pub async fn offload_push(
+        &self,
+        entries: &[Entry],
+        models: Vec<raw_blob::Model>,
+    ) -> Result<Vec<raw_blob::Model>, MegaError> {
+        if let Some(backend) = self.backend.as_ref().filter(|_| !entries.is_empty()) {
+            if let Some(location) = backend.put_pack(entries).await? {
+                return Ok(models
+                    .into_iter()
+                    .map(|x| moved(x, backend.storage_type(), location.clone()))
+                    .collect());
+            }
+        }
+        self.offload_all(models).await
+    }
+
+    pub async fn offload_all(
+        &self,
+        models: Vec<raw_blob::Model>,
+    ) -> Result<Vec<raw_blob::Model>, MegaError> {
+        let mut offloaded = Vec::with_capacity(models.len());
+        for model in models {
+            offloaded.push(self.offload(model).await?);
+        }
+        Ok(offloaded)
+    }
+
+    /// Fill in the content of `model` when it's kept outside the database.
+    pub async fn load(&self, mut model: raw_blob::Model) -> Result<raw_blob::Model, MegaError> {
+        let location = match model.storage_type {
+            StorageType::Database => return decompress(model),
+            StorageType::LocalFs | StorageType::PackFile => model.local_path.as_deref(),
+            StorageType::RemoteUrl => model.remote_url.as_deref(),
+        };
+        let location = location.ok_or_else(|| {
+            MegaError::with_message(&format!("blob {} has no location", model.sha1))
+        })?;
+        let data = if model.storage_type == StorageType::LocalFs {
+            // files written before the backend was switched stay readable
+            LocalFsStorage::read(location)?
+        } else {
+            match &self.backend {
+                Some(backend) if backend.storage_type() == model.storage_type => {
+                    backend.get_object(&model.sha1, location).await?
+                }
+                _ => {
+                    return Err(MegaError::with_message(&format!(
+                        "blob {} is kept in {} storage which isn't configured",
+                        model.sha1, model.storage_type
+                    )))
+                }
+            }
+        };
+        model.data = Some(data);
+        Ok(model)
+    }
+
+    /// Remove the content of blob `sha1` kept at `location` of a storage of `storage_type`.
+    /// Packs are removed whole by their storage, not blob by blob.
+    pub async fn remove(
+        &self,
+        sha1: &str,
+        storage_type: StorageType,
+        location: &str,
+    ) -> Result<(), MegaError> {
+        match storage_type {
+            StorageType::Database | StorageType::PackFile => Ok(()),
+            // files written before the backend was switched are removed too
+            StorageType::LocalFs => LocalFsStorage::remove(location),
+            StorageType::RemoteUrl => match &self.backend {
+                Some(backend) if backend.storage_type() == storage_type => {
+                    backend.delete_object(sha1, location).await
+                }
+                _ => Err(MegaError::with_message(&format!(
+                    "blob {} is kept in {} storage which isn't configured",
+                    sha1, storage_type
+                ))),
+            },
+        }
+    }
+
+    /// The storage keeping whole pushes, when that's the configured one.
+    pub fn pack_storage(&self) -> Option<Arc<PackStorage>> {
+        self.packs.clone()
+    }
+}
+
+/// `model` with its content moved to `location` of a storage of `storage_type`.
+fn moved(
+    mut model: raw_blob::Model,
+    storage_type: StorageType,
+    location: String,
+) -> raw_blob::Model {
+    match storage_type {
+        StorageType::RemoteUrl => model.remote_url = Some(location),
+        _ => model.local_path = Some(location),
+    }
+    model.storage_type = storage_type;
+    model.data = None;
+    model.compression = None;
+    model
+}
+
+/// `model` with its content compressed, unless that doesn't make it any smaller.
+pub fn compress(mut model: raw_blob::Model) -> Result<raw_blob::Model, MegaError> {
+    let Some(data) = model.data.as_ref().filter(|_| model.compression.is_none()) else {
+        return Ok(model);
+    };
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    if compressed.len() < data.len() {
+        model.data = Some(compressed);
+        model.compression = Some(CompressionType::Zlib);
+    }
+    Ok(model)
+}
+
+fn decompress(mut model: raw_blob::Model) -> Result<raw_blob::Model, MegaError> {
+    match (model.compression, &model.data) {
+        (Some(CompressionType::Zlib), Some(data)) => {
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(data.as_slice()).read_to_end(&mut decompressed)?;
+            model.data = Some(decompressed);
+            model.compression = None;
+            Ok(model)
+        }
+        _ => Ok(model),
+    }
+}
+
+pub fn init(config: &StorageConfig) -> BlobStore {
+    let backend: Arc<dyn BlobStorage> = match config.raw_obj_storage_type.as_str() {
+        "DATABASE" => return BlobStore::default().with_compression(config.compress_raw_obj),
+        "LOCAL" => Arc::new(LocalFsStorage::init(config.raw_obj_local_path.clone())),
+        "S3" => Arc::new(S3Storage::init(config)),
+        "PACK" => {
+            let packs = Arc::new(PackStorage::init(config.raw_obj_local_path.clone()));
+            return BlobStore::with_packs(packs, config.big_obj_threshold)
+                .with_compression(config.compress_raw_obj);
+        }
+        _ => unreachable!(
+            "Not supported config, raw_obj_storage_type should be 'DATABASE', 'LOCAL', 'S3' or 'PACK'"
+        ),
+    };
+    BlobStore::new(backend, config.big_obj_threshold).with_compression(config.compress_raw_obj)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, sync::Arc};
+
+    use callisto::db_enums::{CompressionType, StorageType};
+    use mercury::internal::object::blob::Blob;
+
+    use crate::blob_storage::{local_fs_storage::LocalFsStorage, BlobStore};
+
+    #[tokio::test]
+    async fn test_offload_and_load() {
+        let base_path = env::temp_dir().join("mega-blob-store-test");
+        let store = BlobStore::new(Arc::new(LocalFsStorage::init(base_path.clone())), 8);
+
+        let small = store
+            .offload(Blob::from_content("tiny").into())
+            .await
+            .unwrap();
+        assert_eq!(small.storage_type, StorageType::Database);
+        assert_eq!(small.data.as_deref(), Some("tiny".as_bytes()));
+
+        let blob = Blob::from_content("more than eight bytes");
+        let large = store.offload(blob.clone().into()).await.unwrap();
+        assert_eq!(large.storage_type, StorageType::LocalFs);
+        assert!(large.data.is_none());
+        assert!(large
+            .local_path
+            .as_deref()
+            .is_some_and(|x| x.starts_with(base_path.to_str().unwrap())));
+
+        let loaded = BlobStore::default().load(large).await.unwrap();
+        assert_eq!(loaded.data, Some(blob.data));
+        std::fs::remove_dir_all(base_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let store = BlobStore::default().with_compression(true);
+        let blob = Blob::from_content(&"a line repeated\n".repeat(100));
+        let stored = store.offload(blob.clone().into()).await.unwrap();
+        assert_eq!(stored.storage_type, StorageType::Database);
+        assert_eq!(stored.compression, Some(CompressionType::Zlib));
+        assert!(stored.data.as_ref().unwrap().len() < blob.data.len());
+        let loaded = store.load(stored).await.unwrap();
+        assert_eq!(loaded.data, Some(blob.data));
+        assert_eq!(loaded.compression, None);
+
+        // not worth it
+        let tiny = store.offload(Blob::from_content("a").into()).await.unwrap();
+        assert_eq!(tiny.compression, None);
+    }
+
+    #[tokio::test]
+    async fn test_load_unconfigured_remote() {
+        let mut model: callisto::raw_blob::Model = Blob::from_content("remote").into();
+        model.storage_type = StorageType::RemoteUrl;
+        model.remote_url = Some("http://localhost/mega/ab/cd".to_owned());
+        model.data = None;
+        assert!(BlobStore::default().load(model).await.is_err());
+    }
+}
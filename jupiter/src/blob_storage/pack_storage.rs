@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+use sha1::{Digest, Sha1};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use callisto::db_enums::StorageType;
+use common::errors::MegaError;
+use mercury::hash::SHA1;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::pack::idx::{IdxEntry, PackIdx};
+use mercury::internal::pack::{entry::Entry, utils::read_type_and_varint_size};
+
+use crate::blob_storage::BlobStorage;
+
+/// Objects kept in packfiles under `base_path`, one pack for the objects of each push
+/// with a version 2 `.idx` next to it, so git can read them too. Objects are stored
+/// whole rather than as deltas, one is read with a single seek.
+pub struct PackStorage {
+    base_path: PathBuf,
+    indexes: Mutex<HashMap<PathBuf, Arc<PackIdx>>>,
+}
+
+impl PackStorage {
+    pub fn init(base_path: PathBuf) -> PackStorage {
+        fs::create_dir_all(&base_path).expect("Create directory failed!");
+        PackStorage {
+            base_path,
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The `.idx` of `pack`, read once.
+    pub fn index(&self, pack: &Path) -> Result<Arc<PackIdx>, MegaError> {
+        if let Some(index) = self.indexes.lock().unwrap().get(pack) {
+            return Ok(index.clone());
+        }
+        let index = PackIdx::read(&pack.with_extension("idx"))
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        let index = Arc::new(index);
+        self.indexes
+            .lock()
+            .unwrap()
+            .insert(pack.to_owned(), index.clone());
+        Ok(index)
+    }
+
+    /// The packs written before `before`.
+    pub fn packs_before(&self, before: SystemTime) -> Result<Vec<String>, MegaError> {
+        let mut packs = vec![];
+        for entry in fs::read_dir(&self.base_path)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|x| x == "pack")
+                && fs::metadata(&path)?.modified()? < before
+            {
+                packs.push(path.to_string_lossy().into_owned());
+            }
+        }
+        Ok(packs)
+    }
+
+    /// Write `entries` into a new pack, returns its path.
+    fn write_pack(&self, entries: &[Entry]) -> Result<PathBuf, MegaError> {
+        let tmp = self
+            .base_path
+            .join(format!("tmp-{}", Uuid::new_v4().simple()));
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        let mut hash = Sha1::new();
+        let mut write = |data: &[u8]| -> Result<(), MegaError> {
+            hash.update(data);
+            Ok(file.write_all(data)?)
+        };
+
+        let header = pack_header(entries.len());
+        write(&header)?;
+
+        let mut objects = Vec::with_capacity(entries.len());
+        let mut offset = header.len() as u64;
+        for entry in entries {
+            let data = encode_object(entry)?;
+            let mut crc = Crc::new();
+            crc.update(&data);
+            objects.push(IdxEntry {
+                hash: entry.hash,
+                offset,
+                crc32: crc.sum(),
+            });
+            write(&data)?;
+            offset += data.len() as u64;
+        }
+        let checksum: [u8; 20] = hash.finalize().into();
+        file.write_all(&checksum)?;
+        file.flush()?;
+        drop(file);
+
+        let name = format!("pack-{}", hex::encode(checksum));
+        let pack = self.base_path.join(format!("{}.pack", name));
+        PackIdx::new(objects, SHA1(checksum))
+            .write(&self.base_path.join(format!("{}.idx", name)))
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        fs::rename(&tmp, &pack)?;
+        Ok(pack)
+    }
+
+    /// Send the objects of the stored `packs` as one pack. They are copied as stored,
+    /// none of them is a delta to be rebased.
+    pub async fn combine(
+        &self,
+        packs: &[String],
+        sender: mpsc::Sender<Vec<u8>>,
+    ) -> Result<(), MegaError> {
+        let mut total = 0;
+        for pack in packs {
+            total += self.index(Path::new(pack))?.len();
+        }
+        let mut hash = Sha1::new();
+        let sender = &sender;
+        let send = |data: Vec<u8>| async move {
+            sender
+                .send(data)
+                .await
+                .map_err(|_| MegaError::with_message("pack receiver closed"))
+        };
+
+        let header = pack_header(total);
+        hash.update(&header);
+        send(header).await?;
+        for pack in packs {
+            let mut file = File::open(pack)?;
+            let body = file.metadata()?.len().saturating_sub(12 + 20);
+            file.seek(SeekFrom::Start(12))?;
+            let mut body = file.take(body);
+            loop {
+                let mut chunk = vec![0; COMBINE_CHUNK_SIZE];
+                let n = body.read(&mut chunk)?;
+                if n == 0 {
+                    break;
+                }
+                chunk.truncate(n);
+                hash.update(&chunk);
+                send(chunk).await?;
+            }
+        }
+        send(hash.finalize().to_vec()).await
+    }
+}
+
+/// Bytes of a stored pack read at once while combining packs.
+const COMBINE_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[async_trait]
+impl BlobStorage for PackStorage {
+    fn storage_type(&self) -> StorageType {
+        StorageType::PackFile
+    }
+
+    async fn put_object(&self, sha1: &str, data: &[u8]) -> Result<String, MegaError> {
+        let entry = Entry {
+            obj_type: ObjectType::Blob,
+            data: data.to_vec(),
+            hash: sha1
+                .parse()
+                .map_err(|e: String| MegaError::with_message(&e))?,
+        };
+        self.put_pack(&[entry])
+            .await
+            .map(|x| x.expect("pack storage keeps packs"))
+    }
+
+    async fn put_pack(&self, entries: &[Entry]) -> Result<Option<String>, MegaError> {
+        let pack = self.write_pack(entries)?;
+        Ok(Some(pack.to_string_lossy().into_owned()))
+    }
+
+    async fn get_object(&self, sha1: &str, location: &str) -> Result<Vec<u8>, MegaError> {
+        let pack = Path::new(location);
+        let hash: SHA1 = sha1
+            .parse()
+            .map_err(|e: String| MegaError::with_message(&e))?;
+        let offset = self
+            .index(pack)?
+            .offset(&hash)
+            .ok_or_else(|| MegaError::with_message(&format!("{} isn't in {}", sha1, location)))?;
+        read_object(pack, offset)
+    }
+
+    /// The pack at `location` is removed with all of its objects, it's only done once
+    /// none of them is kept.
+    async fn delete_object(&self, _sha1: &str, location: &str) -> Result<(), MegaError> {
+        let pack = Path::new(location);
+        self.indexes.lock().unwrap().remove(pack);
+        for path in [pack.with_extension("idx"), pack.to_owned()] {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn pack_header(count: usize) -> Vec<u8> {
+    let mut header = b"PACK".to_vec();
+    header.extend(2u32.to_be_bytes());
+    header.extend((count as u32).to_be_bytes());
+    header
+}
+
+/// Pack representation of `entry`, its type and size header followed by its zlib
+/// compressed content.
+pub(crate) fn encode_object(entry: &Entry) -> Result<Vec<u8>, MegaError> {
+    let mut size = entry.data.len();
+    let mut byte = (entry.obj_type.to_u8() << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    let mut header = vec![];
+    while size > 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+    let mut encoder = ZlibEncoder::new(header, Compression::default());
+    encoder.write_all(&entry.data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Content of the whole object stored at `offset` of `pack`.
+fn read_object(pack: &Path, offset: u64) -> Result<Vec<u8>, MegaError> {
+    let mut file = File::open(pack)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let (_, size) = read_type_and_varint_size(&mut file, &mut 0)?;
+    let mut data = Vec::with_capacity(size);
+    ZlibDecoder::new(file).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use sha1::{Digest, Sha1};
+    use tokio::sync::mpsc;
+
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::pack::entry::Entry;
+
+    use crate::blob_storage::{pack_storage::PackStorage, BlobStorage};
+
+    #[tokio::test]
+    async fn test_put_and_get_pack() {
+        let base_path = env::temp_dir().join("mega-pack-storage-test");
+        let storage = PackStorage::init(base_path.clone());
+        let blobs: Vec<Blob> = (0..300)
+            .map(|i| Blob::from_content(&format!("blob {}\n", i).repeat(i)))
+            .collect();
+        let entries: Vec<Entry> = blobs.iter().cloned().map(Entry::from).collect();
+
+        let pack = storage.put_pack(&entries).await.unwrap().unwrap();
+        let index = storage.index(pack.as_ref()).unwrap();
+        assert_eq!(index.len(), blobs.len());
+        for blob in &blobs {
+            assert!(index.contains(&blob.id));
+            let data = storage
+                .get_object(&blob.id.to_string(), &pack)
+                .await
+                .unwrap();
+            assert_eq!(data, blob.data);
+        }
+        assert!(storage.get_object(&"0".repeat(40), &pack).await.is_err());
+
+        let other = storage
+            .put_object(&blobs[0].id.to_string(), &blobs[0].data)
+            .await
+            .unwrap();
+        let (sender, mut receiver) = mpsc::channel(16);
+        storage.combine(&[pack, other], sender).await.unwrap();
+        let mut combined = vec![];
+        while let Some(chunk) = receiver.recv().await {
+            combined.extend(chunk);
+        }
+        assert_eq!(&combined[..4], b"PACK");
+        assert_eq!(combined[8..12], 301u32.to_be_bytes());
+        let (body, checksum) = combined.split_at(combined.len() - 20);
+        assert_eq!(checksum, Sha1::digest(body).as_slice());
+        std::fs::remove_dir_all(base_path).unwrap();
+    }
+}
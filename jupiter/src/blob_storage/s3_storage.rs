@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{Client, Method, Url};
+use ring::{digest, hmac};
+
+use callisto::db_enums::StorageType;
+use common::config::StorageConfig;
+use common::errors::MegaError;
+
+use crate::blob_storage::BlobStorage;
+
+/// Blobs kept in a bucket of an S3 compatible object storage, addressed path style as
+/// `{endpoint}/{bucket}/{sha1[..2]}/{sha1[2..]}`. Requests are signed with AWS
+/// signature version 4.
+pub struct S3Storage {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Storage {
+    pub fn init(config: &StorageConfig) -> S3Storage {
+        let endpoint = Url::parse(&config.obs_endpoint).expect("Invalid obs_endpoint");
+        assert!(
+            !config.obs_bucket.is_empty(),
+            "obs_bucket is required for S3 storage"
+        );
+        S3Storage {
+            client: Client::new(),
+            endpoint,
+            bucket: config.obs_bucket.clone(),
+            region: config.obs_region.clone(),
+            access_key: config.obs_access_key.clone(),
+            secret_key: config.obs_secret_key.clone(),
+        }
+    }
+
+    fn object_url(&self, sha1: &str) -> Url {
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .expect("obs_endpoint can't be a base")
+            .pop_if_empty()
+            .extend([self.bucket.as_str(), &sha1[..2], &sha1[2..]]);
+        url
+    }
+
+    async fn send(&self, method: Method, url: Url, body: Vec<u8>) -> Result<Vec<u8>, MegaError> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(digest::digest(&digest::SHA256, &body));
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method.as_str(),
+            url.path(),
+            host,
+            payload_hash,
+            amz_date,
+            SIGNED_HEADERS,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(digest::digest(
+                &digest::SHA256,
+                canonical_request.as_bytes()
+            ))
+        );
+        let key = signing_key(&self.secret_key, &date, &self.region, "s3");
+        let signature = hex::encode(hmac::sign(&key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, SIGNED_HEADERS, signature
+        );
+
+        let response = self
+            .client
+            .request(method, url.clone())
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(MegaError::with_message(&format!(
+                "object storage answered {} for {}",
+                status, url
+            )));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Key requests of `date` to `service` in `region` are signed with.
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> hmac::Key {
+    let sign =
+        |key: &[u8], msg: &str| hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), msg.as_bytes());
+    let key = sign(format!("AWS4{}", secret_key).as_bytes(), date);
+    let key = sign(key.as_ref(), region);
+    let key = sign(key.as_ref(), service);
+    let key = sign(key.as_ref(), "aws4_request");
+    hmac::Key::new(hmac::HMAC_SHA256, key.as_ref())
+}
+
+#[async_trait]
+impl BlobStorage for S3Storage {
+    fn storage_type(&self) -> StorageType {
+        StorageType::RemoteUrl
+    }
+
+    async fn put_object(&self, sha1: &str, data: &[u8]) -> Result<String, MegaError> {
+        let url = self.object_url(sha1);
+        self.send(Method::PUT, url.clone(), data.to_vec()).await?;
+        Ok(url.to_string())
+    }
+
+    async fn get_object(&self, _sha1: &str, location: &str) -> Result<Vec<u8>, MegaError> {
+        let url = Url::parse(location).map_err(|e| MegaError::with_message(&e.to_string()))?;
+        self.send(Method::GET, url, Vec::new()).await
+    }
+
+    async fn delete_object(&self, _sha1: &str, location: &str) -> Result<(), MegaError> {
+        let url = Url::parse(location).map_err(|e| MegaError::with_message(&e.to_string()))?;
+        self.send(Method::DELETE, url, Vec::new()).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::hmac;
+
+    use super::signing_key;
+
+    #[test]
+    fn test_signing_key() {
+        // example from the AWS signature version 4 documentation
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        let tag = hmac::sign(&key, b"");
+        let expected = hmac::sign(
+            &hmac::Key::new(
+                hmac::HMAC_SHA256,
+                &hex::decode("f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d")
+                    .unwrap(),
+            ),
+            b"",
+        );
+        assert_eq!(tag.as_ref(), expected.as_ref());
+    }
+}
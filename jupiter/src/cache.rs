@@ -0,0 +1,338 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru_mem::{HeapSize, LruCache};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use callisto::{mega_commit, mega_refs, mega_tree};
+use common::config::CacheConfig;
+
+/// Refs, trees and commits of the monorepo read on almost every request, kept in an LRU
+/// of `mem_size` bytes in process, or in Redis when several instances share a database.
+/// Trees and commits never change once stored, a ref is dropped whenever it's written.
+///
+/// A cache of storages writing through a transaction is neither read nor filled, it
+/// drops the refs the transaction writes, and drops them again once it's committed.
+#[derive(Clone, Default)]
+pub struct MonoCache {
+    backend: Option<Backend>,
+    /// bumped by every ref dropped, a ref read before isn't cached
+    ref_epoch: Arc<AtomicU64>,
+    pending: Option<Arc<Mutex<Vec<Invalidation>>>>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Memory(Arc<Mutex<LruCache<String, Cached>>>),
+    Redis {
+        manager: ConnectionManager,
+        ttl: u64,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum Cached {
+    Ref(mega_refs::Model),
+    Tree(mega_tree::Model),
+    Commit(mega_commit::Model),
+}
+
+impl HeapSize for Cached {
+    fn heap_size(&self) -> usize {
+        match self {
+            Cached::Ref(x) => {
+                x.path.heap_size()
+                    + x.ref_name.heap_size()
+                    + x.ref_commit_hash.heap_size()
+                    + x.ref_tree_hash.heap_size()
+            }
+            Cached::Tree(x) => {
+                x.tree_id.heap_size() + x.sub_trees.heap_size() + x.commit_id.heap_size()
+            }
+            Cached::Commit(x) => {
+                x.commit_id.heap_size()
+                    + x.tree.heap_size()
+                    + x.parents_id.to_string().len()
+                    + x.author.heap_size()
+                    + x.committer.heap_size()
+                    + x.content.heap_size()
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Invalidation {
+    Ref(String),
+    RefsBelow(String),
+}
+
+const REF_PREFIX: &str = "mega:ref:";
+
+fn ref_key(path: &str) -> String {
+    format!("{}{}", REF_PREFIX, path)
+}
+
+fn tree_key(hash: &str) -> String {
+    format!("mega:tree:{}", hash)
+}
+
+fn commit_key(hash: &str) -> String {
+    format!("mega:commit:{}", hash)
+}
+
+impl MonoCache {
+    pub async fn init(config: &CacheConfig) -> MonoCache {
+        let backend = if !config.redis_url.is_empty() {
+            let client = redis::Client::open(config.redis_url.as_str()).expect("Invalid redis_url");
+            let manager = ConnectionManager::new(client)
+                .await
+                .expect("Failed to connect to redis");
+            Some(Backend::Redis {
+                manager,
+                ttl: config.redis_ttl,
+            })
+        } else if config.mem_size > 0 {
+            Some(Backend::Memory(Arc::new(Mutex::new(LruCache::new(
+                config.mem_size,
+            )))))
+        } else {
+            None
+        };
+        MonoCache {
+            backend,
+            ..Default::default()
+        }
+    }
+
+    /// The cache of storages writing through a transaction.
+    pub fn deferred(&self) -> MonoCache {
+        MonoCache {
+            backend: self.backend.clone(),
+            ref_epoch: self.ref_epoch.clone(),
+            pending: Some(Default::default()),
+        }
+    }
+
+    /// Drop the refs written by the committed transaction of a deferred cache again,
+    /// others may have cached what they read before the commit.
+    pub async fn commit(&self) {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        let pending = std::mem::take(&mut *pending.lock().unwrap());
+        for invalidation in pending {
+            self.drop_now(invalidation).await;
+        }
+    }
+
+    /// Pass to [`put_ref`](Self::put_ref) what this returned before the ref was read.
+    pub fn ref_epoch(&self) -> u64 {
+        self.ref_epoch.load(Ordering::SeqCst)
+    }
+
+    pub async fn get_ref(&self, path: &str) -> Option<mega_refs::Model> {
+        match self.get(&ref_key(path)).await? {
+            Cached::Ref(refs) => Some(refs),
+            _ => None,
+        }
+    }
+
+    pub async fn put_ref(&self, refs: &mega_refs::Model, epoch: u64) {
+        if self.ref_epoch() == epoch {
+            self.put(ref_key(&refs.path), Cached::Ref(refs.clone()))
+                .await;
+        }
+    }
+
+    pub async fn get_tree(&self, hash: &str) -> Option<mega_tree::Model> {
+        match self.get(&tree_key(hash)).await? {
+            Cached::Tree(tree) => Some(tree),
+            _ => None,
+        }
+    }
+
+    pub async fn put_tree(&self, tree: &mega_tree::Model) {
+        self.put(tree_key(&tree.tree_id), Cached::Tree(tree.clone()))
+            .await;
+    }
+
+    pub async fn get_commit(&self, hash: &str) -> Option<mega_commit::Model> {
+        match self.get(&commit_key(hash)).await? {
+            Cached::Commit(commit) => Some(commit),
+            _ => None,
+        }
+    }
+
+    pub async fn put_commit(&self, commit: &mega_commit::Model) {
+        self.put(
+            commit_key(&commit.commit_id),
+            Cached::Commit(commit.clone()),
+        )
+        .await;
+    }
+
+    pub async fn invalidate_ref(&self, path: &str) {
+        self.invalidate(Invalidation::Ref(path.to_owned())).await;
+    }
+
+    /// Drop the refs of `path` and the directories below it.
+    pub async fn invalidate_refs_below(&self, path: &str) {
+        self.invalidate(Invalidation::RefsBelow(path.to_owned()))
+            .await;
+    }
+
+    async fn invalidate(&self, invalidation: Invalidation) {
+        if let Some(pending) = &self.pending {
+            pending.lock().unwrap().push(invalidation.clone());
+        }
+        self.drop_now(invalidation).await;
+    }
+
+    async fn get(&self, key: &str) -> Option<Cached> {
+        if self.pending.is_some() {
+            return None;
+        }
+        match self.backend.as_ref()? {
+            Backend::Memory(lru) => lru.lock().unwrap().get(key).cloned(),
+            Backend::Redis { manager, .. } => {
+                let data: Option<Vec<u8>> = manager
+                    .clone()
+                    .get(key)
+                    .await
+                    .inspect_err(|e| tracing::warn!("Failed to read {} from redis: {}", key, e))
+                    .ok()?;
+                serde_json::from_slice(&data?).ok()
+            }
+        }
+    }
+
+    async fn put(&self, key: String, value: Cached) {
+        if self.pending.is_some() {
+            return;
+        }
+        match &self.backend {
+            None => {}
+            Some(Backend::Memory(lru)) => {
+                // an entry larger than the whole cache isn't kept
+                let _ = lru.lock().unwrap().insert(key, value);
+            }
+            Some(Backend::Redis { manager, ttl }) => {
+                let data = serde_json::to_vec(&value).unwrap();
+                if let Err(e) = manager.clone().set_ex::<_, _, ()>(&key, data, *ttl).await {
+                    tracing::warn!("Failed to write {} to redis: {}", key, e);
+                }
+            }
+        }
+    }
+
+    async fn drop_now(&self, invalidation: Invalidation) {
+        self.ref_epoch.fetch_add(1, Ordering::SeqCst);
+        let Some(backend) = &self.backend else {
+            return;
+        };
+        match (backend, invalidation) {
+            (Backend::Memory(lru), Invalidation::Ref(path)) => {
+                lru.lock().unwrap().remove(&ref_key(&path));
+            }
+            (Backend::Memory(lru), Invalidation::RefsBelow(path)) => {
+                let prefix = ref_key(&path);
+                lru.lock()
+                    .unwrap()
+                    .retain(|key, _| !key.starts_with(&prefix));
+            }
+            (Backend::Redis { manager, .. }, Invalidation::Ref(path)) => {
+                let key = ref_key(&path);
+                if let Err(e) = manager.clone().del::<_, ()>(&key).await {
+                    tracing::error!("Failed to drop {} from redis: {}", key, e);
+                }
+            }
+            (Backend::Redis { manager, .. }, Invalidation::RefsBelow(path)) => {
+                if let Err(e) = drop_below(manager.clone(), &ref_key(&path)).await {
+                    tracing::error!("Failed to drop refs below {} from redis: {}", path, e);
+                }
+            }
+        }
+    }
+}
+
+/// Delete the Redis keys starting with `prefix`.
+async fn drop_below(mut manager: ConnectionManager, prefix: &str) -> redis::RedisResult<()> {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    let mut keys: Vec<String> = vec![];
+    let mut iter = manager.scan_match::<_, String>(pattern).await?;
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    drop(iter);
+    if !keys.is_empty() {
+        manager.del::<_, ()>(keys).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use callisto::mega_refs;
+    use common::config::CacheConfig;
+
+    use crate::cache::MonoCache;
+
+    fn refs(path: &str, commit: &str) -> mega_refs::Model {
+        mega_refs::Model {
+            id: 1,
+            path: path.to_owned(),
+            ref_name: "main".to_owned(),
+            ref_commit_hash: commit.to_owned(),
+            ref_tree_hash: commit.to_owned(),
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ref_invalidation() {
+        let cache = MonoCache::init(&CacheConfig::default()).await;
+        for path in ["/", "/project", "/project/a", "/third-party"] {
+            cache.put_ref(&refs(path, "1"), cache.ref_epoch()).await;
+        }
+        assert!(cache.get_ref("/project").await.is_some());
+
+        cache.invalidate_ref("/").await;
+        assert!(cache.get_ref("/").await.is_none());
+        cache.invalidate_refs_below("/project").await;
+        assert!(cache.get_ref("/project").await.is_none());
+        assert!(cache.get_ref("/project/a").await.is_none());
+        assert!(cache.get_ref("/third-party").await.is_some());
+
+        // read before the ref was written
+        let epoch = cache.ref_epoch();
+        cache.invalidate_ref("/").await;
+        cache.put_ref(&refs("/", "1"), epoch).await;
+        assert!(cache.get_ref("/").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deferred() {
+        let cache = MonoCache::init(&CacheConfig::default()).await;
+        let deferred = cache.deferred();
+        deferred
+            .put_ref(&refs("/", "1"), deferred.ref_epoch())
+            .await;
+        assert!(cache.get_ref("/").await.is_none());
+
+        deferred.invalidate_ref("/").await;
+        cache.put_ref(&refs("/", "1"), cache.ref_epoch()).await;
+        assert!(deferred.get_ref("/").await.is_none());
+        deferred.commit().await;
+        assert!(cache.get_ref("/").await.is_none());
+    }
+}
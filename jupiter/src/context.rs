@@ -1,13 +1,28 @@
-use std::{env, path::PathBuf, sync::Arc};
+use std::{
+    env,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use sea_orm::{DatabaseConnection, TransactionTrait};
 
 use common::config::Config;
+use common::errors::MegaError;
 
 use crate::{
+    blob_storage,
+    cache::MonoCache,
     lfs_storage::{local_storage::LocalStorage, LfsStorage},
     storage::{
+        acl_storage::AclStorage, audit_storage::AuditStorage, check_storage::CheckStorage,
         git_db_storage::GitDbStorage, init::database_connection, issue_storage::IssueStorage,
         lfs_db_storage::LfsDbStorage, mono_storage::MonoStorage, mq_storage::MQStorage,
-        mr_storage::MrStorage, raw_db_storage::RawDbStorage, user_storage::UserStorage,
+        mr_storage::MrStorage, notification_storage::NotificationStorage,
+        protection_storage::ProtectionStorage, raw_db_storage::RawDbStorage,
+        release_storage::ReleaseStorage, search_storage::SearchStorage,
+        signature_storage::SignatureStorage, stats_storage::StatsStorage,
+        traffic_storage::TrafficStorage, transaction::StorageConnection, user_storage::UserStorage,
         ztm_storage::ZTMStorage,
     },
 };
@@ -38,12 +53,119 @@ impl Context {
         self.services.mr_storage()
     }
 
+    pub fn traffic_stg(&self) -> TrafficStorage {
+        self.services.traffic_storage()
+    }
+
+    pub fn check_stg(&self) -> CheckStorage {
+        self.services.check_storage()
+    }
+
+    pub fn search_stg(&self) -> SearchStorage {
+        self.services.search_storage()
+    }
+
+    pub fn release_stg(&self) -> ReleaseStorage {
+        self.services.release_storage()
+    }
+
+    pub fn protection_stg(&self) -> ProtectionStorage {
+        self.services.protection_storage()
+    }
+
+    pub fn acl_stg(&self) -> AclStorage {
+        self.services.acl_storage()
+    }
+
+    pub fn signature_stg(&self) -> SignatureStorage {
+        self.services.signature_storage()
+    }
+
+    pub fn stats_stg(&self) -> StatsStorage {
+        self.services.stats_storage()
+    }
+
+    pub fn audit_stg(&self) -> AuditStorage {
+        self.services.audit_storage()
+    }
+
+    pub fn notification_stg(&self) -> NotificationStorage {
+        self.services.notification_storage()
+    }
+
+    /// Run `f` on a context whose monorepo, import repo and MR storages write through one
+    /// database transaction. It's committed when `f` succeeds and rolled back when `f`
+    /// fails or panics, so a merge that breaks off half way leaves no trace.
+    ///
+    /// Tasks spawned by `f` must be finished when it returns, a transaction still in use
+    /// is rolled back.
+    pub async fn transaction<T, E, F, Fut>(&self, f: F) -> Result<T, E>
+    where
+        E: From<MegaError>,
+        F: FnOnce(Context) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let txn = self
+            .services
+            .connection
+            .begin()
+            .await
+            .map_err(MegaError::from)?;
+        let txn = Arc::new(txn);
+        let connection = StorageConnection::Transaction(txn.clone());
+        let mut services = Service::clone(&self.services);
+        services.mono_storage = self
+            .services
+            .mono_storage
+            .in_transaction(connection.clone());
+        services.git_db_storage = self
+            .services
+            .git_db_storage
+            .in_transaction(connection.clone());
+        services.mr_storage.connection = connection;
+        let mono_storage = services.mono_storage.clone();
+        let context = Context {
+            services: Arc::new(services),
+            config: self.config.clone(),
+        };
+
+        let res = f(context).await;
+        let Ok(txn) = Arc::try_unwrap(txn) else {
+            return Err(MegaError::with_message("transaction still in use after it ended").into());
+        };
+        match res {
+            Ok(value) => {
+                txn.commit().await.map_err(MegaError::from)?;
+                mono_storage.transaction_committed().await;
+                Ok(value)
+            }
+            Err(e) => {
+                txn.rollback().await.map_err(MegaError::from)?;
+                Err(e)
+            }
+        }
+    }
+
     pub fn mock() -> Self {
         Context {
             services: Service::mock(),
             config: Config::default(),
         }
     }
+
+    /// A context on a new sqlite database in `dir`, where blobs, LFS objects and decoded
+    /// packs are kept too. For tests that read back what they store.
+    pub async fn sqlite(dir: &Path) -> Self {
+        let mut config = Config::default();
+        config.base_dir = dir.to_owned();
+        config.database.db_path = dir.join("mega.db").to_string_lossy().into_owned();
+        config.database.db_type = "sqlite".to_owned();
+        config.database.min_connection = 1;
+        config.storage.raw_obj_local_path = dir.join("objects");
+        config.lfs.lfs_obj_local_path = dir.join("lfs");
+        config.pack.pack_decode_cache_path = dir.join("cache");
+        Context::new(config).await
+    }
 }
 
 #[derive(Clone)]
@@ -57,23 +179,47 @@ pub struct Service {
     user_storage: UserStorage,
     mr_storage: MrStorage,
     issue_storage: IssueStorage,
+    traffic_storage: TrafficStorage,
+    check_storage: CheckStorage,
+    search_storage: SearchStorage,
+    release_storage: ReleaseStorage,
+    protection_storage: ProtectionStorage,
+    acl_storage: AclStorage,
+    signature_storage: SignatureStorage,
+    stats_storage: StatsStorage,
+    audit_storage: AuditStorage,
+    notification_storage: NotificationStorage,
     pub lfs_storage: Arc<dyn LfsStorage>,
+    pub(crate) connection: Arc<DatabaseConnection>,
 }
 
 impl Service {
     async fn new(config: &Config) -> Service {
         let connection = Arc::new(database_connection(&config.database).await);
+        let blob_store = blob_storage::init(&config.storage);
+        let cache = MonoCache::init(&config.cache).await;
         Service {
-            mono_storage: MonoStorage::new(connection.clone()).await,
-            git_db_storage: GitDbStorage::new(connection.clone()).await,
-            raw_db_storage: RawDbStorage::new(connection.clone()).await,
+            mono_storage: MonoStorage::new(connection.clone(), blob_store.clone(), cache).await,
+            git_db_storage: GitDbStorage::new(connection.clone(), blob_store.clone()).await,
+            raw_db_storage: RawDbStorage::new(connection.clone(), blob_store).await,
             lfs_db_storage: LfsDbStorage::new(connection.clone()).await,
             ztm_storage: ZTMStorage::new(connection.clone()).await,
             mq_storage: MQStorage::new(connection.clone()).await,
             user_storage: UserStorage::new(connection.clone()).await,
             mr_storage: MrStorage::new(connection.clone()).await,
             issue_storage: IssueStorage::new(connection.clone()).await,
+            traffic_storage: TrafficStorage::new(connection.clone()).await,
+            check_storage: CheckStorage::new(connection.clone()).await,
+            search_storage: SearchStorage::new(connection.clone()).await,
+            release_storage: ReleaseStorage::new(connection.clone()).await,
+            protection_storage: ProtectionStorage::new(connection.clone()).await,
+            acl_storage: AclStorage::new(connection.clone()).await,
+            signature_storage: SignatureStorage::new(connection.clone()).await,
+            stats_storage: StatsStorage::new(connection.clone()).await,
+            audit_storage: AuditStorage::new(connection.clone()).await,
+            notification_storage: NotificationStorage::new(connection.clone()).await,
             lfs_storage: Arc::new(LocalStorage::init(config.lfs.lfs_obj_local_path.clone())),
+            connection,
         }
     }
 
@@ -93,6 +239,46 @@ impl Service {
         self.user_storage.clone()
     }
 
+    pub fn traffic_storage(&self) -> TrafficStorage {
+        self.traffic_storage.clone()
+    }
+
+    pub fn check_storage(&self) -> CheckStorage {
+        self.check_storage.clone()
+    }
+
+    pub fn search_storage(&self) -> SearchStorage {
+        self.search_storage.clone()
+    }
+
+    pub fn release_storage(&self) -> ReleaseStorage {
+        self.release_storage.clone()
+    }
+
+    pub fn protection_storage(&self) -> ProtectionStorage {
+        self.protection_storage.clone()
+    }
+
+    pub fn acl_storage(&self) -> AclStorage {
+        self.acl_storage.clone()
+    }
+
+    pub fn signature_storage(&self) -> SignatureStorage {
+        self.signature_storage.clone()
+    }
+
+    pub fn stats_storage(&self) -> StatsStorage {
+        self.stats_storage.clone()
+    }
+
+    pub fn audit_storage(&self) -> AuditStorage {
+        self.audit_storage.clone()
+    }
+
+    pub fn notification_storage(&self) -> NotificationStorage {
+        self.notification_storage.clone()
+    }
+
     fn mock() -> Arc<Self> {
         Arc::new(Self {
             mono_storage: MonoStorage::mock(),
@@ -107,6 +293,17 @@ impl Service {
             )),
             mr_storage: MrStorage::mock(),
             issue_storage: IssueStorage::mock(),
+            traffic_storage: TrafficStorage::mock(),
+            check_storage: CheckStorage::mock(),
+            search_storage: SearchStorage::mock(),
+            release_storage: ReleaseStorage::mock(),
+            protection_storage: ProtectionStorage::mock(),
+            acl_storage: AclStorage::mock(),
+            signature_storage: SignatureStorage::mock(),
+            stats_storage: StatsStorage::mock(),
+            audit_storage: AuditStorage::mock(),
+            notification_storage: NotificationStorage::mock(),
+            connection: Arc::new(DatabaseConnection::default()),
         })
     }
 }
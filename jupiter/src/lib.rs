@@ -1,3 +1,6 @@
+pub mod backup;
+pub mod blob_storage;
+pub mod cache;
 pub mod context;
 pub mod lfs_storage;
 pub mod storage;
@@ -0,0 +1,406 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+
+use callisto::db_enums::{AclPermission, AclPrincipal};
+use callisto::{mega_path_acl, mega_team, mega_team_member};
+use common::errors::MegaError;
+use common::utils::generate_id;
+
+/// Whether `path` is `dir` or lies below it.
+fn is_within(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The path ACL as seen by one user.
+///
+/// A path is open to everyone until a grant is made on it or on one of its parent
+/// directories, from then on the grants of the deepest such directory decide who can
+/// access it. Write access includes read access, the monorepo admin can access everything.
+#[derive(Debug, Clone, Default)]
+pub struct PathAcl {
+    entries: Vec<mega_path_acl::Model>,
+    user: Option<String>,
+    teams: Vec<String>,
+    admin: bool,
+}
+
+impl PathAcl {
+    pub fn new(
+        entries: Vec<mega_path_acl::Model>,
+        user: Option<String>,
+        teams: Vec<String>,
+        admin: bool,
+    ) -> Self {
+        PathAcl {
+            entries,
+            user,
+            teams,
+            admin,
+        }
+    }
+
+    pub fn allows(&self, path: &str, permission: AclPermission) -> bool {
+        if self.admin {
+            return true;
+        }
+        let covering: Vec<&mega_path_acl::Model> = self
+            .entries
+            .iter()
+            .filter(|x| is_within(path, &x.path))
+            .collect();
+        let Some(deepest) = covering.iter().map(|x| x.path.len()).max() else {
+            return true;
+        };
+        covering
+            .into_iter()
+            .filter(|x| x.path.len() == deepest)
+            .any(|x| x.permission >= permission && self.is_grantee(x))
+    }
+
+    /// Directories below `path` the user can't read, the outermost ones only. Content
+    /// served for `path` has to leave them out.
+    pub fn hidden_below(&self, path: &str) -> Vec<String> {
+        let mut candidates: Vec<&str> = self
+            .entries
+            .iter()
+            .map(|x| x.path.as_str())
+            .filter(|x| *x != path && is_within(x, path))
+            .filter(|x| !self.allows(x, AclPermission::Read))
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        let mut hidden: Vec<String> = Vec::new();
+        for dir in candidates {
+            if !hidden.iter().any(|x| is_within(dir, x)) {
+                hidden.push(dir.to_owned());
+            }
+        }
+        hidden
+    }
+
+    fn is_grantee(&self, entry: &mega_path_acl::Model) -> bool {
+        match entry.principal_type {
+            AclPrincipal::User => self.user.as_deref() == Some(entry.principal.as_str()),
+            AclPrincipal::Team => self.teams.contains(&entry.principal),
+        }
+    }
+}
+
+/// A reviewer written as `team:<name>` stands for the members of the team.
+pub fn team_reference(name: &str) -> Option<&str> {
+    name.strip_prefix("team:")
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+}
+
+#[derive(Clone)]
+pub struct AclStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl AclStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        AclStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        AclStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// The ACL as seen by `username`, anonymous requests only get the open paths.
+    pub async fn load_path_acl(
+        &self,
+        username: Option<&str>,
+        admin: &str,
+    ) -> Result<PathAcl, MegaError> {
+        let entries = self.get_acls().await?;
+        let teams = match username {
+            Some(username) => self
+                .get_user_teams(username)
+                .await?
+                .into_iter()
+                .map(|x| x.team)
+                .collect(),
+            None => vec![],
+        };
+        Ok(PathAcl::new(
+            entries,
+            username.map(str::to_owned),
+            teams,
+            username == Some(admin),
+        ))
+    }
+
+    pub async fn save_acl(
+        &self,
+        acl: mega_path_acl::Model,
+    ) -> Result<mega_path_acl::Model, MegaError> {
+        Ok(acl
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    pub async fn delete_acl(&self, id: i64) -> Result<(), MegaError> {
+        mega_path_acl::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_acl(&self, id: i64) -> Result<Option<mega_path_acl::Model>, MegaError> {
+        Ok(mega_path_acl::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_acls(&self) -> Result<Vec<mega_path_acl::Model>, MegaError> {
+        Ok(mega_path_acl::Entity::find()
+            .order_by_asc(mega_path_acl::Column::Path)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The grant of `principal` on exactly `path`.
+    pub async fn get_acl_by_principal(
+        &self,
+        path: &str,
+        principal_type: AclPrincipal,
+        principal: &str,
+    ) -> Result<Option<mega_path_acl::Model>, MegaError> {
+        Ok(mega_path_acl::Entity::find()
+            .filter(mega_path_acl::Column::Path.eq(path))
+            .filter(mega_path_acl::Column::PrincipalType.eq(principal_type))
+            .filter(mega_path_acl::Column::Principal.eq(principal))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_team(&self, team: mega_team::Model) -> Result<mega_team::Model, MegaError> {
+        Ok(team
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    pub async fn update_team(&self, team: mega_team::Model) -> Result<(), MegaError> {
+        let mut a_model = team.into_active_model().reset_all();
+        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        a_model.update(self.get_connection()).await?;
+        Ok(())
+    }
+
+    pub async fn get_team(&self, name: &str) -> Result<Option<mega_team::Model>, MegaError> {
+        Ok(mega_team::Entity::find()
+            .filter(mega_team::Column::Name.eq(name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_teams(&self) -> Result<Vec<mega_team::Model>, MegaError> {
+        Ok(mega_team::Entity::find()
+            .order_by_asc(mega_team::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Remove a team with its members and the grants made to it.
+    pub async fn delete_team(&self, name: &str) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        mega_team_member::Entity::delete_many()
+            .filter(mega_team_member::Column::Team.eq(name))
+            .exec(&txn)
+            .await?;
+        mega_path_acl::Entity::delete_many()
+            .filter(mega_path_acl::Column::PrincipalType.eq(AclPrincipal::Team))
+            .filter(mega_path_acl::Column::Principal.eq(name))
+            .exec(&txn)
+            .await?;
+        mega_team::Entity::delete_many()
+            .filter(mega_team::Column::Name.eq(name))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Remove a user from every team, when the user is deleted.
+    pub async fn remove_user_memberships(&self, username: &str) -> Result<(), MegaError> {
+        mega_team_member::Entity::delete_many()
+            .filter(mega_team_member::Column::Username.eq(username))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Replace the `team:<name>` entries of `names` with the members of the team, in
+    /// order and each name once. A team that doesn't exist is left out.
+    pub async fn expand_teams(&self, names: &[String]) -> Result<Vec<String>, MegaError> {
+        let mut res: Vec<String> = Vec::new();
+        for name in names {
+            let members = match team_reference(name) {
+                Some(team) => self
+                    .get_team_members(team)
+                    .await?
+                    .into_iter()
+                    .map(|x| x.username)
+                    .collect(),
+                None => vec![name.clone()],
+            };
+            for member in members {
+                if !res.contains(&member) {
+                    res.push(member);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Add `username` to `team`, adding a member twice keeps one.
+    pub async fn add_team_member(&self, team: &str, username: &str) -> Result<(), MegaError> {
+        let exists = mega_team_member::Entity::find()
+            .filter(mega_team_member::Column::Team.eq(team))
+            .filter(mega_team_member::Column::Username.eq(username))
+            .one(self.get_connection())
+            .await?;
+        if exists.is_none() {
+            mega_team_member::Model {
+                id: generate_id(),
+                team: team.to_owned(),
+                username: username.to_owned(),
+                created_at: chrono::Utc::now().naive_utc(),
+            }
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn remove_team_member(&self, team: &str, username: &str) -> Result<(), MegaError> {
+        mega_team_member::Entity::delete_many()
+            .filter(mega_team_member::Column::Team.eq(team))
+            .filter(mega_team_member::Column::Username.eq(username))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_team_members(
+        &self,
+        team: &str,
+    ) -> Result<Vec<mega_team_member::Model>, MegaError> {
+        Ok(mega_team_member::Entity::find()
+            .filter(mega_team_member::Column::Team.eq(team))
+            .order_by_asc(mega_team_member::Column::Username)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_user_teams(
+        &self,
+        username: &str,
+    ) -> Result<Vec<mega_team_member::Model>, MegaError> {
+        Ok(mega_team_member::Entity::find()
+            .filter(mega_team_member::Column::Username.eq(username))
+            .all(self.get_connection())
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use callisto::db_enums::{AclPermission, AclPrincipal};
+    use callisto::mega_path_acl;
+
+    use super::{team_reference, PathAcl};
+
+    fn grant(
+        path: &str,
+        principal_type: AclPrincipal,
+        principal: &str,
+        permission: AclPermission,
+    ) -> mega_path_acl::Model {
+        mega_path_acl::Model {
+            id: 0,
+            path: path.to_owned(),
+            principal_type,
+            principal: principal.to_owned(),
+            permission,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_path_acl() {
+        let entries = vec![
+            grant(
+                "/project/secret",
+                AclPrincipal::Team,
+                "infra",
+                AclPermission::Read,
+            ),
+            grant(
+                "/project/secret/keys",
+                AclPrincipal::User,
+                "bob",
+                AclPermission::Write,
+            ),
+            grant(
+                "/project/app",
+                AclPrincipal::User,
+                "alice",
+                AclPermission::Write,
+            ),
+        ];
+        let alice = PathAcl::new(entries.clone(), Some("alice".to_owned()), vec![], false);
+        assert!(alice.allows("/project", AclPermission::Write));
+        assert!(alice.allows("/project/app/src", AclPermission::Write));
+        assert!(!alice.allows("/project/secret", AclPermission::Read));
+        assert!(alice.allows("/project/secrets", AclPermission::Read));
+        assert_eq!(alice.hidden_below("/project"), vec!["/project/secret"]);
+        assert_eq!(alice.hidden_below("/"), vec!["/project/secret"]);
+
+        let infra = PathAcl::new(
+            entries.clone(),
+            Some("carol".to_owned()),
+            vec!["infra".to_owned()],
+            false,
+        );
+        assert!(infra.allows("/project/secret/a", AclPermission::Read));
+        assert!(!infra.allows("/project/secret/a", AclPermission::Write));
+        assert!(!infra.allows("/project/app", AclPermission::Read));
+        assert_eq!(
+            infra.hidden_below("/project"),
+            vec!["/project/app", "/project/secret/keys"]
+        );
+
+        let anonymous = PathAcl::new(entries.clone(), None, vec![], false);
+        assert!(anonymous.allows("/doc", AclPermission::Write));
+        assert!(!anonymous.allows("/project/app", AclPermission::Read));
+
+        let admin = PathAcl::new(entries, Some("admin".to_owned()), vec![], true);
+        assert!(admin.hidden_below("/").is_empty());
+    }
+
+    #[test]
+    fn test_team_reference() {
+        assert_eq!(team_reference("team:infra"), Some("infra"));
+        assert_eq!(team_reference("team: "), None);
+        assert_eq!(team_reference("alice"), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder,
+};
+
+use callisto::audit_log;
+use callisto::db_enums::AuditAction;
+use common::errors::MegaError;
+use common::model::Pagination;
+
+#[derive(Clone)]
+pub struct AuditStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+/// Which records to list, every field left out matches all.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    /// the target and everything below it
+    pub target: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl AuditStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        AuditStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        AuditStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Store a record, one stored already is left as is.
+    pub async fn save(&self, model: audit_log::Model) -> Result<(), MegaError> {
+        audit_log::Entity::insert(model.into_active_model())
+            .on_conflict(
+                OnConflict::column(audit_log::Column::Id)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Records matching `filter`, the latest first, with the number of all matching.
+    pub async fn get_logs(
+        &self,
+        filter: &AuditFilter,
+        page: Pagination,
+    ) -> Result<(Vec<audit_log::Model>, u64), MegaError> {
+        let mut query = audit_log::Entity::find();
+        if let Some(actor) = &filter.actor {
+            query = query.filter(audit_log::Column::Actor.eq(actor));
+        }
+        if let Some(action) = filter.action {
+            query = query.filter(audit_log::Column::Action.eq(action));
+        }
+        if let Some(target) = filter
+            .target
+            .as_deref()
+            .map(|x| x.trim_end_matches('/'))
+            .filter(|x| !x.is_empty())
+        {
+            query = query.filter(
+                Condition::any()
+                    .add(audit_log::Column::Target.eq(target))
+                    .add(audit_log::Column::Target.starts_with(format!("{}/", target))),
+            );
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(audit_log::Column::CreatedAt.gte(since));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(audit_log::Column::CreatedAt.lt(until));
+        }
+        let paginator = query
+            .order_by_desc(audit_log::Column::CreatedAt)
+            .order_by_desc(audit_log::Column::Id)
+            .paginate(self.get_connection(), page.per_page);
+        let total = paginator.num_items().await?;
+        Ok(paginator
+            .fetch_page(page.page - 1)
+            .await
+            .map(|m| (m, total))?)
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, Set,
+};
+
+use callisto::{check_annotations, check_runs};
+use common::errors::MegaError;
+
+use crate::storage::batch_save_model;
+
+#[derive(Clone)]
+pub struct CheckStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl CheckStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        CheckStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        CheckStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    pub async fn save_check_run(
+        &self,
+        run: check_runs::Model,
+    ) -> Result<check_runs::Model, MegaError> {
+        Ok(run.into_active_model().insert(self.get_connection()).await?)
+    }
+
+    pub async fn get_check_run(&self, id: i64) -> Result<Option<check_runs::Model>, MegaError> {
+        Ok(check_runs::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn update_check_run(
+        &self,
+        run: check_runs::Model,
+    ) -> Result<check_runs::Model, MegaError> {
+        let mut a_model = run.into_active_model();
+        a_model = a_model.reset_all();
+        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        Ok(a_model.update(self.get_connection()).await?)
+    }
+
+    /// All check runs reported for a commit, newest first.
+    pub async fn get_check_runs_by_commit(
+        &self,
+        commit_id: &str,
+    ) -> Result<Vec<check_runs::Model>, MegaError> {
+        Ok(check_runs::Entity::find()
+            .filter(check_runs::Column::CommitId.eq(commit_id))
+            .order_by_desc(check_runs::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_annotations(
+        &self,
+        annotations: Vec<check_annotations::Model>,
+    ) -> Result<(), MegaError> {
+        let save_models = annotations
+            .into_iter()
+            .map(|x| x.into_active_model())
+            .collect();
+        batch_save_model(self.get_connection(), save_models).await
+    }
+
+    pub async fn get_annotations(
+        &self,
+        check_run_ids: Vec<i64>,
+    ) -> Result<Vec<check_annotations::Model>, MegaError> {
+        Ok(check_annotations::Entity::find()
+            .filter(check_annotations::Column::CheckRunId.is_in(check_run_ids))
+            .order_by_asc(check_annotations::Column::Path)
+            .order_by_asc(check_annotations::Column::StartLine)
+            .all(self.get_connection())
+            .await?)
+    }
+}
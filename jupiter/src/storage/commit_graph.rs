@@ -0,0 +1,425 @@
+//! Ancestry queries of the monorepo answered from the commit graph, a row for each commit
+//! with its parents and generation number. A walk down the history stops once it's
+//! below the generation of the commit looked for, and reads the rows of the generations
+//! right below it in one query, rather than one query for each commit.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use sea_orm::{ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect};
+
+use callisto::{mega_commit, mega_commit_graph};
+use common::errors::MegaError;
+use common::utils::generate_id;
+use mercury::internal::object::commit::Commit;
+
+use crate::storage::{batch_save_model, mono_storage::MonoStorage};
+
+/// Generations read at once while walking down the history.
+const PREFETCH_GENERATIONS: i64 = 256;
+
+/// Upper bound of the rows of one prefetch, histories with many parallel branches are
+/// read in several.
+const PREFETCH_ROWS: u64 = 2000;
+
+impl MonoStorage {
+    /// Add `commits` to the commit graph. Their parents missing from it, like commits
+    /// stored before it existed, are added too.
+    pub async fn save_commit_graph(&self, commits: &[mega_commit::Model]) -> Result<(), MegaError> {
+        let mut pending: HashMap<String, GraphCommit> = commits
+            .iter()
+            .map(|x| (x.commit_id.clone(), GraphCommit::from(x)))
+            .collect();
+        let mut known: HashMap<String, i64> = HashMap::new();
+        loop {
+            let missing: HashSet<String> = pending
+                .values()
+                .flat_map(|x| x.parents.iter())
+                .filter(|x| !pending.contains_key(*x) && !known.contains_key(*x))
+                .cloned()
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+            let missing: Vec<String> = missing.into_iter().collect();
+            for node in self.get_graph_rows(missing.clone()).await? {
+                known.insert(node.commit_id, node.generation);
+            }
+            let missing: Vec<String> = missing
+                .into_iter()
+                .filter(|x| !known.contains_key(x))
+                .collect();
+            if missing.is_empty() {
+                continue;
+            }
+            for commit in self.get_commits_by_hashes(&missing).await? {
+                pending.insert(commit.commit_id.clone(), GraphCommit::from(&commit));
+            }
+            for id in missing {
+                if !pending.contains_key(&id) {
+                    // a parent that was never stored counts as below every commit
+                    known.insert(id, 0);
+                }
+            }
+        }
+
+        let generations = assign_generations(&pending, &known);
+        let now = chrono::Utc::now().naive_utc();
+        let rows: Vec<mega_commit_graph::ActiveModel> = pending
+            .into_values()
+            .map(|x| {
+                mega_commit_graph::Model {
+                    id: generate_id(),
+                    generation: generations[&x.id],
+                    commit_id: x.id,
+                    parents_id: x.parents.into(),
+                    commit_time: x.time,
+                    created_at: now,
+                }
+                .into_active_model()
+            })
+            .collect();
+        batch_save_model(self.get_connection(), rows).await
+    }
+
+    /// Whether `ancestor` can be reached from `head` through parents, a commit counts as
+    /// its own ancestor.
+    pub async fn is_ancestor(&self, ancestor: &str, head: &str) -> Result<bool, MegaError> {
+        if ancestor == head {
+            return Ok(true);
+        }
+        let mut walk = GraphWalk::new(self);
+        let Some(target) = walk.nodes(vec![ancestor.to_owned()]).await?.pop() else {
+            return Ok(false);
+        };
+        let mut visited = HashSet::new();
+        let mut generation = walk.nodes(vec![head.to_owned()]).await?;
+        while !generation.is_empty() {
+            let mut next = vec![];
+            for node in generation {
+                if node.commit_id == target.commit_id {
+                    return Ok(true);
+                }
+                if node.generation > target.generation && visited.insert(node.commit_id.clone()) {
+                    next.extend(walk.parents(&node).await?);
+                }
+            }
+            generation = next;
+        }
+        Ok(false)
+    }
+
+    /// The best common ancestor of `a` and `b`, the one with the highest generation when
+    /// there are several. `None` when their histories are unrelated.
+    pub async fn merge_base(&self, a: &str, b: &str) -> Result<Option<String>, MegaError> {
+        const A: u8 = 1;
+        const B: u8 = 2;
+        let mut walk = GraphWalk::new(self);
+        let mut flags: HashMap<String, u8> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for (id, flag) in [(a, A), (b, B)] {
+            for node in walk.nodes(vec![id.to_owned()]).await? {
+                if !flags.contains_key(&node.commit_id) {
+                    queue.push(Queued(node.clone()));
+                }
+                *flags.entry(node.commit_id).or_default() |= flag;
+            }
+        }
+        // children come out of the queue before their parents, so a commit has all its
+        // flags once it's popped and the first one reached from both is the best
+        while let Some(Queued(node)) = queue.pop() {
+            let flag = flags[&node.commit_id];
+            if flag == A | B {
+                return Ok(Some(node.commit_id));
+            }
+            for parent in walk.parents(&node).await? {
+                let parent_flag = flags.entry(parent.commit_id.clone()).or_default();
+                if *parent_flag == 0 {
+                    queue.push(Queued(parent));
+                }
+                *parent_flag |= flag;
+            }
+        }
+        Ok(None)
+    }
+
+    /// `start` and its ancestors, at most `limit` of them. Commits come after all their
+    /// children, newer first among commits of the same generation.
+    pub async fn walk(
+        &self,
+        start: &str,
+        limit: usize,
+    ) -> Result<Vec<mega_commit_graph::Model>, MegaError> {
+        let mut walk = GraphWalk::new(self);
+        let mut seen = HashSet::from([start.to_owned()]);
+        let mut queue: BinaryHeap<Queued> = walk
+            .nodes(vec![start.to_owned()])
+            .await?
+            .into_iter()
+            .map(Queued)
+            .collect();
+        let mut commits = vec![];
+        while commits.len() < limit {
+            let Some(Queued(node)) = queue.pop() else {
+                break;
+            };
+            for parent in walk.parents(&node).await? {
+                if seen.insert(parent.commit_id.clone()) {
+                    queue.push(Queued(parent));
+                }
+            }
+            commits.push(node);
+        }
+        Ok(commits)
+    }
+
+    async fn get_graph_rows(
+        &self,
+        ids: Vec<String>,
+    ) -> Result<Vec<mega_commit_graph::Model>, MegaError> {
+        Ok(mega_commit_graph::Entity::find()
+            .filter(mega_commit_graph::Column::CommitId.is_in(ids))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Graph rows of `ids`, commits stored before the graph existed are added to it
+    /// first. Ids that aren't commits are skipped.
+    async fn get_graph_nodes(
+        &self,
+        ids: Vec<String>,
+    ) -> Result<Vec<mega_commit_graph::Model>, MegaError> {
+        let mut nodes = self.get_graph_rows(ids.clone()).await?;
+        let found: HashSet<&str> = nodes.iter().map(|x| x.commit_id.as_str()).collect();
+        let missing: Vec<String> = ids
+            .into_iter()
+            .filter(|x| !found.contains(x.as_str()))
+            .collect();
+        if !missing.is_empty() {
+            let commits = self.get_commits_by_hashes(&missing).await?;
+            if !commits.is_empty() {
+                self.save_commit_graph(&commits).await?;
+                nodes.extend(self.get_graph_rows(missing).await?);
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+/// What the graph keeps of a commit about to be added.
+struct GraphCommit {
+    id: String,
+    parents: Vec<String>,
+    time: i64,
+}
+
+impl From<&mega_commit::Model> for GraphCommit {
+    fn from(value: &mega_commit::Model) -> Self {
+        let commit = Commit::from(value.clone());
+        GraphCommit {
+            id: value.commit_id.clone(),
+            parents: commit
+                .parent_commit_ids
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            time: commit.committer.timestamp as i64,
+        }
+    }
+}
+
+/// Generation of each of the `pending` commits, `known` has those of the parents already
+/// in the graph.
+fn assign_generations(
+    pending: &HashMap<String, GraphCommit>,
+    known: &HashMap<String, i64>,
+) -> HashMap<String, i64> {
+    let mut generations = known.clone();
+    let mut waiting: HashMap<&str, usize> = HashMap::new();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut ready = vec![];
+    for commit in pending.values() {
+        let pending_parents: Vec<&String> = commit
+            .parents
+            .iter()
+            .filter(|x| pending.contains_key(*x))
+            .collect();
+        for parent in &pending_parents {
+            children
+                .entry(parent.as_str())
+                .or_default()
+                .push(&commit.id);
+        }
+        if pending_parents.is_empty() {
+            ready.push(commit.id.as_str());
+        } else {
+            waiting.insert(&commit.id, pending_parents.len());
+        }
+    }
+    while let Some(id) = ready.pop() {
+        let generation = 1 + pending[id]
+            .parents
+            .iter()
+            .map(|x| generations.get(x).copied().unwrap_or_default())
+            .max()
+            .unwrap_or_default();
+        generations.insert(id.to_owned(), generation);
+        for child in children.get(id).into_iter().flatten() {
+            let count = waiting.get_mut(*child).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push(*child);
+            }
+        }
+    }
+    generations
+}
+
+fn parent_ids(node: &mega_commit_graph::Model) -> Vec<String> {
+    node.parents_id
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str().map(str::to_owned))
+        .collect()
+}
+
+/// A commit of a walk, higher generations first, then newer commits.
+struct Queued(mega_commit_graph::Model);
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.generation, self.0.commit_time, &self.0.commit_id).cmp(&(
+            other.0.generation,
+            other.0.commit_time,
+            &other.0.commit_id,
+        ))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+/// The graph rows read during one query.
+struct GraphWalk<'a> {
+    storage: &'a MonoStorage,
+    nodes: HashMap<String, mega_commit_graph::Model>,
+    /// every row of this generation and above, down to where the walk started, was read
+    prefetched_to: i64,
+}
+
+impl<'a> GraphWalk<'a> {
+    fn new(storage: &'a MonoStorage) -> Self {
+        GraphWalk {
+            storage,
+            nodes: HashMap::new(),
+            prefetched_to: i64::MAX,
+        }
+    }
+
+    async fn nodes(
+        &mut self,
+        ids: Vec<String>,
+    ) -> Result<Vec<mega_commit_graph::Model>, MegaError> {
+        let missing: Vec<String> = ids
+            .iter()
+            .filter(|x| !self.nodes.contains_key(*x))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            for node in self.storage.get_graph_nodes(missing).await? {
+                self.nodes.insert(node.commit_id.clone(), node);
+            }
+        }
+        Ok(ids
+            .iter()
+            .filter_map(|x| self.nodes.get(x).cloned())
+            .collect())
+    }
+
+    async fn parents(
+        &mut self,
+        node: &mega_commit_graph::Model,
+    ) -> Result<Vec<mega_commit_graph::Model>, MegaError> {
+        let ids = parent_ids(node);
+        if ids.iter().any(|x| !self.nodes.contains_key(x)) && node.generation <= self.prefetched_to
+        {
+            self.prefetch(node.generation).await?;
+        }
+        self.nodes(ids).await
+    }
+
+    /// Read the rows of the generations below `generation`.
+    async fn prefetch(&mut self, generation: i64) -> Result<(), MegaError> {
+        let to = generation - PREFETCH_GENERATIONS;
+        let rows = mega_commit_graph::Entity::find()
+            .filter(mega_commit_graph::Column::Generation.lt(generation))
+            .filter(mega_commit_graph::Column::Generation.gte(to))
+            .order_by_desc(mega_commit_graph::Column::Generation)
+            .limit(PREFETCH_ROWS)
+            .all(self.storage.get_connection())
+            .await?;
+        self.prefetched_to = match rows.last() {
+            // the rows of the lowest generation read may be cut off
+            Some(last) if rows.len() as u64 == PREFETCH_ROWS => last.generation + 1,
+            _ => to,
+        };
+        for node in rows {
+            self.nodes.entry(node.commit_id.clone()).or_insert(node);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{assign_generations, GraphCommit};
+
+    fn commit(id: &str, parents: &[&str]) -> (String, GraphCommit) {
+        (
+            id.to_owned(),
+            GraphCommit {
+                id: id.to_owned(),
+                parents: parents.iter().map(|x| x.to_string()).collect(),
+                time: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn test_assign_generations() {
+        // a - b - c - e
+        //      \     /
+        //        d -
+        let pending = HashMap::from([
+            commit("b", &["a"]),
+            commit("c", &["b"]),
+            commit("d", &["b"]),
+            commit("e", &["c", "d", "x"]),
+        ]);
+        let known = HashMap::from([("a".to_owned(), 7), ("x".to_owned(), 1)]);
+        let generations = assign_generations(&pending, &known);
+        assert_eq!(generations["b"], 8);
+        assert_eq!(generations["c"], 9);
+        assert_eq!(generations["d"], 9);
+        assert_eq!(generations["e"], 10);
+
+        let roots = HashMap::from([commit("r", &[]), commit("s", &["missing"])]);
+        let generations = assign_generations(&roots, &HashMap::new());
+        assert_eq!(generations["r"], 1);
+        assert_eq!(generations["s"], 1);
+    }
+}
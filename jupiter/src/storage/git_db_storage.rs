@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use futures::{stream, Stream, StreamExt};
@@ -6,7 +7,7 @@ use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
     IntoActiveModel, QueryFilter, QueryTrait, Set,
 };
-use sea_orm::{PaginatorTrait, QueryOrder};
+use sea_orm::{PaginatorTrait, QueryOrder, QuerySelect};
 use tokio::sync::Mutex;
 
 use callisto::{git_blob, git_commit, git_repo, git_tag, git_tree, import_refs, raw_blob};
@@ -14,11 +15,16 @@ use common::errors::MegaError;
 use mercury::internal::object::GitObjectModel;
 use mercury::internal::pack::entry::Entry;
 
+use crate::blob_storage::BlobStore;
 use crate::storage::batch_save_model;
+use crate::storage::transaction::StorageConnection;
 
 #[derive(Clone)]
 pub struct GitDbStorage {
-    pub connection: Arc<DatabaseConnection>,
+    pub connection: StorageConnection,
+    /// objects of a repo are streamed from the database itself, never in a transaction
+    database: Arc<DatabaseConnection>,
+    blob_store: BlobStore,
 }
 
 #[derive(Debug)]
@@ -26,22 +32,37 @@ struct GitObjects {
     commits: Vec<git_commit::ActiveModel>,
     trees: Vec<git_tree::ActiveModel>,
     blobs: Vec<git_blob::ActiveModel>,
-    raw_blobs: Vec<raw_blob::ActiveModel>,
+    raw_blobs: Vec<raw_blob::Model>,
     tags: Vec<git_tag::ActiveModel>,
 }
 
 impl GitDbStorage {
-    pub fn get_connection(&self) -> &DatabaseConnection {
+    pub fn get_connection(&self) -> &StorageConnection {
         &self.connection
     }
 
-    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
-        GitDbStorage { connection }
+    pub async fn new(connection: Arc<DatabaseConnection>, blob_store: BlobStore) -> Self {
+        GitDbStorage {
+            connection: connection.clone().into(),
+            database: connection,
+            blob_store,
+        }
     }
 
     pub fn mock() -> Self {
+        let connection = Arc::new(DatabaseConnection::default());
+        GitDbStorage {
+            connection: connection.clone().into(),
+            database: connection,
+            blob_store: BlobStore::default(),
+        }
+    }
+
+    /// This storage writing through the transaction `connection`.
+    pub fn in_transaction(&self, connection: StorageConnection) -> Self {
         GitDbStorage {
-            connection: Arc::new(DatabaseConnection::default()),
+            connection,
+            ..self.clone()
         }
     }
 
@@ -127,7 +148,7 @@ impl GitDbStorage {
             tags: Vec::new(),
         }));
 
-        stream::iter(entry_list)
+        stream::iter(&entry_list)
             .for_each_concurrent(None, |entry| {
                 let git_objects = git_objects.clone();
 
@@ -148,7 +169,7 @@ impl GitDbStorage {
                         GitObjectModel::Blob(mut blob, raw) => {
                             blob.repo_id = repo_id;
                             git_objects.blobs.push(blob.clone().into_active_model());
-                            git_objects.raw_blobs.push(raw.into_active_model());
+                            git_objects.raw_blobs.push(raw);
                         }
                         GitObjectModel::Tag(mut tag) => {
                             tag.repo_id = repo_id;
@@ -171,7 +192,14 @@ impl GitDbStorage {
         batch_save_model(self.get_connection(), git_objects.blobs)
             .await
             .unwrap();
-        batch_save_model(self.get_connection(), git_objects.raw_blobs)
+        let raw_blobs: Vec<raw_blob::ActiveModel> = self
+            .blob_store
+            .offload_push(&entry_list, git_objects.raw_blobs)
+            .await?
+            .into_iter()
+            .map(|x| x.into_active_model())
+            .collect();
+        batch_save_model(self.get_connection(), raw_blobs)
             .await
             .unwrap();
         batch_save_model(self.get_connection(), git_objects.tags)
@@ -264,7 +292,7 @@ impl GitDbStorage {
     ) -> Result<impl Stream<Item = Result<git_commit::Model, DbErr>> + Send + '_, MegaError> {
         let stream = git_commit::Entity::find()
             .filter(git_commit::Column::RepoId.eq(repo_id))
-            .stream(self.get_connection())
+            .stream(self.database.as_ref())
             .await
             .unwrap();
         Ok(stream)
@@ -276,7 +304,7 @@ impl GitDbStorage {
     ) -> Result<impl Stream<Item = Result<git_tree::Model, DbErr>> + '_ + Send, MegaError> {
         Ok(git_tree::Entity::find()
             .filter(git_tree::Column::RepoId.eq(repo_id))
-            .stream(self.get_connection())
+            .stream(self.database.as_ref())
             .await
             .unwrap())
     }
@@ -313,7 +341,7 @@ impl GitDbStorage {
     ) -> Result<impl Stream<Item = Result<git_blob::Model, DbErr>> + '_ + Send, MegaError> {
         Ok(git_blob::Entity::find()
             .filter(git_blob::Column::RepoId.eq(repo_id))
-            .stream(self.get_connection())
+            .stream(self.database.as_ref())
             .await
             .unwrap())
     }
@@ -371,4 +399,46 @@ impl GitDbStorage {
             .try_into()
             .unwrap()
     }
+
+    /// Hashes of all commits, trees, blobs and tags of the repo.
+    pub async fn get_obj_ids_by_repo_id(&self, repo_id: i64) -> Result<HashSet<String>, MegaError> {
+        let mut ids = HashSet::new();
+        ids.extend(
+            git_commit::Entity::find()
+                .select_only()
+                .column(git_commit::Column::CommitId)
+                .filter(git_commit::Column::RepoId.eq(repo_id))
+                .into_tuple::<String>()
+                .all(self.get_connection())
+                .await?,
+        );
+        ids.extend(
+            git_tree::Entity::find()
+                .select_only()
+                .column(git_tree::Column::TreeId)
+                .filter(git_tree::Column::RepoId.eq(repo_id))
+                .into_tuple::<String>()
+                .all(self.get_connection())
+                .await?,
+        );
+        ids.extend(
+            git_blob::Entity::find()
+                .select_only()
+                .column(git_blob::Column::BlobId)
+                .filter(git_blob::Column::RepoId.eq(repo_id))
+                .into_tuple::<String>()
+                .all(self.get_connection())
+                .await?,
+        );
+        ids.extend(
+            git_tag::Entity::find()
+                .select_only()
+                .column(git_tag::Column::TagId)
+                .filter(git_tag::Column::RepoId.eq(repo_id))
+                .into_tuple::<String>()
+                .all(self.get_connection())
+                .await?,
+        );
+        Ok(ids)
+    }
 }
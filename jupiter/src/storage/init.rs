@@ -1,6 +1,6 @@
 use std::{path::Path, time::Duration};
 
-use sea_orm::{ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbErr, Statement, TransactionError, TransactionTrait};
+use sea_orm::{metric, ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbErr, Statement, TransactionError, TransactionTrait};
 use tracing::log;
 
 use common::config::DbConfig;
@@ -32,9 +32,10 @@ pub async fn database_connection(db_config: &DbConfig) -> DatabaseConnection {
         .max_lifetime(Duration::from_secs(8))
         .sqlx_logging(db_config.sqlx_logging)
         .sqlx_logging_level(log::LevelFilter::Debug);
-    let conn = Database::connect(opt)
+    let mut conn = Database::connect(opt)
         .await
         .expect("Database connection failed");
+    conn.set_metric_callback(record_query);
 
     // setup sqlite database (execute .sql)
     if is_sqlite && is_file_empty(db_path) {
@@ -44,6 +45,21 @@ pub async fn database_connection(db_config: &DbConfig) -> DatabaseConnection {
     conn
 }
 
+/// Count every query and time it, by the kind of statement.
+fn record_query(info: &metric::Info<'_>) {
+    let kind = match info.statement.sql.split_whitespace().next() {
+        Some(word) if word.eq_ignore_ascii_case("select") => "select",
+        Some(word) if word.eq_ignore_ascii_case("insert") => "insert",
+        Some(word) if word.eq_ignore_ascii_case("update") => "update",
+        Some(word) if word.eq_ignore_ascii_case("delete") => "delete",
+        _ => "other",
+    };
+    let outcome = if info.failed { "error" } else { "ok" };
+    metrics::counter!("mega_db_queries_total", "kind" => kind, "outcome" => outcome).increment(1);
+    metrics::histogram!("mega_db_query_duration_seconds", "kind" => kind)
+        .record(info.elapsed.as_secs_f64());
+}
+
 /// create table from .sql file
 async fn setup_sql(conn: &DatabaseConnection) -> Result<(), TransactionError<DbErr>> {
     conn.transaction::<_, _, DbErr>(|txn| {
@@ -51,7 +67,7 @@ async fn setup_sql(conn: &DatabaseConnection) -> Result<(), TransactionError<DbE
             let backend = txn.get_database_backend();
 
             // `include_str!` will expand the file while compiling, so `.sql` is not needed after that
-            const SETUP_SQL: &str = include_str!("../../../sql/sqlite/sqlite_20241204_init.sql");
+            const SETUP_SQL: &str = include_str!("../../../sql/sqlite/sqlite_20261015_init.sql");
             txn.execute(Statement::from_string(backend, SETUP_SQL)).await?;
             Ok(())
         })
@@ -129,6 +129,11 @@ impl IssueStorage {
             user_id,
             conv_type: ConvType::Comment,
             comment,
+            path: None,
+            commit_id: None,
+            line: None,
+            reply_to: None,
+            resolved: false,
             created_at: chrono::Utc::now().naive_utc(),
             updated_at: chrono::Utc::now().naive_utc(),
         };
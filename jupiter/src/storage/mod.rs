@@ -1,3 +1,7 @@
+pub mod acl_storage;
+pub mod audit_storage;
+pub mod check_storage;
+pub mod commit_graph;
 pub mod git_db_storage;
 pub mod init;
 pub mod issue_storage;
@@ -5,7 +9,16 @@ pub mod lfs_db_storage;
 pub mod mono_storage;
 pub mod mq_storage;
 pub mod mr_storage;
+pub mod notification_storage;
+pub mod protection_storage;
 pub mod raw_db_storage;
+pub mod release_storage;
+pub mod search_storage;
+pub mod signature_storage;
+pub mod stats_storage;
+pub mod tombstone;
+pub mod traffic_storage;
+pub mod transaction;
 pub mod user_storage;
 pub mod ztm_storage;
 
@@ -1,49 +1,88 @@
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
+use chrono::NaiveDateTime;
+
 use futures::{stream, StreamExt};
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect
 };
 
-use callisto::{mega_blob, mega_commit, mega_refs, mega_tag, mega_tree, raw_blob};
+use callisto::{
+    db_enums::StorageType, git_blob, mega_blob, mega_commit, mega_commit_graph, mega_refs,
+    mega_tag, mega_tree, raw_blob,
+};
 use common::config::MonoConfig;
 use common::errors::MegaError;
 use common::utils::{generate_id, MEGA_BRANCH_NAME};
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::object::tree::Tree;
 use mercury::internal::object::MegaObjectModel;
 use mercury::internal::{object::commit::Commit, pack::entry::Entry};
+use mercury::tree_diff::TreeLoader;
 
+use crate::blob_storage::{BlobStorage, BlobStore};
+use crate::cache::MonoCache;
 use crate::storage::batch_save_model;
+use crate::storage::transaction::StorageConnection;
 use crate::utils::converter::MegaModelConverter;
 
 #[derive(Clone)]
 pub struct MonoStorage {
-    pub connection: Arc<DatabaseConnection>,
+    pub connection: StorageConnection,
+    blob_store: BlobStore,
+    cache: MonoCache,
 }
 
 #[derive(Debug)]
 struct GitObjects {
-    pub commits: Vec<mega_commit::ActiveModel>,
+    pub commits: Vec<mega_commit::Model>,
     trees: Vec<mega_tree::ActiveModel>,
     blobs: Vec<mega_blob::ActiveModel>,
-    raw_blobs: Vec<raw_blob::ActiveModel>,
+    raw_blobs: Vec<raw_blob::Model>,
     tags: Vec<mega_tag::ActiveModel>,
 }
 
 impl MonoStorage {
-    pub fn get_connection(&self) -> &DatabaseConnection {
+    pub fn get_connection(&self) -> &StorageConnection {
         &self.connection
     }
 
-    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
-        MonoStorage { connection }
+    pub async fn new(
+        connection: Arc<DatabaseConnection>,
+        blob_store: BlobStore,
+        cache: MonoCache,
+    ) -> Self {
+        MonoStorage {
+            connection: connection.into(),
+            blob_store,
+            cache,
+        }
     }
 
     pub fn mock() -> Self {
         MonoStorage {
-            connection: Arc::new(DatabaseConnection::default()),
+            connection: Arc::new(DatabaseConnection::default()).into(),
+            blob_store: BlobStore::default(),
+            cache: MonoCache::default(),
+        }
+    }
+
+    /// This storage writing through the transaction `connection`, call
+    /// [`transaction_committed`](Self::transaction_committed) once it's committed.
+    pub fn in_transaction(&self, connection: StorageConnection) -> Self {
+        MonoStorage {
+            connection,
+            blob_store: self.blob_store.clone(),
+            cache: self.cache.deferred(),
         }
     }
 
+    pub async fn transaction_committed(&self) {
+        self.cache.commit().await;
+    }
+
     pub async fn save_ref(
         &self,
         path: &str,
@@ -63,16 +102,24 @@ impl MonoStorage {
         model
             .into_active_model()
             .insert(self.get_connection())
-            .await
-            .unwrap();
+            .await?;
+        self.cache.invalidate_ref(path).await;
         Ok(())
     }
 
+    /// Remove the main and MR refs of `path` and the directories below it, named branches
+    /// are kept.
     pub async fn remove_refs(&self, path: &str) -> Result<(), MegaError> {
         mega_refs::Entity::delete_many()
             .filter(mega_refs::Column::Path.starts_with(path))
+            .filter(
+                Condition::any()
+                    .add(mega_refs::Column::RefName.eq(MEGA_BRANCH_NAME))
+                    .add(mega_refs::Column::RefName.starts_with("refs/heads/").not()),
+            )
             .exec(self.get_connection())
             .await?;
+        self.cache.invalidate_refs_below(path).await;
         Ok(())
     }
 
@@ -80,6 +127,7 @@ impl MonoStorage {
         mega_refs::Entity::delete_by_id(refs.id)
             .exec(self.get_connection())
             .await?;
+        self.cache.invalidate_ref(&refs.path).await;
         Ok(())
     }
 
@@ -96,14 +144,45 @@ impl MonoStorage {
         &self,
         path: &str,
     ) -> Result<Option<mega_refs::Model>, MegaError> {
+        if let Some(refs) = self.cache.get_ref(path).await {
+            return Ok(Some(refs));
+        }
+        let epoch = self.cache.ref_epoch();
         let result = mega_refs::Entity::find()
             .filter(mega_refs::Column::Path.eq(path))
             .filter(mega_refs::Column::RefName.eq(MEGA_BRANCH_NAME.to_owned()))
             .one(self.get_connection())
             .await?;
+        if let Some(refs) = &result {
+            self.cache.put_ref(refs, epoch).await;
+        }
         Ok(result)
     }
 
+    /// The main refs of `paths`, paths without one are skipped.
+    pub async fn get_refs_by_paths(
+        &self,
+        paths: Vec<String>,
+    ) -> Result<Vec<mega_refs::Model>, MegaError> {
+        Ok(mega_refs::Entity::find()
+            .filter(mega_refs::Column::Path.is_in(paths))
+            .filter(mega_refs::Column::RefName.eq(MEGA_BRANCH_NAME))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_ref_by_name(
+        &self,
+        path: &str,
+        ref_name: &str,
+    ) -> Result<Option<mega_refs::Model>, MegaError> {
+        Ok(mega_refs::Entity::find()
+            .filter(mega_refs::Column::Path.eq(path))
+            .filter(mega_refs::Column::RefName.eq(ref_name))
+            .one(self.get_connection())
+            .await?)
+    }
+
     pub async fn get_ref_by_commit(
         &self,
         path: &str,
@@ -125,12 +204,32 @@ impl MonoStorage {
         Ok(res)
     }
 
-    pub async fn update_ref(&self, refs: mega_refs::Model) -> Result<(), MegaError> {
+    /// Move `refs` to its new commit and tree if it still points to `old_commit_hash`.
+    /// A ref another write moved meanwhile is left alone and a ref conflict returned, the
+    /// caller reads the ref again and retries.
+    pub async fn update_ref(
+        &self,
+        refs: mega_refs::Model,
+        old_commit_hash: &str,
+    ) -> Result<(), MegaError> {
+        let (id, path, ref_name) = (refs.id, refs.path.clone(), refs.ref_name.clone());
         let mut ref_data: mega_refs::ActiveModel = refs.into();
         ref_data.reset(mega_refs::Column::RefCommitHash);
         ref_data.reset(mega_refs::Column::RefTreeHash);
         ref_data.reset(mega_refs::Column::UpdatedAt);
-        ref_data.update(self.get_connection()).await.unwrap();
+        let res = mega_refs::Entity::update_many()
+            .set(ref_data)
+            .filter(mega_refs::Column::Id.eq(id))
+            .filter(mega_refs::Column::RefCommitHash.eq(old_commit_hash))
+            .exec(self.get_connection())
+            .await?;
+        self.cache.invalidate_ref(&path).await;
+        if res.rows_affected == 0 {
+            return Err(MegaError::ref_conflict(&format!(
+                "{} of {} was updated concurrently",
+                ref_name, path
+            )));
+        }
         Ok(())
     }
 
@@ -147,7 +246,7 @@ impl MonoStorage {
             tags: Vec::new(),
         }));
 
-        stream::iter(entry_list)
+        stream::iter(&entry_list)
             .for_each_concurrent(None, |entry| {
                 let git_objects = git_objects.clone();
                 async move {
@@ -155,9 +254,7 @@ impl MonoStorage {
                     let model = raw_obj.convert_to_mega_model();
                     let mut git_objects = git_objects.lock().unwrap();
                     match model {
-                        MegaObjectModel::Commit(commit) => {
-                            git_objects.commits.push(commit.into_active_model())
-                        }
+                        MegaObjectModel::Commit(commit) => git_objects.commits.push(commit),
                         MegaObjectModel::Tree(mut tree) => {
                             commit_id.clone_into(&mut tree.commit_id);
                             git_objects.trees.push(tree.into_active_model());
@@ -165,7 +262,7 @@ impl MonoStorage {
                         MegaObjectModel::Blob(mut blob, raw) => {
                             commit_id.clone_into(&mut blob.commit_id);
                             git_objects.blobs.push(blob.clone().into_active_model());
-                            git_objects.raw_blobs.push(raw.into_active_model());
+                            git_objects.raw_blobs.push(raw);
                         }
                         MegaObjectModel::Tag(tag) => git_objects.tags.push(tag.into_active_model()),
                     }
@@ -178,25 +275,33 @@ impl MonoStorage {
             .into_inner()
             .unwrap();
 
-        batch_save_model(self.get_connection(), git_objects.commits)
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), git_objects.trees)
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), git_objects.blobs)
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), git_objects.raw_blobs)
-            .await
-            .unwrap();
-        batch_save_model(self.get_connection(), git_objects.tags)
-            .await
-            .unwrap();
+        self.save_commit_graph(&git_objects.commits).await?;
+        let commits = git_objects
+            .commits
+            .into_iter()
+            .map(|x| x.into_active_model())
+            .collect();
+        batch_save_model(self.get_connection(), commits).await?;
+        batch_save_model(self.get_connection(), git_objects.trees).await?;
+        batch_save_model(self.get_connection(), git_objects.blobs).await?;
+        let raw_blobs: Vec<raw_blob::ActiveModel> = self
+            .blob_store
+            .offload_push(&entry_list, git_objects.raw_blobs)
+            .await?
+            .into_iter()
+            .map(|x| x.into_active_model())
+            .collect();
+        batch_save_model(self.get_connection(), raw_blobs).await?;
+        batch_save_model(self.get_connection(), git_objects.tags).await?;
 
         Ok(())
     }
 
+    pub async fn save_raw_blob(&self, model: raw_blob::Model) -> Result<(), MegaError> {
+        let model = self.blob_store.offload(model).await?;
+        batch_save_model(self.get_connection(), vec![model.into_active_model()]).await
+    }
+
     pub async fn init_monorepo(&self, mono_config: &MonoConfig) {
         if self.get_ref("/").await.unwrap().is_some() {
             tracing::info!("Monorepo Directory Already Inited, skip init process!");
@@ -204,6 +309,7 @@ impl MonoStorage {
         }
         let converter = MegaModelConverter::init(mono_config);
         let commit: mega_commit::Model = converter.commit.into();
+        self.save_commit_graph(&[commit.clone()]).await.unwrap();
         mega_commit::Entity::insert(commit.into_active_model())
             .exec(self.get_connection())
             .await
@@ -230,13 +336,12 @@ impl MonoStorage {
     pub async fn save_mega_commits(&self, commits: Vec<Commit>) -> Result<(), MegaError> {
         let mega_commits: Vec<mega_commit::Model> =
             commits.into_iter().map(mega_commit::Model::from).collect();
+        self.save_commit_graph(&mega_commits).await?;
         let mut save_models = Vec::new();
         for mega_commit in mega_commits {
             save_models.push(mega_commit.into_active_model());
         }
-        batch_save_model(self.get_connection(), save_models)
-            .await
-            .unwrap();
+        batch_save_model(self.get_connection(), save_models).await?;
         Ok(())
     }
 
@@ -244,11 +349,17 @@ impl MonoStorage {
         &self,
         hash: &str,
     ) -> Result<Option<mega_commit::Model>, MegaError> {
-        Ok(mega_commit::Entity::find()
+        if let Some(commit) = self.cache.get_commit(hash).await {
+            return Ok(Some(commit));
+        }
+        let result = mega_commit::Entity::find()
             .filter(mega_commit::Column::CommitId.eq(hash))
             .one(self.get_connection())
-            .await
-            .unwrap())
+            .await?;
+        if let Some(commit) = &result {
+            self.cache.put_commit(commit).await;
+        }
+        Ok(result)
     }
 
     pub async fn get_commits_by_hashes(
@@ -258,19 +369,24 @@ impl MonoStorage {
         Ok(mega_commit::Entity::find()
             .filter(mega_commit::Column::CommitId.is_in(hashes))
             .all(self.get_connection())
-            .await
-            .unwrap())
+            .await?)
     }
 
     pub async fn get_tree_by_hash(
         &self,
         hash: &str,
     ) -> Result<Option<mega_tree::Model>, MegaError> {
-        Ok(mega_tree::Entity::find()
+        if let Some(tree) = self.cache.get_tree(hash).await {
+            return Ok(Some(tree));
+        }
+        let result = mega_tree::Entity::find()
             .filter(mega_tree::Column::TreeId.eq(hash))
             .one(self.get_connection())
-            .await
-            .unwrap())
+            .await?;
+        if let Some(tree) = &result {
+            self.cache.put_tree(tree).await;
+        }
+        Ok(result)
     }
 
     pub async fn get_trees_by_hashes(
@@ -281,8 +397,7 @@ impl MonoStorage {
             .filter(mega_tree::Column::TreeId.is_in(hashes))
             .distinct()
             .all(self.get_connection())
-            .await
-            .unwrap())
+            .await?)
     }
 
     pub async fn get_mega_blobs_by_hashes(
@@ -292,10 +407,342 @@ impl MonoStorage {
         Ok(mega_blob::Entity::find()
             .filter(mega_blob::Column::BlobId.is_in(hashes))
             .all(self.get_connection())
-            .await
-            .unwrap())
+            .await?)
+    }
+
+    pub async fn save_mega_tag(&self, tag: mega_tag::Model) -> Result<(), MegaError> {
+        tag.into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Tags scoped to `path`, newest first.
+    pub async fn get_tags_by_path(&self, path: &str) -> Result<Vec<mega_tag::Model>, MegaError> {
+        Ok(mega_tag::Entity::find()
+            .filter(mega_tag::Column::Path.eq(path))
+            .order_by_desc(mega_tag::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_tag_by_name(
+        &self,
+        path: &str,
+        tag_name: &str,
+    ) -> Result<Option<mega_tag::Model>, MegaError> {
+        Ok(mega_tag::Entity::find()
+            .filter(mega_tag::Column::Path.eq(path))
+            .filter(mega_tag::Column::TagName.eq(tag_name))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The refs of all paths and branches.
+    pub async fn get_all_refs(&self) -> Result<Vec<mega_refs::Model>, MegaError> {
+        Ok(mega_refs::Entity::find().all(self.get_connection()).await?)
+    }
+
+    pub async fn get_all_tags(&self) -> Result<Vec<mega_tag::Model>, MegaError> {
+        Ok(mega_tag::Entity::find().all(self.get_connection()).await?)
+    }
+
+    /// Remove the commits stored before `before` that aren't in `reachable`, and their
+    /// rows of the commit graph, returns how many were removed.
+    pub async fn sweep_commits(
+        &self,
+        reachable: &HashSet<String>,
+        before: NaiveDateTime,
+    ) -> Result<u64, MegaError> {
+        sweep::<mega_commit_graph::Entity>(
+            self.get_connection(),
+            (
+                mega_commit_graph::Column::Id,
+                mega_commit_graph::Column::CommitId,
+                mega_commit_graph::Column::CreatedAt,
+            ),
+            reachable,
+            before,
+        )
+        .await?;
+        sweep::<mega_commit::Entity>(
+            self.get_connection(),
+            (
+                mega_commit::Column::Id,
+                mega_commit::Column::CommitId,
+                mega_commit::Column::CreatedAt,
+            ),
+            reachable,
+            before,
+        )
+        .await
+    }
+
+    /// Remove the trees stored before `before` that aren't in `reachable`.
+    pub async fn sweep_trees(
+        &self,
+        reachable: &HashSet<String>,
+        before: NaiveDateTime,
+    ) -> Result<u64, MegaError> {
+        sweep::<mega_tree::Entity>(
+            self.get_connection(),
+            (
+                mega_tree::Column::Id,
+                mega_tree::Column::TreeId,
+                mega_tree::Column::CreatedAt,
+            ),
+            reachable,
+            before,
+        )
+        .await
+    }
+
+    /// Remove the blobs stored before `before` that aren't in `reachable`.
+    pub async fn sweep_blobs(
+        &self,
+        reachable: &HashSet<String>,
+        before: NaiveDateTime,
+    ) -> Result<u64, MegaError> {
+        sweep::<mega_blob::Entity>(
+            self.get_connection(),
+            (
+                mega_blob::Column::Id,
+                mega_blob::Column::BlobId,
+                mega_blob::Column::CreatedAt,
+            ),
+            reachable,
+            before,
+        )
+        .await
+    }
+
+    /// Remove the raw blobs stored before `before` that aren't in `reachable` and that
+    /// no blob of the monorepo or of an import repo is stored as any more, together with
+    /// their content kept outside the database. Packs written before `before` that no
+    /// raw blob is kept in are removed too, like those of a push that was rolled back.
+    /// Returns how many raw blobs were removed.
+    pub async fn sweep_raw_blobs(
+        &self,
+        reachable: &HashSet<String>,
+        before: NaiveDateTime,
+    ) -> Result<u64, MegaError> {
+        type Location = (i64, String, StorageType, Option<String>, Option<String>);
+        let db = self.get_connection();
+        let mut removed = 0;
+        let mut last_id = i64::MIN;
+        loop {
+            let rows: Vec<Location> = raw_blob::Entity::find()
+                .select_only()
+                .columns([
+                    raw_blob::Column::Id,
+                    raw_blob::Column::Sha1,
+                    raw_blob::Column::StorageType,
+                    raw_blob::Column::LocalPath,
+                    raw_blob::Column::RemoteUrl,
+                ])
+                .filter(raw_blob::Column::Id.gt(last_id))
+                .filter(raw_blob::Column::CreatedAt.lt(before))
+                .order_by_asc(raw_blob::Column::Id)
+                .limit(SWEEP_BATCH)
+                .into_tuple()
+                .all(db)
+                .await?;
+            let Some((max_id, ..)) = rows.last() else {
+                break;
+            };
+            last_id = *max_id;
+            let unreachable: Vec<String> = rows
+                .iter()
+                .filter(|x| !reachable.contains(&x.1))
+                .map(|x| x.1.clone())
+                .collect();
+            if unreachable.is_empty() {
+                continue;
+            }
+            // blobs stored within the grace period or by an import repo still use them
+            let mut used: HashSet<String> = mega_blob::Entity::find()
+                .select_only()
+                .column(mega_blob::Column::BlobId)
+                .filter(mega_blob::Column::BlobId.is_in(unreachable.clone()))
+                .into_tuple::<String>()
+                .all(db)
+                .await?
+                .into_iter()
+                .collect();
+            used.extend(
+                git_blob::Entity::find()
+                    .select_only()
+                    .column(git_blob::Column::BlobId)
+                    .filter(git_blob::Column::BlobId.is_in(unreachable))
+                    .into_tuple::<String>()
+                    .all(db)
+                    .await?,
+            );
+            let garbage: Vec<Location> = rows
+                .into_iter()
+                .filter(|x| !reachable.contains(&x.1) && !used.contains(&x.1))
+                .collect();
+            if garbage.is_empty() {
+                continue;
+            }
+            removed += raw_blob::Entity::delete_many()
+                .filter(raw_blob::Column::Id.is_in(garbage.iter().map(|x| x.0)))
+                .exec(db)
+                .await?
+                .rows_affected;
+            for (_, sha1, storage_type, local_path, remote_url) in garbage {
+                let Some(location) = local_path.or(remote_url) else {
+                    continue;
+                };
+                // the row is gone already, a file left behind is only wasted space
+                if let Err(e) = self.blob_store.remove(&sha1, storage_type, &location).await {
+                    tracing::warn!("failed to remove blob {} at {}: {}", sha1, location, e);
+                }
+            }
+        }
+
+        if let Some(packs) = self.blob_store.pack_storage() {
+            for pack in packs.packs_before(before.and_utc().into())? {
+                let kept = raw_blob::Entity::find()
+                    .filter(raw_blob::Column::StorageType.eq(StorageType::PackFile))
+                    .filter(raw_blob::Column::LocalPath.eq(pack.as_str()))
+                    .count(db)
+                    .await?;
+                if kept == 0 {
+                    packs.delete_object("", &pack).await?;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Rows read per query while sweeping.
+const SWEEP_BATCH: u64 = 1000;
+
+/// Delete the rows of `E` created before `before` whose object hash isn't in `reachable`,
+/// `columns` are the id, hash and creation time columns. Rows are walked in id order a
+/// batch at a time.
+async fn sweep<E>(
+    db: &StorageConnection,
+    columns: (E::Column, E::Column, E::Column),
+    reachable: &HashSet<String>,
+    before: NaiveDateTime,
+) -> Result<u64, MegaError>
+where
+    E: EntityTrait,
+{
+    let (id, hash, created_at) = columns;
+    let mut removed = 0;
+    let mut last_id = i64::MIN;
+    loop {
+        let rows: Vec<(i64, String)> = E::find()
+            .select_only()
+            .column(id)
+            .column(hash)
+            .filter(id.gt(last_id))
+            .filter(created_at.lt(before))
+            .order_by_asc(id)
+            .limit(SWEEP_BATCH)
+            .into_tuple()
+            .all(db)
+            .await?;
+        let Some((max_id, _)) = rows.last() else {
+            break;
+        };
+        last_id = *max_id;
+        let garbage: Vec<i64> = rows
+            .into_iter()
+            .filter(|(_, x)| !reachable.contains(x))
+            .map(|(x, _)| x)
+            .collect();
+        if !garbage.is_empty() {
+            removed += E::delete_many()
+                .filter(id.is_in(garbage))
+                .exec(db)
+                .await?
+                .rows_affected;
+        }
+    }
+    Ok(removed)
+}
+
+impl TreeLoader for MonoStorage {
+    async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+        match self.get_tree_by_hash(&id.to_string()).await {
+            Ok(Some(model)) => Ok(model.into()),
+            _ => Err(GitError::ObjectNotFound(id.to_string())),
+        }
     }
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use std::collections::HashSet;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use sea_orm::EntityTrait;
+
+    use callisto::{db_enums::StorageType, raw_blob};
+    use common::config::DbConfig;
+    use common::utils::ZERO_ID;
+    use mercury::internal::object::blob::Blob;
+
+    use crate::blob_storage::{local_fs_storage::LocalFsStorage, BlobStore};
+    use crate::cache::MonoCache;
+    use crate::storage::init::database_connection;
+    use crate::storage::mono_storage::MonoStorage;
+
+    /// A storage on a new sqlite database in `dir`, keeping blobs over 8 bytes as files.
+    async fn storage(dir: &Path) -> MonoStorage {
+        let config = DbConfig {
+            db_path: dir.join("mega.db").to_string_lossy().into_owned(),
+            min_connection: 1,
+            ..Default::default()
+        };
+        let blob_store = BlobStore::new(Arc::new(LocalFsStorage::init(dir.join("objects"))), 8);
+        let connection = Arc::new(database_connection(&config).await);
+        MonoStorage::new(connection, blob_store, MonoCache::default()).await
+    }
+
+    #[tokio::test]
+    async fn test_sweep_raw_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(dir.path()).await;
+        let kept = Blob::from_content("a reachable blob");
+        let garbage = Blob::from_content("an unreachable blob");
+        storage
+            .save_entry(ZERO_ID, vec![kept.clone().into(), garbage.clone().into()])
+            .await
+            .unwrap();
+        let files: Vec<String> = raw_blob::Entity::find()
+            .all(storage.get_connection())
+            .await
+            .unwrap()
+            .into_iter()
+            .inspect(|x| assert_eq!(x.storage_type, StorageType::LocalFs))
+            .filter_map(|x| x.local_path)
+            .collect();
+        assert_eq!(files.len(), 2);
+
+        let reachable = HashSet::from([kept.id.to_string()]);
+        // the blob row of the push still uses it until it's swept itself
+        let before = Utc::now().naive_utc() + Duration::minutes(1);
+        assert_eq!(storage.sweep_raw_blobs(&reachable, before).await.unwrap(), 0);
+        assert_eq!(storage.sweep_blobs(&reachable, before).await.unwrap(), 1);
+        assert_eq!(storage.sweep_raw_blobs(&reachable, before).await.unwrap(), 1);
+
+        let left: Vec<raw_blob::Model> = raw_blob::Entity::find()
+            .all(storage.get_connection())
+            .await
+            .unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].sha1, kept.id.to_string());
+        let kept_file = left[0].local_path.clone().unwrap();
+        for file in files {
+            assert_eq!(Path::new(&file).exists(), file == kept_file);
+        }
+    }
+}
@@ -1,7 +1,14 @@
 use std::sync::Arc;
 
 use callisto::mq_storage::*;
-use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder, QuerySelect};
+use callisto::{mq_dead_letter, mq_idempotency_key, mq_job};
+use chrono::NaiveDateTime;
+use common::errors::MegaError;
+use sea_orm::{
+    sea_query::{Condition, Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+    QuerySelect,
+};
 
 use super::batch_save_model;
 
@@ -35,6 +42,173 @@ impl MQStorage {
         batch_save_model(self.get_connection(), msgs).await.unwrap();
     }
 
+    /// Store a message before it's processed, a message stored already is left as is.
+    pub async fn save_message(&self, msg: Model) -> Result<(), MegaError> {
+        Entity::insert(msg.into_active_model())
+            .on_conflict(OnConflict::column(Column::Id).do_nothing().to_owned())
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_done(&self, id: i64) -> Result<(), MegaError> {
+        Entity::update_many()
+            .col_expr(Column::Done, Expr::value(true))
+            .filter(Column::Id.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Messages stored but not processed yet, oldest first.
+    pub async fn get_pending_messages(&self) -> Result<Vec<Model>, MegaError> {
+        Ok(Entity::find()
+            .filter(Column::Done.eq(false))
+            .order_by_asc(Column::Id)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Keep a message that failed every attempt, it's requeued by hand.
+    pub async fn save_dead_letter(&self, msg: mq_dead_letter::Model) -> Result<(), MegaError> {
+        mq_dead_letter::Entity::insert(msg.into_active_model())
+            .on_conflict(
+                OnConflict::column(mq_dead_letter::Column::Id)
+                    .update_columns([
+                        mq_dead_letter::Column::Attempts,
+                        mq_dead_letter::Column::Error,
+                        mq_dead_letter::Column::DeadAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Dead letters, the latest first.
+    pub async fn get_dead_letters(&self) -> Result<Vec<mq_dead_letter::Model>, MegaError> {
+        Ok(mq_dead_letter::Entity::find()
+            .order_by_desc(mq_dead_letter::Column::DeadAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_dead_letter(
+        &self,
+        id: i64,
+    ) -> Result<Option<mq_dead_letter::Model>, MegaError> {
+        Ok(mq_dead_letter::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn remove_dead_letter(&self, id: i64) -> Result<(), MegaError> {
+        mq_dead_letter::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Have message `id` process the event of `key`, unless another message claimed it
+    /// after `since`. Returns whether the key is the message's, it is when the message
+    /// claimed it before, e.g. on an earlier attempt.
+    pub async fn claim_key(
+        &self,
+        key: &str,
+        id: i64,
+        since: NaiveDateTime,
+    ) -> Result<bool, MegaError> {
+        // An expired claim is taken over.
+        mq_idempotency_key::Entity::delete_many()
+            .filter(mq_idempotency_key::Column::Key.eq(key))
+            .filter(mq_idempotency_key::Column::ClaimedAt.lt(since))
+            .exec(self.get_connection())
+            .await?;
+        let claim = mq_idempotency_key::Model {
+            key: key.to_owned(),
+            message_id: id,
+            claimed_at: chrono::Utc::now().naive_utc(),
+        };
+        mq_idempotency_key::Entity::insert(claim.into_active_model())
+            .on_conflict(
+                OnConflict::column(mq_idempotency_key::Column::Key)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        let owner = mq_idempotency_key::Entity::find_by_id(key)
+            .one(self.get_connection())
+            .await?;
+        Ok(owner.is_some_and(|x| x.message_id == id))
+    }
+
+    /// Give up the claim of message `id` on `key`, so another message may process it.
+    pub async fn release_key(&self, key: &str, id: i64) -> Result<(), MegaError> {
+        mq_idempotency_key::Entity::delete_many()
+            .filter(mq_idempotency_key::Column::Key.eq(key))
+            .filter(mq_idempotency_key::Column::MessageId.eq(id))
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Drop the claims made before `before`, they don't keep duplicates out any more.
+    pub async fn purge_keys(&self, before: NaiveDateTime) -> Result<u64, MegaError> {
+        let res = mq_idempotency_key::Entity::delete_many()
+            .filter(mq_idempotency_key::Column::ClaimedAt.lt(before))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+
+    /// Drop the messages processed and created before `before`, those pending are kept.
+    pub async fn purge_messages(&self, before: NaiveDateTime) -> Result<u64, MegaError> {
+        let res = Entity::delete_many()
+            .filter(Column::Done.eq(true))
+            .filter(Column::CreateTime.lt(before))
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected)
+    }
+
+    /// Scheduled jobs and their last runs, a job never run has no row.
+    pub async fn get_jobs(&self) -> Result<Vec<mq_job::Model>, MegaError> {
+        Ok(mq_job::Entity::find().all(self.get_connection()).await?)
+    }
+
+    /// Record the run of job `name` at `run`, unless a run at or after it was recorded.
+    /// Returns whether it was, instances sharing the database run a job once that way.
+    pub async fn claim_job_run(&self, name: &str, run: NaiveDateTime) -> Result<bool, MegaError> {
+        let job = mq_job::Model {
+            name: name.to_owned(),
+            last_run: None,
+        };
+        mq_job::Entity::insert(job.into_active_model())
+            .on_conflict(
+                OnConflict::column(mq_job::Column::Name)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        let res = mq_job::Entity::update_many()
+            .col_expr(mq_job::Column::LastRun, Expr::value(run))
+            .filter(mq_job::Column::Name.eq(name))
+            .filter(
+                Condition::any()
+                    .add(mq_job::Column::LastRun.is_null())
+                    .add(mq_job::Column::LastRun.lt(run)),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
     pub async fn get_latest_message(&self) -> Option<Model> {
         Entity::find()
             .order_by_desc(Column::Id)
@@ -44,3 +218,52 @@ impl MQStorage {
             .unwrap()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use callisto::mq_storage::Model;
+    use common::config::DbConfig;
+
+    use super::MQStorage;
+    use crate::storage::init::database_connection;
+
+    #[tokio::test]
+    async fn test_purge_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DbConfig {
+            db_path: dir.path().join("mega.db").to_string_lossy().into_owned(),
+            min_connection: 1,
+            ..Default::default()
+        };
+        let storage = MQStorage::new(Arc::new(database_connection(&config).await)).await;
+        let now = Utc::now().naive_utc();
+        let old = now - Duration::days(8);
+        // old and done, old but pending, recent and done
+        for (id, create_time, done) in [(1, old, true), (2, old, false), (3, now, true)] {
+            let msg = Model {
+                id,
+                category: Some("ApiRequestEvent".to_owned()),
+                create_time,
+                content: None,
+                done,
+                priority: 0,
+                deliver_at: None,
+                idempotency_key: None,
+            };
+            storage.save_message(msg).await.unwrap();
+        }
+
+        let purged = storage
+            .purge_messages(now - Duration::days(7))
+            .await
+            .unwrap();
+        assert_eq!(purged, 1);
+        let pending = storage.get_pending_messages().await.unwrap();
+        assert_eq!(pending.iter().map(|x| x.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(storage.get_latest_message().await.unwrap().id, 3);
+    }
+}
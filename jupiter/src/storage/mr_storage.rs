@@ -1,35 +1,105 @@
 use std::sync::Arc;
 
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
-    PaginatorTrait, QueryFilter, QueryOrder, Set,
+    sea_query::{Expr, SimpleExpr},
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
 };
 
-use callisto::db_enums::{ConvType, MergeStatus};
-use callisto::{mega_conversation, mega_mr};
+use callisto::db_enums::{ConvType, MergeStatus, ReviewState};
+use callisto::{mega_conversation, mega_mr, mega_mr_review};
 use common::errors::MegaError;
+use common::model::Pagination;
 use common::utils::generate_id;
 
+use crate::storage::transaction::StorageConnection;
+
+/// Which MRs to list, every field left out matches all.
+#[derive(Debug, Clone, Default)]
+pub struct MrFilter {
+    pub status: Vec<MergeStatus>,
+    /// the directory and everything below it
+    pub path: Option<String>,
+    pub label: Option<String>,
+    pub assignee: Option<String>,
+    pub milestone: Option<String>,
+}
+
+// Whether the JSON array of strings in `column` holds `value`. The array is matched as
+// text, which works the same on every database.
+fn json_array_contains(column: &str, value: &str) -> SimpleExpr {
+    let element = serde_json::to_string(value).unwrap();
+    let pattern = format!(
+        "%{}%",
+        element
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    Expr::cust_with_values(
+        format!("CAST(\"{}\" AS TEXT) LIKE ? ESCAPE '\\'", column),
+        [pattern],
+    )
+}
+
 #[derive(Clone)]
 pub struct MrStorage {
-    pub connection: Arc<DatabaseConnection>,
+    pub connection: StorageConnection,
 }
 
 impl MrStorage {
-    pub fn get_connection(&self) -> &DatabaseConnection {
+    pub fn get_connection(&self) -> &StorageConnection {
         &self.connection
     }
 
     pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
-        MrStorage { connection }
+        MrStorage {
+            connection: connection.into(),
+        }
     }
 
     pub fn mock() -> Self {
         MrStorage {
-            connection: Arc::new(DatabaseConnection::default()),
+            connection: Arc::new(DatabaseConnection::default()).into(),
         }
     }
 
+    /// The from and to commits of every MR whatever its status, garbage collection keeps
+    /// them so that merged and closed MRs can still be viewed and reopened.
+    pub async fn get_mr_commits(&self) -> Result<Vec<(String, String)>, MegaError> {
+        Ok(mega_mr::Entity::find()
+            .select_only()
+            .columns([mega_mr::Column::FromHash, mega_mr::Column::ToHash])
+            .into_tuple()
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The open MRs that build on the MR of `link`.
+    pub async fn get_open_mrs_depending_on(
+        &self,
+        link: &str,
+    ) -> Result<Vec<mega_mr::Model>, MegaError> {
+        Ok(mega_mr::Entity::find()
+            .filter(mega_mr::Column::DependsOn.eq(link))
+            .filter(mega_mr::Column::Status.eq(MergeStatus::Open))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The open MRs whose head is `commit_id`.
+    pub async fn get_open_mrs_by_head(
+        &self,
+        commit_id: &str,
+    ) -> Result<Vec<mega_mr::Model>, MegaError> {
+        Ok(mega_mr::Entity::find()
+            .filter(mega_mr::Column::ToHash.eq(commit_id))
+            .filter(mega_mr::Column::Status.eq(MergeStatus::Open))
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The open MR that pushes to `path` go to.
     pub async fn get_open_mr_by_path(
         &self,
         path: &str,
@@ -37,25 +107,74 @@ impl MrStorage {
         let model = mega_mr::Entity::find()
             .filter(mega_mr::Column::Path.eq(path))
             .filter(mega_mr::Column::Status.eq(MergeStatus::Open))
+            .filter(mega_mr::Column::SourceBranch.is_null())
             .one(self.get_connection())
             .await
             .unwrap();
         Ok(model)
     }
 
+    /// The open MR from `branch` of `path`, a branch has at most one.
+    pub async fn get_open_mr_by_branch(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<mega_mr::Model>, MegaError> {
+        Ok(mega_mr::Entity::find()
+            .filter(mega_mr::Column::Path.eq(path))
+            .filter(mega_mr::Column::Status.eq(MergeStatus::Open))
+            .filter(mega_mr::Column::SourceBranch.eq(branch))
+            .one(self.get_connection())
+            .await?)
+    }
+
     pub async fn get_mr_by_status(
         &self,
         status: Vec<MergeStatus>,
-        page: u64,
-        per_page: u64,
+        page: Pagination,
     ) -> Result<(Vec<mega_mr::Model>, u64), MegaError> {
-        let paginator = mega_mr::Entity::find()
-            .filter(mega_mr::Column::Status.is_in(status))
+        let filter = MrFilter {
+            status,
+            ..Default::default()
+        };
+        self.get_mr_by_filter(&filter, page).await
+    }
+
+    /// MRs matching `filter`, the latest first, with the number of all matching.
+    pub async fn get_mr_by_filter(
+        &self,
+        filter: &MrFilter,
+        page: Pagination,
+    ) -> Result<(Vec<mega_mr::Model>, u64), MegaError> {
+        let mut query =
+            mega_mr::Entity::find().filter(mega_mr::Column::Status.is_in(filter.status.clone()));
+        if let Some(label) = &filter.label {
+            query = query.filter(json_array_contains("labels", label));
+        }
+        if let Some(assignee) = &filter.assignee {
+            query = query.filter(json_array_contains("assignees", assignee));
+        }
+        if let Some(milestone) = &filter.milestone {
+            query = query.filter(mega_mr::Column::Milestone.eq(milestone));
+        }
+        if let Some(path) = filter
+            .path
+            .as_deref()
+            .map(|x| x.trim_end_matches('/'))
+            .filter(|x| !x.is_empty())
+        {
+            query = query.filter(
+                Condition::any()
+                    .add(mega_mr::Column::Path.eq(path))
+                    .add(mega_mr::Column::Path.starts_with(format!("{}/", path))),
+            );
+        }
+        let paginator = query
             .order_by_desc(mega_mr::Column::CreatedAt)
-            .paginate(self.get_connection(), per_page);
+            .paginate(self.get_connection(), page.per_page);
         let num_pages = paginator.num_items().await?;
         Ok(paginator
-            .fetch_page(page - 1)
+            .fetch_page(page.page - 1)
             .await
             .map(|m| (m, num_pages))?)
     }
@@ -111,6 +230,32 @@ impl MrStorage {
         Ok(())
     }
 
+    /// Mark an MR as a draft or ready for review, with a conversation entry saying so.
+    pub async fn set_draft(
+        &self,
+        mut model: mega_mr::Model,
+        draft: bool,
+        user_id: i64,
+        username: &str,
+    ) -> Result<(), MegaError> {
+        model.draft = draft;
+        self.update_mr(model.clone()).await?;
+        let (conv_type, comment) = if draft {
+            (
+                ConvType::Draft,
+                format!("{} marked this as draft", username),
+            )
+        } else {
+            (
+                ConvType::Ready,
+                format!("{} marked this ready for review", username),
+            )
+        };
+        self.add_mr_conversation(&model.link, user_id, conv_type, Some(comment))
+            .await?;
+        Ok(())
+    }
+
     pub async fn update_mr(&self, mr: mega_mr::Model) -> Result<(), MegaError> {
         let mut a_model = mr.into_active_model();
         a_model = a_model.reset_all();
@@ -130,6 +275,23 @@ impl MrStorage {
         Ok(model?)
     }
 
+    /// Conversations of a MR in the order they were written.
+    pub async fn get_mr_conversations_page(
+        &self,
+        link: &str,
+        page: Pagination,
+    ) -> Result<(Vec<mega_conversation::Model>, u64), MegaError> {
+        let paginator = mega_conversation::Entity::find()
+            .filter(mega_conversation::Column::Link.eq(link))
+            .order_by_asc(mega_conversation::Column::CreatedAt)
+            .paginate(self.get_connection(), page.per_page);
+        let total = paginator.num_items().await?;
+        Ok(paginator
+            .fetch_page(page.page - 1)
+            .await
+            .map(|m| (m, total))?)
+    }
+
     pub async fn remove_mr_conversation(&self, id: i64) -> Result<(), MegaError> {
         mega_conversation::Entity::delete_by_id(id)
             .exec(self.get_connection())
@@ -151,6 +313,11 @@ impl MrStorage {
             user_id,
             conv_type,
             comment,
+            path: None,
+            commit_id: None,
+            line: None,
+            reply_to: None,
+            resolved: false,
             created_at: chrono::Utc::now().naive_utc(),
             updated_at: chrono::Utc::now().naive_utc(),
         };
@@ -158,4 +325,171 @@ impl MrStorage {
         let res = conversation.insert(self.get_connection()).await.unwrap();
         Ok(res.id)
     }
+
+    pub async fn get_mr_conversation(
+        &self,
+        id: i64,
+    ) -> Result<Option<mega_conversation::Model>, MegaError> {
+        Ok(mega_conversation::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Start a review thread on `line` of `path` as of `commit_id`.
+    pub async fn add_mr_review_comment(
+        &self,
+        link: &str,
+        user_id: i64,
+        path: &str,
+        commit_id: &str,
+        line: i32,
+        comment: String,
+    ) -> Result<i64, MegaError> {
+        let conversation = mega_conversation::Model {
+            id: generate_id(),
+            link: link.to_owned(),
+            user_id,
+            conv_type: ConvType::ReviewComment,
+            comment: Some(comment),
+            path: Some(path.to_owned()),
+            commit_id: Some(commit_id.to_owned()),
+            line: Some(line),
+            reply_to: None,
+            resolved: false,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        };
+        let res = conversation
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        Ok(res.id)
+    }
+
+    /// Reply in the thread of `reply_to`, which may be any comment of the thread.
+    ///
+    /// Returns `None` when `reply_to` isn't a review comment of the MR.
+    pub async fn reply_mr_review_comment(
+        &self,
+        link: &str,
+        user_id: i64,
+        reply_to: i64,
+        comment: String,
+    ) -> Result<Option<i64>, MegaError> {
+        let Some(parent) = self
+            .get_mr_conversation(reply_to)
+            .await?
+            .filter(|x| x.link == link && x.conv_type == ConvType::ReviewComment)
+        else {
+            return Ok(None);
+        };
+        let conversation = mega_conversation::Model {
+            id: generate_id(),
+            link: link.to_owned(),
+            user_id,
+            conv_type: ConvType::ReviewComment,
+            comment: Some(comment),
+            reply_to: Some(parent.reply_to.unwrap_or(parent.id)),
+            resolved: false,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+            ..parent
+        };
+        let res = conversation
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?;
+        Ok(Some(res.id))
+    }
+
+    /// Review comments of a MR in the order they were written.
+    pub async fn get_mr_review_comments(
+        &self,
+        link: &str,
+    ) -> Result<Vec<mega_conversation::Model>, MegaError> {
+        Ok(mega_conversation::Entity::find()
+            .filter(mega_conversation::Column::Link.eq(link))
+            .filter(mega_conversation::Column::ConvType.eq(ConvType::ReviewComment))
+            .order_by_asc(mega_conversation::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Resolve or unresolve the thread of review comment `id`, the state is kept on the
+    /// first comment of the thread. Returns false when `id` isn't a review comment.
+    pub async fn set_review_thread_resolved(
+        &self,
+        id: i64,
+        resolved: bool,
+    ) -> Result<bool, MegaError> {
+        let Some(model) = self
+            .get_mr_conversation(id)
+            .await?
+            .filter(|x| x.conv_type == ConvType::ReviewComment)
+        else {
+            return Ok(false);
+        };
+        let root = match model.reply_to {
+            Some(root) => match self.get_mr_conversation(root).await? {
+                Some(root) => root,
+                None => return Ok(false),
+            },
+            None => model,
+        };
+        let mut a_model = root.into_active_model();
+        a_model.resolved = Set(resolved);
+        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        a_model.update(self.get_connection()).await?;
+        Ok(true)
+    }
+
+    /// Record the review of `user_id`, replacing the one the user gave before.
+    pub async fn save_mr_review(
+        &self,
+        link: &str,
+        user_id: i64,
+        username: &str,
+        state: ReviewState,
+    ) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let existing = mega_mr_review::Entity::find()
+            .filter(mega_mr_review::Column::Link.eq(link))
+            .filter(mega_mr_review::Column::UserId.eq(user_id))
+            .one(self.get_connection())
+            .await?;
+        match existing {
+            Some(model) => {
+                let mut a_model = model.into_active_model();
+                a_model.state = Set(state);
+                a_model.updated_at = Set(now);
+                a_model.update(self.get_connection()).await?;
+            }
+            None => {
+                mega_mr_review::Model {
+                    id: generate_id(),
+                    link: link.to_owned(),
+                    user_id,
+                    username: username.to_owned(),
+                    state,
+                    created_at: now,
+                    updated_at: now,
+                }
+                .into_active_model()
+                .insert(self.get_connection())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get_mr_reviews(
+        &self,
+        link: &str,
+    ) -> Result<Vec<mega_mr_review::Model>, MegaError> {
+        Ok(mega_mr_review::Entity::find()
+            .filter(mega_mr_review::Column::Link.eq(link))
+            .order_by_asc(mega_mr_review::Column::UpdatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
 }
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter,
+    QueryOrder,
+};
+
+use callisto::mega_notification;
+use common::errors::MegaError;
+use common::model::Pagination;
+
+#[derive(Clone)]
+pub struct NotificationStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl NotificationStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        NotificationStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        NotificationStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Store a notification, one stored already is left as is.
+    pub async fn save(&self, model: mega_notification::Model) -> Result<(), MegaError> {
+        mega_notification::Entity::insert(model.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_notification::Column::Id)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .do_nothing()
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Notifications of a user, the latest first, with the number of all of them.
+    pub async fn list(
+        &self,
+        user_id: i64,
+        unread_only: bool,
+        page: Pagination,
+    ) -> Result<(Vec<mega_notification::Model>, u64), MegaError> {
+        let mut query =
+            mega_notification::Entity::find().filter(mega_notification::Column::UserId.eq(user_id));
+        if unread_only {
+            query = query.filter(mega_notification::Column::Read.eq(false));
+        }
+        let paginator = query
+            .order_by_desc(mega_notification::Column::CreatedAt)
+            .order_by_desc(mega_notification::Column::Id)
+            .paginate(self.get_connection(), page.per_page);
+        let total = paginator.num_items().await?;
+        Ok(paginator
+            .fetch_page(page.page - 1)
+            .await
+            .map(|m| (m, total))?)
+    }
+
+    pub async fn unread_count(&self, user_id: i64) -> Result<u64, MegaError> {
+        Ok(mega_notification::Entity::find()
+            .filter(mega_notification::Column::UserId.eq(user_id))
+            .filter(mega_notification::Column::Read.eq(false))
+            .count(self.get_connection())
+            .await?)
+    }
+
+    /// Mark notifications of a user read, all of them when `ids` is `None`. Returns how
+    /// many were unread.
+    pub async fn mark_read(&self, user_id: i64, ids: Option<Vec<i64>>) -> Result<u64, MegaError> {
+        let mut query = mega_notification::Entity::update_many()
+            .col_expr(mega_notification::Column::Read, Expr::value(true))
+            .filter(mega_notification::Column::UserId.eq(user_id))
+            .filter(mega_notification::Column::Read.eq(false));
+        if let Some(ids) = ids {
+            query = query.filter(mega_notification::Column::Id.is_in(ids));
+        }
+        let res = query.exec(self.get_connection()).await?;
+        Ok(res.rows_affected)
+    }
+}
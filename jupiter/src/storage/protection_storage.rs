@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+
+use callisto::mega_protection_rule;
+use common::config::most_specific;
+use common::errors::MegaError;
+
+/// Names kept in a JSON array column of a rule, a malformed column protects nothing.
+pub fn rule_names(value: &serde_json::Value) -> Vec<String> {
+    serde_json::from_value(value.clone()).unwrap_or_default()
+}
+
+#[derive(Clone)]
+pub struct ProtectionStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ProtectionStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ProtectionStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        ProtectionStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    pub async fn save_rule(
+        &self,
+        rule: mega_protection_rule::Model,
+    ) -> Result<mega_protection_rule::Model, MegaError> {
+        Ok(rule
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    pub async fn update_rule(
+        &self,
+        rule: mega_protection_rule::Model,
+    ) -> Result<mega_protection_rule::Model, MegaError> {
+        Ok(rule
+            .into_active_model()
+            .reset_all()
+            .update(self.get_connection())
+            .await?)
+    }
+
+    pub async fn delete_rule(&self, id: i64) -> Result<(), MegaError> {
+        mega_protection_rule::Entity::delete_by_id(id)
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_rule(
+        &self,
+        id: i64,
+    ) -> Result<Option<mega_protection_rule::Model>, MegaError> {
+        Ok(mega_protection_rule::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// The rule set on exactly `branch` of `path`, rules of parent directories aside.
+    pub async fn get_rule_by_path(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<mega_protection_rule::Model>, MegaError> {
+        Ok(mega_protection_rule::Entity::find()
+            .filter(mega_protection_rule::Column::Path.eq(path))
+            .filter(mega_protection_rule::Column::Branch.eq(branch))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_rules(&self) -> Result<Vec<mega_protection_rule::Model>, MegaError> {
+        Ok(mega_protection_rule::Entity::find()
+            .order_by_asc(mega_protection_rule::Column::Path)
+            .order_by_asc(mega_protection_rule::Column::Branch)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// The rule protecting `branch` of `path`, the one with the most specific path when
+    /// several directories above it are protected.
+    pub async fn find_rule(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<mega_protection_rule::Model>, MegaError> {
+        let rules = mega_protection_rule::Entity::find()
+            .filter(mega_protection_rule::Column::Branch.eq(branch))
+            .all(self.get_connection())
+            .await?;
+        Ok(most_specific(&rules, path, |rule| &rule.path).cloned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::rule_names;
+
+    #[test]
+    fn test_rule_names() {
+        assert_eq!(rule_names(&json!(["ci", "lint"])), vec!["ci", "lint"]);
+        assert!(rule_names(&json!([])).is_empty());
+        assert!(rule_names(&json!({ "ci": true })).is_empty());
+    }
+}
@@ -1,14 +1,24 @@
 use std::sync::Arc;
 
-use futures::Stream;
-use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use futures::{Stream, StreamExt};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set, Unchanged,
+};
 
-use callisto::raw_blob;
+use callisto::{db_enums::StorageType, raw_blob};
 use common::errors::MegaError;
 
+use crate::blob_storage::{self, BlobStore};
+use crate::storage::batch_save_model;
+
+/// Blobs read per query while compressing stored blobs.
+const COMPRESS_BATCH: u64 = 100;
+
 #[derive(Clone)]
 pub struct RawDbStorage {
     pub connection: Arc<DatabaseConnection>,
+    blob_store: BlobStore,
 }
 
 impl RawDbStorage {
@@ -16,46 +26,128 @@ impl RawDbStorage {
         &self.connection
     }
 
-    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
-        RawDbStorage { connection }
+    pub async fn new(connection: Arc<DatabaseConnection>, blob_store: BlobStore) -> Self {
+        RawDbStorage {
+            connection,
+            blob_store,
+        }
     }
 
     pub fn mock() -> Self {
         RawDbStorage {
             connection: Arc::new(DatabaseConnection::default()),
+            blob_store: BlobStore::default(),
         }
     }
 
+    pub fn blob_store(&self) -> &BlobStore {
+        &self.blob_store
+    }
+
+    pub async fn save_raw_blob(&self, model: raw_blob::Model) -> Result<(), MegaError> {
+        let model = self.blob_store.offload(model).await?;
+        batch_save_model(self.get_connection(), vec![raw_blob::ActiveModel::from(model)]).await
+    }
+
     pub async fn get_raw_blobs_by_hashes(
         &self,
         hashes: Vec<String>,
     ) -> Result<Vec<raw_blob::Model>, MegaError> {
-        Ok(raw_blob::Entity::find()
+        let models = raw_blob::Entity::find()
             .filter(raw_blob::Column::Sha1.is_in(hashes))
             .all(self.get_connection())
-            .await
-            .unwrap())
+            .await?;
+        let mut loaded = Vec::with_capacity(models.len());
+        for model in models {
+            loaded.push(self.blob_store.load(model).await?);
+        }
+        Ok(loaded)
     }
 
     pub async fn get_raw_blob_by_hash(
         &self,
         hash: &str,
     ) -> Result<Option<raw_blob::Model>, MegaError> {
-        Ok(raw_blob::Entity::find()
+        match raw_blob::Entity::find()
             .filter(raw_blob::Column::Sha1.eq(hash))
             .one(self.get_connection())
-            .await
-            .unwrap())
+            .await?
+        {
+            Some(model) => Ok(Some(self.blob_store.load(model).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Where the content of each of the blobs is kept, without loading it.
+    pub async fn get_raw_blob_locations(
+        &self,
+        hashes: Vec<String>,
+    ) -> Result<Vec<(StorageType, Option<String>)>, MegaError> {
+        Ok(raw_blob::Entity::find()
+            .select_only()
+            .column(raw_blob::Column::StorageType)
+            .column(raw_blob::Column::LocalPath)
+            .filter(raw_blob::Column::Sha1.is_in(hashes))
+            .into_tuple()
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Compress the content of the blobs kept uncompressed in the database, like those
+    /// stored before compression was enabled. Returns how many were compressed.
+    pub async fn compress_stored(&self) -> Result<u64, MegaError> {
+        let mut compressed = 0;
+        let mut last_id = i64::MIN;
+        loop {
+            let models = raw_blob::Entity::find()
+                .filter(raw_blob::Column::Id.gt(last_id))
+                .filter(raw_blob::Column::StorageType.eq(StorageType::Database))
+                .filter(raw_blob::Column::Compression.is_null())
+                .filter(raw_blob::Column::Data.is_not_null())
+                .order_by_asc(raw_blob::Column::Id)
+                .limit(COMPRESS_BATCH)
+                .all(self.get_connection())
+                .await?;
+            let Some(last) = models.last() else {
+                break;
+            };
+            last_id = last.id;
+            for model in models {
+                let model = blob_storage::compress(model)?;
+                if model.compression.is_none() {
+                    continue;
+                }
+                raw_blob::ActiveModel {
+                    id: Unchanged(model.id),
+                    data: Set(model.data),
+                    compression: Set(model.compression),
+                    ..Default::default()
+                }
+                .update(self.get_connection())
+                .await?;
+                compressed += 1;
+            }
+        }
+        Ok(compressed)
     }
 
     pub async fn get_raw_blobs_stream(
         &self,
         hashes: Vec<String>,
     ) -> Result<impl Stream<Item = Result<raw_blob::Model, DbErr>> + '_ + Send, MegaError> {
-        Ok(raw_blob::Entity::find()
+        let stream = raw_blob::Entity::find()
             .filter(raw_blob::Column::Sha1.is_in(hashes))
             .stream(self.get_connection())
-            .await
-            .unwrap())
+            .await?;
+        Ok(stream.then(move |model| async move {
+            match model {
+                Ok(model) => self
+                    .blob_store
+                    .load(model)
+                    .await
+                    .map_err(|e| DbErr::Custom(e.to_string())),
+                Err(e) => Err(e),
+            }
+        }))
     }
 }
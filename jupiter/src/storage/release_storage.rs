@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set,
+};
+
+use callisto::{mega_release, mega_release_asset};
+use common::errors::MegaError;
+
+use crate::storage::batch_save_model;
+
+#[derive(Clone)]
+pub struct ReleaseStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl ReleaseStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        ReleaseStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        ReleaseStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    pub async fn save_release(
+        &self,
+        release: mega_release::Model,
+    ) -> Result<mega_release::Model, MegaError> {
+        Ok(release
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_release(&self, id: i64) -> Result<Option<mega_release::Model>, MegaError> {
+        Ok(mega_release::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_release_by_tag(
+        &self,
+        tag_id: &str,
+    ) -> Result<Option<mega_release::Model>, MegaError> {
+        Ok(mega_release::Entity::find()
+            .filter(mega_release::Column::TagId.eq(tag_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn update_release(
+        &self,
+        release: mega_release::Model,
+    ) -> Result<mega_release::Model, MegaError> {
+        let mut a_model = release.into_active_model();
+        a_model = a_model.reset_all();
+        a_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        Ok(a_model.update(self.get_connection()).await?)
+    }
+
+    /// Releases of the tags scoped to `path`, newest first.
+    pub async fn get_releases_by_path(
+        &self,
+        path: &str,
+    ) -> Result<Vec<mega_release::Model>, MegaError> {
+        Ok(mega_release::Entity::find()
+            .filter(mega_release::Column::Path.eq(path))
+            .order_by_desc(mega_release::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_assets(
+        &self,
+        assets: Vec<mega_release_asset::Model>,
+    ) -> Result<(), MegaError> {
+        let save_models = assets.into_iter().map(|x| x.into_active_model()).collect();
+        batch_save_model(self.get_connection(), save_models).await
+    }
+
+    pub async fn get_assets(
+        &self,
+        release_ids: Vec<i64>,
+    ) -> Result<Vec<mega_release_asset::Model>, MegaError> {
+        Ok(mega_release_asset::Entity::find()
+            .filter(mega_release_asset::Column::ReleaseId.is_in(release_ids))
+            .order_by_asc(mega_release_asset::Column::Name)
+            .all(self.get_connection())
+            .await?)
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::{Expr, Func, Query},
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+use callisto::{search_file, search_state, search_trigram};
+use common::errors::MegaError;
+use common::utils::generate_id;
+
+use crate::storage::batch_save_model;
+
+/// The index follows a single ref, the root of the monorepo.
+const ROOT_STATE_ID: i64 = 1;
+
+/// Lowercase trigrams of `text`, the unit of the search index. Windows containing
+/// whitespace are left out, they are common and tell little about a file.
+pub fn trigrams(text: &str) -> BTreeSet<String> {
+    let chars: Vec<char> = text.to_lowercase().chars().collect();
+    chars
+        .windows(3)
+        .filter(|x| !x.iter().any(|c| c.is_whitespace()))
+        .map(|x| x.iter().collect())
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct SearchStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SearchStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SearchStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        SearchStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Commit the index was last brought up to date with.
+    pub async fn get_state(&self) -> Result<Option<search_state::Model>, MegaError> {
+        Ok(search_state::Entity::find_by_id(ROOT_STATE_ID)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn set_state(&self, commit_id: &str, tree_id: &str) -> Result<(), MegaError> {
+        let model = search_state::Model {
+            id: ROOT_STATE_ID,
+            commit_id: commit_id.to_owned(),
+            tree_id: tree_id.to_owned(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        };
+        match self.get_state().await? {
+            Some(_) => {
+                model
+                    .into_active_model()
+                    .reset_all()
+                    .update(self.get_connection())
+                    .await?;
+            }
+            None => {
+                model
+                    .into_active_model()
+                    .insert(self.get_connection())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Index `path` with the content of `blob_id`, replacing what was indexed for it before.
+    pub async fn index_file(
+        &self,
+        path: &str,
+        blob_id: &str,
+        trigrams: BTreeSet<String>,
+    ) -> Result<(), MegaError> {
+        let now = chrono::Utc::now().naive_utc();
+        let existing = search_file::Entity::find()
+            .filter(search_file::Column::Path.eq(path))
+            .one(self.get_connection())
+            .await?;
+        let file_id = match existing {
+            Some(file) if file.blob_id == blob_id => return Ok(()),
+            Some(file) => {
+                search_trigram::Entity::delete_many()
+                    .filter(search_trigram::Column::FileId.eq(file.id))
+                    .exec(self.get_connection())
+                    .await?;
+                let mut a_model = file.into_active_model();
+                a_model.blob_id = Set(blob_id.to_owned());
+                a_model.updated_at = Set(now);
+                a_model.update(self.get_connection()).await?.id
+            }
+            None => {
+                let file = search_file::Model {
+                    id: generate_id(),
+                    path: path.to_owned(),
+                    blob_id: blob_id.to_owned(),
+                    updated_at: now,
+                };
+                file.into_active_model()
+                    .insert(self.get_connection())
+                    .await?
+                    .id
+            }
+        };
+        let rows: Vec<search_trigram::ActiveModel> = trigrams
+            .into_iter()
+            .map(|trigram| search_trigram::Model { file_id, trigram }.into_active_model())
+            .collect();
+        batch_save_model(self.get_connection(), rows).await
+    }
+
+    /// Drop `path` from the index, along with everything below it when it's a directory.
+    pub async fn remove_path(&self, path: &str) -> Result<(), MegaError> {
+        let files = search_file::Entity::find()
+            .filter(
+                Condition::any()
+                    .add(search_file::Column::Path.eq(path))
+                    .add(search_file::Column::Path.starts_with(format!("{}/", path))),
+            )
+            .all(self.get_connection())
+            .await?;
+        for chunk in files.chunks(1000) {
+            let ids: Vec<i64> = chunk.iter().map(|x| x.id).collect();
+            search_trigram::Entity::delete_many()
+                .filter(search_trigram::Column::FileId.is_in(ids.clone()))
+                .exec(self.get_connection())
+                .await?;
+            search_file::Entity::delete_many()
+                .filter(search_file::Column::Id.is_in(ids))
+                .exec(self.get_connection())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Files under `path` containing all of `trigrams`, ordered by path. They are only
+    /// candidates, the content still has to be checked for the query.
+    pub async fn find_candidates(
+        &self,
+        trigrams: &BTreeSet<String>,
+        path: &str,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<search_file::Model>, MegaError> {
+        let matching = Query::select()
+            .column(search_trigram::Column::FileId)
+            .from(search_trigram::Entity)
+            .and_where(search_trigram::Column::Trigram.is_in(trigrams.iter().cloned()))
+            .group_by_col(search_trigram::Column::FileId)
+            .and_having(
+                Expr::expr(Func::count(Expr::col(search_trigram::Column::Trigram)))
+                    .eq(trigrams.len() as i64),
+            )
+            .to_owned();
+        let mut query =
+            search_file::Entity::find().filter(search_file::Column::Id.in_subquery(matching));
+        if path != "/" {
+            query = query.filter(search_file::Column::Path.starts_with(format!("{}/", path)));
+        }
+        Ok(query
+            .order_by_asc(search_file::Column::Path)
+            .offset(offset)
+            .limit(limit)
+            .all(self.get_connection())
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::trigrams;
+
+    #[test]
+    fn test_trigrams() {
+        let grams: Vec<String> = trigrams("Fn main(").into_iter().collect();
+        assert_eq!(grams, vec!["ain", "in(", "mai"]);
+        assert!(trigrams("a b").is_empty());
+        assert_eq!(trigrams("ÄÖÜ").len(), 1);
+    }
+}
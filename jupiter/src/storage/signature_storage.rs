@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel,
+    QueryFilter,
+};
+
+use callisto::{mega_commit_signature, mega_push_cert};
+use common::errors::MegaError;
+
+#[derive(Clone)]
+pub struct SignatureStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl SignatureStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        SignatureStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        SignatureStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Save the verification of a commit signature, replacing an earlier one.
+    pub async fn save_commit_signature(
+        &self,
+        model: mega_commit_signature::Model,
+    ) -> Result<(), MegaError> {
+        mega_commit_signature::Entity::insert(model.into_active_model())
+            .on_conflict(
+                OnConflict::column(mega_commit_signature::Column::CommitId)
+                    .update_columns([
+                        mega_commit_signature::Column::Format,
+                        mega_commit_signature::Column::Verified,
+                        mega_commit_signature::Column::Signer,
+                        mega_commit_signature::Column::Reason,
+                        mega_commit_signature::Column::CreatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_commit_signature(
+        &self,
+        commit_id: &str,
+    ) -> Result<Option<mega_commit_signature::Model>, MegaError> {
+        Ok(mega_commit_signature::Entity::find()
+            .filter(mega_commit_signature::Column::CommitId.eq(commit_id))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn save_push_cert(&self, model: mega_push_cert::Model) -> Result<(), MegaError> {
+        mega_push_cert::Entity::insert(model.into_active_model())
+            .exec_without_returning(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter,
+};
+
+use callisto::dir_stats;
+use common::errors::MegaError;
+
+#[derive(Clone)]
+pub struct StatsStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl StatsStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        StatsStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        StatsStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    pub async fn get_stats(&self, path: &str) -> Result<Option<dir_stats::Model>, MegaError> {
+        Ok(dir_stats::Entity::find()
+            .filter(dir_stats::Column::Path.eq(path))
+            .one(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_stats_by_paths(
+        &self,
+        paths: Vec<String>,
+    ) -> Result<Vec<dir_stats::Model>, MegaError> {
+        let mut stats = Vec::with_capacity(paths.len());
+        for chunk in paths.chunks(1000) {
+            stats.extend(
+                dir_stats::Entity::find()
+                    .filter(dir_stats::Column::Path.is_in(chunk.to_vec()))
+                    .all(self.get_connection())
+                    .await?,
+            );
+        }
+        Ok(stats)
+    }
+
+    /// Insert the statistics of a directory or replace those kept for its path.
+    pub async fn save_stats(&self, model: dir_stats::Model) -> Result<(), MegaError> {
+        dir_stats::Entity::insert(model.into_active_model())
+            .on_conflict(
+                OnConflict::column(dir_stats::Column::Path)
+                    .update_columns([
+                        dir_stats::Column::TreeId,
+                        dir_stats::Column::Size,
+                        dir_stats::Column::FileCount,
+                        dir_stats::Column::OwnSize,
+                        dir_stats::Column::OwnFileCount,
+                        dir_stats::Column::LastActivity,
+                        dir_stats::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Drop the statistics of `path` and the directories below it.
+    pub async fn remove_below(&self, path: &str) -> Result<(), MegaError> {
+        dir_stats::Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(dir_stats::Column::Path.eq(path))
+                    .add(dir_stats::Column::Path.starts_with(format!("{}/", path))),
+            )
+            .exec(self.get_connection())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Directories deleted from the monorepo, kept as a record of the tree they had when
+//! they were deleted so they can be brought back by a new commit.
+
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set,
+};
+
+use callisto::mega_tombstone;
+use common::errors::MegaError;
+use common::utils::generate_id;
+
+use crate::storage::mono_storage::MonoStorage;
+
+impl MonoStorage {
+    /// Record that `commit_id` deleted the directory `path`, whose tree was `tree_id`.
+    pub async fn save_tombstone(
+        &self,
+        path: &str,
+        commit_id: &str,
+        tree_id: &str,
+    ) -> Result<mega_tombstone::Model, MegaError> {
+        let model = mega_tombstone::Model {
+            id: generate_id(),
+            path: path.to_owned(),
+            commit_id: commit_id.to_owned(),
+            tree_id: tree_id.to_owned(),
+            restored_commit: None,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        Ok(model
+            .into_active_model()
+            .insert(self.get_connection())
+            .await?)
+    }
+
+    pub async fn get_tombstone(&self, id: i64) -> Result<Option<mega_tombstone::Model>, MegaError> {
+        Ok(mega_tombstone::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?)
+    }
+
+    /// Directories deleted at `path` or below it, the latest first.
+    pub async fn get_tombstones(
+        &self,
+        path: &str,
+    ) -> Result<Vec<mega_tombstone::Model>, MegaError> {
+        let mut query = mega_tombstone::Entity::find();
+        if path != "/" {
+            query = query.filter(
+                Condition::any()
+                    .add(mega_tombstone::Column::Path.eq(path))
+                    .add(mega_tombstone::Column::Path.starts_with(format!("{}/", path))),
+            );
+        }
+        Ok(query
+            .order_by_desc(mega_tombstone::Column::CreatedAt)
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Note the commit that brought the directory of `tombstone` back.
+    pub async fn set_tombstone_restored(
+        &self,
+        tombstone: mega_tombstone::Model,
+        commit_id: &str,
+    ) -> Result<(), MegaError> {
+        let mut a_model = tombstone.into_active_model();
+        a_model.restored_commit = Set(Some(commit_id.to_owned()));
+        a_model.update(self.get_connection()).await?;
+        Ok(())
+    }
+}
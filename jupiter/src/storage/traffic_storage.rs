@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use chrono::{NaiveDate, Utc};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+
+use callisto::db_enums::TrafficKind;
+use callisto::traffic_stats;
+use common::errors::MegaError;
+use common::utils::generate_id;
+
+#[derive(Clone)]
+pub struct TrafficStorage {
+    pub connection: Arc<DatabaseConnection>,
+}
+
+impl TrafficStorage {
+    pub fn get_connection(&self) -> &DatabaseConnection {
+        &self.connection
+    }
+
+    pub async fn new(connection: Arc<DatabaseConnection>) -> Self {
+        TrafficStorage { connection }
+    }
+
+    pub fn mock() -> Self {
+        TrafficStorage {
+            connection: Arc::new(DatabaseConnection::default()),
+        }
+    }
+
+    /// Count one hit of `kind` on `path` for the given day.
+    pub async fn record(
+        &self,
+        path: &str,
+        kind: TrafficKind,
+        day: NaiveDate,
+    ) -> Result<(), MegaError> {
+        let now = Utc::now().naive_utc();
+        let model = traffic_stats::Model {
+            id: generate_id(),
+            path: path.to_owned(),
+            kind,
+            day,
+            count: 1,
+            updated_at: now,
+        };
+        traffic_stats::Entity::insert(model.into_active_model())
+            .on_conflict(
+                OnConflict::columns([
+                    traffic_stats::Column::Path,
+                    traffic_stats::Column::Kind,
+                    traffic_stats::Column::Day,
+                ])
+                .value(
+                    traffic_stats::Column::Count,
+                    Expr::col((traffic_stats::Entity, traffic_stats::Column::Count)).add(1),
+                )
+                .update_column(traffic_stats::Column::UpdatedAt)
+                .to_owned(),
+            )
+            .exec_without_returning(self.get_connection())
+            .await?;
+        Ok(())
+    }
+
+    /// Daily counters of `path` and everything below it since `since`, oldest first.
+    pub async fn get_stats_under_path(
+        &self,
+        path: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<traffic_stats::Model>, MegaError> {
+        let mut query =
+            traffic_stats::Entity::find().filter(traffic_stats::Column::Day.gte(since));
+        if path != "/" {
+            query = query.filter(
+                Condition::any()
+                    .add(traffic_stats::Column::Path.eq(path))
+                    .add(traffic_stats::Column::Path.starts_with(format!("{}/", path))),
+            );
+        }
+        Ok(query
+            .order_by_asc(traffic_stats::Column::Day)
+            .all(self.get_connection())
+            .await?)
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sea_orm::{
+    ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, DbErr, ExecResult,
+    QueryResult, Statement,
+};
+
+/// The connection a storage writes through, the database itself or a transaction opened
+/// on it by [`Context::transaction`](crate::context::Context::transaction).
+#[derive(Clone)]
+pub enum StorageConnection {
+    Database(Arc<DatabaseConnection>),
+    Transaction(Arc<DatabaseTransaction>),
+}
+
+impl From<Arc<DatabaseConnection>> for StorageConnection {
+    fn from(value: Arc<DatabaseConnection>) -> Self {
+        StorageConnection::Database(value)
+    }
+}
+
+#[async_trait]
+impl ConnectionTrait for StorageConnection {
+    fn get_database_backend(&self) -> DbBackend {
+        match self {
+            StorageConnection::Database(db) => db.get_database_backend(),
+            StorageConnection::Transaction(txn) => txn.get_database_backend(),
+        }
+    }
+
+    async fn execute(&self, stmt: Statement) -> Result<ExecResult, DbErr> {
+        match self {
+            StorageConnection::Database(db) => db.execute(stmt).await,
+            StorageConnection::Transaction(txn) => txn.execute(stmt).await,
+        }
+    }
+
+    async fn execute_unprepared(&self, sql: &str) -> Result<ExecResult, DbErr> {
+        match self {
+            StorageConnection::Database(db) => db.execute_unprepared(sql).await,
+            StorageConnection::Transaction(txn) => txn.execute_unprepared(sql).await,
+        }
+    }
+
+    async fn query_one(&self, stmt: Statement) -> Result<Option<QueryResult>, DbErr> {
+        match self {
+            StorageConnection::Database(db) => db.query_one(stmt).await,
+            StorageConnection::Transaction(txn) => txn.query_one(stmt).await,
+        }
+    }
+
+    async fn query_all(&self, stmt: Statement) -> Result<Vec<QueryResult>, DbErr> {
+        match self {
+            StorageConnection::Database(db) => db.query_all(stmt).await,
+            StorageConnection::Transaction(txn) => txn.query_all(stmt).await,
+        }
+    }
+
+    fn support_returning(&self) -> bool {
+        match self {
+            StorageConnection::Database(db) => db.support_returning(),
+            StorageConnection::Transaction(txn) => txn.support_returning(),
+        }
+    }
+
+    fn is_mock_connection(&self) -> bool {
+        match self {
+            StorageConnection::Database(db) => db.is_mock_connection(),
+            StorageConnection::Transaction(txn) => txn.is_mock_connection(),
+        }
+    }
+}
@@ -1,13 +1,41 @@
 use std::sync::Arc;
 
+use ring::digest;
 use sea_orm::{
     ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, ModelTrait,
-    QueryFilter,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
 };
+use serde_json::json;
 use uuid::Uuid;
 
 use callisto::{access_token, ssh_keys, user};
-use common::{errors::MegaError, utils::generate_id};
+use common::{
+    errors::MegaError,
+    model::{Pagination, TokenScope},
+    utils::generate_id,
+};
+
+/// Tokens start with it so they are easy to tell apart from other secrets.
+const TOKEN_PREFIX: &str = "mega_";
+
+/// Seconds `last_used_at` may lag behind, so every request doesn't write the token row.
+const LAST_USED_INTERVAL: i64 = 60;
+
+/// Tokens are looked up by their SHA-256, the token itself is never stored.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, token.as_bytes()))
+}
+
+/// Scopes kept in the JSON array column of a token, unknown ones are ignored.
+pub fn token_scopes(value: &serde_json::Value) -> Vec<TokenScope> {
+    match value {
+        serde_json::Value::Array(items) => items
+            .iter()
+            .filter_map(|x| serde_json::from_value(x.clone()).ok())
+            .collect(),
+        _ => vec![],
+    }
+}
 
 #[derive(Clone)]
 pub struct UserStorage {
@@ -45,12 +73,77 @@ impl UserStorage {
         Ok(res)
     }
 
+    pub async fn find_user_by_id(&self, id: i64) -> Result<Option<user::Model>, MegaError> {
+        let res = user::Entity::find_by_id(id)
+            .one(self.get_connection())
+            .await?;
+        Ok(res)
+    }
+
+    pub async fn find_users_by_emails(
+        &self,
+        emails: Vec<String>,
+    ) -> Result<Vec<user::Model>, MegaError> {
+        let res = user::Entity::find()
+            .filter(user::Column::Email.is_in(emails))
+            .all(self.get_connection())
+            .await?;
+        Ok(res)
+    }
+
+    /// The avatars users set, garbage collection keeps the blobs they point to.
+    pub async fn get_avatar_urls(&self) -> Result<Vec<String>, MegaError> {
+        Ok(user::Entity::find()
+            .select_only()
+            .column(user::Column::AvatarUrl)
+            .filter(user::Column::AvatarUrl.ne(""))
+            .into_tuple()
+            .all(self.get_connection())
+            .await?)
+    }
+
+    /// Users by name, with the number of all of them.
+    pub async fn list_users(&self, page: Pagination) -> Result<(Vec<user::Model>, u64), MegaError> {
+        let paginator = user::Entity::find()
+            .order_by_asc(user::Column::Name)
+            .paginate(self.get_connection(), page.per_page);
+        let total = paginator.num_items().await?;
+        Ok(paginator
+            .fetch_page(page.page - 1)
+            .await
+            .map(|m| (m, total))?)
+    }
+
     pub async fn save_user(&self, user: user::Model) -> Result<(), MegaError> {
         let a_model = user.into_active_model();
         a_model.insert(self.get_connection()).await.unwrap();
         Ok(())
     }
 
+    pub async fn update_user(&self, user: user::Model) -> Result<(), MegaError> {
+        let mut a_model = user.into_active_model();
+        a_model = a_model.reset_all();
+        a_model.updated_at = Set(Some(chrono::Utc::now().naive_utc()));
+        a_model.update(self.get_connection()).await?;
+        Ok(())
+    }
+
+    /// Remove the user together with the ssh keys and access tokens belong to it.
+    pub async fn delete_user(&self, user_id: i64) -> Result<(), MegaError> {
+        let txn = self.get_connection().begin().await?;
+        ssh_keys::Entity::delete_many()
+            .filter(ssh_keys::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        access_token::Entity::delete_many()
+            .filter(access_token::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        user::Entity::delete_by_id(user_id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
     pub async fn save_ssh_key(
         &self,
         user_id: i64,
@@ -58,6 +151,10 @@ impl UserStorage {
         ssh_key: &str,
         finger: &str,
     ) -> Result<(), MegaError> {
+        // ssh server identifies the user by fingerprint, so it must be unique
+        if !self.search_ssh_key_finger(finger).await?.is_empty() {
+            return Err(MegaError::with_message("SSH key already exists"));
+        }
         let model = ssh_keys::Model {
             id: generate_id(),
             user_id,
@@ -102,17 +199,28 @@ impl UserStorage {
         Ok(res)
     }
 
-    pub async fn generate_token(&self, user_id: i64) -> Result<String, MegaError> {
-        let token_str = Uuid::new_v4().to_string();
+    /// Create a token with `scopes` for the user. Only a hash of it is stored, the token
+    /// itself is returned once and can't be shown again.
+    pub async fn generate_token(
+        &self,
+        user_id: i64,
+        name: &str,
+        scopes: &[TokenScope],
+    ) -> Result<String, MegaError> {
+        let token_str = format!("{}{}", TOKEN_PREFIX, Uuid::new_v4().simple());
         let model = access_token::Model {
             id: generate_id(),
             user_id,
-            token: token_str.clone(),
+            name: name.to_owned(),
+            token_hash: hash_token(&token_str),
+            token_prefix: token_str[..TOKEN_PREFIX.len() + 8].to_owned(),
+            scopes: json!(scopes),
+            last_used_at: None,
             created_at: chrono::Utc::now().naive_utc(),
         };
         let a_model = model.into_active_model();
-        a_model.insert(self.get_connection()).await.unwrap();
-        Ok(token_str.to_owned())
+        a_model.insert(self.get_connection()).await?;
+        Ok(token_str)
     }
 
     pub async fn delete_token(&self, user_id: i64, id: i64) -> Result<(), MegaError> {
@@ -135,14 +243,55 @@ impl UserStorage {
         Ok(res)
     }
 
-    pub async fn check_token(&self, user_id: i64, token: &str) -> Result<bool, MegaError> {
-        let res = access_token::Entity::find()
-            .filter(access_token::Column::UserId.eq(user_id))
-            .filter(access_token::Column::Token.eq(token))
+    /// Id of the token, `None` when there's no such token.
+    pub async fn find_token_id(&self, token: &str) -> Result<Option<i64>, MegaError> {
+        Ok(access_token::Entity::find()
+            .filter(access_token::Column::TokenHash.eq(hash_token(token)))
             .one(self.get_connection())
-            .await?;
-        match res {
-            Some(_) => Ok(true),
+            .await?
+            .map(|x| x.id))
+    }
+
+    /// The user a token belongs to along with the token, the time it was last used is
+    /// updated on the way when it's older than `LAST_USED_INTERVAL` seconds.
+    pub async fn resolve_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<(user::Model, access_token::Model)>, MegaError> {
+        let Some(model) = access_token::Entity::find()
+            .filter(access_token::Column::TokenHash.eq(hash_token(token)))
+            .one(self.get_connection())
+            .await?
+        else {
+            return Ok(None);
+        };
+        let Some(user) = self.find_user_by_id(model.user_id).await? else {
+            return Ok(None);
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let recent = model
+            .last_used_at
+            .is_some_and(|x| now - x < chrono::Duration::seconds(LAST_USED_INTERVAL));
+        if recent {
+            return Ok(Some((user, model)));
+        }
+        let mut a_model = model.into_active_model();
+        a_model.last_used_at = Set(Some(now));
+        let model = a_model.update(self.get_connection()).await?;
+        Ok(Some((user, model)))
+    }
+
+    /// Whether `token` belongs to the user and can be used for `scope`.
+    pub async fn check_token(
+        &self,
+        user_id: i64,
+        token: &str,
+        scope: TokenScope,
+    ) -> Result<bool, MegaError> {
+        match self.resolve_token(token).await? {
+            Some((user, model)) => {
+                Ok(user.id == user_id && scope.granted_by(&token_scopes(&model.scopes)))
+            }
             None => Ok(false),
         }
     }
@@ -150,11 +299,82 @@ impl UserStorage {
 
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use sea_orm::{ActiveModelTrait, IntoActiveModel, Set};
+    use serde_json::json;
     use uuid::Uuid;
 
+    use callisto::user;
+    use common::config::DbConfig;
+    use common::model::TokenScope;
+    use common::utils::generate_id;
+
+    use super::{hash_token, token_scopes, UserStorage};
+    use crate::storage::init::database_connection;
+
     #[test]
     fn token_format() {
         let uuid = Uuid::new_v4().to_string();
         println!("{:?}", uuid);
     }
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(hash_token("mega_a"), hash_token("mega_b"));
+    }
+
+    #[test]
+    fn test_token_scopes() {
+        assert_eq!(
+            token_scopes(&json!(["repo:read", "unknown", "mr:merge"])),
+            vec![TokenScope::RepoRead, TokenScope::MrMerge]
+        );
+        assert!(token_scopes(&json!("repo:read")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_token_throttles_last_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DbConfig {
+            db_path: dir.path().join("mega.db").to_string_lossy().into_owned(),
+            min_connection: 1,
+            ..Default::default()
+        };
+        let storage = UserStorage::new(Arc::new(database_connection(&config).await)).await;
+        let user_id = generate_id();
+        storage
+            .save_user(user::Model {
+                id: user_id,
+                name: "alice".to_owned(),
+                email: "alice@example.com".to_owned(),
+                avatar_url: String::new(),
+                is_github: false,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+            })
+            .await
+            .unwrap();
+        let token = storage
+            .generate_token(user_id, "ci", &[TokenScope::RepoRead])
+            .await
+            .unwrap();
+
+        let (_, first) = storage.resolve_token(&token).await.unwrap().unwrap();
+        let used = first.last_used_at.unwrap();
+        let (_, second) = storage.resolve_token(&token).await.unwrap().unwrap();
+        assert_eq!(second.last_used_at, Some(used));
+
+        let stale = used - Duration::minutes(2);
+        let mut model = second.into_active_model();
+        model.last_used_at = Set(Some(stale));
+        model.update(storage.get_connection()).await.unwrap();
+        let (_, third) = storage.resolve_token(&token).await.unwrap().unwrap();
+        assert!(third.last_used_at.unwrap() > stale);
+    }
 }
@@ -86,6 +86,9 @@ impl MegaModelConverter {
                     .borrow_mut()
                     .insert(child_tree.id, mega_tree.clone().into());
                 self.traverse_for_update(child_tree);
+            } else if item.mode == TreeItemMode::Commit {
+                // gitlink points to a commit of another repository, nothing to store
+                continue;
             } else {
                 let blob = self.blob_maps.get(&item.id).unwrap();
                 let mut mega_blob: mega_blob::Model = blob.into();
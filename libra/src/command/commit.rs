@@ -171,7 +171,7 @@ mod test {
     use mercury::internal::object::ObjectTrait;
 
     use crate::{
-        command::{add::AddArgs, load_object},
+        command::{add::AddArgs, load_object, status},
         utils::test,
     };
 
@@ -216,6 +216,21 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_index_tree_matches_commit() {
+        let index = Index::from_file("../tests/data/index/index-760").unwrap();
+        test::setup_with_new_libra().await;
+        let storage = ClientStorage::init(path::objects());
+        let tree = create_tree(&index, &storage, "".into()).await;
+
+        // status builds the same trees without saving them
+        let mut trees = std::collections::HashMap::new();
+        let entries = status::index_entries(&index);
+        let built = status::index_tree(&entries, std::path::Path::new(""), &mut trees).unwrap();
+        assert_eq!(built.id, tree.id);
+        assert!(trees.keys().all(|id| storage.get(id).is_ok()));
+    }
+
     #[tokio::test]
     #[should_panic]
     async fn test_execute_commit_with_empty_index_fail() {
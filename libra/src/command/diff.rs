@@ -6,14 +6,15 @@ use std::{
 };
 
 use clap::Parser;
-use imara_diff::{intern::InternedInput, Algorithm, UnifiedDiffBuilder};
 use mercury::{
+    diff::Algorithm,
     hash::SHA1,
     internal::{
         index::Index,
         object::{blob::Blob, commit::Commit, tree::Tree, types::ObjectType},
         pack::utils::calculate_object_hash,
     },
+    tree_diff::diff_trees,
 };
 use similar;
 
@@ -23,7 +24,10 @@ use crate::{
         status::{self, changes_to_be_committed},
     },
     internal::head::Head,
-    utils::{object_ext::TreeExt, path, util},
+    utils::{
+        object_ext::{ObjectTreeLoader, TreeExt},
+        path, util,
+    },
 };
 
 #[cfg(unix)]
@@ -77,36 +81,48 @@ pub async fn execute(args: DiffArgs) {
         None => None,
     };
 
-    let old_blobs = match args.old {
-        Some(ref source) => match get_target_commit(source).await {
-            Ok(commit_hash) => get_commit_blobs(&commit_hash).await,
-            Err(e) => {
-                eprintln!("fatal: {}, can't use as diff old source", e);
-                return;
-            }
-        },
-        None => {
-            // if the staged is not empty, use it as old commit. Otherwise, use HEAD
-            if status::changes_to_be_committed().await.is_empty() {
-                let commit_hash = Head::current_commit().await.unwrap();
-                get_commit_blobs(&commit_hash).await
-            } else {
-                let changes = changes_to_be_committed().await;
-                // diff didn't show untracked or deleted files
-                get_files_blobs(&changes.modified)
-            }
+    let (old_blobs, new_blobs) = match (&args.old, &args.new) {
+        // two commits are compared tree by tree, directories they share aren't read
+        (Some(old), Some(new)) => {
+            let old = match get_target_commit(old).await {
+                Ok(commit_hash) => commit_hash,
+                Err(e) => {
+                    eprintln!("fatal: {}, can't use as diff old source", e);
+                    return;
+                }
+            };
+            let new = match get_target_commit(new).await {
+                Ok(commit_hash) => commit_hash,
+                Err(e) => {
+                    eprintln!("fatal: {}, can't use as diff new source", e);
+                    return;
+                }
+            };
+            get_changed_blobs(&old, &new).await
         }
-    };
+        _ => {
+            let old_blobs = match args.old {
+                Some(ref source) => match get_target_commit(source).await {
+                    Ok(commit_hash) => get_commit_blobs(&commit_hash).await,
+                    Err(e) => {
+                        eprintln!("fatal: {}, can't use as diff old source", e);
+                        return;
+                    }
+                },
+                None => {
+                    // if the staged is not empty, use it as old commit. Otherwise, use HEAD
+                    if status::changes_to_be_committed().await.is_empty() {
+                        let commit_hash = Head::current_commit().await.unwrap();
+                        get_commit_blobs(&commit_hash).await
+                    } else {
+                        let changes = changes_to_be_committed().await;
+                        // diff didn't show untracked or deleted files
+                        get_files_blobs(&changes.modified)
+                    }
+                }
+            };
 
-    let new_blobs = match args.new {
-        Some(ref source) => match get_target_commit(source).await {
-            Ok(commit_hash) => get_commit_blobs(&commit_hash).await,
-            Err(e) => {
-                eprintln!("fatal: {}, can't use as diff new source", e);
-                return;
-            }
-        },
-        None => {
+            // `--new` requires `--old`, the new side is the stage or the working directory
             let files = if args.staged {
                 // use staged as new commit
                 index.tracked_files()
@@ -115,7 +131,8 @@ pub async fn execute(args: DiffArgs) {
                 // NOTE: git didn't show diff for untracked files, but we do
                 util::list_workdir_files().unwrap()
             };
-            get_files_blobs(&files)
+            let new_blobs = get_files_blobs(&files);
+            (old_blobs, new_blobs)
         }
     };
 
@@ -231,7 +248,7 @@ pub async fn diff(
             String::from_utf8(new_content),
         ) {
             (Ok(old_text), Ok(new_text)) => {
-                imara_diff_result(&old_text, &new_text, w);
+                line_diff_result(&old_text, &new_text, w);
             }
             _ => {
                 // TODO: Handle non-UTF-8 data as binary for now; consider optimization in the future.
@@ -247,6 +264,32 @@ pub async fn diff(
     }
 }
 
+/// Blobs of the files that differ between two commits, on the old and the new side.
+async fn get_changed_blobs(
+    old: &SHA1,
+    new: &SHA1,
+) -> (Vec<(PathBuf, SHA1)>, Vec<(PathBuf, SHA1)>) {
+    let old_tree = load_object::<Tree>(&load_object::<Commit>(old).unwrap().tree_id).unwrap();
+    let new_tree = load_object::<Tree>(&load_object::<Commit>(new).unwrap().tree_id).unwrap();
+    let changes = diff_trees(
+        &ObjectTreeLoader::default(),
+        Some(&old_tree),
+        Some(&new_tree),
+        PathBuf::new(),
+    )
+    .await
+    .unwrap();
+    let old_blobs = changes
+        .iter()
+        .filter_map(|x| x.old.as_ref().map(|item| (x.path.clone(), item.id)))
+        .collect();
+    let new_blobs = changes
+        .iter()
+        .filter_map(|x| x.new.as_ref().map(|item| (x.path.clone(), item.id)))
+        .collect();
+    (old_blobs, new_blobs)
+}
+
 async fn get_commit_blobs(commit_hash: &SHA1) -> Vec<(PathBuf, SHA1)> {
     let commit = load_object::<Commit>(commit_hash).unwrap();
     let tree = load_object::<Tree>(&commit.tree_id).unwrap();
@@ -326,14 +369,10 @@ fn similar_diff_result(old: &str, new: &str, w: &mut dyn io::Write) {
     }
 }
 
-fn imara_diff_result(old: &str, new: &str, w: &mut dyn io::Write) {
-    let input = InternedInput::new(old, new);
-    let diff = imara_diff::diff(
-        Algorithm::Histogram,
-        &input,
-        UnifiedDiffBuilder::new(&input),
-    );
-    write!(w, "{}", diff).unwrap();
+fn line_diff_result(old: &str, new: &str, w: &mut dyn io::Write) {
+    for hunk in mercury::diff::lines(old, new, Algorithm::Histogram) {
+        write!(w, "{}", hunk).unwrap();
+    }
 }
 
 #[cfg(test)]
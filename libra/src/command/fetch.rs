@@ -18,11 +18,11 @@ use crate::{
         branch::Branch,
         config::{Config, RemoteConfig},
         head::Head,
-        protocol::{https_client::HttpsClient, ProtocolClient},
+        protocol::{https_client::HttpsClient, retry::RetryPolicy, ProtocolClient},
     },
     utils::{self, path_ext::PathExt},
 };
-use crate::utils::util;
+use crate::utils::{client_storage::MIDX_MIN_PACKS, util};
 
 const DEFAULT_REMOTE: &str = "origin";
 
@@ -128,10 +128,115 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
         .collect::<Vec<_>>();
     let have = current_have().await; // TODO: return `DiscRef` rather than only hash, to compare `have` & `want` more accurately
 
+    if let Err(e) = fetch_packs(&http_client, have, want).await {
+        eprintln!("fatal: {}", e);
+        return;
+    }
+
+    /* update reference  */
+    for r in &ref_heads {
+        let branch_name = r._ref.strip_prefix("refs/heads/").unwrap();
+        let remote = Some(remote_config.name.as_str());
+        Branch::update_branch(branch_name, &r._hash, remote).await;
+    }
+    match remote_head {
+        Some(remote_head) => {
+            let remote_head_ref = ref_heads
+                .iter()
+                .find(|r| r._hash == remote_head._hash);
+
+            match remote_head_ref {
+                Some(remote_head_ref) => {
+                    let remote_head_branch = remote_head_ref._ref.strip_prefix("refs/heads/").unwrap();
+                    Head::update(Head::Branch(remote_head_branch.to_owned()), Some(&remote_config.name)).await;
+                }
+                None => {
+                    if branch.is_none() {
+                        eprintln!("remote HEAD not found");
+                    } else {
+                        // normal: remote HEAD usually points to master
+                        tracing::debug!("Specified branch not found in remote HEAD");
+                    }
+                }
+            }
+        }
+        None => {
+            tracing::warn!("fetch empty, remote HEAD not found");
+        }
+    }
+}
+
+/// Fetch everything in `want`, first as a single pack. If that transfer can't be completed,
+/// the refs are fetched one at a time instead: each finished pack is stored right away and its
+/// tip is sent as `have` from then on, so an interruption only loses the ref in flight.
+async fn fetch_packs(
+    http_client: &HttpsClient,
+    mut have: Vec<String>,
+    mut want: Vec<String>,
+) -> io::Result<()> {
+    want.sort();
+    want.dedup();
+    match fetch_pack(http_client, &have, &want).await {
+        Ok(pack_data) => {
+            save_pack(pack_data);
+            return Ok(());
+        }
+        Err(e) if want.len() > 1 && is_interrupted(&e) => {
+            eprintln!("fetch interrupted ({}), fetching refs one by one", e);
+        }
+        Err(e) => return Err(e),
+    }
+    for w in want {
+        if have.contains(&w) {
+            continue;
+        }
+        let pack_data = fetch_pack(http_client, &have, &[w.clone()]).await?;
+        save_pack(pack_data);
+        have.push(w);
+    }
+    Ok(())
+}
+
+/// Download one pack, the negotiation is repeated when the transfer breaks off.
+async fn fetch_pack(
+    http_client: &HttpsClient,
+    have: &[String],
+    want: &[String],
+) -> io::Result<Vec<u8>> {
+    let policy = RetryPolicy::default();
+    let mut attempt = 0;
+    loop {
+        match receive_pack(http_client, have, want).await {
+            Ok(pack_data) => return Ok(pack_data),
+            Err(e) if is_interrupted(&e) => {
+                eprintln!("transfer interrupted: {}", e);
+                if !policy.wait(attempt).await {
+                    return Err(e);
+                }
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The pack stream broke off, as opposed to the server rejecting the request
+/// (`InvalidData`) or being unreachable even after the request retries (`NotConnected`).
+fn is_interrupted(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::NotConnected
+    )
+}
+
+async fn receive_pack(
+    http_client: &HttpsClient,
+    have: &[String],
+    want: &[String],
+) -> io::Result<Vec<u8>> {
     let mut result_stream = http_client
-        .fetch_objects(&have, &want)
-        .await
-        .unwrap();
+        .fetch_objects(&have.to_vec(), &want.to_vec())
+        .await?;
 
     let mut reader = StreamReader::new(&mut result_stream);
     let mut pack_data = Vec::new();
@@ -139,7 +244,7 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
     let bar = ProgressBar::new_spinner();
     let time = Instant::now();
     loop {
-        let (len, data) = read_pkt_line(&mut reader).await.unwrap();
+        let (len, data) = read_pkt_line(&mut reader).await?;
         if len == 0 {
             break;
         }
@@ -165,7 +270,11 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
                     std::io::stdout().flush().unwrap();
                 }
                 3 => { // Error
-                    eprintln!("{}", String::from_utf8_lossy(data));
+                    bar.finish();
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        String::from_utf8_lossy(data).to_string(),
+                    ));
                 }
                 _ => {
                     eprintln!("unknown side-band-64k code: {}", code);
@@ -178,67 +287,49 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
     };
     bar.finish();
 
-    /* save pack file */
-    let pack_file = {
-        let hash = SHA1::new(&pack_data[..pack_data.len() - 20]);
-
-        let checksum = SHA1::from_bytes(&pack_data[pack_data.len() - 20..]);
-        assert_eq!(hash, checksum);
-        let checksum = checksum.to_string();
-        println!("checksum: {}", checksum);
-
-        if pack_data.len() > 32 { // 12 header + 20 hash
-            let pack_file = utils::path::objects()
-                .join("pack")
-                .join(format!("pack-{}.pack", checksum));
-            let mut file = fs::File::create(pack_file.clone()).unwrap();
-            file.write_all(&pack_data).expect("write failed");
-
-            Some(pack_file.to_string_or_panic())
-        } else {
-            tracing::debug!("Empty pack file");
-            None
-        }
-    };
-
-    if let Some(pack_file) = pack_file {
-        /* build .idx file from PACK */
-        index_pack::execute(IndexPackArgs {
-            pack_file,
-            index_file: None,
-            index_version: None,
-        });
+    if pack_data.len() < 32 { // 12 header + 20 hash
+        tracing::debug!("Empty pack file");
+        return Ok(pack_data);
+    }
+    // a broken transfer may still end with a flush-pkt, check the trailer before keeping it
+    let hash = SHA1::new(&pack_data[..pack_data.len() - 20]);
+    let checksum = SHA1::from_bytes(&pack_data[pack_data.len() - 20..]);
+    if hash != checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("pack checksum mismatch, expected {} but got {}", checksum, hash),
+        ));
     }
+    Ok(pack_data)
+}
 
-    /* update reference  */
-    for r in &ref_heads {
-        let branch_name = r._ref.strip_prefix("refs/heads/").unwrap();
-        let remote = Some(remote_config.name.as_str());
-        Branch::update_branch(branch_name, &r._hash, remote).await;
+/// Write the pack into the objects dir and build its `.idx`,
+/// the multi-pack-index is rewritten once packs accumulate.
+fn save_pack(pack_data: Vec<u8>) {
+    if pack_data.len() <= 32 { // 12 header + 20 hash
+        tracing::debug!("Empty pack file");
+        return;
     }
-    match remote_head {
-        Some(remote_head) => {
-            let remote_head_ref = ref_heads
-                .iter()
-                .find(|r| r._hash == remote_head._hash);
+    let checksum = SHA1::from_bytes(&pack_data[pack_data.len() - 20..]).to_string();
+    println!("checksum: {}", checksum);
 
-            match remote_head_ref {
-                Some(remote_head_ref) => {
-                    let remote_head_branch = remote_head_ref._ref.strip_prefix("refs/heads/").unwrap();
-                    Head::update(Head::Branch(remote_head_branch.to_owned()), Some(&remote_config.name)).await;
-                }
-                None => {
-                    if branch.is_none() {
-                        eprintln!("remote HEAD not found");
-                    } else {
-                        // normal: remote HEAD usually points to master
-                        tracing::debug!("Specified branch not found in remote HEAD");
-                    }
-                }
-            }
-        }
-        None => {
-            tracing::warn!("fetch empty, remote HEAD not found");
+    let pack_file = utils::path::objects()
+        .join("pack")
+        .join(format!("pack-{}.pack", checksum));
+    let mut file = fs::File::create(pack_file.clone()).unwrap();
+    file.write_all(&pack_data).expect("write failed");
+
+    /* build .idx file from PACK */
+    index_pack::execute(IndexPackArgs {
+        pack_file: pack_file.to_string_or_panic(),
+        index_file: None,
+        index_version: None,
+    });
+
+    let storage = util::objects_storage();
+    if storage.pack_count() >= MIDX_MIN_PACKS {
+        if let Err(e) = storage.write_multi_pack_index() {
+            tracing::warn!("failed to write multi-pack-index: {}", e);
         }
     }
 }
@@ -7,6 +7,7 @@ use byteorder::{BigEndian, WriteBytesExt};
 use clap::Parser;
 use sha1::{Digest, Sha1};
 
+use mercury::internal::pack::idx::PackIdx;
 use mercury::internal::pack::Pack;
 use mercury::errors::GitError;
 
@@ -46,15 +47,36 @@ pub fn execute(args: IndexPackArgs) {
     if let Some(version) = args.index_version {
         match version {
             1 => build_index_v1(&pack_file, &index_file).unwrap(),
-            2 => println!("support later"),
+            2 => build_index_v2(&pack_file, &index_file).unwrap(),
             _ => eprintln!("fatal: unsupported index version"),
         }
     } else {
-        // default version = 1
-        build_index_v1(&pack_file, &index_file).unwrap();
+        // default version = 2, as git
+        build_index_v2(&pack_file, &index_file).unwrap();
     }
 }
 
+/// Build index file for pack file, version 2, with the CRC32 of every object
+/// and 64-bit offsets for packs over 2 GiB
+pub fn build_index_v2(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+    let pack_path = PathBuf::from(pack_file);
+    let tmp_path = pack_path.parent().unwrap();
+    let mut pack_reader = std::io::BufReader::new(std::fs::File::open(pack_file)?);
+    let objects = Arc::new(Mutex::new(Vec::new()));
+    let objects_c = objects.clone();
+    let mut pack = Pack::new(Some(8), Some(1024 * 1024 * 1024), Some(tmp_path.to_path_buf()), true);
+    pack.decode(&mut pack_reader, move |entry, offset| {
+        objects_c.lock().unwrap().push((entry.hash, offset as u64));
+    })?;
+
+    let objects = Arc::try_unwrap(objects).unwrap().into_inner().unwrap();
+    let index = PackIdx::build(&mut pack_reader, objects)?;
+    index.write(index_file.as_ref())?;
+
+    tracing::debug!("Index file is written to {:?}", index_file);
+    Ok(())
+}
+
 /// Build index file for pack file, version 1
 /// [pack-format](https://git-scm.com/docs/pack-format)
 pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError> {
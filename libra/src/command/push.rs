@@ -23,6 +23,9 @@ use crate::internal::protocol::lfs_client::LFSClient;
 use crate::internal::protocol::ProtocolClient;
 use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
 
+/// Pushes larger than this are uploaded in resumable chunks.
+const RESUMABLE_PUSH_SIZE: usize = 32 * 1024 * 1024;
+
 #[derive(Parser, Debug)]
 pub struct PushArgs { // TODO --force
     /// repository, e.g. origin
@@ -137,7 +140,12 @@ pub async fn execute(args: PushArgs) {
     data.extend_from_slice(&pack_data);
     println!("Delta compression done.");
 
-    let res = client.send_pack(data.freeze()).await.unwrap(); // TODO: send stream
+    let data = data.freeze();
+    let res = if data.len() > RESUMABLE_PUSH_SIZE {
+        client.send_pack_resumable(data).await.unwrap()
+    } else {
+        client.send_pack(data).await.unwrap() // TODO: send stream
+    };
 
     if res.status() != 200 {
         eprintln!("status code: {}", res.status());
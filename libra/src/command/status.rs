@@ -1,15 +1,17 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use colored::Colorize;
 
+use mercury::hash::SHA1;
 use mercury::internal::object::commit::Commit;
-use mercury::internal::object::tree::Tree;
+use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+use mercury::tree_diff::diff_trees;
 
 use crate::internal::head::Head;
 use mercury::internal::index::Index;
 use crate::command::calc_file_blob_hash;
-use crate::utils::object_ext::{CommitExt, TreeExt};
+use crate::utils::object_ext::{CommitExt, ObjectTreeLoader, TreeExt};
 use crate::utils::{path, util};
 
 /// path: to workdir
@@ -131,28 +133,77 @@ pub async fn changes_to_be_committed() -> Changes {
     let head_commit = head_commit.unwrap();
     let commit = Commit::load(&head_commit);
     let tree = Tree::load(&commit.tree_id);
-    let tree_files = tree.get_plain_items();
 
-    for (item_path, item_hash) in tree_files.iter() {
-        let item_str = item_path.to_str().unwrap();
-        if index.tracked(item_str, 0) {
-            if !index.verify_hash(item_str, 0, item_hash) {
-                changes.modified.push(item_path.clone());
-            }
-        } else {
+    // the index as trees, directories it shares with the last commit aren't walked
+    let mut loader = ObjectTreeLoader::default();
+    let index_tree = index_tree(&index_entries(&index), Path::new(""), &mut loader.trees);
+    let diff = diff_trees(&loader, Some(&tree), index_tree.as_ref(), PathBuf::new())
+        .await
+        .unwrap();
+    for change in diff {
+        match (change.old, change.new) {
             // in the last commit but not in the index
-            changes.deleted.push(item_path.clone());
+            (Some(_), None) => changes.deleted.push(change.path),
+            // in the index but not in the last commit
+            (None, Some(_)) => changes.new.push(change.path),
+            _ => changes.modified.push(change.path),
         }
     }
-    let tree_files_set: HashSet<PathBuf> = tree_files.into_iter().map(|(path, _)| path).collect();
-    // `new` means the files in index but not in the last commit
-    changes.new = tracked_files.into_iter()
-        .filter(|path| !tree_files_set.contains(path))
-        .collect();
-
     changes
 }
 
+/// Tracked files of the index with the tree entries they get in a commit, sorted by path.
+pub(crate) fn index_entries(index: &Index) -> Vec<(PathBuf, TreeItem)> {
+    index
+        .tracked_entries(0)
+        .into_iter()
+        .map(|entry| {
+            let path = PathBuf::from(&entry.name);
+            let mode = format!("{:o}", entry.mode);
+            let item = TreeItem {
+                name: path.file_name().unwrap().to_string_lossy().into_owned(),
+                mode: TreeItemMode::tree_item_type_from_bytes(mode.as_bytes()).unwrap(),
+                id: entry.hash,
+            };
+            (path, item)
+        })
+        .collect()
+}
+
+/// Tree of the index `entries` below `dir`, built the way `commit` builds it, but kept in
+/// `trees` instead of the object store. `None` for a directory without entries.
+pub(crate) fn index_tree(
+    entries: &[(PathBuf, TreeItem)],
+    dir: &Path,
+    trees: &mut HashMap<SHA1, Tree>,
+) -> Option<Tree> {
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+        let (path, item) = &entries[i];
+        if path.parent() == Some(dir) {
+            items.push(item.clone());
+            i += 1;
+            continue;
+        }
+        let name = path.strip_prefix(dir).unwrap().components().next().unwrap();
+        let name = name.as_os_str().to_string_lossy().into_owned();
+        let sub_dir = dir.join(&name);
+        // entries are sorted by path, the ones of a sub directory come one after another
+        let end = i + entries[i..]
+            .iter()
+            .take_while(|(path, _)| path.starts_with(&sub_dir))
+            .count();
+        if let Some(sub_tree) = index_tree(&entries[i..end], &sub_dir, trees) {
+            items.push(TreeItem::new(TreeItemMode::Tree, sub_tree.id, name));
+        }
+        i = end;
+    }
+    let tree = Tree::from_tree_items(items).ok()?;
+    trees.insert(tree.id, tree.clone());
+    Some(tree)
+}
+
 /// Compare the difference between `index` and the `workdir`
 pub fn changes_to_be_staged() -> Changes {
     let mut changes = Changes::default();
@@ -1,3 +1,4 @@
+use super::retry::{is_transient_error, is_transient_status, RetryPolicy};
 use super::ProtocolClient;
 use bytes::Bytes;
 use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
@@ -11,6 +12,7 @@ use reqwest::{Body, RequestBuilder, Response, StatusCode};
 use std::io::Error as IoError;
 use std::ops::Deref;
 use std::sync::Mutex;
+use std::time::Duration;
 use tokio_util::bytes::BytesMut;
 use url::Url;
 use crate::command::ask_basic_auth;
@@ -32,7 +34,11 @@ impl ProtocolClient for HttpsClient {
             url.set_path(&format!("{}/", url.path()));
             url
         };
-        let client = reqwest::Client::builder().http1_only().build().unwrap();
+        let client = reqwest::Client::builder()
+            .http1_only()
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
         Self { url, client }
     }
 }
@@ -51,19 +57,41 @@ impl BasicAuth {
     }
 
     /// send request with basic auth, retry 3 times
+    /// - transient network failures and `502`/`503`/`504` responses are retried with backoff
     pub async fn send<Fut>(request_builder: impl Fn() -> Fut) -> Result<Response, reqwest::Error>
     where
         Fut: std::future::Future<Output=RequestBuilder>,
     {
         const MAX_TRY: usize = 3;
+        let policy = RetryPolicy::default();
         let mut res;
         let mut try_cnt = 0;
+        let mut network_try_cnt = 0;
         loop {
             let mut request = request_builder().await; // RequestBuilder can't be cloned
             if let Some(auth) = AUTH.lock().unwrap().deref() {
                 request = request.basic_auth(auth.username.clone(), Some(auth.password.clone()));
             } // if no auth exists, try without auth (e.g. clone public)
-            res = request.send().await?;
+            res = match request.send().await {
+                Ok(res) => res,
+                Err(e) if is_transient_error(&e) => {
+                    tracing::warn!("request failed: {}", e);
+                    if policy.wait(network_try_cnt).await {
+                        network_try_cnt += 1;
+                        continue;
+                    }
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
+            if is_transient_status(res.status()) {
+                tracing::warn!("server unavailable: {}", res.status());
+                if policy.wait(network_try_cnt).await {
+                    network_try_cnt += 1;
+                    continue;
+                }
+                break;
+            }
             if res.status() == StatusCode::FORBIDDEN { // 403: no access, no need to retry
                 eprintln!("Authentication failed, forbidden");
                 break;
@@ -214,13 +242,13 @@ impl HttpsClient {
                 .post(url.clone())
                 .header("Content-Type", "application/x-git-upload-pack-request")
                 .body(body.clone())
-        }).await.unwrap();
+        }).await.map_err(|e| IoError::new(std::io::ErrorKind::NotConnected, e))?; // already retried
         tracing::debug!("request: {:?}", res);
 
         if res.status() != 200 && res.status() != 304 {
             tracing::error!("request failed: {:?}", res);
             return Err(IoError::new(
-                std::io::ErrorKind::Other,
+                std::io::ErrorKind::InvalidData,
                 format!("Error Response format, status code: {}", res.status()),
             ));
         }
@@ -243,6 +271,70 @@ impl HttpsClient {
                 .body(data.clone())
         }).await
     }
+
+    /// Push `data` in chunks through a resumable upload session, an interrupted chunk is
+    /// sent again from the offset the server has stored instead of restarting the push.
+    /// Servers without upload sessions get the whole request with [`send_pack`](Self::send_pack).
+    pub async fn send_pack_resumable(&self, data: Bytes) -> Result<Response, reqwest::Error> {
+        let upload_url = self.url.join("git-receive-pack/upload").unwrap();
+        let res = BasicAuth::send(|| async { self.client.post(upload_url.clone()) }).await?;
+        if res.status() != StatusCode::CREATED {
+            tracing::debug!("no upload session: {}", res.status());
+            return self.send_pack(data).await;
+        }
+        let status: UploadStatus = res.json().await?;
+        let session_url = self
+            .url
+            .join(&format!("git-receive-pack/upload/{}", status.session))
+            .unwrap();
+
+        let mut offset = status.offset;
+        let mut resumes = 0;
+        while offset < data.len() as u64 {
+            let end = (offset as usize + UPLOAD_CHUNK_SIZE).min(data.len());
+            let chunk = data.slice(offset as usize..end);
+            let res = BasicAuth::send(|| async {
+                self.client
+                    .post(session_url.clone())
+                    .query(&[("offset", offset)])
+                    .body(chunk.clone())
+            })
+            .await;
+            offset = match res {
+                Ok(res) if res.status() == StatusCode::OK => {
+                    res.json::<UploadStatus>().await?.offset
+                }
+                // the chunk broke off or the server stored a different offset, resume there
+                Ok(_) | Err(_) if resumes < MAX_UPLOAD_RESUMES => {
+                    resumes += 1;
+                    let res =
+                        BasicAuth::send(|| async { self.client.get(session_url.clone()) }).await?;
+                    let stored = res.error_for_status()?.json::<UploadStatus>().await?.offset;
+                    eprintln!("upload interrupted, resuming at {} of {} bytes", stored, data.len());
+                    stored
+                }
+                Ok(res) => return Ok(res),
+                Err(e) => return Err(e),
+            };
+        }
+        let done_url = self
+            .url
+            .join(&format!("git-receive-pack/upload/{}/done", status.session))
+            .unwrap();
+        BasicAuth::send(|| async { self.client.post(done_url.clone()) }).await
+    }
+}
+
+/// Size of the chunks of a resumable push.
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+/// How often a resumable push continues after an interrupted chunk.
+const MAX_UPLOAD_RESUMES: usize = 10;
+
+/// Progress of a resumable push as reported by the server.
+#[derive(Debug, serde::Deserialize)]
+struct UploadStatus {
+    session: String,
+    offset: u64,
 }
 /// for fetching
 async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) -> Bytes {
@@ -2,6 +2,7 @@ use url::Url;
 
 pub mod https_client;
 pub mod lfs_client;
+pub mod retry;
 
 #[allow(dead_code)] // todo: unimplemented
 pub trait ProtocolClient {
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// How often and how long to wait before repeating a request that failed for a reason
+/// that is likely to go away, e.g. a dropped connection or an overloaded server.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff, `attempt` starts from 0.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let factor = 1u32 << attempt.min(16);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Sleep before the next attempt, returns `false` once the retries are used up.
+    pub async fn wait(&self, attempt: usize) -> bool {
+        if attempt >= self.max_retries {
+            return false;
+        }
+        let delay = self.delay(attempt);
+        eprintln!(
+            "network error, retrying in {:.1}s ({}/{})",
+            delay.as_secs_f64(),
+            attempt + 1,
+            self.max_retries
+        );
+        tokio::time::sleep(delay).await;
+        true
+    }
+}
+
+/// Errors on the connection itself, the request never got a full response.
+pub fn is_transient_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout() || err.is_request() || err.is_body()
+}
+
+/// Responses of gateways and servers that are temporarily unable to answer.
+pub fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(10), policy.max_delay);
+        assert_eq!(policy.delay(usize::MAX), policy.max_delay);
+    }
+
+    #[test]
+    fn test_transient_status() {
+        assert!(is_transient_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_transient_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_transient_status(StatusCode::NOT_FOUND));
+        assert!(!is_transient_status(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+}
@@ -1,28 +1,36 @@
 use std::{fs, io};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use byteorder::{BigEndian, ReadBytesExt};
 use flate2::Compression;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use lru_mem::LruCache;
 use once_cell::sync::Lazy;
 use mercury::internal::pack::cache_object::CacheObject;
+use mercury::internal::pack::idx::PackIdx;
+use mercury::internal::pack::midx::{MultiPackIndex, MIDX_FILE_NAME};
 use mercury::internal::pack::Pack;
 use mercury::errors::GitError;
 use mercury::hash::SHA1;
 use mercury::internal::object::types::ObjectType;
-use mercury::utils::read_sha1;
 
 use crate::command;
 static PACK_OBJ_CACHE: Lazy<Mutex<LruCache<String, CacheObject>>> = Lazy::new(|| {
     // `lazy_static!` may affect IDE's code completion
     Mutex::new(LruCache::new(1024 * 1024 * 200))
 });
+/// .idx files read so far, packs are named by their checksum so an index never changes
+static PACK_IDX_CACHE: Lazy<Mutex<HashMap<PathBuf, Arc<PackIdx>>>> = Lazy::new(Default::default);
+/// multi-pack-index read so far, with the modification time it was read at, it's rewritten as packs come
+static MIDX_CACHE: Lazy<Mutex<HashMap<PathBuf, (SystemTime, Arc<MultiPackIndex>)>>> = Lazy::new(Default::default);
+
+/// Packs from which a multi-pack-index is kept, below that looking up each .idx is cheap enough
+pub const MIDX_MIN_PACKS: usize = 4;
 
 #[derive(Default)]
 pub struct ClientStorage {
@@ -200,7 +208,6 @@ impl ClientStorage {
         Path::exists(&path)
     }
 }
-const FANOUT: u64 = 256 * 4;
 // TODO refactor to `PackReader`
 impl ClientStorage {
     /// List all .pack files in `pack` directory
@@ -217,24 +224,80 @@ impl ClientStorage {
     }
 
     /// List all .idx files in `pack` directory
-    /// - If .idx file not exists, build it
+    /// - If .idx file not exists, or isn't a version 2 one, build it
     fn list_all_idx(&self) -> Vec<PathBuf> {
         let packs = self.list_all_packs();
         let mut idxs = Vec::new();
         for pack in packs {
             let idx = pack.with_extension("idx");
-            if !idx.exists() {
-                command::index_pack::build_index_v1(pack.to_str().unwrap(), idx.to_str().unwrap()).unwrap();
+            if !idx.exists() || Self::load_idx(&idx).is_err() {
+                command::index_pack::build_index_v2(pack.to_str().unwrap(), idx.to_str().unwrap()).unwrap();
             }
             idxs.push(idx);
         }
         idxs
     }
 
+    /// Number of packs in `pack` directory
+    pub fn pack_count(&self) -> usize {
+        self.list_all_packs().len()
+    }
+
+    /// Write the multi-pack-index of all packs, the newest copy of an object is preferred
+    pub fn write_multi_pack_index(&self) -> Result<(), GitError> {
+        let mut idxes = Vec::new();
+        for idx in self.list_all_idx() {
+            let modified = fs::metadata(idx.with_extension("pack"))?.modified()?;
+            let name = idx.file_name().unwrap().to_string_lossy().into_owned();
+            idxes.push((modified, name, Self::load_idx(&idx)?));
+        }
+        idxes.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified)); // newest first
+        let packs: Vec<(String, &PackIdx)> = idxes.iter().map(|(_, name, idx)| (name.clone(), idx.as_ref())).collect();
+
+        let path = self.base_path.join("pack").join(MIDX_FILE_NAME);
+        MultiPackIndex::build(&packs).write(&path)?;
+        tracing::debug!("multi-pack-index of {} packs is written to {:?}", packs.len(), path);
+        Ok(())
+    }
+
+    /// Read multi-pack-index of `pack` directory, cached until it's rewritten.
+    /// - A missing or broken one is ignored, objects are then looked up in each .idx
+    fn load_midx(&self) -> Option<Arc<MultiPackIndex>> {
+        let path = self.base_path.join("pack").join(MIDX_FILE_NAME);
+        let modified = fs::metadata(&path).and_then(|x| x.modified()).ok()?;
+        if let Some((time, midx)) = MIDX_CACHE.lock().unwrap().get(&path) {
+            if *time == modified {
+                return Some(midx.clone());
+            }
+        }
+        let midx = match MultiPackIndex::read(&path) {
+            Ok(midx) => Arc::new(midx),
+            Err(e) => {
+                tracing::warn!("ignore multi-pack-index: {}", e);
+                return None;
+            }
+        };
+        MIDX_CACHE.lock().unwrap().insert(path, (modified, midx.clone()));
+        Some(midx)
+    }
+
     /// Get object from PACKs by hash, if not found, return None
+    /// - The multi-pack-index is searched first, then the packs it doesn't cover
     fn get_from_pack(&self, obj_id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
+        let midx = self.load_midx();
+        if let Some((idx, offset)) = midx.as_ref().and_then(|x| x.find(obj_id)) {
+            let pack_file = self.base_path.join("pack").join(idx).with_extension("pack");
+            if pack_file.exists() {
+                let data = Self::read_pack_obj(&pack_file, offset)?;
+                return Ok(Some((data.data_decompressed.clone(), data.object_type())));
+            }
+        }
+        let covered = |idx: &Path| {
+            let name = idx.file_name().unwrap().to_string_lossy();
+            midx.as_ref().is_some_and(|x| x.packs().binary_search_by(|p| p.as_str().cmp(name.as_ref())).is_ok())
+        };
         let idxes = self.list_all_idx(); // list or build
-        for idx in idxes {
+        for idx in idxes.into_iter().filter(|x| !covered(x)) {
             let res = Self::read_pack_by_idx(&idx, obj_id)?;
             if let Some(data) = res {
                 return Ok(Some((data.data_decompressed.clone(), data.object_type())));
@@ -244,58 +307,25 @@ impl ClientStorage {
         Ok(None)
     }
 
-    fn read_idx_fanout(idx_file: &Path) -> Result<[u32; 256], io::Error> {
-        let mut idx_file = fs::File::open(idx_file)?;
-        // const FANOUT: usize = 256 * 4;
-        let mut fanout: [u32; 256] = [0; 256]; // 256 * 4 bytes
-        let mut buf = [0; 4];
-        fanout.iter_mut().for_each(|x| {
-            idx_file.read_exact(&mut buf).unwrap();
-            *x = u32::from_be_bytes(buf);
-        });
-        Ok(fanout)
+    /// Read .idx file, cached
+    fn load_idx(idx_file: &Path) -> Result<Arc<PackIdx>, GitError> {
+        if let Some(idx) = PACK_IDX_CACHE.lock().unwrap().get(idx_file) {
+            return Ok(idx.clone());
+        }
+        let idx = Arc::new(PackIdx::read(idx_file)?);
+        PACK_IDX_CACHE.lock().unwrap().insert(idx_file.to_owned(), idx.clone());
+        Ok(idx)
     }
 
     /// List all objects hash in .idx file
-    fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?; // TODO param change to `&mut File`, to auto seek
-        let mut idx_file = fs::File::open(idx_file)?;
-        idx_file.seek(io::SeekFrom::Start(FANOUT))?; // important!
-
-        let mut objs = Vec::new();
-        for _ in 0..fanout[255] {
-            let _offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
-
-            objs.push(hash);
-        }
-        Ok(objs)
+    fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, GitError> {
+        let idx = Self::load_idx(idx_file)?;
+        Ok(idx.entries().iter().map(|x| x.hash).collect())
     }
 
     /// Read object `offset` from .idx file by `hash`
-    fn read_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<u64>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?;
-        let mut idx_file = fs::File::open(idx_file)?;
-
-        let first_byte = obj_id.0[0];
-        let start = if first_byte == 0 {
-            0
-        } else {
-            fanout[first_byte as usize - 1] as usize
-        };
-        let end = fanout[first_byte as usize] as usize;
-
-        idx_file.seek(io::SeekFrom::Start(FANOUT + 24 * start as u64))?;
-        for _ in start..end {
-            let offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
-
-            if &hash == obj_id {
-                return Ok(Some(offset as u64));
-            }
-        }
-
-        Ok(None)
+    fn read_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<u64>, GitError> {
+        Ok(Self::load_idx(idx_file)?.offset(obj_id))
     }
 
     /// Get object from pack by .idx file
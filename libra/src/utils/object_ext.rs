@@ -1,12 +1,15 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 use colored::Colorize;
+use mercury::errors::GitError;
 use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::ObjectTrait;
 use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::tree_diff::TreeLoader;
 
 use crate::utils::{lfs, util};
 
@@ -59,6 +62,23 @@ impl TreeExt for Tree {
     }
 }
 
+/// Loads the trees of a diff from the object store, or from `trees` for the ones built in
+/// memory and not saved, like the trees of the index.
+#[derive(Default)]
+pub struct ObjectTreeLoader {
+    pub trees: HashMap<SHA1, Tree>,
+}
+
+impl TreeLoader for ObjectTreeLoader {
+    async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+        if let Some(tree) = self.trees.get(id) {
+            return Ok(tree.clone());
+        }
+        let data = util::objects_storage().get(id)?;
+        Tree::from_bytes(&data, *id)
+    }
+}
+
 impl CommitExt for Commit {
     fn load(hash: &SHA1) -> Commit {
         let storage = util::objects_storage();
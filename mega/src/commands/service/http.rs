@@ -1,5 +1,6 @@
 use clap::{ArgMatches, Args, Command, FromArgMatches};
 
+use ceres::pack::cache::spawn_warmer;
 use common::{config::Config, errors::MegaResult};
 use gateway::https_server::{self, HttpOptions};
 use jupiter::context::Context;
@@ -20,6 +21,7 @@ pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
         .mono_storage
         .init_monorepo(&config.monorepo)
         .await;
+    spawn_warmer(context.clone());
     https_server::http_server(context, server_matchers).await;
     Ok(())
 }
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 
 use clap::{ArgMatches, Args, Command, FromArgMatches, ValueEnum};
 
+use ceres::pack::cache::spawn_warmer;
 use common::{
     config::Config,
     errors::MegaResult,
@@ -61,6 +62,7 @@ pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
 
     let context = Context::new(config.clone()).await;
     context.services.mono_storage.init_monorepo(&config.monorepo).await;
+    spawn_warmer(context.clone());
 
     let context_clone = context.clone();
     let http_server = if service_type.contains(&StartCommand::Http) {
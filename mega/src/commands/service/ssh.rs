@@ -1,5 +1,6 @@
 use clap::{ArgMatches, Args, Command, FromArgMatches};
 
+use ceres::pack::cache::spawn_warmer;
 use common::config::Config;
 use common::errors::MegaResult;
 use jupiter::context::Context;
@@ -21,6 +22,7 @@ pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
         .mono_storage
         .init_monorepo(&config.monorepo)
         .await;
+    spawn_warmer(context.clone());
     start_server(context, &server_matchers).await;
     Ok(())
 }
@@ -0,0 +1,1258 @@
+//! Line level diff of two versions of a file.
+//!
+//! The edit script is computed with the linear space variant of Myers' O(ND) algorithm,
+//! splitting the problem at the middle snake, so memory stays proportional to the input
+//! even for files that were rewritten completely. The histogram algorithm anchors the
+//! script on lines that are rare in the old text instead, and falls back to Myers where
+//! no such line is left.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Index, IndexMut, Range};
+
+/// Unchanged lines kept around the changes of a hunk by `lines`, as git does.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Lines occurring more often than this in the old text aren't used as histogram anchors.
+const MAX_CHAIN_LEN: usize = 64;
+
+/// Algorithm computing the edit script of a diff.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// A shortest edit script.
+    #[default]
+    Myers,
+    /// Git's histogram diff, which keeps moved blocks and reordered functions readable
+    /// at the price of scripts that are sometimes a bit longer.
+    Histogram,
+}
+
+/// One step of an edit script, indexes are 0 based positions in the old and new input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal {
+        old_index: usize,
+        new_index: usize,
+        len: usize,
+    },
+    Delete {
+        old_index: usize,
+        len: usize,
+    },
+    Insert {
+        new_index: usize,
+        len: usize,
+    },
+}
+
+/// Split a text into lines, each line keeps its terminating `\n`.
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Compute a shortest edit script turning `old` into `new`.
+///
+/// Adjacent operations of the same kind are merged, so `Equal` and change blocks alternate.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut vf = V::new(max_d);
+    let mut vb = V::new(max_d);
+    let mut ops = Vec::new();
+    conquer(
+        old,
+        0..old.len(),
+        new,
+        0..new.len(),
+        &mut vf,
+        &mut vb,
+        &mut ops,
+    );
+    ops
+}
+
+/// Compute an edit script turning `old` into `new` with the given algorithm.
+pub fn diff_with<T: Eq + Hash>(old: &[T], new: &[T], algorithm: Algorithm) -> Vec<DiffOp> {
+    match algorithm {
+        Algorithm::Myers => diff(old, new),
+        Algorithm::Histogram => {
+            let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+            let mut vf = V::new(max_d);
+            let mut vb = V::new(max_d);
+            let mut ops = Vec::new();
+            histogram(
+                old,
+                0..old.len(),
+                new,
+                0..new.len(),
+                &mut vf,
+                &mut vb,
+                &mut ops,
+            );
+            ops
+        }
+    }
+}
+
+/// How a line of a hunk changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineTag {
+    Equal,
+    Delete,
+    Insert,
+}
+
+impl LineTag {
+    /// Prefix of the line in a unified diff.
+    pub fn sign(&self) -> char {
+        match self {
+            LineTag::Equal => ' ',
+            LineTag::Delete => '-',
+            LineTag::Insert => '+',
+        }
+    }
+}
+
+/// A line of a hunk, the text keeps its terminating `\n` unless it's the last line of
+/// a file without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HunkLine<'a> {
+    pub tag: LineTag,
+    pub text: &'a str,
+}
+
+/// Changes close to each other with the unchanged lines around them, the ranges are the
+/// 0 based lines of the old and new text the hunk covers.
+///
+/// Its `Display` is the hunk of a unified diff, starting with the `@@` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk<'a> {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+    pub lines: Vec<HunkLine<'a>>,
+}
+
+impl fmt::Display for Hunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "@@ -{} +{} @@",
+            hunk_range(self.old.start, self.old.len()),
+            hunk_range(self.new.start, self.new.len())
+        )?;
+        for line in &self.lines {
+            write!(f, "{}{}", line.tag.sign(), line.text)?;
+            if !line.text.ends_with('\n') {
+                writeln!(f, "\n\\ No newline at end of file")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hunks of the line diff from `old` to `new` with `DEFAULT_CONTEXT` lines of context,
+/// empty when both texts are equal.
+pub fn lines<'a>(old: &'a str, new: &'a str, algorithm: Algorithm) -> Vec<Hunk<'a>> {
+    hunks(old, new, algorithm, DEFAULT_CONTEXT)
+}
+
+/// Hunks of the line diff from `old` to `new`, keeping `context` unchanged lines around
+/// changes. Changes whose context overlaps share a hunk.
+pub fn hunks<'a>(
+    old: &'a str,
+    new: &'a str,
+    algorithm: Algorithm,
+    context: usize,
+) -> Vec<Hunk<'a>> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let mut lines: Vec<HunkLine> = Vec::new();
+    let mut extend = |tag: LineTag, texts: &[&'a str]| {
+        lines.extend(texts.iter().map(|text| HunkLine { tag, text }));
+    };
+    for op in diff_with(&old_lines, &new_lines, algorithm) {
+        match op {
+            DiffOp::Equal { old_index, len, .. } => {
+                extend(LineTag::Equal, &old_lines[old_index..old_index + len])
+            }
+            DiffOp::Delete { old_index, len } => {
+                extend(LineTag::Delete, &old_lines[old_index..old_index + len])
+            }
+            DiffOp::Insert { new_index, len } => {
+                extend(LineTag::Insert, &new_lines[new_index..new_index + len])
+            }
+        }
+    }
+
+    // windows of context around every change, overlapping windows form one hunk
+    let mut windows: Vec<Range<usize>> = Vec::new();
+    for (index, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.tag != LineTag::Equal)
+    {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match windows.last_mut() {
+            Some(last) if last.end >= start => last.end = end,
+            _ => windows.push(start..end),
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let (mut old_before, mut new_before, mut pos) = (0, 0, 0);
+    for window in windows {
+        for line in &lines[pos..window.start] {
+            old_before += (line.tag != LineTag::Insert) as usize;
+            new_before += (line.tag != LineTag::Delete) as usize;
+        }
+        let hunk_lines = lines[window.clone()].to_vec();
+        let old_count = hunk_lines
+            .iter()
+            .filter(|x| x.tag != LineTag::Insert)
+            .count();
+        let new_count = hunk_lines
+            .iter()
+            .filter(|x| x.tag != LineTag::Delete)
+            .count();
+        hunks.push(Hunk {
+            old: old_before..old_before + old_count,
+            new: new_before..new_before + new_count,
+            lines: hunk_lines,
+        });
+        old_before += old_count;
+        new_before += new_count;
+        pos = window.end;
+    }
+    hunks
+}
+
+/// For every line of `new`, the line of `old` it was kept from, `None` for inserted lines.
+pub fn line_mapping<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Option<usize>> {
+    let mut mapping = vec![None; new.len()];
+    for op in diff(old, new) {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = op
+        {
+            for i in 0..len {
+                mapping[new_index + i] = Some(old_index + i);
+            }
+        }
+    }
+    mapping
+}
+
+/// Render the change from `old` to `new` as the hunks of a unified diff, each hunk
+/// starting with its `@@` header and keeping `context` unchanged lines around changes.
+/// The result is empty when both texts are equal.
+pub fn unified_diff(old: &str, new: &str, context: usize) -> String {
+    hunks(old, new, Algorithm::Myers, context)
+        .iter()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Share of the lines of the longer of two files kept in the other one, in percent.
+/// Contents which aren't text are only similar when they are equal.
+pub fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    if old == new {
+        return 100;
+    }
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return 0;
+    };
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let kept: usize = diff(&old_lines, &new_lines)
+        .iter()
+        .map(|op| match op {
+            DiffOp::Equal { len, .. } => *len,
+            _ => 0,
+        })
+        .sum();
+    (kept * 100 / old_lines.len().max(new_lines.len())) as u8
+}
+
+/// A file taken as moved or copied from another one, indexes are positions in the
+/// compared lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimilarFile {
+    pub source: usize,
+    pub target: usize,
+    pub similarity: u8,
+}
+
+/// Pair every target with the source it's the most similar to, by at least `threshold`
+/// percent, the best pairs are taken first. A source is paired once when `exclusive`,
+/// as renamed files are, and any number of times otherwise, as copied files are.
+/// Empty files and files whose sizes are too far apart aren't compared.
+pub fn find_similar(
+    sources: &[&[u8]],
+    targets: &[&[u8]],
+    threshold: u8,
+    exclusive: bool,
+) -> Vec<SimilarFile> {
+    let mut candidates = Vec::new();
+    for (target, new) in targets.iter().enumerate() {
+        for (source, old) in sources.iter().enumerate() {
+            let (small, large) = (old.len().min(new.len()), old.len().max(new.len()));
+            if small == 0 || small * 100 < large * threshold as usize {
+                continue;
+            }
+            let similarity = similarity(old, new);
+            if similarity >= threshold {
+                candidates.push(SimilarFile {
+                    source,
+                    target,
+                    similarity,
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.similarity
+            .cmp(&a.similarity)
+            .then(a.target.cmp(&b.target))
+            .then(a.source.cmp(&b.source))
+    });
+
+    let mut paired_sources = vec![false; sources.len()];
+    let mut paired_targets = vec![false; targets.len()];
+    let mut res = Vec::new();
+    for candidate in candidates {
+        if paired_targets[candidate.target] || (exclusive && paired_sources[candidate.source]) {
+            continue;
+        }
+        paired_targets[candidate.target] = true;
+        paired_sources[candidate.source] = true;
+        res.push(candidate);
+    }
+    res.sort_by_key(|x| x.target);
+    res
+}
+
+/// Merge the changes from `base` to `ours` and from `base` to `theirs` line by line.
+///
+/// Returns `None` when both sides changed the same or adjacent lines in different ways,
+/// changes made identically on both sides are taken once.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let (merged, conflicts) = merge_lines(base, ours, theirs, None);
+    (conflicts == 0).then_some(merged)
+}
+
+/// Names written on the conflict markers of a merge, usually branches or commits.
+#[derive(Debug, Clone, Copy)]
+pub struct MergeLabels<'a> {
+    pub ours: &'a str,
+    pub base: &'a str,
+    pub theirs: &'a str,
+}
+
+/// Outcome of merging two versions of a file changed from a common base.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobMerge {
+    /// the changes of both sides combined
+    Clean(Vec<u8>),
+    /// the file with every conflict written between diff3 style markers
+    Conflict { content: Vec<u8>, conflicts: usize },
+    /// both sides changed a file which isn't text
+    Binary,
+}
+
+/// Bytes looked at to tell binary files apart, as git does.
+const BINARY_PROBE: usize = 8000;
+
+/// Whether `content` is taken as binary: it isn't UTF-8 or has a NUL byte near its start.
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_PROBE)].contains(&0) || std::str::from_utf8(content).is_err()
+}
+
+/// Three-way merge of file contents with diff3 semantics.
+///
+/// A side left as the base takes the other one, binary files are merged only then.
+/// Text is merged line by line like `merge3`, every conflict is written as
+///
+/// ```text
+/// <<<<<<< ours
+/// lines of ours
+/// ||||||| base
+/// lines of the base
+/// =======
+/// lines of theirs
+/// >>>>>>> theirs
+/// ```
+///
+/// whitespace is compared exactly, a line only changed in its whitespace is a change.
+pub fn merge_blobs(base: &[u8], ours: &[u8], theirs: &[u8], labels: &MergeLabels) -> BlobMerge {
+    if ours == theirs || base == theirs {
+        return BlobMerge::Clean(ours.to_vec());
+    }
+    if base == ours {
+        return BlobMerge::Clean(theirs.to_vec());
+    }
+    if [base, ours, theirs].into_iter().any(is_binary) {
+        return BlobMerge::Binary;
+    }
+    let (Ok(base), Ok(ours), Ok(theirs)) = (
+        std::str::from_utf8(base),
+        std::str::from_utf8(ours),
+        std::str::from_utf8(theirs),
+    ) else {
+        return BlobMerge::Binary;
+    };
+    match merge_lines(base, ours, theirs, Some(labels)) {
+        (merged, 0) => BlobMerge::Clean(merged.into_bytes()),
+        (merged, conflicts) => BlobMerge::Conflict {
+            content: merged.into_bytes(),
+            conflicts,
+        },
+    }
+}
+
+/// Merge line by line, with the number of conflicts. They're written with markers when
+/// `labels` are given, merging stops at the first one otherwise.
+fn merge_lines(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    labels: Option<&MergeLabels>,
+) -> (String, usize) {
+    let base_lines = split_lines(base);
+    let ours_lines = split_lines(ours);
+    let theirs_lines = split_lines(theirs);
+    let ours_chunks = change_chunks(&base_lines, &ours_lines);
+    let theirs_chunks = change_chunks(&base_lines, &theirs_lines);
+
+    let mut out = String::new();
+    let mut conflicts = 0;
+    let (mut i, mut j, mut pos) = (0, 0, 0);
+    while i < ours_chunks.len() || j < theirs_chunks.len() {
+        // a cluster of chunks from both sides which touch each other
+        let (i0, j0) = (i, j);
+        let (start, mut end) = if j == theirs_chunks.len()
+            || (i < ours_chunks.len() && ours_chunks[i].0.start <= theirs_chunks[j].0.start)
+        {
+            i += 1;
+            (ours_chunks[i - 1].0.start, ours_chunks[i - 1].0.end)
+        } else {
+            j += 1;
+            (theirs_chunks[j - 1].0.start, theirs_chunks[j - 1].0.end)
+        };
+        loop {
+            if i < ours_chunks.len() && ours_chunks[i].0.start <= end {
+                end = end.max(ours_chunks[i].0.end);
+                i += 1;
+            } else if j < theirs_chunks.len() && theirs_chunks[j].0.start <= end {
+                end = end.max(theirs_chunks[j].0.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        out.extend(base_lines[pos..start].iter().copied());
+        let ours_part = apply_chunks(&base_lines, &ours_lines, &ours_chunks[i0..i], start..end);
+        let theirs_part = apply_chunks(
+            &base_lines,
+            &theirs_lines,
+            &theirs_chunks[j0..j],
+            start..end,
+        );
+        if i0 == i || ours_part == theirs_part {
+            out.push_str(&theirs_part);
+        } else if j0 == j {
+            out.push_str(&ours_part);
+        } else {
+            conflicts += 1;
+            let Some(labels) = labels else {
+                return (out, conflicts);
+            };
+            let base_part: String = base_lines[start..end].concat();
+            push_marked(&mut out, "<<<<<<<", labels.ours, &ours_part);
+            push_marked(&mut out, "|||||||", labels.base, &base_part);
+            push_marked(&mut out, "=======", "", &theirs_part);
+            push_line(&mut out, &format!(">>>>>>> {}", labels.theirs));
+        }
+        pos = end;
+    }
+    out.extend(base_lines[pos..].iter().copied());
+    (out, conflicts)
+}
+
+/// A conflict marker line followed by `lines`, which end with a newline in any case so
+/// that the next marker starts its own line.
+fn push_marked(out: &mut String, marker: &str, label: &str, lines: &str) {
+    push_line(out, format!("{} {}", marker, label).trim_end());
+    out.push_str(lines);
+    if !lines.is_empty() && !lines.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn push_line(out: &mut String, line: &str) {
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Changed blocks of `side` against `base`, as the replaced range of `base` and the
+/// replacing range of `side`.
+fn change_chunks<T: PartialEq>(base: &[T], side: &[T]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut chunks: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for op in diff(base, side) {
+        match op {
+            DiffOp::Equal { len, .. } => {
+                old_pos += len;
+                new_pos += len;
+            }
+            DiffOp::Delete { len, .. } => {
+                chunks.push((old_pos..old_pos + len, new_pos..new_pos));
+                old_pos += len;
+            }
+            DiffOp::Insert { len, .. } => {
+                match chunks.last_mut() {
+                    // replaces the lines deleted right before
+                    Some((old, new)) if old.end == old_pos && new.end == new_pos => new.end += len,
+                    _ => chunks.push((old_pos..old_pos, new_pos..new_pos + len)),
+                }
+                new_pos += len;
+            }
+        }
+    }
+    chunks
+}
+
+/// Text of `range` of `base` with the given chunks of `side` applied.
+fn apply_chunks(
+    base: &[&str],
+    side: &[&str],
+    chunks: &[(Range<usize>, Range<usize>)],
+    range: Range<usize>,
+) -> String {
+    let mut out = String::new();
+    let mut pos = range.start;
+    for (old, new) in chunks {
+        out.extend(base[pos..old.start].iter().copied());
+        out.extend(side[new.clone()].iter().copied());
+        pos = old.end;
+    }
+    out.extend(base[pos..range.end].iter().copied());
+    out
+}
+
+/// `start,count` of a hunk header, an empty range points at the line before it.
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, count),
+    }
+}
+
+/// Furthest reaching x for every diagonal k, indexed with negative diagonals too.
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> Self {
+        V {
+            offset: max_d as isize,
+            v: vec![0; 2 * max_d + 2],
+        }
+    }
+}
+
+impl Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+fn common_prefix_len<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix_len<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Find a point on an optimal path through the middle of the edit graph.
+fn find_middle_snake<T: PartialEq>(
+    old: &[T],
+    old_range: Range<usize>,
+    new: &[T],
+    new_range: Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+) -> Option<(usize, usize)> {
+    let n = old_range.len();
+    let m = new_range.len();
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    vf[1] = 0;
+    vb[1] = 0;
+
+    let d_max = (n + m).div_ceil(2) + 1;
+    for d in 0..d_max as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
+                vf[k + 1]
+            } else {
+                vf[k - 1] + 1
+            };
+            let y = (x as isize - k) as usize;
+            let (x0, y0) = (x, y);
+            if x < n && y < m {
+                x += common_prefix_len(
+                    &old[old_range.start + x..old_range.end],
+                    &new[new_range.start + y..new_range.end],
+                );
+            }
+            vf[k] = x;
+            if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                return Some((x0 + old_range.start, y0 + new_range.start));
+            }
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) {
+                vb[k + 1]
+            } else {
+                vb[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let advance = common_suffix_len(
+                    &old[old_range.start..old_range.start + n - x],
+                    &new[new_range.start..new_range.start + m - y],
+                );
+                x += advance;
+                y += advance;
+            }
+            vb[k] = x;
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                return Some((n - x + old_range.start, m - y + new_range.start));
+            }
+        }
+    }
+    None
+}
+
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_range: Range<usize>,
+    new: &[T],
+    mut new_range: Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+    ops: &mut Vec<DiffOp>,
+) {
+    let prefix = common_prefix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    if prefix > 0 {
+        push(
+            ops,
+            DiffOp::Equal {
+                old_index: old_range.start,
+                new_index: new_range.start,
+                len: prefix,
+            },
+        );
+        old_range.start += prefix;
+        new_range.start += prefix;
+    }
+    let suffix = common_suffix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() && new_range.is_empty() {
+        // nothing left between prefix and suffix
+    } else if new_range.is_empty() {
+        push(
+            ops,
+            DiffOp::Delete {
+                old_index: old_range.start,
+                len: old_range.len(),
+            },
+        );
+    } else if old_range.is_empty() {
+        push(
+            ops,
+            DiffOp::Insert {
+                new_index: new_range.start,
+                len: new_range.len(),
+            },
+        );
+    } else if let Some((x, y)) =
+        find_middle_snake(old, old_range.clone(), new, new_range.clone(), vf, vb)
+    {
+        conquer(
+            old,
+            old_range.start..x,
+            new,
+            new_range.start..y,
+            vf,
+            vb,
+            ops,
+        );
+        conquer(old, x..old_range.end, new, y..new_range.end, vf, vb, ops);
+    } else {
+        push(
+            ops,
+            DiffOp::Delete {
+                old_index: old_range.start,
+                len: old_range.len(),
+            },
+        );
+        push(
+            ops,
+            DiffOp::Insert {
+                new_index: new_range.start,
+                len: new_range.len(),
+            },
+        );
+    }
+
+    if suffix > 0 {
+        push(
+            ops,
+            DiffOp::Equal {
+                old_index: old_range.end,
+                new_index: new_range.end,
+                len: suffix,
+            },
+        );
+    }
+}
+
+/// Histogram diff of the given ranges: the longest common block containing the line
+/// with the fewest occurrences in `old` is kept, and both sides of it are diffed the
+/// same way. Ranges without a common line occurring at most `MAX_CHAIN_LEN` times are
+/// left to Myers.
+fn histogram<T: Eq + Hash>(
+    old: &[T],
+    mut old_range: Range<usize>,
+    new: &[T],
+    mut new_range: Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+    ops: &mut Vec<DiffOp>,
+) {
+    let prefix = common_prefix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    if prefix > 0 {
+        push(
+            ops,
+            DiffOp::Equal {
+                old_index: old_range.start,
+                new_index: new_range.start,
+                len: prefix,
+            },
+        );
+        old_range.start += prefix;
+        new_range.start += prefix;
+    }
+    let suffix = common_suffix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() || new_range.is_empty() {
+        conquer(old, old_range.clone(), new, new_range.clone(), vf, vb, ops);
+    } else if let Some((x, y, len)) =
+        find_rare_block(old, old_range.clone(), new, new_range.clone())
+    {
+        histogram(
+            old,
+            old_range.start..x,
+            new,
+            new_range.start..y,
+            vf,
+            vb,
+            ops,
+        );
+        push(
+            ops,
+            DiffOp::Equal {
+                old_index: x,
+                new_index: y,
+                len,
+            },
+        );
+        histogram(
+            old,
+            x + len..old_range.end,
+            new,
+            y + len..new_range.end,
+            vf,
+            vb,
+            ops,
+        );
+    } else {
+        conquer(old, old_range.clone(), new, new_range.clone(), vf, vb, ops);
+    }
+
+    if suffix > 0 {
+        push(
+            ops,
+            DiffOp::Equal {
+                old_index: old_range.end,
+                new_index: new_range.end,
+                len: suffix,
+            },
+        );
+    }
+}
+
+/// The common block of the ranges whose rarest line occurs the fewest times in `old`,
+/// the longest one on ties, as its start in `old`, its start in `new` and its length.
+fn find_rare_block<T: Eq + Hash>(
+    old: &[T],
+    old_range: Range<usize>,
+    new: &[T],
+    new_range: Range<usize>,
+) -> Option<(usize, usize, usize)> {
+    let mut occurrences: HashMap<&T, Vec<usize>> = HashMap::new();
+    for (index, line) in old
+        .iter()
+        .enumerate()
+        .take(old_range.end)
+        .skip(old_range.start)
+    {
+        occurrences.entry(line).or_default().push(index);
+    }
+
+    // (count of the rarest line, x, y, len)
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut y = new_range.start;
+    while y < new_range.end {
+        let mut next = y + 1;
+        let positions = match occurrences.get(&new[y]) {
+            Some(positions) if positions.len() <= MAX_CHAIN_LEN => positions,
+            _ => {
+                y = next;
+                continue;
+            }
+        };
+        for &x in positions {
+            let (mut start_x, mut start_y) = (x, y);
+            while start_x > old_range.start
+                && start_y > new_range.start
+                && old[start_x - 1] == new[start_y - 1]
+            {
+                start_x -= 1;
+                start_y -= 1;
+            }
+            let (mut end_x, mut end_y) = (x + 1, y + 1);
+            while end_x < old_range.end && end_y < new_range.end && old[end_x] == new[end_y] {
+                end_x += 1;
+                end_y += 1;
+            }
+            let count = old[start_x..end_x]
+                .iter()
+                .map(|line| occurrences[line].len())
+                .min()
+                .unwrap_or(usize::MAX);
+            let len = end_x - start_x;
+            let better = match best {
+                None => true,
+                Some((best_count, _, _, best_len)) => {
+                    count < best_count || (count == best_count && len > best_len)
+                }
+            };
+            if better {
+                best = Some((count, start_x, start_y, len));
+            }
+            next = next.max(end_y);
+        }
+        y = next;
+    }
+    best.map(|(_, x, y, len)| (x, y, len))
+}
+
+/// Append an operation, extending the last one when they are of the same kind.
+/// Within a change block deletions are kept before insertions.
+fn push(ops: &mut Vec<DiffOp>, op: DiffOp) {
+    if matches!(op, DiffOp::Delete { .. }) && matches!(ops.last(), Some(DiffOp::Insert { .. })) {
+        let insert = ops.pop().unwrap();
+        push(ops, op);
+        ops.push(insert);
+        return;
+    }
+    match (ops.last_mut(), op) {
+        (Some(DiffOp::Equal { len, .. }), DiffOp::Equal { len: extra, .. })
+        | (Some(DiffOp::Delete { len, .. }), DiffOp::Delete { len: extra, .. })
+        | (Some(DiffOp::Insert { len, .. }), DiffOp::Insert { len: extra, .. }) => *len += extra,
+        _ => ops.push(op),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rebuild `new` from `old` and the edit script, counting the edits.
+    fn apply<T: PartialEq + Clone + std::fmt::Debug>(old: &[T], new: &[T]) -> usize {
+        apply_ops(old, new, diff(old, new))
+    }
+
+    fn apply_ops<T: PartialEq + Clone + std::fmt::Debug>(
+        old: &[T],
+        new: &[T],
+        ops: Vec<DiffOp>,
+    ) -> usize {
+        let mut out = Vec::new();
+        let (mut old_pos, mut new_pos, mut edits) = (0, 0, 0);
+        for op in ops {
+            match op {
+                DiffOp::Equal {
+                    old_index,
+                    new_index,
+                    len,
+                } => {
+                    assert_eq!((old_index, new_index), (old_pos, new_pos));
+                    out.extend_from_slice(&old[old_index..old_index + len]);
+                    old_pos += len;
+                    new_pos += len;
+                }
+                DiffOp::Delete { old_index, len } => {
+                    assert_eq!(old_index, old_pos);
+                    old_pos += len;
+                    edits += len;
+                }
+                DiffOp::Insert { new_index, len } => {
+                    assert_eq!(new_index, new_pos);
+                    out.extend_from_slice(&new[new_index..new_index + len]);
+                    new_pos += len;
+                    edits += len;
+                }
+            }
+        }
+        assert_eq!((old_pos, new_pos), (old.len(), new.len()));
+        assert_eq!(out, new);
+        edits
+    }
+
+    #[test]
+    fn test_diff_shortest_script() {
+        let old: Vec<char> = "ABCABBA".chars().collect();
+        let new: Vec<char> = "CBABAC".chars().collect();
+        assert_eq!(apply(&old, &new), 5);
+        assert_eq!(apply::<char>(&[], &[]), 0);
+        assert_eq!(apply(&old, &[]), 7);
+        assert_eq!(apply(&[], &new), 6);
+        assert_eq!(apply(&old, &old), 0);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = split_lines("a\nb\nc\nd\n");
+        let new = split_lines("a\nc\nx\nd\ne");
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                DiffOp::Equal {
+                    old_index: 0,
+                    new_index: 0,
+                    len: 1
+                },
+                DiffOp::Delete {
+                    old_index: 1,
+                    len: 1
+                },
+                DiffOp::Equal {
+                    old_index: 2,
+                    new_index: 1,
+                    len: 1
+                },
+                DiffOp::Insert {
+                    new_index: 2,
+                    len: 1
+                },
+                DiffOp::Equal {
+                    old_index: 3,
+                    new_index: 3,
+                    len: 1
+                },
+                DiffOp::Insert {
+                    new_index: 4,
+                    len: 1
+                },
+            ]
+        );
+        assert_eq!(
+            line_mapping(&old, &new),
+            vec![Some(0), Some(2), None, Some(3), None]
+        );
+    }
+
+    #[test]
+    fn test_diff_large_rewrite() {
+        let old: Vec<usize> = (0..2000).collect();
+        let new: Vec<usize> = (0..2000).map(|x| x * 7 % 2003).collect();
+        apply(&old, &new);
+        let new: Vec<usize> = (0..2000).rev().collect();
+        assert_eq!(apply(&old, &new), 3998);
+    }
+
+    #[test]
+    fn test_diff_histogram() {
+        let histogram = |old: &[usize], new: &[usize]| {
+            apply_ops(old, new, diff_with(old, new, Algorithm::Histogram))
+        };
+        assert_eq!(histogram(&[], &[]), 0);
+        assert_eq!(histogram(&[1, 2, 3], &[]), 3);
+        assert_eq!(histogram(&[], &[1, 2]), 2);
+        // the script is valid, though not always the shortest one
+        assert!(histogram(&[1, 2, 3, 1, 2, 2, 1], &[3, 2, 1, 2, 1, 3]) >= 5);
+        let old: Vec<usize> = (0..2000).collect();
+        histogram(&old, &(0..2000).map(|x| x * 7 % 2003).collect::<Vec<_>>());
+        // lines too common to anchor on are left to Myers
+        let old: Vec<usize> = (0..500).map(|x| x % 2).collect();
+        let new: Vec<usize> = (0..400).map(|x| x % 3 % 2).collect();
+        assert_eq!(histogram(&old, &new), apply(&old, &new));
+
+        // Myers keeps the leading lines, histogram the line occurring once it meets first
+        let (old, new) = ("x\n}\na\n}\nb\n", "a\n}\nx\n}\nb\n");
+        let render = |algorithm| {
+            hunks(old, new, algorithm, 0)
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<String>()
+        };
+        assert_eq!(
+            render(Algorithm::Myers),
+            "@@ -0,0 +1,2 @@\n+a\n+}\n@@ -2,2 +3,0 @@\n-}\n-a\n"
+        );
+        assert_eq!(
+            render(Algorithm::Histogram),
+            "@@ -1,2 +0,0 @@\n-x\n-}\n@@ -3,0 +2,2 @@\n+}\n+x\n"
+        );
+    }
+
+    #[test]
+    fn test_lines() {
+        assert!(lines("a\n", "a\n", Algorithm::Histogram).is_empty());
+        let old: String = (1..=10).map(|x| format!("{}\n", x)).collect();
+        let new = old.replace("2\n", "two\n").replace("10\n", "10");
+        let hunks = lines(&old, &new, Algorithm::Histogram);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old.clone(), hunks[0].new.clone()), (0..5, 0..5));
+        assert_eq!(
+            hunks[0].lines[..3],
+            [
+                HunkLine {
+                    tag: LineTag::Equal,
+                    text: "1\n"
+                },
+                HunkLine {
+                    tag: LineTag::Delete,
+                    text: "2\n"
+                },
+                HunkLine {
+                    tag: LineTag::Insert,
+                    text: "two\n"
+                },
+            ]
+        );
+        assert_eq!((hunks[1].old.clone(), hunks[1].new.clone()), (6..10, 6..10));
+        assert_eq!(
+            hunks[1].to_string(),
+            "@@ -7,4 +7,4 @@\n 7\n 8\n 9\n-10\n+10\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", 3), "");
+        assert_eq!(
+            unified_diff("a\nb\nc\n", "a\nB\nc\n", 1),
+            "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+        assert_eq!(
+            unified_diff("", "x", 3),
+            "@@ -0,0 +1 @@\n+x\n\\ No newline at end of file\n"
+        );
+        let old: String = (1..=20).map(|x| format!("{}\n", x)).collect();
+        let new = old.replacen("2\n", "two\n", 1).replace("18\n", "");
+        assert_eq!(
+            unified_diff(&old, &new, 2),
+            "@@ -1,4 +1,4 @@\n 1\n-2\n+two\n 3\n 4\n@@ -16,5 +16,4 @@\n 16\n 17\n-18\n 19\n 20\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_blobs() {
+        let labels = MergeLabels {
+            ours: "main",
+            base: "base",
+            theirs: "feature",
+        };
+        let base = b"a\nb\nc\nd\ne\n".as_slice();
+        assert_eq!(
+            merge_blobs(base, b"A\nb\nc\nd\ne\n", b"a\nb\nc\nd\nE\n", &labels),
+            BlobMerge::Clean(b"A\nb\nc\nd\nE\n".to_vec())
+        );
+        assert_eq!(
+            merge_blobs(base, base, b"x", &labels),
+            BlobMerge::Clean(b"x".to_vec())
+        );
+
+        let merged = merge_blobs(base, b"a\nB\nc\nd\ne\n", b"a\nX\nc\nd\nE\n", &labels);
+        assert_eq!(
+            merged,
+            BlobMerge::Conflict {
+                content:
+                    b"a\n<<<<<<< main\nB\n||||||| base\nb\n=======\nX\n>>>>>>> feature\nc\nd\nE\n"
+                        .to_vec(),
+                conflicts: 1,
+            }
+        );
+        // a last line without newline still leaves the markers on their own lines
+        let BlobMerge::Conflict { content, conflicts } =
+            merge_blobs(b"a\nb", b"a\nours", b"a\ntheirs", &labels)
+        else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            String::from_utf8(content).unwrap(),
+            "a\n<<<<<<< main\nours\n||||||| base\nb\n=======\ntheirs\n>>>>>>> feature\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_blobs_whitespace() {
+        let labels = MergeLabels {
+            ours: "ours",
+            base: "base",
+            theirs: "theirs",
+        };
+        let base = b"fn main() {\n    run();\n}\n\nfn run() {}\n".as_slice();
+        // an indentation change merges with a change elsewhere
+        assert_eq!(
+            merge_blobs(
+                base,
+                b"fn main() {\n\trun();\n}\n\nfn run() {}\n",
+                b"fn main() {\n    run();\n}\n\nfn run() { todo!() }\n",
+                &labels
+            ),
+            BlobMerge::Clean(b"fn main() {\n\trun();\n}\n\nfn run() { todo!() }\n".to_vec())
+        );
+        // different whitespace changes of the same line conflict
+        assert!(matches!(
+            merge_blobs(
+                base,
+                b"fn main() {\n\trun();\n}\n\nfn run() {}\n",
+                b"fn main() {\n  run();\n}\n\nfn run() {}\n",
+                &labels
+            ),
+            BlobMerge::Conflict { conflicts: 1, .. }
+        ));
+        // line endings are kept as they are
+        assert_eq!(
+            merge_blobs(b"a\r\nb\r\n", b"A\r\nb\r\n", b"a\r\nb\r\nc\r\n", &labels),
+            BlobMerge::Clean(b"A\r\nb\r\nc\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_merge_blobs_binary() {
+        let labels = MergeLabels {
+            ours: "ours",
+            base: "base",
+            theirs: "theirs",
+        };
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0"));
+        assert!(is_binary(&[0xff, 0xfe, b'a']));
+        assert!(!is_binary("héllo\n".as_bytes()));
+        assert_eq!(
+            merge_blobs(b"a\0", b"b\0", b"c\0", &labels),
+            BlobMerge::Binary
+        );
+        // a side left unchanged takes the other one, binary or not
+        assert_eq!(
+            merge_blobs(b"a\0", b"a\0", b"c\0", &labels),
+            BlobMerge::Clean(b"c\0".to_vec())
+        );
+        assert_eq!(
+            merge_blobs(b"a\0", b"b\0", b"b\0", &labels),
+            BlobMerge::Clean(b"b\0".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity(b"", b""), 100);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nb\nc\nd\n"), 100);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nb\nc\nx\n"), 75);
+        assert_eq!(similarity(b"a\nb\n", b"a\nb\nc\nd\n"), 50);
+        assert_eq!(similarity(b"a\n", b""), 0);
+        assert_eq!(similarity(&[0xff, 0x00], &[0xff, 0x01]), 0);
+    }
+
+    #[test]
+    fn test_find_similar() {
+        let sources: Vec<&[u8]> = vec![b"a\nb\nc\nd\n", b"w\nx\ny\nz\n", b""];
+        let targets: Vec<&[u8]> = vec![b"w\nx\ny\n0\n", b"a\nb\nc\nd\n", b"a\nb\nc\n0\n", b""];
+        assert_eq!(
+            find_similar(&sources, &targets, 50, true),
+            vec![
+                SimilarFile {
+                    source: 1,
+                    target: 0,
+                    similarity: 75
+                },
+                SimilarFile {
+                    source: 0,
+                    target: 1,
+                    similarity: 100
+                },
+            ]
+        );
+        let copies = find_similar(&sources, &targets, 50, false);
+        assert_eq!(copies.len(), 3);
+        assert_eq!((copies[2].source, copies[2].target), (0, 2));
+        assert!(find_similar(&sources, &targets, 80, true)
+            .iter()
+            .all(|x| x.similarity == 100));
+    }
+
+    #[test]
+    fn test_merge3() {
+        let base = "a\nb\nc\nd\ne\n";
+        // changes far enough apart merge cleanly
+        assert_eq!(
+            merge3(base, "A\nb\nc\nd\ne\n", "a\nb\nc\nd\nE\n").as_deref(),
+            Some("A\nb\nc\nd\nE\n")
+        );
+        // the same change on both sides is taken once
+        assert_eq!(
+            merge3(base, "a\nb\nC\nd\ne\n", "a\nb\nC\nd\nx\n").as_deref(),
+            Some("a\nb\nC\nd\nx\n")
+        );
+        // insertions and deletions
+        assert_eq!(
+            merge3(base, "a\nnew\nb\nc\nd\ne\n", "a\nb\nc\ne\n").as_deref(),
+            Some("a\nnew\nb\nc\ne\n")
+        );
+        assert_eq!(merge3(base, base, "").as_deref(), Some(""));
+        // conflicting edits of the same or adjacent lines
+        assert_eq!(merge3(base, "a\nB\nc\nd\ne\n", "a\nX\nc\nd\ne\n"), None);
+        assert_eq!(merge3(base, "a\nB\nc\nd\ne\n", "a\nb\nC\nd\ne\n"), None);
+        assert_eq!(merge3("", "x\n", "y\n"), None);
+    }
+}
@@ -3,6 +3,8 @@ use std::string::FromUtf8Error;
 
 use thiserror::Error;
 
+use common::errors::{MegaError, MonoPathError};
+
 #[derive(Error, Debug)]
 pub enum GitError {
     #[error("The `{0}` is not a valid git object type.")]
@@ -29,9 +31,15 @@ pub enum GitError {
     #[error("Not a valid git tag object.")]
     InvalidTagObject,
 
+    #[error("The object {0} is malformed: {1}")]
+    MalformedObject(String, String),
+
     #[error("The `{0}` is not a valid idx file.")]
     InvalidIdxFile(String),
 
+    #[error("The `{0}` is not a valid multi-pack-index file.")]
+    InvalidMidxFile(String),
+
     #[error("The `{0}` is not a valid pack file.")]
     InvalidPackFile(String),
 
@@ -80,6 +88,12 @@ pub enum GitError {
     #[error("Can't find specific object: {0}")]
     ObjectNotFound(String),
 
+    #[error("An entry with the same name already exists: {0}")]
+    EntryExists(String),
+
+    #[error("{0}")]
+    RefConflict(String),
+
     #[error("Repository not found")]
     RepoNotFound,
 
@@ -99,3 +113,28 @@ impl From<FromUtf8Error> for GitError {
         GitError::ConversionError(err.to_string())
     }
 }
+
+impl From<MonoPathError> for GitError {
+    fn from(err: MonoPathError) -> Self {
+        GitError::InvalidArgument(err.to_string())
+    }
+}
+
+impl From<MegaError> for GitError {
+    fn from(err: MegaError) -> Self {
+        if err.is_ref_conflict() {
+            GitError::RefConflict(err.to_string())
+        } else {
+            GitError::CustomError(err.to_string())
+        }
+    }
+}
+
+impl From<GitError> for MegaError {
+    fn from(err: GitError) -> Self {
+        match err {
+            GitError::RefConflict(msg) => MegaError::ref_conflict(&msg),
+            err => MegaError::with_message(&err.to_string()),
+        }
+    }
+}
@@ -39,6 +39,7 @@ impl From<Blob> for raw_blob::Model {
             sha1: value.id.to_string(),
             storage_type: StorageType::Database,
             data: Some(value.data),
+            compression: None,
             content: None,
             file_type: None,
             local_path: None,
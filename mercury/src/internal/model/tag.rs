@@ -13,6 +13,7 @@ impl From<Tag> for mega_tag::Model {
         mega_tag::Model {
             id: generate_id(),
             tag_id: value.id.to_string(),
+            path: "/".to_owned(),
             object_id: value.object_hash.to_string(),
             object_type: value.object_type.to_string(),
             tag_name: value.tag_name,
@@ -121,6 +121,33 @@ impl Commit {
         }
         self.message.clone()
     }
+
+    /// The signature of a signed commit and the data it signs, which is the commit without
+    /// its `gpgsig` header. `None` when the commit isn't signed.
+    pub fn signature(&self) -> Option<(Vec<u8>, String)> {
+        let data = self.to_data().ok()?;
+        let header_end = data.find(b"\n\n").unwrap_or(data.len());
+        let start = data[..header_end].find(b"\ngpgsig ")? + 1;
+        // the header goes on over the following lines starting with a space
+        let mut end = start;
+        loop {
+            end += data[end..]
+                .find_byte(b'\n')
+                .map_or(data.len() - end, |x| x + 1);
+            if data.get(end) != Some(&b' ') {
+                break;
+            }
+        }
+        let mut signature = String::new();
+        for line in data[start + "gpgsig ".len()..end].lines() {
+            let line = line.strip_prefix(b" ").unwrap_or(line);
+            signature.push_str(&String::from_utf8_lossy(line));
+            signature.push('\n');
+        }
+        let mut payload = data[..start].to_vec();
+        payload.extend(&data[end..]);
+        Some((payload, signature))
+    }
 }
 
 impl ObjectTrait for Commit {
@@ -214,3 +241,30 @@ impl ObjectTrait for Commit {
         Ok(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_signature() {
+        let header = "tree 8ab686eafeb1f44702738c8b0f24f2567c36da6d\n\
+            author Mega <mega@example.com> 1700000000 +0800\n\
+            committer Mega <mega@example.com> 1700000000 +0800\n";
+        let signed = format!(
+            "{}gpgsig -----BEGIN SSH SIGNATURE-----\n abc\n \n -----END SSH SIGNATURE-----\n\nfix\n",
+            header
+        );
+        let commit = Commit::from_bytes(signed.as_bytes(), SHA1::default()).unwrap();
+        let (payload, signature) = commit.signature().unwrap();
+        assert_eq!(payload, format!("{}\nfix\n", header).into_bytes());
+        assert_eq!(
+            signature,
+            "-----BEGIN SSH SIGNATURE-----\nabc\n\n-----END SSH SIGNATURE-----\n"
+        );
+
+        let unsigned = format!("{}\nfix\n", header);
+        let commit = Commit::from_bytes(unsigned.as_bytes(), SHA1::default()).unwrap();
+        assert!(commit.signature().is_none());
+    }
+}
@@ -0,0 +1,239 @@
+//! Checks of received objects, a subset of what `git fsck` verifies, so a push carrying
+//! malformed objects is refused instead of stored.
+
+use std::collections::HashSet;
+
+use crate::errors::GitError;
+use crate::internal::object::tree::TreeItemMode;
+use crate::internal::object::types::ObjectType;
+use crate::internal::pack::entry::Entry;
+use crate::internal::pack::utils::calculate_object_hash;
+
+/// Check that the hash of `entry` matches its content, and that trees, commits and tags
+/// are well formed.
+pub fn check_entry(entry: &Entry) -> Result<(), GitError> {
+    let malformed = |msg: &str| GitError::MalformedObject(entry.hash.to_string(), msg.to_owned());
+    if calculate_object_hash(entry.obj_type, &entry.data) != entry.hash {
+        return Err(malformed("hash does not match the content"));
+    }
+    match entry.obj_type {
+        ObjectType::Tree => check_tree(&entry.data),
+        ObjectType::Commit => check_commit(&entry.data),
+        ObjectType::Tag => check_tag(&entry.data),
+        _ => Ok(()),
+    }
+    .map_err(malformed)
+}
+
+/// Entries are `<mode> <name>\0<20 byte hash>`, with known modes, names a checkout can
+/// create, and in git's order where a tree sorts as if its name ended with `/`.
+fn check_tree(data: &[u8]) -> Result<(), &'static str> {
+    let mut names = HashSet::new();
+    let mut last_key: Option<Vec<u8>> = None;
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = memchr::memchr(b' ', rest).ok_or("truncated tree entry")?;
+        let mode = TreeItemMode::tree_item_type_from_bytes(&rest[..space])
+            .map_err(|_| "bad tree entry mode")?;
+        rest = &rest[space + 1..];
+        let nul = memchr::memchr(0, rest).ok_or("truncated tree entry")?;
+        let name = &rest[..nul];
+        if rest.len() < nul + 21 {
+            return Err("truncated tree entry");
+        }
+        rest = &rest[nul + 21..];
+
+        if name.is_empty() {
+            return Err("empty name in tree");
+        }
+        if name.contains(&b'/') {
+            return Err("tree entry name contains '/'");
+        }
+        if name == b"." || name == b".." || name.eq_ignore_ascii_case(b".git") {
+            return Err("tree entry named '.', '..' or '.git'");
+        }
+        if !names.insert(name) {
+            return Err("duplicate tree entry");
+        }
+        let mut key = name.to_vec();
+        if mode == TreeItemMode::Tree {
+            key.push(b'/');
+        }
+        if last_key.as_ref().is_some_and(|last| *last >= key) {
+            return Err("tree entries not sorted");
+        }
+        last_key = Some(key);
+    }
+    Ok(())
+}
+
+/// `tree`, any `parent`s, `author` and `committer` headers come first and in that order.
+fn check_commit(data: &[u8]) -> Result<(), &'static str> {
+    let mut lines = header_lines(data)?.into_iter().peekable();
+    let tree = lines.next().and_then(|l| l.strip_prefix(b"tree "));
+    if !tree.is_some_and(is_hex_hash) {
+        return Err("missing or bad tree header");
+    }
+    while let Some(parent) = lines.peek().and_then(|l| l.strip_prefix(b"parent ")) {
+        if !is_hex_hash(parent) {
+            return Err("bad parent header");
+        }
+        lines.next();
+    }
+    let author = lines.next().and_then(|l| l.strip_prefix(b"author "));
+    if !author.is_some_and(is_ident) {
+        return Err("missing or bad author header");
+    }
+    let committer = lines.next().and_then(|l| l.strip_prefix(b"committer "));
+    if !committer.is_some_and(is_ident) {
+        return Err("missing or bad committer header");
+    }
+    Ok(())
+}
+
+/// `object`, `type` and `tag` headers come first, the `tagger` after them is optional as
+/// old git versions didn't write it.
+fn check_tag(data: &[u8]) -> Result<(), &'static str> {
+    let mut lines = header_lines(data)?.into_iter();
+    let object = lines.next().and_then(|l| l.strip_prefix(b"object "));
+    if !object.is_some_and(is_hex_hash) {
+        return Err("missing or bad object header");
+    }
+    let obj_type = lines.next().and_then(|l| l.strip_prefix(b"type "));
+    if !obj_type.is_some_and(|t| ObjectType::from_string(&String::from_utf8_lossy(t)).is_ok()) {
+        return Err("missing or bad type header");
+    }
+    let tag = lines.next().and_then(|l| l.strip_prefix(b"tag "));
+    if !tag.is_some_and(|t| !t.is_empty()) {
+        return Err("missing tag header");
+    }
+    if let Some(tagger) = lines.next().and_then(|l| l.strip_prefix(b"tagger ")) {
+        if !is_ident(tagger) {
+            return Err("bad tagger header");
+        }
+    }
+    Ok(())
+}
+
+/// The lines before the blank line ending the headers.
+fn header_lines(data: &[u8]) -> Result<Vec<&[u8]>, &'static str> {
+    let end = memchr::memmem::find(data, b"\n\n").ok_or("unterminated header")?;
+    Ok(data[..end].split(|b| *b == b'\n').collect())
+}
+
+fn is_hex_hash(s: &[u8]) -> bool {
+    s.len() == 40 && s.iter().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// `Name <email> <seconds> <+hhmm>`, the name may be empty.
+fn is_ident(s: &[u8]) -> bool {
+    let Some(lt) = memchr::memchr(b'<', s) else {
+        return false;
+    };
+    let Some(gt) = memchr::memchr(b'>', &s[lt..]).map(|x| x + lt) else {
+        return false;
+    };
+    if s[lt + 1..gt].contains(&b'\n') {
+        return false;
+    }
+    let mut date = s[gt + 1..].splitn(3, |b| *b == b' ');
+    let (Some(b""), Some(seconds), Some(tz)) = (date.next(), date.next(), date.next()) else {
+        return false;
+    };
+    !seconds.is_empty()
+        && seconds.iter().all(u8::is_ascii_digit)
+        && tz.len() == 5
+        && matches!(tz[0], b'+' | b'-')
+        && tz[1..].iter().all(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::SHA1;
+
+    fn entry(obj_type: ObjectType, data: &[u8]) -> Entry {
+        Entry {
+            obj_type,
+            hash: calculate_object_hash(obj_type, &data.to_vec()),
+            data: data.to_vec(),
+        }
+    }
+
+    fn tree_data(items: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = vec![];
+        for (mode, name) in items {
+            data.extend(format!("{} {}\0", mode, name).as_bytes());
+            data.extend([1; 20]);
+        }
+        data
+    }
+
+    const IDENT: &str = "Mega <mega@example.com> 1700000000 +0800";
+    const HASH: &str = "8ab686eafeb1f44702738c8b0f24f2567c36da6d";
+
+    #[test]
+    fn test_check_hash() {
+        let mut blob = entry(ObjectType::Blob, b"hello");
+        assert!(check_entry(&blob).is_ok());
+        blob.hash = SHA1::default();
+        assert!(check_entry(&blob).is_err());
+    }
+
+    #[test]
+    fn test_check_tree() {
+        let ok = tree_data(&[
+            ("100644", "a"),
+            ("100644", "a.txt"),
+            ("40000", "a0"),
+            ("40000", "b"),
+        ]);
+        assert!(check_entry(&entry(ObjectType::Tree, &ok)).is_ok());
+        // a tree named `a` sorts as `a/`, after `a.txt`
+        let ok = tree_data(&[("100644", "a.txt"), ("40000", "a")]);
+        assert!(check_entry(&entry(ObjectType::Tree, &ok)).is_ok());
+
+        let unsorted = tree_data(&[("100644", "b"), ("100644", "a")]);
+        assert!(check_entry(&entry(ObjectType::Tree, &unsorted)).is_err());
+        let tree_unsorted = tree_data(&[("40000", "a"), ("100644", "a.txt")]);
+        assert!(check_entry(&entry(ObjectType::Tree, &tree_unsorted)).is_err());
+        let duplicate = tree_data(&[("100644", "a"), ("100644", "a-b"), ("40000", "a")]);
+        assert!(check_entry(&entry(ObjectType::Tree, &duplicate)).is_err());
+        let bad_mode = tree_data(&[("100666", "a")]);
+        assert!(check_entry(&entry(ObjectType::Tree, &bad_mode)).is_err());
+        let dot_git = tree_data(&[("40000", ".GIT")]);
+        assert!(check_entry(&entry(ObjectType::Tree, &dot_git)).is_err());
+        let mut truncated = tree_data(&[("100644", "a")]);
+        truncated.pop();
+        assert!(check_entry(&entry(ObjectType::Tree, &truncated)).is_err());
+    }
+
+    #[test]
+    fn test_check_commit() {
+        let ok =
+            format!("tree {HASH}\nparent {HASH}\nauthor {IDENT}\ncommitter {IDENT}\n\nmessage\n");
+        assert!(check_entry(&entry(ObjectType::Commit, ok.as_bytes())).is_ok());
+        let root = format!("tree {HASH}\nauthor {IDENT}\ncommitter {IDENT}\ngpgsig x\n\nmsg");
+        assert!(check_entry(&entry(ObjectType::Commit, root.as_bytes())).is_ok());
+
+        let no_tree = format!("author {IDENT}\ncommitter {IDENT}\n\nmessage\n");
+        assert!(check_entry(&entry(ObjectType::Commit, no_tree.as_bytes())).is_err());
+        let bad_date = format!(
+            "tree {HASH}\nauthor Mega <mega@example.com> yesterday +0800\ncommitter {IDENT}\n\nm"
+        );
+        assert!(check_entry(&entry(ObjectType::Commit, bad_date.as_bytes())).is_err());
+        let no_committer = format!("tree {HASH}\nauthor {IDENT}\n\nmessage\n");
+        assert!(check_entry(&entry(ObjectType::Commit, no_committer.as_bytes())).is_err());
+    }
+
+    #[test]
+    fn test_check_tag() {
+        let ok = format!("object {HASH}\ntype commit\ntag v1.0\ntagger {IDENT}\n\nrelease\n");
+        assert!(check_entry(&entry(ObjectType::Tag, ok.as_bytes())).is_ok());
+        let no_tagger = format!("object {HASH}\ntype commit\ntag v1.0\n\nrelease\n");
+        assert!(check_entry(&entry(ObjectType::Tag, no_tagger.as_bytes())).is_ok());
+
+        let bad_type = format!("object {HASH}\ntype branch\ntag v1.0\n\nrelease\n");
+        assert!(check_entry(&entry(ObjectType::Tag, bad_type.as_bytes())).is_err());
+    }
+}
@@ -75,6 +75,27 @@ impl Display for Tag {
 }
 
 impl Tag {
+    /// Create an annotated tag of `object_hash`. As in parsed tags, the stored message
+    /// starts with the blank line separating it from the tagger.
+    pub fn new(
+        object_hash: SHA1,
+        object_type: ObjectType,
+        tag_name: String,
+        tagger: Signature,
+        message: &str,
+    ) -> Tag {
+        let mut tag = Tag {
+            id: SHA1::default(),
+            object_hash,
+            object_type,
+            tag_name,
+            tagger,
+            message: format!("\n{}", message),
+        };
+        tag.id = SHA1::from_type_and_data(ObjectType::Tag, &tag.to_data().unwrap());
+        tag
+    }
+
     // pub fn new_from_meta(meta: Meta) -> Result<Tag, GitError> {
     //     Ok(Tag::new_from_data(meta.data))
     // }
@@ -181,3 +202,34 @@ impl ObjectTrait for Tag {
         Ok(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::object::signature::SignatureType;
+
+    #[test]
+    fn test_new_tag_round_trip() {
+        let tagger = Signature::new(
+            SignatureType::Tagger,
+            "mega".to_owned(),
+            "admin@mega.org".to_owned(),
+        );
+        let tag = Tag::new(
+            SHA1::from_str("4b00093bee9b3ef5afc5f8e3645dc39cfa2f49aa").unwrap(),
+            ObjectType::Commit,
+            "v1.0.0".to_owned(),
+            tagger,
+            "first release\n",
+        );
+        let data = tag.to_data().unwrap();
+        assert!(data.ends_with(b"\n\nfirst release\n"));
+        let parsed = Tag::from_bytes(&data, tag.id).unwrap();
+        assert_eq!(parsed.tag_name, "v1.0.0");
+        assert_eq!(parsed.message, tag.message);
+        assert_eq!(
+            SHA1::from_type_and_data(ObjectType::Tag, &parsed.to_data().unwrap()),
+            tag.id
+        );
+    }
+}
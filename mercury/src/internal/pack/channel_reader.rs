@@ -1,8 +1,10 @@
 use std::io;
 use std::io::{BufRead, Read};
-use std::sync::mpsc::Receiver;
+
+use tokio::sync::mpsc::Receiver;
 
 /// Custom BufRead implementation that reads from the channel
+/// <br> Reading blocks until a chunk is received, so it must be read outside of the async runtime (e.g. `spawn_blocking`)
 pub(crate) struct ChannelReader {
     receiver: Receiver<Vec<u8>>,
     buffer: io::Cursor<Vec<u8>>,
@@ -20,11 +22,11 @@ impl ChannelReader {
 impl Read for ChannelReader {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if self.buffer.position() as usize == self.buffer.get_ref().len() { // buffer has been read completely
-            match self.receiver.recv() {
-                Ok(data) => {
+            match self.receiver.blocking_recv() {
+                Some(data) => {
                     self.buffer = io::Cursor::new(data);
                 }
-                Err(_) => return Ok(0), // Channel is closed
+                None => return Ok(0), // Channel is closed
             }
         }
         self.buffer.read(buf)
@@ -34,11 +36,11 @@ impl Read for ChannelReader {
 impl BufRead for ChannelReader {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         if self.buffer.position() as usize == self.buffer.get_ref().len() {
-            match self.receiver.recv() {
-                Ok(data) => {
+            match self.receiver.blocking_recv() {
+                Some(data) => {
                     self.buffer = io::Cursor::new(data);
                 }
-                Err(_) => return Ok(&[]), // Channel is closed
+                None => return Ok(&[]), // Channel is closed
             }
         }
         self.buffer.fill_buf()
@@ -1,8 +1,8 @@
 use std::io::{self, BufRead, Cursor, ErrorKind, Read};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, mpsc};
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::{Sender, SyncSender};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::time::Instant;
 
@@ -23,12 +23,15 @@ use crate::internal::pack::cache::_Cache;
 use crate::internal::pack::cache_object::{CacheObject, MemSizeRecorder};
 use crate::internal::pack::waitlist::Waitlist;
 use crate::internal::pack::wrapper::Wrapper;
-use crate::internal::pack::{utils, Pack, DEFAULT_TMP_DIR};
+use crate::internal::pack::{utils, ExternalBase, Pack, DEFAULT_TMP_DIR};
 use crate::internal::pack::channel_reader::ChannelReader;
 use crate::internal::pack::entry::Entry;
 
 use super::cache_object::CacheObjectInfo;
 
+/// Chunks of a streamed pack received ahead of decoding, the network waits beyond them
+const STREAM_WINDOW: usize = 64;
+
 /// For the convenience of passing parameters
 struct SharedParams {
     pub pool: Arc<ThreadPool>,
@@ -79,9 +82,17 @@ impl Pack {
             mem_limit,
             cache_objs_mem: Arc::new(AtomicUsize::default()),
             clean_tmp,
+            external_base: None,
         }
     }
 
+    /// Accept thin packs, deltas against objects missing from the pack are rebuilt from the
+    /// bases `external_base` finds.
+    pub fn with_external_base(mut self, external_base: ExternalBase) -> Self {
+        self.external_base = Some(external_base);
+        self
+    }
+
     /// Checks and reads the header of a Git pack file.
     ///
     /// This function reads the first 12 bytes of a pack file, which include the b"PACK" magic identifier,
@@ -426,12 +437,18 @@ impl Pack {
         }
 
         self.pool.join(); // wait for all threads to finish
+        self.resolve_external_bases(callback)?;
         // !Attention: Caches threadpool may not stop, but it's not a problem (garbage file data)
         // So that files != self.number
         assert_eq!(self.waitlist.map_offset.len(), 0);
         assert_eq!(self.waitlist.map_ref.len(), 0);
         assert_eq!(self.number, caches.total_inserted());
         tracing::info!("The pack file has been decoded successfully, takes: [ {:?} ]", time.elapsed());
+        metrics::histogram!("mega_pack_decode_duration_seconds")
+            .record(time.elapsed().as_secs_f64());
+        // the trailer is a SHA-1
+        metrics::histogram!("mega_pack_decode_bytes").record((offset + 20) as f64);
+        metrics::counter!("mega_pack_decode_objects_total").increment(self.number as u64);
         self.caches.clear(); // clear cached objects & stop threads
         assert_eq!(self.cache_objs_mem_used(), 0); // all the objs should be dropped until here
 
@@ -443,6 +460,50 @@ impl Pack {
         Ok(())
     }
 
+    /// Rebuild the hash deltas still waiting once the whole pack is read, their bases are not
+    /// in the pack. A rebuilt object can be the base of other waiting deltas, so this goes on
+    /// until nothing waits or no base can be found.
+    fn resolve_external_bases(&self, callback: Arc<dyn Fn(Entry, usize) + Sync + Send>) -> Result<(), GitError> {
+        let params = Arc::new(SharedParams {
+            pool: self.pool.clone(),
+            waitlist: self.waitlist.clone(),
+            caches: self.caches.clone(),
+            cache_objs_mem_size: self.cache_objs_mem.clone(),
+            callback,
+        });
+        loop {
+            let waiting: Vec<SHA1> = self.waitlist.map_ref.iter().map(|x| *x.key()).collect();
+            if waiting.is_empty() {
+                return Ok(());
+            }
+            let mut resolved = false;
+            for hash in &waiting {
+                let Some(base) = self.external_base.as_ref().and_then(|f| f(*hash)) else {
+                    continue;
+                };
+                let base_obj = Arc::new(CacheObject {
+                    info: CacheObjectInfo::BaseObject(base.obj_type, base.hash),
+                    offset: 0,
+                    data_decompressed: base.data,
+                    mem_recorder: None,
+                });
+                if let Some((_, objs)) = self.waitlist.map_ref.remove(hash) {
+                    for obj in objs {
+                        Self::process_delta(params.clone(), obj, base_obj.clone());
+                    }
+                }
+                resolved = true;
+            }
+            self.pool.join();
+            if !resolved {
+                return Err(GitError::InvalidPackFile(format!(
+                    "The delta bases {:?} are missing",
+                    waiting.iter().map(|x| x.to_string()).collect::<Vec<_>>()
+                )));
+            }
+        }
+    }
+
     /// Decode a Pack in a new thread and send the CacheObjects while decoding.
     /// <br> Attention: It will consume the `pack` and return in a JoinHandle.
     pub fn decode_async(mut self, mut pack: (impl BufRead + Send + 'static), sender: Sender<Entry>) -> JoinHandle<Pack> {
@@ -455,13 +516,18 @@ impl Pack {
     }
 
     /// Decodes a `Pack` from a `Stream` of `Bytes`, and sends the `Entry` while decoding.
+    ///
+    /// Memory stays bounded whatever the size of the pack: at most [STREAM_WINDOW] chunks of the stream
+    /// wait to be decoded, deltas are resolved by the thread pool within `mem_limit`, and decoding pauses
+    /// while the bounded `sender` is full. So the receiver must be drained while the pack is decoded,
+    /// not after the handles finish.
     pub async fn decode_stream(mut self,
                                mut stream: impl Stream<Item = Result<Bytes, Error>> + Unpin + Send + 'static,
                                pack_limit: usize,
-                               sender: Sender<Entry>)
-        -> (tokio::task::JoinHandle<Pack>, tokio::task::JoinHandle<Result<(), ProtocolError>>)
+                               sender: SyncSender<Entry>)
+        -> (tokio::task::JoinHandle<Result<Pack, GitError>>, tokio::task::JoinHandle<Result<(), ProtocolError>>)
     {
-        let (tx, rx): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = mpsc::channel();
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_WINDOW);
         let mut reader = ChannelReader::new(rx);
         let mut total_size = 0;
         let convert_handle = tokio::spawn(async move {
@@ -473,7 +539,9 @@ impl Pack {
                     eprintln!("Body size ({}) exceeded limit ({}). Terminating connection.", total_size, pack_limit);
                     return Err(ProtocolError::TooLarge(total_size.to_string()))
                 }
-                tx.send(data).unwrap();
+                if tx.send(data).await.is_err() {
+                    break; // decoding stopped on an error, the unpack handle returns it
+                }
             }
             Ok(())
         });
@@ -482,8 +550,8 @@ impl Pack {
         let unpack_handle = tokio::task::spawn_blocking(move || {
             self.decode(&mut reader, move |entry, _| {
                 if sender.send(entry).is_ok() {}
-            }).unwrap();
-            self
+            })?;
+            Ok(self)
         });
         (unpack_handle, convert_handle)
     }
@@ -622,9 +690,12 @@ mod tests {
     use flate2::Compression;
     use tokio_util::io::ReaderStream;
 
+    use crate::internal::object::blob::Blob;
+    use crate::internal::pack::entry::Entry;
     use crate::internal::pack::tests::init_logger;
     use crate::internal::pack::Pack;
     use futures_util::TryStreamExt;
+    use sha1::{Digest, Sha1};
 
     #[test]
     fn test_pack_check_header() {
@@ -738,7 +809,8 @@ mod tests {
         });
         let p = Pack::new(Some(20), Some(1024*1024*1024*4), Some(tmp.clone()), true);
 
-        let (tx, rx) = std::sync::mpsc::channel();
+        // a small window, decoding waits for the entries to be received
+        let (tx, rx) = std::sync::mpsc::sync_channel(16);
         let (pack, _ ) = p.decode_stream(stream, 1024 * 1024 * 1024, tx).await;
 
         let count = Arc::new(AtomicUsize::new(0));
@@ -752,7 +824,7 @@ mod tests {
             tracing::info!("Received: {}", cnt);
             count_c.store(cnt, Ordering::Release);
         }).await.unwrap();
-        let p = pack.await.unwrap();
+        let p = pack.await.unwrap().unwrap();
         assert_eq!(count.load(Ordering::Acquire), p.number);
     }
 
@@ -801,4 +873,51 @@ mod tests {
         task1.join().unwrap();
         task2.join().unwrap();
     }
+
+    /// A pack of one hash delta against `base`, which the pack doesn't contain.
+    fn thin_pack(base: &Blob, target: &[u8]) -> Vec<u8> {
+        let delta = delta::encode(&base.data, target);
+        let mut pack = b"PACK".to_vec();
+        pack.extend([0, 0, 0, 2, 0, 0, 0, 1]);
+        // object header: type 7 (hash delta) and the size of the delta
+        let mut size = delta.len();
+        let mut byte = (7 << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        while size > 0 {
+            pack.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        pack.push(byte);
+        pack.extend(base.id.0);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&delta).unwrap();
+        pack.extend(encoder.finish().unwrap());
+        let trailer = Sha1::digest(&pack);
+        pack.extend(trailer);
+        pack
+    }
+
+    #[test]
+    fn test_pack_decode_thin_pack() {
+        let base = Blob::from_content("the content of a file the receiver already has\n");
+        let target = "the content of a file the receiver already has\nand a new line\n";
+        let pack = thin_pack(&base, target.as_bytes());
+        let tmp = PathBuf::from("/tmp/.cache_temp");
+
+        // without a way to find the base the pack is incomplete
+        let mut p = Pack::new(None, Some(1024*1024*20), Some(tmp.clone()), true);
+        assert!(p.decode(&mut Cursor::new(pack.clone()), |_,_|{}).is_err());
+
+        let base_entry: Entry = base.clone().into();
+        let mut p = Pack::new(None, Some(1024*1024*20), Some(tmp), true)
+            .with_external_base(Arc::new(move |hash| (hash == base_entry.hash).then(|| base_entry.clone())));
+        let decoded = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let decoded_c = decoded.clone();
+        p.decode(&mut Cursor::new(pack), move |entry, _| decoded_c.lock().unwrap().push(entry)).unwrap();
+        let decoded = decoded.lock().unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].hash, Blob::from_content(target).id);
+        assert_eq!(decoded[0].data, target.as_bytes());
+    }
 }
@@ -1,5 +1,6 @@
 use std::collections::VecDeque;
 use std::io::Write;
+use std::time::Instant;
 
 use flate2::write::ZlibEncoder;
 use rayon::prelude::*;
@@ -12,13 +13,16 @@ use crate::time_it;
 use crate::{errors::GitError, hash::SHA1, internal::pack::entry::Entry};
 
 const MIN_DELTA_RATE: f64 = 0.5; // minimum delta rate can accept
+/// Default longest chain of deltas an object is stored behind, the same as git.
+pub const DEFAULT_MAX_DELTA_DEPTH: usize = 50;
 
 /// A encoder for generating pack files with delta objects.
 pub struct PackEncoder {
     object_number: usize,
     process_index: usize,
     window_size: usize,
-    window: VecDeque<(Entry, usize)>, // entry and offset
+    max_depth: usize,
+    window: VecDeque<(Entry, usize, usize)>, // entry, offset and delta depth
     sender: Option<mpsc::Sender<Vec<u8>>>,
     inner_offset: usize, // offset of current entry
     inner_hash: Sha1,    // Not SHA1 because need update trait
@@ -113,6 +117,7 @@ impl PackEncoder {
         PackEncoder {
             object_number,
             window_size,
+            max_depth: DEFAULT_MAX_DELTA_DEPTH,
             process_index: 0,
             window: VecDeque::with_capacity(window_size),
             sender: Some(sender),
@@ -123,6 +128,13 @@ impl PackEncoder {
         }
     }
 
+    /// Limit how many deltas have to be applied to get an object back, objects at the
+    /// limit are no longer used as delta bases. 0 turns delta compression off.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
     pub fn drop_sender(&mut self) {
         self.sender.take(); // Take the sender out, dropping it
     }
@@ -146,6 +158,7 @@ impl PackEncoder {
     /// - Returns a `GitError` if there is a failure during the encoding process.
     /// - Returns `PackEncodeError` if an encoding operation is already in progress.
     pub async fn encode(&mut self, mut entry_rx: mpsc::Receiver<Entry>) -> Result<(), GitError> {
+        let start = Instant::now();
         let head = encode_header(self.object_number);
         self.send_data(head.clone()).await;
         self.inner_hash.update(&head);
@@ -163,11 +176,13 @@ impl PackEncoder {
                     // push window after encode to void diff by self
                     let offset = self.inner_offset;
                     let mut try_delta_entry = entry.clone();
-                    let try_delfa_offset = self.try_as_offset_delta(&mut try_delta_entry);
-                    let obj_data = encode_one_object(&try_delta_entry, try_delfa_offset)?;
+                    let delta = self.try_as_offset_delta(&mut try_delta_entry);
+                    let obj_data =
+                        encode_one_object(&try_delta_entry, delta.map(|(offset, _)| offset))?;
 
                     self.write_all_and_update(&obj_data).await;
-                    self.window.push_back((entry, offset));
+                    let depth = delta.map_or(0, |(_, depth)| depth);
+                    self.window.push_back((entry, offset, depth));
                     if self.window.len() > self.window_size {
                         self.window.pop_front();
                     }
@@ -189,6 +204,7 @@ impl PackEncoder {
         self.final_hash = Some(SHA1::from_bytes(&hash_result));
         self.send_data((hash_result).to_vec()).await;
         self.drop_sender();
+        self.record_metrics(start);
         Ok(())
     }
 
@@ -203,6 +219,7 @@ impl PackEncoder {
             ));
         }
 
+        let start = Instant::now();
         let head = encode_header(self.object_number);
         self.send_data(head.clone()).await;
         self.inner_hash.update(&head);
@@ -261,18 +278,32 @@ impl PackEncoder {
         self.final_hash = Some(SHA1::from_bytes(&hash_result));
         self.send_data((hash_result).to_vec()).await;
         self.drop_sender();
+        self.record_metrics(start);
         Ok(())
     }
 
+    /// Report the pack written to the metrics recorder, if one is installed.
+    fn record_metrics(&self, start: Instant) {
+        metrics::histogram!("mega_pack_encode_duration_seconds")
+            .record(start.elapsed().as_secs_f64());
+        // the trailer is a SHA-1
+        metrics::histogram!("mega_pack_encode_bytes").record((self.inner_offset + 20) as f64);
+        metrics::counter!("mega_pack_encode_objects_total").increment(self.object_number as u64);
+    }
+
     /// Try to encode as delta using objects in window
     /// # Returns
-    /// - Return (offset) if success make delta
+    /// - Return (offset, depth) if success make delta, depth is the length of the delta chain
     /// - Return (None) if didn't delta,
-    fn try_as_offset_delta(&mut self, entry: &mut Entry) -> Option<usize> {
-        let mut best_base: Option<&(Entry, usize)> = None;
+    fn try_as_offset_delta(&mut self, entry: &mut Entry) -> Option<(usize, usize)> {
+        let mut best_base: Option<&(Entry, usize, usize)> = None;
         let mut best_rate: f64 = 0.0;
         for try_base in self.window.iter() {
-            if try_base.0.obj_type != entry.obj_type {
+            if try_base.0.obj_type != entry.obj_type || try_base.2 >= self.max_depth {
+                continue;
+            }
+            // like git, a base much larger than the object isn't worth comparing
+            if entry.data.len() < try_base.0.data.len() / 32 {
                 continue;
             }
             let rate = delta::encode_rate(&try_base.0.data, &entry.data);
@@ -288,13 +319,14 @@ impl PackEncoder {
             entry.obj_type = ObjectType::OffsetDelta;
             entry.data = delta;
 
-            Some(offset)
+            Some((offset, best_base.2 + 1))
         } else {
             None
         }
     }
 
-    /// Write data to writer and update hash & offset
+    /// Write data to writer and update hash & offset.
+    /// Each object is sent as a chunk of its own, readers of the stream count objects by chunks.
     async fn write_all_and_update(&mut self, data: &[u8]) {
         self.inner_hash.update(data);
         self.inner_offset += data.len();
@@ -480,6 +512,66 @@ mod tests {
         }
     }
 
+    /// Versions of a file growing by a line each, like the blobs of a file's history.
+    fn file_versions(count: usize) -> Vec<Entry> {
+        let mut content = String::new();
+        (0..count)
+            .map(|i| {
+                content.push_str(&format!("line {} of a file changed over time\n", i));
+                Blob::from_content(&content.repeat(8)).into()
+            })
+            .collect()
+    }
+
+    async fn encode_entries(entries: Vec<Entry>, window_size: usize, max_depth: usize) -> Vec<u8> {
+        let (tx, mut rx) = mpsc::channel(100);
+        let (entry_tx, entry_rx) = mpsc::channel::<Entry>(100);
+        let encoder = PackEncoder::new(entries.len(), window_size, tx).with_max_depth(max_depth);
+        encoder.encode_async(entry_rx).await.unwrap();
+        for entry in entries {
+            entry_tx.send(entry).await.unwrap();
+        }
+        drop(entry_tx);
+        let mut result = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            result.extend(chunk);
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_delta_size() {
+        init_logger();
+        let entries = file_versions(50);
+        let full = encode_entries(entries.clone(), 0, DEFAULT_MAX_DELTA_DEPTH).await;
+        let delta = time_it!("encode with delta window 10", {
+            encode_entries(entries, 10, DEFAULT_MAX_DELTA_DEPTH).await
+        });
+        tracing::info!(
+            "pack size without delta: {}, with delta: {}",
+            full.len(),
+            delta.len()
+        );
+        assert!(delta.len() < full.len());
+        check_format(&delta);
+    }
+
+    #[tokio::test]
+    async fn test_pack_encoder_max_depth() {
+        let entries = file_versions(10);
+        let full = encode_entries(entries.clone(), 0, DEFAULT_MAX_DELTA_DEPTH).await;
+        // no object can be a base, nothing is deltified
+        let depth_zero = encode_entries(entries.clone(), 4, 0).await;
+        assert_eq!(depth_zero, full);
+
+        let shallow = encode_entries(entries.clone(), 4, 1).await;
+        let deep = encode_entries(entries, 4, DEFAULT_MAX_DELTA_DEPTH).await;
+        assert!(shallow.len() < full.len());
+        assert!(deep.len() < full.len());
+        check_format(&shallow);
+        check_format(&deep);
+    }
+
     #[test]
     fn test_encode_offset() {
         let value = 11013;
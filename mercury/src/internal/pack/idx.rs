@@ -0,0 +1,319 @@
+//! Version 2 pack index, the `.idx` kept next to a packfile so that its objects can be
+//! read by hash with a single seek instead of decoding the whole pack.
+//!
+//! ## Reference
+//! 1. Git Pack-Format [Introduce](https://git-scm.com/docs/pack-format)
+//!
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::CrcReader;
+use sha1::{Digest, Sha1};
+
+use crate::errors::GitError;
+use crate::hash::SHA1;
+
+/// Magic number and version of a version 2 index.
+const IDX_HEADER: [u8; 8] = [0xff, b't', b'O', b'c', 0, 0, 0, 2];
+
+/// Offsets from this one on don't fit the offset table and go to the large offset table.
+const LARGE_OFFSET: u64 = 0x8000_0000;
+
+/// An object of an indexed pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdxEntry {
+    pub hash: SHA1,
+    /// Position of the object from the start of the pack.
+    pub offset: u64,
+    /// CRC32 of the object as stored in the pack, its header and compressed data.
+    pub crc32: u32,
+}
+
+/// The objects of a pack sorted by hash, along with the checksum of the pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackIdx {
+    entries: Vec<IdxEntry>,
+    pack_checksum: SHA1,
+}
+
+impl PackIdx {
+    pub fn new(mut entries: Vec<IdxEntry>, pack_checksum: SHA1) -> Self {
+        entries.sort_by_key(|x| x.hash);
+        PackIdx {
+            entries,
+            pack_checksum,
+        }
+    }
+
+    /// Index the pack read from `pack`, given the hash and offset of each of its objects
+    /// as `Pack::decode` reports them. An object spans the bytes up to the next one, or
+    /// up to the trailing checksum for the last one, its CRC32 is computed over them.
+    pub fn build<R: Read + Seek>(
+        pack: &mut R,
+        mut objects: Vec<(SHA1, u64)>,
+    ) -> Result<PackIdx, GitError> {
+        let end = pack.seek(SeekFrom::End(0))?;
+        let trailer = end
+            .checked_sub(20)
+            .filter(|x| *x >= 12)
+            .ok_or_else(|| GitError::InvalidPackFile("pack is too short".to_string()))?;
+        let mut checksum = [0; 20];
+        pack.seek(SeekFrom::Start(trailer))?;
+        pack.read_exact(&mut checksum)?;
+
+        objects.sort_by_key(|(_, offset)| *offset);
+        let mut entries = Vec::with_capacity(objects.len());
+        for (i, &(hash, offset)) in objects.iter().enumerate() {
+            let next = objects.get(i + 1).map_or(trailer, |(_, x)| *x);
+            if offset < 12 || next > trailer || next <= offset {
+                return Err(GitError::InvalidPackFile(format!(
+                    "object {} at invalid offset {}",
+                    hash, offset
+                )));
+            }
+            pack.seek(SeekFrom::Start(offset))?;
+            let mut reader = CrcReader::new(pack.by_ref().take(next - offset));
+            io::copy(&mut reader, &mut io::sink())?;
+            entries.push(IdxEntry {
+                hash,
+                offset,
+                crc32: reader.crc().sum(),
+            });
+        }
+        Ok(PackIdx::new(entries, SHA1(checksum)))
+    }
+
+    /// Read the index stored at `path`.
+    pub fn read(path: &Path) -> Result<PackIdx, GitError> {
+        PackIdx::decode(&fs::read(path)?).map_err(|e| match e {
+            GitError::InvalidIdxFile(reason) => {
+                GitError::InvalidIdxFile(format!("{}: {}", path.display(), reason))
+            }
+            e => e,
+        })
+    }
+
+    /// Write the index to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), GitError> {
+        Ok(fs::write(path, self.encode())?)
+    }
+
+    /// Parse a version 2 index, its trailing checksum is verified.
+    pub fn decode(data: &[u8]) -> Result<PackIdx, GitError> {
+        let invalid = |reason: &str| GitError::InvalidIdxFile(reason.to_string());
+        if data.len() < IDX_HEADER.len() + 256 * 4 + 40 {
+            return Err(invalid("file is too short"));
+        }
+        if data[..IDX_HEADER.len()] != IDX_HEADER {
+            return Err(invalid("not a version 2 index"));
+        }
+        let (content, checksum) = data.split_at(data.len() - 20);
+        if Sha1::digest(content).as_slice() != checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let u32_at = |at: usize| u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+        let fan_out = IDX_HEADER.len();
+        let count = u32_at(fan_out + 255 * 4) as usize;
+        let names = fan_out + 256 * 4;
+        let crcs = names + count * 20;
+        let offsets = crcs + count * 4;
+        let large = offsets + count * 4;
+        if content.len() < large + 20 {
+            return Err(invalid("file is too short"));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let hash = SHA1(
+                data[names + i * 20..names + (i + 1) * 20]
+                    .try_into()
+                    .unwrap(),
+            );
+            let offset = u32_at(offsets + i * 4);
+            let offset = if offset as u64 & LARGE_OFFSET == 0 {
+                offset as u64
+            } else {
+                let at = large + (offset as u64 & !LARGE_OFFSET) as usize * 8;
+                let bytes = content[..content.len() - 20]
+                    .get(at..at + 8)
+                    .ok_or_else(|| invalid("large offset out of range"))?;
+                u64::from_be_bytes(bytes.try_into().unwrap())
+            };
+            entries.push(IdxEntry {
+                hash,
+                offset,
+                crc32: u32_at(crcs + i * 4),
+            });
+        }
+        if entries.windows(2).any(|x| x[0].hash >= x[1].hash) {
+            return Err(invalid("object names aren't sorted"));
+        }
+        let pack_checksum = SHA1(content[content.len() - 20..].try_into().unwrap());
+        Ok(PackIdx {
+            entries,
+            pack_checksum,
+        })
+    }
+
+    /// The version 2 index: the fan-out table, then the object names, their CRC32 and
+    /// offsets, the offsets that don't fit 31 bits, and the pack and index checksums.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut idx = IDX_HEADER.to_vec();
+        for first in 0..=255u8 {
+            let count = self.entries.partition_point(|x| x.hash.0[0] <= first);
+            idx.extend((count as u32).to_be_bytes());
+        }
+        for entry in &self.entries {
+            idx.extend(entry.hash.0);
+        }
+        for entry in &self.entries {
+            idx.extend(entry.crc32.to_be_bytes());
+        }
+        let mut large = vec![];
+        for entry in &self.entries {
+            if entry.offset < LARGE_OFFSET {
+                idx.extend((entry.offset as u32).to_be_bytes());
+            } else {
+                idx.extend((LARGE_OFFSET as u32 | large.len() as u32).to_be_bytes());
+                large.push(entry.offset);
+            }
+        }
+        for offset in large {
+            idx.extend(offset.to_be_bytes());
+        }
+        idx.extend(self.pack_checksum.0);
+        let checksum: [u8; 20] = Sha1::digest(&idx).into();
+        idx.extend(checksum);
+        idx
+    }
+
+    pub fn entries(&self) -> &[IdxEntry] {
+        &self.entries
+    }
+
+    pub fn pack_checksum(&self) -> SHA1 {
+        self.pack_checksum
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, hash: &SHA1) -> Option<&IdxEntry> {
+        self.entries
+            .binary_search_by(|x| x.hash.cmp(hash))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// Position of the object with `hash` in the pack.
+    pub fn offset(&self, hash: &SHA1) -> Option<u64> {
+        self.get(hash).map(|x| x.offset)
+    }
+
+    pub fn contains(&self, hash: &SHA1) -> bool {
+        self.get(hash).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::write::ZlibEncoder;
+    use flate2::{Compression, Crc};
+
+    use super::*;
+    use crate::internal::object::types::ObjectType;
+
+    /// Hash, offset and stored bytes of an object of a pack.
+    type PackedObject = (SHA1, u64, Vec<u8>);
+
+    /// A pack of whole blobs, with each of its objects.
+    fn pack_of(blobs: &[&str]) -> (Vec<u8>, Vec<PackedObject>) {
+        let mut pack = b"PACK".to_vec();
+        pack.extend(2u32.to_be_bytes());
+        pack.extend((blobs.len() as u32).to_be_bytes());
+        let mut objects = vec![];
+        for blob in blobs {
+            // type 3 (blob) and a size below 16 fit the first byte
+            let mut encoder =
+                ZlibEncoder::new(vec![0x30 | blob.len() as u8], Compression::default());
+            encoder.write_all(blob.as_bytes()).unwrap();
+            let data = encoder.finish().unwrap();
+            let hash = SHA1::from_type_and_data(ObjectType::Blob, blob.as_bytes());
+            objects.push((hash, pack.len() as u64, data.clone()));
+            pack.extend(data);
+        }
+        let checksum: [u8; 20] = Sha1::digest(&pack).into();
+        pack.extend(checksum);
+        (pack, objects)
+    }
+
+    #[test]
+    fn test_build_and_decode() {
+        let (pack, objects) = pack_of(&["a\n", "b\n", "hello\n"]);
+        let idx = PackIdx::build(
+            &mut Cursor::new(&pack),
+            objects
+                .iter()
+                .map(|(hash, offset, _)| (*hash, *offset))
+                .collect(),
+        )
+        .unwrap();
+        assert_eq!(idx.len(), 3);
+        assert_eq!(idx.pack_checksum().0, pack[pack.len() - 20..]);
+        for (hash, offset, data) in &objects {
+            let mut crc = Crc::new();
+            crc.update(data);
+            let entry = idx.get(hash).unwrap();
+            assert_eq!((entry.offset, entry.crc32), (*offset, crc.sum()));
+        }
+        assert!(idx.entries().windows(2).all(|x| x[0].hash < x[1].hash));
+        assert!(!idx.contains(&SHA1::default()));
+
+        let encoded = idx.encode();
+        assert_eq!(encoded[..8], IDX_HEADER);
+        assert_eq!(PackIdx::decode(&encoded).unwrap(), idx);
+    }
+
+    #[test]
+    fn test_large_offsets() {
+        let entries: Vec<IdxEntry> = [(1, 12), (2, 0x8000_0000), (3, 0x1_0000_0000), (4, 99)]
+            .into_iter()
+            .map(|(first, offset)| IdxEntry {
+                hash: SHA1([first; 20]),
+                offset,
+                crc32: first as u32,
+            })
+            .collect();
+        let idx = PackIdx::new(entries.clone(), SHA1([9; 20]));
+        let encoded = idx.encode();
+        // two of the offsets are written to the large offset table
+        assert_eq!(encoded.len(), 8 + 256 * 4 + 4 * (20 + 4 + 4) + 2 * 8 + 40);
+        let decoded = PackIdx::decode(&encoded).unwrap();
+        assert_eq!(decoded.entries(), entries);
+        assert_eq!(decoded.offset(&SHA1([3; 20])), Some(0x1_0000_0000));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let (pack, objects) = pack_of(&["a\n"]);
+        let idx = PackIdx::build(&mut Cursor::new(&pack), vec![(objects[0].0, 12)]).unwrap();
+        let mut encoded = idx.encode();
+        encoded[8 + 256 * 4] ^= 1;
+        assert!(matches!(
+            PackIdx::decode(&encoded),
+            Err(GitError::InvalidIdxFile(_))
+        ));
+        assert!(PackIdx::decode(&encoded[..100]).is_err());
+        // the offset of an object beyond the end of the pack
+        assert!(PackIdx::build(&mut Cursor::new(&pack), vec![(objects[0].0, 1000)]).is_err());
+    }
+}
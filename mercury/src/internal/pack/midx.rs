@@ -0,0 +1,368 @@
+//! Multi-pack-index, one index over the objects of many packs, so that finding an object
+//! takes a single binary search however many packs have accumulated.
+//!
+//! The file is made of chunks: the names of the indexed packs (`PNAM`), a fan-out table
+//! (`OIDF`), the sorted object names (`OIDL`), the pack and offset of every object
+//! (`OOFF`) and the offsets that don't fit 31 bits (`LOFF`).
+//!
+//! ## Reference
+//! 1. Git [multi-pack-index](https://git-scm.com/docs/gitformat-pack#_multi_pack_index_midx_files_have_the_following_format)
+//!
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+
+use crate::errors::GitError;
+use crate::hash::SHA1;
+use crate::internal::pack::idx::PackIdx;
+
+/// Name of the multi-pack-index in a pack directory.
+pub const MIDX_FILE_NAME: &str = "multi-pack-index";
+
+const MIDX_SIGNATURE: &[u8; 4] = b"MIDX";
+const MIDX_VERSION: u8 = 1;
+/// Object ids are SHA-1.
+const MIDX_HASH_VERSION: u8 = 1;
+const HEADER_LEN: usize = 12;
+const CHUNK_ROW_LEN: usize = 12;
+
+const CHUNK_PACK_NAMES: &[u8; 4] = b"PNAM";
+const CHUNK_FAN_OUT: &[u8; 4] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8; 4] = b"OIDL";
+const CHUNK_OFFSETS: &[u8; 4] = b"OOFF";
+const CHUNK_LARGE_OFFSETS: &[u8; 4] = b"LOFF";
+
+/// Offsets from this one on go to the large offset chunk.
+const LARGE_OFFSET: u64 = 0x8000_0000;
+
+/// A multi-pack-index, kept as read and searched in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiPackIndex {
+    data: Vec<u8>,
+    packs: Vec<String>,
+    fan_out: usize,
+    oids: usize,
+    offsets: usize,
+    large_offsets: Option<Range<usize>>,
+}
+
+impl MultiPackIndex {
+    /// The multi-pack-index of `packs`, each given by the file name of its `.idx`.
+    /// An object stored in several packs is taken from the first of them listed, so
+    /// the preferred packs, usually the newest ones, come first.
+    pub fn build(packs: &[(String, &PackIdx)]) -> MultiPackIndex {
+        MultiPackIndex::decode(MultiPackIndex::encode(packs))
+            .expect("an encoded multi-pack-index is valid")
+    }
+
+    /// Read the multi-pack-index at `path`.
+    pub fn read(path: &Path) -> Result<MultiPackIndex, GitError> {
+        MultiPackIndex::decode(fs::read(path)?).map_err(|e| match e {
+            GitError::InvalidMidxFile(reason) => {
+                GitError::InvalidMidxFile(format!("{}: {}", path.display(), reason))
+            }
+            e => e,
+        })
+    }
+
+    /// Write the multi-pack-index to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), GitError> {
+        Ok(fs::write(path, &self.data)?)
+    }
+
+    /// The multi-pack-index file of `packs`, see `build`.
+    pub fn encode(packs: &[(String, &PackIdx)]) -> Vec<u8> {
+        let mut names: Vec<&str> = packs.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+
+        // (hash, rank of the pack, id of the pack, offset), the preferred copy first
+        let mut objects = Vec::new();
+        for (rank, (name, idx)) in packs.iter().enumerate() {
+            let id = names.binary_search(&name.as_str()).unwrap() as u32;
+            for entry in idx.entries() {
+                objects.push((entry.hash, rank, id, entry.offset));
+            }
+        }
+        objects.sort_unstable_by_key(|(hash, rank, _, _)| (*hash, *rank));
+        objects.dedup_by_key(|(hash, _, _, _)| *hash);
+
+        let mut pack_names = Vec::new();
+        for name in &names {
+            pack_names.extend(name.as_bytes());
+            pack_names.push(0);
+        }
+        pack_names.resize(pack_names.len().next_multiple_of(4), 0);
+
+        let mut fan_out = Vec::with_capacity(256 * 4);
+        for first in 0..=255u8 {
+            let count = objects.partition_point(|(hash, _, _, _)| hash.0[0] <= first);
+            fan_out.extend((count as u32).to_be_bytes());
+        }
+        let mut oids = Vec::with_capacity(objects.len() * 20);
+        let mut offsets = Vec::with_capacity(objects.len() * 8);
+        let mut large_offsets = Vec::new();
+        for (hash, _, id, offset) in &objects {
+            oids.extend(hash.0);
+            offsets.extend(id.to_be_bytes());
+            if *offset < LARGE_OFFSET {
+                offsets.extend((*offset as u32).to_be_bytes());
+            } else {
+                let index = (large_offsets.len() / 8) as u32;
+                offsets.extend((LARGE_OFFSET as u32 | index).to_be_bytes());
+                large_offsets.extend(offset.to_be_bytes());
+            }
+        }
+
+        let mut chunks = vec![
+            (CHUNK_PACK_NAMES, pack_names),
+            (CHUNK_FAN_OUT, fan_out),
+            (CHUNK_OID_LOOKUP, oids),
+            (CHUNK_OFFSETS, offsets),
+        ];
+        if !large_offsets.is_empty() {
+            chunks.push((CHUNK_LARGE_OFFSETS, large_offsets));
+        }
+
+        let mut midx = MIDX_SIGNATURE.to_vec();
+        midx.extend([MIDX_VERSION, MIDX_HASH_VERSION, chunks.len() as u8, 0]);
+        midx.extend((names.len() as u32).to_be_bytes());
+        let mut offset = (HEADER_LEN + (chunks.len() + 1) * CHUNK_ROW_LEN) as u64;
+        for (id, chunk) in &chunks {
+            midx.extend(*id);
+            midx.extend(offset.to_be_bytes());
+            offset += chunk.len() as u64;
+        }
+        midx.extend([0; 4]);
+        midx.extend(offset.to_be_bytes());
+        for (_, chunk) in chunks {
+            midx.extend(chunk);
+        }
+        let checksum: [u8; 20] = Sha1::digest(&midx).into();
+        midx.extend(checksum);
+        midx
+    }
+
+    /// Parse a multi-pack-index, its trailing checksum is verified. Chunks this
+    /// implementation doesn't know are skipped.
+    pub fn decode(data: Vec<u8>) -> Result<MultiPackIndex, GitError> {
+        let invalid = |reason: &str| GitError::InvalidMidxFile(reason.to_string());
+        if data.len() < HEADER_LEN + CHUNK_ROW_LEN + 20 {
+            return Err(invalid("file is too short"));
+        }
+        if &data[..4] != MIDX_SIGNATURE {
+            return Err(invalid("bad signature"));
+        }
+        if data[4] != MIDX_VERSION || data[5] != MIDX_HASH_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        if data[7] != 0 {
+            return Err(invalid("incremental multi-pack-indexes aren't supported"));
+        }
+        let content_len = data.len() - 20;
+        if Sha1::digest(&data[..content_len]).as_slice() != &data[content_len..] {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let chunk_count = data[6] as usize;
+        let pack_count = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+        let table_end = HEADER_LEN + (chunk_count + 1) * CHUNK_ROW_LEN;
+        if content_len < table_end {
+            return Err(invalid("chunk table is truncated"));
+        }
+        let row = |i: usize| {
+            let at = HEADER_LEN + i * CHUNK_ROW_LEN;
+            let id: [u8; 4] = data[at..at + 4].try_into().unwrap();
+            let offset = u64::from_be_bytes(data[at + 4..at + 12].try_into().unwrap());
+            (id, offset as usize)
+        };
+        let mut chunks = Vec::with_capacity(chunk_count);
+        for i in 0..chunk_count {
+            let (id, start) = row(i);
+            let (_, end) = row(i + 1);
+            if start < table_end || start > end || end > content_len {
+                return Err(invalid("chunk out of range"));
+            }
+            chunks.push((id, start..end));
+        }
+        let chunk = |id: &[u8; 4]| {
+            chunks
+                .iter()
+                .find(|(x, _)| x == id)
+                .map(|(_, range)| range.clone())
+        };
+        let required = |id: &[u8; 4]| chunk(id).ok_or_else(|| invalid("missing chunk"));
+
+        let fan_out = required(CHUNK_FAN_OUT)?;
+        if fan_out.len() != 256 * 4 {
+            return Err(invalid("bad fan-out chunk"));
+        }
+        let count =
+            u32::from_be_bytes(data[fan_out.end - 4..fan_out.end].try_into().unwrap()) as usize;
+        let oids = required(CHUNK_OID_LOOKUP)?;
+        let offsets = required(CHUNK_OFFSETS)?;
+        if oids.len() != count * 20 || offsets.len() != count * 8 {
+            return Err(invalid("object chunks don't match the fan-out"));
+        }
+
+        let packs: Vec<String> = data[required(CHUNK_PACK_NAMES)?]
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .collect();
+        if packs.len() != pack_count {
+            return Err(invalid("pack names don't match the header"));
+        }
+
+        let midx = MultiPackIndex {
+            large_offsets: chunk(CHUNK_LARGE_OFFSETS),
+            fan_out: fan_out.start,
+            oids: oids.start,
+            offsets: offsets.start,
+            packs,
+            data,
+        };
+        for i in 0..count {
+            let (pack, offset) = midx.object_at(i);
+            if pack >= midx.packs.len() || offset.is_none() {
+                return Err(invalid("object offset out of range"));
+            }
+        }
+        Ok(midx)
+    }
+
+    /// File names of the `.idx` of the indexed packs, sorted.
+    pub fn packs(&self) -> &[String] {
+        &self.packs
+    }
+
+    pub fn len(&self) -> usize {
+        self.fan_out_at(255)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `.idx` file name of the pack storing the object with `hash`, and the
+    /// position of the object in that pack.
+    pub fn find(&self, hash: &SHA1) -> Option<(&str, u64)> {
+        let first = hash.0[0] as usize;
+        let mut low = if first == 0 {
+            0
+        } else {
+            self.fan_out_at(first - 1)
+        };
+        let mut high = self.fan_out_at(first);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.oid_at(mid).cmp(&hash.0) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    let (pack, offset) = self.object_at(mid);
+                    return Some((&self.packs[pack], offset?));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn contains(&self, hash: &SHA1) -> bool {
+        self.find(hash).is_some()
+    }
+
+    fn u32_at(&self, at: usize) -> u32 {
+        u32::from_be_bytes(self.data[at..at + 4].try_into().unwrap())
+    }
+
+    fn fan_out_at(&self, first: usize) -> usize {
+        self.u32_at(self.fan_out + first * 4) as usize
+    }
+
+    fn oid_at(&self, i: usize) -> &[u8] {
+        &self.data[self.oids + i * 20..self.oids + (i + 1) * 20]
+    }
+
+    /// Pack id and offset of the `i`-th object, `None` for a large offset that isn't
+    /// in the file.
+    fn object_at(&self, i: usize) -> (usize, Option<u64>) {
+        let at = self.offsets + i * 8;
+        let pack = self.u32_at(at) as usize;
+        let offset = self.u32_at(at + 4) as u64;
+        if offset & LARGE_OFFSET == 0 {
+            return (pack, Some(offset));
+        }
+        let index = (offset & !LARGE_OFFSET) as usize;
+        let offset = self.large_offsets.as_ref().and_then(|range| {
+            let at = range.start + index * 8;
+            (at + 8 <= range.end)
+                .then(|| u64::from_be_bytes(self.data[at..at + 8].try_into().unwrap()))
+        });
+        (pack, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::pack::idx::IdxEntry;
+
+    fn idx(objects: &[(u8, u64)]) -> PackIdx {
+        let entries = objects
+            .iter()
+            .map(|(first, offset)| IdxEntry {
+                hash: SHA1([*first; 20]),
+                offset: *offset,
+                crc32: 0,
+            })
+            .collect();
+        PackIdx::new(entries, SHA1::default())
+    }
+
+    #[test]
+    fn test_build_and_find() {
+        let old = idx(&[(1, 12), (2, 100), (0xff, 200)]);
+        let new = idx(&[(2, 12), (3, 0x1_0000_0000), (4, 0x8000_0000)]);
+        let midx = MultiPackIndex::build(&[
+            ("pack-b.idx".to_string(), &new),
+            ("pack-a.idx".to_string(), &old),
+        ]);
+        assert_eq!(midx.packs(), ["pack-a.idx", "pack-b.idx"]);
+        assert_eq!(midx.len(), 5);
+        assert_eq!(midx.find(&SHA1([1; 20])), Some(("pack-a.idx", 12)));
+        // the object in both packs is taken from the one listed first
+        assert_eq!(midx.find(&SHA1([2; 20])), Some(("pack-b.idx", 12)));
+        assert_eq!(
+            midx.find(&SHA1([3; 20])),
+            Some(("pack-b.idx", 0x1_0000_0000))
+        );
+        assert_eq!(midx.find(&SHA1([4; 20])), Some(("pack-b.idx", 0x8000_0000)));
+        assert_eq!(midx.find(&SHA1([0xff; 20])), Some(("pack-a.idx", 200)));
+        assert!(!midx.contains(&SHA1([0; 20])));
+        assert!(!midx.contains(&SHA1([5; 20])));
+
+        let decoded = MultiPackIndex::decode(midx.data.clone()).unwrap();
+        assert_eq!(decoded, midx);
+        assert!(MultiPackIndex::build(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let pack = idx(&[(1, 12)]);
+        let data = MultiPackIndex::encode(&[("pack-a.idx".to_string(), &pack)]);
+        assert_eq!(&data[..4], b"MIDX");
+        let mut corrupted = data.clone();
+        corrupted[HEADER_LEN + 4] ^= 1;
+        assert!(matches!(
+            MultiPackIndex::decode(corrupted),
+            Err(GitError::InvalidMidxFile(_))
+        ));
+        assert!(MultiPackIndex::decode(data[..20].to_vec()).is_err());
+        let mut version = data;
+        version[4] = 2;
+        assert!(MultiPackIndex::decode(version).is_err());
+    }
+}
@@ -8,6 +8,8 @@ pub mod channel_reader;
 pub mod decode;
 pub mod encode;
 pub mod entry;
+pub mod idx;
+pub mod midx;
 pub mod utils;
 pub mod waitlist;
 pub mod wrapper;
@@ -19,9 +21,13 @@ use threadpool::ThreadPool;
 use crate::hash::SHA1;
 use crate::internal::object::ObjectTrait;
 use crate::internal::pack::cache::Caches;
+use crate::internal::pack::entry::Entry;
 use crate::internal::pack::waitlist::Waitlist;
 
 const DEFAULT_TMP_DIR: &str = "./.cache_temp";
+
+/// Looks up an object a pack refers to without containing it, the delta bases of a thin pack.
+pub type ExternalBase = Arc<dyn Fn(SHA1) -> Option<Entry> + Send + Sync>;
 pub struct Pack {
     pub number: usize,
     pub signature: SHA1,
@@ -32,6 +38,7 @@ pub struct Pack {
     pub mem_limit: Option<usize>,
     pub cache_objs_mem: Arc<AtomicUsize>, // the memory size of CacheObjects in this Pack
     pub clean_tmp: bool,
+    pub external_base: Option<ExternalBase>,
 }
 
 #[cfg(test)]
@@ -9,9 +9,11 @@ use mimalloc::MiMalloc;
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+pub mod diff;
 pub mod internal;
 pub mod hash;
 pub mod errors;
+pub mod tree_diff;
 pub mod utils;
 
 #[cfg(test)]
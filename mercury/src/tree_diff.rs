@@ -0,0 +1,265 @@
+//! Difference of two trees as per-path changes.
+//!
+//! `TreeDiff` walks both trees side by side and yields one change at a time, a sub tree
+//! is only loaded when the walk gets to it and its hash differs on both sides. Trees are
+//! loaded through a `TreeLoader`, so the same walk serves every object store.
+
+use std::collections::{btree_map, BTreeMap};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use crate::errors::GitError;
+use crate::hash::SHA1;
+use crate::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+/// Where the sub trees of a diff are loaded from.
+pub trait TreeLoader {
+    fn load_tree(&self, id: &SHA1) -> impl Future<Output = Result<Tree, GitError>> + Send;
+}
+
+/// Change of a single path, `None` means the path doesn't exist on that side.
+///
+/// Diffs only report non-directory paths, a change of a directory entry replaces or
+/// removes the whole subtree at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeChange {
+    pub path: PathBuf,
+    pub old: Option<TreeItem>,
+    pub new: Option<TreeItem>,
+}
+
+impl TreeChange {
+    /// The change that undoes this one.
+    pub fn reverse(&self) -> TreeChange {
+        TreeChange {
+            path: self.path.clone(),
+            old: self.new.clone(),
+            new: self.old.clone(),
+        }
+    }
+
+    /// Move the change under `prefix`, used when a tree is applied to a sub directory.
+    pub fn with_prefix(&self, prefix: &Path) -> TreeChange {
+        TreeChange {
+            path: prefix.join(&self.path),
+            ..self.clone()
+        }
+    }
+}
+
+/// Whether two entries have the same mode and content, their names aren't compared.
+pub fn same_entry(a: Option<&TreeItem>, b: Option<&TreeItem>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => a.mode == b.mode && a.id == b.id,
+        _ => false,
+    }
+}
+
+/// Entries of one directory on both sides, by name.
+struct Level {
+    path: PathBuf,
+    entries: btree_map::IntoIter<String, (Option<TreeItem>, Option<TreeItem>)>,
+    /// change of a non-directory entry at `path`, reported after the changes below it
+    last: Option<TreeChange>,
+}
+
+impl Level {
+    fn new(
+        path: PathBuf,
+        old: Option<&Tree>,
+        new: Option<&Tree>,
+        last: Option<TreeChange>,
+    ) -> Self {
+        let mut entries: BTreeMap<String, (Option<TreeItem>, Option<TreeItem>)> = BTreeMap::new();
+        for item in old.iter().flat_map(|x| &x.tree_items) {
+            entries.entry(item.name.clone()).or_default().0 = Some(item.clone());
+        }
+        for item in new.iter().flat_map(|x| &x.tree_items) {
+            entries.entry(item.name.clone()).or_default().1 = Some(item.clone());
+        }
+        Level {
+            path,
+            entries: entries.into_iter(),
+            last,
+        }
+    }
+}
+
+/// Changes from one tree to another in path order, `None` standing for an empty tree.
+/// The paths are relative to the compared trees and prefixed with the given base.
+pub struct TreeDiff<'a, L> {
+    loader: &'a L,
+    levels: Vec<Level>,
+}
+
+impl<'a, L: TreeLoader + Sync> TreeDiff<'a, L> {
+    pub fn new(loader: &'a L, old: Option<&Tree>, new: Option<&Tree>, base: PathBuf) -> Self {
+        TreeDiff {
+            loader,
+            levels: vec![Level::new(base, old, new, None)],
+        }
+    }
+
+    /// The next change, `None` once both trees have been walked.
+    pub async fn next_change(&mut self) -> Option<Result<TreeChange, GitError>> {
+        loop {
+            let level = self.levels.last_mut()?;
+            let Some((name, (old, new))) = level.entries.next() else {
+                match self.levels.pop().and_then(|x| x.last) {
+                    Some(change) => return Some(Ok(change)),
+                    None => continue,
+                }
+            };
+            if same_entry(old.as_ref(), new.as_ref()) {
+                continue;
+            }
+            let path = level.path.join(name);
+
+            let old_leaf = old.clone().filter(|x| x.mode != TreeItemMode::Tree);
+            let new_leaf = new.clone().filter(|x| x.mode != TreeItemMode::Tree);
+            let last = (!same_entry(old_leaf.as_ref(), new_leaf.as_ref())).then(|| TreeChange {
+                path: path.clone(),
+                old: old_leaf,
+                new: new_leaf,
+            });
+            let old_tree = old.filter(|x| x.mode == TreeItemMode::Tree);
+            let new_tree = new.filter(|x| x.mode == TreeItemMode::Tree);
+            if old_tree.is_none() && new_tree.is_none() {
+                match last {
+                    Some(change) => return Some(Ok(change)),
+                    None => continue,
+                }
+            }
+            let old_tree = match old_tree {
+                Some(item) => match self.loader.load_tree(&item.id).await {
+                    Ok(tree) => Some(tree),
+                    Err(e) => return Some(Err(e)),
+                },
+                None => None,
+            };
+            let new_tree = match new_tree {
+                Some(item) => match self.loader.load_tree(&item.id).await {
+                    Ok(tree) => Some(tree),
+                    Err(e) => return Some(Err(e)),
+                },
+                None => None,
+            };
+            self.levels
+                .push(Level::new(path, old_tree.as_ref(), new_tree.as_ref(), last));
+        }
+    }
+}
+
+/// Every change from `old` to `new`, see `TreeDiff`.
+pub async fn diff_trees<L: TreeLoader + Sync>(
+    loader: &L,
+    old: Option<&Tree>,
+    new: Option<&Tree>,
+    base: PathBuf,
+) -> Result<Vec<TreeChange>, GitError> {
+    let mut diff = TreeDiff::new(loader, old, new, base);
+    let mut changes = Vec::new();
+    while let Some(change) = diff.next_change().await {
+        changes.push(change?);
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    struct MemoryLoader(HashMap<SHA1, Tree>);
+
+    impl TreeLoader for MemoryLoader {
+        async fn load_tree(&self, id: &SHA1) -> Result<Tree, GitError> {
+            self.0
+                .get(id)
+                .cloned()
+                .ok_or_else(|| GitError::ObjectNotFound(id.to_string()))
+        }
+    }
+
+    fn blob(name: &str, data: &str) -> TreeItem {
+        TreeItem::new(
+            TreeItemMode::Blob,
+            SHA1::new(data.as_bytes()),
+            name.to_owned(),
+        )
+    }
+
+    fn tree(loader: &mut MemoryLoader, items: Vec<TreeItem>) -> Tree {
+        let tree = Tree::from_tree_items(items).unwrap();
+        loader.0.insert(tree.id, tree.clone());
+        tree
+    }
+
+    fn dir(name: &str, tree: &Tree) -> TreeItem {
+        TreeItem::new(TreeItemMode::Tree, tree.id, name.to_owned())
+    }
+
+    #[tokio::test]
+    async fn test_diff_trees() {
+        let mut loader = MemoryLoader(HashMap::new());
+        let same = tree(&mut loader, vec![blob("lib.rs", "lib")]);
+        let x = tree(&mut loader, vec![blob("y.rs", "y")]);
+        let old_src = tree(&mut loader, vec![blob("a.rs", "a"), blob("b.rs", "b")]);
+        let new_src = tree(&mut loader, vec![blob("a.rs", "A"), blob("c.rs", "c")]);
+        let old = tree(
+            &mut loader,
+            vec![dir("same", &same), dir("src", &old_src), blob("x", "file")],
+        );
+        let new = tree(
+            &mut loader,
+            vec![dir("same", &same), dir("src", &new_src), dir("x", &x)],
+        );
+        // a sub tree with the same hash on both sides isn't loaded
+        loader.0.remove(&same.id);
+
+        let changes = diff_trees(&loader, Some(&old), Some(&new), PathBuf::from("/"))
+            .await
+            .unwrap();
+        let paths: Vec<&Path> = changes.iter().map(|x| x.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("/src/a.rs"),
+                Path::new("/src/b.rs"),
+                Path::new("/src/c.rs"),
+                Path::new("/x/y.rs"),
+                Path::new("/x"),
+            ]
+        );
+        assert!(changes[1].new.is_none());
+        assert!(changes[2].old.is_none());
+        // the file replaced by a directory is reported after the files below it
+        assert_eq!(changes[4].old, Some(blob("x", "file")));
+        assert!(changes[4].new.is_none());
+
+        let added = diff_trees(&loader, None, Some(&old_src), PathBuf::from("src"))
+            .await
+            .unwrap();
+        assert_eq!(added.len(), 2);
+        assert!(added.iter().all(|x| x.old.is_none()));
+        assert!(diff_trees(&loader, Some(&new), Some(&new), PathBuf::new())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_diff_trees_missing_tree() {
+        let mut loader = MemoryLoader(HashMap::new());
+        let src = tree(&mut loader, vec![blob("a.rs", "a")]);
+        let new = tree(&mut loader, vec![dir("src", &src)]);
+        loader.0.remove(&src.id);
+        let mut diff = TreeDiff::new(&loader, None, Some(&new), PathBuf::from("/"));
+        assert!(matches!(
+            diff.next_change().await,
+            Some(Err(GitError::ObjectNotFound(_)))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use taurus::event::access_log::AccessLogEvent;
+
+use crate::api::oauth::{model::LoginUser, AuthRedirect};
+
+/// Middleware recording an access log for every API request,
+/// apply it with `axum::middleware::from_fn_with_state` so the session user can be resolved.
+pub async fn access_log(
+    user: Result<LoginUser, AuthRedirect>,
+    matched_path: Option<MatchedPath>,
+    path_params: Option<RawPathParams>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = matched_path
+        .map(|x| x.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let path = req.uri().query().and_then(path_from_query);
+    let mr_link = path_params.and_then(|params| {
+        params
+            .iter()
+            .find(|(key, _)| *key == "link")
+            .map(|(_, value)| value.to_owned())
+    });
+
+    let res = next.run(req).await;
+
+    AccessLogEvent {
+        method,
+        route,
+        actor: user.ok().map(|x| x.name),
+        status: res.status().as_u16(),
+        latency_ms: start.elapsed().as_millis() as u64,
+        path,
+        mr_link,
+    }
+    .notify();
+    res
+}
+
+/// Most content endpoints take the monorepo path from the `path` query parameter.
+fn path_from_query(query: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key == "path" {
+            Some(percent_decode(value))
+        } else {
+            None
+        }
+    })
+}
+
+fn percent_decode(value: &str) -> String {
+    let hex = |b: u8| (b as char).to_digit(16).map(|x| x as u8);
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                if let (Some(h), Some(l)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    decoded.push(h << 4 | l);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            b'+' => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::path_from_query;
+
+    #[test]
+    fn test_path_from_query() {
+        assert_eq!(
+            path_from_query("refs=main&path=%2Fproject%2Fmega"),
+            Some("/project/mega".to_owned())
+        );
+        assert_eq!(path_from_query("path=/doc/a+b"), Some("/doc/a b".to_owned()));
+        assert_eq!(path_from_query("path=%2"), Some("%2".to_owned()));
+        assert_eq!(path_from_query("refs=main"), None);
+    }
+}
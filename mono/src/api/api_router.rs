@@ -1,40 +1,99 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::PathBuf;
 
 use axum::{
     body::Body,
-    extract::{Path, Query, State},
+    extract::{OriginalUri, Path, Query, RawQuery, State},
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
-use http::StatusCode;
+use http::{header, HeaderMap, StatusCode};
 
+use callisto::db_enums::{AclPermission, AuditAction, TrafficKind};
 use ceres::{
     api_service::ApiHandler,
     model::{
-        create_file::CreateFileInfo,
-        query::{BlobContentQuery, CodePreviewQuery},
+        blame::{BlameHunk, BlameQuery},
+        blob::{
+            sniff_content_type, BlobBatchItem, BlobBatchQuery, MAX_BATCH_BLOBS, MAX_RAW_BLOB_SIZE,
+        },
+        branch::{BranchInfo, BranchPath, BranchQuery, CreateBranchRequest, DeleteBranchRequest},
+        commit::{CherryPickRequest, CommitDetail, CommitResult, RevertRequest},
+        create_file::{
+            BatchCommitInfo, CreateFileInfo, DeleteEntryInfo, MoveEntryInfo, UpdateFileInfo,
+        },
+        diff::{CommitDiff, Compare, CompareQuery, DiffQuery, DiffStat},
+        query::{ArchiveQuery, BlobContentQuery, CodePreviewQuery, TreeListOptions},
+        search::{SearchMatch, SearchQuery},
         tree::{LatestCommitInfo, TreeBriefItem, TreeCommitItem},
     },
 };
-use common::{errors::ProtocolError, model::CommonResult};
-use taurus::event::api_request::{ApiRequestEvent, ApiType};
+use common::{
+    model::{CommonResult, CursorPage, CursorParams, Page},
+    path::MonoPath,
+};
+use mercury::errors::GitError;
+use mercury::internal::object::tree::TreeItemMode;
+use saturn::ActionEnum;
+use serde_json::json;
+use taurus::event::{
+    api_request::{ApiRequestEvent, ApiType},
+    audit::AuditEvent,
+    live_update::{LiveUpdateEvent, LiveUpdateKind},
+    traffic::TrafficEvent,
+};
 
+use crate::api::audit::audit_router;
+use crate::api::checks::checks_router;
 use crate::api::error::ApiError;
+use crate::api::events::events_router;
+use crate::api::gc::gc_router;
+use crate::api::http_cache::{ByteRange, CacheInfo};
 use crate::api::issue::issue_router;
 use crate::api::mr::mr_router;
+use crate::api::notification::notification_router;
+use crate::api::oauth::model::LoginUser;
+use crate::api::openapi::CODE_TAG;
+use crate::api::preview::{self, BlobPreview, PreviewKind};
+use crate::api::queue::queue_router;
+use crate::api::readme::{self, DirectoryPage};
+use crate::api::release::release_router;
+use crate::api::stats::stats_router;
+use crate::api::tombstone::tombstone_router;
+use crate::api::traffic::traffic_router;
 use crate::api::user::user_router;
+use crate::api::util;
 use crate::api::MonoApiServiceState;
 
 pub fn routers() -> Router<MonoApiServiceState> {
     let router = Router::new()
         .route("/status", get(life_cycle_check))
         .route("/create-file", post(create_file))
+        .route("/update-file", post(update_file))
+        .route("/delete-entry", post(delete_entry))
+        .route("/move-entry", post(move_entry))
+        .route("/batch-commit", post(batch_commit))
+        .route("/cherry-pick", post(cherry_pick))
+        .route("/revert", post(revert_commit))
         .route("/latest-commit", get(get_latest_commit))
+        .route("/history", get(get_file_history))
+        .route("/blame", get(get_blame))
+        .route("/diff", get(get_commit_diff))
+        .route("/commit/{oid}", get(get_commit_detail))
+        .route("/compare", get(get_compare))
+        .route("/branches", get(list_branches).post(create_branch))
+        .route("/branches/delete", post(delete_branch))
+        .route("/archive", get(get_archive))
+        .route("/search", get(search_code))
         .route("/tree/commit-info", get(get_tree_commit_info))
         .route("/tree/path-can-clone", get(path_can_be_cloned))
+        .route("/tree/readme", get(get_directory_page))
         .route("/tree", get(get_tree_info))
         .route("/blob", get(get_blob_string))
+        .route("/raw/{*path}", get(get_raw_blob))
+        .route("/blob/batch", post(get_blob_batch))
+        .route("/blob/preview", get(get_blob_preview))
         .route("/file/blob/{object_id}", get(get_blob_file))
         .route("/file/tree", get(get_tree_file));
     Router::new()
@@ -42,21 +101,253 @@ pub fn routers() -> Router<MonoApiServiceState> {
         .merge(mr_router::routers())
         .merge(user_router::routers())
         .merge(issue_router::routers())
+        .merge(events_router::routers())
+        .merge(traffic_router::routers())
+        .merge(stats_router::routers())
+        .merge(checks_router::routers())
+        .merge(release_router::routers())
+        .merge(gc_router::routers())
+        .merge(tombstone_router::routers())
+        .merge(queue_router::routers())
+        .merge(audit_router::routers())
+        .merge(notification_router::routers())
 }
 
+#[utoipa::path(
+    get,
+    path = "/blob",
+    params(BlobContentQuery),
+    responses(
+        (status = 200, body = CommonResult<BlobBatchItem>),
+        (status = 304, description = "Not modified since the `ETag` sent"),
+    ),
+    tag = CODE_TAG
+)]
+/// Content of a file, one over the inline limit is answered with a link to the raw
+/// endpoint instead.
 async fn get_blob_string(
+    user: Option<LoginUser>,
     Query(query): Query<BlobContentQuery>,
+    OriginalUri(uri): OriginalUri,
     state: State<MonoApiServiceState>,
-) -> Result<Json<CommonResult<String>>, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     ApiRequestEvent::notify(ApiType::Blob, &state.0.context.config);
-    let res = state
-        .api_handler(query.path.clone().into())
-        .await?
-        .get_blob_as_string(query.path.into())
-        .await;
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        let res = CommonResult::<BlobBatchItem>::failed("permission denied");
+        return Ok(Json(res).into_response());
+    }
+    let handler = state.api_handler(query.path.clone().into()).await?;
+    let path = PathBuf::from(&query.path);
 
-    let res = match res {
-        Ok(data) => CommonResult::success(data),
+    let item = match handler.get_item_by_path(&path).await {
+        Ok(item) => item,
+        Err(err) => {
+            let res = CommonResult::<BlobBatchItem>::failed(&err.to_string());
+            return Ok(Json(res).into_response());
+        }
+    };
+    let cache = match &item {
+        Some(item) => {
+            let hash = item.id.to_string();
+            let commit = handler.get_blob_relate_commit(&hash).await.unwrap_or(None);
+            Some(CacheInfo::revalidate(
+                &hash,
+                commit.map(|c| c.committer.timestamp),
+            ))
+        }
+        None => None,
+    };
+    if let Some(cache) = &cache {
+        if cache.is_fresh(&headers) {
+            return Ok(cache.not_modified());
+        }
+    }
+
+    let max_inline = state.context.config.monorepo.max_inline_blob_size;
+    let raw_url = format!(
+        "{}/raw/{}",
+        uri.path().trim_end_matches("/blob"),
+        query.path.trim_start_matches('/')
+    );
+    let res = match item {
+        Some(item) => {
+            let hash = item.id.to_string();
+            let mut sizes = HashMap::new();
+            handler
+                .add_blob_sizes_to_map(&mut sizes, vec![hash.clone()])
+                .await?;
+            match sizes.get(&hash).copied().filter(|x| *x > max_inline) {
+                Some(size) => {
+                    CommonResult::success(Some(BlobBatchItem::too_large(hash, size, raw_url)))
+                }
+                // sizes of files in import repos aren't recorded
+                None => match handler.get_raw_blob_by_hash(&hash).await {
+                    Ok(Some(model)) => {
+                        let data = model.data.unwrap_or_default();
+                        let item = if data.len() > max_inline {
+                            BlobBatchItem::too_large(hash, data.len(), raw_url)
+                        } else {
+                            TrafficEvent::notify(TrafficKind::RawDownload, &query.path);
+                            BlobBatchItem::new(hash, data, false)
+                        };
+                        CommonResult::success(Some(item))
+                    }
+                    Ok(None) => CommonResult::success(None),
+                    Err(err) => CommonResult::failed(&err.to_string()),
+                },
+            }
+        }
+        None => CommonResult::success(None),
+    };
+    let mut res = Json(res).into_response();
+    if let Some(cache) = cache {
+        cache.apply(res.headers_mut());
+    }
+    Ok(res)
+}
+
+/// Bytes of a file with a sniffed content type, a single `Range` is honoured.
+#[utoipa::path(
+    get,
+    path = "/raw/{path}",
+    params(("path" = String, Path, description = "Path of the file")),
+    responses(
+        (status = 200, description = "Bytes of the file", content_type = "application/octet-stream"),
+        (status = 206, description = "The range asked for"),
+        (status = 304, description = "Not modified since the `ETag` sent"),
+        (status = 404, description = "No such file"),
+    ),
+    tag = CODE_TAG
+)]
+async fn get_raw_blob(
+    user: Option<LoginUser>,
+    Path(path): Path<String>,
+    state: State<MonoApiServiceState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    ApiRequestEvent::notify(ApiType::RawBlob, &state.0.context.config);
+    let path = PathBuf::from(format!("/{}", path.trim_start_matches('/')));
+    if !util::can_read(user.as_ref(), &path.to_string_lossy(), &state).await? {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "permission denied"));
+    }
+    let handler = state.api_handler(path.clone()).await?;
+    let item = match handler.get_item_by_path(&path).await {
+        Ok(Some(item)) if !matches!(item.mode, TreeItemMode::Tree | TreeItemMode::Commit) => item,
+        _ => return Ok(plain_response(StatusCode::NOT_FOUND, "file not found")),
+    };
+    let hash = item.id.to_string();
+
+    let mut sizes = HashMap::new();
+    handler
+        .add_blob_sizes_to_map(&mut sizes, vec![hash.clone()])
+        .await?;
+    if sizes.get(&hash).is_some_and(|x| *x > MAX_RAW_BLOB_SIZE) {
+        return Ok(plain_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "file is too large to be served raw",
+        ));
+    }
+    let commit = handler.get_blob_relate_commit(&hash).await.unwrap_or(None);
+    let cache = CacheInfo::revalidate(&hash, commit.map(|c| c.committer.timestamp));
+    if cache.is_fresh(&headers) {
+        return Ok(cache.not_modified());
+    }
+    let data = match handler.get_raw_blob_by_hash(&hash).await {
+        Ok(Some(model)) => model.data.unwrap_or_default(),
+        _ => return Ok(plain_response(StatusCode::NOT_FOUND, "file not found")),
+    };
+    TrafficEvent::notify(TrafficKind::RawDownload, &path.to_string_lossy());
+
+    let len = data.len();
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, sniff_content_type(&data))
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::ACCEPT_RANGES, "bytes");
+    let mut res = match ByteRange::from_headers(&headers, len) {
+        ByteRange::Full => builder.body(Body::from(data)).unwrap(),
+        ByteRange::Partial(range) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+            )
+            .body(Body::from(data[range].to_vec()))
+            .unwrap(),
+        ByteRange::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty())
+            .unwrap(),
+    };
+    cache.apply(res.headers_mut());
+    Ok(res)
+}
+
+fn plain_response(status: StatusCode, message: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message.to_owned()))
+        .unwrap()
+}
+
+#[utoipa::path(
+    post,
+    path = "/blob/batch",
+    request_body = BlobBatchQuery,
+    responses(
+        (status = 200, body = CommonResult<Vec<BlobBatchItem>>),
+    ),
+    tag = CODE_TAG
+)]
+/// Blobs over the inline limit aren't read, they come with a link to their raw content.
+async fn get_blob_batch(
+    user: Option<LoginUser>,
+    OriginalUri(uri): OriginalUri,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<BlobBatchQuery>,
+) -> Result<Json<CommonResult<Vec<BlobBatchItem>>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::BlobBatch, &state.0.context.config);
+    if json.hashes.len() > MAX_BATCH_BLOBS {
+        return Ok(Json(CommonResult::failed(&format!(
+            "too many hashes in one request, the limit is {}",
+            MAX_BATCH_BLOBS
+        ))));
+    }
+    let hashes: HashSet<String> = json.hashes.iter().cloned().collect();
+    let readable = util::readable_objects(user.as_ref(), hashes.clone(), &state).await?;
+    if readable.len() < hashes.len() {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let max_inline = state.context.config.monorepo.max_inline_blob_size;
+    let api_base = uri.path().trim_end_matches("/blob/batch");
+    let handler = state.monorepo();
+    let mut sizes = HashMap::new();
+    handler
+        .add_blob_sizes_to_map(&mut sizes, json.hashes.clone())
+        .await?;
+    let (large, hashes): (Vec<String>, Vec<String>) = json
+        .hashes
+        .into_iter()
+        .partition(|x| sizes.get(x).is_some_and(|size| *size > max_inline));
+    let res = match handler
+        .get_blobs_by_hashes(hashes, json.metadata_only)
+        .await
+    {
+        Ok(mut data) => {
+            for item in data.iter_mut().filter(|x| x.size > max_inline) {
+                *item = BlobBatchItem::too_large(
+                    item.oid.clone(),
+                    item.size,
+                    format!("{}/file/blob/{}", api_base, item.oid),
+                );
+            }
+            data.extend(large.into_iter().map(|oid| {
+                let raw_url = format!("{}/file/blob/{}", api_base, oid);
+                BlobBatchItem::too_large(oid.clone(), sizes[&oid], raw_url)
+            }));
+            CommonResult::success(Some(data))
+        }
         Err(err) => CommonResult::failed(&err.to_string()),
     };
     Ok(Json(res))
@@ -66,62 +357,855 @@ async fn life_cycle_check() -> Result<impl IntoResponse, ApiError> {
     Ok(Json("http ready"))
 }
 
+#[utoipa::path(
+    post,
+    path = "/create-file",
+    request_body = CreateFileInfo,
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = CODE_TAG
+)]
 async fn create_file(
+    user: LoginUser,
     state: State<MonoApiServiceState>,
     Json(json): Json<CreateFileInfo>,
-) -> Result<Json<CommonResult<String>>, ApiError> {
+) -> Result<(StatusCode, Json<CommonResult<String>>), ApiError> {
     ApiRequestEvent::notify(ApiType::CreateFile, &state.0.context.config);
+    if util::check_permissions(
+        &user.name,
+        &json.path,
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            Json(CommonResult::failed("permission denied")),
+        ));
+    }
     let res = state
         .api_handler(json.path.clone().into())
         .await?
         .create_monorepo_file(json.clone())
         .await;
-    let res = match res {
-        Ok(_) => CommonResult::success(None),
+    let (status, res) = match res {
+        Ok(_) => {
+            LiveUpdateEvent::notify(
+                LiveUpdateKind::RefUpdate,
+                &json.path,
+                None,
+                json!({ "reason": "create_file", "name": json.name }),
+            );
+            AuditEvent::notify(
+                &user.name,
+                AuditAction::CreateFile,
+                &format!("{}/{}", json.path.trim_end_matches('/'), json.name),
+                json!({ "is_directory": json.is_directory }),
+            )
+            .await;
+            (StatusCode::OK, CommonResult::success(None))
+        }
+        Err(err) => {
+            let status = match err {
+                GitError::EntryExists(_) | GitError::RefConflict(_) => StatusCode::CONFLICT,
+                GitError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::OK,
+            };
+            (status, CommonResult::failed(&err.to_string()))
+        }
+    };
+    Ok((status, Json(res)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/update-file",
+    request_body = UpdateFileInfo,
+    responses(
+        (status = 200, body = CommonResult<CommitResult>),
+    ),
+    tag = CODE_TAG
+)]
+async fn update_file(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<UpdateFileInfo>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::UpdateFile, &state.0.context.config);
+    let dir = match MonoPath::parse(&json.path).map(|x| x.parent()) {
+        Ok(Some(dir)) => dir.to_string(),
+        _ => return Ok(Json(CommonResult::failed("path must point to a file"))),
+    };
+    if util::check_permissions(
+        &user.name,
+        &dir,
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+
+    let path = json.path.clone();
+    let res = match state.monorepo_as(&user).update_monorepo_file(json).await {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                LiveUpdateEvent::notify(
+                    LiveUpdateKind::RefUpdate,
+                    &dir,
+                    None,
+                    json!({ "reason": "update_file", "path": path, "commit": data.commit_id }),
+                );
+                AuditEvent::notify(
+                    &user.name,
+                    AuditAction::UpdateFile,
+                    &path,
+                    json!({ "commit": data.commit_id }),
+                )
+                .await;
+            }
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/delete-entry",
+    request_body = DeleteEntryInfo,
+    responses(
+        (status = 200, body = CommonResult<CommitResult>),
+    ),
+    tag = CODE_TAG
+)]
+async fn delete_entry(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<DeleteEntryInfo>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::DeleteEntry, &state.0.context.config);
+    let dir = match MonoPath::parse(&json.path).map(|x| x.parent()) {
+        Ok(Some(dir)) => dir.to_string(),
+        _ => return Ok(Json(CommonResult::failed("invalid path"))),
+    };
+    if util::check_permissions(
+        &user.name,
+        &dir,
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+
+    let path = json.path.clone();
+    let res = match state.monorepo_as(&user).delete_monorepo_entry(json).await {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                LiveUpdateEvent::notify(
+                    LiveUpdateKind::RefUpdate,
+                    &dir,
+                    None,
+                    json!({ "reason": "delete_entry", "path": path, "commit": data.commit_id }),
+                );
+                AuditEvent::notify(
+                    &user.name,
+                    AuditAction::DeleteEntry,
+                    &path,
+                    json!({ "commit": data.commit_id }),
+                )
+                .await;
+            }
+            CommonResult::success(Some(data))
+        }
         Err(err) => CommonResult::failed(&err.to_string()),
     };
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    post,
+    path = "/move-entry",
+    request_body = MoveEntryInfo,
+    responses(
+        (status = 200, body = CommonResult<CommitResult>),
+    ),
+    tag = CODE_TAG
+)]
+async fn move_entry(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<MoveEntryInfo>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::MoveEntry, &state.0.context.config);
+    // the entry is removed from one directory and added to the other
+    let mut dirs = vec![];
+    for path in [&json.from, &json.to] {
+        match MonoPath::parse(path).map(|x| x.parent()) {
+            Ok(Some(dir)) => dirs.push(dir.to_string()),
+            _ => return Ok(Json(CommonResult::failed("invalid path"))),
+        }
+    }
+    for dir in &dirs {
+        if util::check_permissions(
+            &user.name,
+            dir,
+            ActionEnum::ApproveMergeRequest,
+            state.clone(),
+        )
+        .await
+        .is_err()
+        {
+            return Ok(Json(CommonResult::failed(&format!(
+                "permission denied on {}",
+                dir
+            ))));
+        }
+    }
+
+    let (from, to) = (json.from.clone(), json.to.clone());
+    let res = match state.monorepo_as(&user).move_monorepo_entry(json).await {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                for dir in &dirs {
+                    LiveUpdateEvent::notify(
+                        LiveUpdateKind::RefUpdate,
+                        dir,
+                        None,
+                        json!({
+                            "reason": "move_entry",
+                            "from": from,
+                            "to": to,
+                            "commit": data.commit_id
+                        }),
+                    );
+                }
+                AuditEvent::notify(
+                    &user.name,
+                    AuditAction::MoveEntry,
+                    &from,
+                    json!({ "to": to, "commit": data.commit_id }),
+                )
+                .await;
+            }
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/batch-commit",
+    request_body = BatchCommitInfo,
+    responses(
+        (status = 200, body = CommonResult<CommitResult>),
+    ),
+    tag = CODE_TAG
+)]
+async fn batch_commit(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<BatchCommitInfo>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::BatchCommit, &state.0.context.config);
+    let mut dirs = BTreeSet::new();
+    for op in &json.operations {
+        match MonoPath::parse(op.path()).map(|x| x.parent()) {
+            Ok(Some(dir)) => dirs.insert(dir.to_string()),
+            _ => return Ok(Json(CommonResult::failed("invalid path"))),
+        };
+    }
+    for dir in &dirs {
+        if util::check_permissions(
+            &user.name,
+            dir,
+            ActionEnum::ApproveMergeRequest,
+            state.clone(),
+        )
+        .await
+        .is_err()
+        {
+            return Ok(Json(CommonResult::failed(&format!(
+                "permission denied on {}",
+                dir
+            ))));
+        }
+    }
+
+    let res = match state.monorepo_as(&user).batch_commit(json).await {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                for dir in &dirs {
+                    LiveUpdateEvent::notify(
+                        LiveUpdateKind::RefUpdate,
+                        dir,
+                        None,
+                        json!({ "reason": "batch_commit", "commit": data.commit_id }),
+                    );
+                    AuditEvent::notify(
+                        &user.name,
+                        AuditAction::BatchCommit,
+                        dir,
+                        json!({ "commit": data.commit_id }),
+                    )
+                    .await;
+                }
+            }
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn cherry_pick(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<CherryPickRequest>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::CherryPick, &state.0.context.config);
+    let target_path = match (&json.target_path, &json.mr_link) {
+        (Some(path), _) => path.clone(),
+        (None, Some(link)) => match state.mr_stg().get_mr(link).await.unwrap() {
+            Some(mr) => mr.path,
+            None => return Ok(Json(CommonResult::failed("merge request not found"))),
+        },
+        (None, None) => String::from("/"),
+    };
+    if util::check_permissions(
+        &user.name,
+        &target_path,
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+
+    let picked = json!({ "picked": json.commit, "mr_link": json.mr_link });
+    let res = match state.monorepo_as(&user).cherry_pick(json).await {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                LiveUpdateEvent::notify(
+                    LiveUpdateKind::RefUpdate,
+                    &target_path,
+                    None,
+                    json!({ "reason": "cherry_pick", "commit": data.commit_id }),
+                );
+                let mut detail = picked;
+                detail["commit"] = json!(data.commit_id);
+                AuditEvent::notify(&user.name, AuditAction::CherryPick, &target_path, detail).await;
+            }
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn revert_commit(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<RevertRequest>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::Revert, &state.0.context.config);
+    let (commit, changes) = match state.monorepo().diff_with_parent(&json.commit).await {
+        Ok(res) => res,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    // the user needs permission on every directory the revert touches
+    let dirs: HashSet<String> = changes
+        .iter()
+        .filter_map(|x| x.path.parent())
+        .map(|x| x.to_string_lossy().into_owned())
+        .collect();
+    for dir in dirs {
+        if util::check_permissions(
+            &user.name,
+            &dir,
+            ActionEnum::ApproveMergeRequest,
+            state.clone(),
+        )
+        .await
+        .is_err()
+        {
+            return Ok(Json(CommonResult::failed(&format!(
+                "permission denied on {}",
+                dir
+            ))));
+        }
+    }
+
+    let res = match state
+        .monorepo_as(&user)
+        .revert_commit(&commit, &changes)
+        .await
+    {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                LiveUpdateEvent::notify(
+                    LiveUpdateKind::RefUpdate,
+                    "/",
+                    None,
+                    json!({ "reason": "revert", "commit": data.commit_id }),
+                );
+                AuditEvent::notify(
+                    &user.name,
+                    AuditAction::Revert,
+                    "/",
+                    json!({ "reverted": json.commit, "commit": data.commit_id }),
+                )
+                .await;
+            }
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/latest-commit",
+    params(CodePreviewQuery),
+    responses(
+        (status = 200, body = LatestCommitInfo),
+    ),
+    tag = CODE_TAG
+)]
 async fn get_latest_commit(
+    user: Option<LoginUser>,
     Query(query): Query<CodePreviewQuery>,
     state: State<MonoApiServiceState>,
-) -> Result<Json<LatestCommitInfo>, ApiError> {
+) -> Result<Response, ApiError> {
     ApiRequestEvent::notify(ApiType::LastestCommit, &state.0.context.config);
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "permission denied"));
+    }
     let res = state
         .api_handler(query.path.clone().into())
         .await?
         .get_latest_commit(query.path.into())
         .await?;
+    Ok(Json(res).into_response())
+}
+
+/// Commits that changed a file or directory, newest first.
+async fn get_file_history(
+    user: Option<LoginUser>,
+    Query(query): Query<BlobContentQuery>,
+    Query(cursor): Query<CursorParams>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<CursorPage<LatestCommitInfo>>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::FileHistory, &state.0.context.config);
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let res = match state
+        .monorepo()
+        .get_file_history(&path, cursor.cursor.clone(), cursor.limit() as usize)
+        .await
+    {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// The commit that last changed every line of a file.
+async fn get_blame(
+    user: Option<LoginUser>,
+    Query(query): Query<BlameQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<BlameHunk>>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::Blame, &state.0.context.config);
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let res = match state
+        .api_handler(path.to_path_buf())
+        .await?
+        .get_blame(path.to_path_buf(), query.refs)
+        .await
+    {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
     Ok(Json(res))
 }
 
+/// Unified diff of every file changed between two commits.
+async fn get_commit_diff(
+    user: Option<LoginUser>,
+    Query(query): Query<DiffQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<CommitDiff>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::CommitDiff, &state.0.context.config);
+    let acl = util::path_acl(user.as_ref(), &state).await?;
+    let res = match state.monorepo().commit_diff(query.from, query.to).await {
+        Ok(mut data) => {
+            data.files
+                .retain(|x| util::shows_file(&acl, &x.path, x.renamed_from.as_deref()));
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Signatures, message, parents and changed files of a single commit.
+async fn get_commit_detail(
+    user: Option<LoginUser>,
+    Path(oid): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<CommitDetail>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::CommitDetail, &state.0.context.config);
+    let acl = util::path_acl(user.as_ref(), &state).await?;
+    let res = match state.monorepo().commit_detail(&oid).await {
+        Ok(mut data) => {
+            data.files
+                .retain(|x| util::shows_file(&acl, &x.path, x.renamed_from.as_deref()));
+            // the stat only covers the files listed
+            data.stat = DiffStat {
+                files_changed: data.files.len(),
+                additions: data.files.iter().map(|x| x.additions).sum(),
+                deletions: data.files.iter().map(|x| x.deletions).sum(),
+            };
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Commits ahead and behind and the file diff between two commits or directory states,
+/// e.g. to preview a merge request before it's pushed.
+async fn get_compare(
+    user: Option<LoginUser>,
+    Query(query): Query<CompareQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Compare>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::Compare, &state.0.context.config);
+    let acl = util::path_acl(user.as_ref(), &state).await?;
+    let res = match state.monorepo().compare(&query.base, &query.head).await {
+        Ok(mut data) => {
+            data.files
+                .retain(|x| util::shows_file(&acl, &x.path, x.renamed_from.as_deref()));
+            data.stat = DiffStat::from_files(&data.files);
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn list_branches(
+    Query(query): Query<BranchQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<BranchInfo>>>, ApiError> {
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let res = match state.monorepo().list_branches(&path).await {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Create a named branch of a directory, as `/project/foo#feature-x`.
+async fn create_branch(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<CreateBranchRequest>,
+) -> Result<(StatusCode, Json<CommonResult<BranchInfo>>), ApiError> {
+    let branch = match BranchPath::parse(&json.branch) {
+        Ok(branch) => branch,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, Json(CommonResult::failed(&err)))),
+    };
+    if util::check_permissions(
+        &user.name,
+        branch.path.as_str(),
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            Json(CommonResult::failed("permission denied")),
+        ));
+    }
+    let (status, res) = match state.monorepo().create_branch(&branch, json.from).await {
+        Ok(data) => {
+            AuditEvent::notify(
+                &user.name,
+                AuditAction::CreateBranch,
+                branch.path.as_str(),
+                json!({ "branch": data.name, "commit": data.commit_id }),
+            )
+            .await;
+            (StatusCode::OK, CommonResult::success(Some(data)))
+        }
+        Err(err) => (
+            branch_error_status(&err),
+            CommonResult::failed(&err.to_string()),
+        ),
+    };
+    Ok((status, Json(res)))
+}
+
+async fn delete_branch(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<DeleteBranchRequest>,
+) -> Result<(StatusCode, Json<CommonResult<String>>), ApiError> {
+    let branch = match BranchPath::parse(&json.branch) {
+        Ok(branch) => branch,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, Json(CommonResult::failed(&err)))),
+    };
+    if util::check_permissions(
+        &user.name,
+        branch.path.as_str(),
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            Json(CommonResult::failed("permission denied")),
+        ));
+    }
+    let (status, res) = match state.monorepo().delete_branch(&branch).await {
+        Ok(_) => {
+            AuditEvent::notify(
+                &user.name,
+                AuditAction::DeleteBranch,
+                branch.path.as_str(),
+                json!({ "branch": branch.branch }),
+            )
+            .await;
+            (StatusCode::OK, CommonResult::success(None))
+        }
+        Err(err) => (
+            branch_error_status(&err),
+            CommonResult::failed(&err.to_string()),
+        ),
+    };
+    Ok((status, Json(res)))
+}
+
+fn branch_error_status(err: &GitError) -> StatusCode {
+    match err {
+        GitError::EntryExists(_) => StatusCode::CONFLICT,
+        GitError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+        GitError::InvalidPathError(_) | GitError::ObjectNotFound(_) => StatusCode::NOT_FOUND,
+        _ => StatusCode::OK,
+    }
+}
+
+/// Code search over the files of the latest commit, paged with a cursor.
+async fn search_code(
+    user: Option<LoginUser>,
+    Query(query): Query<SearchQuery>,
+    Query(cursor): Query<CursorParams>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<CursorPage<SearchMatch>>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::Search, &state.0.context.config);
+    let path = match MonoPath::parse(query.path.as_deref().unwrap_or("/")) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let acl = util::path_acl(user.as_ref(), &state).await?;
+    if !acl.allows(path.as_str(), AclPermission::Read) {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let res = match state
+        .monorepo()
+        .search_code(
+            &query.q,
+            &path,
+            cursor.cursor.clone(),
+            cursor.limit() as usize,
+        )
+        .await
+    {
+        Ok(mut data) => {
+            // matches in directories below `path` the user can't read are left out
+            data.items
+                .retain(|x| acl.allows(&x.path, AclPermission::Read));
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Archive of a directory, written while the blobs are read.
+async fn get_archive(
+    user: Option<LoginUser>,
+    Query(query): Query<ArchiveQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Response, ApiError> {
+    ApiRequestEvent::notify(ApiType::Archive, &state.0.context.config);
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(err.to_string()))
+                .unwrap())
+        }
+    };
+    if !util::can_read(user.as_ref(), path.as_str(), &state).await? {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "permission denied"));
+    }
+    match state
+        .monorepo()
+        .archive(&path, query.refs, query.format)
+        .await
+    {
+        Ok(stream) => {
+            let file_name = format!(
+                "attachment; filename=\"{}.{}\"",
+                path.name().unwrap_or("monorepo"),
+                query.format.extension()
+            );
+            Ok(Response::builder()
+                .header("Content-Type", query.format.content_type())
+                .header("Content-Disposition", file_name)
+                .body(Body::from_stream(stream))
+                .unwrap())
+        }
+        Err(err) => {
+            let status = match err {
+                GitError::ObjectNotFound(_) | GitError::InvalidPathError(_) => {
+                    StatusCode::NOT_FOUND
+                }
+                _ => StatusCode::BAD_REQUEST,
+            };
+            Ok(Response::builder()
+                .status(status)
+                .body(Body::from(err.to_string()))
+                .unwrap())
+        }
+    }
+}
+
+/// Entries of a tree, tagged by the tree hash and the query as the sorting, filter and
+/// page change the listing.
+#[utoipa::path(
+    get,
+    path = "/tree",
+    params(CodePreviewQuery),
+    responses(
+        (status = 200, body = CommonResult<Page<TreeBriefItem>>),
+        (status = 304, description = "Not modified since the `ETag` sent"),
+    ),
+    tag = CODE_TAG
+)]
 async fn get_tree_info(
+    user: Option<LoginUser>,
     Query(query): Query<CodePreviewQuery>,
+    RawQuery(raw_query): RawQuery,
     state: State<MonoApiServiceState>,
-) -> Result<Json<CommonResult<Vec<TreeBriefItem>>>, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     ApiRequestEvent::notify(ApiType::TreeInfo, &state.0.context.config);
-    let res = state
-        .api_handler(query.path.clone().into())
-        .await?
-        .get_tree_info(query.path.into())
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        let res = CommonResult::<Page<TreeBriefItem>>::failed("permission denied");
+        return Ok(Json(res).into_response());
+    }
+    let handler = state.api_handler(query.path.clone().into()).await?;
+    let cache = match handler
+        .search_tree_by_path(std::path::Path::new(&query.path))
+        .await
+    {
+        Ok(Some(tree)) => {
+            let hash = tree.id.to_string();
+            let commit = handler.get_tree_relate_commit(&hash).await.ok();
+            Some(
+                CacheInfo::revalidate(&hash, commit.map(|c| c.committer.timestamp))
+                    .with_query(raw_query.as_deref().unwrap_or_default()),
+            )
+        }
+        _ => None,
+    };
+    if let Some(cache) = &cache {
+        if cache.is_fresh(&headers) {
+            return Ok(cache.not_modified());
+        }
+    }
+
+    let res = handler
+        .get_tree_info(
+            query.path.clone().into(),
+            &query.list_options(),
+            query.pagination(),
+        )
         .await;
     let res = match res {
         Ok(data) => CommonResult::success(Some(data)),
-        Err(err) => CommonResult::failed(&err.to_string()),
+        Err(err) => CommonResult::<Page<TreeBriefItem>>::failed(&err.to_string()),
     };
-    Ok(Json(res))
+    let mut res = Json(res).into_response();
+    if let Some(cache) = cache {
+        cache.apply(res.headers_mut());
+    }
+    Ok(res)
 }
 
+#[utoipa::path(
+    get,
+    path = "/tree/commit-info",
+    params(CodePreviewQuery),
+    responses(
+        (status = 200, body = CommonResult<Page<TreeCommitItem>>),
+    ),
+    tag = CODE_TAG
+)]
 async fn get_tree_commit_info(
+    user: Option<LoginUser>,
     Query(query): Query<CodePreviewQuery>,
     state: State<MonoApiServiceState>,
-) -> Result<Json<CommonResult<Vec<TreeCommitItem>>>, ProtocolError> {
+) -> Result<Json<CommonResult<Page<TreeCommitItem>>>, ApiError> {
     ApiRequestEvent::notify(ApiType::CommitInfo, &state.0.context.config);
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
     let res = state
         .api_handler(query.path.clone().into())
         .await?
-        .get_tree_commit_info(query.path.into())
+        .get_tree_commit_info(
+            query.path.clone().into(),
+            &query.list_options(),
+            query.pagination(),
+        )
         .await;
     let res = match res {
         Ok(data) => CommonResult::success(Some(data)),
@@ -130,20 +1214,125 @@ async fn get_tree_commit_info(
     Ok(Json(res))
 }
 
+async fn get_blob_preview(
+    user: Option<LoginUser>,
+    Query(query): Query<BlobContentQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<BlobPreview>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::BlobPreview, &state.0.context.config);
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let path = PathBuf::from(&query.path);
+    let Some(kind) = PreviewKind::from_path(&path) else {
+        return Ok(Json(CommonResult::failed("no preview for this file type")));
+    };
+    let handler = state.api_handler(query.path.clone().into()).await?;
+    let item = match handler.get_item_by_path(&path).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Ok(Json(CommonResult::failed("file not found"))),
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let data = match handler.get_raw_blob_by_hash(&item.id.to_string()).await {
+        Ok(Some(model)) => model.data.unwrap_or_default(),
+        _ => return Ok(Json(CommonResult::failed("blob not found"))),
+    };
+    let res = match preview::render(kind, &data) {
+        Ok(data) => CommonResult::success(Some(data)),
+        Err(err) => CommonResult::failed(&err),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/file/blob/{object_id}",
+    params(("object_id" = String, Path, description = "Hash of the blob")),
+    responses(
+        (status = 200, description = "Bytes of the blob", content_type = "application/octet-stream"),
+        (status = 304, description = "Not modified since the `ETag` sent"),
+        (status = 404, description = "No such blob"),
+    ),
+    tag = CODE_TAG
+)]
+/// Entries of a directory with its README, markdown rendered to HTML.
+async fn get_directory_page(
+    user: Option<LoginUser>,
+    Query(query): Query<BlobContentQuery>,
+    OriginalUri(uri): OriginalUri,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<DirectoryPage>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::TreeInfo, &state.0.context.config);
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let handler = state.api_handler(query.path.clone().into()).await?;
+    let path = PathBuf::from(&query.path);
+    let tree = match handler.search_tree_by_path(&path).await {
+        Ok(Some(tree)) => tree,
+        Ok(None) => return Ok(Json(CommonResult::failed("directory not found"))),
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let items = match handler
+        .get_tree_info(path, &TreeListOptions::default(), None)
+        .await
+    {
+        Ok(page) => page.items,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let readme = match readme::find_readme(&tree.tree_items) {
+        Some((item, format)) => match handler.get_raw_blob_by_hash(&item.id.to_string()).await {
+            Ok(Some(model)) => {
+                // links are rewritten to the endpoints next to this one
+                let api_base = uri.path().trim_end_matches("/tree/readme");
+                Some(readme::render_readme(
+                    &item.name,
+                    &query.path,
+                    format,
+                    &model.data.unwrap_or_default(),
+                    api_base,
+                ))
+            }
+            _ => None,
+        },
+        None => None,
+    };
+    Ok(Json(CommonResult::success(Some(DirectoryPage {
+        items,
+        readme,
+    }))))
+}
+
 pub async fn get_blob_file(
+    user: Option<LoginUser>,
     state: State<MonoApiServiceState>,
     Path(oid): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Response, ApiError> {
+    let readable =
+        util::readable_objects(user.as_ref(), HashSet::from([oid.clone()]), &state).await?;
+    if readable.is_empty() {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "permission denied"));
+    }
     let api_handler = state.monorepo();
 
-    let result = api_handler.get_raw_blob_by_hash(&oid).await.unwrap();
+    let result = api_handler.get_raw_blob_by_hash(&oid).await?;
     let file_name = format!("inline; filename=\"{}\"", oid);
     match result {
-        Some(model) => Ok(Response::builder()
-            .header("Content-Type", "application/octet-stream")
-            .header("Content-Disposition", file_name)
-            .body(Body::from(model.data.unwrap()))
-            .unwrap()),
+        Some(model) => {
+            // only a blob that exists is cached, a 404 may turn into one
+            let cache = CacheInfo::immutable(&oid);
+            if cache.is_fresh(&headers) {
+                return Ok(cache.not_modified());
+            }
+            let mut res = Response::builder()
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Disposition", file_name)
+                .body(Body::from(model.data.unwrap_or_default()))
+                .unwrap();
+            cache.apply(res.headers_mut());
+            Ok(res)
+        }
         None => Ok({
             Response::builder()
                 .status(StatusCode::NOT_FOUND)
@@ -154,23 +1343,48 @@ pub async fn get_blob_file(
 }
 
 pub async fn get_tree_file(
+    user: Option<LoginUser>,
     state: State<MonoApiServiceState>,
     Query(query): Query<CodePreviewQuery>,
+    headers: HeaderMap,
 ) -> Result<Response, ApiError> {
-    let res = state
-        .api_handler(query.path.clone().into())
-        .await?
-        .get_tree_as_data(std::path::Path::new(&query.path))
+    if !util::can_read(user.as_ref(), &query.path, &state).await? {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "permission denied"));
+    }
+    let handler = state.api_handler(query.path.clone().into()).await?;
+    let tree = handler
+        .search_tree_by_path(std::path::Path::new(&query.path))
         .await;
 
     let file_name = format!("inline; filename=\"{}\"", "");
-    match res {
-        Ok(data) => Ok(Response::builder()
-            .header("Content-Type", "application/octet-stream")
-            .header("Content-Disposition", file_name)
-            .body(Body::from(data))
-            .unwrap()),
-        Err(_) => Ok({
+    match tree {
+        Ok(Some(tree)) => {
+            let hash = tree.id.to_string();
+            let commit = handler.get_tree_relate_commit(&hash).await?;
+            let cache = CacheInfo::revalidate(&hash, Some(commit.committer.timestamp));
+            if cache.is_fresh(&headers) {
+                return Ok(cache.not_modified());
+            }
+            match handler
+                .get_tree_as_data(std::path::Path::new(&query.path))
+                .await
+            {
+                Ok(data) => {
+                    let mut res = Response::builder()
+                        .header("Content-Type", "application/octet-stream")
+                        .header("Content-Disposition", file_name)
+                        .body(Body::from(data))
+                        .unwrap();
+                    cache.apply(res.headers_mut());
+                    Ok(res)
+                }
+                Err(_) => Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .unwrap()),
+            }
+        }
+        _ => Ok({
             Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(Body::empty())
@@ -200,3 +1414,117 @@ async fn path_can_be_cloned(
     };
     Ok(Json(CommonResult::success(Some(res))))
 }
+
+#[cfg(test)]
+mod test {
+    use axum::extract::{Path, Query, State};
+    use http::{HeaderMap, StatusCode};
+
+    use callisto::db_enums::{AclPermission, AclPrincipal};
+    use callisto::mega_path_acl;
+    use ceres::api_service::ApiHandler;
+    use ceres::model::{create_file::CreateFileInfo, diff::DiffQuery};
+    use common::{model::CommonOptions, utils::generate_id};
+    use jupiter::context::Context;
+    use mercury::internal::object::blob::Blob;
+
+    use super::{get_blob_file, get_commit_diff};
+    use crate::api::oauth::model::LoginUser;
+    use crate::api::MonoApiServiceState;
+
+    fn login(name: &str) -> LoginUser {
+        LoginUser {
+            user_id: 0,
+            name: name.to_owned(),
+            avatar_url: String::new(),
+            email: format!("{}@example.com", name),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restricted_paths_are_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = Context::sqlite(dir.path()).await;
+        context
+            .services
+            .mono_storage
+            .init_monorepo(&context.config.monorepo)
+            .await;
+        let state = MonoApiServiceState {
+            context: context.clone(),
+            common: CommonOptions {
+                host: "127.0.0.1".to_owned(),
+            },
+            oauth_client: None,
+            store: None,
+        };
+        let content = "top secret";
+        for (is_directory, name, path) in [
+            (true, "secret", "/project"),
+            (false, "key.txt", "/project/secret"),
+        ] {
+            state
+                .monorepo()
+                .create_monorepo_file(CreateFileInfo {
+                    is_directory,
+                    name: name.to_owned(),
+                    path: path.to_owned(),
+                    content: (!is_directory).then(|| content.to_owned()),
+                })
+                .await
+                .unwrap();
+        }
+        context
+            .acl_stg()
+            .save_acl(mega_path_acl::Model {
+                id: generate_id(),
+                path: "/project/secret".to_owned(),
+                principal_type: AclPrincipal::User,
+                principal: "bob".to_owned(),
+                permission: AclPermission::Read,
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+            .await
+            .unwrap();
+        let head = context
+            .services
+            .mono_storage
+            .get_ref("/")
+            .await
+            .unwrap()
+            .unwrap()
+            .ref_commit_hash;
+        let oid = Blob::from_content(content).id.to_string();
+
+        for (user, visible) in [(login("alice"), false), (login("bob"), true)] {
+            let query = DiffQuery {
+                from: None,
+                to: head.clone(),
+            };
+            let diff = get_commit_diff(Some(user.clone()), Query(query), State(state.clone()))
+                .await
+                .unwrap();
+            let files = diff.0.data.unwrap().files;
+            assert_eq!(
+                files.iter().any(|x| x.path.ends_with("secret/key.txt")),
+                visible
+            );
+
+            let res = get_blob_file(
+                Some(user),
+                State(state.clone()),
+                Path(oid.clone()),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+            let status = if visible {
+                StatusCode::OK
+            } else {
+                StatusCode::FORBIDDEN
+            };
+            assert_eq!(res.status(), status);
+        }
+    }
+}
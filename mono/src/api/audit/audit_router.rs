@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+
+use common::model::{CommonResult, Page};
+
+use crate::api::audit::{AuditItem, AuditQuery};
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new().route("/admin/audit", get(list_audit_log))
+}
+
+/// Changes recorded in the audit log, the latest first.
+async fn list_audit_log(
+    user: LoginUser,
+    Query(query): Query<AuditQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Page<AuditItem>>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let pagination = query.pagination();
+    let res = match state
+        .context
+        .audit_stg()
+        .get_logs(&query.filter(), pagination)
+        .await
+    {
+        Ok((items, total)) => {
+            CommonResult::success(Some(Page::new(items, total, &pagination).map(Into::into)))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use callisto::audit_log;
+use callisto::db_enums::AuditAction;
+use common::model::Pagination;
+use jupiter::storage::audit_storage::AuditFilter;
+
+pub mod audit_router;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    /// only changes on this path or below it
+    pub path: Option<String>,
+    /// unix time, changes made at or after it
+    pub since: Option<i64>,
+    /// unix time, changes made before it
+    pub until: Option<i64>,
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+impl AuditQuery {
+    pub fn pagination(&self) -> Pagination {
+        let default = Pagination::default();
+        Pagination {
+            page: self.page.unwrap_or(default.page),
+            per_page: self.per_page.unwrap_or(default.per_page),
+        }
+        .normalized()
+    }
+
+    pub fn filter(&self) -> AuditFilter {
+        AuditFilter {
+            actor: self.actor.clone(),
+            action: self.action,
+            target: self.path.clone(),
+            since: self.since.and_then(from_timestamp),
+            until: self.until.and_then(from_timestamp),
+        }
+    }
+}
+
+fn from_timestamp(secs: i64) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp(secs, 0).map(|x| x.naive_utc())
+}
+
+/// A change recorded in the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditItem {
+    pub id: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    pub target: String,
+    pub detail: Value,
+    pub created_at: i64,
+}
+
+impl From<audit_log::Model> for AuditItem {
+    fn from(value: audit_log::Model) -> Self {
+        Self {
+            id: value.id,
+            actor: value.actor,
+            action: value.action,
+            target: value.target,
+            detail: serde_json::from_str(&value.detail).unwrap_or(Value::Null),
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+
+use callisto::check_runs;
+use callisto::db_enums::CheckStatus;
+use common::{model::CommonResult, utils::generate_id};
+
+use crate::api::checks::{
+    build_annotations, is_commit_id, load_check_runs, notify_check_run, resolve_status,
+    validate_name, validate_summary, CheckRunItem, CommitStatusInput, CreateCheckRun,
+    UpdateCheckRun,
+};
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new()
+        .route("/checks", post(create_check_run))
+        .route("/checks/{id}/update", post(update_check_run))
+        .route("/checks/commit/{commit_id}", get(list_check_runs))
+        .route("/statuses/{commit_id}", post(create_commit_status))
+}
+
+/// Report a check run on a commit, e.g. from CI or the secret scanner.
+async fn create_check_run(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<CreateCheckRun>,
+) -> Result<Json<CommonResult<i64>>, ApiError> {
+    if !is_commit_id(&payload.commit_id) {
+        return Ok(Json(CommonResult::failed("invalid commit id")));
+    }
+    if let Err(err) = validate_name(&payload.name).and(validate_summary(payload.summary.as_deref()))
+    {
+        return Ok(Json(CommonResult::failed(&err)));
+    }
+    let (status, conclusion) = match resolve_status(
+        payload.status.unwrap_or(CheckStatus::Queued),
+        payload.conclusion,
+    ) {
+        Ok(res) => res,
+        Err(err) => return Ok(Json(CommonResult::failed(&err))),
+    };
+    let id = generate_id();
+    let annotations = match build_annotations(id, payload.annotations) {
+        Ok(annotations) => annotations,
+        Err(err) => return Ok(Json(CommonResult::failed(&err))),
+    };
+    let now = Utc::now().naive_utc();
+    let run = check_runs::Model {
+        id,
+        commit_id: payload.commit_id,
+        name: payload.name,
+        status,
+        conclusion,
+        title: payload.title,
+        summary: payload.summary,
+        details_url: payload.details_url,
+        creator_id: user.user_id,
+        created_at: now,
+        updated_at: now,
+        completed_at: (status == CheckStatus::Completed).then_some(now),
+    };
+    let stg = state.check_stg();
+    let res = match stg.save_check_run(run).await {
+        Ok(run) => match stg.save_annotations(annotations).await {
+            Ok(_) => {
+                notify_check_run(&state.mr_stg(), &run).await;
+                CommonResult::success(Some(run.id))
+            }
+            Err(err) => CommonResult::failed(&err.to_string()),
+        },
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Move a check run forward and append annotations, only its creator can do so.
+async fn update_check_run(
+    user: LoginUser,
+    Path(id): Path<i64>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<UpdateCheckRun>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let stg = state.check_stg();
+    let Some(mut run) = stg.get_check_run(id).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("check run not found")));
+    };
+    if run.creator_id != user.user_id {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    if let Err(err) = validate_summary(payload.summary.as_deref()) {
+        return Ok(Json(CommonResult::failed(&err)));
+    }
+    let was_completed = run.status == CheckStatus::Completed;
+    (run.status, run.conclusion) = match resolve_status(
+        payload.status.unwrap_or(run.status),
+        payload.conclusion.or(run.conclusion),
+    ) {
+        Ok(res) => res,
+        Err(err) => return Ok(Json(CommonResult::failed(&err))),
+    };
+    let annotations = match build_annotations(run.id, payload.annotations) {
+        Ok(annotations) => annotations,
+        Err(err) => return Ok(Json(CommonResult::failed(&err))),
+    };
+    if !was_completed && run.status == CheckStatus::Completed {
+        run.completed_at = Some(Utc::now().naive_utc());
+    }
+    if payload.title.is_some() {
+        run.title = payload.title;
+    }
+    if payload.summary.is_some() {
+        run.summary = payload.summary;
+    }
+    if payload.details_url.is_some() {
+        run.details_url = payload.details_url;
+    }
+    let res = match stg.update_check_run(run).await {
+        Ok(run) => match stg.save_annotations(annotations).await {
+            Ok(_) => {
+                notify_check_run(&state.mr_stg(), &run).await;
+                CommonResult::success(None)
+            }
+            Err(err) => CommonResult::failed(&err.to_string()),
+        },
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Set the status of `context` on a commit, the latest status of a context replaces the
+/// previous one.
+async fn create_commit_status(
+    user: LoginUser,
+    Path(commit_id): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<CommitStatusInput>,
+) -> Result<Json<CommonResult<i64>>, ApiError> {
+    if !is_commit_id(&commit_id) {
+        return Ok(Json(CommonResult::failed("invalid commit id")));
+    }
+    if let Err(err) = validate_name(&payload.context) {
+        return Ok(Json(CommonResult::failed(&err)));
+    }
+    let (status, conclusion) = payload.state.to_check();
+    let stg = state.check_stg();
+    let now = Utc::now().naive_utc();
+    let existing = stg
+        .get_check_runs_by_commit(&commit_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|x| x.name == payload.context);
+    let res = match existing {
+        Some(mut run) => {
+            if run.creator_id != user.user_id {
+                return Ok(Json(CommonResult::failed("permission denied")));
+            }
+            run.status = status;
+            run.conclusion = conclusion;
+            run.title = payload.description;
+            run.details_url = payload.target_url;
+            run.completed_at = (status == CheckStatus::Completed).then_some(now);
+            stg.update_check_run(run).await
+        }
+        None => {
+            stg.save_check_run(check_runs::Model {
+                id: generate_id(),
+                commit_id,
+                name: payload.context,
+                status,
+                conclusion,
+                title: payload.description,
+                summary: None,
+                details_url: payload.target_url,
+                creator_id: user.user_id,
+                created_at: now,
+                updated_at: now,
+                completed_at: (status == CheckStatus::Completed).then_some(now),
+            })
+            .await
+        }
+    };
+    let res = match res {
+        Ok(run) => {
+            notify_check_run(&state.mr_stg(), &run).await;
+            CommonResult::success(Some(run.id))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn list_check_runs(
+    Path(commit_id): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<CheckRunItem>>>, ApiError> {
+    if !is_commit_id(&commit_id) {
+        return Ok(Json(CommonResult::failed("invalid commit id")));
+    }
+    let res = match load_check_runs(&state.check_stg(), &commit_id).await {
+        Ok(runs) => CommonResult::success(Some(runs)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use callisto::db_enums::{AnnotationLevel, CheckConclusion, CheckStatus};
+use callisto::{check_annotations, check_runs};
+use common::errors::MegaError;
+use common::path::MonoPath;
+use common::utils::generate_id;
+use jupiter::storage::check_storage::CheckStorage;
+use jupiter::storage::mr_storage::MrStorage;
+use taurus::event::live_update::{LiveUpdateEvent, LiveUpdateKind};
+
+pub mod checks_router;
+
+/// Annotations accepted by a single create or update request.
+pub const MAX_ANNOTATIONS_PER_REQUEST: usize = 50;
+const MAX_NAME_LEN: usize = 100;
+const MAX_SUMMARY_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCheckRun {
+    pub commit_id: String,
+    pub name: String,
+    pub status: Option<CheckStatus>,
+    pub conclusion: Option<CheckConclusion>,
+    pub title: Option<String>,
+    /// markdown
+    pub summary: Option<String>,
+    pub details_url: Option<String>,
+    #[serde(default)]
+    pub annotations: Vec<AnnotationInput>,
+}
+
+/// Missing fields are left unchanged, annotations are appended to the existing ones.
+#[derive(Debug, Deserialize)]
+pub struct UpdateCheckRun {
+    pub status: Option<CheckStatus>,
+    pub conclusion: Option<CheckConclusion>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub details_url: Option<String>,
+    #[serde(default)]
+    pub annotations: Vec<AnnotationInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationInput {
+    pub path: String,
+    pub start_line: i32,
+    /// same as `start_line` when missing
+    pub end_line: Option<i32>,
+    pub level: AnnotationLevel,
+    pub title: Option<String>,
+    pub message: String,
+}
+
+/// A commit status as sent by simple CI integrations, it is kept as the check run named
+/// after `context`.
+#[derive(Debug, Deserialize)]
+pub struct CommitStatusInput {
+    pub state: CommitState,
+    pub context: String,
+    pub target_url: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitState {
+    Pending,
+    Success,
+    Failure,
+    Error,
+}
+
+impl CommitState {
+    pub fn to_check(self) -> (CheckStatus, Option<CheckConclusion>) {
+        match self {
+            CommitState::Pending => (CheckStatus::InProgress, None),
+            CommitState::Success => (CheckStatus::Completed, Some(CheckConclusion::Success)),
+            CommitState::Failure | CommitState::Error => {
+                (CheckStatus::Completed, Some(CheckConclusion::Failure))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CheckRunItem {
+    pub id: i64,
+    pub commit_id: String,
+    pub name: String,
+    #[schema(value_type = String)]
+    pub status: CheckStatus,
+    #[schema(value_type = Option<String>)]
+    pub conclusion: Option<CheckConclusion>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub details_url: Option<String>,
+    pub creator_id: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub completed_at: Option<i64>,
+    pub annotations: Vec<CheckAnnotation>,
+}
+
+impl From<check_runs::Model> for CheckRunItem {
+    fn from(value: check_runs::Model) -> Self {
+        Self {
+            id: value.id,
+            commit_id: value.commit_id,
+            name: value.name,
+            status: value.status,
+            conclusion: value.conclusion,
+            title: value.title,
+            summary: value.summary,
+            details_url: value.details_url,
+            creator_id: value.creator_id,
+            created_at: value.created_at.and_utc().timestamp(),
+            updated_at: value.updated_at.and_utc().timestamp(),
+            completed_at: value.completed_at.map(|dt| dt.and_utc().timestamp()),
+            annotations: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CheckAnnotation {
+    pub path: String,
+    pub start_line: i32,
+    pub end_line: i32,
+    #[schema(value_type = String)]
+    pub level: AnnotationLevel,
+    pub title: Option<String>,
+    pub message: String,
+}
+
+impl From<check_annotations::Model> for CheckAnnotation {
+    fn from(value: check_annotations::Model) -> Self {
+        Self {
+            path: value.path,
+            start_line: value.start_line,
+            end_line: value.end_line,
+            level: value.level,
+            title: value.title,
+            message: value.message,
+        }
+    }
+}
+
+pub fn is_commit_id(value: &str) -> bool {
+    value.len() == 40
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(format!(
+            "check name must be between 1 and {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_summary(summary: Option<&str>) -> Result<(), String> {
+    match summary {
+        Some(summary) if summary.len() > MAX_SUMMARY_LEN => {
+            Err(format!("summary is longer than {} bytes", MAX_SUMMARY_LEN))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A conclusion implies a completed run, and a completed run needs a conclusion.
+pub fn resolve_status(
+    status: CheckStatus,
+    conclusion: Option<CheckConclusion>,
+) -> Result<(CheckStatus, Option<CheckConclusion>), String> {
+    match (status, conclusion) {
+        (_, Some(conclusion)) => Ok((CheckStatus::Completed, Some(conclusion))),
+        (CheckStatus::Completed, None) => {
+            Err("a completed check run must have a conclusion".to_owned())
+        }
+        (status, None) => Ok((status, None)),
+    }
+}
+
+/// Check line ranges and paths, and build the rows for `check_run_id`.
+pub fn build_annotations(
+    check_run_id: i64,
+    inputs: Vec<AnnotationInput>,
+) -> Result<Vec<check_annotations::Model>, String> {
+    if inputs.len() > MAX_ANNOTATIONS_PER_REQUEST {
+        return Err(format!(
+            "at most {} annotations can be sent per request",
+            MAX_ANNOTATIONS_PER_REQUEST
+        ));
+    }
+    inputs
+        .into_iter()
+        .map(|input| {
+            let path = MonoPath::parse(&input.path).map_err(|err| err.to_string())?;
+            if path.is_root() {
+                return Err("annotation path must point to a file".to_owned());
+            }
+            let end_line = input.end_line.unwrap_or(input.start_line);
+            if input.start_line < 1 || end_line < input.start_line {
+                return Err(format!(
+                    "invalid line range {}-{} for {}",
+                    input.start_line, end_line, path
+                ));
+            }
+            if input.message.trim().is_empty() {
+                return Err(format!("annotation message for {} is empty", path));
+            }
+            Ok(check_annotations::Model {
+                id: generate_id(),
+                check_run_id,
+                path: path.to_string(),
+                start_line: input.start_line,
+                end_line,
+                level: input.level,
+                title: input.title,
+                message: input.message,
+            })
+        })
+        .collect()
+}
+
+/// Check runs of a commit together with their annotations.
+pub async fn load_check_runs(
+    storage: &CheckStorage,
+    commit_id: &str,
+) -> Result<Vec<CheckRunItem>, MegaError> {
+    let runs = storage.get_check_runs_by_commit(commit_id).await?;
+    if runs.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut annotations: HashMap<i64, Vec<CheckAnnotation>> = HashMap::new();
+    for annotation in storage
+        .get_annotations(runs.iter().map(|x| x.id).collect())
+        .await?
+    {
+        annotations
+            .entry(annotation.check_run_id)
+            .or_default()
+            .push(annotation.into());
+    }
+    Ok(runs
+        .into_iter()
+        .map(|run| {
+            let id = run.id;
+            let mut item: CheckRunItem = run.into();
+            item.annotations = annotations.remove(&id).unwrap_or_default();
+            item
+        })
+        .collect())
+}
+
+/// Tell the pages of the open MRs whose head is the commit of `run` that it changed.
+pub async fn notify_check_run(storage: &MrStorage, run: &check_runs::Model) {
+    let mrs = match storage.get_open_mrs_by_head(&run.commit_id).await {
+        Ok(mrs) => mrs,
+        Err(err) => {
+            tracing::error!(
+                "failed to find the MRs of commit {}: {}",
+                run.commit_id,
+                err
+            );
+            return;
+        }
+    };
+    if mrs.is_empty() {
+        return;
+    }
+    let payload = serde_json::to_value(CheckRunItem::from(run.clone())).unwrap();
+    for mr in mrs {
+        LiveUpdateEvent::notify(
+            LiveUpdateKind::CheckRun,
+            &mr.path,
+            Some(&mr.link),
+            payload.clone(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn annotation(path: &str, start_line: i32, end_line: Option<i32>) -> AnnotationInput {
+        AnnotationInput {
+            path: path.to_owned(),
+            start_line,
+            end_line,
+            level: AnnotationLevel::Failure,
+            title: None,
+            message: "possible secret".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_status() {
+        assert_eq!(
+            resolve_status(CheckStatus::InProgress, Some(CheckConclusion::Failure)),
+            Ok((CheckStatus::Completed, Some(CheckConclusion::Failure)))
+        );
+        assert_eq!(
+            resolve_status(CheckStatus::Queued, None),
+            Ok((CheckStatus::Queued, None))
+        );
+        assert!(resolve_status(CheckStatus::Completed, None).is_err());
+    }
+
+    #[test]
+    fn test_build_annotations() {
+        let rows = build_annotations(1, vec![annotation("project//src/main.rs", 3, None)]).unwrap();
+        assert_eq!(rows[0].path, "/project/src/main.rs");
+        assert_eq!(rows[0].end_line, 3);
+
+        assert!(build_annotations(1, vec![annotation("/a.rs", 0, None)]).is_err());
+        assert!(build_annotations(1, vec![annotation("/a.rs", 5, Some(4))]).is_err());
+        assert!(build_annotations(1, vec![annotation("/../a.rs", 1, None)]).is_err());
+        assert!(build_annotations(1, vec![annotation("/", 1, None)]).is_err());
+        let too_many = (0..=MAX_ANNOTATIONS_PER_REQUEST)
+            .map(|_| annotation("/a.rs", 1, None))
+            .collect();
+        assert!(build_annotations(1, too_many).is_err());
+    }
+
+    #[test]
+    fn test_commit_state_to_check() {
+        let input: CommitStatusInput =
+            serde_json::from_str(r#"{"state": "error", "context": "ci/build"}"#).unwrap();
+        assert_eq!(
+            input.state.to_check(),
+            (CheckStatus::Completed, Some(CheckConclusion::Failure))
+        );
+        assert_eq!(
+            CommitState::Pending.to_check(),
+            (CheckStatus::InProgress, None)
+        );
+    }
+
+    #[test]
+    fn test_is_commit_id() {
+        assert!(is_commit_id("0123456789abcdef0123456789abcdef01234567"));
+        assert!(!is_commit_id("0123456789ABCDEF0123456789abcdef01234567"));
+        assert!(!is_commit_id("0123456"));
+    }
+}
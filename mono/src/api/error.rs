@@ -1,11 +1,49 @@
 use axum::response::{IntoResponse, Response};
 use http::StatusCode;
 
+use common::errors::{MonoPathError, ProtocolError};
+use mercury::errors::GitError;
+
 #[derive(Debug)]
 pub struct ApiError(anyhow::Error);
 
+impl ApiError {
+    /// Invalid arguments and paths given by the client are 400, missing objects and paths
+    /// 404, name clashes and refs moved by a concurrent write 409, anything else is a
+    /// server error.
+    fn status(&self) -> StatusCode {
+        if self.0.is::<MonoPathError>() {
+            return StatusCode::BAD_REQUEST;
+        }
+        if let Some(err) = self.0.downcast_ref::<ProtocolError>() {
+            return match err {
+                ProtocolError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+                ProtocolError::Forbidden(_) => StatusCode::FORBIDDEN,
+                ProtocolError::NotFound(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+        match self.0.downcast_ref::<GitError>() {
+            Some(GitError::InvalidArgument(_) | GitError::InvalidHashValue(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            Some(
+                GitError::ObjectNotFound(_)
+                | GitError::RepoNotFound
+                | GitError::InvalidPathError(_),
+            ) => StatusCode::NOT_FOUND,
+            Some(GitError::EntryExists(_) | GitError::RefConflict(_)) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        let status = self.status();
+        if status != StatusCode::INTERNAL_SERVER_ERROR {
+            return (status, self.0.to_string()).into_response();
+        }
         tracing::error!("Application error: {:#}", self.0);
 
         (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong").into_response()
@@ -22,3 +60,39 @@ where
         Self(err.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let status = |err: GitError| ApiError::from(err).status();
+        assert_eq!(
+            status(GitError::ObjectNotFound("tree".to_owned())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(GitError::InvalidPathError("/a".to_owned())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(GitError::RefConflict("/ moved".to_owned())),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status(GitError::InvalidArgument("limit".to_owned())),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(GitError::CustomError("db".to_owned())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let err = ApiError::from(MonoPathError::ParentDir("/a/..".to_owned()));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        let err = ApiError::from(ProtocolError::InvalidInput("/a/..".to_owned()));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        let err = ApiError::from(anyhow::anyhow!("io"));
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
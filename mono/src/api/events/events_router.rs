@@ -0,0 +1,104 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+    routing::get,
+    Router,
+};
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+
+use taurus::event::live_update::LiveUpdateEvent;
+
+use crate::api::events::LiveUpdateQuery;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new().nest(
+        "/events",
+        Router::new()
+            .route("/stream", get(live_update_stream))
+            .route("/ws", get(live_update_socket)),
+    )
+}
+
+/// Server-sent events of a path or merge request, so pages can refresh without polling.
+/// Each event's name is the kind of the update and its data is the event in json.
+///
+/// Events are seen by the instance processing them, with several instances sharing a
+/// Redis broker a client only gets those processed by the instance it's connected to.
+async fn live_update_stream(
+    Query(query): Query<LiveUpdateQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut receiver = LiveUpdateEvent::subscribe();
+
+    let stream = async_stream::stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(evt) => {
+                    if !evt.matches(query.path.as_deref(), query.mr.as_deref()) {
+                        continue;
+                    }
+                    let name = serde_json::to_value(&evt.kind)
+                        .ok()
+                        .and_then(|x| x.as_str().map(|s| s.to_owned()))
+                        .unwrap_or_default();
+                    if let Ok(event) = Event::default().event(name).json_data(&evt) {
+                        yield Ok(event);
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("live update subscriber lagged, {} events skipped", n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+/// The events of [`live_update_stream`] over a WebSocket, for clients that can't use
+/// server-sent events. Each is a text message with the event in json, its `kind` is the
+/// kind of the update. Messages from the client are ignored.
+async fn live_update_socket(
+    Query(query): Query<LiveUpdateQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| forward_live_updates(socket, query))
+}
+
+async fn forward_live_updates(mut socket: WebSocket, query: LiveUpdateQuery) {
+    let mut receiver = LiveUpdateEvent::subscribe();
+    loop {
+        tokio::select! {
+            res = receiver.recv() => match res {
+                Ok(evt) => {
+                    if !evt.matches(query.path.as_deref(), query.mr.as_deref()) {
+                        continue;
+                    }
+                    let text = serde_json::to_string(&evt).unwrap();
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("live update subscriber lagged, {} events skipped", n);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // pings are answered by axum
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
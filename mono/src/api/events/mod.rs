@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+pub mod events_router;
+
+#[derive(Debug, Deserialize)]
+pub struct LiveUpdateQuery {
+    /// receive events under this monorepo path
+    pub path: Option<String>,
+    /// receive events of this merge request
+    pub mr: Option<String>,
+}
@@ -0,0 +1,35 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use common::model::CommonResult;
+use taurus::event::gc::{GcEvent, GcReport};
+
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new().route("/admin/gc", get(gc_report).post(start_gc))
+}
+
+/// The running garbage collection, or what the last one removed.
+async fn gc_report(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<GcReport>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    Ok(Json(CommonResult::success(Some(GcEvent::report()))))
+}
+
+/// Queue a garbage collection now, nothing happens while one is running.
+async fn start_gc(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<GcReport>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    GcEvent::notify(&user.name);
+    Ok(Json(CommonResult::success(Some(GcEvent::report()))))
+}
@@ -0,0 +1,242 @@
+//! Helpers for HTTP conditional requests on content endpoints.
+//!
+//! Git objects are immutable, so anything addressed by an object hash can be
+//! cached forever with the hash as a strong `ETag`. Path based endpoints resolve
+//! to a different object whenever the path changes, so they are marked for
+//! revalidation and also carry a `Last-Modified` taken from the resolving commit.
+//! Raw content also answers single `Range` requests.
+
+use std::ops::Range;
+
+use axum::{body::Body, response::Response};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use mercury::hash::SHA1;
+
+/// Used for endpoints addressed by an object hash.
+pub const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Used for endpoints addressed by a path, the content may change with the next commit.
+pub const CACHE_REVALIDATE: &str = "no-cache";
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Cache validators for a single response.
+#[derive(Debug, Clone)]
+pub struct CacheInfo {
+    pub etag: String,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub cache_control: &'static str,
+}
+
+impl CacheInfo {
+    pub fn immutable(hash: &str) -> Self {
+        CacheInfo {
+            etag: format!("\"{}\"", hash),
+            last_modified: None,
+            cache_control: CACHE_IMMUTABLE,
+        }
+    }
+
+    /// `timestamp` is the committer time (seconds) of the commit the path resolved with.
+    pub fn revalidate(hash: &str, timestamp: Option<usize>) -> Self {
+        CacheInfo {
+            etag: format!("\"{}\"", hash),
+            last_modified: timestamp.and_then(|t| DateTime::from_timestamp(t as i64, 0)),
+            cache_control: CACHE_REVALIDATE,
+        }
+    }
+
+    /// For responses computed from the object with the query, a listing of a tree sorted
+    /// or paged: the tag covers the query too, as a different one gives different content.
+    pub fn with_query(mut self, query: &str) -> Self {
+        if !query.is_empty() {
+            let digest = SHA1::new(query.as_bytes()).to_string();
+            self.etag = format!("\"{}-{}\"", self.etag.trim_matches('"'), &digest[..12]);
+        }
+        self
+    }
+
+    /// Check `If-None-Match` first and only fall back to `If-Modified-Since`
+    /// when the client sent no entity tag, as RFC 9110 requires.
+    pub fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+            return value
+                .to_str()
+                .map(|v| etag_matches(v, &self.etag))
+                .unwrap_or(false);
+        }
+        if let (Some(value), Some(last_modified)) =
+            (headers.get(header::IF_MODIFIED_SINCE), self.last_modified)
+        {
+            if let Some(since) = value.to_str().ok().and_then(parse_http_date) {
+                return last_modified.timestamp() <= since.timestamp();
+            }
+        }
+        false
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(self.cache_control),
+        );
+        if let Some(last_modified) = self.last_modified {
+            if let Ok(value) = HeaderValue::from_str(&format_http_date(last_modified)) {
+                headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+    }
+
+    pub fn not_modified(&self) -> Response {
+        let mut res = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+        self.apply(res.headers_mut());
+        res
+    }
+}
+
+/// Part of a body of known length asked for by a `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// no header, or one that is ignored: malformed or with several ranges
+    Full,
+    Partial(Range<usize>),
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    pub fn from_headers(headers: &HeaderMap, len: usize) -> Self {
+        let Some(value) = headers.get(header::RANGE).and_then(|x| x.to_str().ok()) else {
+            return ByteRange::Full;
+        };
+        let Some((start, end)) = value
+            .trim()
+            .strip_prefix("bytes=")
+            .filter(|x| !x.contains(','))
+            .and_then(|x| x.split_once('-'))
+        else {
+            return ByteRange::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() {
+            // suffix range, the last `end` bytes
+            return match end.parse::<usize>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(suffix) if len > 0 => ByteRange::Partial(len.saturating_sub(suffix)..len),
+                Ok(_) => ByteRange::Unsatisfiable,
+                Err(_) => ByteRange::Full,
+            };
+        }
+        let Ok(start) = start.parse::<usize>() else {
+            return ByteRange::Full;
+        };
+        let end = match end {
+            "" => len,
+            end => match end.parse::<usize>() {
+                Ok(end) if end >= start => len.min(end.saturating_add(1)),
+                _ => return ByteRange::Full,
+            },
+        };
+        if start >= len {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Partial(start..end)
+    }
+}
+
+/// Weak comparison as used by `If-None-Match`, the header may hold a list of tags or `*`.
+fn etag_matches(header_value: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    header_value
+        .split(',')
+        .map(|x| x.trim())
+        .any(|x| x == "*" || x.trim_start_matches("W/") == etag)
+}
+
+fn format_http_date(date: DateTime<Utc>) -> String {
+    date.format(HTTP_DATE_FORMAT).to_string()
+}
+
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    chrono::NaiveDateTime::parse_from_str(value.trim(), HTTP_DATE_FORMAT)
+        .ok()
+        .map(|x| x.and_utc())
+}
+
+#[cfg(test)]
+mod test {
+    use http::{header, HeaderMap, HeaderValue};
+
+    use super::{etag_matches, format_http_date, parse_http_date, ByteRange, CacheInfo};
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"xyz\", \"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"abcd\"", "\"abc\""));
+    }
+
+    #[test]
+    fn test_http_date_round_trip() {
+        let date = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let formatted = format_http_date(date);
+        assert_eq!(formatted, "Tue, 14 Nov 2023 22:13:20 GMT");
+        assert_eq!(parse_http_date(&formatted), Some(date));
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let info = CacheInfo::revalidate("abc", Some(1_700_000_000));
+        let mut headers = HeaderMap::new();
+        assert!(!info.is_fresh(&headers));
+
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Tue, 14 Nov 2023 22:13:20 GMT"),
+        );
+        assert!(info.is_fresh(&headers));
+
+        // a mismatched etag wins over the date
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!info.is_fresh(&headers));
+    }
+
+    #[test]
+    fn test_with_query() {
+        let plain = CacheInfo::immutable("abc");
+        assert_eq!(plain.clone().with_query("").etag, "\"abc\"");
+        let sorted = plain.clone().with_query("path=/&sort=name");
+        assert!(sorted.etag.starts_with("\"abc-") && sorted.etag.ends_with('"'));
+        assert_ne!(sorted.etag, plain.with_query("path=/&sort=size").etag);
+    }
+
+    #[test]
+    fn test_byte_range() {
+        let range = |value: &'static str, len| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, HeaderValue::from_static(value));
+            ByteRange::from_headers(&headers, len)
+        };
+        assert_eq!(
+            ByteRange::from_headers(&HeaderMap::new(), 10),
+            ByteRange::Full
+        );
+        assert_eq!(range("bytes=0-4", 10), ByteRange::Partial(0..5));
+        assert_eq!(range("bytes=5-", 10), ByteRange::Partial(5..10));
+        assert_eq!(range("bytes=5-100", 10), ByteRange::Partial(5..10));
+        assert_eq!(range("bytes=-3", 10), ByteRange::Partial(7..10));
+        assert_eq!(range("bytes=-30", 10), ByteRange::Partial(0..10));
+        assert_eq!(range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-0", 10), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,4-5", 10), ByteRange::Full);
+        assert_eq!(range("bytes=4-1", 10), ByteRange::Full);
+        assert_eq!(range("items=0-1", 10), ByteRange::Full);
+    }
+}
@@ -6,7 +6,7 @@ use axum::{
 use bytes::Bytes;
 use serde::Deserialize;
 
-use common::model::{CommonPage, CommonResult, PageParams};
+use common::model::{CommonResult, Page, PageParams};
 
 use crate::api::error::ApiError;
 use crate::api::issue::{IssueDetail, IssueItem, NewIssue};
@@ -35,16 +35,16 @@ pub struct StatusParams {
 async fn fetch_issue_list(
     state: State<MonoApiServiceState>,
     Json(json): Json<PageParams<StatusParams>>,
-) -> Result<Json<CommonResult<CommonPage<IssueItem>>>, ApiError> {
+) -> Result<Json<CommonResult<Page<IssueItem>>>, ApiError> {
+    let pagination = json.pagination.normalized();
     let res = state
         .issue_stg()
-        .get_issue_by_status(&json.additional.status, json.pagination)
+        .get_issue_by_status(&json.additional.status, pagination)
         .await;
     let res = match res {
-        Ok((items, total)) => CommonResult::success(Some(CommonPage {
-            items: items.into_iter().map(|m| m.into()).collect(),
-            total,
-        })),
+        Ok((items, total)) => {
+            CommonResult::success(Some(Page::new(items, total, &pagination).map(|m| m.into())))
+        }
 
         Err(err) => CommonResult::failed(&err.to_string()),
     };
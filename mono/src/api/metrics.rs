@@ -0,0 +1,107 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, StatusCode};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+/// Buckets of the histograms in seconds.
+const SECONDS_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+/// Buckets of the histograms in bytes, 1 KiB to 4 GiB.
+const BYTES_BUCKETS: &[f64] = &[
+    1024.0,
+    16384.0,
+    262144.0,
+    1048576.0,
+    16777216.0,
+    268435456.0,
+    1073741824.0,
+    4294967296.0,
+];
+
+/// How often the recorder drops what it doesn't need any more between two scrapes.
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Install the Prometheus recorder, metrics recorded before are lost. Installing it again
+/// does nothing.
+pub fn install() {
+    HANDLE.get_or_init(|| {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Suffix("_seconds".to_owned()), SECONDS_BUCKETS)
+            .and_then(|x| {
+                x.set_buckets_for_metric(Matcher::Suffix("_bytes".to_owned()), BYTES_BUCKETS)
+            })
+            .and_then(|x| x.install_recorder())
+            .expect("Failed to install the metrics recorder");
+        let upkeep = handle.clone();
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(UPKEEP_INTERVAL);
+            loop {
+                timer.tick().await;
+                upkeep.run_upkeep();
+            }
+        });
+        handle
+    });
+}
+
+/// Middleware timing every request by the route it matched,
+/// apply it with `axum::middleware::from_fn`.
+pub async fn track(matched_path: Option<MatchedPath>, req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    // Unmatched paths are left out of the labels, there's no end to them.
+    let route = matched_path
+        .map(|x| x.as_str().to_owned())
+        .unwrap_or_else(|| String::from("unmatched"));
+
+    let res = next.run(req).await;
+
+    let status = res.status().as_u16().to_string();
+    metrics::histogram!(
+        "mega_http_request_duration_seconds",
+        "method" => method.clone(),
+        "route" => route.clone(),
+    )
+    .record(start.elapsed().as_secs_f64());
+    metrics::counter!(
+        "mega_http_requests_total",
+        "method" => method,
+        "route" => route,
+        "status" => status,
+    )
+    .increment(1);
+    res
+}
+
+/// The metrics in the Prometheus text format:
+/// - `mega_http_request_duration_seconds` and `mega_http_requests_total` by route
+/// - `mega_http_rate_limited_total` by class, requests turned away by the rate limits
+/// - `mega_pack_encode_*` and `mega_pack_decode_*`, durations, bytes and objects of packs
+/// - `mega_db_queries_total` and `mega_db_query_duration_seconds` by kind of statement
+/// - `mega_queue_messages` by state and `mega_queue_capacity`
+/// - `mega_queue_events_total` by event and outcome, `mega_queue_event_duration_seconds`
+pub async fn render() -> Response {
+    let Some(handle) = HANDLE.get() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "metrics recorder not installed",
+        )
+            .into_response();
+    };
+    taurus::queue::get_mq().record_metrics();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    )
+        .into_response()
+}
@@ -10,18 +10,41 @@ use ceres::{
     },
     protocol::repo::Repo,
 };
-use common::{errors::ProtocolError, model::CommonOptions};
+use common::{errors::ProtocolError, model::CommonOptions, path::MonoPath};
 use jupiter::{
     context::Context,
-    storage::{issue_storage::IssueStorage, mr_storage::MrStorage, user_storage::UserStorage},
+    storage::{
+        check_storage::CheckStorage, issue_storage::IssueStorage, mr_storage::MrStorage,
+        user_storage::UserStorage,
+    },
 };
+use mercury::internal::object::signature::{Signature, SignatureType};
+
+use crate::api::oauth::model::LoginUser;
 
+pub mod access_log;
 pub mod api_router;
+pub mod audit;
+pub mod checks;
 pub mod error;
+pub mod events;
+pub mod gc;
+pub mod http_cache;
 pub mod issue;
 pub mod lfs;
+pub mod metrics;
 pub mod mr;
+pub mod notification;
 pub mod oauth;
+pub mod openapi;
+pub mod preview;
+pub mod queue;
+pub mod rate_limit;
+pub mod readme;
+pub mod release;
+pub mod stats;
+pub mod tombstone;
+pub mod traffic;
 pub mod user;
 
 #[derive(Clone)]
@@ -55,6 +78,19 @@ impl MonoApiServiceState {
     fn monorepo(&self) -> MonoApiService {
         MonoApiService {
             context: self.context.clone(),
+            author: None,
+        }
+    }
+
+    /// The monorepo service acting for `user`, who authors the commits it makes.
+    fn monorepo_as(&self, user: &LoginUser) -> MonoApiService {
+        MonoApiService {
+            context: self.context.clone(),
+            author: Some(Signature::new(
+                SignatureType::Author,
+                user.name.clone(),
+                user.email.clone(),
+            )),
         }
     }
 
@@ -70,7 +106,12 @@ impl MonoApiServiceState {
         self.context.services.user_storage()
     }
 
+    fn check_stg(&self) -> CheckStorage {
+        self.context.check_stg()
+    }
+
     async fn api_handler(&self, path: PathBuf) -> Result<Box<dyn ApiHandler>, ProtocolError> {
+        let path = MonoPath::try_from(path.as_path())?.to_path_buf();
         let import_dir = self.context.config.monorepo.import_dir.clone();
         if path.starts_with(&import_dir) && path != import_dir {
             if let Some(model) = self
@@ -88,20 +129,23 @@ impl MonoApiServiceState {
                 }));
             }
         }
-        Ok(Box::new(MonoApiService {
-            context: self.context.clone(),
-        }))
+        Ok(Box::new(self.monorepo()))
     }
 }
 pub mod util {
+    use std::collections::HashSet;
     use std::path::PathBuf;
 
     use axum::extract::State;
 
+    use callisto::db_enums::AclPermission;
     use cedar_policy::Context;
     use ceres::api_service::ApiHandler;
+    use common::{errors::MegaError, path::MonoPath};
+    use jupiter::storage::acl_storage::PathAcl;
     use saturn::{context::CedarContext, entitystore::EntityStore, util::EntityUid, ActionEnum};
 
+    use crate::api::oauth::model::LoginUser;
     use crate::api::MonoApiServiceState;
 
     pub async fn get_entitystore(path: PathBuf, state: State<MonoApiServiceState>) -> EntityStore {
@@ -122,12 +166,76 @@ pub mod util {
         entities
     }
 
+    /// The path ACL as seen by `user`, anonymous requests only get the open paths.
+    pub async fn path_acl(
+        user: Option<&LoginUser>,
+        state: &MonoApiServiceState,
+    ) -> Result<PathAcl, MegaError> {
+        state
+            .context
+            .acl_stg()
+            .load_path_acl(
+                user.map(|x| x.name.as_str()),
+                &state.context.config.monorepo.admin,
+            )
+            .await
+    }
+
+    /// Whether `user` can read `path`, content below it is served only then.
+    pub async fn can_read(
+        user: Option<&LoginUser>,
+        path: &str,
+        state: &MonoApiServiceState,
+    ) -> Result<bool, MegaError> {
+        // an invalid path is refused by the handler itself
+        let path = MonoPath::parse(path).map_or_else(|_| path.to_owned(), |x| x.to_string());
+        Ok(path_acl(user, state)
+            .await?
+            .allows(&path, AclPermission::Read))
+    }
+
+    /// Whether a changed file can be listed to the user, the source of a rename included.
+    pub fn shows_file(acl: &PathAcl, path: &str, renamed_from: Option<&str>) -> bool {
+        acl.allows(path, AclPermission::Read)
+            && renamed_from.is_none_or(|x| acl.allows(x, AclPermission::Read))
+    }
+
+    /// Which of the objects addressed by hash `user` can read, those found in the latest
+    /// tree outside the directories hidden from them.
+    pub async fn readable_objects(
+        user: Option<&LoginUser>,
+        oids: HashSet<String>,
+        state: &MonoApiServiceState,
+    ) -> Result<HashSet<String>, MegaError> {
+        let acl = path_acl(user, state).await?;
+        if !acl.allows("/", AclPermission::Read) {
+            return Ok(HashSet::new());
+        }
+        let hidden = acl.hidden_below("/");
+        if hidden.is_empty() {
+            return Ok(oids);
+        }
+        Ok(state.monorepo().visible_objects(oids, &hidden).await?)
+    }
+
     pub async fn check_permissions(
         username: &str,
         path: &str,
         operation: ActionEnum,
         state: State<MonoApiServiceState>,
     ) -> Result<(), saturn::context::Error> {
+        let acl = state
+            .context
+            .acl_stg()
+            .load_path_acl(Some(username), &state.context.config.monorepo.admin)
+            .await
+            .map_err(|e| saturn::context::Error::Request(e.to_string()))?;
+        if !acl.allows(path, AclPermission::Write) {
+            return Err(saturn::context::Error::AccessDenied(format!(
+                "{} has no write access to {}",
+                username, path
+            )));
+        }
         let entities = get_entitystore(path.into(), state).await;
         let cedar_context = CedarContext::new(entities).unwrap();
         cedar_context.is_authorized(
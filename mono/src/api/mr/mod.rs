@@ -1,15 +1,87 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use callisto::{db_enums::ReviewState, mega_conversation, mega_mr, mega_mr_review};
+use ceres::{
+    model::{diff::DiffStat, tree::LatestCommitInfo},
+    protocol::mr::MergeOperation,
+};
+use common::model::Pagination;
 
-use callisto::{mega_conversation, mega_mr};
+use crate::api::checks::CheckRunItem;
 
 pub mod mr_router;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct MRStatusParams {
     pub status: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    #[serde(default)]
+    pub milestone: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MRListQuery {
+    /// `open`, `closed` or every status when missing
+    pub status: Option<String>,
+    /// only MRs on this directory or below it
+    pub path: Option<String>,
+    /// only MRs carrying this label
+    pub label: Option<String>,
+    /// only MRs assigned to this user
+    pub assignee: Option<String>,
+    pub milestone: Option<String>,
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+impl MRListQuery {
+    pub fn pagination(&self) -> Pagination {
+        let default = Pagination::default();
+        Pagination {
+            page: self.page.unwrap_or(default.page),
+            per_page: self.per_page.unwrap_or(default.per_page),
+        }
+        .normalized()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MergeParams {
+    #[serde(default)]
+    pub operation: MergeOperation,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FileDiffQuery {
+    /// where a renamed or copied file comes from
+    pub renamed_from: Option<String>,
+}
+
+/// Metadata to replace on a merge request, fields left out are kept and an empty
+/// `milestone` removes it.
+#[derive(Deserialize, ToSchema)]
+pub struct MetadataParams {
+    pub labels: Option<Vec<String>>,
+    pub assignees: Option<Vec<String>>,
+    pub milestone: Option<String>,
+}
+
+/// The MR a merge request builds on, `None` to remove the dependency.
+#[derive(Deserialize, ToSchema)]
+pub struct DependencyParams {
+    pub depends_on: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct MrInfoItem {
     pub link: String,
     pub title: String,
@@ -17,6 +89,10 @@ pub struct MrInfoItem {
     pub open_timestamp: i64,
     pub merge_timestamp: Option<i64>,
     pub updated_at: i64,
+    pub draft: bool,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    pub milestone: Option<String>,
 }
 
 impl From<mega_mr::Model> for MrInfoItem {
@@ -28,11 +104,15 @@ impl From<mega_mr::Model> for MrInfoItem {
             open_timestamp: value.created_at.and_utc().timestamp(),
             merge_timestamp: value.merge_date.map(|dt| dt.and_utc().timestamp()),
             updated_at: value.updated_at.and_utc().timestamp(),
+            draft: value.draft,
+            labels: serde_json::from_value(value.labels).unwrap_or_default(),
+            assignees: serde_json::from_value(value.assignees).unwrap_or_default(),
+            milestone: value.milestone,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct MRDetail {
     pub id: i64,
     pub link: String,
@@ -40,7 +120,33 @@ pub struct MRDetail {
     pub status: String,
     pub open_timestamp: i64,
     pub merge_timestamp: Option<i64>,
+    pub path: String,
+    /// named branch the MR merges, `None` when it was opened by a push
+    pub source_branch: Option<String>,
+    pub target_branch: String,
+    pub description: Option<String>,
+    /// usernames asked to review the MR
+    pub reviewers: Vec<String>,
+    pub draft: bool,
+    /// user name of who opened the MR, `None` for MRs older than authors
+    pub author: Option<String>,
+    /// link of the MR this one builds on, it's merged first
+    pub depends_on: Option<String>,
+    pub labels: Vec<String>,
+    /// usernames working on the MR
+    pub assignees: Vec<String>,
+    pub milestone: Option<String>,
+    /// link of the MR closed for a conflict that this one replaces
+    pub supersedes: Option<String>,
     pub conversations: Vec<MegaConversation>,
+    /// commits of the MR, oldest first
+    pub commits: Vec<LatestCommitInfo>,
+    /// every commit of the MR has a verified signature
+    pub verified: bool,
+    /// check runs reported on the head commit of the MR
+    pub checks: Vec<CheckRunItem>,
+    /// changes between the base and the head of the MR
+    pub diffstat: DiffStat,
 }
 
 impl From<mega_mr::Model> for MRDetail {
@@ -52,17 +158,39 @@ impl From<mega_mr::Model> for MRDetail {
             status: value.status.to_string(),
             open_timestamp: value.created_at.and_utc().timestamp(),
             merge_timestamp: value.merge_date.map(|dt| dt.and_utc().timestamp()),
+            path: value.path,
+            source_branch: value.source_branch,
+            target_branch: value.target_branch,
+            description: value.description,
+            reviewers: serde_json::from_value(value.reviewers).unwrap_or_default(),
+            draft: value.draft,
+            author: value.author,
+            depends_on: value.depends_on,
+            labels: serde_json::from_value(value.labels).unwrap_or_default(),
+            assignees: serde_json::from_value(value.assignees).unwrap_or_default(),
+            milestone: value.milestone,
+            supersedes: value.supersedes,
             conversations: vec![],
+            commits: vec![],
+            verified: false,
+            checks: vec![],
+            diffstat: DiffStat::default(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct MegaConversation {
     pub id: i64,
     pub user_id: i64,
     pub conv_type: String,
     pub comment: Option<String>,
+    /// file and line of a review comment
+    pub path: Option<String>,
+    pub commit_id: Option<String>,
+    pub line: Option<i32>,
+    /// first comment of the thread when this is a reply
+    pub reply_to: Option<i64>,
     pub created_at: i64,
     pub updated_at: i64,
 }
@@ -74,19 +202,103 @@ impl From<mega_conversation::Model> for MegaConversation {
             user_id: value.user_id,
             conv_type: value.conv_type.to_string(),
             comment: value.comment,
+            path: value.path,
+            commit_id: value.commit_id,
+            line: value.line,
+            reply_to: value.reply_to,
             created_at: value.created_at.and_utc().timestamp(),
             updated_at: value.updated_at.and_utc().timestamp(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// A new review thread when `path`, `commit_id` and `line` are set, a reply to `reply_to` otherwise.
+#[derive(Deserialize, ToSchema)]
+pub struct ReviewCommentParams {
+    pub path: Option<String>,
+    pub commit_id: Option<String>,
+    pub line: Option<i32>,
+    pub reply_to: Option<i64>,
+    pub comment: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewThread {
+    pub id: i64,
+    pub path: String,
+    pub commit_id: String,
+    pub line: i32,
+    pub resolved: bool,
+    /// the first comment and its replies, oldest first
+    pub comments: Vec<MegaConversation>,
+}
+
+impl ReviewThread {
+    /// Group review comments, given oldest first, into threads.
+    pub fn from_comments(comments: Vec<mega_conversation::Model>) -> Vec<ReviewThread> {
+        let mut threads: Vec<ReviewThread> = Vec::new();
+        let mut index = HashMap::new();
+        for comment in comments {
+            match comment.reply_to {
+                None => {
+                    index.insert(comment.id, threads.len());
+                    threads.push(ReviewThread {
+                        id: comment.id,
+                        path: comment.path.clone().unwrap_or_default(),
+                        commit_id: comment.commit_id.clone().unwrap_or_default(),
+                        line: comment.line.unwrap_or_default(),
+                        resolved: comment.resolved,
+                        comments: vec![comment.into()],
+                    });
+                }
+                // replies whose thread was deleted are dropped
+                Some(root) => {
+                    if let Some(&idx) = index.get(&root) {
+                        threads[idx].comments.push(comment.into());
+                    }
+                }
+            }
+        }
+        threads
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MrReviewItem {
+    pub user_id: i64,
+    pub username: String,
+    #[schema(value_type = String)]
+    pub state: ReviewState,
+    pub updated_at: i64,
+}
+
+impl From<mega_mr_review::Model> for MrReviewItem {
+    fn from(value: mega_mr_review::Model) -> Self {
+        Self {
+            user_id: value.user_id,
+            username: value.username,
+            state: value.state,
+            updated_at: value.updated_at.and_utc().timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MrReviews {
+    /// approvals configured for the path of the MR
+    pub required: usize,
+    pub approvals: usize,
+    /// the latest review of every reviewer
+    pub reviews: Vec<MrReviewItem>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct FilesChangedItem {
     pub path: String,
     pub status: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct FilesChangedList {
     pub files: Vec<FilesChangedItem>,
     pub content: String,
@@ -1,22 +1,41 @@
 use std::collections::HashMap;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     routing::{get, post},
     Json, Router,
 };
 
 use bytes::Bytes;
+use http::StatusCode;
+use serde_json::json;
 
-use callisto::db_enums::{ConvType, MergeStatus};
-use ceres::protocol::mr::MergeRequest;
-use common::model::{CommonPage, CommonResult, PageParams};
+use callisto::db_enums::{
+    AclPermission, AuditAction, ConvType, MergeStatus, NotificationKind, ReviewState,
+};
+use ceres::api_service::ApiHandler;
+use ceres::model::branch::{BranchPath, CreateBranchMrRequest};
+use ceres::model::diff::{ChangedFile, DiffStat, FileDiff};
+use ceres::protocol::mr::{MergeRequest, MergeResult};
+use common::model::{CommonResult, Page, PageParams, Pagination};
+use jupiter::storage::mr_storage::MrFilter;
+use mercury::errors::GitError;
 use saturn::ActionEnum;
 use taurus::event::api_request::{ApiRequestEvent, ApiType};
+use taurus::event::audit::AuditEvent;
+use taurus::event::live_update::{LiveUpdateEvent, LiveUpdateKind};
+use taurus::event::notification::NotificationEvent;
 
+use crate::api::checks::load_check_runs;
 use crate::api::error::ApiError;
-use crate::api::mr::{FilesChangedItem, FilesChangedList, MRDetail, MRStatusParams, MrInfoItem};
+use crate::api::mr::{
+    DependencyParams, FileDiffQuery, FilesChangedItem, FilesChangedList, MRDetail, MRListQuery,
+    MRStatusParams, MegaConversation, MergeParams, MetadataParams, MrInfoItem, MrReviews,
+    ReviewCommentParams, ReviewThread,
+};
+use crate::api::notification::mentions;
 use crate::api::oauth::model::LoginUser;
+use crate::api::openapi::MR_TAG;
 use crate::api::util;
 use crate::api::MonoApiServiceState;
 
@@ -24,17 +43,45 @@ pub fn routers() -> Router<MonoApiServiceState> {
     Router::new().nest(
         "/mr",
         Router::new()
+            .route("/", get(list_mr))
             .route("/list", post(fetch_mr_list))
+            .route("/new", post(create_mr))
+            .route("/{link}", get(mr_detail))
             .route("/{link}/detail", get(mr_detail))
             .route("/{link}/merge", post(merge))
             .route("/{link}/close", post(close_mr))
             .route("/{link}/reopen", post(reopen_mr))
+            .route("/{link}/ready", post(ready_mr))
+            .route("/{link}/draft", post(draft_mr))
+            .route("/{link}/depends-on", post(set_dependency))
+            .route("/{link}/metadata", post(set_metadata))
+            .route("/{link}/approve", post(approve_mr))
+            .route("/{link}/request-changes", post(request_changes))
+            .route("/{link}/reviews", get(get_mr_reviews))
             .route("/{link}/files-changed", get(get_mr_files_changed))
+            .route("/{link}/files", get(get_mr_files))
+            .route("/{link}/files/{*path}", get(get_mr_file_diff))
+            .route("/{link}/conversations", get(get_mr_conversations))
             .route("/{link}/comment", post(save_comment))
-            .route("/comment/{conv_id}/delete", post(delete_comment)),
+            .route(
+                "/{link}/review-comments",
+                get(get_review_comments).post(save_review_comment),
+            )
+            .route("/comment/{conv_id}/delete", post(delete_comment))
+            .route("/comment/{conv_id}/resolve", post(resolve_comment))
+            .route("/comment/{conv_id}/unresolve", post(unresolve_comment)),
     )
 }
 
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/reopen",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
 async fn reopen_mr(
     user: LoginUser,
     Path(link): Path<String>,
@@ -42,14 +89,35 @@ async fn reopen_mr(
 ) -> Result<Json<CommonResult<String>>, ApiError> {
     if let Some(model) = state.mr_stg().get_mr(&link).await.unwrap() {
         if model.status == MergeStatus::Closed {
-            util::check_permissions(
+            if util::check_permissions(
                 &user.name,
                 &model.path,
                 ActionEnum::EditMergeRequest,
                 state.clone(),
             )
             .await
-            .unwrap();
+            .is_err()
+            {
+                return Ok(Json(CommonResult::failed("permission denied")));
+            }
+            // pushes and branches go to a single open MR, the one replacing this is
+            // closed first
+            let open = match &model.source_branch {
+                Some(branch) => {
+                    state
+                        .mr_stg()
+                        .get_open_mr_by_branch(&model.path, branch)
+                        .await?
+                }
+                None => state.mr_stg().get_open_mr_by_path(&model.path).await?,
+            };
+            if let Some(open) = open {
+                return Ok(Json(CommonResult::failed(&format!(
+                    "MR {} is open for the same changes, close it first",
+                    open.link
+                ))));
+            }
+            let path = model.path.clone();
             let mut mr: MergeRequest = model.into();
             mr.status = MergeStatus::Open;
             let res = match state
@@ -57,7 +125,15 @@ async fn reopen_mr(
                 .reopen_mr(mr.into(), user.user_id, &user.name)
                 .await
             {
-                Ok(_) => CommonResult::success(None),
+                Ok(_) => {
+                    LiveUpdateEvent::notify(
+                        LiveUpdateKind::StatusChange,
+                        &path,
+                        Some(&link),
+                        json!({ "status": MergeStatus::Open.to_string() }),
+                    );
+                    CommonResult::success(None)
+                }
                 Err(err) => CommonResult::failed(&err.to_string()),
             };
             return Ok(Json(res));
@@ -66,6 +142,251 @@ async fn reopen_mr(
     Ok(Json(CommonResult::failed("not found")))
 }
 
+/// Make the MR build on another one, it can't be merged before that one is.
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/depends-on",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    request_body = DependencyParams,
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
+async fn set_dependency(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<DependencyParams>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await? else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    if model.status != MergeStatus::Open {
+        return Ok(Json(CommonResult::failed("the MR isn't open")));
+    }
+    if util::check_permissions(
+        &user.name,
+        &model.path,
+        ActionEnum::EditMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let mut mr: MergeRequest = model.into();
+    if let Some(depends_on) = &json.depends_on {
+        if let Err(err) = mr.check_dependency(&state.mr_stg(), depends_on).await {
+            return Ok(Json(CommonResult::failed(&err.to_string())));
+        }
+    }
+    let comment = match &json.depends_on {
+        Some(depends_on) => format!("{} made this depend on MR {}", user.name, depends_on),
+        None => format!("{} removed the dependency of this", user.name),
+    };
+    mr.depends_on = json.depends_on.clone();
+    let path = mr.path.clone();
+    state.mr_stg().update_mr(mr.into()).await?;
+    state
+        .mr_stg()
+        .add_mr_conversation(&link, user.user_id, ConvType::Edit, Some(comment))
+        .await?;
+    LiveUpdateEvent::notify(
+        LiveUpdateKind::StatusChange,
+        &path,
+        Some(&link),
+        json!({ "depends_on": json.depends_on }),
+    );
+    Ok(Json(CommonResult::success(None)))
+}
+
+/// Replace the labels, assignees or milestone of an open MR.
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/metadata",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    request_body = MetadataParams,
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
+async fn set_metadata(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<MetadataParams>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await? else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    if model.status != MergeStatus::Open {
+        return Ok(Json(CommonResult::failed("the MR isn't open")));
+    }
+    if util::check_permissions(
+        &user.name,
+        &model.path,
+        ActionEnum::EditMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let mut mr: MergeRequest = model.into();
+    let mut changes = vec![];
+    if let Some(labels) = json.labels {
+        mr.labels = distinct(labels);
+        changes.push(format!("labels to [{}]", mr.labels.join(", ")));
+    }
+    if let Some(assignees) = json.assignees {
+        mr.assignees = distinct(assignees);
+        changes.push(format!("assignees to [{}]", mr.assignees.join(", ")));
+    }
+    if let Some(milestone) = json.milestone {
+        let milestone = milestone.trim();
+        mr.milestone = (!milestone.is_empty()).then(|| milestone.to_owned());
+        changes.push(match &mr.milestone {
+            Some(milestone) => format!("milestone to {}", milestone),
+            None => String::from("no milestone"),
+        });
+    }
+    if changes.is_empty() {
+        return Ok(Json(CommonResult::success(None)));
+    }
+    let comment = format!("{} set {}", user.name, changes.join(", "));
+    let update = json!({
+        "labels": mr.labels,
+        "assignees": mr.assignees,
+        "milestone": mr.milestone,
+    });
+    let path = mr.path.clone();
+    state.mr_stg().update_mr(mr.into()).await?;
+    state
+        .mr_stg()
+        .add_mr_conversation(&link, user.user_id, ConvType::Edit, Some(comment))
+        .await?;
+    LiveUpdateEvent::notify(LiveUpdateKind::StatusChange, &path, Some(&link), update);
+    Ok(Json(CommonResult::success(None)))
+}
+
+// Trimmed values without blanks and repeats, in the order given.
+fn distinct(values: Vec<String>) -> Vec<String> {
+    let mut res: Vec<String> = vec![];
+    for value in values {
+        let value = value.trim();
+        if !value.is_empty() && !res.iter().any(|x| x == value) {
+            res.push(value.to_owned());
+        }
+    }
+    res
+}
+
+/// Mark a draft MR ready for review, its reviewers are asked to review it then.
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/ready",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
+async fn ready_mr(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    set_draft(user, link, false, state).await
+}
+
+/// Turn an open MR back into a draft, it can't be merged until it's marked ready.
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/draft",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
+async fn draft_mr(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    set_draft(user, link, true, state).await
+}
+
+async fn set_draft(
+    user: LoginUser,
+    link: String,
+    draft: bool,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await? else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    if model.status != MergeStatus::Open {
+        return Ok(Json(CommonResult::failed("the MR isn't open")));
+    }
+    if model.draft == draft {
+        return Ok(Json(CommonResult::success(None)));
+    }
+    if util::check_permissions(
+        &user.name,
+        &model.path,
+        ActionEnum::EditMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let path = model.path.clone();
+    let (title, reviewers) = (model.title.clone(), model.reviewers.clone());
+    let res = match state
+        .mr_stg()
+        .set_draft(model, draft, user.user_id, &user.name)
+        .await
+    {
+        Ok(_) => {
+            LiveUpdateEvent::notify(
+                LiveUpdateKind::StatusChange,
+                &path,
+                Some(&link),
+                json!({ "draft": draft }),
+            );
+            if !draft {
+                NotificationEvent::notify(
+                    NotificationKind::ReviewRequested,
+                    &user.name,
+                    serde_json::from_value(reviewers).unwrap_or_default(),
+                    &format!("/mr/{}", link),
+                    &title,
+                    None,
+                );
+            }
+            CommonResult::success(None)
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/close",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
 async fn close_mr(
     user: LoginUser,
     Path(link): Path<String>,
@@ -73,14 +394,19 @@ async fn close_mr(
 ) -> Result<Json<CommonResult<String>>, ApiError> {
     if let Some(model) = state.mr_stg().get_mr(&link).await.unwrap() {
         if model.status == MergeStatus::Open {
-            util::check_permissions(
+            if util::check_permissions(
                 &user.name,
                 &model.path,
                 ActionEnum::EditMergeRequest,
                 state.clone(),
             )
             .await
-            .unwrap();
+            .is_err()
+            {
+                return Ok(Json(CommonResult::failed("permission denied")));
+            }
+            let path = model.path.clone();
+            let (author, title) = (model.author.clone(), model.title.clone());
             let mut mr: MergeRequest = model.into();
             mr.status = MergeStatus::Closed;
             let res = match state
@@ -88,7 +414,23 @@ async fn close_mr(
                 .close_mr(mr.into(), user.user_id, &user.name)
                 .await
             {
-                Ok(_) => CommonResult::success(None),
+                Ok(_) => {
+                    LiveUpdateEvent::notify(
+                        LiveUpdateKind::StatusChange,
+                        &path,
+                        Some(&link),
+                        json!({ "status": MergeStatus::Closed.to_string() }),
+                    );
+                    NotificationEvent::notify(
+                        NotificationKind::MrClosed,
+                        &user.name,
+                        author.into_iter().collect(),
+                        &format!("/mr/{}", link),
+                        &title,
+                        None,
+                    );
+                    CommonResult::success(None)
+                }
                 Err(err) => CommonResult::failed(&err.to_string()),
             };
             return Ok(Json(res));
@@ -97,26 +439,146 @@ async fn close_mr(
     Ok(Json(CommonResult::failed("not found")))
 }
 
+/// Open a merge request from a named branch of a directory, pushes to `main` open
+/// their merge request themselves.
+#[utoipa::path(
+    post,
+    path = "/mr/new",
+    request_body = CreateBranchMrRequest,
+    responses(
+        (status = 201, body = CommonResult<String>, description = "Link of the merge request"),
+    ),
+    tag = MR_TAG
+)]
+async fn create_mr(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<CreateBranchMrRequest>,
+) -> Result<(StatusCode, Json<CommonResult<String>>), ApiError> {
+    let source = match BranchPath::parse(&json.source) {
+        Ok(source) => source,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, Json(CommonResult::failed(&err)))),
+    };
+    let res = state
+        .monorepo()
+        .open_branch_mr(
+            &source,
+            &json.target,
+            json.title,
+            json.draft,
+            json.depends_on,
+            &user.name,
+        )
+        .await;
+    let (status, res) = match res {
+        Ok(link) => {
+            LiveUpdateEvent::notify(
+                LiveUpdateKind::StatusChange,
+                source.path.as_str(),
+                Some(&link),
+                json!({ "status": MergeStatus::Open.to_string() }),
+            );
+            (StatusCode::OK, CommonResult::success(Some(link)))
+        }
+        Err(err) => {
+            let status = match err {
+                GitError::EntryExists(_) => StatusCode::CONFLICT,
+                GitError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+                GitError::ObjectNotFound(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::OK,
+            };
+            (status, CommonResult::failed(&err.to_string()))
+        }
+    };
+    Ok((status, Json(res)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/merge",
+    params(("link" = String, Path, description = "Link of the merge request"), MergeParams),
+    responses(
+        (status = 200, body = CommonResult<MergeResult>),
+    ),
+    tag = MR_TAG
+)]
 async fn merge(
     user: LoginUser,
     Path(link): Path<String>,
+    Query(params): Query<MergeParams>,
     state: State<MonoApiServiceState>,
-) -> Result<Json<CommonResult<String>>, ApiError> {
+) -> Result<Json<CommonResult<MergeResult>>, ApiError> {
     if let Some(model) = state.mr_stg().get_mr(&link).await.unwrap() {
         if model.status == MergeStatus::Open {
             let path = model.path.clone();
-            util::check_permissions(
+            if util::check_permissions(
                 &user.name,
                 &path,
                 ActionEnum::ApproveMergeRequest,
                 state.clone(),
             )
             .await
-            .unwrap();
+            .is_err()
+            {
+                return Ok(Json(CommonResult::failed("permission denied")));
+            }
             ApiRequestEvent::notify(ApiType::MergeRequest, &state.0.context.config);
-            let res = state.monorepo().merge_mr(&mut model.into()).await;
+            let (author, title) = (model.author.clone(), model.title.clone());
+            let mut mr: MergeRequest = model.into();
+            let res = state
+                .monorepo()
+                .merge_mr(&mut mr, params.operation, &user.name)
+                .await;
             let res = match res {
-                Ok(_) => CommonResult::success(None),
+                Ok(data) if !data.merged => CommonResult {
+                    req_result: false,
+                    data: Some(data),
+                    err_message: "merge conflict".to_owned(),
+                },
+                Ok(data) => {
+                    AuditEvent::notify(
+                        &user.name,
+                        AuditAction::Merge,
+                        &path,
+                        json!({ "mr_link": link }),
+                    )
+                    .await;
+                    LiveUpdateEvent::notify(
+                        LiveUpdateKind::StatusChange,
+                        &path,
+                        Some(&link),
+                        json!({ "status": MergeStatus::Merged.to_string() }),
+                    );
+                    LiveUpdateEvent::notify(
+                        LiveUpdateKind::RefUpdate,
+                        &path,
+                        Some(&link),
+                        json!({ "reason": "merge" }),
+                    );
+                    // MRs built on this one go where it went
+                    match state.monorepo().retarget_dependents(&mr).await {
+                        Ok(dependents) => {
+                            for dependent in dependents {
+                                LiveUpdateEvent::notify(
+                                    LiveUpdateKind::RefUpdate,
+                                    &path,
+                                    Some(&dependent),
+                                    json!({ "reason": "retarget" }),
+                                );
+                            }
+                        }
+                        Err(err) => tracing::warn!("retarget MRs built on {}: {}", link, err),
+                    }
+                    NotificationEvent::notify(
+                        NotificationKind::MrMerged,
+                        &user.name,
+                        author.into_iter().collect(),
+                        &format!("/mr/{}", link),
+                        &title,
+                        None,
+                    );
+                    CommonResult::success(Some(data))
+                }
                 Err(err) => CommonResult::failed(&err.to_string()),
             };
             ApiRequestEvent::notify(ApiType::MergeDone, &state.0.context.config);
@@ -126,33 +588,113 @@ async fn merge(
     Ok(Json(CommonResult::failed("not found")))
 }
 
+#[utoipa::path(
+    post,
+    path = "/mr/list",
+    request_body = PageParams<MRStatusParams>,
+    responses(
+        (status = 200, body = CommonResult<Page<MrInfoItem>>),
+    ),
+    tag = MR_TAG
+)]
 async fn fetch_mr_list(
     state: State<MonoApiServiceState>,
     Json(json): Json<PageParams<MRStatusParams>>,
-) -> Result<Json<CommonResult<CommonPage<MrInfoItem>>>, ApiError> {
+) -> Result<Json<CommonResult<Page<MrInfoItem>>>, ApiError> {
     ApiRequestEvent::notify(ApiType::MergeList, &state.0.context.config);
-    let status = json.additional.status;
-    let status = if status == "open" {
-        vec![MergeStatus::Open]
-    } else if status == "closed" {
-        vec![MergeStatus::Closed, MergeStatus::Merged]
-    } else {
-        vec![MergeStatus::Open, MergeStatus::Closed, MergeStatus::Merged]
+    let filter = MrFilter {
+        status: parse_status(&json.additional.status),
+        path: None,
+        label: json.additional.label,
+        assignee: json.additional.assignee,
+        milestone: json.additional.milestone,
+    };
+    let pagination = json.pagination.normalized();
+    let res = match state.mr_stg().get_mr_by_filter(&filter, pagination).await {
+        Ok((items, total)) => {
+            CommonResult::success(Some(Page::new(items, total, &pagination).map(|m| m.into())))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr",
+    params(MRListQuery),
+    responses(
+        (status = 200, body = CommonResult<Page<MrInfoItem>>),
+    ),
+    tag = MR_TAG
+)]
+async fn list_mr(
+    Query(query): Query<MRListQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Page<MrInfoItem>>>, ApiError> {
+    ApiRequestEvent::notify(ApiType::MergeList, &state.0.context.config);
+    let pagination = query.pagination();
+    let filter = MrFilter {
+        status: parse_status(query.status.as_deref().unwrap_or_default()),
+        path: query.path,
+        label: query.label,
+        assignee: query.assignee,
+        milestone: query.milestone,
+    };
+    let res = match state.mr_stg().get_mr_by_filter(&filter, pagination).await {
+        Ok((items, total)) => {
+            CommonResult::success(Some(Page::new(items, total, &pagination).map(|m| m.into())))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
     };
+    Ok(Json(res))
+}
+
+fn parse_status(status: &str) -> Vec<MergeStatus> {
+    match status {
+        "open" => vec![MergeStatus::Open],
+        "closed" => vec![MergeStatus::Closed, MergeStatus::Merged],
+        _ => vec![MergeStatus::Open, MergeStatus::Closed, MergeStatus::Merged],
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{link}/conversations",
+    params(("link" = String, Path, description = "Link of the merge request"), Pagination),
+    responses(
+        (status = 200, body = CommonResult<Page<MegaConversation>>),
+    ),
+    tag = MR_TAG
+)]
+async fn get_mr_conversations(
+    Path(link): Path<String>,
+    Query(pagination): Query<Pagination>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Page<MegaConversation>>>, ApiError> {
+    let pagination = pagination.normalized();
     let res = match state
         .mr_stg()
-        .get_mr_by_status(status, json.pagination.page, json.pagination.per_page)
+        .get_mr_conversations_page(&link, pagination)
         .await
     {
-        Ok((items, total)) => CommonResult::success(Some(CommonPage {
-            items: items.into_iter().map(|m| m.into()).collect(),
-            total,
-        })),
+        Ok((items, total)) => {
+            CommonResult::success(Some(Page::new(items, total, &pagination).map(|m| m.into())))
+        }
         Err(err) => CommonResult::failed(&err.to_string()),
     };
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    get,
+    path = "/mr/{link}",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<MRDetail>),
+    ),
+    tag = MR_TAG
+)]
 async fn mr_detail(
     Path(link): Path<String>,
     state: State<MonoApiServiceState>,
@@ -161,7 +703,21 @@ async fn mr_detail(
     let res = match state.mr_stg().get_mr(&link).await {
         Ok(data) => {
             if let Some(model) = data {
+                let checks = load_check_runs(&state.check_stg(), &model.to_hash)
+                    .await
+                    .unwrap();
+                let monorepo = state.monorepo();
+                let commits = monorepo
+                    .convert_commits_to_info(monorepo.get_mr_commits(&model.clone().into()).await?)
+                    .await?;
+                let diff = monorepo
+                    .commit_diff(Some(model.from_hash.clone()), model.to_hash.clone())
+                    .await?;
                 let mut detail: MRDetail = model.into();
+                detail.checks = checks;
+                detail.diffstat = DiffStat::from_files(&diff.files);
+                detail.verified = !commits.is_empty() && commits.iter().all(|x| x.verified);
+                detail.commits = commits;
                 let conversations = state.mr_stg().get_mr_conversations(&link).await.unwrap();
                 detail.conversations = conversations.into_iter().map(|x| x.into()).collect();
                 CommonResult::success(Some(detail))
@@ -174,13 +730,32 @@ async fn mr_detail(
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    get,
+    path = "/mr/{link}/files-changed",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<FilesChangedList>),
+    ),
+    tag = MR_TAG
+)]
 async fn get_mr_files_changed(
+    user: Option<LoginUser>,
     Path(link): Path<String>,
     state: State<MonoApiServiceState>,
 ) -> Result<Json<CommonResult<FilesChangedList>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await? else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    let acl = util::path_acl(user.as_ref(), &state).await?;
     let res = state.monorepo().content_diff(&link).await;
     let res = match res {
         Ok(data) => {
+            // paths in the diff are relative to the directory of the MR
+            let dir = model.path.trim_end_matches('/');
+            let data = retain_diff_files(&data, |file| {
+                acl.allows(&format!("{}/{}", dir, file), AclPermission::Read)
+            });
             let diff_files = extract_files_with_status(&data);
             let mut diff_list: Vec<FilesChangedItem> = vec![];
             for (path, status) in diff_files {
@@ -198,6 +773,88 @@ async fn get_mr_files_changed(
     Ok(Json(res))
 }
 
+/// Files changed by the MR with their status, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/mr/{link}/files",
+    params(("link" = String, Path, description = "Link of the merge request"), Pagination),
+    responses(
+        (status = 200, body = CommonResult<Page<ChangedFile>>),
+    ),
+    tag = MR_TAG
+)]
+async fn get_mr_files(
+    user: Option<LoginUser>,
+    Path(link): Path<String>,
+    Query(pagination): Query<Pagination>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Page<ChangedFile>>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await? else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    let acl = util::path_acl(user.as_ref(), &state).await?;
+    let pagination = pagination.normalized();
+    let res = match state.monorepo().mr_changed_files(&model.into()).await {
+        Ok(mut files) => {
+            files.retain(|x| util::shows_file(&acl, &x.path, x.renamed_from.as_deref()));
+            let total = files.len() as u64;
+            let items = files
+                .into_iter()
+                .skip(pagination.offset() as usize)
+                .take(pagination.per_page as usize)
+                .collect();
+            CommonResult::success(Some(Page::new(items, total, &pagination)))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Unified diff hunks of one file changed by the MR, at `/mr/{link}/files/{path}/diff`.
+#[utoipa::path(
+    get,
+    path = "/mr/{link}/files/{path}/diff",
+    params(
+        ("link" = String, Path, description = "Link of the merge request"),
+        ("path" = String, Path, description = "Path of the file"),
+        FileDiffQuery,
+    ),
+    responses(
+        (status = 200, body = CommonResult<FileDiff>),
+    ),
+    tag = MR_TAG
+)]
+async fn get_mr_file_diff(
+    user: Option<LoginUser>,
+    Path((link, path)): Path<(String, String)>,
+    Query(query): Query<FileDiffQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<FileDiff>>, ApiError> {
+    let Some(path) = path.strip_suffix("/diff") else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    let Some(model) = state.mr_stg().get_mr(&link).await? else {
+        return Ok(Json(CommonResult::failed("not found")));
+    };
+    let path = format!("/{}", path.trim_start_matches('/'));
+    let renamed_from = query
+        .renamed_from
+        .map(|x| format!("/{}", x.trim_start_matches('/')));
+    let acl = util::path_acl(user.as_ref(), &state).await?;
+    if !util::shows_file(&acl, &path, renamed_from.as_deref()) {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let res = match state
+        .monorepo()
+        .mr_file_diff(&model.into(), &path, renamed_from.as_deref())
+        .await
+    {
+        Ok(file) => CommonResult::success(Some(file)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
 async fn save_comment(
     user: LoginUser,
     Path(link): Path<String>,
@@ -214,10 +871,24 @@ async fn save_comment(
                 &model.link,
                 user.user_id,
                 ConvType::Comment,
-                Some(json_string),
+                Some(json_string.clone()),
             )
             .await
             .unwrap();
+        LiveUpdateEvent::notify(
+            LiveUpdateKind::Comment,
+            &model.path,
+            Some(&model.link),
+            json!({ "user_id": user.user_id, "user_name": user.name }),
+        );
+        NotificationEvent::notify(
+            NotificationKind::Mention,
+            &user.name,
+            mentions(&json_string),
+            &format!("/mr/{}", model.link),
+            &model.title,
+            Some(json_string),
+        );
         CommonResult::success(None)
     } else {
         CommonResult::failed("Invalid link")
@@ -236,6 +907,251 @@ async fn delete_comment(
     Ok(Json(res))
 }
 
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/approve",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
+async fn approve_mr(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    review_mr(user, link, ReviewState::Approved, state).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/request-changes",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<String>),
+    ),
+    tag = MR_TAG
+)]
+async fn request_changes(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    review_mr(user, link, ReviewState::ChangesRequested, state).await
+}
+
+async fn review_mr(
+    user: LoginUser,
+    link: String,
+    review: ReviewState,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("Invalid link")));
+    };
+    if model.status != MergeStatus::Open {
+        return Ok(Json(CommonResult::failed("MR is not open")));
+    }
+    if util::check_permissions(
+        &user.name,
+        &model.path,
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let stg = state.mr_stg();
+    let (conv_type, comment) = match review {
+        ReviewState::Approved => (ConvType::Approve, format!("{} approved this", user.name)),
+        ReviewState::ChangesRequested => {
+            (ConvType::Review, format!("{} requested changes", user.name))
+        }
+    };
+    let res = match stg
+        .save_mr_review(&link, user.user_id, &user.name, review)
+        .await
+    {
+        Ok(_) => {
+            stg.add_mr_conversation(&link, user.user_id, conv_type, Some(comment))
+                .await
+                .unwrap();
+            LiveUpdateEvent::notify(
+                LiveUpdateKind::StatusChange,
+                &model.path,
+                Some(&link),
+                json!({ "review": review, "user_name": user.name }),
+            );
+            CommonResult::success(None)
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{link}/reviews",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<MrReviews>),
+    ),
+    tag = MR_TAG
+)]
+async fn get_mr_reviews(
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<MrReviews>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("Invalid link")));
+    };
+    let res = match state.mr_stg().get_mr_reviews(&link).await {
+        Ok(reviews) => CommonResult::success(Some(MrReviews {
+            required: state
+                .0
+                .context
+                .config
+                .monorepo
+                .required_approvals(&model.path),
+            approvals: reviews
+                .iter()
+                .filter(|x| x.state == ReviewState::Approved)
+                .count(),
+            reviews: reviews.into_iter().map(|x| x.into()).collect(),
+        })),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    get,
+    path = "/mr/{link}/review-comments",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    responses(
+        (status = 200, body = CommonResult<Vec<ReviewThread>>),
+    ),
+    tag = MR_TAG
+)]
+async fn get_review_comments(
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<ReviewThread>>>, ApiError> {
+    let res = match state.mr_stg().get_mr_review_comments(&link).await {
+        Ok(comments) => CommonResult::success(Some(ReviewThread::from_comments(comments))),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    post,
+    path = "/mr/{link}/review-comments",
+    params(("link" = String, Path, description = "Link of the merge request")),
+    request_body = ReviewCommentParams,
+    responses(
+        (status = 200, body = CommonResult<i64>, description = "Id of the comment"),
+    ),
+    tag = MR_TAG
+)]
+async fn save_review_comment(
+    user: LoginUser,
+    Path(link): Path<String>,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<ReviewCommentParams>,
+) -> Result<Json<CommonResult<i64>>, ApiError> {
+    let Some(model) = state.mr_stg().get_mr(&link).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("Invalid link")));
+    };
+    let stg = state.mr_stg();
+    let comment = json.comment.clone();
+    let res = match (json.reply_to, json.path, json.commit_id, json.line) {
+        (Some(reply_to), ..) => {
+            stg.reply_mr_review_comment(&link, user.user_id, reply_to, json.comment)
+                .await
+        }
+        (None, Some(path), Some(commit_id), Some(line)) if line > 0 => stg
+            .add_mr_review_comment(&link, user.user_id, &path, &commit_id, line, json.comment)
+            .await
+            .map(Some),
+        _ => Ok(None),
+    };
+    let res = match res {
+        Ok(Some(id)) => {
+            LiveUpdateEvent::notify(
+                LiveUpdateKind::Comment,
+                &model.path,
+                Some(&model.link),
+                json!({ "user_id": user.user_id, "user_name": user.name, "review_comment": id }),
+            );
+            NotificationEvent::notify(
+                NotificationKind::Mention,
+                &user.name,
+                mentions(&comment),
+                &format!("/mr/{}", model.link),
+                &model.title,
+                Some(comment),
+            );
+            CommonResult::success(Some(id))
+        }
+        Ok(None) => {
+            CommonResult::failed("either a valid reply_to or path, commit_id and line are required")
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn resolve_comment(
+    Path(conv_id): Path<i64>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    set_resolved(conv_id, true, state).await
+}
+
+async fn unresolve_comment(
+    Path(conv_id): Path<i64>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    set_resolved(conv_id, false, state).await
+}
+
+async fn set_resolved(
+    conv_id: i64,
+    resolved: bool,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let res = match state
+        .mr_stg()
+        .set_review_thread_resolved(conv_id, resolved)
+        .await
+    {
+        Ok(true) => CommonResult::success(None),
+        Ok(false) => CommonResult::failed("not a review comment"),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Keep the files of a `diff --git` output `keep` accepts, both sides of a rename.
+fn retain_diff_files(diff_output: &str, keep: impl Fn(&str) -> bool) -> String {
+    let mut chunks = diff_output.split("diff --git ");
+    let mut res = chunks.next().unwrap_or_default().to_owned();
+    for chunk in chunks {
+        let mut names = chunk.split_whitespace();
+        let old = names.next().unwrap_or_default().trim_start_matches("a/");
+        let new = names.next().unwrap_or_default().trim_start_matches("b/");
+        if keep(old) && keep(new) {
+            res.push_str("diff --git ");
+            res.push_str(chunk);
+        }
+    }
+    res
+}
+
 fn extract_files_with_status(diff_output: &str) -> HashMap<String, String> {
     let mut files = HashMap::new();
 
@@ -260,7 +1176,10 @@ fn extract_files_with_status(diff_output: &str) -> HashMap<String, String> {
 mod test {
     use std::collections::HashMap;
 
-    use crate::api::mr::mr_router::extract_files_with_status;
+    use callisto::{db_enums::ConvType, mega_conversation};
+
+    use crate::api::mr::mr_router::{extract_files_with_status, retain_diff_files};
+    use crate::api::mr::ReviewThread;
 
     #[test]
     fn test_parse_diff_result_to_filelist() {
@@ -296,4 +1215,49 @@ mod test {
 
         assert_eq!(files_with_status, expected);
     }
+
+    #[test]
+    fn test_retain_diff_files() {
+        let diff_output = "diff --git a/src/lib.rs b/src/lib.rs\n+a\n\
+            diff --git a/secret/key b/secret/key\n+b\n\
+            diff --git a/secret/old b/src/new\n+c\n";
+        let res = retain_diff_files(diff_output, |x| !x.starts_with("secret/"));
+        assert_eq!(res, "diff --git a/src/lib.rs b/src/lib.rs\n+a\n");
+    }
+
+    fn review_comment(id: i64, reply_to: Option<i64>) -> mega_conversation::Model {
+        mega_conversation::Model {
+            id,
+            link: "MR1".to_owned(),
+            user_id: 0,
+            conv_type: ConvType::ReviewComment,
+            comment: Some(format!("comment {}", id)),
+            path: Some("/src/lib.rs".to_owned()),
+            commit_id: Some("a".repeat(40)),
+            line: Some(3),
+            reply_to,
+            resolved: id == 1,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_review_threads() {
+        let comments = vec![
+            review_comment(1, None),
+            review_comment(2, None),
+            review_comment(3, Some(1)),
+            review_comment(4, Some(9)),
+        ];
+        let threads = ReviewThread::from_comments(comments);
+        assert_eq!(threads.len(), 2);
+        assert!(threads[0].resolved);
+        assert_eq!(
+            threads[0].comments.iter().map(|x| x.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(threads[1].comments.len(), 1);
+        assert_eq!(threads[1].line, 3);
+    }
 }
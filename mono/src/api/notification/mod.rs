@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use callisto::db_enums::NotificationKind;
+use callisto::mega_notification;
+use common::model::Pagination;
+
+pub mod notification_router;
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    /// only the notifications not read yet
+    #[serde(default)]
+    pub unread: bool,
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+impl NotificationQuery {
+    pub fn pagination(&self) -> Pagination {
+        let default = Pagination::default();
+        Pagination {
+            page: self.page.unwrap_or(default.page),
+            per_page: self.per_page.unwrap_or(default.per_page),
+        }
+        .normalized()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkReadParams {
+    /// all notifications of the user when left out
+    pub ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationItem {
+    pub id: i64,
+    pub kind: NotificationKind,
+    pub actor: String,
+    pub target: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub read: bool,
+    pub created_at: i64,
+}
+
+impl From<mega_notification::Model> for NotificationItem {
+    fn from(value: mega_notification::Model) -> Self {
+        Self {
+            id: value.id,
+            kind: value.kind,
+            actor: value.actor,
+            target: value.target,
+            title: value.title,
+            body: value.body,
+            read: value.read,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+/// User names mentioned as `@name` in a comment, each once. An `@` within a word, as in
+/// an email address, isn't a mention.
+pub fn mentions(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut prev = None;
+    for (i, c) in text.char_indices() {
+        if c == '@' && !prev.is_some_and(|p: char| p.is_alphanumeric() || p == '_') {
+            let name: String = text[i + 1..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+                .collect();
+            let name = name.trim_end_matches('-');
+            if !name.is_empty() && !names.iter().any(|x| x == name) {
+                names.push(name.to_owned());
+            }
+        }
+        prev = Some(c);
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::mentions;
+
+    #[test]
+    fn test_mentions() {
+        assert_eq!(
+            mentions("@alice could you and @bob-smith- look? cc @alice, mail a@example.com"),
+            vec!["alice", "bob-smith"]
+        );
+        assert!(mentions("no one @ all").is_empty());
+        assert_eq!(mentions("(@carol_1)"), vec!["carol_1"]);
+    }
+}
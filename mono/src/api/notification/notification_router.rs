@@ -0,0 +1,69 @@
+use axum::{
+    extract::{Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+
+use common::model::{CommonResult, Page};
+
+use crate::api::error::ApiError;
+use crate::api::notification::{MarkReadParams, NotificationItem, NotificationQuery};
+use crate::api::oauth::model::LoginUser;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new().nest(
+        "/notifications",
+        Router::new()
+            .route("/", get(list_notifications))
+            .route("/unread-count", get(unread_count))
+            .route("/read", post(mark_read)),
+    )
+}
+
+/// Notifications of the user, the latest first.
+async fn list_notifications(
+    user: LoginUser,
+    Query(query): Query<NotificationQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Page<NotificationItem>>>, ApiError> {
+    let pagination = query.pagination();
+    let res = match state
+        .context
+        .notification_stg()
+        .list(user.user_id, query.unread, pagination)
+        .await
+    {
+        Ok((items, total)) => {
+            CommonResult::success(Some(Page::new(items, total, &pagination).map(Into::into)))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn unread_count(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<u64>>, ApiError> {
+    let count = state
+        .context
+        .notification_stg()
+        .unread_count(user.user_id)
+        .await?;
+    Ok(Json(CommonResult::success(Some(count))))
+}
+
+/// Mark notifications of the user read, returns how many were unread.
+async fn mark_read(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<MarkReadParams>,
+) -> Result<Json<CommonResult<u64>>, ApiError> {
+    let count = state
+        .context
+        .notification_stg()
+        .mark_read(user.user_id, json.ids)
+        .await?;
+    Ok(Json(CommonResult::success(Some(count))))
+}
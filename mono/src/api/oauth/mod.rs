@@ -1,8 +1,11 @@
 use anyhow::Context;
 use async_session::{MemoryStore, Session, SessionStore};
 use axum::{
-    extract::{FromRef, FromRequestParts, Query, State},
+    extract::{
+        FromRef, FromRequestParts, MatchedPath, OptionalFromRequestParts, Query, Request, State,
+    },
     http::{header::SET_COOKIE, HeaderMap},
+    middleware::Next,
     response::{IntoResponse, Redirect, Response},
     routing::get,
     RequestPartsExt, Router,
@@ -10,14 +13,15 @@ use axum::{
 use axum_extra::{headers, typed_header::TypedHeaderRejectionReason, TypedHeader};
 use callisto::user;
 use chrono::{Duration, Utc};
-use http::{header, request::Parts, StatusCode};
+use http::{header, request::Parts, Method, StatusCode};
 use oauth2::{
     basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
     ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
 
 use common::config::OauthConfig;
-use jupiter::storage::user_storage::UserStorage;
+use common::model::TokenScope;
+use jupiter::storage::user_storage::{token_scopes, UserStorage};
 use model::{GitHubUserJson, LoginUser, OauthCallbackParams};
 
 use crate::api::error::ApiError;
@@ -200,6 +204,10 @@ where
     type Rejection = AuthRedirect;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // resolved from an access token by `token_auth`
+        if let Some(user) = parts.extensions.get::<LoginUser>() {
+            return Ok(user.clone());
+        }
         let store = MemoryStore::from_ref(state);
 
         let cookies = parts
@@ -225,3 +233,122 @@ where
         Ok(user)
     }
 }
+
+/// `Option<LoginUser>` is `None` for anonymous requests instead of refusing them.
+impl<S> OptionalFromRequestParts<S> for LoginUser
+where
+    MemoryStore: FromRef<S>,
+    UserStorage: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(
+            <LoginUser as FromRequestParts<S>>::from_request_parts(parts, state)
+                .await
+                .ok(),
+        )
+    }
+}
+
+/// Middleware resolving `Authorization: Bearer <token>` to the user owning the access token,
+/// who is then returned by the `LoginUser` extractor. Requests the scopes of the token don't
+/// cover are refused, requests without a bearer token are passed on untouched.
+pub async fn token_auth(
+    State(state): State<MonoApiServiceState>,
+    matched_path: Option<MatchedPath>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = bearer_token(req.headers()) else {
+        return next.run(req).await;
+    };
+    let (user, token) = match state.user_stg().resolve_token(&token).await {
+        Ok(Some(res)) => res,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "Invalid token").into_response(),
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let route = matched_path
+        .map(|x| x.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let allowed = required_scope(req.method(), &route)
+        .is_some_and(|x| x.granted_by(&token_scopes(&token.scopes)));
+    if !allowed {
+        return (
+            StatusCode::FORBIDDEN,
+            "The token has no scope for this request",
+        )
+            .into_response();
+    }
+    req.extensions_mut().insert(LoginUser::from(user));
+    next.run(req).await
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    value.strip_prefix("Bearer ").map(|x| x.trim().to_owned())
+}
+
+/// Scope a token needs for a request, `None` when tokens can't be used for it at all.
+/// Changing the account, tokens included, needs a login session.
+fn required_scope(method: &Method, route: &str) -> Option<TokenScope> {
+    let read = method == Method::GET || method == Method::HEAD;
+    let route = route.strip_prefix("/api/v1").unwrap_or(route);
+    let segments: Vec<&str> = route.split('/').filter(|x| !x.is_empty()).collect();
+    match segments.as_slice() {
+        ["user", "token", ..] => None,
+        ["user", ..] if !read => None,
+        ["mr", _, "merge"] => Some(TokenScope::MrMerge),
+        _ if read => Some(TokenScope::RepoRead),
+        _ => Some(TokenScope::RepoWrite),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http::Method;
+
+    use common::model::TokenScope;
+
+    use super::required_scope;
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(
+            required_scope(&Method::GET, "/tree/commit-info"),
+            Some(TokenScope::RepoRead)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/update-file"),
+            Some(TokenScope::RepoWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/mr/{link}/merge"),
+            Some(TokenScope::MrMerge)
+        );
+        assert_eq!(required_scope(&Method::GET, "/user/token/list"), None);
+        assert_eq!(required_scope(&Method::POST, "/user/delete"), None);
+        assert_eq!(
+            required_scope(&Method::GET, "/user/profile/{name}"),
+            Some(TokenScope::RepoRead)
+        );
+        // only whole segments at the start of the route count
+        assert_eq!(
+            required_scope(&Method::POST, "/tree/user/token/create"),
+            Some(TokenScope::RepoWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/mr/{link}/comment/merge"),
+            Some(TokenScope::RepoWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/v1/mr/abc/merge-box"),
+            Some(TokenScope::RepoWrite)
+        );
+        assert_eq!(required_scope(&Method::POST, "/user"), None);
+    }
+}
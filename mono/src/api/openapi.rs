@@ -0,0 +1,75 @@
+//! OpenAPI description of the code and merge request endpoints, for clients generated
+//! from it. The paths are relative to where the API router is nested, `/api/v1/mono` in
+//! the gateway.
+
+use utoipa::OpenApi;
+
+use crate::api::{api_router, mr::mr_router};
+
+pub const CODE_TAG: &str = "code";
+pub const MR_TAG: &str = "mr";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Mega API",
+        description = "Code browsing, file changes and merge requests of the monorepo"
+    ),
+    servers((url = "/api/v1/mono")),
+    paths(
+        api_router::get_blob_string,
+        api_router::get_raw_blob,
+        api_router::get_blob_batch,
+        api_router::get_blob_file,
+        api_router::get_tree_info,
+        api_router::get_tree_commit_info,
+        api_router::get_latest_commit,
+        api_router::create_file,
+        api_router::update_file,
+        api_router::delete_entry,
+        api_router::move_entry,
+        api_router::batch_commit,
+        mr_router::list_mr,
+        mr_router::fetch_mr_list,
+        mr_router::create_mr,
+        mr_router::mr_detail,
+        mr_router::merge,
+        mr_router::close_mr,
+        mr_router::reopen_mr,
+        mr_router::ready_mr,
+        mr_router::draft_mr,
+        mr_router::set_dependency,
+        mr_router::set_metadata,
+        mr_router::approve_mr,
+        mr_router::request_changes,
+        mr_router::get_mr_reviews,
+        mr_router::get_mr_files_changed,
+        mr_router::get_mr_files,
+        mr_router::get_mr_file_diff,
+        mr_router::get_mr_conversations,
+        mr_router::get_review_comments,
+        mr_router::save_review_comment,
+    ),
+    tags(
+        (name = CODE_TAG, description = "Trees, blobs and commits of files"),
+        (name = MR_TAG, description = "Merge requests and their reviews"),
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use utoipa::OpenApi;
+
+    use super::ApiDoc;
+
+    #[test]
+    fn test_api_doc() {
+        let doc = ApiDoc::openapi();
+        assert!(doc.paths.paths.contains_key("/tree"));
+        assert!(doc.paths.paths.contains_key("/mr/{link}/merge"));
+        let schemas = doc.components.unwrap().schemas;
+        assert!(schemas.contains_key("CreateFileInfo"));
+        assert!(schemas.contains_key("MRDetail"));
+    }
+}
@@ -0,0 +1,508 @@
+//! Server side previews for file formats that need a dedicated renderer.
+//!
+//! Notebooks are flattened into a list of cells, CSV/TSV files into a bounded table and
+//! SVG images are sanitized so they can be inlined by the UI without running scripts.
+//! Every renderer works on a size limited input and reports when it had to cut content.
+
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Files larger than this are not previewed at all.
+pub const MAX_PREVIEW_SIZE: usize = 5 * 1024 * 1024;
+const MAX_CSV_ROWS: usize = 1000;
+const MAX_CSV_COLUMNS: usize = 100;
+const MAX_OUTPUT_TEXT: usize = 64 * 1024;
+const MAX_OUTPUT_IMAGE: usize = 1024 * 1024;
+
+/// SVG elements that can execute code, load styles or embed foreign documents.
+const SVG_BLOCKED_ELEMENTS: [&str; 8] = [
+    "script",
+    "style",
+    "foreignobject",
+    "iframe",
+    "embed",
+    "object",
+    "handler",
+    "listener",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewKind {
+    Notebook,
+    Csv,
+    Tsv,
+    Svg,
+}
+
+impl PreviewKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "ipynb" => Some(PreviewKind::Notebook),
+            "csv" => Some(PreviewKind::Csv),
+            "tsv" => Some(PreviewKind::Tsv),
+            "svg" => Some(PreviewKind::Svg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlobPreview {
+    Notebook(NotebookPreview),
+    Table(TablePreview),
+    Svg(SvgPreview),
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct NotebookPreview {
+    pub language: Option<String>,
+    pub cells: Vec<NotebookCell>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotebookCell {
+    pub cell_type: String,
+    pub source: String,
+    pub execution_count: Option<i64>,
+    pub outputs: Vec<NotebookOutput>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct NotebookOutput {
+    pub output_type: String,
+    pub text: Option<String>,
+    /// base64 encoded png, as stored in the notebook
+    pub image_png: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TablePreview {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SvgPreview {
+    pub html: String,
+}
+
+pub fn render(kind: PreviewKind, data: &[u8]) -> Result<BlobPreview, String> {
+    if data.len() > MAX_PREVIEW_SIZE {
+        return Err(format!(
+            "file is larger than the preview limit of {} bytes",
+            MAX_PREVIEW_SIZE
+        ));
+    }
+    let text = std::str::from_utf8(data).map_err(|_| "file is not valid UTF-8".to_owned())?;
+    Ok(match kind {
+        PreviewKind::Notebook => BlobPreview::Notebook(render_notebook(text)?),
+        PreviewKind::Csv => BlobPreview::Table(render_table(text, ',')),
+        PreviewKind::Tsv => BlobPreview::Table(render_table(text, '\t')),
+        PreviewKind::Svg => BlobPreview::Svg(SvgPreview {
+            html: sanitize_svg(text),
+        }),
+    })
+}
+
+pub fn render_notebook(text: &str) -> Result<NotebookPreview, String> {
+    let notebook: Value =
+        serde_json::from_str(text).map_err(|err| format!("invalid notebook: {}", err))?;
+    let mut preview = NotebookPreview {
+        language: notebook["metadata"]["language_info"]["name"]
+            .as_str()
+            .or_else(|| notebook["metadata"]["kernelspec"]["language"].as_str())
+            .map(str::to_owned),
+        ..Default::default()
+    };
+    let cells = notebook["cells"]
+        .as_array()
+        .ok_or_else(|| "invalid notebook: missing cells".to_owned())?;
+    for cell in cells {
+        let mut outputs = Vec::new();
+        for output in cell["outputs"].as_array().into_iter().flatten() {
+            let mut res = NotebookOutput {
+                output_type: output["output_type"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_owned(),
+                ..Default::default()
+            };
+            let text = match res.output_type.as_str() {
+                "stream" => join_source(&output["text"]),
+                "error" => join_source(&output["traceback"]),
+                _ => join_source(&output["data"]["text/plain"]),
+            };
+            if !text.is_empty() {
+                let (text, cut) = truncate(text, MAX_OUTPUT_TEXT);
+                preview.truncated |= cut;
+                res.text = Some(text);
+            }
+            if let Some(png) = output["data"]["image/png"].as_str() {
+                let png: String = png.split_whitespace().collect();
+                if png.len() <= MAX_OUTPUT_IMAGE {
+                    res.image_png = Some(png);
+                } else {
+                    preview.truncated = true;
+                }
+            }
+            outputs.push(res);
+        }
+        preview.cells.push(NotebookCell {
+            cell_type: cell["cell_type"].as_str().unwrap_or("raw").to_owned(),
+            source: join_source(&cell["source"]),
+            execution_count: cell["execution_count"].as_i64(),
+            outputs,
+        });
+    }
+    Ok(preview)
+}
+
+/// Multiline fields in notebooks are either a string or a list of lines.
+fn join_source(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines.iter().filter_map(|x| x.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+fn truncate(mut text: String, limit: usize) -> (String, bool) {
+    if text.len() <= limit {
+        return (text, false);
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    (text, true)
+}
+
+/// Parse delimiter separated text with RFC 4180 quoting, the first record is the header.
+pub fn render_table(text: &str, delimiter: char) -> TablePreview {
+    let mut records: Vec<Vec<String>> = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut truncated = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                // header plus the row limit
+                if records.len() > MAX_CSV_ROWS {
+                    truncated = chars.peek().is_some();
+                    break;
+                }
+            }
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    for record in records.iter_mut() {
+        if record.len() > MAX_CSV_COLUMNS {
+            record.truncate(MAX_CSV_COLUMNS);
+            truncated = true;
+        }
+    }
+    let mut records = records.into_iter();
+    TablePreview {
+        header: records.next().unwrap_or_default(),
+        rows: records.collect(),
+        truncated,
+    }
+}
+
+/// Remove scripting and CSS from an SVG document: blocked elements, `<style>` included, are
+/// dropped with their content, event handler and `style` attributes are removed, and links
+/// may only point inside the document or to embedded images.
+pub fn sanitize_svg(svg: &str) -> String {
+    let mut out = String::with_capacity(svg.len());
+    let mut rest = svg;
+    // name of the blocked element being skipped and its nesting depth
+    let mut skipping: Option<(String, usize)> = None;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            out.push_str(&rest[..start]);
+        }
+        rest = &rest[start..];
+
+        // comments, doctype and processing instructions are dropped
+        if let Some(body) = rest.strip_prefix("<!--") {
+            rest = body.find("-->").map_or("", |end| &body[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").map_or(rest.len(), |end| end + 3);
+            if skipping.is_none() {
+                out.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        // an unterminated tag ends the document
+        let Some(end) = tag_end(rest) else {
+            return out;
+        };
+        let tag = &rest[1..end - 1];
+        rest = &rest[end..];
+        let closing = tag.starts_with('/');
+        let self_closing = tag.ends_with('/');
+        // SVG names are case sensitive, only the comparisons ignore case
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '/')
+            .collect();
+        // quotes balanced by `tag_end` could still carry markup inside a name
+        if !is_name(&name) {
+            continue;
+        }
+        let lower_name = name.to_ascii_lowercase();
+
+        if let Some((blocked, depth)) = skipping.as_mut() {
+            if lower_name == *blocked {
+                if closing {
+                    *depth -= 1;
+                } else if !self_closing {
+                    *depth += 1;
+                }
+                if *depth == 0 {
+                    skipping = None;
+                }
+            }
+            continue;
+        }
+        let local_name = lower_name.rsplit(':').next().unwrap_or_default();
+        if SVG_BLOCKED_ELEMENTS.contains(&local_name) {
+            if !closing && !self_closing {
+                skipping = Some((lower_name, 1));
+            }
+            continue;
+        }
+        if closing {
+            out.push_str("</");
+            out.push_str(&name);
+            out.push('>');
+            continue;
+        }
+
+        out.push('<');
+        out.push_str(&name);
+        let attrs_start = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        for (key, value) in parse_attributes(tag[attrs_start..].trim_end_matches('/')) {
+            if is_name(&key) && is_safe_attribute(&key, &value) {
+                out.push_str(&format!(" {}=\"{}\"", key, escape_attribute(&value)));
+            }
+        }
+        out.push_str(if self_closing { "/>" } else { ">" });
+    }
+    if skipping.is_none() {
+        out.push_str(rest);
+    }
+    out
+}
+
+/// Index just after the `>` closing the tag at the start of `s`, quotes are respected.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_attributes(s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let key: String =
+            std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != '/'))
+                .collect();
+        if key.is_empty() {
+            if chars.next().is_none() {
+                break;
+            }
+            continue;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.peek().copied() {
+                Some(q @ ('"' | '\'')) => {
+                    chars.next();
+                    value = std::iter::from_fn(|| chars.next_if(|c| *c != q)).collect();
+                    chars.next();
+                }
+                _ => {
+                    value = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect();
+                }
+            }
+        }
+        attrs.push((key, unescape_entities(&value)));
+    }
+    attrs
+}
+
+/// Whether `s` is a plain XML name, `[A-Za-z_:][-A-Za-z0-9_:.]*`.
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+}
+
+fn is_safe_attribute(key: &str, value: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    let local = key.rsplit(':').next().unwrap_or_default();
+    // CSS can load remote content and overlay the page, presentation attributes are kept
+    if local.starts_with("on") || local == "style" {
+        return false;
+    }
+    let compact: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if compact.contains("javascript:") || compact.contains("vbscript:") {
+        return false;
+    }
+    if local == "href" || local == "src" {
+        return compact.starts_with('#') || compact.starts_with("data:image/");
+    }
+    true
+}
+
+fn unescape_entities(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#58;", ":")
+        .replace("&colon;", ":")
+        .replace("&amp;", "&")
+}
+
+fn escape_attribute(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_notebook() {
+        let notebook = r##"{
+            "metadata": {"language_info": {"name": "python"}},
+            "cells": [
+                {"cell_type": "markdown", "source": ["# Title\n", "text"]},
+                {"cell_type": "code", "execution_count": 1, "source": "print(1)",
+                 "outputs": [{"output_type": "stream", "name": "stdout", "text": ["1\n"]}]}
+            ]
+        }"##;
+        let preview = render_notebook(notebook).unwrap();
+        assert_eq!(preview.language.as_deref(), Some("python"));
+        assert_eq!(preview.cells.len(), 2);
+        assert_eq!(preview.cells[0].source, "# Title\ntext");
+        assert_eq!(preview.cells[1].execution_count, Some(1));
+        assert_eq!(preview.cells[1].outputs[0].text.as_deref(), Some("1\n"));
+        assert!(render_notebook("{}").is_err());
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table("name,desc\r\na,\"x, \"\"y\"\"\"\nb,\"multi\nline\"\n", ',');
+        assert_eq!(table.header, vec!["name", "desc"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                vec!["a".to_owned(), "x, \"y\"".to_owned()],
+                vec!["b".to_owned(), "multi\nline".to_owned()]
+            ]
+        );
+        assert!(!table.truncated);
+
+        let big = "v\n".repeat(MAX_CSV_ROWS + 10);
+        let table = render_table(&big, ',');
+        assert_eq!(table.rows.len(), MAX_CSV_ROWS);
+        assert!(table.truncated);
+    }
+
+    #[test]
+    fn test_sanitize_svg() {
+        let svg = r##"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)"><script>alert(2)</script><a href="javascript:alert(3)"><rect width="10" height='5' fill="red"/></a><use href="#icon"/><foreignObject><div>x</div></foreignObject><text>a &lt; b</text></svg>"##;
+        let res = sanitize_svg(svg);
+        assert!(!res.contains("alert"));
+        assert!(!res.contains("foreignObject"));
+        assert!(sanitize_svg(r#"<svg viewBox="0 0 1 1"><clipPath/></svg>"#)
+            .contains(r#"<svg viewBox="0 0 1 1"><clipPath/></svg>"#));
+        assert!(res.contains(r#"<rect width="10" height="5" fill="red"/>"#));
+        assert!(res.contains(r##"<use href="#icon"/>"##));
+        assert!(res.contains("<text>a &lt; b</text>"));
+        assert!(res.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg">"#));
+
+        let svg = r#"<svg><style>@import url(https://evil.example/x.css);</style><rect style="position:fixed" fill="red"/></svg>"#;
+        assert_eq!(sanitize_svg(svg), r#"<svg><rect fill="red"/></svg>"#);
+
+        // markup hidden in quoted names doesn't get out of the tag
+        let svg =
+            r#"<svg><rect a"><style>*{background:url(https:\00002f\00002fevil.example)}"=1></svg>"#;
+        assert_eq!(sanitize_svg(svg), "<svg><rect></svg>");
+        let svg = r#"<svg><rect"><style>x</style>"/></svg>"#;
+        assert_eq!(sanitize_svg(svg), "<svg></svg>");
+    }
+
+    #[test]
+    fn test_preview_kind() {
+        assert_eq!(
+            PreviewKind::from_path(Path::new("/a/b.IPYNB")),
+            Some(PreviewKind::Notebook)
+        );
+        assert_eq!(PreviewKind::from_path(Path::new("/a/b.rs")), None);
+    }
+}
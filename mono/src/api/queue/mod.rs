@@ -0,0 +1,32 @@
+use serde::Serialize;
+
+use callisto::mq_dead_letter;
+
+pub mod queue_router;
+
+/// A queue message that failed every attempt to process it.
+#[derive(Debug, Serialize)]
+pub struct DeadLetterItem {
+    pub id: i64,
+    pub category: Option<String>,
+    /// the event as JSON
+    pub content: Option<String>,
+    pub attempts: i32,
+    pub error: String,
+    pub created_at: i64,
+    pub dead_at: i64,
+}
+
+impl From<mq_dead_letter::Model> for DeadLetterItem {
+    fn from(value: mq_dead_letter::Model) -> Self {
+        Self {
+            id: value.id,
+            category: value.category,
+            content: value.content,
+            attempts: value.attempts,
+            error: value.error,
+            created_at: value.create_time.and_utc().timestamp(),
+            dead_at: value.dead_at.and_utc().timestamp(),
+        }
+    }
+}
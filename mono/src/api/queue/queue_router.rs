@@ -0,0 +1,101 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+
+use common::model::CommonResult;
+use taurus::job::{self, JobInfo};
+use taurus::queue::{get_mq, QueueDepth};
+
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::queue::DeadLetterItem;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new()
+        .route("/admin/queue", get(queue_depth))
+        .route("/admin/queue/dead-letters", get(list_dead_letters))
+        .route(
+            "/admin/queue/dead-letters/{id}/requeue",
+            post(requeue_dead_letter),
+        )
+        .route("/admin/queue/jobs", get(list_jobs))
+        .route("/admin/queue/jobs/{name}/run", post(run_job))
+}
+
+/// Messages the queue holds, by what they wait for.
+async fn queue_depth(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<QueueDepth>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    Ok(Json(CommonResult::success(Some(get_mq().depth()))))
+}
+
+/// Messages that failed every attempt, the latest first.
+async fn list_dead_letters(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<DeadLetterItem>>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let res = match state.context.services.mq_storage.get_dead_letters().await {
+        Ok(list) => CommonResult::success(Some(list.into_iter().map(Into::into).collect())),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Queue a dead-lettered message again, returns the id of the new message.
+async fn requeue_dead_letter(
+    user: LoginUser,
+    Path(id): Path<i64>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<i64>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let res = match get_mq().requeue(id).await {
+        Ok(Some(new_id)) => CommonResult::success(Some(new_id)),
+        Ok(None) => CommonResult::failed("dead letter not found"),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Jobs run on a schedule, with their last and next runs.
+async fn list_jobs(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<JobInfo>>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let res = match job::list().await {
+        Ok(jobs) => CommonResult::success(Some(jobs)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Run a scheduled job now, its schedule goes on as before.
+async fn run_job(
+    user: LoginUser,
+    Path(name): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let res = match job::trigger(&name).await {
+        Ok(true) => CommonResult::success(None),
+        Ok(false) => CommonResult::failed("job not found"),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
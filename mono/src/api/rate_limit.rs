@@ -0,0 +1,353 @@
+//! Rate limiting of requests with token buckets, configured under `[rate_limit]`. A request
+//! with an access token counts against the token, one without against the client address,
+//! and git protocol requests against buckets of their own as they cost much more than
+//! JSON API requests.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose, Engine};
+use http::{header, HeaderMap, StatusCode};
+
+use common::config::{RateLimit, RateLimitConfig};
+use jupiter::context::Context;
+use jupiter::storage::user_storage::{hash_token, UserStorage};
+
+use crate::server::https_server::is_git_protocol_path;
+
+/// How long the id of an access token is remembered once looked up, unknown tokens
+/// aren't remembered so making them up can't fill the memory.
+const TOKEN_TTL: Duration = Duration::from_secs(60);
+
+/// How often buckets that filled up again and expired tokens are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Api,
+    Pack,
+}
+
+impl Class {
+    fn as_str(self) -> &'static str {
+        match self {
+            Class::Api => "api",
+            Class::Pack => "pack",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Client {
+    Token(i64),
+    Address(IpAddr),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Bucket {
+            tokens: capacity(limit),
+            updated: now,
+        }
+    }
+
+    // Take one request out of the bucket, or how long until there's one.
+    fn take(&mut self, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        self.tokens = self.level(limit, now);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate(limit)))
+        }
+    }
+
+    fn level(&self, limit: RateLimit, now: Instant) -> f64 {
+        let refill = now.duration_since(self.updated).as_secs_f64() * rate(limit);
+        (self.tokens + refill).min(capacity(limit))
+    }
+}
+
+fn capacity(limit: RateLimit) -> f64 {
+    f64::from(limit.burst.max(1))
+}
+
+// Requests a second.
+fn rate(limit: RateLimit) -> f64 {
+    f64::from(limit.per_minute) / 60.0
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    users: UserStorage,
+    buckets: Mutex<HashMap<(Client, Class), Bucket>>,
+    // Ids of the access tokens by the hash of the token.
+    tokens: Mutex<HashMap<String, (i64, Instant)>>,
+}
+
+impl RateLimiter {
+    /// A limiter configured by `[rate_limit]` of the context, when it's enabled a task
+    /// drops the state of clients gone quiet.
+    pub fn new(context: &Context) -> Arc<Self> {
+        let limiter = Arc::new(RateLimiter {
+            config: context.config.rate_limit.clone(),
+            users: context.user_stg(),
+            buckets: Mutex::new(HashMap::new()),
+            tokens: Mutex::new(HashMap::new()),
+        });
+        if limiter.config.enabled {
+            let weak = Arc::downgrade(&limiter);
+            tokio::spawn(async move {
+                let mut timer = tokio::time::interval(SWEEP_INTERVAL);
+                loop {
+                    timer.tick().await;
+                    let Some(limiter) = weak.upgrade() else {
+                        break;
+                    };
+                    limiter.sweep(Instant::now());
+                }
+            });
+        }
+        limiter
+    }
+
+    fn limit(&self, client: Client, class: Class) -> RateLimit {
+        match (client, class) {
+            (Client::Token(_), Class::Api) => self.config.token_api,
+            (Client::Token(_), Class::Pack) => self.config.token_pack,
+            (Client::Address(_), Class::Api) => self.config.api,
+            (Client::Address(_), Class::Pack) => self.config.pack,
+        }
+    }
+
+    fn take(&self, client: Client, class: Class, now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(client, class);
+        if limit.per_minute == 0 {
+            return Ok(());
+        }
+        self.buckets
+            .lock()
+            .unwrap()
+            .entry((client, class))
+            .or_insert_with(|| Bucket::new(limit, now))
+            .take(limit, now)
+    }
+
+    // Take one request out of the bucket of the client. A token not looked up lately is
+    // paid for by the client address first, so made up tokens neither get around the
+    // limits nor cost a query each without limit, and unknown ones count against it.
+    async fn check(
+        &self,
+        token: Option<&str>,
+        address: Option<IpAddr>,
+        class: Class,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let Some(token) = token else {
+            return match address {
+                Some(address) => self.take(Client::Address(address), class, now),
+                // not served over a socket, e.g. in tests
+                None => Ok(()),
+            };
+        };
+        let hash = hash_token(token);
+        let cached = self.tokens.lock().unwrap().get(&hash).copied();
+        if let Some((id, at)) = cached {
+            if now.duration_since(at) < TOKEN_TTL {
+                return self.take(Client::Token(id), class, now);
+            }
+        }
+        if let Some(address) = address {
+            self.take(Client::Address(address), class, now)?;
+        }
+        let id = match self.users.find_token_id(token).await {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::error!("Failed to look up an access token: {e}");
+                None
+            }
+        };
+        match id {
+            Some(id) => {
+                let now = Instant::now();
+                self.tokens.lock().unwrap().insert(hash, (id, now));
+                self.take(Client::Token(id), class, now)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn client_address(&self, req: &Request) -> Option<IpAddr> {
+        if self.config.trust_forwarded_for {
+            let forwarded = forwarded_address(req.headers(), self.config.forwarded_hops);
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        req.extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|x| x.0.ip())
+    }
+
+    fn sweep(&self, now: Instant) {
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|(client, class), bucket| {
+                let limit = self.limit(*client, *class);
+                bucket.level(limit, now) < capacity(limit)
+            });
+        self.tokens
+            .lock()
+            .unwrap()
+            .retain(|_, (_, at)| now.duration_since(*at) < TOKEN_TTL);
+    }
+}
+
+/// Middleware answering requests over the limits with 429 Too Many Requests and
+/// `Retry-After`, apply it with `axum::middleware::from_fn_with_state`.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !limiter.config.enabled {
+        return next.run(req).await;
+    }
+    let class = if is_git_protocol_path(req.uri().path()) {
+        Class::Pack
+    } else {
+        Class::Api
+    };
+    let token = request_token(req.headers());
+    let address = limiter.client_address(&req);
+    match limiter.check(token.as_deref(), address, class).await {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            metrics::counter!("mega_http_rate_limited_total", "class" => class.as_str())
+                .increment(1);
+            let secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, secs.to_string())],
+                format!("Too many requests, retry after {} seconds", secs),
+            )
+                .into_response()
+        }
+    }
+}
+
+// The client address in `X-Forwarded-For` as seen by the outermost of `hops` proxies each
+// appending the address it got the request from, `None` when there are fewer entries.
+fn forwarded_address(headers: &HeaderMap, hops: usize) -> Option<IpAddr> {
+    let value = headers.get("x-forwarded-for")?.to_str().ok()?;
+    let entry = value.rsplit(',').nth(hops.checked_sub(1)?)?;
+    entry.trim().parse().ok()
+}
+
+// The access token of a request, sent as a bearer token to the API or as the password of
+// basic auth by git.
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        return Some(token.trim().to_owned());
+    }
+    let decoded = general_purpose::STANDARD
+        .decode(value.strip_prefix("Basic ")?.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (_, password) = credentials.split_once(':')?;
+    (!password.is_empty()).then(|| password.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use http::{header, HeaderMap, HeaderValue};
+
+    use common::config::RateLimit;
+
+    use super::{forwarded_address, request_token, Bucket};
+
+    #[test]
+    fn test_bucket() {
+        let limit = RateLimit {
+            per_minute: 60,
+            burst: 2,
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(limit, now);
+        assert!(bucket.take(limit, now).is_ok());
+        assert!(bucket.take(limit, now).is_ok());
+        let wait = bucket.take(limit, now).unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        // one request a second comes back
+        let later = now + Duration::from_secs(1);
+        assert!(bucket.take(limit, later).is_ok());
+        assert!(bucket.take(limit, later).is_err());
+        // never more than the burst
+        let much_later = now + Duration::from_secs(3600);
+        assert!(bucket.take(limit, much_later).is_ok());
+        assert!(bucket.take(limit, much_later).is_ok());
+        assert!(bucket.take(limit, much_later).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_address() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forwarded_address(&headers, 1), None);
+        // the client made up the first entry, the proxy appended the real one
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("10.0.0.1, 203.0.113.7"),
+        );
+        assert_eq!(
+            forwarded_address(&headers, 1),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            forwarded_address(&headers, 2),
+            Some("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(forwarded_address(&headers, 3), None);
+        assert_eq!(forwarded_address(&headers, 0), None);
+    }
+
+    #[test]
+    fn test_request_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_token(&headers), None);
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer mega_abc"),
+        );
+        assert_eq!(request_token(&headers).as_deref(), Some("mega_abc"));
+        // `user:mega_abc`
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic dXNlcjptZWdhX2FiYw=="),
+        );
+        assert_eq!(request_token(&headers).as_deref(), Some("mega_abc"));
+        // `user:`
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic dXNlcjo="),
+        );
+        assert_eq!(request_token(&headers), None);
+    }
+}
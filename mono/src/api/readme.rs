@@ -0,0 +1,230 @@
+//! The README of a directory, shown by the UI under the listing as a project page.
+//!
+//! Markdown is rendered to HTML on the server and sanitized, so the UI can insert it as
+//! is. Relative links and images are rewritten to the blob and raw endpoints, they'd
+//! resolve against the page of the UI otherwise.
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use serde::Serialize;
+use url::form_urlencoded;
+
+use ceres::model::tree::TreeBriefItem;
+use mercury::internal::object::tree::{TreeItem, TreeItemMode};
+
+use crate::api::preview::MAX_PREVIEW_SIZE;
+
+/// Names looked for, the first one found in a directory is its README.
+const README_NAMES: [(&str, ReadmeFormat); 5] = [
+    ("readme.md", ReadmeFormat::Markdown),
+    ("readme.markdown", ReadmeFormat::Markdown),
+    ("readme.rst", ReadmeFormat::Rst),
+    ("readme.txt", ReadmeFormat::Text),
+    ("readme", ReadmeFormat::Text),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadmeFormat {
+    Markdown,
+    /// shown as text, there's no renderer for it
+    Rst,
+    Text,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readme {
+    pub name: String,
+    pub path: String,
+    pub format: ReadmeFormat,
+    /// sanitized HTML of a markdown README
+    pub html: Option<String>,
+    /// text of the other formats
+    pub content: Option<String>,
+    /// the README is over the preview limit, neither is set
+    pub too_large: bool,
+}
+
+/// A directory with its README, if it has one.
+#[derive(Debug, Serialize)]
+pub struct DirectoryPage {
+    pub items: Vec<TreeBriefItem>,
+    pub readme: Option<Readme>,
+}
+
+/// The README among the entries of a directory.
+pub fn find_readme(items: &[TreeItem]) -> Option<(&TreeItem, ReadmeFormat)> {
+    README_NAMES.iter().find_map(|(name, format)| {
+        items
+            .iter()
+            .find(|x| {
+                matches!(x.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable)
+                    && x.name.eq_ignore_ascii_case(name)
+            })
+            .map(|x| (x, *format))
+    })
+}
+
+/// `dir` is the directory of the README, `api_base` where the API is served, as
+/// `/api/v1/mono`.
+pub fn render_readme(
+    name: &str,
+    dir: &str,
+    format: ReadmeFormat,
+    data: &[u8],
+    api_base: &str,
+) -> Readme {
+    let mut readme = Readme {
+        name: name.to_owned(),
+        path: join_path(dir, name),
+        format,
+        html: None,
+        content: None,
+        too_large: data.len() > MAX_PREVIEW_SIZE,
+    };
+    if readme.too_large {
+        return readme;
+    }
+    let text = String::from_utf8_lossy(data);
+    match format {
+        ReadmeFormat::Markdown => readme.html = Some(render_markdown(&text, dir, api_base)),
+        ReadmeFormat::Rst | ReadmeFormat::Text => readme.content = Some(text.into_owned()),
+    }
+    readme
+}
+
+pub fn render_markdown(text: &str, dir: &str, api_base: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let events = Parser::new_ext(text, options).map(|event| match event {
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: rewrite_url(dest_url, dir, api_base, false),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: rewrite_url(dest_url, dir, api_base, true),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut out = String::with_capacity(text.len() * 3 / 2);
+    html::push_html(&mut out, events);
+    ammonia::Builder::default()
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(&out)
+        .to_string()
+}
+
+// Links into the repository point to the blob endpoint, images to the raw one so
+// browsers can show them. Urls with a scheme and anchors are left alone.
+fn rewrite_url<'a>(url: CowStr<'a>, dir: &str, api_base: &str, raw: bool) -> CowStr<'a> {
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") || has_scheme(&url) {
+        return url;
+    }
+    let (path, fragment) = match url.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (&*url, None),
+    };
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    let target = if path.starts_with('/') {
+        normalize(path)
+    } else {
+        normalize(&join_path(dir, path))
+    };
+    let mut rewritten = if raw {
+        format!("{}/raw{}", api_base, target)
+    } else {
+        let target: String = form_urlencoded::byte_serialize(target.as_bytes()).collect();
+        format!("{}/blob?path={}", api_base, target)
+    };
+    if let Some(fragment) = fragment {
+        rewritten.push('#');
+        rewritten.push_str(fragment);
+    }
+    rewritten.into()
+}
+
+fn has_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+// Resolve `.` and `..`, going above the root stays at the root.
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_markdown() {
+        let md = "# Title\n\n[guide](docs/guide.md#setup) [up](../LICENSE) \
+                  [site](https://example.com) [top](#title)\n\n\
+                  ![logo](./img/logo.png)\n\n<script>alert(1)</script>\n";
+        let html = render_markdown(md, "/project/foo", "/api/v1/mono");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html
+            .contains(r#"href="/api/v1/mono/blob?path=%2Fproject%2Ffoo%2Fdocs%2Fguide.md#setup""#));
+        assert!(html.contains(r#"href="/api/v1/mono/blob?path=%2Fproject%2FLICENSE""#));
+        assert!(html.contains(r#"href="https://example.com""#));
+        assert!(html.contains(r##"href="#title""##));
+        assert!(html.contains(r#"src="/api/v1/mono/raw/project/foo/img/logo.png""#));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn test_rewrite_url_encodes_path() {
+        let url = rewrite_url("a&b=c d.md".into(), "/project", "/api", false);
+        assert_eq!(&*url, "/api/blob?path=%2Fproject%2Fa%26b%3Dc+d.md");
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/a/./b/../c"), "/a/c");
+        assert_eq!(normalize("/../../a"), "/a");
+        assert_eq!(normalize("/"), "/");
+    }
+
+    #[test]
+    fn test_has_scheme() {
+        assert!(has_scheme("https://example.com"));
+        assert!(has_scheme("mailto:a@example.com"));
+        assert!(!has_scheme("docs/a.md"));
+        assert!(!has_scheme("docs/a:b.md"));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use callisto::{mega_release, mega_release_asset};
+use common::errors::MegaError;
+use common::utils::generate_id;
+use jupiter::storage::release_storage::ReleaseStorage;
+
+pub mod release_router;
+
+/// Assets a release can list, they are metadata only, the files live elsewhere.
+pub const MAX_ASSETS_PER_RELEASE: usize = 50;
+const MAX_TITLE_LEN: usize = 200;
+const MAX_NOTES_LEN: usize = 256 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRelease {
+    /// name of an existing tag in `path`
+    pub tag: String,
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// the tag name when missing
+    pub title: Option<String>,
+    /// markdown
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub assets: Vec<AssetInput>,
+}
+
+/// Missing fields are left unchanged, assets are appended to the existing ones.
+#[derive(Debug, Deserialize)]
+pub struct UpdateRelease {
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub assets: Vec<AssetInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssetInput {
+    pub name: String,
+    /// where the artifact can be downloaded, http or https
+    pub url: String,
+    pub size: Option<i64>,
+    pub content_type: Option<String>,
+    /// e.g. `sha256:<hex>`
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+fn default_path() -> String {
+    "/".to_owned()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReleaseItem {
+    pub id: i64,
+    pub tag_id: String,
+    pub tag_name: String,
+    pub path: String,
+    pub title: String,
+    pub notes: Option<String>,
+    pub creator_id: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+impl From<mega_release::Model> for ReleaseItem {
+    fn from(value: mega_release::Model) -> Self {
+        Self {
+            id: value.id,
+            tag_id: value.tag_id,
+            tag_name: value.tag_name,
+            path: value.path,
+            title: value.title,
+            notes: value.notes,
+            creator_id: value.creator_id,
+            created_at: value.created_at.and_utc().timestamp(),
+            updated_at: value.updated_at.and_utc().timestamp(),
+            assets: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReleaseAsset {
+    pub id: i64,
+    pub name: String,
+    pub url: String,
+    pub size: Option<i64>,
+    pub content_type: Option<String>,
+    pub checksum: Option<String>,
+    pub created_at: i64,
+}
+
+impl From<mega_release_asset::Model> for ReleaseAsset {
+    fn from(value: mega_release_asset::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            url: value.url,
+            size: value.size,
+            content_type: value.content_type,
+            checksum: value.checksum,
+            created_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
+
+pub fn validate_title(title: &str) -> Result<(), String> {
+    if title.trim().is_empty() || title.chars().count() > MAX_TITLE_LEN {
+        return Err(format!(
+            "release title must be between 1 and {} characters",
+            MAX_TITLE_LEN
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_notes(notes: Option<&str>) -> Result<(), String> {
+    match notes {
+        Some(notes) if notes.len() > MAX_NOTES_LEN => Err(format!(
+            "release notes are longer than {} bytes",
+            MAX_NOTES_LEN
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check asset metadata and build the rows for `release_id`, which already lists
+/// `existing` assets.
+pub fn build_assets(
+    release_id: i64,
+    existing: usize,
+    inputs: Vec<AssetInput>,
+) -> Result<Vec<mega_release_asset::Model>, String> {
+    if existing + inputs.len() > MAX_ASSETS_PER_RELEASE {
+        return Err(format!(
+            "a release can list at most {} assets",
+            MAX_ASSETS_PER_RELEASE
+        ));
+    }
+    let now = chrono::Utc::now().naive_utc();
+    inputs
+        .into_iter()
+        .map(|input| {
+            if input.name.trim().is_empty() || input.name.contains('/') {
+                return Err(format!("invalid asset name {:?}", input.name));
+            }
+            if !input.url.starts_with("https://") && !input.url.starts_with("http://") {
+                return Err(format!("asset {} must have an http(s) url", input.name));
+            }
+            if input.size.is_some_and(|x| x < 0) {
+                return Err(format!("asset {} has a negative size", input.name));
+            }
+            Ok(mega_release_asset::Model {
+                id: generate_id(),
+                release_id,
+                name: input.name,
+                url: input.url,
+                size: input.size,
+                content_type: input.content_type,
+                checksum: input.checksum,
+                created_at: now,
+            })
+        })
+        .collect()
+}
+
+/// Releases with their assets.
+pub async fn load_releases(
+    storage: &ReleaseStorage,
+    releases: Vec<mega_release::Model>,
+) -> Result<Vec<ReleaseItem>, MegaError> {
+    if releases.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut assets: HashMap<i64, Vec<ReleaseAsset>> = HashMap::new();
+    for asset in storage
+        .get_assets(releases.iter().map(|x| x.id).collect())
+        .await?
+    {
+        assets
+            .entry(asset.release_id)
+            .or_default()
+            .push(asset.into());
+    }
+    Ok(releases
+        .into_iter()
+        .map(|release| {
+            let id = release.id;
+            let mut item: ReleaseItem = release.into();
+            item.assets = assets.remove(&id).unwrap_or_default();
+            item
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn asset(name: &str, url: &str) -> AssetInput {
+        AssetInput {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            size: Some(1024),
+            content_type: None,
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn test_build_assets() {
+        let assets = build_assets(
+            7,
+            0,
+            vec![asset(
+                "mega-linux.tar.gz",
+                "https://example.com/mega.tar.gz",
+            )],
+        )
+        .unwrap();
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets[0].release_id, 7);
+
+        assert!(build_assets(7, 0, vec![asset("a", "ftp://example.com/a")]).is_err());
+        assert!(build_assets(7, 0, vec![asset("a/b", "https://example.com/a")]).is_err());
+        assert!(build_assets(
+            7,
+            MAX_ASSETS_PER_RELEASE,
+            vec![asset("a", "https://example.com/a")]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate_title() {
+        assert!(validate_title("v1.0.0").is_ok());
+        assert!(validate_title("  ").is_err());
+        assert!(validate_title(&"a".repeat(MAX_TITLE_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use http::StatusCode;
+
+use callisto::mega_release;
+use ceres::model::tag::{CreateTagRequest, TagInfo, TagQuery};
+use common::{model::CommonResult, path::MonoPath, utils::generate_id};
+use mercury::errors::GitError;
+use mercury::internal::object::signature::{Signature, SignatureType};
+use saturn::ActionEnum;
+
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::release::{
+    build_assets, load_releases, validate_notes, validate_title, CreateRelease, ReleaseItem,
+    ReleaseQuery, UpdateRelease,
+};
+use crate::api::util;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new()
+        .route("/tags", get(list_tags).post(create_tag))
+        .route("/releases", get(list_releases).post(create_release))
+        .route("/releases/{id}/update", post(update_release))
+}
+
+/// Tag a commit, scoped to a directory. Only users who can manage the directory may tag.
+async fn create_tag(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<CreateTagRequest>,
+) -> Result<(StatusCode, Json<CommonResult<TagInfo>>), ApiError> {
+    let path = match MonoPath::parse(&payload.path) {
+        Ok(path) => path,
+        Err(err) => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(CommonResult::failed(&err.to_string())),
+            ))
+        }
+    };
+    if util::check_permissions(
+        &user.name,
+        path.as_str(),
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            Json(CommonResult::failed("permission denied")),
+        ));
+    }
+    let tagger = Signature::new(SignatureType::Tagger, user.name, user.email);
+    let (status, res) = match state.monorepo().create_tag(payload, tagger).await {
+        Ok(tag) => (StatusCode::OK, CommonResult::success(Some(tag))),
+        Err(err) => {
+            let status = match err {
+                GitError::EntryExists(_) => StatusCode::CONFLICT,
+                GitError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+                GitError::InvalidPathError(_) | GitError::ObjectNotFound(_) => {
+                    StatusCode::NOT_FOUND
+                }
+                _ => StatusCode::OK,
+            };
+            (status, CommonResult::failed(&err.to_string()))
+        }
+    };
+    Ok((status, Json(res)))
+}
+
+async fn list_tags(
+    Query(query): Query<TagQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<TagInfo>>>, ApiError> {
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let res = match state.monorepo().list_tags(&path).await {
+        Ok(tags) => CommonResult::success(Some(tags)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Publish a release for an existing tag, a tag has at most one release.
+async fn create_release(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<CreateRelease>,
+) -> Result<Json<CommonResult<i64>>, ApiError> {
+    let path = match MonoPath::parse(&payload.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    if util::check_permissions(
+        &user.name,
+        path.as_str(),
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let tag = state
+        .context
+        .services
+        .mono_storage
+        .get_tag_by_name(path.as_str(), &payload.tag)
+        .await
+        .unwrap();
+    let Some(tag) = tag else {
+        return Ok(Json(CommonResult::failed("tag not found")));
+    };
+    let stg = state.context.release_stg();
+    if stg.get_release_by_tag(&tag.tag_id).await.unwrap().is_some() {
+        return Ok(Json(CommonResult::failed(
+            "a release already exists for this tag",
+        )));
+    }
+    let title = payload.title.unwrap_or_else(|| tag.tag_name.clone());
+    if let Err(err) = validate_title(&title).and(validate_notes(payload.notes.as_deref())) {
+        return Ok(Json(CommonResult::failed(&err)));
+    }
+    let id = generate_id();
+    let assets = match build_assets(id, 0, payload.assets) {
+        Ok(assets) => assets,
+        Err(err) => return Ok(Json(CommonResult::failed(&err))),
+    };
+    let now = Utc::now().naive_utc();
+    let release = mega_release::Model {
+        id,
+        tag_id: tag.tag_id,
+        path: tag.path,
+        tag_name: tag.tag_name,
+        title,
+        notes: payload.notes,
+        creator_id: user.user_id,
+        created_at: now,
+        updated_at: now,
+    };
+    let res = match stg.save_release(release).await {
+        Ok(release) => match stg.save_assets(assets).await {
+            Ok(_) => CommonResult::success(Some(release.id)),
+            Err(err) => CommonResult::failed(&err.to_string()),
+        },
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Edit the notes of a release or attach more assets to it.
+async fn update_release(
+    user: LoginUser,
+    Path(id): Path<i64>,
+    state: State<MonoApiServiceState>,
+    Json(payload): Json<UpdateRelease>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let stg = state.context.release_stg();
+    let Some(mut release) = stg.get_release(id).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("release not found")));
+    };
+    if util::check_permissions(
+        &user.name,
+        &release.path,
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    if let Some(title) = payload.title {
+        release.title = title;
+    }
+    if payload.notes.is_some() {
+        release.notes = payload.notes;
+    }
+    if let Err(err) = validate_title(&release.title).and(validate_notes(release.notes.as_deref())) {
+        return Ok(Json(CommonResult::failed(&err)));
+    }
+    let existing = stg.get_assets(vec![release.id]).await.unwrap().len();
+    let assets = match build_assets(release.id, existing, payload.assets) {
+        Ok(assets) => assets,
+        Err(err) => return Ok(Json(CommonResult::failed(&err))),
+    };
+    let res = match stg.update_release(release).await {
+        Ok(_) => match stg.save_assets(assets).await {
+            Ok(_) => CommonResult::success(None),
+            Err(err) => CommonResult::failed(&err.to_string()),
+        },
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Releases of the tags scoped to a directory, newest first.
+async fn list_releases(
+    Query(query): Query<ReleaseQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<ReleaseItem>>>, ApiError> {
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let stg = state.context.release_stg();
+    let res = match stg.get_releases_by_path(path.as_str()).await {
+        Ok(releases) => match load_releases(&stg, releases).await {
+            Ok(items) => CommonResult::success(Some(items)),
+            Err(err) => CommonResult::failed(&err.to_string()),
+        },
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
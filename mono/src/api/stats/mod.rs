@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+use callisto::dir_stats;
+
+pub mod stats_router;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub path: String,
+}
+
+/// Aggregates of a directory and everything below it, as of the last update of the root
+/// ref that was processed.
+#[derive(Debug, Serialize)]
+pub struct DirStats {
+    pub path: String,
+    /// bytes of all files
+    pub size: i64,
+    pub file_count: i64,
+    /// commit time of the last change below the directory
+    pub last_activity: i64,
+    /// quota covering the directory, it may be set on a parent
+    pub quota: Option<QuotaUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaUsage {
+    /// directory the quota is set on
+    pub path: String,
+    pub max_size: u64,
+    /// bytes of all files below `path`
+    pub used: u64,
+}
+
+impl From<dir_stats::Model> for DirStats {
+    fn from(value: dir_stats::Model) -> Self {
+        Self {
+            path: value.path,
+            size: value.size,
+            file_count: value.file_count,
+            last_activity: value.last_activity.and_utc().timestamp(),
+            quota: None,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+
+use common::{errors::MegaError, model::CommonResult, path::MonoPath};
+
+use crate::api::error::ApiError;
+use crate::api::stats::{DirStats, QuotaUsage, StatsQuery};
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new().route("/stats", get(get_stats))
+}
+
+/// Size, file count and last activity of a directory, kept up to date in the background
+/// as the root ref moves, along with the usage of the quota covering it.
+async fn get_stats(
+    Query(query): Query<StatsQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<DirStats>>, ApiError> {
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let res = match dir_stats(&state, path.as_str()).await {
+        Ok(Some(stats)) => CommonResult::success(Some(stats)),
+        Ok(None) => CommonResult::failed(&format!("no statistics for {}", path)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn dir_stats(state: &MonoApiServiceState, path: &str) -> Result<Option<DirStats>, MegaError> {
+    let storage = state.context.stats_stg();
+    let Some(model) = storage.get_stats(path).await? else {
+        return Ok(None);
+    };
+    let mut stats = DirStats::from(model);
+    if let Some(quota) = state.context.config.monorepo.quota(path) {
+        let used = if quota.path == stats.path {
+            stats.size
+        } else {
+            storage.get_stats(&quota.path).await?.map_or(0, |x| x.size)
+        };
+        stats.quota = Some(QuotaUsage {
+            path: quota.path.clone(),
+            max_size: quota.max_size,
+            used: used as u64,
+        });
+    }
+    Ok(Some(stats))
+}
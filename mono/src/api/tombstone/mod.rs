@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use callisto::mega_tombstone;
+
+pub mod tombstone_router;
+
+#[derive(Debug, Deserialize)]
+pub struct TombstoneQuery {
+    #[serde(default = "default_path")]
+    pub path: String,
+}
+
+fn default_path() -> String {
+    "/".to_owned()
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct RestoreTombstone {
+    /// commit message, a default one is generated when missing
+    pub message: Option<String>,
+}
+
+/// A directory deleted from the monorepo.
+#[derive(Debug, Serialize)]
+pub struct TombstoneItem {
+    pub id: i64,
+    pub path: String,
+    /// commit deleting the directory
+    pub commit_id: String,
+    pub tree_id: String,
+    pub restored_commit: Option<String>,
+    pub deleted_at: i64,
+}
+
+impl From<mega_tombstone::Model> for TombstoneItem {
+    fn from(value: mega_tombstone::Model) -> Self {
+        Self {
+            id: value.id,
+            path: value.path,
+            commit_id: value.commit_id,
+            tree_id: value.tree_id,
+            restored_commit: value.restored_commit,
+            deleted_at: value.created_at.and_utc().timestamp(),
+        }
+    }
+}
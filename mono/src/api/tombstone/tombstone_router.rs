@@ -0,0 +1,79 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::json;
+
+use ceres::model::commit::CommitResult;
+use common::{model::CommonResult, path::MonoPath};
+use taurus::event::live_update::{LiveUpdateEvent, LiveUpdateKind};
+
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::tombstone::{RestoreTombstone, TombstoneItem, TombstoneQuery};
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new()
+        .route("/admin/tombstones", get(list_tombstones))
+        .route("/admin/tombstones/{id}/restore", post(restore_tombstone))
+}
+
+/// Directories deleted at a path or below it, the latest first.
+async fn list_tombstones(
+    user: LoginUser,
+    Query(query): Query<TombstoneQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<Vec<TombstoneItem>>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    let res = match state
+        .context
+        .services
+        .mono_storage
+        .get_tombstones(path.as_str())
+        .await
+    {
+        Ok(list) => CommonResult::success(Some(list.into_iter().map(Into::into).collect())),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+/// Bring a deleted directory back in a new commit.
+async fn restore_tombstone(
+    user: LoginUser,
+    Path(id): Path<i64>,
+    state: State<MonoApiServiceState>,
+    payload: Option<Json<RestoreTombstone>>,
+) -> Result<Json<CommonResult<CommitResult>>, ApiError> {
+    if user.name != state.context.config.monorepo.admin {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let Json(payload) = payload.unwrap_or_default();
+    let res = match state
+        .monorepo_as(&user)
+        .restore_tombstone(id, payload.message)
+        .await
+    {
+        Ok(data) => {
+            if data.commit_id.is_some() {
+                LiveUpdateEvent::notify(
+                    LiveUpdateKind::RefUpdate,
+                    "/",
+                    None,
+                    json!({ "reason": "restore", "tombstone": id, "commit": data.commit_id }),
+                );
+            }
+            CommonResult::success(Some(data))
+        }
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
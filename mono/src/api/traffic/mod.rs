@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use callisto::{db_enums::TrafficKind, traffic_stats};
+
+pub mod traffic_router;
+
+/// Longest window that can be requested, older counters are still kept.
+pub const MAX_TRAFFIC_DAYS: i64 = 90;
+
+#[derive(Debug, Deserialize)]
+pub struct TrafficQuery {
+    pub path: String,
+    #[serde(default = "default_days")]
+    pub days: i64,
+}
+
+fn default_days() -> i64 {
+    14
+}
+
+#[derive(Debug, Serialize, Default, Clone, PartialEq, Eq)]
+pub struct TrafficCount {
+    pub raw_download: i64,
+    pub archive_download: i64,
+    pub clone: i64,
+    pub fetch: i64,
+}
+
+impl TrafficCount {
+    fn add(&mut self, kind: TrafficKind, count: i64) {
+        match kind {
+            TrafficKind::RawDownload => self.raw_download += count,
+            TrafficKind::ArchiveDownload => self.archive_download += count,
+            TrafficKind::Clone => self.clone += count,
+            TrafficKind::Fetch => self.fetch += count,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyTraffic {
+    /// `YYYY-MM-DD`, in UTC
+    pub day: String,
+    #[serde(flatten)]
+    pub count: TrafficCount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrafficSummary {
+    pub path: String,
+    pub total: TrafficCount,
+    /// only days with traffic are listed, oldest first
+    pub days: Vec<DailyTraffic>,
+}
+
+impl TrafficSummary {
+    /// Sum the counters of `path` and its sub paths per day.
+    pub fn aggregate(path: String, stats: Vec<traffic_stats::Model>) -> Self {
+        let mut total = TrafficCount::default();
+        let mut days: BTreeMap<_, TrafficCount> = BTreeMap::new();
+        for stat in stats {
+            total.add(stat.kind, stat.count);
+            days.entry(stat.day).or_default().add(stat.kind, stat.count);
+        }
+        TrafficSummary {
+            path,
+            total,
+            days: days
+                .into_iter()
+                .map(|(day, count)| DailyTraffic {
+                    day: day.format("%Y-%m-%d").to_string(),
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn stat(path: &str, kind: TrafficKind, day: u32, count: i64) -> traffic_stats::Model {
+        traffic_stats::Model {
+            id: 0,
+            path: path.to_owned(),
+            kind,
+            day: NaiveDate::from_ymd_opt(2026, 10, day).unwrap(),
+            count,
+            updated_at: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let summary = TrafficSummary::aggregate(
+            "/project".to_owned(),
+            vec![
+                stat("/project", TrafficKind::Clone, 2, 3),
+                stat("/project/a.rs", TrafficKind::RawDownload, 1, 5),
+                stat("/project/sub", TrafficKind::Clone, 2, 1),
+                stat("/project/sub", TrafficKind::Fetch, 2, 7),
+            ],
+        );
+        assert_eq!(summary.total.clone, 4);
+        assert_eq!(summary.total.fetch, 7);
+        assert_eq!(summary.total.raw_download, 5);
+        assert_eq!(summary.days.len(), 2);
+        assert_eq!(summary.days[0].day, "2026-10-01");
+        assert_eq!(summary.days[1].count.clone, 4);
+    }
+}
@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{Duration, Utc};
+
+use common::{model::CommonResult, path::MonoPath};
+use saturn::ActionEnum;
+
+use crate::api::error::ApiError;
+use crate::api::oauth::model::LoginUser;
+use crate::api::traffic::{TrafficQuery, TrafficSummary, MAX_TRAFFIC_DAYS};
+use crate::api::util;
+use crate::api::MonoApiServiceState;
+
+pub fn routers() -> Router<MonoApiServiceState> {
+    Router::new().route("/traffic", get(get_traffic))
+}
+
+/// Daily download and clone counters of a path, including everything below it.
+/// Only users who can manage the path may see them.
+async fn get_traffic(
+    user: LoginUser,
+    Query(query): Query<TrafficQuery>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<TrafficSummary>>, ApiError> {
+    let path = match MonoPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(err) => return Ok(Json(CommonResult::failed(&err.to_string()))),
+    };
+    if util::check_permissions(
+        &user.name,
+        path.as_str(),
+        ActionEnum::ApproveMergeRequest,
+        state.clone(),
+    )
+    .await
+    .is_err()
+    {
+        return Ok(Json(CommonResult::failed("permission denied")));
+    }
+    let days = query.days.clamp(1, MAX_TRAFFIC_DAYS);
+    let since = (Utc::now() - Duration::days(days - 1)).date_naive();
+    let res = match state
+        .context
+        .traffic_stg()
+        .get_stats_under_path(path.as_str(), since)
+        .await
+    {
+        Ok(stats) => CommonResult::success(Some(TrafficSummary::aggregate(
+            path.to_string(),
+            stats,
+        ))),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
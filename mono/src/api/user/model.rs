@@ -1,5 +1,7 @@
-use callisto::{access_token, ssh_keys};
+use callisto::{access_token, ssh_keys, user};
 use chrono::NaiveDateTime;
+use common::model::TokenScope;
+use jupiter::storage::user_storage::token_scopes;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
@@ -29,20 +31,31 @@ impl From<ssh_keys::Model> for ListSSHKey {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ListToken {
     pub id: i64,
-    pub token: String,
+    pub name: String,
+    /// leading characters of the token to recognise it, the rest is never shown again
+    pub token_prefix: String,
+    pub scopes: Vec<TokenScope>,
+    pub last_used_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
 }
 
 impl From<access_token::Model> for ListToken {
     fn from(value: access_token::Model) -> Self {
-        let mut mask_token =  value.token;
-        mask_token.replace_range(7..32, "-******-");
         Self {
             id: value.id,
-            token: mask_token,
+            scopes: token_scopes(&value.scopes),
+            name: value.name,
+            token_prefix: value.token_prefix,
+            last_used_at: value.last_used_at,
             created_at: value.created_at,
         }
     }
@@ -54,3 +67,29 @@ pub struct RepoPermissions {
     pub maintainer: Vec<String>,
     pub reader: Vec<String>,
 }
+
+/// Public information of a user, email is left out on purpose.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub id: i64,
+    pub name: String,
+    pub avatar_url: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<user::Model> for UserProfile {
+    fn from(value: user::Model) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            avatar_url: value.avatar_url,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfile {
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
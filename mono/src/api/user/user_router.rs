@@ -1,17 +1,26 @@
 use std::collections::HashMap;
 
 use axum::{
+    body::{Body, Bytes},
     extract::{Path, Query, State},
+    response::Response,
     routing::{get, post},
     Json, Router,
 };
+use http::{HeaderMap, StatusCode};
 use russh_keys::{parse_public_key_base64, HashAlg};
+use serde_json::json;
 
+use callisto::db_enums::AuditAction;
 use common::model::CommonResult;
+use mercury::internal::object::blob::Blob;
+use taurus::event::audit::AuditEvent;
 
+use crate::api::http_cache::CacheInfo;
 use crate::api::user::model::AddSSHKey;
 use crate::api::user::model::ListSSHKey;
-use crate::api::user::model::ListToken;
+use crate::api::user::model::{CreateToken, ListToken};
+use crate::api::user::model::{UpdateProfile, UserProfile};
 use crate::api::MonoApiServiceState;
 use crate::api::{error::ApiError, oauth::model::LoginUser, util};
 
@@ -20,6 +29,11 @@ pub fn routers() -> Router<MonoApiServiceState> {
         "/user",
         Router::new()
             .route("/", get(user))
+            .route("/profile", post(update_profile))
+            .route("/profile/{name}", get(user_profile))
+            .route("/delete", post(delete_user))
+            .route("/avatar", post(upload_avatar))
+            .route("/avatar/{hash}", get(get_avatar))
             .route("/ssh", get(list_key))
             .route("/ssh", post(add_key))
             .route("/ssh/{key_id}/delete", post(remove_key))
@@ -37,22 +51,167 @@ async fn user(
     Ok(Json(CommonResult::success(Some(user))))
 }
 
+async fn user_profile(
+    Path(name): Path<String>,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<UserProfile>>, ApiError> {
+    let res = match state.user_stg().find_user_by_name(&name).await {
+        Ok(Some(model)) => CommonResult::success(Some(model.into())),
+        Ok(None) => CommonResult::failed("user not found"),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn update_profile(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    Json(json): Json<UpdateProfile>,
+) -> Result<Json<CommonResult<UserProfile>>, ApiError> {
+    let Some(mut model) = state.user_stg().find_user_by_id(user.user_id).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("user not found")));
+    };
+    if let Some(name) = json.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(Json(CommonResult::failed("name can not be empty")));
+        }
+        if name != model.name {
+            if state
+                .user_stg()
+                .find_user_by_name(name)
+                .await
+                .unwrap()
+                .is_some()
+            {
+                return Ok(Json(CommonResult::failed("name already taken")));
+            }
+            model.name = name.to_owned();
+        }
+    }
+    if let Some(avatar_url) = json.avatar_url {
+        model.avatar_url = avatar_url;
+    }
+    let res = match state.user_stg().update_user(model.clone()).await {
+        Ok(_) => CommonResult::success(Some(model.into())),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn delete_user(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    let res = match state.user_stg().delete_user(user.user_id).await {
+        Ok(_) => CommonResult::success(None),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+const MAX_AVATAR_SIZE: usize = 1024 * 1024;
+
+/// Store the uploaded image with the blob backend and point the avatar url of the user to it.
+async fn upload_avatar(
+    user: LoginUser,
+    state: State<MonoApiServiceState>,
+    body: Bytes,
+) -> Result<Json<CommonResult<String>>, ApiError> {
+    if body.len() > MAX_AVATAR_SIZE {
+        return Ok(Json(CommonResult::failed("avatar exceeds the size limit of 1MB")));
+    }
+    if avatar_content_type(&body).is_none() {
+        return Ok(Json(CommonResult::failed(
+            "unsupported image format, expect png, jpeg, gif or webp",
+        )));
+    }
+    let Some(mut model) = state.user_stg().find_user_by_id(user.user_id).await.unwrap() else {
+        return Ok(Json(CommonResult::failed("user not found")));
+    };
+    let blob = Blob::from_content_bytes(body.to_vec());
+    let avatar_url = format!("/api/v1/user/avatar/{}", blob.id);
+    if let Err(err) = state
+        .context
+        .services
+        .raw_db_storage
+        .save_raw_blob(blob.into())
+        .await
+    {
+        return Ok(Json(CommonResult::failed(&err.to_string())));
+    }
+    model.avatar_url = avatar_url.clone();
+    let res = match state.user_stg().update_user(model).await {
+        Ok(_) => CommonResult::success(Some(avatar_url)),
+        Err(err) => CommonResult::failed(&err.to_string()),
+    };
+    Ok(Json(res))
+}
+
+async fn get_avatar(
+    Path(hash): Path<String>,
+    state: State<MonoApiServiceState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let model = state
+        .context
+        .services
+        .raw_db_storage
+        .get_raw_blob_by_hash(&hash)
+        .await?;
+    let data = model.and_then(|x| x.data);
+    match data.as_deref().and_then(avatar_content_type) {
+        Some(content_type) => {
+            // only an avatar that exists is cached, a 404 may turn into one
+            let cache = CacheInfo::immutable(&hash);
+            if cache.is_fresh(&headers) {
+                return Ok(cache.not_modified());
+            }
+            let mut res = Response::builder()
+                .header("Content-Type", content_type)
+                .body(Body::from(data.unwrap()))
+                .unwrap();
+            cache.apply(res.headers_mut());
+            Ok(res)
+        }
+        None => Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap()),
+    }
+}
+
+/// Detect the image type by magic number, only common web formats are accepted as avatar.
+fn avatar_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
 async fn add_key(
     user: LoginUser,
     state: State<MonoApiServiceState>,
     Json(json): Json<AddSSHKey>,
 ) -> Result<Json<CommonResult<String>>, ApiError> {
     let ssh_key: Vec<&str> = json.ssh_key.split_whitespace().collect();
-    let key = parse_public_key_base64(ssh_key.get(1).ok_or("Invalid key format").unwrap())?;
+    let Some(key) = ssh_key
+        .get(1)
+        .and_then(|x| parse_public_key_base64(x).ok())
+    else {
+        return Ok(Json(CommonResult::failed("Invalid key format")));
+    };
     let title = if !json.title.is_empty() {
         json.title
     } else {
-        ssh_key
-            .get(2)
-            .ok_or("Invalid key format")
-            .unwrap()
-            .to_owned()
-            .to_owned()
+        ssh_key.get(2).map(|x| x.to_string()).unwrap_or_default()
     };
 
     let res = state
@@ -99,10 +258,32 @@ async fn list_key(
 async fn generate_token(
     user: LoginUser,
     state: State<MonoApiServiceState>,
+    Json(json): Json<CreateToken>,
 ) -> Result<Json<CommonResult<String>>, ApiError> {
-    let res = state.user_stg().generate_token(user.user_id).await;
+    let name = json.name.trim();
+    if name.is_empty() {
+        return Ok(Json(CommonResult::failed("name can not be empty")));
+    }
+    if json.scopes.is_empty() {
+        return Ok(Json(CommonResult::failed(
+            "a token needs at least one scope",
+        )));
+    }
+    let res = state
+        .user_stg()
+        .generate_token(user.user_id, name, &json.scopes)
+        .await;
     let res = match res {
-        Ok(data) => CommonResult::success(Some(data)),
+        Ok(data) => {
+            AuditEvent::notify(
+                &user.name,
+                AuditAction::CreateToken,
+                name,
+                json!({ "scopes": json.scopes }),
+            )
+            .await;
+            CommonResult::success(Some(data))
+        }
         Err(err) => CommonResult::failed(&err.to_string()),
     };
     Ok(Json(res))
@@ -115,7 +296,16 @@ async fn remove_token(
 ) -> Result<Json<CommonResult<String>>, ApiError> {
     let res = state.user_stg().delete_token(user.user_id, key_id).await;
     let res = match res {
-        Ok(_) => CommonResult::success(None),
+        Ok(_) => {
+            AuditEvent::notify(
+                &user.name,
+                AuditAction::DeleteToken,
+                &key_id.to_string(),
+                json!({}),
+            )
+            .await;
+            CommonResult::success(None)
+        }
         Err(err) => CommonResult::failed(&err.to_string()),
     };
     Ok(Json(res))
@@ -150,6 +340,20 @@ async fn repo_permissions(
 mod test {
     use std::path::{Path, PathBuf};
 
+    use super::avatar_content_type;
+
+    #[test]
+    fn test_avatar_content_type() {
+        assert_eq!(
+            avatar_content_type(b"\x89PNG\r\n\x1a\n0000"),
+            Some("image/png")
+        );
+        assert_eq!(avatar_content_type(&[0xff, 0xd8, 0xff, 0xe0]), Some("image/jpeg"));
+        assert_eq!(avatar_content_type(b"GIF89a...."), Some("image/gif"));
+        assert_eq!(avatar_content_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(avatar_content_type(b"<svg></svg>"), None);
+    }
+
     #[test]
     fn test_parse_all_cedar_file() {
         let path = PathBuf::from("/project/mega/src");
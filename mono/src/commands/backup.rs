@@ -0,0 +1,35 @@
+//! This module is responsible for handling the 'backup' command.
+//! It writes the whole monorepo, refs, commits, trees, blobs and merge requests, to a
+//! bundle the 'restore' command loads into a fresh instance.
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgMatches, Command};
+
+use common::{config::Config, errors::MegaResult};
+use jupiter::{backup, context::Context};
+
+pub fn cli() -> Command {
+    Command::new("backup")
+        .about("Export the monorepo to a bundle restore reads")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Path of the bundle written, a tar.gz"),
+        )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
+    let path = args.get_one::<PathBuf>("file").unwrap();
+    let context = Context::new(config).await;
+    let mut out = BufWriter::new(File::create(path)?);
+    let manifest = backup::export(&context, &mut out).await?;
+    for (table, rows) in &manifest.rows {
+        println!("{}: {} rows", table, rows);
+    }
+    println!("raw blobs: {}", manifest.blobs);
+    Ok(())
+}
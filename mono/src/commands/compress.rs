@@ -0,0 +1,19 @@
+//! This module is responsible for handling the 'compress' command.
+//! It compresses the content of raw blobs kept uncompressed in the database, like the
+//! blobs stored before compression was enabled.
+use clap::{ArgMatches, Command};
+
+use common::{config::Config, errors::MegaResult};
+use jupiter::context::Context;
+
+pub fn cli() -> Command {
+    Command::new("compress").about("Compress the raw blobs stored uncompressed in the database")
+}
+
+#[tokio::main]
+pub(crate) async fn exec(config: Config, _args: &ArgMatches) -> MegaResult {
+    let context = Context::new(config).await;
+    let compressed = context.services.raw_db_storage.compress_stored().await?;
+    println!("compressed {} raw blobs", compressed);
+    Ok(())
+}
@@ -1,3 +1,6 @@
+pub mod backup;
+pub mod compress;
+pub mod restore;
 pub mod service;
 
 use clap::{ArgMatches, Command};
@@ -6,12 +9,20 @@ use common::{config::Config, errors::MegaResult};
 
 
 pub fn builtin() -> Vec<Command> {
-    vec![service::cli()]
+    vec![
+        service::cli(),
+        compress::cli(),
+        backup::cli(),
+        restore::cli(),
+    ]
 }
 
 pub(crate) fn builtin_exec(cmd: &str) -> Option<fn(Config, &ArgMatches) -> MegaResult> {
     let f = match cmd {
         "service" => service::exec,
+        "compress" => compress::exec,
+        "backup" => backup::exec,
+        "restore" => restore::exec,
         _ => return None,
     };
 
@@ -0,0 +1,34 @@
+//! This module is responsible for handling the 'restore' command.
+//! It loads a bundle written by the 'backup' command into the database and blob storage
+//! configured, which must not hold a monorepo yet.
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgMatches, Command};
+
+use common::{config::Config, errors::MegaResult};
+use jupiter::{backup, context::Context};
+
+pub fn cli() -> Command {
+    Command::new("restore")
+        .about("Restore a bundle written by backup into a fresh instance")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Path of the bundle to restore"),
+        )
+}
+
+#[tokio::main]
+pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
+    let path = args.get_one::<PathBuf>("file").unwrap();
+    let context = Context::new(config).await;
+    let manifest = backup::restore(&context, BufReader::new(File::open(path)?)).await?;
+    println!(
+        "restored the backup of {} with {} raw blobs",
+        manifest.created_at, manifest.blobs
+    );
+    Ok(())
+}
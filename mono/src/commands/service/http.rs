@@ -1,5 +1,6 @@
 use clap::{ArgMatches, Args, Command, FromArgMatches};
 
+use ceres::pack::cache::spawn_warmer;
 use common::{config::Config, errors::MegaResult};
 use jupiter::context::Context;
 use crate::server::https_server::{self, HttpOptions};
@@ -22,6 +23,7 @@ pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
         .mono_storage
         .init_monorepo(&config.monorepo)
         .await;
+    spawn_warmer(context.clone());
     https_server::start_http(context, server_matchers).await;
     Ok(())
 }
@@ -3,6 +3,8 @@
 //!
 //!
 //!
+use std::time::Duration;
+
 use clap::{ArgMatches, Command};
 
 use common::{config::Config, errors::MegaResult};
@@ -12,6 +14,9 @@ pub mod https;
 pub mod multi;
 pub mod ssh;
 
+// How long a shutdown waits for the events being processed.
+const QUEUE_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 // This function generates the CLI for the 'service' command.
 // It includes subcommands for each server type.
 pub fn cli() -> Command {
@@ -35,13 +40,16 @@ pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
             return Ok(());
         }
     };
-    match cmd {
+    let res = match cmd {
         "http" => http::exec(config, subcommand_args).await,
         "https" => https::exec(config, subcommand_args).await,
         "ssh" => ssh::exec(config, subcommand_args).await,
         "multi" => multi::exec(config, subcommand_args).await,
         _ => Ok(()),
-    }
+    };
+    // The servers return on a shutdown signal, let the events they raised finish.
+    taurus::queue::get_mq().shutdown(QUEUE_DRAIN_TIMEOUT).await;
+    res
 }
 
 #[cfg(test)]
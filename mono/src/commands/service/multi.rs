@@ -7,6 +7,7 @@ use crate::server::{
     https_server::{self, HttpOptions, HttpsOptions},
     ssh_server::{self, SshCustom, SshOptions},
 };
+use ceres::pack::cache::spawn_warmer;
 use common::{config::Config, errors::MegaResult, model::CommonOptions};
 
 #[derive(Debug, PartialEq, Clone, ValueEnum)]
@@ -60,6 +61,7 @@ pub(crate) async fn exec(config: Config, args: &ArgMatches) -> MegaResult {
         .mono_storage
         .init_monorepo(&config.monorepo)
         .await;
+    spawn_warmer(context.clone());
     let context_clone = context.clone();
     let http_server = if service_type.contains(&StartCommand::Http) {
         let http = HttpOptions {
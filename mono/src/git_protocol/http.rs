@@ -1,20 +1,28 @@
 use std::convert::Infallible;
+use std::path::Path;
+use std::pin::Pin;
+use std::time::Duration;
 
 use anyhow::Result;
 use axum::body::Body;
-use axum::http::{HeaderValue, Request, Response};
+use axum::http::{HeaderValue, Method, Request, Response, StatusCode};
+use axum::response::IntoResponse;
 use base64::engine::general_purpose;
 use base64::prelude::*;
 use bytes::{Bytes, BytesMut};
-use futures::{stream, TryStreamExt};
+use futures::{stream, Stream, TryStreamExt};
 use http::HeaderMap;
 use jupiter::context::Context;
-use tokio::io::AsyncReadExt;
 use tokio_stream::StreamExt;
 
+use callisto::db_enums::{AclPermission, TrafficKind};
 use ceres::protocol::{smart, ServiceType, SmartProtocol};
 use common::errors::ProtocolError;
-use common::model::InfoRefsParams;
+use common::model::{InfoRefsParams, TokenScope, UploadParams};
+use taurus::event::traffic::TrafficEvent;
+
+use crate::git_protocol::apply_acl;
+use crate::git_protocol::upload::{UploadSession, UploadStatus};
 
 // # Discovering Reference
 // HTTP clients that support the "smart" protocol (or both the "smart" and "dumb" protocols) MUST
@@ -24,10 +32,21 @@ use common::model::InfoRefsParams;
 // The request MUST NOT contain additional query parameters.
 pub async fn git_info_refs(
     params: InfoRefsParams,
+    headers: &HeaderMap<HeaderValue>,
     mut pack_protocol: SmartProtocol,
 ) -> Result<Response<Body>, ProtocolError> {
     let service_name = params.service.unwrap();
-    pack_protocol.service_type = Some(service_name.parse::<ServiceType>().unwrap());
+    let service_type = service_name.parse::<ServiceType>().unwrap();
+    pack_protocol.service_type = Some(service_type);
+
+    let (permission, scope) = match service_type {
+        ServiceType::UploadPack => (AclPermission::Read, TokenScope::RepoRead),
+        ServiceType::ReceivePack => (AclPermission::Write, TokenScope::RepoWrite),
+    };
+    let user = http_user(headers, &pack_protocol.context, scope).await;
+    if !apply_acl(&mut pack_protocol, user.as_deref(), permission).await? {
+        return access_denied(user.as_deref(), &pack_protocol.path);
+    }
 
     let pkt_line_stream = pack_protocol.git_info_refs().await?;
 
@@ -41,48 +60,56 @@ pub async fn git_info_refs(
     Ok(response)
 }
 
-async fn http_auth(header: &HeaderMap<HeaderValue>, context: &Context) -> bool {
-    for (k, v) in header {
-        if k == http::header::AUTHORIZATION {
-            let decoded = general_purpose::STANDARD
-                .decode(
-                    v.to_str()
-                        .unwrap()
-                        .strip_prefix("Basic ")
-                        .unwrap()
-                        .as_bytes(),
-                )
-                .unwrap();
-            let credentials = String::from_utf8(decoded).unwrap_or_default();
-            let mut parts = credentials.splitn(2, ':');
-            let username = parts.next().unwrap_or("");
-            let token = parts.next().unwrap_or("");
-            tracing::debug!("{}, {}", username, token);
-            let auth_config = context.config.authentication.clone();
-            if auth_config.enable_test_user
-                && username == auth_config.test_user_name
-                && token == auth_config.test_user_token
-            {
-                return true;
-            }
-            match context
-                .user_stg()
-                .find_user_by_name(username)
-                .await
-                .unwrap()
-            {
-                Some(user) => {
-                    return context
-                        .user_stg()
-                        .check_token(user.id, token)
-                        .await
-                        .unwrap();
-                }
-                None => return false,
-            }
-        }
+/// The user a request authenticates as with basic auth, `None` for anonymous requests
+/// and for credentials that are unknown or whose token has no `scope`.
+async fn http_user(
+    header: &HeaderMap<HeaderValue>,
+    context: &Context,
+    scope: TokenScope,
+) -> Option<String> {
+    let value = header.get(http::header::AUTHORIZATION)?.to_str().ok()?;
+    let decoded = general_purpose::STANDARD
+        .decode(value.strip_prefix("Basic ")?.as_bytes())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).unwrap_or_default();
+    let mut parts = credentials.splitn(2, ':');
+    let username = parts.next().unwrap_or("");
+    let token = parts.next().unwrap_or("");
+    tracing::debug!("{}, {}", username, token);
+    let auth_config = context.config.authentication.clone();
+    if auth_config.enable_test_user
+        && username == auth_config.test_user_name
+        && token == auth_config.test_user_token
+    {
+        return Some(username.to_owned());
+    }
+    let user = context
+        .user_stg()
+        .find_user_by_name(username)
+        .await
+        .unwrap()?;
+    if context
+        .user_stg()
+        .check_token(user.id, token, scope)
+        .await
+        .unwrap()
+    {
+        Some(username.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Anonymous requests are asked for credentials, known users are turned away.
+fn access_denied(user: Option<&str>, path: &Path) -> Result<Response<Body>, ProtocolError> {
+    match user {
+        Some(user) => Err(ProtocolError::Forbidden(format!(
+            "{} has no access to {}",
+            user,
+            path.display()
+        ))),
+        None => auth_failed(),
     }
-    false
 }
 
 fn auth_failed() -> Result<Response<Body>, ProtocolError> {
@@ -121,6 +148,10 @@ pub async fn git_upload_pack(
     req: Request<Body>,
     mut pack_protocol: SmartProtocol,
 ) -> Result<Response<Body>, ProtocolError> {
+    let user = http_user(req.headers(), &pack_protocol.context, TokenScope::RepoRead).await;
+    if !apply_acl(&mut pack_protocol, user.as_deref(), AclPermission::Read).await? {
+        return access_denied(user.as_deref(), &pack_protocol.path);
+    }
     let upload_request: BytesMut = req
         .into_body()
         .into_data_stream()
@@ -131,27 +162,18 @@ pub async fn git_upload_pack(
         .await
         .unwrap();
     tracing::debug!("Receive bytes: <-------- {:?}", upload_request);
-    let (mut send_pack_data, protocol_buf) = pack_protocol
+    record_upload_traffic(&pack_protocol.path, &upload_request);
+    let (send_pack_data, protocol_buf) = pack_protocol
         .git_upload_pack(&mut upload_request.freeze())
         .await?;
 
+    let mut pack_stream = Box::pin(pack_protocol.pack_data_stream(send_pack_data));
     let body_stream = async_stream::stream! {
         tracing::info!("send ack/nak message buf: --------> {:?}", &protocol_buf);
         yield Ok::<_, Infallible>(Bytes::copy_from_slice(&protocol_buf));
         // send packdata with sideband64k
-        while let Some(chunk) = send_pack_data.next().await {
-            let mut reader = chunk.as_slice();
-            loop {
-                let mut temp = BytesMut::new();
-                temp.reserve(65500);
-                let length = reader.read_buf(&mut temp).await.unwrap();
-                if length == 0 {
-                    break;
-                }
-                let bytes_out = pack_protocol.build_side_band_format(temp, length);
-                // tracing::info!("send pack file: length: {:?}", bytes_out.len());
-                yield Ok::<_, Infallible>(bytes_out.freeze());
-            }
+        while let Some(bytes_out) = pack_stream.next().await {
+            yield Ok::<_, Infallible>(bytes_out);
         }
         let bytes_out = Bytes::from_static(smart::PKT_LINE_END_MARKER);
         tracing::info!("send back pkt-flush line '0000', actually: {:?}", bytes_out);
@@ -185,13 +207,37 @@ pub async fn git_receive_pack(
     req: Request<Body>,
     mut pack_protocol: SmartProtocol,
 ) -> Result<Response<Body>, ProtocolError> {
-    if pack_protocol.context.config.authentication.enable_http_auth
-        && !http_auth(req.headers(), &pack_protocol.context).await
-    {
-        return auth_failed();
+    if let Err(resp) = push_user(req.headers(), &mut pack_protocol).await {
+        return Ok(resp);
     }
     // Convert the request body into a data stream.
-    let mut data_stream = req.into_body().into_data_stream();
+    let data_stream = req.into_body().into_data_stream();
+    receive_pack(Box::pin(data_stream), pack_protocol).await
+}
+
+/// The user a push authenticates as, `Err` with the response turning the push away when
+/// the user can't write to the repository.
+async fn push_user(
+    headers: &HeaderMap<HeaderValue>,
+    pack_protocol: &mut SmartProtocol,
+) -> Result<Option<String>, Response<Body>> {
+    let user = http_user(headers, &pack_protocol.context, TokenScope::RepoWrite).await;
+    let denied = if pack_protocol.context.config.authentication.enable_http_auth && user.is_none() {
+        auth_failed()
+    } else {
+        match apply_acl(pack_protocol, user.as_deref(), AclPermission::Write).await {
+            Ok(true) => return Ok(user),
+            Ok(false) => access_denied(user.as_deref(), &pack_protocol.path),
+            Err(e) => Err(e),
+        }
+    };
+    Err(denied.unwrap_or_else(IntoResponse::into_response))
+}
+
+async fn receive_pack(
+    mut data_stream: Pin<Box<dyn Stream<Item = Result<Bytes, axum::Error>> + Send>>,
+    mut pack_protocol: SmartProtocol,
+) -> Result<Response<Body>, ProtocolError> {
     let mut report_status = Bytes::new();
 
     let mut chunk_buffer = BytesMut::new(); // Used to cache the data of chunks before the PACK subsequence is found.
@@ -222,11 +268,104 @@ pub async fn git_receive_pack(
     Ok(response)
 }
 
+/// # Resumable receive-pack
+///
+/// A large push can be sent in chunks instead of a single request body:
+/// - `POST .../git-receive-pack/upload` opens a session and answers with its id and offset 0.
+/// - `POST .../git-receive-pack/upload/{session}?offset=n` stores the chunk in the body,
+///   it must start at the offset the server reports. A chunk at another offset gets
+///   `409 Conflict` with the stored offset.
+/// - `GET .../git-receive-pack/upload/{session}` reports the stored offset, a client
+///   resumes there after an interrupted chunk.
+/// - `POST .../git-receive-pack/upload/{session}/done` runs the push on the stored body
+///   and answers like a plain receive-pack request. The session is gone afterwards.
+///
+/// Sessions belong to the repository and user that opened them.
+pub async fn git_receive_pack_upload(
+    method: Method,
+    req: Request<Body>,
+    session: Option<&str>,
+    params: UploadParams,
+    done: bool,
+    mut pack_protocol: SmartProtocol,
+) -> Result<Response<Body>, ProtocolError> {
+    let user = match push_user(req.headers(), &mut pack_protocol).await {
+        Ok(user) => user,
+        Err(resp) => return Ok(resp),
+    };
+    let config = &pack_protocol.context.config.pack;
+    let root = config.upload_session_path.clone();
+    let Some(id) = session else {
+        let ttl = Duration::from_secs(config.upload_session_ttl);
+        let session =
+            UploadSession::create(&root, ttl, &pack_protocol.path, user.as_deref()).await?;
+        tracing::info!(
+            "open upload session {} for {:?}",
+            session.id,
+            pack_protocol.path
+        );
+        return Ok(upload_status(StatusCode::CREATED, &session.status().await?));
+    };
+    let session = UploadSession::open(&root, id, &pack_protocol.path, user.as_deref())
+        .await?
+        .ok_or_else(|| ProtocolError::NotFound(format!("upload session {}", id)))?;
+    if method == Method::GET {
+        return Ok(upload_status(StatusCode::OK, &session.status().await?));
+    }
+    if done {
+        let body = session
+            .body()
+            .await?
+            .map(|bytes| bytes.map_err(axum::Error::new));
+        let resp = receive_pack(Box::pin(body), pack_protocol).await?;
+        session.remove().await?;
+        return Ok(resp);
+    }
+    let offset = params
+        .offset
+        .ok_or_else(|| ProtocolError::InvalidInput("missing offset".to_owned()))?;
+    let chunk = req.into_body().into_data_stream();
+    let (status, offset) = match session.append(offset, chunk).await? {
+        Ok(offset) => (StatusCode::OK, offset),
+        Err(stored) => (StatusCode::CONFLICT, stored),
+    };
+    let status_body = UploadStatus {
+        session: session.id,
+        offset,
+    };
+    Ok(upload_status(status, &status_body))
+}
+
+fn upload_status(status: StatusCode, upload: &UploadStatus) -> Response<Body> {
+    let mut response = Response::builder()
+        .status(status)
+        .body(Body::from(serde_json::to_vec(upload).unwrap()))
+        .unwrap();
+    response
+        .headers_mut()
+        .insert("Content-Type", HeaderValue::from_static("application/json"));
+    response
+}
+
 // Function to find the subsequence in a slice
 pub fn search_subsequence(chunk: &[u8], search: &[u8]) -> Option<usize> {
     chunk.windows(search.len()).position(|s| s == search)
 }
 
+/// Count a finished upload-pack negotiation, it's a fetch when the client already has objects.
+/// Intermediate rounds of a stateless negotiation don't send `done` and are skipped.
+pub fn record_upload_traffic(path: &Path, upload_request: &[u8]) {
+    if search_subsequence(upload_request, b"done").is_none() {
+        return;
+    }
+    let kind = if search_subsequence(upload_request, b"have ").is_some() {
+        TrafficKind::Fetch
+    } else {
+        TrafficKind::Clone
+    };
+    TrafficEvent::notify(kind, &path.to_string_lossy());
+}
+
 /// # Build Response headers for Smart Server.
 /// Clients MUST NOT reuse or revalidate a cached response.
 /// Servers MUST include sufficient Cache-Control headers to prevent caching of the response.
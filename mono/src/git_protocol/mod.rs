@@ -1,2 +1,37 @@
+use std::path::PathBuf;
+
+use callisto::db_enums::AclPermission;
+use ceres::protocol::SmartProtocol;
+use common::errors::ProtocolError;
+
 pub mod ssh;
-pub mod http;
\ No newline at end of file
+pub mod http;
+pub mod upload;
+
+/// Check the path ACL for a pack request. The repository path needs `permission`, the
+/// directories below it the user can't read are hidden from what is served. Pushing
+/// needs the whole directory, changes made on a partial view can't be told apart from
+/// deletions. The user is kept on `pack_protocol`, updates are recorded as theirs.
+pub(crate) async fn apply_acl(
+    pack_protocol: &mut SmartProtocol,
+    user: Option<&str>,
+    permission: AclPermission,
+) -> Result<bool, ProtocolError> {
+    let context = &pack_protocol.context;
+    let acl = context
+        .acl_stg()
+        .load_path_acl(user, &context.config.monorepo.admin)
+        .await
+        .map_err(|e| ProtocolError::IO(std::io::Error::other(e.to_string())))?;
+    let path = pack_protocol.path.to_str().unwrap();
+    if !acl.allows(path, permission) {
+        return Ok(false);
+    }
+    let hidden = acl.hidden_below(path);
+    if permission == AclPermission::Write && !hidden.is_empty() {
+        return Ok(false);
+    }
+    pack_protocol.hidden_paths = hidden.into_iter().map(PathBuf::from).collect();
+    pack_protocol.user = user.map(str::to_owned);
+    Ok(true)
+}
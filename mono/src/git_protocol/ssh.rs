@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -10,16 +9,18 @@ use futures::{stream, StreamExt};
 use russh::server::{self, Auth, Msg, Session};
 use russh::{Channel, ChannelId, MethodSet};
 use russh_keys::{self, HashAlg, PublicKey};
-use tokio::io::AsyncReadExt;
 
+use callisto::db_enums::AclPermission;
 use ceres::lfs::lfs_structs::Link;
 use ceres::protocol::smart::{self};
 use ceres::protocol::ServiceType;
 use ceres::protocol::{SmartProtocol, TransportProtocol};
+use common::path::MonoPath;
 use jupiter::context::Context;
 use tokio::sync::Mutex;
 
-use crate::git_protocol::http::search_subsequence;
+use crate::git_protocol::apply_acl;
+use crate::git_protocol::http::{record_upload_traffic, search_subsequence};
 
 type ClientMap = HashMap<(usize, ChannelId), Channel<Msg>>;
 #[allow(dead_code)]
@@ -30,6 +31,8 @@ pub struct SshServer {
     pub context: Context,
     pub smart_protocol: Option<SmartProtocol>,
     pub data_combined: BytesMut,
+    /// Owner of the key the client authenticated with, the path ACL is checked against it.
+    pub user: Option<String>,
 }
 
 impl server::Server for SshServer {
@@ -82,18 +85,35 @@ impl server::Handler for SshServer {
         // Pull: git-upload-pack '/path/to/repo.git'
         // LFS HTTP Authenticate: git-lfs-authenticate '/path/to/repo.git' download/upload
         let command: Vec<_> = data.split(' ').collect();
-        let path = command[1];
-        let path = path.replace(".git", "").replace('\'', "");
+        let Some(path) = command.get(1) else {
+            return self.reject(channel, session, "fatal: missing repository path");
+        };
+        let path = path.trim_matches('\'');
+        let path = MonoPath::parse(path.strip_suffix(".git").unwrap_or(path))?;
         let mut smart_protocol = SmartProtocol::new(
-            PathBuf::from(&path),
+            path.into(),
             self.context.clone(),
             TransportProtocol::Ssh,
         );
         match command[0] {
             "git-upload-pack" | "git-receive-pack" => {
-                smart_protocol.service_type = Some(ServiceType::from_str(command[0]).unwrap());
-                // TODO handler ProtocolError
-                let res = smart_protocol.git_info_refs().await.unwrap();
+                let service_type = ServiceType::from_str(command[0]).unwrap();
+                smart_protocol.service_type = Some(service_type);
+                let permission = match service_type {
+                    ServiceType::UploadPack => AclPermission::Read,
+                    ServiceType::ReceivePack => AclPermission::Write,
+                };
+                if !apply_acl(&mut smart_protocol, self.user.as_deref(), permission).await? {
+                    return self.reject(
+                        channel,
+                        session,
+                        &format!("fatal: access to {} denied", smart_protocol.path.display()),
+                    );
+                }
+                let res = match smart_protocol.git_info_refs().await {
+                    Ok(res) => res,
+                    Err(err) => return self.reject(channel, session, &format!("fatal: {}", err)),
+                };
                 self.smart_protocol = Some(smart_protocol);
                 session.data(channel, res.to_vec().into())?;
                 session.channel_success(channel)?;
@@ -120,7 +140,13 @@ impl server::Handler for SshServer {
                 };
                 session.data(channel, serde_json::to_vec(&link).unwrap().into())?;
             }
-            command => tracing::error!("Not Supported command! {}", command),
+            command => {
+                return self.reject(
+                    channel,
+                    session,
+                    &format!("fatal: unsupported command {}", command),
+                );
+            }
         }
         Ok(())
     }
@@ -137,9 +163,21 @@ impl server::Handler for SshServer {
             user,
             fingerprint
         );
-        let res = self.context.user_stg().search_ssh_key_finger(&fingerprint).await.unwrap();
-        if !res.is_empty() {
+        let user_stg = self.context.user_stg();
+        let keys = user_stg
+            .search_ssh_key_finger(&fingerprint)
+            .await
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let owner = match keys.first() {
+            Some(key) => user_stg
+                .find_user_by_id(key.user_id)
+                .await
+                .map_err(|e| anyhow::anyhow!(e.to_string()))?,
+            None => None,
+        };
+        if let Some(owner) = owner {
             tracing::info!("Client public key verified successfully!");
+            self.user = Some(owner.name);
             Ok(Auth::Accept)
         } else {
             tracing::warn!("Client public key verification failed!");
@@ -155,7 +193,10 @@ impl server::Handler for SshServer {
         data: &[u8],
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let smart_protocol = self.smart_protocol.as_mut().unwrap();
+        // the command was rejected, nothing is listening for the data
+        let Some(smart_protocol) = self.smart_protocol.as_mut() else {
+            return Ok(());
+        };
         tracing::info!(
             "receiving data length:{}",
             // String::from_utf8_lossy(data),
@@ -196,10 +237,25 @@ impl server::Handler for SshServer {
 }
 
 impl SshServer {
+    /// Report `message` on the stderr of the client and end the command with a failure.
+    fn reject(
+        &self,
+        channel: ChannelId,
+        session: &mut Session,
+        message: &str,
+    ) -> Result<(), anyhow::Error> {
+        tracing::warn!("ssh command rejected: {}", message);
+        session.extended_data(channel, 1, format!("{}\n", message).into_bytes().into())?;
+        session.exit_status_request(channel, 1)?;
+        session.close(channel)?;
+        Ok(())
+    }
+
     async fn handle_upload_pack(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) {
         let smart_protocol = self.smart_protocol.as_mut().unwrap();
+        record_upload_traffic(&smart_protocol.path, data);
 
-        let (mut send_pack_data, buf) = smart_protocol
+        let (send_pack_data, buf) = smart_protocol
             .git_upload_pack(&mut Bytes::copy_from_slice(data))
             .await
             .unwrap();
@@ -207,18 +263,9 @@ impl SshServer {
         tracing::info!("buf is {:?}", buf);
         session.data(channel, String::from_utf8(buf.to_vec()).unwrap().into()).unwrap();
 
-        while let Some(chunk) = send_pack_data.next().await {
-            let mut reader = chunk.as_slice();
-            loop {
-                let mut temp = BytesMut::new();
-                temp.reserve(65500);
-                let length = reader.read_buf(&mut temp).await.unwrap();
-                if length == 0 {
-                    break;
-                }
-                let bytes_out = smart_protocol.build_side_band_format(temp, length);
-                session.data(channel, bytes_out.to_vec().into()).unwrap();
-            }
+        let mut pack_stream = Box::pin(smart_protocol.pack_data_stream(send_pack_data));
+        while let Some(bytes_out) = pack_stream.next().await {
+            session.data(channel, bytes_out.to_vec().into()).unwrap();
         }
         session.data(channel, smart::PKT_LINE_END_MARKER.to_vec().into()).unwrap();
     }
@@ -0,0 +1,231 @@
+//! Resumable receive-pack uploads. A client pushing a large pack opens a session, sends the
+//! request body in chunks starting at the offset the server has stored, then finishes the
+//! session to run the push. After a dropped connection it asks for the stored offset and
+//! continues from there instead of sending the whole pack again.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+const BODY_FILE: &str = "body";
+const OWNER_FILE: &str = "owner.json";
+
+/// What the server reports about a session, the client sends its next chunk at `offset`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct UploadStatus {
+    pub session: String,
+    pub offset: u64,
+}
+
+/// The push a session belongs to, other repositories or users can't touch it.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Owner {
+    repo: PathBuf,
+    user: Option<String>,
+}
+
+pub struct UploadSession {
+    pub id: String,
+    dir: PathBuf,
+}
+
+impl UploadSession {
+    /// Open a new session for a push of `user` to `repo`, sessions idle for longer than
+    /// `ttl` are removed first.
+    pub async fn create(
+        root: &Path,
+        ttl: Duration,
+        repo: &Path,
+        user: Option<&str>,
+    ) -> io::Result<Self> {
+        remove_expired(root, ttl).await?;
+        let id = Uuid::new_v4().to_string();
+        let dir = root.join(&id);
+        fs::create_dir_all(&dir).await?;
+        let owner = Owner {
+            repo: repo.to_owned(),
+            user: user.map(str::to_owned),
+        };
+        fs::write(dir.join(OWNER_FILE), serde_json::to_vec(&owner)?).await?;
+        File::create(dir.join(BODY_FILE)).await?;
+        Ok(UploadSession { id, dir })
+    }
+
+    /// The session `id` of a push of `user` to `repo`, `None` if there's no such session.
+    pub async fn open(
+        root: &Path,
+        id: &str,
+        repo: &Path,
+        user: Option<&str>,
+    ) -> io::Result<Option<Self>> {
+        // the id becomes a path, only accept what `create` hands out
+        if Uuid::parse_str(id).is_err() {
+            return Ok(None);
+        }
+        let dir = root.join(id);
+        let owner = match fs::read(dir.join(OWNER_FILE)).await {
+            Ok(owner) => owner,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let owner: Owner = serde_json::from_slice(&owner)?;
+        if owner.repo != repo || owner.user.as_deref() != user {
+            return Ok(None);
+        }
+        Ok(Some(UploadSession {
+            id: id.to_owned(),
+            dir,
+        }))
+    }
+
+    /// Number of bytes of the request body received so far.
+    pub async fn offset(&self) -> io::Result<u64> {
+        Ok(fs::metadata(self.dir.join(BODY_FILE)).await?.len())
+    }
+
+    pub async fn status(&self) -> io::Result<UploadStatus> {
+        Ok(UploadStatus {
+            session: self.id.clone(),
+            offset: self.offset().await?,
+        })
+    }
+
+    /// Store a chunk of the request body starting at `offset`. A chunk that doesn't start
+    /// where the stored body ends is refused with `Err` of the stored offset. The bytes
+    /// received before the chunk breaks off are kept, the client resumes after them.
+    pub async fn append<S, E>(&self, offset: u64, mut chunk: S) -> io::Result<Result<u64, u64>>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(self.dir.join(BODY_FILE))
+            .await?;
+        let stored = file.metadata().await?.len();
+        if offset != stored {
+            return Ok(Err(stored));
+        }
+        file.seek(io::SeekFrom::Start(offset)).await?;
+        let mut written = offset;
+        while let Some(bytes) = chunk.next().await {
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    tracing::warn!("upload session {} broke off at {}: {}", self.id, written, e);
+                    break;
+                }
+            };
+            file.write_all(&bytes).await?;
+            written += bytes.len() as u64;
+        }
+        file.flush().await?;
+        Ok(Ok(written))
+    }
+
+    /// The request body received so far.
+    pub async fn body(&self) -> io::Result<Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>> {
+        let mut file = File::open(self.dir.join(BODY_FILE)).await?;
+        Ok(Box::pin(async_stream::stream! {
+            loop {
+                let mut buf = vec![0; 64 * 1024];
+                match file.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        buf.truncate(n);
+                        yield Ok(Bytes::from(buf));
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                }
+            }
+        }))
+    }
+
+    pub async fn remove(self) -> io::Result<()> {
+        fs::remove_dir_all(&self.dir).await
+    }
+}
+
+/// Remove the sessions that haven't received a chunk for longer than `ttl`.
+async fn remove_expired(root: &Path, ttl: Duration) -> io::Result<()> {
+    fs::create_dir_all(root).await?;
+    let mut entries = fs::read_dir(root).await?;
+    let now = SystemTime::now();
+    while let Some(entry) = entries.next_entry().await? {
+        let modified = match fs::metadata(entry.path().join(BODY_FILE)).await {
+            Ok(meta) => meta.modified()?,
+            Err(_) => continue,
+        };
+        if now.duration_since(modified).unwrap_or_default() > ttl {
+            tracing::info!("remove expired upload session {:?}", entry.file_name());
+            fs::remove_dir_all(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &'static [u8]) -> impl Stream<Item = Result<Bytes, io::Error>> + Unpin {
+        tokio_stream::iter(vec![Ok(Bytes::from_static(data))])
+    }
+
+    #[tokio::test]
+    async fn test_resume_upload() {
+        let root = tempfile::tempdir().unwrap();
+        let repo = Path::new("/project");
+        let ttl = Duration::from_secs(60);
+        let session = UploadSession::create(root.path(), ttl, repo, Some("mega"))
+            .await
+            .unwrap();
+        assert_eq!(session.append(0, chunk(b"0000")).await.unwrap(), Ok(4));
+        // a retried chunk the server already has is refused with the offset to resume at
+        assert_eq!(session.append(0, chunk(b"0000")).await.unwrap(), Err(4));
+        assert_eq!(session.append(4, chunk(b"PACK")).await.unwrap(), Ok(8));
+
+        let id = session.id.clone();
+        assert!(UploadSession::open(root.path(), &id, repo, None)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(
+            UploadSession::open(root.path(), "../etc", repo, Some("mega"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let session = UploadSession::open(root.path(), &id, repo, Some("mega"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.status().await.unwrap().offset, 8);
+        let body: Vec<Bytes> = session
+            .body()
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(body.concat(), b"0000PACK");
+
+        session.remove().await.unwrap();
+        assert!(UploadSession::open(root.path(), &id, repo, Some("mega"))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
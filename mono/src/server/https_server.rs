@@ -6,7 +6,8 @@ use anyhow::Result;
 use async_session::MemoryStore;
 use axum::body::Body;
 use axum::extract::{Query, State};
-use axum::http::{self, Request, Uri};
+use axum::http::{self, Method, Request, Uri};
+use axum::middleware;
 use axum::response::Response;
 use axum::routing::get;
 use axum::Router;
@@ -21,13 +22,18 @@ use tower_http::trace::TraceLayer;
 
 use ceres::protocol::{ServiceType, SmartProtocol, TransportProtocol};
 use common::errors::ProtocolError;
-use common::model::{CommonOptions, InfoRefsParams};
+use common::model::{CommonOptions, InfoRefsParams, UploadParams};
+use common::path::MonoPath;
 use jupiter::context::Context;
 
+use crate::api::access_log;
 use crate::api::api_router::{self};
 use crate::api::lfs::lfs_router;
+use crate::api::metrics;
 use crate::api::oauth::{self, oauth_client};
+use crate::api::rate_limit::{self, RateLimiter};
 use crate::api::MonoApiServiceState;
+use crate::server::{shutdown_handle, shutdown_signal};
 
 #[derive(Args, Clone, Debug)]
 pub struct HttpOptions {
@@ -61,8 +67,13 @@ pub struct AppState {
     pub common: CommonOptions,
 }
 
-pub fn remove_git_suffix(uri: Uri, git_suffix: &str) -> PathBuf {
-    PathBuf::from(uri.path().replace(".git", "").replace(git_suffix, ""))
+/// Strip the git service suffix and the optional `.git` extension from a request uri,
+/// the remaining repo path is normalized so it addresses the same tree as the API.
+pub fn remove_git_suffix(uri: Uri, git_suffix: &str) -> Result<PathBuf, ProtocolError> {
+    let path = uri.path();
+    let path = path.strip_suffix(git_suffix).unwrap_or(path);
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    Ok(MonoPath::parse(path)?.into())
 }
 
 pub async fn start_https(context: Context, options: HttpsOptions) {
@@ -81,7 +92,8 @@ pub async fn start_https(context: Context, options: HttpsOptions) {
         .await
         .unwrap();
     axum_server::bind_rustls(addr, config)
-        .serve(app.into_make_service())
+        .handle(shutdown_handle())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -98,15 +110,19 @@ pub async fn start_http(context: Context, options: HttpOptions) {
 
     let addr = SocketAddr::from_str(&server_url).unwrap();
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
 }
 
 /// This is the main entry for the mono server.
 /// It is responsible for creating the main router and setting up the necessary middleware.
 ///
-/// The main router is composed of three nested routers:
+/// The main router is composed of these routers:
 /// 1. The LFS router nested in the `/`:
 ///   - GET or PUT `/objects/:object_id`
 ///   - GET or PUT `/locks`
@@ -117,22 +133,72 @@ pub async fn start_http(context: Context, options: HttpOptions) {
 /// 2. The API router nested in the `/api/v1`:
 ///   - GET        `/api/v1/status`
 ///   - POST       `/api/v1/create-file`
+///   - POST       `/api/v1/update-file`
+///   - POST       `/api/v1/delete-entry`
+///   - POST       `/api/v1/move-entry`
+///   - POST       `/api/v1/batch-commit`
+///   - POST       `/api/v1/cherry-pick`
+///   - POST       `/api/v1/revert`
 ///   - GET        `/api/v1/latest-commit`
+///   - GET        `/api/v1/history`
+///   - GET        `/api/v1/blame`
+///   - GET        `/api/v1/diff`
+///   - GET        `/api/v1/commit/{oid}`
+///   - GET        `/api/v1/compare`
+///   - GET        `/api/v1/branches`
+///   - POST       `/api/v1/branches`
+///   - POST       `/api/v1/branches/delete`
+///   - GET        `/api/v1/archive`
+///   - GET        `/api/v1/search`
 ///   - GET        `/api/v1/tree/commit-info`
 ///   - GET        `/api/v1/tree`
+///   - GET        `/api/v1/tree/readme`
 ///   - GET        `/api/v1/blob`
+///   - GET        `/api/v1/raw/{path}`
+///   - POST       `/api/v1/blob/batch`
+///   - GET        `/api/v1/blob/preview`
 ///   - GET        `/api/v1/file/blob/:object_id`
 ///   - GET        `/api/v1/file/tree`
 ///   - GET        `/api/v1/path-can-clone`
+///   - GET        `/api/v1/events/stream`
+///   - GET        `/api/v1/events/ws`
+///   - GET        `/api/v1/traffic`
+///   - GET        `/api/v1/stats`
+///   - POST       `/api/v1/checks`
+///   - POST       `/api/v1/checks/{id}/update`
+///   - GET        `/api/v1/checks/commit/{commit_id}`
+///   - GET        `/api/v1/tags`
+///   - POST       `/api/v1/tags`
+///   - GET        `/api/v1/releases`
+///   - POST       `/api/v1/releases`
+///   - POST       `/api/v1/releases/{id}/update`
+///   - GET        `/api/v1/admin/gc`
+///   - POST       `/api/v1/admin/gc`
+///   - GET        `/api/v1/admin/tombstones`
+///   - POST       `/api/v1/admin/tombstones/{id}/restore`
+///   - GET        `/api/v1/admin/queue`
+///   - GET        `/api/v1/admin/queue/dead-letters`
+///   - POST       `/api/v1/admin/queue/dead-letters/{id}/requeue`
+///   - GET        `/api/v1/admin/queue/jobs`
+///   - POST       `/api/v1/admin/queue/jobs/{name}/run`
+///   - GET        `/api/v1/admin/audit`
+///   - GET        `/api/v1/notifications`
+///   - GET        `/api/v1/notifications/unread-count`
+///   - POST       `/api/v1/notifications/read`
 /// 3. The OAuth router nested in the `/auth`:
 ///   - GET        `/auth/github`
 ///   - GET        `/auth/authorized`
 ///   - GET        `/auth/logout`
-/// 4. The other routers for the git protocol:
+/// 4. The Prometheus metrics, left out of the authentication for scrapers:
+///   - GET        `/metrics`
+/// 5. The other routers for the git protocol:
 ///   - GET        end of `Regex::new(r"/info/refs$")`
 ///   - POST       end of `Regex::new(r"/git-upload-pack$")`
 ///   - POST       end of `Regex::new(r"/git-receive-pack$")`
+///   - GET, POST  end of `Regex::new(r"/git-receive-pack/upload(?:/([0-9a-f-]+)(/done)?)?$")`
 pub async fn app(context: Context, host: String, port: u16, common: CommonOptions) -> Router {
+    metrics::install();
+    let rate_limiter = RateLimiter::new(&context);
     let state = AppState {
         host,
         port,
@@ -154,11 +220,26 @@ pub async fn app(context: Context, host: String, port: u16, common: CommonOption
         .merge(lfs_router::routers().with_state(api_state.clone()))
         .merge(Router::new().nest(
             "/api/v1",
-            api_router::routers().with_state(api_state.clone()),
+            api_router::routers()
+                .layer(middleware::from_fn_with_state(
+                    api_state.clone(),
+                    access_log::access_log,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    api_state.clone(),
+                    oauth::token_auth,
+                ))
+                .with_state(api_state.clone()),
         ))
         .merge(Router::new().nest("/auth", oauth::routers().with_state(api_state.clone())))
+        .route("/metrics", get(metrics::render))
         // Using Regular Expressions for Path Matching in Protocol
         .route("/{*path}", get(get_method_router).post(post_method_router))
+        .layer(middleware::from_fn_with_state(
+            rate_limiter,
+            rate_limit::rate_limit,
+        ))
+        .layer(middleware::from_fn(metrics::track))
         .layer(
             ServiceBuilder::new().layer(CorsLayer::new().allow_origin(Any).allow_headers(vec![
                 http::header::AUTHORIZATION,
@@ -170,25 +251,48 @@ pub async fn app(context: Context, host: String, port: u16, common: CommonOption
         .with_state(state)
 }
 
+/// Whether `path` is one of the git protocol endpoints, which send and receive packs.
+pub fn is_git_protocol_path(path: &str) -> bool {
+    INFO_REFS_REGEX.is_match(path)
+        || REGEX_GIT_UPLOAD_PACK.is_match(path)
+        || REGEX_GIT_RECEIVE_PACK.is_match(path)
+        || REGEX_RECEIVE_PACK_UPLOAD.is_match(path)
+}
+
 lazy_static! {
     /// The following regular expressions are used to match the Git server protocol.
     static ref INFO_REFS_REGEX: Regex = Regex::new(r"/info/refs$").unwrap();
     static ref REGEX_GIT_UPLOAD_PACK: Regex = Regex::new(r"/git-upload-pack$").unwrap();
     static ref REGEX_GIT_RECEIVE_PACK: Regex = Regex::new(r"/git-receive-pack$").unwrap();
+    static ref REGEX_RECEIVE_PACK_UPLOAD: Regex =
+        Regex::new(r"/git-receive-pack/upload(?:/([0-9a-f-]+)(/done)?)?$").unwrap();
 }
 
 pub async fn get_method_router(
     state: State<AppState>,
     Query(params): Query<InfoRefsParams>,
     uri: Uri,
+    req: Request<Body>,
 ) -> Result<Response<Body>, ProtocolError> {
     if INFO_REFS_REGEX.is_match(uri.path()) {
         let pack_protocol = SmartProtocol::new(
-            remove_git_suffix(uri, "/info/refs"),
+            remove_git_suffix(uri, "/info/refs")?,
             state.context.clone(),
             TransportProtocol::Http,
         );
-        crate::git_protocol::http::git_info_refs(params, pack_protocol).await
+        crate::git_protocol::http::git_info_refs(params, req.headers(), pack_protocol).await
+    } else if let Some(caps) = REGEX_RECEIVE_PACK_UPLOAD.captures(uri.path()) {
+        let session = caps.get(1).map(|m| m.as_str());
+        let pack_protocol = receive_pack_protocol(&state, &uri, &caps[0])?;
+        crate::git_protocol::http::git_receive_pack_upload(
+            Method::GET,
+            req,
+            session,
+            UploadParams { offset: None },
+            false,
+            pack_protocol,
+        )
+        .await
     } else {
         Err(ProtocolError::NotFound(
             "Operation not supported".to_owned(),
@@ -198,25 +302,34 @@ pub async fn get_method_router(
 
 pub async fn post_method_router(
     state: State<AppState>,
+    Query(params): Query<UploadParams>,
     uri: Uri,
     req: Request<Body>,
 ) -> Result<Response, ProtocolError> {
     if REGEX_GIT_UPLOAD_PACK.is_match(uri.path()) {
         let mut pack_protocol = SmartProtocol::new(
-            remove_git_suffix(uri.clone(), "/git-upload-pack"),
+            remove_git_suffix(uri.clone(), "/git-upload-pack")?,
             state.context.clone(),
             TransportProtocol::Http,
         );
         pack_protocol.service_type = Some(ServiceType::UploadPack);
         crate::git_protocol::http::git_upload_pack(req, pack_protocol).await
     } else if REGEX_GIT_RECEIVE_PACK.is_match(uri.path()) {
-        let mut pack_protocol = SmartProtocol::new(
-            remove_git_suffix(uri.clone(), "/git-receive-pack"),
-            state.context.clone(),
-            TransportProtocol::Http,
-        );
-        pack_protocol.service_type = Some(ServiceType::ReceivePack);
+        let pack_protocol = receive_pack_protocol(&state, &uri, "/git-receive-pack")?;
         crate::git_protocol::http::git_receive_pack(req, pack_protocol).await
+    } else if let Some(caps) = REGEX_RECEIVE_PACK_UPLOAD.captures(uri.path()) {
+        let session = caps.get(1).map(|m| m.as_str());
+        let done = caps.get(2).is_some();
+        let pack_protocol = receive_pack_protocol(&state, &uri, &caps[0])?;
+        crate::git_protocol::http::git_receive_pack_upload(
+            Method::POST,
+            req,
+            session,
+            params,
+            done,
+            pack_protocol,
+        )
+        .await
     } else {
         return Err(ProtocolError::NotFound(
             "Operation not supported".to_owned(),
@@ -224,5 +337,19 @@ pub async fn post_method_router(
     }
 }
 
+fn receive_pack_protocol(
+    state: &AppState,
+    uri: &Uri,
+    git_suffix: &str,
+) -> Result<SmartProtocol, ProtocolError> {
+    let mut pack_protocol = SmartProtocol::new(
+        remove_git_suffix(uri.clone(), git_suffix)?,
+        state.context.clone(),
+        TransportProtocol::Http,
+    );
+    pack_protocol.service_type = Some(ServiceType::ReceivePack);
+    Ok(pack_protocol)
+}
+
 #[cfg(test)]
 mod tests {}
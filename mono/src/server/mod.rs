@@ -1,2 +1,42 @@
+use std::time::Duration;
+
 pub mod https_server;
-pub mod ssh_server;
\ No newline at end of file
+pub mod ssh_server;
+
+/// Time requests still running get to finish once a shutdown signal came.
+const GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Wait for Ctrl-C, or SIGTERM as sent by a deploy.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown signal received");
+}
+
+/// A handle shutting an `axum_server` down gracefully on a shutdown signal.
+pub fn shutdown_handle() -> axum_server::Handle {
+    let handle = axum_server::Handle::new();
+    let signaled = handle.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        signaled.graceful_shutdown(Some(GRACE_PERIOD));
+    });
+    handle
+}
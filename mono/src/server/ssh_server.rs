@@ -16,6 +16,7 @@ use tokio::sync::Mutex;
 use vault::vault::{read_secret, write_secret};
 
 use crate::git_protocol::ssh::SshServer;
+use crate::server::shutdown_signal;
 
 #[derive(Args, Clone, Debug)]
 pub struct SshOptions {
@@ -59,10 +60,15 @@ pub async fn start_server(context: Context, command: &SshOptions) {
         context,
         smart_protocol: None,
         data_combined: BytesMut::new(),
+        user: None,
     };
     let server_url = format!("{}:{}", host, ssh_port);
     let addr = SocketAddr::from_str(&server_url).unwrap();
-    ssh_server.run_on_address(ru_config, addr).await.unwrap();
+    // open sessions are dropped on shutdown, a client retries a clone or push
+    tokio::select! {
+        res = ssh_server.run_on_address(ru_config, addr) => res.unwrap(),
+        _ = shutdown_signal() => {}
+    }
 }
 
 pub fn load_key() -> PrivateKey {
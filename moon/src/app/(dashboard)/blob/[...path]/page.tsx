@@ -67,6 +67,6 @@ export default function BlobPage({ params }: { params: Params }) {
 async function getFileContent(pathname: string) {
     const res = await fetch(`/api/blob?path=${pathname}`);
     const response = await res.json();
-    const directory = response.data.data;
+    const directory = response.data.data?.content ?? '';
     return directory
 }
@@ -31,7 +31,7 @@ export default function HomePage() {
 async function getDirectory(pathname: string) {
   const res = await fetch(`/api/tree/commit-info?path=${pathname}`);
   const response = await res.json();
-  const directory = response.data.data;
+  const directory = response.data.data.items;
   return directory
 }
 
@@ -41,7 +41,7 @@ async function getReadmeContent(pathname, directory) {
     if (project.name === 'README.md' && project.content_type === 'file') {
       const res = await fetch(`/api/blob?path=${pathname}/README.md`);
       const response = await res.json();
-      readmeContent = response.data.data;
+      readmeContent = response.data.data?.content ?? '';
       break;
     }
   }
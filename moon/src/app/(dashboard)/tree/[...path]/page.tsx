@@ -82,7 +82,7 @@ export default function Page({ params }: { params: Params }) {
 async function getDirectory(pathname: string) {
     const res = await fetch(`/api/tree/commit-info?path=${pathname}`);
     const response = await res.json();
-    return response.data.data
+    return response.data.data.items
 }
 
 async function getReadmeContent(pathname, directory) {
@@ -91,7 +91,7 @@ async function getReadmeContent(pathname, directory) {
         if (project.name === 'README.md' && project.content_type === 'file') {
             const res = await fetch(`/api/blob?path=${pathname}/README.md`);
             const response = await res.json();
-            readmeContent = response.data.data;
+            readmeContent = response.data.data?.content ?? '';
             break;
         }
     }
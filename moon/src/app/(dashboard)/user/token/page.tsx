@@ -13,7 +13,9 @@ import copy from 'copy-to-clipboard';
 
 interface TokenItem {
     id: number,
-    token: string,
+    name: string,
+    token_prefix: string,
+    scopes: string[],
     created_at: string,
 }
 
@@ -40,6 +42,10 @@ export default function KeysPage() {
         try {
             const res = await fetch(`/api/user/token`, {
                 method: 'POST',
+                body: JSON.stringify({
+                    name: `token-${format(new Date(), "yyyyMMddHHmmss")}`,
+                    scopes: ['repo:read', 'repo:write'],
+                }),
             });
             const response = await res.json();
             const token = response.data.data;
@@ -116,7 +122,7 @@ export default function KeysPage() {
                             }
                             title={
                                 <>
-                                    {item.token}
+                                    {item.name} ({item.token_prefix}...) {item.scopes.join(', ')}
                                 </>
                             }
                             description={`Generated on ${format(new Date(item.created_at), "MMM dd,yyyy")}`}
@@ -17,6 +17,7 @@ export async function POST(request: NextRequest) {
             'Content-Type': 'application/json',
         },
         method: 'POST',
+        body: await request.text(),
     })
     if (!res.ok) {
         return new NextResponse(
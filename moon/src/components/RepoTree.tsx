@@ -98,7 +98,7 @@ const RepoTree = ({ directory }) => {
             } catch (error) {
                 console.error('Error fetching tree data:', error);
             }
-            const subTreeData = convertToTreeData(responseData.data.data);
+            const subTreeData = convertToTreeData(responseData.data.data.items);
             const newTreeData = appendTreeData(treeData, subTreeData, node.title);
             setExpandedKeys([...expandedKeys, node.key]);
             setTreeData(newTreeData);
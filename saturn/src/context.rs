@@ -38,6 +38,8 @@ pub enum ContextError {
 pub enum Error {
     #[error("Authorization Denied")]
     AuthDenied(Diagnostics),
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
     #[error("Error constructing authorization request: {0}")]
     Request(String),
 }
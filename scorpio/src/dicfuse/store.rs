@@ -108,13 +108,18 @@ impl DicItem {
 #[derive(Serialize, Deserialize, Debug,Default,Clone)]
 struct ApiResponse {
     req_result: bool,
-    data: Vec<Item>,
+    data: TreePage,
     err_message: String,
 }
+/// One page of a tree listing, the whole directory is returned when no page is requested.
+#[derive(Serialize, Deserialize, Debug,Default,Clone)]
+struct TreePage {
+    items: Vec<Item>,
+}
 impl Iterator for ApiResponse{
     type Item = Item;
     fn next(&mut self) -> Option<Self::Item> {
-        self.data.pop()
+        self.data.items.pop()
     }
 }
 // Get Mega dictionary tree from server
@@ -143,7 +148,7 @@ impl DictionaryStore {
     #[allow(clippy::await_holding_lock)]
     pub async fn async_import(&self){
     
-            let items = fetch_tree("").await.unwrap().data.clone() ;
+            let items = fetch_tree("").await.unwrap().data.items.clone() ;
 
             let root_inode: Arc<DicItem> = self.inodes.lock().await.get(&1).unwrap().clone();
             for it in items{
@@ -170,7 +175,7 @@ impl DictionaryStore {
                         if path.len()>1{
                             drop(ct);
                             let t = fetch_tree(&path.clone()).await;
-                            new_items = t.unwrap().data.clone() ;
+                            new_items = t.unwrap().data.items.clone() ;
                         }
                     }
 
@@ -226,7 +231,7 @@ impl DictionaryStore {
 
     pub async fn import(&self){
         // 在阻塞线程中运行异步任务
-        let items =  fetch_tree("").await.unwrap().data;
+        let items =  fetch_tree("").await.unwrap().data.items;
         
         let root_inode = self.inodes.lock().await.get(&1).unwrap().clone();
         for it in items{
@@ -247,7 +252,7 @@ impl DictionaryStore {
                         println!("fetch path :{}",path);
                         
                         // 在阻塞线程中运行异步任务
-                        new_items =fetch_tree(&path).await.unwrap().data;
+                        new_items =fetch_tree(&path).await.unwrap().data.items;
                 
                     }
                    
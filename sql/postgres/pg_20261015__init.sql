@@ -31,6 +31,7 @@ CREATE INDEX "idx_mb_git_id" ON "mega_blob" ("blob_id");
 CREATE TABLE IF NOT EXISTS "mega_tag" (
   "id" BIGINT PRIMARY KEY,
   "tag_id" VARCHAR(40) NOT NULL,
+  "path" TEXT NOT NULL DEFAULT '/',
   "object_id" VARCHAR(40) NOT NULL,
   "object_type" VARCHAR(20) NOT NULL,
   "tag_name" TEXT NOT NULL,
@@ -48,6 +49,17 @@ CREATE TABLE IF NOT EXISTS "mega_mr" (
   "path" TEXT NOT NULL,
   "from_hash" VARCHAR(40) NOT NULL,
   "to_hash" VARCHAR(40) NOT NULL,
+  "source_branch" TEXT,
+  "target_branch" TEXT NOT NULL DEFAULT 'main',
+  "description" TEXT,
+  "reviewers" JSON NOT NULL DEFAULT '[]',
+  "draft" BOOLEAN NOT NULL DEFAULT FALSE,
+  "author" TEXT,
+  "depends_on" VARCHAR(40),
+  "labels" JSON NOT NULL DEFAULT '[]',
+  "assignees" JSON NOT NULL DEFAULT '[]',
+  "milestone" TEXT,
+  "supersedes" VARCHAR(40),
   "created_at" TIMESTAMP NOT NULL,
   "updated_at" TIMESTAMP NOT NULL
 );
@@ -59,6 +71,11 @@ CREATE TABLE IF NOT EXISTS "mega_conversation" (
   "user_id" BIGINT NOT NULL,
   "conv_type"  VARCHAR(20) NOT NULL,
   "comment" TEXT,
+  "path" TEXT,
+  "commit_id" VARCHAR(40),
+  "line" INTEGER,
+  "reply_to" BIGINT,
+  "resolved" BOOLEAN NOT NULL DEFAULT FALSE,
   "created_at" TIMESTAMP NOT NULL,
   "updated_at" TIMESTAMP NOT NULL
 );
@@ -164,6 +181,7 @@ CREATE TABLE IF NOT EXISTS "raw_blob" (
   "file_type" VARCHAR(20),
   "storage_type" VARCHAR(20) NOT NULL,
   "data" BYTEA,
+  "compression" VARCHAR(20),
   "local_path" TEXT,
   "remote_url" TEXT,
   "created_at" TIMESTAMP NOT NULL,
@@ -271,7 +289,34 @@ CREATE TABLE IF NOT EXISTS "mq_storage" (
   "id" BIGINT PRIMARY KEY,
   "category" VARCHAR(64),
   "create_time" TIMESTAMP NOT NULL,
-  "content" TEXT
+  "content" TEXT,
+  "done" BOOLEAN NOT NULL DEFAULT FALSE,
+  "priority" INTEGER NOT NULL DEFAULT 1,
+  "deliver_at" TIMESTAMP,
+  "idempotency_key" VARCHAR(128)
+);
+CREATE INDEX "idx_mq_storage_done" ON "mq_storage" ("done");
+
+CREATE TABLE IF NOT EXISTS "mq_dead_letter" (
+  "id" BIGINT PRIMARY KEY,
+  "category" VARCHAR(64),
+  "content" TEXT,
+  "attempts" INTEGER NOT NULL,
+  "error" TEXT NOT NULL,
+  "create_time" TIMESTAMP NOT NULL,
+  "dead_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mq_idempotency_key" (
+  "key" VARCHAR(128) PRIMARY KEY,
+  "message_id" BIGINT NOT NULL,
+  "claimed_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mq_idempotency_key_claimed_at" ON "mq_idempotency_key" ("claimed_at");
+
+CREATE TABLE IF NOT EXISTS "mq_job" (
+  "name" VARCHAR(64) PRIMARY KEY,
+  "last_run" TIMESTAMP
 );
 
 CREATE TABLE IF NOT EXISTS "ztm_path_mapping" (
@@ -309,11 +354,15 @@ CREATE INDEX "idx_ssh_key_finger" ON "ssh_keys" ((left(finger, 8)));
 CREATE TABLE IF NOT EXISTS "access_token" (
   "id" BIGINT PRIMARY KEY,
   "user_id" BIGINT NOT NULL,
-  "token" TEXT NOT NULL,
-  "created_at" TIMESTAMP NOT NULL
+  "name" TEXT NOT NULL,
+  "token_hash" TEXT NOT NULL,
+  "token_prefix" VARCHAR(20) NOT NULL,
+  "scopes" JSON NOT NULL,
+  "last_used_at" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_token_hash UNIQUE (token_hash)
 );
 CREATE INDEX "idx_token_user_id" ON "access_token" ("user_id");
-CREATE INDEX "idx_token" ON "access_token" ((left(token, 8)));
 
 
 CREATE TABLE IF NOT EXISTS "builds" (
@@ -324,4 +373,223 @@ CREATE TABLE IF NOT EXISTS "builds" (
   "end_at" timestamp with time zone NOT NULL,
   "repo_name" varchar NOT NULL,
   "target" varchar NOT NULL
-);
\ No newline at end of file
+);
+
+CREATE TABLE IF NOT EXISTS "traffic_stats" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "kind" VARCHAR(20) NOT NULL,
+  "day" DATE NOT NULL,
+  "count" BIGINT NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_traffic_path_kind_day UNIQUE (path, kind, day)
+);
+CREATE INDEX "idx_traffic_day" ON "traffic_stats" ("day");
+
+CREATE TABLE IF NOT EXISTS "audit_log" (
+  "id" BIGINT PRIMARY KEY,
+  "actor" VARCHAR(255) NOT NULL,
+  "action" VARCHAR(20) NOT NULL,
+  "target" TEXT NOT NULL,
+  "detail" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_audit_log_created_at" ON "audit_log" ("created_at");
+CREATE INDEX "idx_audit_log_actor" ON "audit_log" ("actor");
+
+CREATE TABLE IF NOT EXISTS "mega_notification" (
+  "id" BIGINT PRIMARY KEY,
+  "user_id" BIGINT NOT NULL,
+  "kind" VARCHAR(20) NOT NULL,
+  "actor" VARCHAR(255) NOT NULL,
+  "target" TEXT NOT NULL,
+  "title" TEXT NOT NULL,
+  "body" TEXT,
+  "read" BOOLEAN NOT NULL DEFAULT FALSE,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mega_notification_user" ON "mega_notification" ("user_id", "read");
+
+CREATE TABLE IF NOT EXISTS "dir_stats" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL UNIQUE,
+  "tree_id" VARCHAR(40) NOT NULL,
+  "size" BIGINT NOT NULL,
+  "file_count" BIGINT NOT NULL,
+  "own_size" BIGINT NOT NULL,
+  "own_file_count" BIGINT NOT NULL,
+  "last_activity" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+
+
+CREATE TABLE IF NOT EXISTS "check_runs" (
+  "id" BIGINT PRIMARY KEY,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "name" TEXT NOT NULL,
+  "status" VARCHAR(20) NOT NULL,
+  "conclusion" VARCHAR(20),
+  "title" TEXT,
+  "summary" TEXT,
+  "details_url" TEXT,
+  "creator_id" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  "completed_at" TIMESTAMP
+);
+CREATE INDEX "idx_check_runs_commit" ON "check_runs" ("commit_id");
+
+CREATE TABLE IF NOT EXISTS "check_annotations" (
+  "id" BIGINT PRIMARY KEY,
+  "check_run_id" BIGINT NOT NULL,
+  "path" TEXT NOT NULL,
+  "start_line" INTEGER NOT NULL,
+  "end_line" INTEGER NOT NULL,
+  "level" VARCHAR(20) NOT NULL,
+  "title" TEXT,
+  "message" TEXT NOT NULL
+);
+CREATE INDEX "idx_check_annotations_run" ON "check_annotations" ("check_run_id");
+
+CREATE TABLE IF NOT EXISTS "mega_mr_review" (
+  "id" BIGINT PRIMARY KEY,
+  "link" VARCHAR(20) NOT NULL,
+  "user_id" BIGINT NOT NULL,
+  "username" VARCHAR(255) NOT NULL,
+  "state" VARCHAR(20) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE UNIQUE INDEX "idx_mr_review_user" ON "mega_mr_review" ("link", "user_id");
+
+CREATE TABLE IF NOT EXISTS "search_file" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL UNIQUE,
+  "blob_id" VARCHAR(40) NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "search_trigram" (
+  "file_id" BIGINT NOT NULL,
+  "trigram" VARCHAR(12) NOT NULL,
+  PRIMARY KEY ("file_id", "trigram")
+);
+CREATE INDEX "idx_search_trigram" ON "search_trigram" ("trigram");
+
+CREATE TABLE IF NOT EXISTS "search_state" (
+  "id" BIGINT PRIMARY KEY,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "tree_id" VARCHAR(40) NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX "idx_mtag_path_name" ON "mega_tag" ("path", "tag_name");
+
+CREATE TABLE IF NOT EXISTS "mega_tombstone" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "tree_id" VARCHAR(40) NOT NULL,
+  "restored_commit" VARCHAR(40),
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mega_tombstone_path" ON "mega_tombstone" ("path");
+
+CREATE TABLE IF NOT EXISTS "mega_release" (
+  "id" BIGINT PRIMARY KEY,
+  "tag_id" VARCHAR(40) NOT NULL UNIQUE,
+  "path" TEXT NOT NULL,
+  "tag_name" TEXT NOT NULL,
+  "title" TEXT NOT NULL,
+  "notes" TEXT,
+  "creator_id" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mega_release_path" ON "mega_release" ("path");
+
+CREATE TABLE IF NOT EXISTS "mega_release_asset" (
+  "id" BIGINT PRIMARY KEY,
+  "release_id" BIGINT NOT NULL,
+  "name" TEXT NOT NULL,
+  "url" TEXT NOT NULL,
+  "size" BIGINT,
+  "content_type" TEXT,
+  "checksum" TEXT,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mega_release_asset_release" ON "mega_release_asset" ("release_id");
+
+CREATE TABLE IF NOT EXISTS "mega_protection_rule" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "branch" TEXT NOT NULL,
+  "require_mr" BOOLEAN NOT NULL,
+  "forbid_force_update" BOOLEAN NOT NULL,
+  "required_checks" JSON NOT NULL,
+  "allowed_mergers" JSON NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_mpr_path_branch UNIQUE (path, branch)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_path_acl" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "principal_type" VARCHAR(20) NOT NULL,
+  "principal" VARCHAR(255) NOT NULL,
+  "permission" VARCHAR(20) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_macl_path_principal UNIQUE (path, principal_type, principal)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_team" (
+  "id" BIGINT PRIMARY KEY,
+  "name" VARCHAR(255) NOT NULL UNIQUE,
+  "description" TEXT,
+  "created_at" TIMESTAMP NOT NULL,
+  "updated_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mega_team_member" (
+  "id" BIGINT PRIMARY KEY,
+  "team" VARCHAR(255) NOT NULL,
+  "username" VARCHAR(255) NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_mtm_team_user UNIQUE (team, username)
+);
+CREATE INDEX "idx_mega_team_member_user" ON "mega_team_member" ("username");
+
+CREATE TABLE IF NOT EXISTS "mega_commit_signature" (
+  "id" BIGINT PRIMARY KEY,
+  "commit_id" VARCHAR(40) NOT NULL UNIQUE,
+  "format" VARCHAR(20) NOT NULL,
+  "verified" BOOLEAN NOT NULL,
+  "signer" TEXT,
+  "reason" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mega_push_cert" (
+  "id" BIGINT PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "pusher" TEXT NOT NULL,
+  "nonce_status" VARCHAR(20) NOT NULL,
+  "format" VARCHAR(20),
+  "verified" BOOLEAN NOT NULL,
+  "signer" TEXT,
+  "reason" TEXT NOT NULL,
+  "certificate" TEXT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mega_push_cert_path" ON "mega_push_cert" ("path");
+CREATE TABLE IF NOT EXISTS "mega_commit_graph" (
+  "id" BIGINT PRIMARY KEY,
+  "commit_id" VARCHAR(40) NOT NULL,
+  "parents_id" JSON NOT NULL,
+  "generation" BIGINT NOT NULL,
+  "commit_time" BIGINT NOT NULL,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_mcg_commit_id UNIQUE (commit_id)
+);
+CREATE INDEX "idx_mcg_generation" ON "mega_commit_graph" ("generation");
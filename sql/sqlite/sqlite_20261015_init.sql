@@ -31,6 +31,7 @@ CREATE INDEX "idx_mb_git_id" ON "mega_blob" ("blob_id");
 CREATE TABLE IF NOT EXISTS "mega_tag" (
   "id" INTEGER PRIMARY KEY,
   "tag_id" TEXT NOT NULL,
+  "path" TEXT NOT NULL DEFAULT '/',
   "object_id" TEXT NOT NULL,
   "object_type" TEXT NOT NULL,
   "tag_name" TEXT NOT NULL,
@@ -48,6 +49,17 @@ CREATE TABLE IF NOT EXISTS "mega_mr" (
   "path" TEXT NOT NULL,
   "from_hash" TEXT NOT NULL,
   "to_hash" TEXT NOT NULL,
+  "source_branch" TEXT,
+  "target_branch" TEXT NOT NULL DEFAULT 'main',
+  "description" TEXT,
+  "reviewers" TEXT NOT NULL DEFAULT '[]',  -- Use JSON to store array
+  "draft" INTEGER NOT NULL DEFAULT 0,
+  "author" TEXT,
+  "depends_on" TEXT,
+  "labels" TEXT NOT NULL DEFAULT '[]',
+  "assignees" TEXT NOT NULL DEFAULT '[]',
+  "milestone" TEXT,
+  "supersedes" TEXT,
   "created_at" TEXT NOT NULL,
   "updated_at" TEXT NOT NULL
 );
@@ -59,6 +71,11 @@ CREATE TABLE IF NOT EXISTS "mega_conversation" (
   "user_id" INTEGER NOT NULL,
   "conv_type" TEXT NOT NULL,
   "comment" TEXT,
+  "path" TEXT,
+  "commit_id" TEXT,
+  "line" INTEGER,
+  "reply_to" INTEGER,
+  "resolved" INTEGER NOT NULL DEFAULT 0,
   "created_at" TEXT NOT NULL,
   "updated_at" TEXT NOT NULL
 );
@@ -162,6 +179,7 @@ CREATE TABLE IF NOT EXISTS "raw_blob" (
   "file_type" TEXT,
   "storage_type" TEXT NOT NULL,
   "data" BLOB,
+  "compression" TEXT,
   "local_path" TEXT,
   "remote_url" TEXT,
   "created_at" TEXT NOT NULL,
@@ -269,7 +287,34 @@ CREATE TABLE IF NOT EXISTS "mq_storage" (
   "id" INTEGER PRIMARY KEY,
   "category" TEXT,
   "create_time" TIMESTAMP NOT NULL,
-  "content" TEXT
+  "content" TEXT,
+  "done" BOOLEAN NOT NULL DEFAULT FALSE,
+  "priority" INTEGER NOT NULL DEFAULT 1,
+  "deliver_at" TIMESTAMP,
+  "idempotency_key" TEXT
+);
+CREATE INDEX "idx_mq_storage_done" ON "mq_storage" ("done");
+
+CREATE TABLE IF NOT EXISTS "mq_dead_letter" (
+  "id" INTEGER PRIMARY KEY,
+  "category" TEXT,
+  "content" TEXT,
+  "attempts" INTEGER NOT NULL,
+  "error" TEXT NOT NULL,
+  "create_time" TIMESTAMP NOT NULL,
+  "dead_at" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mq_idempotency_key" (
+  "key" TEXT PRIMARY KEY,
+  "message_id" INTEGER NOT NULL,
+  "claimed_at" TIMESTAMP NOT NULL
+);
+CREATE INDEX "idx_mq_idempotency_key_claimed_at" ON "mq_idempotency_key" ("claimed_at");
+
+CREATE TABLE IF NOT EXISTS "mq_job" (
+  "name" TEXT PRIMARY KEY,
+  "last_run" TIMESTAMP
 );
 
 CREATE TABLE IF NOT EXISTS "ztm_path_mapping" (
@@ -306,8 +351,231 @@ CREATE INDEX "idx_ssh_key_finger" ON "ssh_keys" ("finger");
 CREATE TABLE IF NOT EXISTS "access_token" (
   "id" BIGINT PRIMARY KEY,
   "user_id" BIGINT NOT NULL,
-  "token" TEXT NOT NULL,
-  "created_at" TIMESTAMP NOT NULL
+  "name" TEXT NOT NULL,
+  "token_hash" TEXT NOT NULL,
+  "token_prefix" TEXT NOT NULL,
+  "scopes" TEXT NOT NULL,  -- Use JSON to store array
+  "last_used_at" TIMESTAMP,
+  "created_at" TIMESTAMP NOT NULL,
+  CONSTRAINT uniq_token_hash UNIQUE (token_hash)
 );
 CREATE INDEX "idx_token_user_id" ON "access_token" ("user_id");
-CREATE INDEX "idx_token" ON "access_token" ("token");
\ No newline at end of file
+
+CREATE TABLE IF NOT EXISTS "traffic_stats" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "kind" TEXT NOT NULL,
+  "day" TEXT NOT NULL,
+  "count" INTEGER NOT NULL,
+  "updated_at" TEXT NOT NULL,
+  CONSTRAINT uniq_traffic_path_kind_day UNIQUE (path, kind, day)
+);
+CREATE INDEX "idx_traffic_day" ON "traffic_stats" ("day");
+
+CREATE TABLE IF NOT EXISTS "audit_log" (
+  "id" INTEGER PRIMARY KEY,
+  "actor" TEXT NOT NULL,
+  "action" TEXT NOT NULL,
+  "target" TEXT NOT NULL,
+  "detail" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL
+);
+CREATE INDEX "idx_audit_log_created_at" ON "audit_log" ("created_at");
+CREATE INDEX "idx_audit_log_actor" ON "audit_log" ("actor");
+
+CREATE TABLE IF NOT EXISTS "mega_notification" (
+  "id" INTEGER PRIMARY KEY,
+  "user_id" INTEGER NOT NULL,
+  "kind" TEXT NOT NULL,
+  "actor" TEXT NOT NULL,
+  "target" TEXT NOT NULL,
+  "title" TEXT NOT NULL,
+  "body" TEXT,
+  "read" INTEGER NOT NULL DEFAULT 0,
+  "created_at" TEXT NOT NULL
+);
+CREATE INDEX "idx_mega_notification_user" ON "mega_notification" ("user_id", "read");
+
+CREATE TABLE IF NOT EXISTS "dir_stats" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL UNIQUE,
+  "tree_id" TEXT NOT NULL,
+  "size" INTEGER NOT NULL,
+  "file_count" INTEGER NOT NULL,
+  "own_size" INTEGER NOT NULL,
+  "own_file_count" INTEGER NOT NULL,
+  "last_activity" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL
+);
+
+
+CREATE TABLE IF NOT EXISTS "check_runs" (
+  "id" INTEGER PRIMARY KEY,
+  "commit_id" TEXT NOT NULL,
+  "name" TEXT NOT NULL,
+  "status" TEXT NOT NULL,
+  "conclusion" TEXT,
+  "title" TEXT,
+  "summary" TEXT,
+  "details_url" TEXT,
+  "creator_id" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL,
+  "completed_at" TEXT
+);
+CREATE INDEX "idx_check_runs_commit" ON "check_runs" ("commit_id");
+
+CREATE TABLE IF NOT EXISTS "check_annotations" (
+  "id" INTEGER PRIMARY KEY,
+  "check_run_id" INTEGER NOT NULL,
+  "path" TEXT NOT NULL,
+  "start_line" INTEGER NOT NULL,
+  "end_line" INTEGER NOT NULL,
+  "level" TEXT NOT NULL,
+  "title" TEXT,
+  "message" TEXT NOT NULL
+);
+CREATE INDEX "idx_check_annotations_run" ON "check_annotations" ("check_run_id");
+
+CREATE TABLE IF NOT EXISTS "mega_mr_review" (
+  "id" INTEGER PRIMARY KEY,
+  "link" TEXT NOT NULL,
+  "user_id" INTEGER NOT NULL,
+  "username" TEXT NOT NULL,
+  "state" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL
+);
+CREATE UNIQUE INDEX "idx_mr_review_user" ON "mega_mr_review" ("link", "user_id");
+
+CREATE TABLE IF NOT EXISTS "search_file" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL UNIQUE,
+  "blob_id" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "search_trigram" (
+  "file_id" INTEGER NOT NULL,
+  "trigram" TEXT NOT NULL,
+  PRIMARY KEY ("file_id", "trigram")
+);
+CREATE INDEX "idx_search_trigram" ON "search_trigram" ("trigram");
+
+CREATE TABLE IF NOT EXISTS "search_state" (
+  "id" INTEGER PRIMARY KEY,
+  "commit_id" TEXT NOT NULL,
+  "tree_id" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL
+);
+
+CREATE INDEX "idx_mtag_path_name" ON "mega_tag" ("path", "tag_name");
+
+CREATE TABLE IF NOT EXISTS "mega_tombstone" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "commit_id" TEXT NOT NULL,
+  "tree_id" TEXT NOT NULL,
+  "restored_commit" TEXT,
+  "created_at" TEXT NOT NULL
+);
+CREATE INDEX "idx_mega_tombstone_path" ON "mega_tombstone" ("path");
+
+CREATE TABLE IF NOT EXISTS "mega_release" (
+  "id" INTEGER PRIMARY KEY,
+  "tag_id" TEXT NOT NULL UNIQUE,
+  "path" TEXT NOT NULL,
+  "tag_name" TEXT NOT NULL,
+  "title" TEXT NOT NULL,
+  "notes" TEXT,
+  "creator_id" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL
+);
+CREATE INDEX "idx_mega_release_path" ON "mega_release" ("path");
+
+CREATE TABLE IF NOT EXISTS "mega_release_asset" (
+  "id" INTEGER PRIMARY KEY,
+  "release_id" INTEGER NOT NULL,
+  "name" TEXT NOT NULL,
+  "url" TEXT NOT NULL,
+  "size" INTEGER,
+  "content_type" TEXT,
+  "checksum" TEXT,
+  "created_at" TEXT NOT NULL
+);
+CREATE INDEX "idx_mega_release_asset_release" ON "mega_release_asset" ("release_id");
+
+CREATE TABLE IF NOT EXISTS "mega_protection_rule" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "branch" TEXT NOT NULL,
+  "require_mr" INTEGER NOT NULL,
+  "forbid_force_update" INTEGER NOT NULL,
+  "required_checks" TEXT NOT NULL,  -- Use JSON to store array
+  "allowed_mergers" TEXT NOT NULL,  -- Use JSON to store array
+  "created_at" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL,
+  CONSTRAINT uniq_mpr_path_branch UNIQUE (path, branch)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_path_acl" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "principal_type" TEXT NOT NULL,
+  "principal" TEXT NOT NULL,
+  "permission" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_macl_path_principal UNIQUE (path, principal_type, principal)
+);
+
+CREATE TABLE IF NOT EXISTS "mega_team" (
+  "id" INTEGER PRIMARY KEY,
+  "name" TEXT NOT NULL UNIQUE,
+  "description" TEXT,
+  "created_at" TEXT NOT NULL,
+  "updated_at" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mega_team_member" (
+  "id" INTEGER PRIMARY KEY,
+  "team" TEXT NOT NULL,
+  "username" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_mtm_team_user UNIQUE (team, username)
+);
+CREATE INDEX "idx_mega_team_member_user" ON "mega_team_member" ("username");
+
+CREATE TABLE IF NOT EXISTS "mega_commit_signature" (
+  "id" INTEGER PRIMARY KEY,
+  "commit_id" TEXT NOT NULL UNIQUE,
+  "format" TEXT NOT NULL,
+  "verified" INTEGER NOT NULL,
+  "signer" TEXT,
+  "reason" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "mega_push_cert" (
+  "id" INTEGER PRIMARY KEY,
+  "path" TEXT NOT NULL,
+  "pusher" TEXT NOT NULL,
+  "nonce_status" TEXT NOT NULL,
+  "format" TEXT,
+  "verified" INTEGER NOT NULL,
+  "signer" TEXT,
+  "reason" TEXT NOT NULL,
+  "certificate" TEXT NOT NULL,
+  "created_at" TEXT NOT NULL
+);
+CREATE INDEX "idx_mega_push_cert_path" ON "mega_push_cert" ("path");
+CREATE TABLE IF NOT EXISTS "mega_commit_graph" (
+  "id" INTEGER PRIMARY KEY,
+  "commit_id" TEXT NOT NULL,
+  "parents_id" TEXT NOT NULL,
+  "generation" INTEGER NOT NULL,
+  "commit_time" INTEGER NOT NULL,
+  "created_at" TEXT NOT NULL,
+  CONSTRAINT uniq_mcg_commit_id UNIQUE (commit_id)
+);
+CREATE INDEX "idx_mcg_generation" ON "mega_commit_graph" ("generation");
@@ -0,0 +1,296 @@
+//! Transports carrying stored messages to the instance processing them. The local broker
+//! keeps them in the process, the Redis one spreads them over every instance reading the
+//! same stream.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use callisto::mq_storage;
+use common::config::{QueueBroker, QueueConfig};
+use common::errors::MegaError;
+use redis::aio::{ConnectionManager, MultiplexedConnection};
+use redis::streams::{
+    StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, RedisError};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use crate::event::Message;
+
+#[async_trait]
+pub trait Broker: Send + Sync {
+    /// Hand a stored message to whichever instance consumes it first.
+    async fn publish(&self, msg: Message) -> Result<(), MegaError>;
+
+    /// Wait for the next message for this instance to process.
+    async fn consume(&self) -> Result<Message, MegaError>;
+
+    /// The consumed message `id` is done with, processed or dead-lettered.
+    async fn ack(&self, id: i64) -> Result<(), MegaError>;
+
+    /// Whether messages consumed but never acked are handed out again by the broker,
+    /// the queue replays those left pending in the database otherwise.
+    fn redelivers(&self) -> bool;
+}
+
+/// The broker configured under `[queue]`.
+pub async fn connect(config: &QueueConfig) -> Result<Arc<dyn Broker>, MegaError> {
+    Ok(match config.broker {
+        QueueBroker::Local => Arc::new(LocalBroker::new(config.capacity.max(1))),
+        QueueBroker::Redis => Arc::new(RedisBroker::connect(config).await?),
+    })
+}
+
+pub struct LocalBroker {
+    sender: Sender<Message>,
+    receiver: tokio::sync::Mutex<Receiver<Message>>,
+}
+
+impl LocalBroker {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        LocalBroker {
+            sender,
+            receiver: tokio::sync::Mutex::new(receiver),
+        }
+    }
+}
+
+#[async_trait]
+impl Broker for LocalBroker {
+    async fn publish(&self, msg: Message) -> Result<(), MegaError> {
+        self.sender
+            .send(msg)
+            .await
+            .map_err(|_| MegaError::with_message("the local broker is closed"))
+    }
+
+    async fn consume(&self) -> Result<Message, MegaError> {
+        self.receiver
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| MegaError::with_message("the local broker is closed"))
+    }
+
+    async fn ack(&self, _id: i64) -> Result<(), MegaError> {
+        Ok(())
+    }
+
+    fn redelivers(&self) -> bool {
+        false
+    }
+}
+
+// Consumer group all instances join, each entry goes to one of them.
+const GROUP: &str = "mega";
+// Entries read or claimed at once.
+const BATCH: usize = 16;
+// How long a read waits for new entries.
+const BLOCK: Duration = Duration::from_secs(5);
+// Entries another consumer hasn't acked for that long are taken over, its instance is
+// assumed gone.
+const CLAIM_IDLE: Duration = Duration::from_secs(600);
+// How often to look for such entries.
+const CLAIM_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Messages go through a Redis stream read by a consumer group. Delivery is at least once,
+/// an instance stopping before acking has its entries resumed on restart, or claimed by
+/// another instance after [`CLAIM_IDLE`].
+pub struct RedisBroker {
+    manager: ConnectionManager,
+    reader: tokio::sync::Mutex<Reader>,
+    stream: String,
+    consumer: String,
+    // Stream entry of each message consumed but not acked yet.
+    entries: Mutex<HashMap<i64, String>>,
+}
+
+struct Reader {
+    // Reads block, so they get a connection of their own.
+    conn: MultiplexedConnection,
+    buffered: VecDeque<StreamId>,
+    // Where to go on reading the entries delivered to this consumer before a restart,
+    // `None` once they're all read.
+    history: Option<String>,
+    // Where to go on claiming idle entries, `None` between two sweeps.
+    claim: Option<String>,
+    last_claim: Instant,
+}
+
+impl RedisBroker {
+    pub async fn connect(config: &QueueConfig) -> Result<Self, MegaError> {
+        let client = redis::Client::open(config.redis_url.as_str()).map_err(redis_error)?;
+        let mut manager = ConnectionManager::new(client.clone())
+            .await
+            .map_err(redis_error)?;
+        let conn = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(redis_error)?;
+
+        // From the start of the stream, entries added before the group existed are
+        // processed too.
+        let created: Result<(), RedisError> = manager
+            .xgroup_create_mkstream(&config.stream, GROUP, "0")
+            .await;
+        match created {
+            Ok(()) => {}
+            Err(e) if e.code() == Some("BUSYGROUP") => {}
+            Err(e) => return Err(redis_error(e)),
+        }
+
+        let consumer = if config.consumer.is_empty() {
+            host_name()
+        } else {
+            config.consumer.clone()
+        };
+        tracing::info!("Consuming {} as {}", config.stream, consumer);
+        Ok(RedisBroker {
+            manager,
+            reader: tokio::sync::Mutex::new(Reader {
+                conn,
+                buffered: VecDeque::new(),
+                history: Some(String::from("0")),
+                claim: None,
+                last_claim: Instant::now(),
+            }),
+            stream: config.stream.clone(),
+            consumer,
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn read(&self, reader: &mut Reader) -> Result<(), MegaError> {
+        let options = StreamReadOptions::default()
+            .group(GROUP, &self.consumer)
+            .count(BATCH);
+        let (start, options) = match &reader.history {
+            Some(last) => (last.clone(), options),
+            None => (String::from(">"), options.block(BLOCK.as_millis() as usize)),
+        };
+        let reply: Option<StreamReadReply> = reader
+            .conn
+            .xread_options(&[&self.stream], &[&start], &options)
+            .await
+            .map_err(redis_error)?;
+        let entries: Vec<StreamId> = reply
+            .map(|reply| reply.keys.into_iter().flat_map(|key| key.ids).collect())
+            .unwrap_or_default();
+        if reader.history.is_some() {
+            reader.history = entries.last().map(|entry| entry.id.clone());
+        }
+        reader.buffered.extend(entries);
+        Ok(())
+    }
+
+    async fn claim(&self, reader: &mut Reader) -> Result<(), MegaError> {
+        let start = reader.claim.take().unwrap_or_else(|| String::from("0-0"));
+        let reply: StreamAutoClaimReply = reader
+            .conn
+            .xautoclaim_options(
+                &self.stream,
+                GROUP,
+                &self.consumer,
+                CLAIM_IDLE.as_millis() as u64,
+                start,
+                StreamAutoClaimOptions::default().count(BATCH),
+            )
+            .await
+            .map_err(redis_error)?;
+        if !reply.claimed.is_empty() {
+            tracing::warn!(
+                "Took over {} messages left unprocessed by another instance",
+                reply.claimed.len()
+            );
+        }
+        if reply.next_stream_id != "0-0" {
+            reader.claim = Some(reply.next_stream_id);
+        }
+        reader.buffered.extend(reply.claimed);
+        Ok(())
+    }
+
+    async fn remove(&self, entry: &str) -> Result<(), MegaError> {
+        let _: () = redis::pipe()
+            .xack(&self.stream, GROUP, &[entry])
+            .ignore()
+            .xdel(&self.stream, &[entry])
+            .ignore()
+            .query_async(&mut self.manager.clone())
+            .await
+            .map_err(redis_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Broker for RedisBroker {
+    async fn publish(&self, msg: Message) -> Result<(), MegaError> {
+        let model = mq_storage::Model::from(msg);
+        let payload =
+            serde_json::to_string(&model).map_err(|e| MegaError::with_message(&e.to_string()))?;
+        let _: String = self
+            .manager
+            .clone()
+            .xadd(&self.stream, "*", &[("message", payload)])
+            .await
+            .map_err(redis_error)?;
+        Ok(())
+    }
+
+    async fn consume(&self) -> Result<Message, MegaError> {
+        let mut reader = self.reader.lock().await;
+        loop {
+            if let Some(entry) = reader.buffered.pop_front() {
+                let msg = entry
+                    .get::<String>("message")
+                    .and_then(|payload| serde_json::from_str::<mq_storage::Model>(&payload).ok())
+                    .and_then(|model| Message::try_from(model).ok());
+                let Some(msg) = msg else {
+                    tracing::warn!("Dropped unreadable stream entry {}", entry.id);
+                    self.remove(&entry.id).await?;
+                    continue;
+                };
+                self.entries.lock().unwrap().insert(msg.id, entry.id);
+                return Ok(msg);
+            }
+            if reader.claim.is_some() || reader.last_claim.elapsed() >= CLAIM_INTERVAL {
+                reader.last_claim = Instant::now();
+                self.claim(&mut reader).await?;
+                if !reader.buffered.is_empty() {
+                    continue;
+                }
+            }
+            self.read(&mut reader).await?;
+        }
+    }
+
+    async fn ack(&self, id: i64) -> Result<(), MegaError> {
+        let entry = self.entries.lock().unwrap().remove(&id);
+        match entry {
+            Some(entry) => self.remove(&entry).await,
+            None => Ok(()),
+        }
+    }
+
+    fn redelivers(&self) -> bool {
+        true
+    }
+}
+
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("mega"))
+}
+
+fn redis_error(e: RedisError) -> MegaError {
+    MegaError::with_message(&format!("redis: {e}"))
+}
@@ -0,0 +1,232 @@
+//! Cron expressions of the scheduled jobs: minute, hour, day of month, month and day of
+//! week, in UTC. Fields take `*`, values, ranges `a-b`, steps `*/n` or `a-b/n` and lists
+//! of those. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` stand for the usual
+//! expressions.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use thiserror::Error;
+
+// How far ahead to look for a time matching, expressions like `0 0 31 2 *` never do.
+const HORIZON_DAYS: i64 = 5 * 366;
+
+#[derive(Debug, Error)]
+#[error("invalid cron expression \"{expr}\": {reason}")]
+pub struct CronError {
+    expr: String,
+    reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    // Bit `n` is set when value `n` matches.
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // A day matches either field when both are restricted, as in cron.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let error = |reason: String| CronError {
+            expr: expr.to_owned(),
+            reason,
+        };
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(error(format!("{} fields instead of 5", fields.len())));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7).map_err(error)?;
+        // 7 is sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(minute, 0, 59).map_err(error)?,
+            hours: parse_field(hour, 0, 23).map_err(error)?,
+            days: parse_field(day, 1, 31).map_err(error)?,
+            months: parse_field(month, 1, 12).map_err(error)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    /// The first time matching after `time`, `None` when there's none within years.
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = time.naive_utc().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let horizon = start + Duration::days(HORIZON_DAYS);
+        let mut t = start;
+        while t <= horizon {
+            if !matches(self.months, t.month()) {
+                let (year, month) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = midnight(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.matches_day(t.date()) {
+                t = midnight(t.date().succ_opt()?);
+            } else if !matches(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+            } else if !matches(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t.and_utc());
+            }
+        }
+        None
+    }
+
+    /// The last time matching at or before `now` and after `since`, the run missed
+    /// since then if any.
+    pub fn last_between(&self, since: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut last = None;
+        let mut t = since;
+        while let Some(next) = self.next_after(t).filter(|x| *x <= now) {
+            last = Some(next);
+            t = next;
+        }
+        last
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = matches(self.days, date.day());
+        let weekday = matches(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn matches(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).unwrap()
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|x| *x > 0)
+                    .ok_or_else(|| format!("invalid step in \"{part}\""))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((from, to)) => (parse_value(from, min, max)?, parse_value(to, min, max)?),
+                // `a/n` runs from `a` to the end
+                None if step > 1 => (parse_value(range, min, max)?, max),
+                None => {
+                    let value = parse_value(range, min, max)?;
+                    (value, value)
+                }
+            },
+        };
+        if from > to {
+            return Err(format!("empty range \"{part}\""));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|x| (min..=max).contains(x))
+        .ok_or_else(|| format!("\"{value}\" is not within {min}-{max}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use super::CronSchedule;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn next(expr: &str, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        expr.parse::<CronSchedule>().unwrap().next_after(time)
+    }
+
+    #[test]
+    fn test_next_after() {
+        // 2026-10-15 is a thursday
+        let now = at(2026, 10, 15, 10, 30);
+        assert_eq!(next("* * * * *", now), Some(at(2026, 10, 15, 10, 31)));
+        assert_eq!(next("0 3 * * *", now), Some(at(2026, 10, 16, 3, 0)));
+        assert_eq!(next("@hourly", now), Some(at(2026, 10, 15, 11, 0)));
+        assert_eq!(next("*/20 * * * *", now), Some(at(2026, 10, 15, 10, 40)));
+        assert_eq!(next("0 4 * * 0", now), Some(at(2026, 10, 18, 4, 0)));
+        assert_eq!(next("0 4 * * 7", now), Some(at(2026, 10, 18, 4, 0)));
+        assert_eq!(next("0 0 1 * *", now), Some(at(2026, 11, 1, 0, 0)));
+        assert_eq!(next("0 0 1 1 *", now), Some(at(2027, 1, 1, 0, 0)));
+        assert_eq!(
+            next("15 9-17/4 * * 1-5", now),
+            Some(at(2026, 10, 15, 13, 15))
+        );
+        // either the day of month or the weekday when both are restricted
+        assert_eq!(next("0 0 20 * 6", now), Some(at(2026, 10, 17, 0, 0)));
+        assert_eq!(next("0 0 29 2 *", now), Some(at(2028, 2, 29, 0, 0)));
+        assert_eq!(next("0 0 31 2 *", now), None);
+    }
+
+    #[test]
+    fn test_last_between() {
+        let schedule: CronSchedule = "0 3 * * *".parse().unwrap();
+        let since = at(2026, 10, 12, 3, 0);
+        assert_eq!(
+            schedule.last_between(since, at(2026, 10, 15, 10, 30)),
+            Some(at(2026, 10, 15, 3, 0))
+        );
+        assert_eq!(schedule.last_between(since, at(2026, 10, 13, 2, 59)), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for expr in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_err(), "{expr}");
+        }
+    }
+}
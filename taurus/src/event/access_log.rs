@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use common::errors::MegaError;
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+
+/// # Access Log Event
+///
+/// One record per API request, for usage analysis and abuse investigations.
+/// Records are persisted along with other messages by the message queue,
+/// and written to the `access_log` tracing target when processed.
+///
+/// This is not an audit log, it records who called what and how it went,
+/// not which change was made. Changes are recorded by [`AuditEvent`].
+///
+/// [`AuditEvent`]: crate::event::audit::AuditEvent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogEvent {
+    pub method: String,
+    /// matched route template, e.g. `/api/v1/mr/{link}/detail`
+    pub route: String,
+    /// user name of the session, `None` for anonymous requests
+    pub actor: Option<String>,
+    pub status: u16,
+    pub latency_ms: u64,
+    /// monorepo path the request was about
+    pub path: Option<String>,
+    /// merge request the request was about
+    pub mr_link: Option<String>,
+}
+
+impl std::fmt::Display for AccessLogEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Access Log Event: {} {} {}",
+            self.method, self.route, self.status
+        )
+    }
+}
+
+#[async_trait]
+impl EventBase for AccessLogEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        tracing::info!(
+            target: "access_log",
+            method = %self.method,
+            route = %self.route,
+            actor = self.actor.as_deref().unwrap_or("anonymous"),
+            status = self.status,
+            latency_ms = self.latency_ms,
+            path = self.path.as_deref().unwrap_or_default(),
+            mr_link = self.mr_link.as_deref().unwrap_or_default(),
+        );
+        Ok(())
+    }
+}
+
+impl AccessLogEvent {
+    // Create and enqueue this event.
+    pub fn notify(self) {
+        let _ = get_mq().send(EventType::AccessLog(self));
+    }
+}
+
+// For storing the data into database.
+impl From<AccessLogEvent> for Value {
+    fn from(value: AccessLogEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for AccessLogEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: AccessLogEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
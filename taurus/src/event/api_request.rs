@@ -1,4 +1,5 @@
 use common::config::Config;
+use common::errors::MegaError;
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
 
@@ -22,10 +23,26 @@ pub struct ApiRequestEvent {
 pub enum ApiType {
     // Common Api enum for api_routers
     CreateFile,
+    UpdateFile,
+    DeleteEntry,
+    MoveEntry,
+    BatchCommit,
+    CherryPick,
+    Revert,
     LastestCommit,
     CommitInfo,
     TreeInfo,
     Blob,
+    RawBlob,
+    BlobBatch,
+    BlobPreview,
+    FileHistory,
+    Blame,
+    CommitDiff,
+    CommitDetail,
+    Compare,
+    Archive,
+    Search,
     Publish,
 
     // Merge Api enum for mr_routers
@@ -44,15 +61,16 @@ impl std::fmt::Display for ApiRequestEvent {
 
 #[async_trait]
 impl EventBase for ApiRequestEvent {
-    async fn process(&self) {
+    async fn process(&self) -> Result<(), MegaError> {
         tracing::info!("Handling Api Request event: [{}]", &self);
+        Ok(())
     }
 }
 
 impl ApiRequestEvent {
     // Create and enqueue this event.
     pub fn notify(api: ApiType, config: &Config) {
-        get_mq().send(EventType::ApiRequest(ApiRequestEvent {
+        let _ = get_mq().send(EventType::ApiRequest(ApiRequestEvent {
             api,
             config: config.clone(),
         }));
@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use callisto::audit_log;
+use callisto::db_enums::AuditAction;
+use common::errors::MegaError;
+use common::utils::generate_id;
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+
+/// # Audit Event
+///
+/// A change made by a user, e.g. a push, a merge or a new token. Processing the
+/// event stores it in the audit log, which admins query to find out who changed
+/// what and when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// id of the record, so storing it twice keeps one
+    pub id: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    /// monorepo path the change was made on, the name or id of a token for token changes
+    pub target: String,
+    pub detail: Value,
+    pub time: DateTime<Utc>,
+}
+
+impl std::fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Audit Event: {} {} {}",
+            self.actor, self.action, self.target
+        )
+    }
+}
+
+#[async_trait]
+impl EventBase for AuditEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        get_mq()
+            .context
+            .audit_stg()
+            .save(audit_log::Model {
+                id: self.id,
+                actor: self.actor.clone(),
+                action: self.action,
+                target: self.target.clone(),
+                detail: self.detail.to_string(),
+                created_at: self.time.naive_utc(),
+            })
+            .await
+    }
+}
+
+impl AuditEvent {
+    // Create and enqueue this event, waits while the queue is full so no change goes
+    // unrecorded.
+    pub async fn notify(actor: &str, action: AuditAction, target: &str, detail: Value) {
+        let _ = get_mq()
+            .send_wait(EventType::Audit(AuditEvent {
+                id: generate_id(),
+                actor: actor.to_owned(),
+                action,
+                target: target.to_owned(),
+                detail,
+                time: Utc::now(),
+            }))
+            .await;
+    }
+}
+
+// For storing the data into database.
+impl From<AuditEvent> for Value {
+    fn from(value: AuditEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for AuditEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: AuditEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use callisto::dir_stats;
+use common::errors::MegaError;
+use common::utils::generate_id;
+use jupiter::context::Context;
+use jupiter::storage::mono_storage::MonoStorage;
+use jupiter::storage::raw_db_storage::RawDbStorage;
+use jupiter::storage::stats_storage::StatsStorage;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+use crate::scheduler::Priority;
+
+/// Blobs loaded per query to measure them.
+const SIZE_BLOB_BATCH: usize = 100;
+
+/// Events are processed concurrently, updates of the statistics must not interleave.
+static STATS_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// # Dir Stats Event
+///
+/// The root ref of the monorepo moved. Processing brings the size, file count and last
+/// activity kept for every directory up to date with the ref. Only the directories whose
+/// tree changed are visited and only the files changed in them are read, so a missed
+/// event is caught up by the next one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirStatsEvent {
+    /// commit the ref was moved to
+    pub commit_id: String,
+}
+
+impl std::fmt::Display for DirStatsEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dir Stats Event: {}", self.commit_id)
+    }
+}
+
+#[async_trait]
+impl EventBase for DirStatsEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        let _guard = STATS_LOCK.lock().await;
+        sync_stats(&get_mq().context).await
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+}
+
+impl DirStatsEvent {
+    // Create and enqueue this event.
+    pub fn notify(commit_id: &str) {
+        let _ = get_mq().send(EventType::DirStats(DirStatsEvent {
+            commit_id: commit_id.to_owned(),
+        }));
+    }
+}
+
+async fn sync_stats(context: &Context) -> Result<(), MegaError> {
+    let mono_storage = context.services.mono_storage.clone();
+    let Some(root) = mono_storage.get_ref("/").await? else {
+        return Ok(());
+    };
+    let time = mono_storage
+        .get_commit_by_hash(&root.ref_commit_hash)
+        .await?
+        .and_then(|x| DateTime::from_timestamp(Commit::from(x).committer.timestamp as i64, 0))
+        .unwrap_or_else(Utc::now)
+        .naive_utc();
+    let updater = StatsUpdater {
+        mono_storage,
+        raw_storage: context.services.raw_db_storage.clone(),
+        stats_storage: context.stats_stg(),
+        time,
+    };
+    let kept = updater.stats_storage.get_stats("/").await?;
+    updater
+        .update_dir("/".to_owned(), root.ref_tree_hash, kept)
+        .await?;
+    Ok(())
+}
+
+struct StatsUpdater {
+    mono_storage: MonoStorage,
+    raw_storage: RawDbStorage,
+    stats_storage: StatsStorage,
+    /// last activity of the directories changed
+    time: NaiveDateTime,
+}
+
+type StatsFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<dir_stats::Model, MegaError>> + Send + 'a>>;
+
+impl StatsUpdater {
+    /// Bring the statistics of directory `path` to the tree `tree_id`, `kept` is what
+    /// was stored for it so far. The files directly in it are counted from what was kept
+    /// for its previous tree, adding what was added and subtracting what was dropped.
+    fn update_dir(
+        &self,
+        path: String,
+        tree_id: String,
+        kept: Option<dir_stats::Model>,
+    ) -> StatsFuture<'_> {
+        Box::pin(async move {
+            if let Some(kept) = kept.as_ref().filter(|x| x.tree_id == tree_id) {
+                return Ok(kept.clone());
+            }
+            let previous = match &kept {
+                Some(kept) => self
+                    .mono_storage
+                    .get_tree_by_hash(&kept.tree_id)
+                    .await?
+                    .map(|x| (kept, Tree::from(x).tree_items)),
+                None => None,
+            };
+            // without the previous tree every file is counted again
+            let (mut own_size, mut own_file_count, mut old_items) = match previous {
+                Some((kept, items)) => (
+                    kept.own_size,
+                    kept.own_file_count,
+                    items
+                        .into_iter()
+                        .map(|x| (x.name.clone(), x))
+                        .collect::<HashMap<_, _>>(),
+                ),
+                None => (0, 0, HashMap::new()),
+            };
+
+            let items = self.load_items(&tree_id).await?;
+            let sub_paths = items
+                .iter()
+                .filter(|x| x.mode == TreeItemMode::Tree)
+                .map(|x| join(&path, &x.name))
+                .collect();
+            let mut sub_stats: HashMap<String, dir_stats::Model> = self
+                .stats_storage
+                .get_stats_by_paths(sub_paths)
+                .await?
+                .into_iter()
+                .map(|x| (x.path.clone(), x))
+                .collect();
+            let mut size = 0;
+            let mut file_count = 0;
+            let mut added = vec![];
+            let mut dropped = vec![];
+            for item in items {
+                let old_item = old_items.remove(&item.name);
+                let unchanged = old_item
+                    .as_ref()
+                    .is_some_and(|x| x.id == item.id && x.mode == item.mode);
+                if !unchanged {
+                    if let Some(old_item) = old_item {
+                        let still_dir = item.mode == TreeItemMode::Tree;
+                        self.drop_item(&path, old_item, still_dir, &mut dropped)
+                            .await?;
+                    }
+                    if is_file(&item) {
+                        added.push(item.id.to_string());
+                    }
+                }
+                if item.mode == TreeItemMode::Tree {
+                    let sub_path = join(&path, &item.name);
+                    let kept = sub_stats.remove(&sub_path);
+                    let sub = self.update_dir(sub_path, item.id.to_string(), kept).await?;
+                    size += sub.size;
+                    file_count += sub.file_count;
+                }
+            }
+            for old_item in old_items.into_values() {
+                self.drop_item(&path, old_item, false, &mut dropped).await?;
+            }
+
+            let sizes = self.blob_sizes(added.iter().chain(&dropped)).await?;
+            own_size += added.iter().map(|x| sizes[x]).sum::<i64>();
+            own_size -= dropped.iter().map(|x| sizes[x]).sum::<i64>();
+            own_file_count += added.len() as i64 - dropped.len() as i64;
+
+            let model = dir_stats::Model {
+                id: kept.map(|x| x.id).unwrap_or_else(generate_id),
+                path,
+                tree_id,
+                size: size + own_size,
+                file_count: file_count + own_file_count,
+                own_size,
+                own_file_count,
+                last_activity: self.time,
+                updated_at: Utc::now().naive_utc(),
+            };
+            self.stats_storage.save_stats(model.clone()).await?;
+            Ok(model)
+        })
+    }
+
+    /// Take out `item` of the previous tree of directory `path`. A directory still there
+    /// as one keeps its statistics, they are updated along with the new tree.
+    async fn drop_item(
+        &self,
+        path: &str,
+        item: TreeItem,
+        still_dir: bool,
+        dropped: &mut Vec<String>,
+    ) -> Result<(), MegaError> {
+        match item.mode {
+            TreeItemMode::Tree if !still_dir => {
+                self.stats_storage
+                    .remove_below(&join(path, &item.name))
+                    .await
+            }
+            _ if is_file(&item) => {
+                dropped.push(item.id.to_string());
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Size of each of the blobs `ids`, those not found count as empty.
+    async fn blob_sizes(
+        &self,
+        ids: impl Iterator<Item = &String>,
+    ) -> Result<HashMap<String, i64>, MegaError> {
+        let mut sizes: HashMap<String, i64> = ids.map(|x| (x.clone(), 0)).collect();
+        let ids: Vec<String> = sizes.keys().cloned().collect();
+        for batch in ids.chunks(SIZE_BLOB_BATCH) {
+            for blob in self
+                .raw_storage
+                .get_raw_blobs_by_hashes(batch.to_vec())
+                .await?
+            {
+                let size = blob.data.map(|x| x.len()).unwrap_or_default();
+                sizes.insert(blob.sha1, size as i64);
+            }
+        }
+        Ok(sizes)
+    }
+
+    async fn load_items(&self, id: &str) -> Result<Vec<TreeItem>, MegaError> {
+        match self.mono_storage.get_tree_by_hash(id).await? {
+            Some(model) => Ok(Tree::from(model).tree_items),
+            None => Err(MegaError::with_message(&format!("tree {} not found", id))),
+        }
+    }
+}
+
+/// Files are counted and measured, links and submodules aren't.
+fn is_file(item: &TreeItem) -> bool {
+    matches!(item.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable)
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+// For storing the data into database.
+impl From<DirStatsEvent> for Value {
+    fn from(value: DirStatsEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for DirStatsEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: DirStatsEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use common::errors::MegaError;
+use jupiter::context::Context;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+use crate::retry::RetryPolicy;
+use crate::scheduler::Priority;
+
+/// Objects loaded per query while marking.
+const MARK_BATCH: usize = 500;
+
+/// Only one collection runs at a time, a request while one runs is dropped.
+static GC_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn last_report() -> &'static Mutex<GcReport> {
+    static REPORT: OnceLock<Mutex<GcReport>> = OnceLock::new();
+    REPORT.get_or_init(|| Mutex::new(GcReport::default()))
+}
+
+/// # Garbage Collection Event
+///
+/// Removes the commits, trees and blobs nothing points to any more, e.g. those of a
+/// push that was replaced before it was merged, along with the content of the blobs.
+/// Everything reachable from a ref, a tag, an MR of any status, a deleted directory or
+/// an avatar is kept, as is everything stored within the grace period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcEvent {
+    /// who asked for the collection, `schedule` for the periodic runs
+    pub trigger: String,
+}
+
+/// What the last collection found and removed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    pub running: bool,
+    pub trigger: Option<String>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+    pub reachable_commits: usize,
+    pub reachable_trees: usize,
+    pub reachable_blobs: usize,
+    pub removed_commits: u64,
+    pub removed_trees: u64,
+    pub removed_blobs: u64,
+    pub removed_raw_blobs: u64,
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for GcEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GC Event: triggered by {}", self.trigger)
+    }
+}
+
+#[async_trait]
+impl EventBase for GcEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        let Ok(_guard) = GC_LOCK.try_lock() else {
+            tracing::info!("garbage collection already running, {} skipped", self);
+            return Ok(());
+        };
+        let mut report = GcReport {
+            running: true,
+            trigger: Some(self.trigger.clone()),
+            started_at: Some(Utc::now().naive_utc()),
+            ..Default::default()
+        };
+        *last_report().lock().unwrap() = report.clone();
+
+        let context = &get_mq().context;
+        let grace_period = context.config.gc.grace_period as i64;
+        // read before marking, objects stored while marking are always in the grace period
+        let before = Utc::now().naive_utc() - chrono::Duration::seconds(grace_period);
+        let res = collect(context, before, &mut report).await;
+        if let Err(err) = &res {
+            report.error = Some(err.to_string());
+        }
+        report.running = false;
+        report.finished_at = Some(Utc::now().naive_utc());
+        tracing::info!("garbage collection finished: {:?}", report);
+        *last_report().lock().unwrap() = report;
+        res
+    }
+
+    // The next run comes with the schedule, or when an admin asks again.
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::none()
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+}
+
+impl GcEvent {
+    // Create and enqueue this event.
+    pub fn notify(trigger: &str) {
+        let _ = get_mq().send(EventType::Gc(GcEvent {
+            trigger: trigger.to_owned(),
+        }));
+    }
+
+    /// The running collection, or the last one finished since the server started.
+    pub fn report() -> GcReport {
+        last_report().lock().unwrap().clone()
+    }
+
+    /// Queue a collection every `interval` seconds, 0 never does.
+    pub fn schedule(interval: u64) {
+        if interval == 0 {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(Duration::from_secs(interval));
+            // the first tick completes immediately, don't collect right at startup
+            timer.tick().await;
+            loop {
+                timer.tick().await;
+                GcEvent::notify("schedule");
+            }
+        });
+    }
+}
+
+/// Objects reached from the refs, tags, MRs, deleted directories and avatars.
+#[derive(Default)]
+struct Reachable {
+    commits: HashSet<String>,
+    trees: HashSet<String>,
+    blobs: HashSet<String>,
+}
+
+/// Remove the objects stored before `before` that aren't reachable.
+async fn collect(
+    context: &Context,
+    before: NaiveDateTime,
+    report: &mut GcReport,
+) -> Result<(), MegaError> {
+    let reachable = mark(context).await?;
+    report.reachable_commits = reachable.commits.len();
+    report.reachable_trees = reachable.trees.len();
+    report.reachable_blobs = reachable.blobs.len();
+
+    let storage = context.services.mono_storage.clone();
+    report.removed_commits = storage.sweep_commits(&reachable.commits, before).await?;
+    report.removed_trees = storage.sweep_trees(&reachable.trees, before).await?;
+    report.removed_blobs = storage.sweep_blobs(&reachable.blobs, before).await?;
+    report.removed_raw_blobs = storage.sweep_raw_blobs(&reachable.blobs, before).await?;
+    Ok(())
+}
+
+async fn mark(context: &Context) -> Result<Reachable, MegaError> {
+    let storage = context.services.mono_storage.clone();
+    let mut reachable = Reachable::default();
+    let mut commits = Vec::new();
+    let mut trees = Vec::new();
+    let mut blobs = Vec::new();
+
+    for r in storage.get_all_refs().await? {
+        commits.push(r.ref_commit_hash);
+        trees.push(r.ref_tree_hash);
+    }
+    for tag in storage.get_all_tags().await? {
+        match tag.object_type.as_str() {
+            "tree" => trees.push(tag.object_id),
+            "blob" => blobs.push(tag.object_id),
+            _ => commits.push(tag.object_id),
+        }
+    }
+    // deleted directories stay restorable
+    for tombstone in storage.get_tombstones("/").await? {
+        trees.push(tombstone.tree_id);
+    }
+    // closed MRs can be reopened, merged ones are still viewed
+    for (from_hash, to_hash) in context.mr_stg().get_mr_commits().await? {
+        commits.push(from_hash);
+        commits.push(to_hash);
+    }
+    // avatars are raw blobs, the url ends with the hash
+    for url in context.user_stg().get_avatar_urls().await? {
+        if let Some(hash) = url.rsplit('/').next().filter(|x| x.len() == 40) {
+            blobs.push(hash.to_owned());
+        }
+    }
+    reachable.blobs.extend(blobs);
+
+    while !commits.is_empty() {
+        let batch: Vec<String> = commits
+            .drain(..commits.len().min(MARK_BATCH))
+            .filter(|x| reachable.commits.insert(x.clone()))
+            .collect();
+        if batch.is_empty() {
+            continue;
+        }
+        for model in storage.get_commits_by_hashes(&batch).await? {
+            let commit = Commit::from(model);
+            trees.push(commit.tree_id.to_string());
+            commits.extend(commit.parent_commit_ids.iter().map(|x| x.to_string()));
+        }
+    }
+
+    while !trees.is_empty() {
+        let batch: Vec<String> = trees
+            .drain(..trees.len().min(MARK_BATCH))
+            .filter(|x| reachable.trees.insert(x.clone()))
+            .collect();
+        if batch.is_empty() {
+            continue;
+        }
+        for model in storage.get_trees_by_hashes(batch).await? {
+            for item in Tree::from(model).tree_items {
+                match item.mode {
+                    TreeItemMode::Tree => trees.push(item.id.to_string()),
+                    // submodules point to commits of other repositories
+                    TreeItemMode::Commit => {}
+                    _ => {
+                        reachable.blobs.insert(item.id.to_string());
+                    }
+                }
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+// For storing the data into database.
+impl From<GcEvent> for Value {
+    fn from(value: GcEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for GcEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: GcEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use callisto::{db_enums::MergeStatus, mega_mr, user};
+    use common::utils::generate_id;
+    use jupiter::context::Context;
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::object::commit::Commit;
+    use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+    use super::{collect, GcReport};
+
+    /// Store a commit of one file holding `content`, returns it with its blob.
+    async fn store_commit(context: &Context, content: &str) -> (Commit, Blob) {
+        let blob = Blob::from_content(content);
+        let item = TreeItem::new(TreeItemMode::Blob, blob.id, "file".to_owned());
+        let tree = Tree::from_tree_items(vec![item]).unwrap();
+        let commit = Commit::from_tree_id(tree.id, vec![], content);
+        let entries = vec![blob.clone().into(), tree.into(), commit.clone().into()];
+        context
+            .services
+            .mono_storage
+            .save_entry(&commit.id.to_string(), entries)
+            .await
+            .unwrap();
+        (commit, blob)
+    }
+
+    async fn save_mr(context: &Context, status: MergeStatus, from: &Commit, to: &Commit) {
+        let now = Utc::now().naive_utc();
+        let mr = mega_mr::Model {
+            id: generate_id(),
+            link: generate_id().to_string(),
+            title: "test".to_owned(),
+            merge_date: None,
+            status,
+            path: "/project".to_owned(),
+            from_hash: from.id.to_string(),
+            to_hash: to.id.to_string(),
+            source_branch: None,
+            target_branch: "main".to_owned(),
+            description: None,
+            reviewers: json!([]),
+            draft: false,
+            author: None,
+            depends_on: None,
+            labels: json!([]),
+            assignees: json!([]),
+            milestone: None,
+            supersedes: None,
+            created_at: now,
+            updated_at: now,
+        };
+        context.mr_stg().save_mr(mr).await.unwrap();
+    }
+
+    /// Whether the blob is still stored, as a blob and as a raw blob.
+    async fn is_stored(context: &Context, blob: &Blob) -> (bool, bool) {
+        let hash = blob.id.to_string();
+        let blobs = context
+            .services
+            .mono_storage
+            .get_mega_blobs_by_hashes(vec![hash.clone()])
+            .await
+            .unwrap();
+        let raw_blob = context
+            .services
+            .raw_db_storage
+            .get_raw_blob_by_hash(&hash)
+            .await
+            .unwrap();
+        (!blobs.is_empty(), raw_blob.is_some())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_mr_commits_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = Context::sqlite(dir.path()).await;
+        let (merged_from, merged_from_blob) = store_commit(&context, "merged from\n").await;
+        let (merged_to, merged_to_blob) = store_commit(&context, "merged to\n").await;
+        let (closed, closed_blob) = store_commit(&context, "closed\n").await;
+        let (_, garbage_blob) = store_commit(&context, "garbage\n").await;
+        save_mr(&context, MergeStatus::Merged, &merged_from, &merged_to).await;
+        save_mr(&context, MergeStatus::Closed, &merged_from, &closed).await;
+
+        // an avatar is a raw blob of its own
+        let avatar = Blob::from_content("avatar");
+        context
+            .services
+            .raw_db_storage
+            .save_raw_blob(avatar.clone().into())
+            .await
+            .unwrap();
+        context
+            .user_stg()
+            .save_user(user::Model {
+                id: generate_id(),
+                name: "alice".to_owned(),
+                email: "alice@example.com".to_owned(),
+                avatar_url: format!("/api/v1/user/avatar/{}", avatar.id),
+                is_github: false,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+            })
+            .await
+            .unwrap();
+
+        let mut report = GcReport::default();
+        let before = Utc::now().naive_utc() + Duration::minutes(1);
+        collect(&context, before, &mut report).await.unwrap();
+        for blob in [&merged_from_blob, &merged_to_blob, &closed_blob] {
+            assert_eq!(is_stored(&context, blob).await, (true, true));
+        }
+        assert_eq!(is_stored(&context, &garbage_blob).await, (false, false));
+        assert!(is_stored(&context, &avatar).await.1);
+        assert_eq!(report.removed_commits, 1);
+        assert_eq!(report.removed_trees, 1);
+        assert_eq!((report.removed_blobs, report.removed_raw_blobs), (1, 1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_grace_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = Context::sqlite(dir.path()).await;
+        let (_, blob) = store_commit(&context, "unreachable\n").await;
+
+        // stored within the grace period
+        let mut report = GcReport::default();
+        let before = Utc::now().naive_utc() - Duration::hours(1);
+        collect(&context, before, &mut report).await.unwrap();
+        assert_eq!(report.removed_commits, 0);
+        assert_eq!(is_stored(&context, &blob).await, (true, true));
+
+        let before = Utc::now().naive_utc() + Duration::minutes(1);
+        collect(&context, before, &mut report).await.unwrap();
+        assert_eq!(report.removed_commits, 1);
+        assert_eq!(is_stored(&context, &blob).await, (false, false));
+    }
+}
@@ -1,13 +1,20 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+
+use common::errors::MegaError;
+
 use crate::event::{EventBase, EventType};
 use crate::queue::get_mq;
+use crate::scheduler::Priority;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GithubWebhookEvent {
     pub _type: WebhookType,
     pub payload: Value,
+    /// `X-GitHub-Delivery` id, the same when GitHub redelivers the webhook
+    #[serde(default)]
+    pub delivery: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -35,18 +42,30 @@ impl std::fmt::Display for GithubWebhookEvent {
 
 #[async_trait]
 impl EventBase for GithubWebhookEvent {
-    async fn process(&self) {
+    async fn process(&self) -> Result<(), MegaError> {
         tracing::info!("Processing: [{}]", &self);
         tracing::info!("Payload: {:#?}", &self.payload);
+        Ok(())
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::High
+    }
+
+    fn idempotency_key(&self) -> Option<String> {
+        self.delivery
+            .as_ref()
+            .map(|delivery| format!("github-delivery:{delivery}"))
     }
 }
 
 impl GithubWebhookEvent {
     // Create and enqueue this event.
-    pub fn notify(_type: WebhookType, payload: Value) {
-        get_mq().send(EventType::GithubWebhook(GithubWebhookEvent {
+    pub fn notify(_type: WebhookType, payload: Value, delivery: Option<String>) {
+        let _ = get_mq().send(EventType::GithubWebhook(GithubWebhookEvent {
             _type,
             payload,
+            delivery,
         }));
     }
 }
@@ -0,0 +1,158 @@
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use common::errors::MegaError;
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+use crate::scheduler::Priority;
+
+// Slow subscribers will skip events once they lag behind this many messages.
+const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+fn live_channel() -> &'static broadcast::Sender<LiveUpdateEvent> {
+    static CHANNEL: OnceLock<broadcast::Sender<LiveUpdateEvent>> = OnceLock::new();
+    CHANNEL.get_or_init(|| broadcast::channel(LIVE_CHANNEL_CAPACITY).0)
+}
+
+/// # Live Update Event
+///
+/// Something visible to web clients changed under a monorepo path,
+/// e.g. a new MR comment, an MR status change, a ref advanced or a check
+/// run reported on the head of an MR.
+/// After going through the message queue, the event is broadcasted to
+/// every subscriber, which is how the SSE endpoint gets fed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveUpdateEvent {
+    pub kind: LiveUpdateKind,
+    /// monorepo path the event happened under
+    pub path: String,
+    /// set when the event belongs to a merge request
+    pub mr_link: Option<String>,
+    /// extra data for clients, such as the new commit hash or the MR status
+    pub payload: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveUpdateKind {
+    Comment,
+    StatusChange,
+    RefUpdate,
+    CheckRun,
+}
+
+impl std::fmt::Display for LiveUpdateEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Live Update Event: {:?} on {}", self.kind, self.path)
+    }
+}
+
+#[async_trait]
+impl EventBase for LiveUpdateEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        // Err only means nobody is listening right now.
+        let _ = live_channel().send(self.clone());
+        Ok(())
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::High
+    }
+}
+
+impl LiveUpdateEvent {
+    // Create and enqueue this event.
+    pub fn notify(kind: LiveUpdateKind, path: &str, mr_link: Option<&str>, payload: Value) {
+        let _ = get_mq().send(EventType::LiveUpdate(LiveUpdateEvent {
+            kind,
+            path: path.to_owned(),
+            mr_link: mr_link.map(|x| x.to_owned()),
+            payload,
+        }));
+    }
+
+    /// Receive every live update event processed from now on.
+    pub fn subscribe() -> broadcast::Receiver<LiveUpdateEvent> {
+        live_channel().subscribe()
+    }
+
+    /// Whether a subscriber watching `path` and/or `mr_link` is interested in this event.
+    /// Events under a sub directory of `path` also match.
+    pub fn matches(&self, path: Option<&str>, mr_link: Option<&str>) -> bool {
+        if let Some(link) = mr_link {
+            if self.mr_link.as_deref() != Some(link) {
+                return false;
+            }
+        }
+        if let Some(path) = path {
+            let path = path.trim_end_matches('/');
+            if !(path.is_empty()
+                || self.path == path
+                || self
+                    .path
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('/')))
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// For storing the data into database.
+impl From<LiveUpdateEvent> for Value {
+    fn from(value: LiveUpdateEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for LiveUpdateEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: LiveUpdateEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{LiveUpdateEvent, LiveUpdateKind};
+
+    fn event(path: &str, mr_link: Option<&str>) -> LiveUpdateEvent {
+        LiveUpdateEvent {
+            kind: LiveUpdateKind::Comment,
+            path: path.to_owned(),
+            mr_link: mr_link.map(|x| x.to_owned()),
+            payload: Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_matches_path() {
+        let evt = event("/project/foo", None);
+        assert!(evt.matches(None, None));
+        assert!(evt.matches(Some("/"), None));
+        assert!(evt.matches(Some("/project"), None));
+        assert!(evt.matches(Some("/project/foo/"), None));
+        assert!(!evt.matches(Some("/project/fo"), None));
+        assert!(!evt.matches(Some("/project/foo/bar"), None));
+    }
+
+    #[test]
+    fn test_matches_mr() {
+        let evt = event("/project/foo", Some("ABCD1234"));
+        assert!(evt.matches(None, Some("ABCD1234")));
+        assert!(evt.matches(Some("/project"), Some("ABCD1234")));
+        assert!(!evt.matches(None, Some("OTHER")));
+        assert!(!event("/project/foo", None).matches(None, Some("ABCD1234")));
+    }
+}
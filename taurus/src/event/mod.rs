@@ -1,22 +1,60 @@
 use std::fmt::Display;
 
+use access_log::AccessLogEvent;
 use api_request::ApiRequestEvent;
+use audit::AuditEvent;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
+use common::errors::MegaError;
+use dir_stats::DirStatsEvent;
 use github_webhook::GithubWebhookEvent;
+use gc::GcEvent;
+use live_update::LiveUpdateEvent;
+use notification::NotificationEvent;
+use pack_cache::PackCacheEvent;
+use push::PushEvent;
+use search_index::SearchIndexEvent;
+use topic::TopicEvent;
+use traffic::TrafficEvent;
 
+use crate::retry::RetryPolicy;
+use crate::scheduler::Priority;
+use crate::topic::Permit;
+
+pub mod access_log;
 pub mod api_request;
+pub mod audit;
+pub mod dir_stats;
+pub mod gc;
 pub mod github_webhook;
+pub mod live_update;
+pub mod notification;
+pub mod pack_cache;
+pub mod push;
+pub mod search_index;
+pub mod topic;
+pub mod traffic;
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventType {
     ApiRequest(ApiRequestEvent),
     GithubWebhook(GithubWebhookEvent),
+    LiveUpdate(LiveUpdateEvent),
+    AccessLog(AccessLogEvent),
+    Traffic(TrafficEvent),
+    SearchIndex(SearchIndexEvent),
+    DirStats(DirStatsEvent),
+    Push(PushEvent),
+    PackCache(PackCacheEvent),
+    Gc(GcEvent),
+    Topic(TopicEvent),
+    Audit(AuditEvent),
+    Notification(NotificationEvent),
 
     // Reserved
     ErrorEvent,
@@ -27,6 +65,13 @@ pub struct Message {
     pub(crate) id: i64,
     pub(crate) create_time: DateTime<Utc>,
     pub(crate) evt: EventType,
+    pub(crate) priority: Priority,
+    // Not processed before then when set.
+    pub(crate) deliver_at: Option<DateTime<Utc>>,
+    // Counted from 1, not stored so a replayed message starts over.
+    pub(crate) attempt: u32,
+    // Only one message with the key is processed within the idempotency ttl.
+    pub(crate) key: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -40,7 +85,23 @@ pub trait EventBase:
     Send + Sync + std::fmt::Display + Into<serde_json::Value> + TryFrom<serde_json::Value>
 {
     // defines the callback function for this event.
-    async fn process(&self);
+    // An error has the queue try again as the retry policy says.
+    async fn process(&self) -> Result<(), MegaError>;
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    // Sent with this priority unless the sender picks one.
+    fn priority(&self) -> Priority {
+        Priority::Normal
+    }
+
+    // Identifies the logical event, a duplicate of it, e.g. a redelivered webhook,
+    // is skipped.
+    fn idempotency_key(&self) -> Option<String> {
+        None
+    }
 }
 
 impl Display for EventType {
@@ -50,7 +111,7 @@ impl Display for EventType {
 }
 
 impl EventType {
-    pub(crate) async fn process(&self) {
+    pub(crate) async fn process(&self) -> Result<(), MegaError> {
         match self {
             // I can't easily add a trait bound for the enum members,
             // so you have to manually add a process logic for your event here.
@@ -58,6 +119,17 @@ impl EventType {
             // EventType::SomeOtherEvent(xxx) => xxx.process().await,
 
             EventType::GithubWebhook(evt) => evt.process().await,
+            EventType::LiveUpdate(evt) => evt.process().await,
+            EventType::AccessLog(evt) => evt.process().await,
+            EventType::Traffic(evt) => evt.process().await,
+            EventType::SearchIndex(evt) => evt.process().await,
+            EventType::DirStats(evt) => evt.process().await,
+            EventType::Push(evt) => evt.process().await,
+            EventType::PackCache(evt) => evt.process().await,
+            EventType::Gc(evt) => evt.process().await,
+            EventType::Topic(evt) => evt.process().await,
+            EventType::Audit(evt) => evt.process().await,
+            EventType::Notification(evt) => evt.process().await,
 
             // This won't happen unless failed to load events from database.
             // And that's because of a event conversion error.
@@ -65,6 +137,92 @@ impl EventType {
             EventType::ErrorEvent => panic!("Got error event"),
         }
     }
+
+    pub(crate) fn retry_policy(&self) -> RetryPolicy {
+        match self {
+            EventType::ApiRequest(evt) => evt.retry_policy(),
+            EventType::GithubWebhook(evt) => evt.retry_policy(),
+            EventType::LiveUpdate(evt) => evt.retry_policy(),
+            EventType::AccessLog(evt) => evt.retry_policy(),
+            EventType::Traffic(evt) => evt.retry_policy(),
+            EventType::SearchIndex(evt) => evt.retry_policy(),
+            EventType::DirStats(evt) => evt.retry_policy(),
+            EventType::Push(evt) => evt.retry_policy(),
+            EventType::PackCache(evt) => evt.retry_policy(),
+            EventType::Gc(evt) => evt.retry_policy(),
+            EventType::Topic(evt) => evt.retry_policy(),
+            EventType::Audit(evt) => evt.retry_policy(),
+            EventType::Notification(evt) => evt.retry_policy(),
+            EventType::ErrorEvent => RetryPolicy::none(),
+        }
+    }
+
+    pub(crate) fn priority(&self) -> Priority {
+        match self {
+            EventType::ApiRequest(evt) => evt.priority(),
+            EventType::GithubWebhook(evt) => evt.priority(),
+            EventType::LiveUpdate(evt) => evt.priority(),
+            EventType::AccessLog(evt) => evt.priority(),
+            EventType::Traffic(evt) => evt.priority(),
+            EventType::SearchIndex(evt) => evt.priority(),
+            EventType::DirStats(evt) => evt.priority(),
+            EventType::Push(evt) => evt.priority(),
+            EventType::PackCache(evt) => evt.priority(),
+            EventType::Gc(evt) => evt.priority(),
+            EventType::Topic(evt) => evt.priority(),
+            EventType::Audit(evt) => evt.priority(),
+            EventType::Notification(evt) => evt.priority(),
+            EventType::ErrorEvent => Priority::Normal,
+        }
+    }
+
+    pub(crate) fn idempotency_key(&self) -> Option<String> {
+        match self {
+            EventType::ApiRequest(evt) => evt.idempotency_key(),
+            EventType::GithubWebhook(evt) => evt.idempotency_key(),
+            EventType::LiveUpdate(evt) => evt.idempotency_key(),
+            EventType::AccessLog(evt) => evt.idempotency_key(),
+            EventType::Traffic(evt) => evt.idempotency_key(),
+            EventType::SearchIndex(evt) => evt.idempotency_key(),
+            EventType::DirStats(evt) => evt.idempotency_key(),
+            EventType::Push(evt) => evt.idempotency_key(),
+            EventType::PackCache(evt) => evt.idempotency_key(),
+            EventType::Gc(evt) => evt.idempotency_key(),
+            EventType::Topic(evt) => evt.idempotency_key(),
+            EventType::Audit(evt) => evt.idempotency_key(),
+            EventType::Notification(evt) => evt.idempotency_key(),
+            EventType::ErrorEvent => None,
+        }
+    }
+
+    // Kind of the event, it's stored with this category.
+    pub(crate) fn category(&self) -> &'static str {
+        match self {
+            EventType::ApiRequest(_) => "ApiRequestEvent",
+            EventType::GithubWebhook(_) => "GithubWebhookEvent",
+            EventType::LiveUpdate(_) => "LiveUpdateEvent",
+            EventType::AccessLog(_) => "AccessLogEvent",
+            EventType::Traffic(_) => "TrafficEvent",
+            EventType::SearchIndex(_) => "SearchIndexEvent",
+            EventType::DirStats(_) => "DirStatsEvent",
+            EventType::Push(_) => "PushEvent",
+            EventType::PackCache(_) => "PackCacheEvent",
+            EventType::Gc(_) => "GcEvent",
+            EventType::Topic(_) => "TopicEvent",
+            EventType::Audit(_) => "AuditEvent",
+            EventType::Notification(_) => "NotificationEvent",
+            EventType::ErrorEvent => "Unknown",
+        }
+    }
+
+    // A permit to process the event now, `None` while as many as its kind allows are
+    // processed already.
+    pub(crate) fn permit(&self) -> Option<Permit> {
+        match self {
+            EventType::Topic(evt) => crate::topic::permit(evt),
+            _ => Some(Permit::unlimited()),
+        }
+    }
 }
 
 impl Display for Message {
@@ -81,15 +239,22 @@ impl From<Message> for callisto::mq_storage::Model {
     fn from(val: Message) -> Self {
         use callisto::mq_storage::Model;
 
-        let category = match val.evt {
-            EventType::ApiRequest(_) => Some(String::from("ApiRequestEvent")),
-
-            #[allow(unreachable_patterns)]
-            _ => Some(String::from("Unknown")),
-        };
+        let category = Some(String::from(val.evt.category()));
 
         let content: Value = match val.evt {
             EventType::ApiRequest(evt) => evt.into(),
+            EventType::GithubWebhook(evt) => evt.into(),
+            EventType::LiveUpdate(evt) => evt.into(),
+            EventType::AccessLog(evt) => evt.into(),
+            EventType::Traffic(evt) => evt.into(),
+            EventType::SearchIndex(evt) => evt.into(),
+            EventType::DirStats(evt) => evt.into(),
+            EventType::Push(evt) => evt.into(),
+            EventType::PackCache(evt) => evt.into(),
+            EventType::Gc(evt) => evt.into(),
+            EventType::Topic(evt) => evt.into(),
+            EventType::Audit(evt) => evt.into(),
+            EventType::Notification(evt) => evt.into(),
 
             #[allow(unreachable_patterns)]
             _ => Value::Null,
@@ -100,27 +265,139 @@ impl From<Message> for callisto::mq_storage::Model {
             category,
             create_time: val.create_time.naive_utc(),
             content: Some(content.to_string()),
+            done: false,
+            priority: val.priority.into(),
+            deliver_at: val.deliver_at.map(|x| x.naive_utc()),
+            idempotency_key: val.key,
         }
     }
 }
 
-impl From<callisto::mq_storage::Model> for Message {
-    fn from(value: callisto::mq_storage::Model) -> Self {
+// A stored message whose content doesn't parse any more, e.g. after its event changed,
+// is an error, one of an unknown kind or without content is an `ErrorEvent`.
+impl TryFrom<callisto::mq_storage::Model> for Message {
+    type Error = Error;
+
+    fn try_from(value: callisto::mq_storage::Model) -> Result<Self, Self::Error> {
         let id = value.id;
         let create_time = value.create_time.and_utc();
-        let evt = match value.category.unwrap().as_str() {
+        let evt = match value.category.as_deref().unwrap_or_default() {
             "ApiRequestEvent" => {
                 if let Some(s) = value.content {
-                    let evt = serde_json::from_str(&s).unwrap();
+                    let evt = serde_json::from_str(&s)?;
                     EventType::ApiRequest(evt)
                 } else {
                     EventType::ErrorEvent
                 }
             },
+            "GithubWebhookEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::GithubWebhook(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "LiveUpdateEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::LiveUpdate(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "AccessLogEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::AccessLog(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "TrafficEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::Traffic(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "SearchIndexEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::SearchIndex(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "DirStatsEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::DirStats(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "PushEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::Push(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "PackCacheEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::PackCache(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "GcEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::Gc(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "TopicEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::Topic(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "AuditEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::Audit(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
+            "NotificationEvent" => {
+                if let Some(s) = value.content {
+                    let evt = serde_json::from_str(&s)?;
+                    EventType::Notification(evt)
+                } else {
+                    EventType::ErrorEvent
+                }
+            },
 
             _ => EventType::ErrorEvent
         };
 
-        Self { id, create_time, evt }
+        Ok(Self {
+            id,
+            create_time,
+            evt,
+            priority: value.priority.into(),
+            deliver_at: value.deliver_at.map(|x| x.and_utc()),
+            attempt: 1,
+            key: value.idempotency_key,
+        })
     }
 }
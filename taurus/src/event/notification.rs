@@ -0,0 +1,144 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use callisto::db_enums::NotificationKind;
+use callisto::mega_notification;
+use common::errors::MegaError;
+use common::utils::generate_id;
+
+use crate::event::{EventBase, EventType};
+use crate::mail::send_mail;
+use crate::queue::get_mq;
+
+/// # Notification Event
+///
+/// Something a user should know about, e.g. being mentioned in a comment or their
+/// merge request being merged. Processing the event stores a notification for every
+/// recipient, and emails it to them when email is enabled in the `notification`
+/// config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationEvent {
+    pub kind: NotificationKind,
+    /// user name of who caused it, never notified themselves
+    pub actor: String,
+    /// user names, the ones without an account are skipped
+    pub recipients: Vec<String>,
+    /// page of the UI it's about, as `/mr/{link}`
+    pub target: String,
+    pub title: String,
+    /// the comment of a mention
+    pub body: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+impl std::fmt::Display for NotificationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Notification Event: {} {} {:?}",
+            self.kind, self.target, self.recipients
+        )
+    }
+}
+
+#[async_trait]
+impl EventBase for NotificationEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        let context = &get_mq().context;
+        let config = &context.config.notification;
+        let mut recipients: Vec<&String> = self
+            .recipients
+            .iter()
+            .filter(|x| **x != self.actor)
+            .collect();
+        recipients.sort();
+        recipients.dedup();
+        for name in recipients {
+            let Some(user) = context.user_stg().find_user_by_name(name).await? else {
+                continue;
+            };
+            context
+                .notification_stg()
+                .save(mega_notification::Model {
+                    id: generate_id(),
+                    user_id: user.id,
+                    kind: self.kind,
+                    actor: self.actor.clone(),
+                    target: self.target.clone(),
+                    title: self.title.clone(),
+                    body: self.body.clone(),
+                    read: false,
+                    created_at: self.time.naive_utc(),
+                })
+                .await?;
+            // the notification is in the UI already, a failed email isn't sent again
+            if config.email && !user.email.is_empty() {
+                let subject = format!("[Mega] {}", self.title);
+                let body = format!("{}\n\n{}{}\n", self.summary(), config.ui_url, self.target);
+                if let Err(e) = send_mail(config, &user.email, &subject, body).await {
+                    tracing::warn!("notification of {} to {}: {}", self.target, name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NotificationEvent {
+    // Create and enqueue this event.
+    pub fn notify(
+        kind: NotificationKind,
+        actor: &str,
+        recipients: Vec<String>,
+        target: &str,
+        title: &str,
+        body: Option<String>,
+    ) {
+        if recipients.is_empty() {
+            return;
+        }
+        let _ = get_mq().send(EventType::Notification(NotificationEvent {
+            kind,
+            actor: actor.to_owned(),
+            recipients,
+            target: target.to_owned(),
+            title: title.to_owned(),
+            body,
+            time: Utc::now(),
+        }));
+    }
+
+    fn summary(&self) -> String {
+        match self.kind {
+            NotificationKind::Mention => format!(
+                "{} mentioned you in {}:\n\n{}",
+                self.actor,
+                self.title,
+                self.body.as_deref().unwrap_or_default()
+            ),
+            NotificationKind::ReviewRequested => {
+                format!("{} asked you to review {}", self.actor, self.title)
+            }
+            NotificationKind::MrMerged => format!("{} merged {}", self.actor, self.title),
+            NotificationKind::MrClosed => format!("{} closed {}", self.actor, self.title),
+        }
+    }
+}
+
+// For storing the data into database.
+impl From<NotificationEvent> for Value {
+    fn from(value: NotificationEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for NotificationEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: NotificationEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use common::errors::MegaError;
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+use crate::scheduler::Priority;
+
+// Merges coming faster than the warmer keeps up with skip the older ones.
+const WARM_CHANNEL_CAPACITY: usize = 64;
+
+fn warm_channel() -> &'static broadcast::Sender<PackCacheEvent> {
+    static CHANNEL: OnceLock<broadcast::Sender<PackCacheEvent>> = OnceLock::new();
+    CHANNEL.get_or_init(|| broadcast::channel(WARM_CHANNEL_CAPACITY).0)
+}
+
+/// # Pack Cache Event
+///
+/// A merge moved the head of a monorepo path, the packs cached for clones of it and
+/// the paths around it are out of date. Processing hands the event to the pack cache
+/// warmer, which builds the pack of the new head before the next clone asks for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackCacheEvent {
+    /// monorepo path the merge went to
+    pub path: String,
+    /// commit the root ref was moved to
+    pub commit_id: String,
+}
+
+impl std::fmt::Display for PackCacheEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pack Cache Event: {} at {}", self.path, self.commit_id)
+    }
+}
+
+#[async_trait]
+impl EventBase for PackCacheEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        // Err only means no warmer is running.
+        let _ = warm_channel().send(self.clone());
+        Ok(())
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::High
+    }
+}
+
+impl PackCacheEvent {
+    // Create and enqueue this event.
+    pub fn notify(path: &str, commit_id: &str) {
+        let _ = get_mq().send(EventType::PackCache(PackCacheEvent {
+            path: path.to_owned(),
+            commit_id: commit_id.to_owned(),
+        }));
+    }
+
+    /// Receive every pack cache event processed from now on.
+    pub fn subscribe() -> broadcast::Receiver<PackCacheEvent> {
+        warm_channel().subscribe()
+    }
+}
+
+// For storing the data into database.
+impl From<PackCacheEvent> for Value {
+    fn from(value: PackCacheEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for PackCacheEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: PackCacheEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
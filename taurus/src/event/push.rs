@@ -0,0 +1,120 @@
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use common::config::HookStage;
+use common::errors::MegaError;
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+
+/// # Push Event
+///
+/// A ref of a monorepo directory was updated by a push. Processing the event
+/// runs the post-receive hooks configured for the directory, the same update
+/// is given to pre-receive hooks before the ref moves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushEvent {
+    /// monorepo directory the push went to
+    pub path: String,
+    pub ref_name: String,
+    pub old_id: String,
+    pub new_id: String,
+    /// hashes of the commits the update adds, newest first
+    pub commits: Vec<String>,
+}
+
+impl std::fmt::Display for PushEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Push Event: {} of {} {} -> {}",
+            self.ref_name, self.path, self.old_id, self.new_id
+        )
+    }
+}
+
+#[async_trait]
+impl EventBase for PushEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        let config = &get_mq().context.config.monorepo;
+        for hook in config.hooks(HookStage::PostReceive, &self.path) {
+            if hook.command.is_empty() {
+                continue;
+            }
+            if let Err(err) = run_hook(&hook.command, self).await {
+                tracing::error!(
+                    "post-receive hook {:?} failed on {}: {}",
+                    hook.command,
+                    self.path,
+                    err
+                );
+            }
+        }
+        // hooks may not be safe to run twice, a failing one isn't retried
+        Ok(())
+    }
+}
+
+impl PushEvent {
+    // Create and enqueue this event, waits while the queue is full so a burst of
+    // pushes slows down instead of losing hooks.
+    pub async fn notify(event: PushEvent) {
+        let _ = get_mq().send_wait(EventType::Push(event)).await;
+    }
+}
+
+/// Run a hook command with `event` as JSON on its stdin. A hook fails when it can't be
+/// started or exits non-zero, the error is its output, or its exit status if it printed
+/// nothing.
+pub async fn run_hook(command: &[String], event: &PushEvent) -> Result<(), String> {
+    let (program, args) = command.split_first().ok_or("empty hook command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = serde_json::to_vec(event).unwrap();
+        // a hook not reading its input still decides by its exit status
+        let _ = stdin.write_all(&input).await;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+    if message.is_empty() {
+        message = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    }
+    if message.is_empty() {
+        message = format!("{} exited with {}", program, output.status);
+    }
+    Err(message)
+}
+
+// For storing the data into database.
+impl From<PushEvent> for Value {
+    fn from(value: PushEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for PushEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: PushEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
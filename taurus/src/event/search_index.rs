@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use common::errors::MegaError;
+use jupiter::context::Context;
+use jupiter::storage::mono_storage::MonoStorage;
+use jupiter::storage::search_storage::trigrams;
+use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+use crate::scheduler::Priority;
+
+/// Larger files are left out of the search index.
+pub const MAX_INDEXED_FILE_SIZE: usize = 1024 * 1024;
+/// Blobs loaded per query while indexing.
+const INDEX_BLOB_BATCH: usize = 100;
+
+/// Events are processed concurrently, updates of the index must not interleave.
+static INDEX_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// # Search Index Event
+///
+/// The root ref of the monorepo moved. Processing brings the code search index up
+/// to date with the ref, only the paths changed since the indexed tree are read again,
+/// so a missed event is caught up by the next one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndexEvent {
+    /// commit the ref was moved to
+    pub commit_id: String,
+}
+
+impl std::fmt::Display for SearchIndexEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Search Index Event: {}", self.commit_id)
+    }
+}
+
+#[async_trait]
+impl EventBase for SearchIndexEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        let _guard = INDEX_LOCK.lock().await;
+        sync_index(&get_mq().context).await
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+}
+
+impl SearchIndexEvent {
+    // Create and enqueue this event.
+    pub fn notify(commit_id: &str) {
+        let _ = get_mq().send(EventType::SearchIndex(SearchIndexEvent {
+            commit_id: commit_id.to_owned(),
+        }));
+    }
+}
+
+async fn sync_index(context: &Context) -> Result<(), MegaError> {
+    let mono_storage = context.services.mono_storage.clone();
+    let raw_storage = context.services.raw_db_storage.clone();
+    let search_storage = context.search_stg();
+    let Some(root) = mono_storage.get_ref("/").await? else {
+        return Ok(());
+    };
+    let indexed = search_storage.get_state().await?.map(|x| x.tree_id);
+    if indexed.as_deref() == Some(root.ref_tree_hash.as_str()) {
+        return Ok(());
+    }
+
+    let (updated, removed) = diff_files(&mono_storage, indexed, root.ref_tree_hash.clone()).await?;
+    for path in removed {
+        search_storage.remove_path(&path).await?;
+    }
+    for batch in updated.chunks(INDEX_BLOB_BATCH) {
+        let hashes = batch.iter().map(|(_, id)| id.clone()).collect();
+        let blobs: HashMap<String, Vec<u8>> = raw_storage
+            .get_raw_blobs_by_hashes(hashes)
+            .await?
+            .into_iter()
+            .map(|x| (x.sha1, x.data.unwrap_or_default()))
+            .collect();
+        for (path, id) in batch {
+            let text = blobs
+                .get(id)
+                .filter(|x| x.len() <= MAX_INDEXED_FILE_SIZE && !x.contains(&0))
+                .and_then(|x| std::str::from_utf8(x).ok());
+            match text {
+                Some(text) => search_storage.index_file(path, id, trigrams(text)).await?,
+                // binary or too large, a text version of it may have been indexed
+                None => search_storage.remove_path(path).await?,
+            }
+        }
+    }
+    search_storage
+        .set_state(&root.ref_commit_hash, &root.ref_tree_hash)
+        .await
+}
+
+/// Files to index as `(path, blob id)` and paths to drop, going from the tree `old`
+/// to `new`. Subtrees with the same id on both sides are skipped.
+async fn diff_files(
+    storage: &MonoStorage,
+    old: Option<String>,
+    new: String,
+) -> Result<(Vec<(String, String)>, Vec<String>), MegaError> {
+    let mut updated = Vec::new();
+    let mut removed = Vec::new();
+    let mut pending = vec![(String::new(), old, new)];
+    while let Some((dir, old, new)) = pending.pop() {
+        let mut old_items: HashMap<String, TreeItem> = match old {
+            Some(old) => load_items(storage, &old)
+                .await?
+                .into_iter()
+                .map(|x| (x.name.clone(), x))
+                .collect(),
+            None => HashMap::new(),
+        };
+        for item in load_items(storage, &new).await? {
+            let path = format!("{}/{}", dir, item.name);
+            let old_item = old_items.remove(&item.name);
+            if old_item
+                .as_ref()
+                .is_some_and(|x| x.id == item.id && x.mode == item.mode)
+            {
+                continue;
+            }
+            let old_tree = old_item
+                .as_ref()
+                .filter(|x| x.mode == TreeItemMode::Tree)
+                .map(|x| x.id.to_string());
+            match item.mode {
+                TreeItemMode::Tree => {
+                    if old_item.is_some() && old_tree.is_none() {
+                        removed.push(path.clone());
+                    }
+                    pending.push((path, old_tree, item.id.to_string()));
+                }
+                TreeItemMode::Blob | TreeItemMode::BlobExecutable => {
+                    if old_tree.is_some() {
+                        removed.push(path.clone());
+                    }
+                    updated.push((path, item.id.to_string()));
+                }
+                // links and submodules aren't indexed
+                _ => {
+                    if old_item.is_some() {
+                        removed.push(path);
+                    }
+                }
+            }
+        }
+        removed.extend(
+            old_items
+                .into_keys()
+                .map(|name| format!("{}/{}", dir, name)),
+        );
+    }
+    Ok((updated, removed))
+}
+
+async fn load_items(storage: &MonoStorage, id: &str) -> Result<Vec<TreeItem>, MegaError> {
+    match storage.get_tree_by_hash(id).await? {
+        Some(model) => Ok(Tree::from(model).tree_items),
+        None => Err(MegaError::with_message(&format!("tree {} not found", id))),
+    }
+}
+
+// For storing the data into database.
+impl From<SearchIndexEvent> for Value {
+    fn from(value: SearchIndexEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for SearchIndexEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: SearchIndexEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
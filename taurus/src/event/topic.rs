@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use common::errors::MegaError;
+
+use crate::event::EventBase;
+use crate::topic;
+
+/// # Topic Event
+///
+/// An event published to a topic, queued once for each handler subscribed to it with
+/// [`topic::subscribe`]. Processing calls that handler.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicEvent {
+    pub topic: String,
+    /// name the handler subscribed with
+    pub handler: String,
+    pub payload: Value,
+}
+
+impl std::fmt::Display for TopicEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Topic Event: {} for {}", self.topic, self.handler)
+    }
+}
+
+#[async_trait]
+impl EventBase for TopicEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        topic::handle(self).await
+    }
+}
+
+// For storing the data into database.
+impl From<TopicEvent> for Value {
+    fn from(value: TopicEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for TopicEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: TopicEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use callisto::db_enums::TrafficKind;
+use common::errors::MegaError;
+
+use crate::event::{EventBase, EventType};
+use crate::queue::get_mq;
+use crate::scheduler::Priority;
+
+/// # Traffic Event
+///
+/// A file was downloaded or a path was cloned/fetched. Processing the event
+/// adds it to the daily counters of the path, so the request itself never
+/// waits for the database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficEvent {
+    pub kind: TrafficKind,
+    /// monorepo path of the file or the cloned directory
+    pub path: String,
+    /// unix timestamp of the hit, decides the day it's counted for
+    pub timestamp: i64,
+}
+
+impl std::fmt::Display for TrafficEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Traffic Event: {} on {}", self.kind, self.path)
+    }
+}
+
+#[async_trait]
+impl EventBase for TrafficEvent {
+    async fn process(&self) -> Result<(), MegaError> {
+        let storage = get_mq().context.traffic_stg();
+        let day = DateTime::from_timestamp(self.timestamp, 0)
+            .unwrap_or_else(Utc::now)
+            .date_naive();
+        storage.record(&self.path, self.kind, day).await
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+}
+
+impl TrafficEvent {
+    // Create and enqueue this event.
+    pub fn notify(kind: TrafficKind, path: &str) {
+        let _ = get_mq().send(EventType::Traffic(TrafficEvent {
+            kind,
+            path: path.to_owned(),
+            timestamp: Utc::now().timestamp(),
+        }));
+    }
+}
+
+// For storing the data into database.
+impl From<TrafficEvent> for Value {
+    fn from(value: TrafficEvent) -> Self {
+        serde_json::to_value(value).unwrap()
+    }
+}
+
+impl TryFrom<Value> for TrafficEvent {
+    type Error = crate::event::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let res: TrafficEvent = serde_json::from_value(value)?;
+        Ok(res)
+    }
+}
@@ -1,16 +1,21 @@
 use common::config::Config;
 use jupiter::context::Context;
-use crate::queue::{MessageQueue, MQ};
+use crate::broker;
+use crate::event::gc::GcEvent;
+use crate::job;
+use crate::queue::{get_mq, MessageQueue, MQ};
 
 pub async fn init_mq(config: &Config) {
     let ctx = Context::new(config.clone()).await;
-    let seq = match ctx.services.mq_storage.get_latest_message().await {
-        Some(model) => model.id + 1,
-        None => 1,
-    };
+    let broker = broker::connect(&config.queue)
+        .await
+        .expect("Failed to connect to the queue broker");
 
-    let mq = MessageQueue::new(seq, ctx);
+    let mq = MessageQueue::new(ctx, broker);
     mq.start();
 
     MQ.set(mq).unwrap();
+    get_mq().replay().await;
+    GcEvent::schedule(config.gc.interval);
+    job::start(&config.queue.jobs);
 }
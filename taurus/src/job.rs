@@ -0,0 +1,169 @@
+//! Jobs sending events on a cron schedule, configured under `[[queue.jobs]]`. Runs are
+//! recorded in the database, instances sharing it run a job once, and a run missed while
+//! no server was up is made up for on the next start.
+
+use std::sync::{Arc, OnceLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+
+use common::config::JobConfig;
+use common::errors::MegaError;
+use jupiter::storage::mq_storage::MQStorage;
+
+use crate::cron::CronSchedule;
+use crate::event::gc::GcEvent;
+use crate::queue::get_mq;
+use crate::topic;
+
+const BUILTIN_GC: &str = "gc";
+
+static JOBS: OnceLock<Vec<Arc<Job>>> = OnceLock::new();
+
+struct Job {
+    config: JobConfig,
+    schedule: CronSchedule,
+}
+
+/// A scheduled job, for the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub name: String,
+    pub schedule: String,
+    pub topic: Option<String>,
+    pub builtin: Option<String>,
+    /// unix time of the last run, scheduled or manual
+    pub last_run: Option<i64>,
+    pub next_run: Option<i64>,
+}
+
+// Start running the configured jobs, those configured wrong are logged and left out.
+pub(crate) fn start(jobs: &[JobConfig]) {
+    let jobs: Vec<Arc<Job>> = jobs
+        .iter()
+        .filter_map(|config| match Job::new(config) {
+            Ok(job) => Some(Arc::new(job)),
+            Err(e) => {
+                tracing::error!("Job {} left out: {e}", config.name);
+                None
+            }
+        })
+        .collect();
+    if JOBS.set(jobs).is_err() {
+        panic!("jobs started twice");
+    }
+    for job in jobs() {
+        tokio::spawn(job.clone().run_schedule());
+    }
+}
+
+/// The scheduled jobs with their last and next runs.
+pub async fn list() -> Result<Vec<JobInfo>, MegaError> {
+    let runs = storage().get_jobs().await?;
+    let now = Utc::now();
+    Ok(jobs()
+        .iter()
+        .map(|job| JobInfo {
+            name: job.config.name.clone(),
+            schedule: job.config.schedule.clone(),
+            topic: job.config.topic.clone(),
+            builtin: job.config.builtin.clone(),
+            last_run: runs
+                .iter()
+                .find(|x| x.name == job.config.name)
+                .and_then(|x| x.last_run)
+                .map(|x| x.and_utc().timestamp()),
+            next_run: job.schedule.next_after(now).map(|x| x.timestamp()),
+        })
+        .collect())
+}
+
+/// Run job `name` now, off its schedule. `false` when there's no such job.
+pub async fn trigger(name: &str) -> Result<bool, MegaError> {
+    let Some(job) = jobs().iter().find(|x| x.config.name == name) else {
+        return Ok(false);
+    };
+    let now = Utc::now();
+    storage().claim_job_run(name, now.naive_utc()).await?;
+    job.emit(now);
+    Ok(true)
+}
+
+fn jobs() -> &'static [Arc<Job>] {
+    JOBS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+fn storage() -> &'static MQStorage {
+    &get_mq().context.services.mq_storage
+}
+
+impl Job {
+    fn new(config: &JobConfig) -> Result<Self, String> {
+        let schedule = config
+            .schedule
+            .parse::<CronSchedule>()
+            .map_err(|e| e.to_string())?;
+        match (&config.topic, config.builtin.as_deref()) {
+            (Some(_), None) | (None, Some(BUILTIN_GC)) => {}
+            (None, Some(other)) => return Err(format!("no job {other} is built in")),
+            _ => return Err(String::from("set either topic or builtin")),
+        }
+        Ok(Job {
+            config: config.clone(),
+            schedule,
+        })
+    }
+
+    async fn run_schedule(self: Arc<Self>) {
+        let name = &self.config.name;
+        let last_run = match storage().get_jobs().await {
+            Ok(runs) => runs
+                .into_iter()
+                .find(|x| &x.name == name)
+                .and_then(|x| x.last_run),
+            Err(e) => {
+                tracing::error!("Failed to load the last run of job {name}: {e}");
+                None
+            }
+        };
+        let now = Utc::now();
+        // Only the latest of the runs missed is made up for.
+        if let Some(missed) =
+            last_run.and_then(|last| self.schedule.last_between(last.and_utc(), now))
+        {
+            tracing::info!("Job {name} missed its run at {missed}, running it now");
+            self.run_at(missed).await;
+        }
+        let mut after = now;
+        while let Some(next) = self.schedule.next_after(after) {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            self.run_at(next).await;
+            after = next;
+        }
+        tracing::warn!("Job {name} won't run again, its schedule has no time left");
+    }
+
+    // The scheduled run at `time`, left to another instance that ran it already.
+    async fn run_at(&self, time: DateTime<Utc>) {
+        let name = &self.config.name;
+        match storage().claim_job_run(name, time.naive_utc()).await {
+            Ok(true) => self.emit(time),
+            Ok(false) => tracing::debug!("Job {name} already ran at {time}"),
+            Err(e) => tracing::error!("Failed to record the run of job {name}: {e}"),
+        }
+    }
+
+    fn emit(&self, time: DateTime<Utc>) {
+        let name = &self.config.name;
+        if let Some(topic) = &self.config.topic {
+            let payload = json!({ "job": name, "run_at": time.timestamp() });
+            if let Err(e) = topic::publish(topic, payload) {
+                tracing::error!("Job {name} failed to publish to {topic}: {e}");
+            }
+        } else if self.config.builtin.as_deref() == Some(BUILTIN_GC) {
+            GcEvent::notify(&format!("job {name}"));
+        }
+    }
+}
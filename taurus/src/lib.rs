@@ -1,4 +1,10 @@
+pub mod broker;
+pub mod cron;
 pub mod init;
+pub mod job;
 pub mod event;
+pub mod mail;
 pub mod queue;
-pub mod cache;
+pub mod retry;
+pub mod scheduler;
+pub mod topic;
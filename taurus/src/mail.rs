@@ -0,0 +1,43 @@
+//! Emails sent through the SMTP server of the `notification` config.
+
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use common::config::NotificationConfig;
+use common::errors::MegaError;
+
+/// Send a plain text email to `to`, an address or `Name <address>`.
+pub async fn send_mail(
+    config: &NotificationConfig,
+    to: &str,
+    subject: &str,
+    body: String,
+) -> Result<(), MegaError> {
+    let email = Message::builder()
+        .from(config.from.parse().map_err(mail_error)?)
+        .to(to.parse().map_err(mail_error)?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .map_err(mail_error)?;
+    let mut transport = if config.smtp_starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)
+            .map_err(mail_error)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+    }
+    .port(config.smtp_port);
+    if !config.smtp_username.is_empty() {
+        transport = transport.credentials(Credentials::new(
+            config.smtp_username.clone(),
+            config.smtp_password.clone(),
+        ));
+    }
+    transport.build().send(email).await.map_err(mail_error)?;
+    Ok(())
+}
+
+fn mail_error(e: impl std::fmt::Display) -> MegaError {
+    MegaError::with_message(&format!("failed to send email: {}", e))
+}
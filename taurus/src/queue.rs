@@ -1,14 +1,31 @@
 use std::fmt::Debug;
-use std::sync::atomic::AtomicI64;
-use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use chrono::Utc;
-use crossbeam_channel::{unbounded, Sender};
-use crossbeam_channel::Receiver;
+use callisto::{mq_dead_letter, mq_storage};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use common::errors::MegaError;
+use common::utils::generate_id;
 use jupiter::context::Context;
+use jupiter::storage::mq_storage::MQStorage;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::sync::{Notify, Semaphore};
 
-use crate::cache::get_mcache;
+use crate::broker::Broker;
 use crate::event::{Message, EventType};
+use crate::scheduler::{Priority, Scheduler};
+
+// Messages processed at once, the others wait in the scheduler by priority.
+const WORKERS: usize = 16;
+// Delay of a message whose kind is processed as often at once as it may be.
+const BUSY_DELAY: Duration = Duration::from_millis(100);
+// How often the expired idempotency keys are dropped.
+const KEY_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+// How often the messages processed longer ago than the retention are dropped.
+const MESSAGE_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
 
 // Lazy initialized static MessageQueue instance.
 pub(crate) static MQ: OnceLock<MessageQueue> = OnceLock::new();
@@ -16,67 +33,510 @@ pub fn get_mq() -> &'static MessageQueue {
     MQ.get().unwrap()
 }
 
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("the message queue is full")]
+    Full,
+    #[error("the message queue is closed")]
+    Closed,
+}
+
+/// Messages the queue holds, for monitoring.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueDepth {
+    /// sent but not stored yet
+    pub incoming: usize,
+    /// stored and waiting for a worker
+    pub ready: usize,
+    /// waiting for their delivery time or their next attempt
+    pub delayed: usize,
+    pub in_flight: usize,
+    /// messages `incoming` and `ready` may hold before senders have to wait
+    pub capacity: usize,
+}
+
 pub struct MessageQueue {
     sender: Sender<Message>,
-    receiver: Receiver<Message>,
-    // sem: Arc<Semaphore>,
-    cur_id: Arc<AtomicI64>,
+    // Taken by the event loop on start.
+    receiver: Mutex<Option<Receiver<Message>>>,
+    capacity: usize,
+    broker: Arc<dyn Broker>,
+    scheduler: Arc<Scheduler>,
+    // Set by `shutdown`, messages are only stored from then on.
+    stopping: Arc<AtomicBool>,
+    in_flight: Arc<InFlight>,
     pub(crate) context: Context,
 }
 
+// Messages being processed, `idle` is notified when the last one is done.
+#[derive(Default)]
+struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+// Counts a message as in flight for as long as it lives, panics included.
+struct InFlightGuard(Arc<InFlight>);
+
+impl InFlightGuard {
+    fn new(in_flight: Arc<InFlight>) -> Self {
+        in_flight.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
 unsafe impl Send for MessageQueue{}
 unsafe impl Sync for MessageQueue{}
 
 impl Debug for MessageQueue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Just ignore context field.
-        f.debug_struct("MessageQueue").field("sender", &self.sender).field("receiver", &self.receiver).finish()
+        f.debug_struct("MessageQueue").field("sender", &self.sender).field("capacity", &self.capacity).finish()
     }
 }
 
 impl MessageQueue {
     // Should be singleton.
-    pub(crate) fn new(seq: i64, ctx: Context) -> Self {
-        let (s, r) = unbounded::<Message>();
+    pub(crate) fn new(ctx: Context, broker: Arc<dyn Broker>) -> Self {
+        let capacity = ctx.config.queue.capacity.max(1);
+        let (s, r) = mpsc::channel::<Message>(capacity);
 
         MessageQueue {
-            sender: s.to_owned(),
-            receiver: r.to_owned(),
-            // sem: Arc::new(Semaphore::new(n_workers)),
-            cur_id: Arc::new(AtomicI64::new(seq)),
+            sender: s,
+            receiver: Mutex::new(Some(r)),
+            capacity,
+            broker,
+            scheduler: Arc::new(Scheduler::default()),
+            stopping: Arc::new(AtomicBool::new(false)),
+            in_flight: Arc::new(InFlight::default()),
             context: ctx,
         }
     }
 
     pub(crate) fn start(&self) {
-        let receiver = self.receiver.clone();
-        // let sem = self.sem.clone();
+        let mut receiver = self.receiver.lock().unwrap().take().expect("started twice");
+        let storage = self.context.services.mq_storage.clone();
+        let broker = self.broker.clone();
 
         tokio::spawn(async move {
-            let mc = get_mcache();
             loop {
-                match receiver.recv() {
-                    Ok(msg) => {
-                        let stored = msg.clone();
-                        mc.add(stored).await;
-                        tokio::spawn(async move {
-                            msg.evt.process().await;
-                        });
+                match receiver.recv().await {
+                    Some(msg) => {
+                        // Stored before processing so a crash can't lose it,
+                        // messages replayed on startup are stored already.
+                        let id = msg.id;
+                        if let Err(e) = storage.save_message(msg.clone().into()).await {
+                            tracing::error!("Failed to store message {id}: {e}");
+                        }
+                        // Waits while the broker is full, so senders feel the backpressure.
+                        if let Err(e) = broker.publish(msg).await {
+                            tracing::error!("Failed to publish message {id}: {e}");
+                        }
                     },
+                    None => {
+                        // Only happens once every sender is gone, nothing comes in any more.
+                        tracing::error!("Event loop stopped");
+                        return;
+                    }
+                }
+            }
+        });
+        self.consume();
+        self.dispatch();
+        self.purge_keys();
+        self.purge_messages();
+    }
+
+    // Drop the idempotency keys past their ttl now and then.
+    fn purge_keys(&self) {
+        let storage = self.context.services.mq_storage.clone();
+        let key_ttl = self.key_ttl();
+
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(KEY_PURGE_INTERVAL);
+            loop {
+                timer.tick().await;
+                match storage.purge_keys(expiry(key_ttl)).await {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::debug!("Purged {purged} expired idempotency keys"),
+                    Err(e) => tracing::error!("Failed to purge idempotency keys: {e}"),
+                }
+            }
+        });
+    }
+
+    // Drop the messages processed longer ago than the retention now and then, the table
+    // would grow with every event otherwise.
+    fn purge_messages(&self) {
+        let storage = self.context.services.mq_storage.clone();
+        let retention = Duration::from_secs(self.context.config.queue.message_retention);
+        if retention.is_zero() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(MESSAGE_PURGE_INTERVAL);
+            loop {
+                timer.tick().await;
+                match storage.purge_messages(expiry(retention)).await {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::debug!("Purged {purged} processed messages"),
+                    Err(e) => tracing::error!("Failed to purge processed messages: {e}"),
+                }
+            }
+        });
+    }
+
+    fn key_ttl(&self) -> Duration {
+        Duration::from_secs(self.context.config.queue.idempotency_ttl)
+    }
+
+    // Take the messages for this instance from the broker.
+    fn consume(&self) {
+        let broker = self.broker.clone();
+        let scheduler = self.scheduler.clone();
+        let stopping = self.stopping.clone();
+        let capacity = self.capacity;
+
+        tokio::spawn(async move {
+            loop {
+                // Leaves messages with the broker while enough wait for a worker.
+                scheduler.wait_for_room(capacity).await;
+                if stopping.load(Ordering::SeqCst) {
+                    return;
+                }
+                match broker.consume().await {
+                    Ok(msg) => scheduler.push(msg),
                     Err(e) => {
-                        // Should not error here.
-                        panic!("Event Loop Panic: {e}");
+                        tracing::error!("Failed to consume messages: {e}");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
                     }
                 }
             }
         });
     }
 
-    pub(crate) fn send(&self, evt: EventType) {
-        let _ = self.sender.send(Message {
-            id: self.cur_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
-            create_time: Utc::now(),
-            evt
+    // Hand the messages due to workers, the most urgent first.
+    fn dispatch(&self) {
+        let storage = self.context.services.mq_storage.clone();
+        let broker = self.broker.clone();
+        let scheduler = self.scheduler.clone();
+        let stopping = self.stopping.clone();
+        let in_flight = self.in_flight.clone();
+        let key_ttl = self.key_ttl();
+        let workers = Arc::new(Semaphore::new(WORKERS));
+
+        tokio::spawn(async move {
+            loop {
+                let worker = workers.clone().acquire_owned().await.unwrap();
+                let msg = scheduler.pop().await;
+                // Counted before checking, so `shutdown` can't miss it.
+                let guard = InFlightGuard::new(in_flight.clone());
+                if stopping.load(Ordering::SeqCst) {
+                    // Left pending for the next start to replay, or for the broker to
+                    // hand out again.
+                    continue;
+                }
+                let storage = storage.clone();
+                let broker = broker.clone();
+                let scheduler = scheduler.clone();
+                tokio::spawn(async move {
+                    process(msg, storage, &scheduler, broker.as_ref(), key_ttl).await;
+                    drop(worker);
+                    drop(guard);
+                });
+            }
         });
     }
+
+    // Re-enqueue the messages left unprocessed by the last run.
+    pub(crate) async fn replay(&self) {
+        if self.broker.redelivers() {
+            // Pending ones may be another instance's to process.
+            return;
+        }
+        let storage = &self.context.services.mq_storage;
+        let pending = match storage.get_pending_messages().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                tracing::error!("Failed to load unprocessed messages: {e}");
+                return;
+            }
+        };
+        for model in pending {
+            let id = model.id;
+            let msg = match Message::try_from(model) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    // Can't be processed, don't try again on every start.
+                    tracing::warn!("Dropped unreadable message {id}: {e}");
+                    let _ = storage.set_done(id).await;
+                    continue;
+                }
+            };
+            if let EventType::ErrorEvent = msg.evt {
+                tracing::warn!("Dropped unreadable message {}", msg.id);
+                let _ = storage.set_done(msg.id).await;
+                continue;
+            }
+            tracing::info!("Replaying message {}", msg);
+            let _ = self.sender.send(msg).await;
+        }
+    }
+
+    /// Stop processing messages and wait up to `timeout` for those being processed.
+    /// Messages sent from now on are stored and processed on the next start, as are
+    /// those still being processed at the timeout. Returns whether all were done.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.stopping.store(true, Ordering::SeqCst);
+        let drained = tokio::time::timeout(timeout, async {
+            loop {
+                let idle = self.in_flight.idle.notified();
+                if self.in_flight.count.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await
+        .is_ok();
+        if drained {
+            tracing::info!("Message queue drained");
+        } else {
+            tracing::warn!(
+                "{} messages still processing at shutdown, they run again on the next start",
+                self.in_flight.count.load(Ordering::SeqCst)
+            );
+        }
+        drained
+    }
+
+    /// Queue a dead-lettered message again, as a new message whose id is returned.
+    /// `None` when there's no such dead letter.
+    pub async fn requeue(&self, id: i64) -> Result<Option<i64>, MegaError> {
+        let storage = &self.context.services.mq_storage;
+        let Some(dead) = storage.get_dead_letter(id).await? else {
+            return Ok(None);
+        };
+        let msg = Message::try_from(mq_storage::Model {
+            id: dead.id,
+            category: dead.category,
+            create_time: dead.create_time,
+            content: dead.content,
+            done: false,
+            priority: Priority::default().into(),
+            deliver_at: None,
+            idempotency_key: None,
+        })
+        .map_err(|e| {
+            MegaError::with_message(&format!("the message can't be read any more: {e}"))
+        })?;
+        if let EventType::ErrorEvent = msg.evt {
+            return Err(MegaError::with_message(
+                "the message can't be read any more",
+            ));
+        }
+        let new_id = self
+            .send_wait(msg.evt)
+            .await
+            .map_err(|e| MegaError::with_message(&e.to_string()))?;
+        storage.remove_dead_letter(id).await?;
+        Ok(Some(new_id))
+    }
+
+    /// What the queue holds right now.
+    pub fn depth(&self) -> QueueDepth {
+        let (ready, delayed) = self.scheduler.len();
+        QueueDepth {
+            incoming: self.capacity - self.sender.capacity(),
+            ready,
+            delayed,
+            in_flight: self.in_flight.count.load(Ordering::SeqCst),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Set the gauges of what the queue holds, before the metrics are rendered.
+    pub fn record_metrics(&self) {
+        let depth = self.depth();
+        for (state, count) in [
+            ("incoming", depth.incoming),
+            ("ready", depth.ready),
+            ("delayed", depth.delayed),
+            ("in_flight", depth.in_flight),
+        ] {
+            metrics::gauge!("mega_queue_messages", "state" => state).set(count as f64);
+        }
+        metrics::gauge!("mega_queue_capacity").set(depth.capacity as f64);
+    }
+
+    // Queue an event, fails instead of waiting when the queue is full.
+    pub(crate) fn send(&self, evt: EventType) -> Result<i64, QueueError> {
+        let priority = evt.priority();
+        self.send_at(evt, priority, None)
+    }
+
+    // Queue an event, waits for room when the queue is full.
+    pub(crate) async fn send_wait(&self, evt: EventType) -> Result<i64, QueueError> {
+        let msg = self.message(evt.priority(), None, evt);
+        let id = msg.id;
+        self.sender
+            .send(msg)
+            .await
+            .map_err(|_| QueueError::Closed)?;
+        Ok(id)
+    }
+
+    /// Queue an event with `priority` instead of the one of its kind, not to be
+    /// processed before `deliver_at` when set. Returns the id of the message, or
+    /// fails when the queue is full.
+    pub fn send_at(
+        &self,
+        evt: EventType,
+        priority: Priority,
+        deliver_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, QueueError> {
+        let msg = self.message(priority, deliver_at, evt);
+        let id = msg.id;
+        match self.sender.try_send(msg) {
+            Ok(()) => Ok(id),
+            Err(TrySendError::Full(msg)) => {
+                tracing::warn!("Message queue full, dropped {}: {}", msg, msg.evt);
+                Err(QueueError::Full)
+            }
+            Err(TrySendError::Closed(_)) => Err(QueueError::Closed),
+        }
+    }
+
+    fn message(
+        &self,
+        priority: Priority,
+        deliver_at: Option<DateTime<Utc>>,
+        evt: EventType,
+    ) -> Message {
+        let key = evt.idempotency_key();
+        Message {
+            id: generate_id(),
+            create_time: Utc::now(),
+            evt,
+            priority,
+            deliver_at,
+            attempt: 1,
+            key,
+        }
+    }
+}
+
+// Claims made before then have expired.
+fn expiry(key_ttl: Duration) -> NaiveDateTime {
+    (Utc::now() - key_ttl).naive_utc()
+}
+
+// Process a message. One failing goes back to the scheduler until its retry policy
+// runs out of attempts, then it's dead-lettered. One whose idempotency key another
+// message claimed is skipped.
+async fn process(
+    mut msg: Message,
+    storage: MQStorage,
+    scheduler: &Scheduler,
+    broker: &dyn Broker,
+    key_ttl: Duration,
+) {
+    let Some(_permit) = msg.evt.permit() else {
+        // Not an attempt, it just goes back until there's room.
+        msg.deliver_at = Some(Utc::now() + BUSY_DELAY);
+        scheduler.push(msg);
+        return;
+    };
+    let claimed = match &msg.key {
+        Some(key) => match storage.claim_key(key, msg.id, expiry(key_ttl)).await {
+            Ok(claimed) => claimed,
+            Err(e) => {
+                // Processed rather than lost.
+                tracing::error!("Failed to claim key {key} of message {}: {e}", msg.id);
+                true
+            }
+        },
+        None => true,
+    };
+    if !claimed {
+        tracing::info!(
+            "Message {} skipped, another one processed {}",
+            msg.id,
+            msg.key.as_deref().unwrap_or_default()
+        );
+        count(&msg.evt, "duplicate");
+    } else if let Err(e) = attempt(&msg.evt).await {
+        let policy = msg.evt.retry_policy();
+        let attempt = msg.attempt;
+        if attempt < policy.max_attempts {
+            let delay = policy.delay(attempt);
+            tracing::warn!(
+                "Message {} failed, attempt {attempt} of {}, retrying in {delay:?}: {e}",
+                msg.id,
+                policy.max_attempts
+            );
+            count(&msg.evt, "retried");
+            msg.attempt += 1;
+            msg.deliver_at = Some(Utc::now() + delay);
+            scheduler.push(msg);
+            return;
+        }
+        tracing::error!(
+            "Message {} failed {attempt} times, dead-lettered: {e}",
+            msg.id
+        );
+        let model = mq_storage::Model::from(msg.clone());
+        let dead = mq_dead_letter::Model {
+            id: model.id,
+            category: model.category,
+            content: model.content,
+            attempts: attempt as i32,
+            error: e.to_string(),
+            create_time: model.create_time,
+            dead_at: Utc::now().naive_utc(),
+        };
+        if let Err(e) = storage.save_dead_letter(dead).await {
+            // Left pending, so it's processed again on the next start.
+            tracing::error!("Failed to dead-letter message {}: {e}", msg.id);
+            return;
+        }
+        // A duplicate may still make it.
+        if let Some(key) = &msg.key {
+            if let Err(e) = storage.release_key(key, msg.id).await {
+                tracing::error!("Failed to release key {key} of message {}: {e}", msg.id);
+            }
+        }
+        count(&msg.evt, "dead_lettered");
+    } else {
+        count(&msg.evt, "processed");
+    }
+    if let Err(e) = storage.set_done(msg.id).await {
+        tracing::error!("Failed to mark message {} done: {e}", msg.id);
+    }
+    if let Err(e) = broker.ack(msg.id).await {
+        tracing::error!("Failed to ack message {}: {e}", msg.id);
+    }
+}
+
+// Process the event once, timing it.
+async fn attempt(evt: &EventType) -> Result<(), MegaError> {
+    let start = Instant::now();
+    let res = evt.process().await;
+    metrics::histogram!("mega_queue_event_duration_seconds", "event" => evt.category())
+        .record(start.elapsed().as_secs_f64());
+    res
+}
+
+fn count(evt: &EventType, outcome: &'static str) {
+    metrics::counter!("mega_queue_events_total", "event" => evt.category(), "outcome" => outcome)
+        .increment(1);
 }
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// How often an event failing is processed again before it's dead-lettered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// attempts in all, 1 never retries
+    pub max_attempts: u32,
+    /// delay before the first retry, doubled for each one after
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Fail at the first error.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before attempting again after `attempt`, counted from 1, failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |x| x.min(self.max_backoff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn test_delay_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(30),
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(4), Duration::from_secs(16));
+        assert_eq!(policy.delay(5), Duration::from_secs(30));
+        assert_eq!(policy.delay(40), Duration::from_secs(30));
+    }
+}
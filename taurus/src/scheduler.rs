@@ -0,0 +1,215 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::event::Message;
+
+/// How urgent a message is, more urgent ones are processed first while all workers
+/// are busy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    /// latency-sensitive, like webhook deliveries and cache invalidation
+    High,
+    #[default]
+    Normal,
+    /// bulk jobs, like garbage collection and indexing
+    Low,
+}
+
+impl From<Priority> for i32 {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+impl From<i32> for Priority {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Priority::High,
+            2 => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+// Most urgent first, then the oldest.
+struct Ready(Message);
+
+impl Ord for Ready {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .0
+            .priority
+            .cmp(&self.0.priority)
+            .then_with(|| other.0.id.cmp(&self.0.id))
+    }
+}
+
+impl PartialOrd for Ready {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ready {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ready {}
+
+// Due first.
+struct Delayed(Message);
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.deliver_at.cmp(&self.0.deliver_at)
+    }
+}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delayed {}
+
+#[derive(Default)]
+struct State {
+    ready: BinaryHeap<Ready>,
+    delayed: BinaryHeap<Delayed>,
+}
+
+/// Messages waiting for a worker, handed out by priority once they're due.
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    state: Mutex<State>,
+    wake: Notify,
+    // Notified when a message was handed out.
+    room: Notify,
+}
+
+impl Scheduler {
+    pub(crate) fn push(&self, msg: Message) {
+        {
+            let mut state = self.state.lock().unwrap();
+            match msg.deliver_at {
+                Some(at) if at > Utc::now() => state.delayed.push(Delayed(msg)),
+                _ => state.ready.push(Ready(msg)),
+            }
+        }
+        self.wake.notify_one();
+    }
+
+    /// Messages ready and messages delayed.
+    pub(crate) fn len(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.ready.len(), state.delayed.len())
+    }
+
+    /// Wait until fewer than `capacity` messages are ready.
+    pub(crate) async fn wait_for_room(&self, capacity: usize) {
+        while self.len().0 >= capacity {
+            self.room.notified().await;
+        }
+    }
+
+    /// The most urgent message due, waits until there's one.
+    pub(crate) async fn pop(&self) -> Message {
+        loop {
+            let next_due = {
+                let mut state = self.state.lock().unwrap();
+                let now = Utc::now();
+                while state
+                    .delayed
+                    .peek()
+                    .is_some_and(|x| x.0.deliver_at <= Some(now))
+                {
+                    let Delayed(msg) = state.delayed.pop().unwrap();
+                    state.ready.push(Ready(msg));
+                }
+                if let Some(Ready(msg)) = state.ready.pop() {
+                    self.room.notify_one();
+                    return msg;
+                }
+                state.delayed.peek().and_then(|x| x.0.deliver_at)
+            };
+            match next_due {
+                Some(at) => {
+                    let wait = (at - Utc::now()).to_std().unwrap_or_default();
+                    tokio::select! {
+                        _ = self.wake.notified() => {},
+                        _ = tokio::time::sleep(wait) => {},
+                    }
+                }
+                None => self.wake.notified().await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{Priority, Scheduler};
+    use crate::event::{EventType, Message};
+
+    fn message(id: i64, priority: Priority, delay: Option<i64>) -> Message {
+        Message {
+            id,
+            create_time: Utc::now(),
+            evt: EventType::ErrorEvent,
+            priority,
+            deliver_at: delay.map(|x| Utc::now() + Duration::milliseconds(x)),
+            attempt: 1,
+            key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pop_by_priority_then_age() {
+        let scheduler = Scheduler::default();
+        scheduler.push(message(1, Priority::Low, None));
+        scheduler.push(message(2, Priority::Normal, None));
+        scheduler.push(message(3, Priority::High, None));
+        scheduler.push(message(4, Priority::Normal, None));
+        let order: Vec<i64> = [
+            scheduler.pop().await,
+            scheduler.pop().await,
+            scheduler.pop().await,
+            scheduler.pop().await,
+        ]
+        .iter()
+        .map(|x| x.id)
+        .collect();
+        assert_eq!(order, vec![3, 2, 4, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_delayed_waits_until_due() {
+        let scheduler = Scheduler::default();
+        scheduler.push(message(1, Priority::High, Some(200)));
+        scheduler.push(message(2, Priority::Low, None));
+        assert_eq!(scheduler.pop().await.id, 2);
+        let start = std::time::Instant::now();
+        assert_eq!(scheduler.pop().await.id, 1);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,154 @@
+//! Subscriptions to event topics. Subsystems register a handler for the topics they care
+//! about instead of adding a variant to [`EventType`], a published event goes through the
+//! queue once for each handler subscribed, so each is retried and dead-lettered on its own.
+
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use common::errors::MegaError;
+use serde_json::Value;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::event::topic::TopicEvent;
+use crate::event::EventType;
+use crate::queue::{get_mq, QueueError};
+
+#[async_trait]
+pub trait TopicHandler: Send + Sync {
+    /// Handle an event published to `topic`, an error has it retried.
+    async fn handle(&self, topic: &str, payload: &Value) -> Result<(), MegaError>;
+}
+
+struct Subscription {
+    /// topic, or a prefix of topics ending with `.*`
+    pattern: String,
+    name: String,
+    handler: Arc<dyn TopicHandler>,
+    // Events handled at once, those over it wait in the queue.
+    limit: Arc<Semaphore>,
+}
+
+static SUBSCRIPTIONS: RwLock<Vec<Arc<Subscription>>> = RwLock::new(Vec::new());
+
+/// Have `handler` called for the events published to `topic`, handling at most
+/// `concurrency` of them at once. `topic` ending with `.*` subscribes to every topic it
+/// prefixes, `mr.*` to `mr.merged` and `mr.closed`. Subscribing a `name` taken for the
+/// same topic replaces that handler. Every instance processing the queue must subscribe
+/// the same handlers.
+pub fn subscribe(
+    topic: &str,
+    name: &str,
+    concurrency: usize,
+    handler: impl TopicHandler + 'static,
+) {
+    let subscription = Arc::new(Subscription {
+        pattern: topic.to_owned(),
+        name: name.to_owned(),
+        handler: Arc::new(handler),
+        limit: Arc::new(Semaphore::new(concurrency.max(1))),
+    });
+    let mut subscriptions = SUBSCRIPTIONS.write().unwrap();
+    subscriptions.retain(|x| x.pattern != topic || x.name != name);
+    subscriptions.push(subscription);
+}
+
+/// Stop calling the handler `name` subscribed to `topic`, events already queued for it
+/// fail until they're dead-lettered.
+pub fn unsubscribe(topic: &str, name: &str) {
+    SUBSCRIPTIONS
+        .write()
+        .unwrap()
+        .retain(|x| x.pattern != topic || x.name != name);
+}
+
+/// Queue `payload` for every handler subscribed to `topic`, returns the ids of the
+/// messages. An event nobody subscribed to is dropped.
+pub fn publish(topic: &str, payload: Value) -> Result<Vec<i64>, QueueError> {
+    let handlers: Vec<String> = SUBSCRIPTIONS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|x| matches(&x.pattern, topic))
+        .map(|x| x.name.clone())
+        .collect();
+    if handlers.is_empty() {
+        tracing::debug!("No handler subscribed to {topic}, dropped the event");
+    }
+    handlers
+        .into_iter()
+        .map(|handler| {
+            get_mq().send(EventType::Topic(TopicEvent {
+                topic: topic.to_owned(),
+                handler,
+                payload: payload.clone(),
+            }))
+        })
+        .collect()
+}
+
+/// Held while an event is processed, limiting how many of its kind are at once.
+pub(crate) struct Permit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Permit {
+    pub(crate) fn unlimited() -> Self {
+        Permit { _permit: None }
+    }
+}
+
+/// A permit to handle `evt`, `None` while its handler is at its concurrency limit.
+pub(crate) fn permit(evt: &TopicEvent) -> Option<Permit> {
+    match find(evt) {
+        Some(subscription) => subscription
+            .limit
+            .clone()
+            .try_acquire_owned()
+            .ok()
+            .map(|x| Permit { _permit: Some(x) }),
+        // Fails on processing.
+        None => Some(Permit::unlimited()),
+    }
+}
+
+pub(crate) async fn handle(evt: &TopicEvent) -> Result<(), MegaError> {
+    let Some(subscription) = find(evt) else {
+        return Err(MegaError::with_message(&format!(
+            "no handler {} subscribed to {}",
+            evt.handler, evt.topic
+        )));
+    };
+    subscription.handler.handle(&evt.topic, &evt.payload).await
+}
+
+fn find(evt: &TopicEvent) -> Option<Arc<Subscription>> {
+    SUBSCRIPTIONS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|x| x.name == evt.handler && matches(&x.pattern, &evt.topic))
+        .cloned()
+}
+
+fn matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) if prefix.is_empty() || prefix.ends_with('.') => topic.starts_with(prefix),
+        _ => pattern == topic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("mr.merged", "mr.merged"));
+        assert!(!matches("mr.merged", "mr.closed"));
+        assert!(matches("mr.*", "mr.closed"));
+        assert!(matches("mr.*", "mr.review.added"));
+        assert!(!matches("mr.*", "mrs.closed"));
+        assert!(!matches("mr*", "mrs.closed"));
+        assert!(matches("*", "push"));
+    }
+}